@@ -1,8 +1,8 @@
 use crate::SharedOptions;
 
 use probe_rs::{
-    architecture::arm::ap::AccessPortError, config::TargetSelector, flashing::FileDownloadError,
-    DebugProbeError, Error, Probe, Session,
+    architecture::arm::ap::AccessPortError, config::TargetSelector, debug::DebugError,
+    flashing::FileDownloadError, AttachConfig, DebugProbeError, Error, Probe, Session,
 };
 
 use std::fmt;
@@ -39,6 +39,11 @@ pub enum CliError {
         #[from]
         Error,
     ),
+    Debug(
+        #[source]
+        #[from]
+        DebugError,
+    ),
 }
 
 impl fmt::Display for CliError {
@@ -56,6 +61,7 @@ impl fmt::Display for CliError {
                 Some(details) => write!(f, "Unable to open probe: {}", details),
             },
             ProbeRs(ref e) => e.fmt(f),
+            Debug(ref e) => e.fmt(f),
         }
     }
 }
@@ -92,6 +98,10 @@ pub(crate) fn with_device<F>(shared_options: &SharedOptions, f: F) -> Result<()>
 where
     F: FnOnce(Session) -> Result<()>,
 {
+    if let Some(pack) = &shared_options.chip_pack {
+        probe_rs::config::registry::load_pack(pack)?;
+    }
+
     let mut probe = open_probe(shared_options.n)?;
 
     let target_selector = match &shared_options.chip {
@@ -107,6 +117,19 @@ where
         )?;
     }
 
+    if shared_options.power_target {
+        probe.set_target_power(false)?;
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        probe.set_target_power(true)?;
+    }
+
+    probe.set_swj_sequence(shared_options.swj_sequence)?;
+
+    probe.set_attach_config(AttachConfig {
+        retry_count: shared_options.attach_retries,
+        ..AttachConfig::default()
+    });
+
     let session = if shared_options.connect_under_reset {
         probe.attach_under_reset(target_selector)?
     } else {
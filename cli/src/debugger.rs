@@ -1,8 +1,7 @@
 use crate::common::CliError;
 
-use capstone::Capstone;
 use probe_rs::architecture::arm::CortexDump;
-use probe_rs::debug::DebugInfo;
+use probe_rs::debug::{disassemble_memory, DebugInfo};
 use probe_rs::{Core, CoreRegisterAddress, MemoryInterface};
 use std::fs::File;
 use std::{io::prelude::*, time::Duration};
@@ -41,27 +40,8 @@ impl DebugCli {
                 let cpu_info = cli_data.core.halt(Duration::from_millis(100))?;
                 println!("Core stopped at address 0x{:08x}", cpu_info.pc);
 
-                let mut code = [0u8; 16 * 2];
-
-                cli_data.core.read_8(cpu_info.pc, &mut code)?;
-
-                /*
-                let instructions = cli_data
-                    .capstone
-                    .disasm_all(&code, u64::from(cpu_info.pc))
-                    .unwrap();
-
-                for i in instructions.iter() {
-                    println!("{}", i);
-                }
-                 */
-
-                for (offset, instruction) in code.iter().enumerate() {
-                    println!(
-                        "{:#010x}: {:010x}",
-                        cpu_info.pc + offset as u32,
-                        instruction
-                    );
+                for instruction in disassemble_memory(&mut cli_data.core, cpu_info.pc, 16 * 2)? {
+                    println!("{}", instruction);
                 }
 
                 Ok(CliState::Continue)
@@ -122,7 +102,7 @@ impl DebugCli {
 
                 let mut buff = vec![0u32; num_words];
 
-                cli_data.core.read_32(address, &mut buff)?;
+                cli_data.core.read_32(address.into(), &mut buff)?;
 
                 for (offset, word) in buff.iter().enumerate() {
                     println!("0x{:08x} = 0x{:08x}", address + (offset * 4) as u32, word);
@@ -143,7 +123,7 @@ impl DebugCli {
                 let data_str = args.get(1).ok_or(CliError::MissingArgument)?;
                 let data = parse_u32_hex(data_str);
 
-                cli_data.core.write_word_32(address, data)?;
+                cli_data.core.write_word_32(address.into(), data)?;
 
                 Ok(CliState::Continue)
             },
@@ -236,7 +216,7 @@ impl DebugCli {
 
                 let mut stack = vec![0u8; (stack_top - stack_bot) as usize];
 
-                cli_data.core.read_8(stack_bot, &mut stack[..])?;
+                cli_data.core.read(stack_bot.into(), &mut stack[..])?;
 
                 let mut dump = CortexDump::new(stack_bot, stack);
 
@@ -320,7 +300,6 @@ impl DebugCli {
 pub struct CliData<'p> {
     pub core: Core<'p>,
     pub debug_info: Option<DebugInfo>,
-    pub capstone: Capstone,
 }
 
 pub enum CliState {
@@ -1,4 +1,7 @@
-use crate::{common::open_probe, SharedOptions};
+use crate::{
+    common::{open_probe, with_device},
+    SharedOptions,
+};
 
 use probe_rs::{
     architecture::arm::{
@@ -7,6 +10,7 @@ use probe_rs::{
         memory::Component,
         ApInformation,
     },
+    flashing::SectorProtection,
     CoreRegister,
 };
 
@@ -14,6 +18,16 @@ use anyhow::Result;
 
 pub(crate) fn show_info_of_device(shared_options: &SharedOptions) -> Result<()> {
     let mut probe = open_probe(shared_options.n)?;
+
+    // The voltage is shown first, as attaching fails if the target is not powered.
+    match probe.target_voltage() {
+        Some(voltage) => println!("Target voltage: {:.2} V", voltage),
+        None => println!("Target voltage: not measured by the probe"),
+    }
+    if probe.can_power_target() {
+        println!("The probe can supply power to the target.");
+    }
+
     probe.attach_to_unspecified()?;
 
     /*
@@ -97,3 +111,32 @@ pub(crate) fn show_info_of_device(shared_options: &SharedOptions) -> Result<()>
 
     Ok(())
 }
+
+pub(crate) fn show_flash_sectors(shared_options: &SharedOptions) -> Result<()> {
+    with_device(shared_options, |mut session| {
+        let sectors = session.flash_sectors()?;
+
+        if sectors.is_empty() {
+            println!("No flash algorithm describes the sectors of the target.");
+            return Ok(());
+        }
+
+        println!("{:<22}  {:>12}  Protection", "Sector", "Size");
+        for sector in sectors {
+            let protection = match sector.protection {
+                SectorProtection::Unprotected => "unprotected",
+                SectorProtection::Protected => "write protected",
+                SectorProtection::Unknown => "unknown",
+            };
+            println!(
+                "{:#010x}..{:#010x}  {:>8} KiB  {}",
+                sector.address,
+                sector.address + sector.size,
+                f64::from(sector.size) / 1024.0,
+                protection
+            );
+        }
+
+        Ok(())
+    })
+}
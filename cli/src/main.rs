@@ -5,26 +5,82 @@ mod info;
 use common::with_device;
 use debugger::CliState;
 
+use probe_rs::architecture::arm::{
+    swo::{pc_sampling_interval, PcProfile, ProfileLocation},
+    SwjSequence, SwoConfig,
+};
 use probe_rs::{
-    debug::DebugInfo,
-    flashing::{download_file, Format},
-    MemoryInterface, Probe, Session,
+    debug::{DebugError, DebugInfo},
+    flashing::{
+        download_file, erase_all, erase_all_side_effects, BinOptions, FileDownloadError,
+        FlashError, FlashProgress, Format, VerifyMethod,
+    },
+    AccessWidth, DumpFormat, DumpOptions, MemoryInterface, Probe, ReadOptions, Session,
 };
 
-use capstone::{arch::arm::ArchMode, prelude::*, Capstone, Endian};
 use rustyline::Editor;
 use structopt::StructOpt;
 
 use anyhow::{anyhow, Result};
 
+use std::io::Write;
 use std::num::ParseIntError;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 fn parse_hex(src: &str) -> Result<u32, ParseIntError> {
     u32::from_str_radix(src, 16)
 }
 
+fn parse_hex_u64(src: &str) -> Result<u64, ParseIntError> {
+    u64::from_str_radix(src.trim_start_matches("0x"), 16)
+}
+
+fn parse_hex_u8(src: &str) -> Result<u8, ParseIntError> {
+    u8::from_str_radix(src.trim_start_matches("0x"), 16)
+}
+
+/// Parses a range of hexadecimal addresses like `0x08000000..0x08010000`.
+fn parse_range(src: &str) -> Result<Range<u32>> {
+    let (start, end) = src
+        .split_once("..")
+        .ok_or_else(|| anyhow!("Invalid range '{}', expected e.g. 0x0..0x1000", src))?;
+    let parse = |address: &str| u32::from_str_radix(address.trim_start_matches("0x"), 16);
+    let range = parse(start)?..parse(end)?;
+
+    if range.is_empty() {
+        return Err(anyhow!("The range '{}' is empty", src));
+    }
+    Ok(range)
+}
+
+/// Parses a duration like `10s`, `500ms` or `2m`, where a plain number is in seconds.
+fn parse_duration(src: &str) -> Result<Duration> {
+    let (value, unit) = match src.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(index) => src.split_at(index),
+        None => (src, "s"),
+    };
+    let value: f64 = value
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration '{}', expected e.g. 10s or 500ms", src))?;
+
+    let seconds = match unit {
+        "ms" => value / 1000.0,
+        "s" => value,
+        "m" => value * 60.0,
+        _ => return Err(anyhow!("Unknown unit '{}', expected ms, s or m", unit)),
+    };
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+fn parse_width(src: &str) -> Result<AccessWidth> {
+    src.parse()
+        .ok()
+        .and_then(AccessWidth::from_bits)
+        .ok_or_else(|| anyhow!("Unsupported width '{}', expected 8, 16, 32 or 64", src))
+}
+
 #[derive(StructOpt)]
 #[structopt(
     name = "Probe-rs CLI",
@@ -40,6 +96,9 @@ enum CLI {
     Info {
         #[structopt(flatten)]
         shared: SharedOptions,
+        /// Show the sectors of the flash of the target and their write protection instead
+        #[structopt(long = "flash")]
+        flash: bool,
     },
     /// Resets the target attached to the selected debug probe
     #[structopt(name = "reset")]
@@ -49,6 +108,10 @@ enum CLI {
 
         /// Whether the reset pin should be asserted or deasserted. If left open, just pulse it
         assert: Option<bool>,
+
+        /// Print the reason of the previous reset of the target before resetting it
+        #[structopt(long = "report-reason")]
+        report_reason: bool,
     },
     #[structopt(name = "debug")]
     Debug {
@@ -58,18 +121,64 @@ enum CLI {
         #[structopt(long, parse(from_os_str))]
         /// Binary to debug
         exe: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        debug_file: DebugFileOptions,
     },
-    /// Dump memory from attached target
+    /// Dump a region of the memory of the attached target to a file
     #[structopt(name = "dump")]
     Dump {
         #[structopt(flatten)]
         shared: SharedOptions,
 
-        /// The address of the memory to dump from the target (in hexadecimal without 0x prefix)
-        #[structopt(parse(try_from_str = parse_hex))]
-        loc: u32,
-        /// The amount of memory (in words) to dump
-        words: u32,
+        /// The memory to dump, as a range of hexadecimal addresses, e.g. 0x08000000..0x08010000
+        #[structopt(long, parse(try_from_str = parse_range))]
+        range: Range<u32>,
+        /// The file to write the memory to
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+        /// The format of the file: bin or hex
+        #[structopt(long, default_value = "bin")]
+        format: String,
+        /// Fill memory which cannot be read with this byte (in hexadecimal), instead of stopping
+        #[structopt(long, parse(try_from_str = parse_hex_u8))]
+        fill: Option<u8>,
+        /// Read the memory with a routine loaded into the target RAM, which is faster for large regions
+        #[structopt(long)]
+        fast: bool,
+    },
+    /// Read values from the memory of the attached target, e.g. peripheral registers
+    #[structopt(name = "read")]
+    Read {
+        #[structopt(flatten)]
+        shared: SharedOptions,
+
+        /// The address to read from (in hexadecimal), or the name of a system register like DHCSR
+        location: String,
+        /// The number of values to read
+        #[structopt(default_value = "1")]
+        count: usize,
+        /// The width of the values in bits: 8, 16, 32 or 64
+        #[structopt(long, default_value = "32", parse(try_from_str = parse_width))]
+        width: AccessWidth,
+    },
+    /// Write a single value to the memory of the attached target, e.g. to a peripheral register
+    #[structopt(name = "write")]
+    Write {
+        #[structopt(flatten)]
+        shared: SharedOptions,
+
+        /// The address to write to (in hexadecimal), or the name of a system register like DHCSR
+        location: String,
+        /// The value to write (in hexadecimal)
+        #[structopt(parse(try_from_str = parse_hex_u64))]
+        value: u64,
+        /// The width of the value in bits: 8, 16, 32 or 64
+        #[structopt(long, default_value = "32", parse(try_from_str = parse_width))]
+        width: AccessWidth,
+        /// Write even if the memory is read-only or not part of the memory map of the target
+        #[structopt(long)]
+        force: bool,
     },
     /// Download memory to attached target
     #[structopt(name = "download")]
@@ -79,6 +188,41 @@ enum CLI {
 
         /// The path to the file to be downloaded to the flash
         path: String,
+
+        /// The format of the file: elf, hex or bin
+        #[structopt(long, default_value = "elf")]
+        format: String,
+
+        /// The address a bin file is loaded at (in hexadecimal without 0x prefix)
+        #[structopt(long, parse(try_from_str = parse_hex))]
+        base_address: Option<u32>,
+    },
+    /// Check whether the flash of the attached target contains a file, without changing the flash
+    #[structopt(name = "verify")]
+    Verify {
+        #[structopt(flatten)]
+        shared: SharedOptions,
+
+        /// The path to the file to compare with the flash
+        path: String,
+
+        /// The format of the file: elf, hex or bin
+        #[structopt(long, default_value = "elf")]
+        format: String,
+
+        /// The address a bin file is loaded at (in hexadecimal without 0x prefix)
+        #[structopt(long, parse(try_from_str = parse_hex))]
+        base_address: Option<u32>,
+
+        /// Read back all data instead of comparing checksums computed on the target first
+        #[structopt(long)]
+        full: bool,
+    },
+    /// Erase the complete flash of the attached target
+    #[structopt(name = "erase")]
+    Erase {
+        #[structopt(flatten)]
+        shared: SharedOptions,
     },
     #[structopt(name = "trace")]
     Trace {
@@ -89,6 +233,39 @@ enum CLI {
         #[structopt(parse(try_from_str = parse_hex))]
         loc: u32,
     },
+    /// Profile the running target by sampling its PC over SWO
+    #[structopt(name = "profile")]
+    Profile {
+        #[structopt(flatten)]
+        shared: SharedOptions,
+
+        /// The ELF file running on the target, used to find the functions of the samples
+        #[structopt(long, parse(from_os_str))]
+        exe: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        debug_file: DebugFileOptions,
+
+        /// How long to sample, e.g. 10s or 500ms
+        #[structopt(long, default_value = "10s", parse(try_from_str = parse_duration))]
+        duration: Duration,
+
+        /// The clock of the core and the TPIU in Hz
+        #[structopt(long)]
+        clock: u32,
+
+        /// The SWO baud rate
+        #[structopt(long, default_value = "1000000")]
+        baud: u32,
+
+        /// Sample the PC every 64 * (postpreset + 1) core cycles, postpreset is 0 to 15
+        #[structopt(long, default_value = "15")]
+        postpreset: u8,
+
+        /// The number of functions to print
+        #[structopt(long, default_value = "20")]
+        top: usize,
+    },
 }
 
 /// Shared options for all commands which use a specific probe
@@ -102,12 +279,50 @@ struct SharedOptions {
     #[structopt(short, long)]
     chip: Option<String>,
 
+    /// A CMSIS pack, or the `.pdsc` file of an extracted pack, to load additional targets from.
+    #[structopt(long, parse(from_os_str))]
+    chip_pack: Option<PathBuf>,
+
     /// Protocol to use for target connection
     #[structopt(short, long)]
     protocol: Option<String>,
 
     #[structopt(long)]
     connect_under_reset: bool,
+
+    /// Power cycle the target with the power supplied by the probe before connecting.
+    #[structopt(long)]
+    power_target: bool,
+
+    /// How often attaching to the target is retried after it failed
+    #[structopt(long, default_value = "0")]
+    attach_retries: u32,
+
+    /// The sequence which switches the debug port to the protocol [jtag-to-swd, swd-to-jtag, dormant-to-swd, dormant-to-jtag]
+    #[structopt(long)]
+    swj_sequence: Option<SwjSequence>,
+}
+
+/// Options for loading the DWARF information of a stripped binary from a separate file
+#[derive(StructOpt)]
+struct DebugFileOptions {
+    /// The separate debug file of a stripped binary, matched to it by the GNU build ID
+    #[structopt(long, parse(from_os_str))]
+    debug_file: Option<PathBuf>,
+
+    /// Use the debug file even if its build ID is missing or does not match the binary
+    #[structopt(long)]
+    allow_mismatch: bool,
+}
+
+impl DebugFileOptions {
+    /// Loads the debug information of `exe`, from the debug file if one is given.
+    fn load(&self, exe: &Path) -> Result<DebugInfo, DebugError> {
+        match &self.debug_file {
+            Some(debug_file) => DebugInfo::from_split_files(exe, debug_file, self.allow_mismatch),
+            None => DebugInfo::from_file(exe),
+        }
+    }
 }
 
 fn main() -> Result<()> {
@@ -118,12 +333,83 @@ fn main() -> Result<()> {
 
     match matches {
         CLI::List {} => list_connected_devices(),
-        CLI::Info { shared } => crate::info::show_info_of_device(&shared),
-        CLI::Reset { shared, assert } => reset_target_of_device(&shared, assert),
-        CLI::Debug { shared, exe } => debug(&shared, exe),
-        CLI::Dump { shared, loc, words } => dump_memory(&shared, loc, words),
-        CLI::Download { shared, path } => download_program_fast(&shared, &path),
+        CLI::Info { shared, flash } => match flash {
+            true => crate::info::show_flash_sectors(&shared),
+            false => crate::info::show_info_of_device(&shared),
+        },
+        CLI::Reset {
+            shared,
+            assert,
+            report_reason,
+        } => reset_target_of_device(&shared, assert, report_reason),
+        CLI::Debug {
+            shared,
+            exe,
+            debug_file,
+        } => debug(&shared, exe, &debug_file),
+        CLI::Dump {
+            shared,
+            range,
+            output,
+            format,
+            fill,
+            fast,
+        } => dump_memory(&shared, range, &output, &format, fill, fast),
+        CLI::Read {
+            shared,
+            location,
+            count,
+            width,
+        } => read_values(&shared, &location, width, count),
+        CLI::Write {
+            shared,
+            location,
+            value,
+            width,
+            force,
+        } => write_value(&shared, &location, width, value, force),
+        CLI::Download {
+            shared,
+            path,
+            format,
+            base_address,
+        } => download_program_fast(&shared, &path, parse_format(&format, base_address)?),
+        CLI::Verify {
+            shared,
+            path,
+            format,
+            base_address,
+            full,
+        } => verify_flash(
+            &shared,
+            &path,
+            parse_format(&format, base_address)?,
+            if full {
+                VerifyMethod::Full
+            } else {
+                VerifyMethod::Crc32
+            },
+        ),
+        CLI::Erase { shared } => erase_flash(&shared),
         CLI::Trace { shared, loc } => trace_u32_on_target(&shared, loc),
+        CLI::Profile {
+            shared,
+            exe,
+            debug_file,
+            duration,
+            clock,
+            baud,
+            postpreset,
+            top,
+        } => profile(
+            &shared,
+            exe,
+            &debug_file,
+            duration,
+            SwoConfig::new(clock).set_baud(baud),
+            postpreset,
+            top,
+        ),
     }
 }
 
@@ -132,10 +418,9 @@ fn list_connected_devices() -> Result<()> {
 
     if !links.is_empty() {
         println!("The following devices were found:");
-        links
-            .iter()
-            .enumerate()
-            .for_each(|(num, link)| println!("[{}]: {:?}", num, link));
+        links.iter().enumerate().for_each(|(num, link)| {
+            println!("[{}]: {:?} (ID: {})", num, link, link.stable_identifier())
+        });
     } else {
         println!("No devices were found.");
     }
@@ -143,47 +428,208 @@ fn list_connected_devices() -> Result<()> {
     Ok(())
 }
 
-fn dump_memory(shared_options: &SharedOptions, loc: u32, words: u32) -> Result<()> {
+fn dump_memory(
+    shared_options: &SharedOptions,
+    range: Range<u32>,
+    output: &Path,
+    format: &str,
+    fill: Option<u8>,
+    fast: bool,
+) -> Result<()> {
+    let format = match format.to_ascii_lowercase().as_str() {
+        "bin" => DumpFormat::Bin,
+        "hex" | "ihex" => DumpFormat::Hex,
+        _ => return Err(anyhow!("Unknown format '{}', expected bin or hex", format)),
+    };
+
     with_device(shared_options, |mut session| {
-        let mut data = vec![0 as u32; words as usize];
+        let options = DumpOptions {
+            read: ReadOptions { fast_read: fast },
+            fill,
+        };
+        let mut file = std::io::BufWriter::new(std::fs::File::create(output)?);
 
-        // Start timer.
         let instant = Instant::now();
+        let summary = session.dump_region(0, range.clone(), format, options, &mut file)?;
+        file.flush()?;
+
+        println!(
+            "Dumped {} bytes at 0x{:08x}..0x{:08x} to {} in {:?}",
+            summary.bytes,
+            range.start,
+            range.end,
+            output.display(),
+            instant.elapsed()
+        );
+        for hole in &summary.holes {
+            println!(
+                "Filled 0x{:08x}..0x{:08x}, which cannot be read",
+                hole.start, hole.end
+            );
+        }
 
-        // let loc = 220 * 1024;
-
-        let mut core = session.core(0)?;
+        Ok(())
+    })
+}
 
-        core.read_32(loc, &mut data.as_mut_slice())?;
-        // Stop timer.
-        let elapsed = instant.elapsed();
+fn read_values(
+    shared_options: &SharedOptions,
+    location: &str,
+    width: AccessWidth,
+    count: usize,
+) -> Result<()> {
+    with_device(shared_options, |mut session| {
+        let address = session.resolve_address(location)?;
+        let values = session.read_values(0, address, width, count)?;
 
-        // Print read values.
-        for word in 0..words {
-            println!(
-                "Addr 0x{:08x?}: 0x{:08x}",
-                loc + 4 * word,
-                data[word as usize]
-            );
+        for line in format_values(address, width, &values) {
+            println!("{}", line);
         }
-        // Print stats.
-        println!("Read {:?} words in {:?}", words, elapsed);
 
         Ok(())
     })
 }
 
-fn download_program_fast(shared_options: &SharedOptions, path: &str) -> Result<()> {
+/// Formats `values` as a hex dump with 16 bytes per line, followed by their ASCII representation.
+fn format_values(address: u32, width: AccessWidth, values: &[u64]) -> Vec<String> {
+    let bytes = width.bytes();
+    let per_line = (16 / bytes).max(1);
+
+    values
+        .chunks(per_line)
+        .enumerate()
+        .map(|(line, values)| {
+            let hex: Vec<String> = values
+                .iter()
+                .map(|value| format!("{:0width$x}", value, width = bytes * 2))
+                .collect();
+            let ascii: String = values
+                .iter()
+                .flat_map(|value| value.to_le_bytes().to_vec().into_iter().take(bytes))
+                .map(|byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+
+            format!(
+                "0x{:08x}: {:<width$} |{}|",
+                address as usize + line * per_line * bytes,
+                hex.join(" "),
+                ascii,
+                width = per_line * (bytes * 2 + 1) - 1
+            )
+        })
+        .collect()
+}
+
+fn write_value(
+    shared_options: &SharedOptions,
+    location: &str,
+    width: AccessWidth,
+    value: u64,
+    force: bool,
+) -> Result<()> {
+    with_device(shared_options, |mut session| {
+        let address = session.resolve_address(location)?;
+        session.write_value(0, address, width, value, force)?;
+
+        println!("Wrote {:#x} to 0x{:08x}", value, address);
+
+        Ok(())
+    })
+}
+
+fn parse_format(format: &str, base_address: Option<u32>) -> Result<Format> {
+    match format.to_ascii_lowercase().as_str() {
+        "elf" => Ok(Format::Elf),
+        "hex" | "ihex" => Ok(Format::Hex),
+        "bin" => Ok(Format::Bin(BinOptions {
+            base_address,
+            skip: 0,
+        })),
+        _ => Err(anyhow!(
+            "Unknown format '{}', expected elf, hex or bin",
+            format
+        )),
+    }
+}
+
+fn download_program_fast(shared_options: &SharedOptions, path: &str, format: Format) -> Result<()> {
+    with_device(shared_options, |mut session| {
+        download_file(&mut session, std::path::Path::new(&path), format)?;
+
+        Ok(())
+    })
+}
+
+fn verify_flash(
+    shared_options: &SharedOptions,
+    path: &str,
+    format: Format,
+    method: VerifyMethod,
+) -> Result<()> {
     with_device(shared_options, |mut session| {
-        download_file(&mut session, std::path::Path::new(&path), Format::Elf)?;
+        let summary = match session.verify_image(std::path::Path::new(&path), format, method) {
+            Err(FileDownloadError::Flash(FlashError::RoutineNotSupported("crc32"))) => {
+                return Err(anyhow!(
+                    "The flash algorithm has no CRC32 routine, use --full to read back the flash contents instead."
+                ));
+            }
+            result => result?,
+        };
+
+        for region in &summary.regions {
+            match region.first_mismatch {
+                None => println!(
+                    "0x{:08x}..0x{:08x}: matches ({} bytes)",
+                    region.region.start, region.region.end, region.bytes
+                ),
+                Some(address) => println!(
+                    "0x{:08x}..0x{:08x}: differs, first at 0x{:08x}",
+                    region.region.start, region.region.end, address
+                ),
+            }
+        }
+
+        if summary.matches() {
+            println!("The flash contains the file.");
+            Ok(())
+        } else {
+            Err(anyhow!("The flash does not contain the file."))
+        }
+    })
+}
+
+fn erase_flash(shared_options: &SharedOptions) -> Result<()> {
+    with_device(shared_options, |mut session| {
+        if let Some(side_effects) = erase_all_side_effects(&session) {
+            println!("Note: {}", side_effects);
+        }
+
+        erase_all(&mut session, &FlashProgress::default())?;
+        println!("The flash was erased.");
 
         Ok(())
     })
 }
 
-fn reset_target_of_device(shared_options: &SharedOptions, _assert: Option<bool>) -> Result<()> {
+fn reset_target_of_device(
+    shared_options: &SharedOptions,
+    _assert: Option<bool>,
+    report_reason: bool,
+) -> Result<()> {
     with_device(shared_options, |mut session| {
-        session.core(0)?.reset()?;
+        if report_reason {
+            // The flags are cleared, so the reset below is reported on its own next time.
+            let reason = session.reset_reason(0, true)?;
+            println!("The previous reset was caused by: {:?}", reason);
+        }
+
+        session.reset(0)?;
 
         Ok(())
     })
@@ -193,7 +639,6 @@ fn trace_u32_on_target(shared_options: &SharedOptions, loc: u32) -> Result<()> {
     use scroll::{Pwrite, LE};
     use std::io::prelude::*;
     use std::thread::sleep;
-    use std::time::Duration;
 
     let mut xs = vec![];
     let mut ys = vec![];
@@ -209,7 +654,7 @@ fn trace_u32_on_target(shared_options: &SharedOptions, loc: u32) -> Result<()> {
             let instant = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis());
 
             // Read data.
-            let value: u32 = core.read_word_32(loc)?;
+            let value: u32 = core.read_word_32(loc.into())?;
 
             xs.push(instant);
             ys.push(value);
@@ -233,18 +678,92 @@ fn trace_u32_on_target(shared_options: &SharedOptions, loc: u32) -> Result<()> {
     })
 }
 
-fn debug(shared_options: &SharedOptions, exe: Option<PathBuf>) -> Result<()> {
-    let runner = |mut session: Session| {
-        let cs = Capstone::new()
-            .arm()
-            .mode(ArchMode::Thumb)
-            .endian(Endian::Little)
-            .build()
-            .map_err(|err| anyhow!("Error creating capstone: {:?}", err))?;
+fn profile(
+    shared_options: &SharedOptions,
+    exe: Option<PathBuf>,
+    debug_file: &DebugFileOptions,
+    duration: Duration,
+    config: SwoConfig,
+    postpreset: u8,
+    top: usize,
+) -> Result<()> {
+    if postpreset > 15 {
+        return Err(anyhow!(
+            "Unsupported postpreset {}, expected 0 to 15",
+            postpreset
+        ));
+    }
+
+    let debug_info = exe.map(|exe| debug_file.load(&exe)).transpose()?;
 
-        let di = exe
-            .as_ref()
-            .and_then(|path| DebugInfo::from_file(path).ok());
+    with_device(shared_options, |mut session| {
+        session.enable_swo(&config)?;
+        session.enable_swv_pc_sampling(postpreset)?;
+
+        let mut profile = PcProfile::new();
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            profile.add_packets(&session.read_swo()?);
+        }
+        let elapsed = start.elapsed();
+
+        session.disable_swv_pc_sampling()?;
+        session.disable_swv()?;
+
+        let interval = pc_sampling_interval(postpreset);
+        println!(
+            "Sampled the PC every {} cycles, {:.0} samples per second at {} Hz.",
+            interval,
+            f64::from(config.tpiu_clk()) / f64::from(interval),
+            config.tpiu_clk()
+        );
+        println!(
+            "Received {} samples in {:.2?} ({:.0} samples per second).",
+            profile.sample_count(),
+            elapsed,
+            profile.sample_count() as f64 / elapsed.as_secs_f64()
+        );
+
+        if profile.sample_count() == 0 {
+            println!("No samples were received, check the clock and the baud rate.");
+            return Ok(());
+        }
+
+        let entries = profile.entries(|pc| {
+            debug_info
+                .as_ref()
+                .and_then(|debug_info| debug_info.function_name(pc.into()))
+        });
+
+        println!();
+        println!("{:>8} {:>7}  function", "samples", "%");
+        for entry in entries.iter().take(top) {
+            let location = match &entry.location {
+                ProfileLocation::Function(name) => name.clone(),
+                ProfileLocation::Unknown(address) => format!("unknown ({:#010x})", address),
+            };
+            println!(
+                "{:>8} {:>6.2}%  {}",
+                entry.samples,
+                100.0 * entry.samples as f64 / profile.sample_count() as f64,
+                location
+            );
+        }
+        if entries.len() > top {
+            println!("... and {} more", entries.len() - top);
+        }
+
+        Ok(())
+    })
+}
+
+fn debug(
+    shared_options: &SharedOptions,
+    exe: Option<PathBuf>,
+    debug_file: &DebugFileOptions,
+) -> Result<()> {
+    let runner = |mut session: Session| {
+        let di = exe.as_ref().map(|path| debug_file.load(path)).transpose()?;
 
         let cli = debugger::DebugCli::new();
 
@@ -253,7 +772,6 @@ fn debug(shared_options: &SharedOptions, exe: Option<PathBuf>) -> Result<()> {
         let mut cli_data = debugger::CliData {
             core,
             debug_info: di,
-            capstone: cs,
         };
 
         let mut rl = Editor::<()>::new();
@@ -0,0 +1,110 @@
+//! Flashing with the `vFlashErase`, `vFlashWrite` and `vFlashDone` packets.
+//!
+//! GDB uses these packets for the memory regions announced as flash in the memory map.
+//! The written data is collected, and flashed with the flash loader once GDB sends `vFlashDone`,
+//! which erases and programs whole sectors as required.
+
+use anyhow::{anyhow, Result};
+use std::{collections::BTreeMap, convert::TryFrom};
+
+/// The data written with `vFlashWrite` since the last `vFlashDone`.
+#[derive(Debug, Default)]
+pub(crate) struct FlashImage {
+    /// Non-overlapping and non-adjacent blocks of data, by their start address.
+    blocks: BTreeMap<u32, Vec<u8>>,
+}
+
+impl FlashImage {
+    /// Adds `data` at `address`, which replaces previously written data it overlaps with.
+    ///
+    /// Fails if the data does not fit into the 32-bit address space.
+    pub(crate) fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let end = u32::try_from(data.len())
+            .ok()
+            .and_then(|len| address.checked_add(len))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Writing {} bytes at {:#010x} exceeds the address space",
+                    data.len(),
+                    address
+                )
+            })?;
+
+        // All blocks touching the new data are merged into a single block.
+        let touching: Vec<u32> = self
+            .blocks
+            .range(..=end)
+            .filter(|(start, block)| *start + block.len() as u32 >= address)
+            .map(|(start, _)| *start)
+            .collect();
+
+        let start = touching
+            .first()
+            .map_or(address, |first| address.min(*first));
+        let merged_end = touching
+            .last()
+            .map_or(end, |last| end.max(*last + self.blocks[last].len() as u32));
+
+        let mut merged = vec![0; (merged_end - start) as usize];
+        for block_start in touching {
+            let block = self.blocks.remove(&block_start).unwrap();
+            let offset = (block_start - start) as usize;
+            merged[offset..offset + block.len()].copy_from_slice(&block);
+        }
+
+        let offset = (address - start) as usize;
+        merged[offset..offset + data.len()].copy_from_slice(data);
+
+        self.blocks.insert(start, merged);
+        Ok(())
+    }
+
+    /// Returns the collected blocks sorted by address, and clears the image.
+    pub(crate) fn take_blocks(&mut self) -> Vec<(u32, Vec<u8>)> {
+        std::mem::take(&mut self.blocks).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn disjoint_writes_are_kept_apart() {
+        let mut image = FlashImage::default();
+        image.write(0x100, &[1, 2]).unwrap();
+        image.write(0x000, &[3]).unwrap();
+
+        assert_eq!(
+            image.take_blocks(),
+            vec![(0x000, vec![3]), (0x100, vec![1, 2])]
+        );
+        assert!(image.take_blocks().is_empty());
+    }
+
+    #[test]
+    fn writes_past_the_address_space_are_rejected() {
+        let mut image = FlashImage::default();
+        assert!(image.write(0xffff_fffe, &[1, 2, 3]).is_err());
+        assert!(image.take_blocks().is_empty());
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_writes_are_merged() {
+        let mut image = FlashImage::default();
+        image.write(0x10, &[1, 1, 1, 1]).unwrap();
+        image.write(0x18, &[2, 2]).unwrap();
+        // Fills the gap, and overwrites the end of the first and the start of the second write.
+        image.write(0x12, &[3, 3, 3, 3, 3, 3, 3]).unwrap();
+        image.write(0x1a, &[4]).unwrap();
+
+        assert_eq!(
+            image.take_blocks(),
+            vec![(0x10, vec![1, 1, 3, 3, 3, 3, 3, 3, 3, 2, 4])]
+        );
+    }
+}
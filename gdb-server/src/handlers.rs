@@ -1,8 +1,21 @@
-use probe_rs::{config::MemoryRegion, Core, MemoryInterface, Session};
+use crate::flash::FlashImage;
+use crate::parser::v_packet::Action;
+use crate::parser::{ThreadId, ThreadOperation};
+use crate::threads::Threads;
+use probe_rs::flashing::{download_data, sector_layout, DownloadOptions};
+use probe_rs::{
+    config::MemoryRegion, Architecture, Core, CoreAvailability, CoreStatus, MemoryInterface,
+    RangeStepMode, Session,
+};
 use std::time::Duration;
 
+/// The maximum time a single range step may take.
+const RANGE_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub(crate) fn q_supported() -> Option<String> {
-    Some("PacketSize=2048;swbreak-;hwbreak+;vContSupported+;qXfer:memory-map:read+".into())
+    Some(
+        "PacketSize=2048;swbreak-;hwbreak+;vContSupported+;qXfer:memory-map:read+;QNonStop+".into(),
+    )
 }
 
 pub(crate) fn reply_empty() -> Option<String> {
@@ -13,8 +26,134 @@ pub(crate) fn q_attached() -> Option<String> {
     Some("1".into())
 }
 
-pub(crate) fn halt_reason() -> Option<String> {
-    Some("S05".into())
+/// Returns the stop reply for `signal` on `core`.
+pub(crate) fn stop_reply(signal: u8, core: usize) -> String {
+    format!("T{:02x}thread:{:x};", signal, Threads::thread_id(core))
+}
+
+/// Returns core `n`, or `None` if it can't be accessed, e.g. because it is powered down.
+pub(crate) fn available_core(session: &mut Session, n: usize) -> Option<Core<'_>> {
+    let mut core = session.core(n).ok()?;
+    core.status().ok()?;
+    Some(core)
+}
+
+pub(crate) fn halt_reason(
+    session: &mut Session,
+    threads: &mut Threads,
+    non_stop: bool,
+) -> Option<String> {
+    if !non_stop {
+        return Some(stop_reply(5, threads.general));
+    }
+
+    // In non-stop mode, every halted core is reported, the first one in the reply
+    // and the others in the replies to `vStopped`.
+    let stop_replies = (0..threads.count())
+        .filter(|core| {
+            matches!(
+                available_core(session, *core).map(|mut core| core.core_halted()),
+                Some(Ok(true))
+            )
+        })
+        .map(|core| stop_reply(5, core))
+        .collect();
+
+    Some(threads.report_stops(stop_replies))
+}
+
+pub(crate) fn current_thread(threads: &Threads) -> Option<String> {
+    Some(format!("QC{:x}", Threads::thread_id(threads.general)))
+}
+
+pub(crate) fn thread_info_first(threads: &Threads) -> Option<String> {
+    let ids: Vec<String> = (0..threads.count())
+        .map(|core| format!("{:x}", Threads::thread_id(core)))
+        .collect();
+
+    Some(format!("m{}", ids.join(",")))
+}
+
+pub(crate) fn thread_info_next() -> Option<String> {
+    // All threads are listed in the reply to `qfThreadInfo`.
+    Some("l".into())
+}
+
+pub(crate) fn thread_extra_info(
+    session: &mut Session,
+    threads: &Threads,
+    thread: ThreadId,
+) -> Option<String> {
+    let core = match threads.core(thread, threads.general) {
+        Some(core) => core,
+        None => return Some("E01".into()),
+    };
+
+    let info = &session.list_cores()[core];
+    let state = match info.availability {
+        CoreAvailability::Available(CoreStatus::Running) => "running",
+        CoreAvailability::Available(CoreStatus::Halted(_)) => "halted",
+        CoreAvailability::Available(CoreStatus::Sleeping) => "sleeping",
+        CoreAvailability::Available(CoreStatus::Unknown) => "unknown",
+        CoreAvailability::Unavailable => "unavailable",
+    };
+
+    let description = format!("{} ({})", info.name, state);
+    Some(
+        description
+            .bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+    )
+}
+
+pub(crate) fn select_thread(
+    session: &mut Session,
+    threads: &mut Threads,
+    operation: ThreadOperation,
+    thread: ThreadId,
+) -> Option<String> {
+    match operation {
+        ThreadOperation::General => match threads.core(thread, threads.general) {
+            Some(core) if available_core(session, core).is_some() => {
+                threads.general = core;
+                Some("OK".into())
+            }
+            Some(core) => {
+                log::warn!("Core {} is unavailable, it can't be selected", core);
+                Some("E01".into())
+            }
+            None => Some("E01".into()),
+        },
+        ThreadOperation::Continue => match threads.core(thread, threads.resume) {
+            Some(core) => {
+                threads.resume = core;
+                Some("OK".into())
+            }
+            None => Some("E01".into()),
+        },
+    }
+}
+
+pub(crate) fn thread_alive(threads: &Threads, thread: ThreadId) -> Option<String> {
+    // Unavailable cores are still listed, their state is shown by `qThreadExtraInfo`.
+    match threads.core(thread, threads.general) {
+        Some(_) => Some("OK".into()),
+        None => Some("E01".into()),
+    }
+}
+
+/// Returns an error reply if the memory of `core` can't be accessed right now.
+///
+/// ARM cores allow accessing memory while they are running, RISC-V cores only while halted.
+fn memory_busy(core: &mut Core) -> Option<String> {
+    if core.architecture() == Architecture::Riscv && !core.core_halted().unwrap_or(false) {
+        log::warn!("The memory of a running RISC-V core can't be accessed, halt the core first");
+        // EBUSY
+        return Some("E10".into());
+    }
+
+    None
 }
 
 pub(crate) fn read_general_registers(mut core: Core) -> Option<String> {
@@ -69,8 +208,12 @@ pub(crate) fn read_register(register: u32, mut core: Core) -> Option<String> {
 }
 
 pub(crate) fn read_memory(address: u32, length: u32, mut core: Core) -> Option<String> {
+    if let Some(error) = memory_busy(&mut core) {
+        return Some(error);
+    }
+
     let mut readback_data = vec![0u8; length as usize];
-    match core.read_8(address, &mut readback_data) {
+    match core.read(address.into(), &mut readback_data) {
         Ok(_) => Some(
             readback_data
                 .iter()
@@ -90,7 +233,7 @@ pub(crate) fn vcont_supported() -> Option<String> {
     // the variants with and without signal support,
     // i.e. both c and C, otherwise GDB will not use
     // the command.
-    Some("vCont;c;C;t;s;S".into())
+    Some("vCont;c;C;t;s;S;r".into())
 }
 
 pub(crate) fn host_info() -> Option<String> {
@@ -112,26 +255,136 @@ pub(crate) fn stop(mut core: Core, awaits_halt: &mut bool) -> Option<String> {
     Some("OK".into())
 }
 
-pub(crate) fn step(mut core: Core, awaits_halt: &mut bool) -> Option<String> {
+pub(crate) fn step(mut core: Core, core_index: usize, awaits_halt: &mut bool) -> Option<String> {
     core.step().unwrap();
     *awaits_halt = false;
-    Some("S05".into())
+    Some(stop_reply(5, core_index))
+}
+
+/// Steps until the program counter leaves `start..end`.
+///
+/// GDB handles calls itself, so the core is stopped at the first instruction of a called function.
+pub(crate) fn range_step(
+    mut core: Core,
+    core_index: usize,
+    start: u32,
+    end: u32,
+    awaits_halt: &mut bool,
+) -> Option<String> {
+    *awaits_halt = false;
+
+    if let Err(e) = core.step_range(start..end, RangeStepMode::Into, RANGE_STEP_TIMEOUT) {
+        log::warn!("Failed to step: {}", e);
+    }
+
+    Some(stop_reply(5, core_index))
+}
+
+/// Runs `action` on the cores selected by `thread`.
+///
+/// In non-stop mode, the stop replies are sent as notifications instead.
+pub(crate) fn v_cont(
+    session: &mut Session,
+    threads: &mut Threads,
+    non_stop: bool,
+    action: Action,
+    thread: Option<ThreadId>,
+    notification: &mut Option<String>,
+) -> Option<String> {
+    // Only the selected thread is stepped if no thread is given, but all threads are continued.
+    let thread = match action {
+        Action::Step | Action::RangeStep { .. } => Some(thread.unwrap_or(ThreadId::Any)),
+        _ => thread,
+    };
+    let cores = match threads.cores(thread) {
+        Some(cores) => cores,
+        None => return Some("E01".into()),
+    };
+
+    let mut replies = vec![];
+    for index in cores {
+        let core = match available_core(session, index) {
+            Some(core) => core,
+            None => {
+                log::debug!("Skipping unavailable core {}", index);
+                continue;
+            }
+        };
+        let awaits_halt = &mut threads.awaits_halt[index];
+
+        replies.push(match action {
+            Action::Continue => run(core, awaits_halt),
+            Action::Stop if non_stop => {
+                stop(core, awaits_halt);
+                Some(stop_reply(0, index))
+            }
+            Action::Stop => stop(core, awaits_halt),
+            Action::Step => step(core, index, awaits_halt),
+            Action::RangeStep { start, end } => range_step(core, index, start, end, awaits_halt),
+            Action::ContinueSignal(_) | Action::StepSignal => {
+                log::warn!("vCont with action {:?} not supported", action);
+                return reply_empty();
+            }
+        });
+    }
+
+    if non_stop {
+        for stop_reply in replies.into_iter().flatten() {
+            if let Some(stop_notification) = threads.notify_stop(stop_reply) {
+                *notification = Some(stop_notification);
+            }
+        }
+        Some("OK".into())
+    } else {
+        replies.pop().unwrap_or_else(|| Some("E01".into()))
+    }
 }
 
-pub(crate) fn insert_hardware_break(address: u32, _kind: u32, mut core: Core) -> Option<String> {
-    core.set_hw_breakpoint(address).unwrap();
+/// Sets the breakpoint on every core, as GDB expects breakpoints to apply to all threads.
+pub(crate) fn insert_hardware_break(
+    address: u32,
+    _kind: u32,
+    session: &mut Session,
+) -> Option<String> {
+    for n in 0..session.list_cores().len() {
+        if let Some(mut core) = available_core(session, n) {
+            if let Err(e) = core.set_hw_breakpoint(address) {
+                log::warn!("Failed to set a breakpoint on core {}: {}", n, e);
+                return Some("E01".into());
+            }
+        }
+    }
     Some("OK".into())
 }
 
-pub(crate) fn remove_hardware_break(address: u32, _kind: u32, mut core: Core) -> Option<String> {
-    core.clear_hw_breakpoint(address).unwrap();
+pub(crate) fn remove_hardware_break(
+    address: u32,
+    _kind: u32,
+    session: &mut Session,
+) -> Option<String> {
+    for n in 0..session.list_cores().len() {
+        if let Some(mut core) = available_core(session, n) {
+            if let Err(e) = core.clear_hw_breakpoint(address) {
+                log::warn!("Failed to clear a breakpoint on core {}: {}", n, e);
+                return Some("E01".into());
+            }
+        }
+    }
     Some("OK".into())
 }
 
 pub(crate) fn write_memory(address: u32, data: &[u8], mut core: Core) -> Option<String> {
-    core.write_8(address, data).unwrap();
+    if let Some(error) = memory_busy(&mut core) {
+        return Some(error);
+    }
 
-    Some("OK".into())
+    match core.write(address.into(), data) {
+        Ok(()) => Some("OK".into()),
+        Err(e) => {
+            log::error!("Failed to write to {:#010x}: {}", address, e);
+            Some("E79".into())
+        }
+    }
 }
 
 pub(crate) fn get_memory_map(session: &Session) -> Option<String> {
@@ -153,12 +406,28 @@ pub(crate) fn get_memory_map(session: &Session) -> Option<String> {
                 region.range.end - region.range.start
             ),
             MemoryRegion::Flash(region) => {
-                // TODO: Use flash with block size
-                format!(
-                    r#"<memory type="rom" start="{:#x}" length="{:#x}"/>\n"#,
-                    region.range.start,
-                    region.range.end - region.range.start
-                )
+                let layout = sector_layout(session, region);
+                if layout.is_empty() {
+                    // Without a flash algorithm, the flash cannot be written by GDB.
+                    format!(
+                        r#"<memory type="rom" start="{:#x}" length="{:#x}"/>\n"#,
+                        region.range.start,
+                        region.range.end - region.range.start
+                    )
+                } else {
+                    // GDB uses the vFlash packets for flash, erasing in multiples of the block size.
+                    layout
+                        .iter()
+                        .map(|(range, sector_size)| {
+                            format!(
+                                r#"<memory type="flash" start="{:#x}" length="{:#x}"><property name="blocksize">{:#x}</property></memory>\n"#,
+                                range.start,
+                                range.end - range.start,
+                                sector_size
+                            )
+                        })
+                        .collect()
+                }
             }
         };
 
@@ -169,19 +438,75 @@ pub(crate) fn get_memory_map(session: &Session) -> Option<String> {
     Some(String::from_utf8(gdb_sanitize_file(xml_map.as_bytes(), 0, 1000)).unwrap())
 }
 
-pub(crate) fn user_halt(mut core: Core, awaits_halt: &mut bool) -> Option<String> {
-    let _ = core.halt(Duration::from_millis(100));
-    *awaits_halt = false;
-    Some("T02".into())
+pub(crate) fn flash_erase(address: u32, length: u32) -> Option<String> {
+    // The sectors are erased by the flash loader when the data is flashed on `vFlashDone`,
+    // GDB always erases the sectors which it writes afterwards.
+    log::debug!(
+        "Erase of {:#010x}..{:#010x} requested",
+        address,
+        address + length
+    );
+    Some("OK".into())
 }
 
-pub(crate) fn detach(break_due: &mut bool) -> Option<String> {
-    *break_due = true;
-    Some("OK".into())
+pub(crate) fn flash_write(
+    address: u32,
+    data: &[u8],
+    session: &mut Session,
+    image: &mut FlashImage,
+) -> Option<String> {
+    let is_flash = session.memory_map().iter().any(
+        |region| matches!(region, MemoryRegion::Flash(flash) if flash.range.contains(&address)),
+    );
+
+    if is_flash {
+        match image.write(address, data) {
+            Ok(()) => Some("OK".into()),
+            Err(e) => {
+                log::error!("{}", e);
+                Some("E79".into())
+            }
+        }
+    } else {
+        // Writes outside of the flash, for example into RAM, are done immediately.
+        match session
+            .core(0)
+            .and_then(|mut core| core.write(address.into(), data))
+        {
+            Ok(()) => Some("OK".into()),
+            Err(e) => {
+                log::error!("Failed to write to {:#010x}: {}", address, e);
+                Some("E79".into())
+            }
+        }
+    }
 }
 
-pub(crate) fn reset_halt(mut core: Core) -> Option<String> {
-    let _cpu_info = core.reset_and_halt(Duration::from_millis(400));
+pub(crate) fn flash_done(session: &mut Session, image: &mut FlashImage) -> Option<String> {
+    let blocks = image.take_blocks();
+
+    match download_data(session, &blocks, DownloadOptions::default()) {
+        Ok(_) => Some("OK".into()),
+        Err(e) => {
+            log::error!("Failed to flash the data written by GDB: {}", e);
+            // EIO, see the comment in `read_memory` on the choice of error codes.
+            Some("E05".into())
+        }
+    }
+}
+
+pub(crate) fn user_halt(session: &mut Session, threads: &mut Threads) -> Option<String> {
+    for index in 0..threads.count() {
+        if let Some(mut core) = available_core(session, index) {
+            let _ = core.halt(Duration::from_millis(100));
+        }
+        threads.awaits_halt[index] = false;
+    }
+    Some(stop_reply(2, threads.general))
+}
+
+pub(crate) fn detach(break_due: &mut bool) -> Option<String> {
+    *break_due = true;
     Some("OK".into())
 }
 
@@ -1,9 +1,12 @@
 #![recursion_limit = "256"]
 
+mod flash;
 mod gdb_server_async;
 mod handlers;
+mod monitor;
 mod parser;
 mod reader;
+mod threads;
 mod worker;
 mod writer;
 
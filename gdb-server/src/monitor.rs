@@ -0,0 +1,107 @@
+//! Handling of `monitor` commands.
+//!
+//! GDB sends `monitor <command>` as a `qRcmd` packet. The commands are looked up
+//! in [`COMMANDS`], to add a new command, simply add an entry to the table.
+
+use anyhow::Result;
+use probe_rs::Session;
+use std::time::Duration;
+
+/// A command which can be issued with `monitor <name>` from GDB.
+struct MonitorCommand {
+    /// The words of the command, e.g. `reset halt`.
+    name: &'static str,
+    help: &'static str,
+    /// Executes the command, returning the text which is displayed in GDB.
+    handler: fn(&mut Session) -> Result<String>,
+}
+
+const COMMANDS: &[MonitorCommand] = &[
+    MonitorCommand {
+        name: "help",
+        help: "Show the list of available monitor commands",
+        handler: help,
+    },
+    MonitorCommand {
+        name: "reset",
+        help: "Reset the core and halt it",
+        handler: reset_halt,
+    },
+    MonitorCommand {
+        name: "reset halt",
+        help: "Reset the core and halt it",
+        handler: reset_halt,
+    },
+];
+
+/// Finds the command matching the given command line.
+///
+/// Words are separated by whitespace, and all words of the command line have to match.
+fn find_command(command_line: &str) -> Option<&'static MonitorCommand> {
+    let words: Vec<_> = command_line.split_whitespace().collect();
+
+    COMMANDS
+        .iter()
+        .find(|command| command.name.split_whitespace().eq(words.iter().copied()))
+}
+
+/// Handles a `qRcmd` packet with the given, already hex decoded command.
+pub(crate) fn handle_command(session: &mut Session, command: &[u8]) -> Option<String> {
+    let command_line = String::from_utf8_lossy(command);
+
+    let command = match find_command(&command_line) {
+        Some(command) => command,
+        None => {
+            log::debug!("Unknown monitor command: '{}'", command_line);
+            // An empty response tells GDB that the command is not supported.
+            return Some("".into());
+        }
+    };
+
+    let output = match (command.handler)(session) {
+        Ok(output) => output,
+        Err(e) => format!("Command '{}' failed: {:?}\n", command.name, e),
+    };
+
+    if output.is_empty() {
+        Some("OK".into())
+    } else {
+        Some(hex::encode(output.as_bytes()))
+    }
+}
+
+fn help(_session: &mut Session) -> Result<String> {
+    let mut output = String::from("Available monitor commands:\n");
+
+    for command in COMMANDS {
+        output.push_str(&format!("  {:<12} {}\n", command.name, command.help));
+    }
+
+    Ok(output)
+}
+
+fn reset_halt(session: &mut Session) -> Result<String> {
+    session
+        .core(0)?
+        .reset_and_halt(Duration::from_millis(400))?;
+    Ok(String::new())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_multi_word_command() {
+        assert_eq!(find_command("reset halt").unwrap().name, "reset halt");
+        assert_eq!(find_command("  reset   halt ").unwrap().name, "reset halt");
+        assert_eq!(find_command("reset").unwrap().name, "reset");
+    }
+
+    #[test]
+    fn unknown_command_is_not_found() {
+        assert!(find_command("reset now").is_none());
+        assert!(find_command("rtt start").is_none());
+        assert!(find_command("").is_none());
+    }
+}
@@ -21,7 +21,8 @@ use query::query_packet;
 use v_packet::v_packet;
 
 pub use query::{Pid, QueryPacket};
-use util::hex_u64;
+pub use util::ThreadId;
+use util::{hex_u64, thread_id};
 pub use v_packet::VPacket;
 
 #[allow(dead_code)]
@@ -60,7 +61,10 @@ pub enum Packet {
     /// Packet `G`
     WriteGeneralRegister,
     /// Packet `H`
-    SelectThread,
+    SelectThread {
+        operation: ThreadOperation,
+        thread: ThreadId,
+    },
     /// Packet `i`
     StepClockCycle,
     /// Packet `I`
@@ -81,7 +85,7 @@ pub enum Packet {
     // Packet 'q'
     Query(QueryPacket),
     // Packet 'Q'
-    QuerySet,
+    QuerySet(QuerySetPacket),
     // Packet 'r'
     Reset,
     // Packet 'R'
@@ -93,7 +97,7 @@ pub enum Packet {
     // Packet 't'
     SearchBackwards,
     // Packet 'T'
-    ThreadInfo,
+    ThreadAlive(ThreadId),
     // Packet 'v'
     V(VPacket),
     // Packet 'X'
@@ -117,6 +121,21 @@ pub enum Packet {
     Interrupt,
 }
 
+/// The operations a thread is selected for with the `H` packet.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ThreadOperation {
+    /// `Hg`, for register and memory accesses.
+    General,
+    /// `Hc`, for stepping and continuing.
+    Continue,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum QuerySetPacket {
+    /// Packet `QNonStop`, which enables or disables non-stop mode.
+    NonStop(bool),
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum BreakpointType {
     Software,
@@ -135,6 +154,9 @@ pub fn parse_packet(input: &[u8]) -> Result<Packet> {
         read_register_hex,
         read_memory,
         query,
+        query_set,
+        select_thread,
+        thread_alive,
         v,
         insert_breakpoint,
         remove_breakpoint,
@@ -184,6 +206,32 @@ fn query(input: &[u8]) -> IResult<&[u8], Packet> {
     Ok((input, Packet::Query(packet)))
 }
 
+fn query_set(input: &[u8]) -> IResult<&[u8], Packet> {
+    let (input, _) = char('Q')(input)?;
+    let (input, _) = tag("NonStop:")(input)?;
+    let (input, enabled) = alt((value(false, char('0')), value(true, char('1'))))(input)?;
+
+    Ok((input, Packet::QuerySet(QuerySetPacket::NonStop(enabled))))
+}
+
+fn select_thread(input: &[u8]) -> IResult<&[u8], Packet> {
+    let (input, _) = char('H')(input)?;
+    let (input, operation) = alt((
+        value(ThreadOperation::General, char('g')),
+        value(ThreadOperation::Continue, char('c')),
+    ))(input)?;
+    let (input, thread) = thread_id(input)?;
+
+    Ok((input, Packet::SelectThread { operation, thread }))
+}
+
+fn thread_alive(input: &[u8]) -> IResult<&[u8], Packet> {
+    let (input, _) = char('T')(input)?;
+    let (input, thread) = thread_id(input)?;
+
+    Ok((input, Packet::ThreadAlive(thread)))
+}
+
 fn v(input: &[u8]) -> IResult<&[u8], Packet> {
     let (input, _) = char('v')(input)?;
 
@@ -299,6 +347,31 @@ mod test {
             ("qSupported", Packet::Query(QueryPacket::Supported(vec![]))),
             ("qHostInfo", Packet::Query(QueryPacket::HostInfo)),
             ("vCont?", Packet::V(VPacket::QueryContSupport)),
+            (
+                "QNonStop:1",
+                Packet::QuerySet(QuerySetPacket::NonStop(true)),
+            ),
+            ("vStopped", Packet::V(VPacket::Stopped)),
+            (
+                "Hg2",
+                Packet::SelectThread {
+                    operation: ThreadOperation::General,
+                    thread: ThreadId::Id(2),
+                },
+            ),
+            (
+                "Hc-1",
+                Packet::SelectThread {
+                    operation: ThreadOperation::Continue,
+                    thread: ThreadId::All,
+                },
+            ),
+            ("T1", Packet::ThreadAlive(ThreadId::Id(1))),
+            ("qfThreadInfo", Packet::Query(QueryPacket::ThreadInfoFirst)),
+            (
+                "qThreadExtraInfo,2",
+                Packet::Query(QueryPacket::ThreadExtraInfo(ThreadId::Id(2))),
+            ),
             (
                 "vMustReplyEmpty",
                 Packet::V(VPacket::Unknown("MustReplyEmpty".into())),
@@ -1,9 +1,9 @@
-use super::util::hex_bytes;
+use super::util::{hex_bytes, thread_id, ThreadId};
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_until, take_while},
     character::complete::char,
-    combinator::{all_consuming, opt, peek},
+    combinator::{all_consuming, opt, peek, value},
     error::ErrorKind,
     multi::separated_list1,
     number::complete::hex_u32,
@@ -14,6 +14,12 @@ use nom::{
 #[derive(Debug, PartialEq, Clone)]
 pub enum QueryPacket {
     ThreadId,
+    /// Packet `qfThreadInfo`, which requests the first part of the thread list.
+    ThreadInfoFirst,
+    /// Packet `qsThreadInfo`, which requests the rest of the thread list.
+    ThreadInfoNext,
+    /// Packet `qThreadExtraInfo`, which requests a description of the thread.
+    ThreadExtraInfo(ThreadId),
     Attached(Option<Pid>),
     Command(Vec<u8>),
     Supported(Vec<String>),
@@ -53,6 +59,8 @@ pub fn pid(input: &[u8]) -> IResult<&[u8], Pid> {
 pub fn query_packet(input: &[u8]) -> IResult<&[u8], QueryPacket> {
     let (input, query_packet) = alt((
         query_thread_id,
+        query_thread_info,
+        query_thread_extra_info,
         query_attached,
         query_command,
         query_crc,
@@ -73,6 +81,20 @@ fn query_thread_id(input: &[u8]) -> IResult<&[u8], QueryPacket> {
     Ok((input, QueryPacket::ThreadId))
 }
 
+fn query_thread_info(input: &[u8]) -> IResult<&[u8], QueryPacket> {
+    alt((
+        value(QueryPacket::ThreadInfoFirst, tag("fThreadInfo")),
+        value(QueryPacket::ThreadInfoNext, tag("sThreadInfo")),
+    ))(input)
+}
+
+fn query_thread_extra_info(input: &[u8]) -> IResult<&[u8], QueryPacket> {
+    let (input, _) = tag("ThreadExtraInfo,")(input)?;
+    let (input, thread) = thread_id(input)?;
+
+    Ok((input, QueryPacket::ThreadExtraInfo(thread)))
+}
+
 fn query_command(input: &[u8]) -> IResult<&[u8], QueryPacket> {
     let (input, _) = tag("Rcmd,")(input)?;
 
@@ -1,4 +1,33 @@
-use nom::{bytes::complete::take_while_m_n, character::is_hex_digit, multi::many1, IResult};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while_m_n},
+    character::is_hex_digit,
+    combinator::{map, value},
+    multi::many1,
+    number::complete::hex_u32,
+    IResult,
+};
+
+/// A thread id, as used by the `H`, `T` and `vCont` packets.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ThreadId {
+    /// `-1`, which selects all threads.
+    All,
+    /// `0`, which selects an arbitrary thread.
+    Any,
+    Id(u32),
+}
+
+/// Parse a thread id, which is either `-1` or a hex number.
+pub fn thread_id(input: &[u8]) -> IResult<&[u8], ThreadId> {
+    alt((
+        value(ThreadId::All, tag("-1")),
+        map(hex_u32, |id| match id {
+            0 => ThreadId::Any,
+            id => ThreadId::Id(id),
+        }),
+    ))(input)
+}
 
 /// Parse bytes encoded as a ASCII hex string.
 ///
@@ -62,6 +91,13 @@ mod test {
         assert_eq!(hex_byte(b"853").unwrap(), ("3".as_bytes(), 0x85));
     }
 
+    #[test]
+    fn parse_thread_id() {
+        assert_eq!(thread_id(b"-1").unwrap(), (EMPTY, ThreadId::All));
+        assert_eq!(thread_id(b"0").unwrap(), (EMPTY, ThreadId::Any));
+        assert_eq!(thread_id(b"1a").unwrap(), (EMPTY, ThreadId::Id(0x1a)));
+    }
+
     #[test]
     fn parse_hex_u64() {
         assert_eq!(hex_u64(b"0").unwrap(), (EMPTY, 0x0));
@@ -1,14 +1,33 @@
+use super::util::{thread_id, ThreadId};
 use super::{query::pid, Pid};
 use nom::{
-    branch::alt, bytes::complete::tag, character::complete::char, combinator::value, IResult,
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::char,
+    combinator::{opt, rest, value},
+    number::complete::hex_u32,
+    sequence::preceded,
+    IResult,
 };
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum VPacket {
     Attach(Pid),
-    Continue(Action),
+    /// Packet `vCont` with the action and the thread it applies to, if given.
+    Continue(Action, Option<ThreadId>),
     Unknown(Vec<u8>),
     QueryContSupport,
+    FlashErase {
+        address: u32,
+        length: u32,
+    },
+    FlashWrite {
+        address: u32,
+        data: Vec<u8>,
+    },
+    FlashDone,
+    /// Packet `vStopped`, which acknowledges a stop notification in non-stop mode.
+    Stopped,
 }
 
 #[allow(dead_code)]
@@ -23,7 +42,15 @@ pub enum Action {
 }
 
 pub fn v_packet(input: &[u8]) -> IResult<&[u8], VPacket> {
-    let parse_result = alt((v_attach, v_cont_support, v_cont))(input);
+    let parse_result = alt((
+        v_attach,
+        v_cont_support,
+        v_cont,
+        v_flash_erase,
+        v_flash_write,
+        v_flash_done,
+        v_stopped,
+    ))(input);
 
     match parse_result {
         Ok((input, packet)) => Ok((input, packet)),
@@ -54,8 +81,46 @@ fn v_cont(input: &[u8]) -> IResult<&[u8], VPacket> {
     let (input, _) = tag("Cont;")(input)?;
 
     let (input, action) = v_cont_action(input)?;
+    let (input, thread) = opt(preceded(char(':'), thread_id))(input)?;
 
-    Ok((input, VPacket::Continue(action)))
+    // Actions for other threads are ignored, they keep their current state.
+    let (input, _) = rest(input)?;
+
+    Ok((input, VPacket::Continue(action, thread)))
+}
+
+fn v_flash_erase(input: &[u8]) -> IResult<&[u8], VPacket> {
+    let (input, _) = tag("FlashErase:")(input)?;
+
+    let (input, address) = hex_u32(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, length) = hex_u32(input)?;
+
+    Ok((input, VPacket::FlashErase { address, length }))
+}
+
+fn v_flash_write(input: &[u8]) -> IResult<&[u8], VPacket> {
+    let (input, _) = tag("FlashWrite:")(input)?;
+
+    let (input, address) = hex_u32(input)?;
+    let (input, _) = char(':')(input)?;
+    let (input, data) = rest(input)?;
+
+    Ok((
+        input,
+        VPacket::FlashWrite {
+            address,
+            data: data.to_owned(),
+        },
+    ))
+}
+
+fn v_flash_done(input: &[u8]) -> IResult<&[u8], VPacket> {
+    value(VPacket::FlashDone, tag("FlashDone"))(input)
+}
+
+fn v_stopped(input: &[u8]) -> IResult<&[u8], VPacket> {
+    value(VPacket::Stopped, tag("Stopped"))(input)
 }
 
 fn v_cont_action(input: &[u8]) -> IResult<&[u8], Action> {
@@ -63,9 +128,19 @@ fn v_cont_action(input: &[u8]) -> IResult<&[u8], Action> {
         value(Action::Continue, char('c')),
         value(Action::Step, char('s')),
         value(Action::Stop, char('t')),
+        v_cont_range_step,
     ))(input)
 }
 
+fn v_cont_range_step(input: &[u8]) -> IResult<&[u8], Action> {
+    let (input, _) = char('r')(input)?;
+    let (input, start) = hex_u32(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, end) = hex_u32(input)?;
+
+    Ok((input, Action::RangeStep { start, end }))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -89,7 +164,7 @@ mod test {
     fn parse_v_cont_cont() {
         assert_eq!(
             v_packet(b"Cont;c").unwrap(),
-            (EMPTY, VPacket::Continue(Action::Continue))
+            (EMPTY, VPacket::Continue(Action::Continue, None))
         );
     }
 
@@ -97,7 +172,7 @@ mod test {
     fn parse_v_cont_step() {
         assert_eq!(
             v_packet(b"Cont;s").unwrap(),
-            (EMPTY, VPacket::Continue(Action::Step))
+            (EMPTY, VPacket::Continue(Action::Step, None))
         );
     }
 
@@ -105,7 +180,60 @@ mod test {
     fn parse_v_cont_stop() {
         assert_eq!(
             v_packet(b"Cont;t").unwrap(),
-            (EMPTY, VPacket::Continue(Action::Stop))
+            (EMPTY, VPacket::Continue(Action::Stop, None))
+        );
+    }
+
+    #[test]
+    fn parse_v_cont_with_thread() {
+        assert_eq!(
+            v_packet(b"Cont;s:2;c").unwrap(),
+            (
+                EMPTY,
+                VPacket::Continue(Action::Step, Some(ThreadId::Id(2)))
+            )
+        );
+    }
+
+    #[test]
+    fn parse_v_cont_range_step() {
+        assert_eq!(
+            v_packet(b"Cont;r1000,1010:1").unwrap(),
+            (
+                EMPTY,
+                VPacket::Continue(
+                    Action::RangeStep {
+                        start: 0x1000,
+                        end: 0x1010
+                    },
+                    Some(ThreadId::Id(1))
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn parse_v_flash() {
+        assert_eq!(
+            v_packet(b"FlashErase:08000000,4000").unwrap(),
+            (
+                EMPTY,
+                VPacket::FlashErase {
+                    address: 0x0800_0000,
+                    length: 0x4000
+                }
+            )
+        );
+        assert_eq!(
+            v_packet(b"FlashWrite:08000010:\x01:\x02").unwrap(),
+            (
+                EMPTY,
+                VPacket::FlashWrite {
+                    address: 0x0800_0010,
+                    data: b"\x01:\x02".to_vec()
+                }
+            )
         );
+        assert_eq!(v_packet(b"FlashDone").unwrap(), (EMPTY, VPacket::FlashDone));
     }
 }
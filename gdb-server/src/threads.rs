@@ -0,0 +1,146 @@
+//! The cores of the target, as threads of GDB.
+//!
+//! Each core is a thread whose id is the number of the core plus one,
+//! as GDB uses the ids 0 and -1 to select any and all threads.
+
+use crate::parser::ThreadId;
+use std::collections::VecDeque;
+
+#[derive(Debug)]
+pub(crate) struct Threads {
+    /// The core used for register and memory accesses, selected with `Hg`.
+    pub(crate) general: usize,
+    /// The core stepped if no thread is given, selected with `Hc`.
+    pub(crate) resume: usize,
+    /// Whether a stop reply is awaited, for every core.
+    pub(crate) awaits_halt: Vec<bool>,
+    /// Whether GDB has not yet acknowledged the last stop notification with `vStopped`.
+    notification_pending: bool,
+    /// The stop replies queued while a notification is pending.
+    stops: VecDeque<String>,
+}
+
+impl Threads {
+    pub(crate) fn new(cores: usize) -> Self {
+        Self {
+            general: 0,
+            resume: 0,
+            awaits_halt: vec![false; cores],
+            notification_pending: false,
+            stops: VecDeque::new(),
+        }
+    }
+
+    /// The number of cores.
+    pub(crate) fn count(&self) -> usize {
+        self.awaits_halt.len()
+    }
+
+    /// Returns the thread id of `core`.
+    pub(crate) fn thread_id(core: usize) -> u32 {
+        core as u32 + 1
+    }
+
+    /// Returns the core of `thread`, or `None` if there is no such thread.
+    ///
+    /// `ThreadId::Any` and `ThreadId::All` return `default`.
+    pub(crate) fn core(&self, thread: ThreadId, default: usize) -> Option<usize> {
+        match thread {
+            ThreadId::All | ThreadId::Any => Some(default),
+            ThreadId::Id(id) => (id as usize)
+                .checked_sub(1)
+                .filter(|core| *core < self.count()),
+        }
+    }
+
+    /// Returns the cores selected by `thread`, where `None` selects all cores.
+    pub(crate) fn cores(&self, thread: Option<ThreadId>) -> Option<Vec<usize>> {
+        match thread {
+            None | Some(ThreadId::All) => Some((0..self.count()).collect()),
+            Some(thread) => self.core(thread, self.resume).map(|core| vec![core]),
+        }
+    }
+
+    /// Reports `stop_reply` in non-stop mode.
+    ///
+    /// Returns the notification to send, or `None` if the stop is queued
+    /// until GDB requests it with `vStopped`.
+    pub(crate) fn notify_stop(&mut self, stop_reply: String) -> Option<String> {
+        if self.notification_pending {
+            self.stops.push_back(stop_reply);
+            None
+        } else {
+            self.notification_pending = true;
+            Some(format!("Stop:{}", stop_reply))
+        }
+    }
+
+    /// Returns the reply to `?` in non-stop mode, with the other stop replies queued for `vStopped`.
+    pub(crate) fn report_stops(&mut self, stop_replies: Vec<String>) -> String {
+        self.stops = stop_replies.into();
+        self.next_stop()
+    }
+
+    /// Returns the reply to `vStopped`, which is the next queued stop reply or `OK`.
+    pub(crate) fn next_stop(&mut self) -> String {
+        match self.stops.pop_front() {
+            Some(stop_reply) => {
+                self.notification_pending = true;
+                stop_reply
+            }
+            None => {
+                self.notification_pending = false;
+                "OK".into()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thread_ids_are_cores_plus_one() {
+        let threads = Threads::new(2);
+
+        assert_eq!(Threads::thread_id(1), 2);
+        assert_eq!(threads.core(ThreadId::Id(2), 0), Some(1));
+        assert_eq!(threads.core(ThreadId::Id(3), 0), None);
+        assert_eq!(threads.core(ThreadId::Any, 1), Some(1));
+        assert_eq!(threads.cores(None), Some(vec![0, 1]));
+        assert_eq!(threads.cores(Some(ThreadId::Id(1))), Some(vec![0]));
+    }
+
+    #[test]
+    fn stops_are_queued_until_acknowledged() {
+        let mut threads = Threads::new(2);
+
+        assert_eq!(
+            threads.notify_stop("T05thread:1;".into()),
+            Some("Stop:T05thread:1;".into())
+        );
+        assert_eq!(threads.notify_stop("T05thread:2;".into()), None);
+
+        assert_eq!(threads.next_stop(), "T05thread:2;");
+        assert_eq!(threads.next_stop(), "OK");
+
+        assert!(threads.notify_stop("T00thread:1;".into()).is_some());
+    }
+
+    #[test]
+    fn halt_reasons_are_reported_one_by_one() {
+        let mut threads = Threads::new(2);
+
+        assert_eq!(threads.report_stops(vec![]), "OK");
+        assert_eq!(
+            threads.report_stops(vec!["T05thread:1;".into(), "T05thread:2;".into()]),
+            "T05thread:1;"
+        );
+        // Further stops are queued until all halt reasons have been read.
+        assert_eq!(threads.notify_stop("T05thread:1;".into()), None);
+        assert_eq!(threads.next_stop(), "T05thread:2;");
+        assert_eq!(threads.next_stop(), "T05thread:1;");
+        assert_eq!(threads.next_stop(), "OK");
+    }
+}
@@ -10,7 +10,10 @@ use std::time::Duration;
 
 use crate::parser::parse_packet;
 
+use crate::flash::FlashImage;
 use crate::handlers;
+use crate::monitor;
+use crate::threads::Threads;
 
 type ServerResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 type Sender<T> = mpsc::UnboundedSender<T>;
@@ -21,21 +24,23 @@ pub async fn worker(
     output_stream: Sender<CheckedPacket>,
     session: &mut Session,
 ) -> ServerResult<()> {
-    let mut awaits_halt = false;
+    let mut threads = Threads::new(session.list_cores().len());
+    let mut non_stop = false;
+    let mut flash_image = FlashImage::default();
 
     loop {
         select! {
             potential_packet = input_stream.next().fuse() => {
                 if let Some(packet) = potential_packet {
                     log::warn!("WORKING {}", String::from_utf8_lossy(&packet.data));
-                    if handler(session, &output_stream, &mut awaits_halt, packet).await? {
+                    if handler(session, &output_stream, &mut threads, &mut non_stop, &mut flash_image, packet).await? {
                         break;
                     }
                 } else {
                     break
                 }
             },
-            _ = await_halt(session, &output_stream, &mut awaits_halt).fuse() => {}
+            _ = await_halt(session, &output_stream, &mut threads, non_stop).fuse() => {}
         }
     }
     Ok(())
@@ -44,69 +49,96 @@ pub async fn worker(
 pub async fn handler(
     session: &mut Session,
     output_stream: &Sender<CheckedPacket>,
-    awaits_halt: &mut bool,
+    threads: &mut Threads,
+    non_stop: &mut bool,
+    flash_image: &mut FlashImage,
     packet: CheckedPacket,
 ) -> ServerResult<bool> {
     let parsed_packet = parse_packet(&packet.data);
     let mut break_due = false;
+    // In non-stop mode, the stop reply of a vCont packet is sent as a notification.
+    let mut notification = None;
 
     use crate::parser::v_packet::Action;
     use crate::parser::BreakpointType;
     use crate::parser::Packet::*;
     use crate::parser::QueryPacket;
+    use crate::parser::QuerySetPacket;
     use crate::parser::VPacket;
 
+    let general = threads.general;
+
     let response: Option<String> = match parsed_packet {
         Ok(parsed_packet) => {
             log::debug!("Parsed packet: {:?}", parsed_packet);
             match parsed_packet {
-                HaltReason => handlers::halt_reason(),
-                Continue => handlers::run(session.core(0)?, awaits_halt),
+                HaltReason => handlers::halt_reason(session, threads, *non_stop),
+                QuerySet(QuerySetPacket::NonStop(enabled)) => {
+                    *non_stop = enabled;
+                    Some("OK".into())
+                }
+                V(VPacket::Stopped) => Some(threads.next_stop()),
+                SelectThread { operation, thread } => {
+                    handlers::select_thread(session, threads, operation, thread)
+                }
+                ThreadAlive(thread) => handlers::thread_alive(threads, thread),
+                Query(QueryPacket::ThreadId) => handlers::current_thread(threads),
+                Query(QueryPacket::ThreadInfoFirst) => handlers::thread_info_first(threads),
+                Query(QueryPacket::ThreadInfoNext) => handlers::thread_info_next(),
+                Query(QueryPacket::ThreadExtraInfo(thread)) => {
+                    handlers::thread_extra_info(session, threads, thread)
+                }
+                Continue => handlers::v_cont(
+                    session,
+                    threads,
+                    *non_stop,
+                    Action::Continue,
+                    None,
+                    &mut notification,
+                ),
                 V(VPacket::QueryContSupport) => handlers::vcont_supported(),
+                V(VPacket::FlashErase { address, length }) => {
+                    handlers::flash_erase(address, length)
+                }
+                V(VPacket::FlashWrite { address, data }) => {
+                    handlers::flash_write(address, &data, session, flash_image)
+                }
+                V(VPacket::FlashDone) => handlers::flash_done(session, flash_image),
                 Query(QueryPacket::Supported { .. }) => handlers::q_supported(),
                 Query(QueryPacket::Attached { .. }) => handlers::q_attached(),
-                Query(QueryPacket::Command(cmd)) => {
-                    if cmd == b"reset" {
-                        handlers::reset_halt(session.core(0)?)
-                    } else {
-                        log::debug!("Unknown monitor command: '{:?}'", cmd);
-                        Some(hex::encode(
-                            "Unknown monitor command\nOnly 'reset' is currently supported\n"
-                                .as_bytes(),
-                        ))
-                    }
-                }
+                Query(QueryPacket::Command(cmd)) => monitor::handle_command(session, &cmd),
                 Query(QueryPacket::HostInfo) => handlers::host_info(),
-                ReadGeneralRegister => handlers::read_general_registers(session.core(0)?),
-                ReadRegisterHex(register) => handlers::read_register(register, session.core(0)?),
+                ReadGeneralRegister => handlers::read_general_registers(session.core(general)?),
+                ReadRegisterHex(register) => {
+                    handlers::read_register(register, session.core(general)?)
+                }
                 ReadMemory { address, length } => {
                     // LLDB will send 64 bit addresses, which are not supported by probe-rs
                     // yet.
 
                     if let Ok(address) = u32::try_from(address) {
-                        handlers::read_memory(address, length, session.core(0)?)
+                        handlers::read_memory(address, length, session.core(general)?)
                     } else {
                         //
                         handlers::reply_empty()
                     }
                 }
                 Detach => handlers::detach(&mut break_due),
-                V(VPacket::Continue(action)) => match action {
-                    Action::Continue => handlers::run(session.core(0)?, awaits_halt),
-                    Action::Stop => handlers::stop(session.core(0)?, awaits_halt),
-                    Action::Step => handlers::step(session.core(0)?, awaits_halt),
-                    other => {
-                        log::warn!("vCont with action {:?} not supported", other);
-                        handlers::reply_empty()
-                    }
-                },
+                V(VPacket::Continue(action, thread)) => handlers::v_cont(
+                    session,
+                    threads,
+                    *non_stop,
+                    action,
+                    thread,
+                    &mut notification,
+                ),
                 InsertBreakpoint {
                     breakpoint_type,
                     address,
                     kind,
                 } => match breakpoint_type {
                     BreakpointType::Hardware => {
-                        handlers::insert_hardware_break(address, kind, session.core(0)?)
+                        handlers::insert_hardware_break(address, kind, session)
                     }
                     other => {
                         log::warn!("Breakpoint type {:?} is not supported.", other);
@@ -119,7 +151,7 @@ pub async fn handler(
                     kind,
                 } => match breakpoint_type {
                     BreakpointType::Hardware => {
-                        handlers::remove_hardware_break(address, kind, session.core(0)?)
+                        handlers::remove_hardware_break(address, kind, session)
                     }
                     other => {
                         log::warn!("Breakpoint type {:?} is not supported.", other);
@@ -127,7 +159,7 @@ pub async fn handler(
                     }
                 },
                 WriteMemoryBinary { address, data } => {
-                    handlers::write_memory(address, &data, session.core(0)?)
+                    handlers::write_memory(address, &data, session.core(general)?)
                 }
                 Query(QueryPacket::Transfer { object, operation }) => {
                     use crate::parser::query::TransferOperation;
@@ -145,7 +177,7 @@ pub async fn handler(
                         handlers::reply_empty()
                     }
                 }
-                Interrupt => handlers::user_halt(session.core(0)?, awaits_halt),
+                Interrupt => handlers::user_halt(session, threads),
                 other => {
                     log::warn!("Unknown command: '{:?}'", other);
 
@@ -161,35 +193,61 @@ pub async fn handler(
     };
 
     if let Some(response) = response {
-        let response = CheckedPacket::from_data(PacketKind::Packet, response.into_bytes());
-
-        let mut bytes = Vec::new();
-        response.encode(&mut bytes).unwrap();
-        log::debug!(
-            "Response: '{:x?}'",
-            std::str::from_utf8(&response.data).unwrap()
-        );
-        log::debug!("-----------------------------------------------");
-        output_stream.unbounded_send(response)?;
+        send(output_stream, PacketKind::Packet, response)?;
     };
+    if let Some(notification) = notification {
+        send(output_stream, PacketKind::Notification, notification)?;
+    }
 
     Ok(break_due)
 }
 
+fn send(output_stream: &Sender<CheckedPacket>, kind: PacketKind, data: String) -> ServerResult<()> {
+    let response = CheckedPacket::from_data(kind, data.into_bytes());
+
+    log::debug!(
+        "Response: '{:x?}'",
+        std::str::from_utf8(&response.data).unwrap()
+    );
+    log::debug!("-----------------------------------------------");
+    output_stream.unbounded_send(response)?;
+
+    Ok(())
+}
+
 pub async fn await_halt(
     session: &mut Session,
     output_stream: &Sender<CheckedPacket>,
-    await_halt: &mut bool,
+    threads: &mut Threads,
+    non_stop: bool,
 ) -> ServerResult<()> {
     task::sleep(Duration::from_millis(10)).await;
-    if *await_halt && session.core(0)?.core_halted().unwrap() {
-        let response = CheckedPacket::from_data(PacketKind::Packet, b"T05hwbreak:;".to_vec());
-
-        let mut bytes = Vec::new();
-        response.encode(&mut bytes).unwrap();
-        *await_halt = false;
+    for core in 0..threads.count() {
+        if threads.awaits_halt[core] && session.core(core)?.core_halted()? {
+            threads.awaits_halt[core] = false;
+            let stop_reply = format!("{}hwbreak:;", handlers::stop_reply(5, core));
+
+            if non_stop {
+                // In non-stop mode, GDB is notified asynchronously about the halt,
+                // and the other cores keep running.
+                if let Some(notification) = threads.notify_stop(stop_reply) {
+                    let _ = send(output_stream, PacketKind::Notification, notification);
+                }
+            } else {
+                // In all-stop mode, the other cores are halted as well.
+                for other in 0..threads.count() {
+                    if threads.awaits_halt[other] {
+                        threads.awaits_halt[other] = false;
+                        if let Some(mut other) = handlers::available_core(session, other) {
+                            let _ = other.halt(Duration::from_millis(100));
+                        }
+                    }
+                }
 
-        let _ = output_stream.unbounded_send(response);
+                let _ = send(output_stream, PacketKind::Packet, stop_reply);
+                break;
+            }
+        }
     }
 
     Ok(())
@@ -1,21 +1,63 @@
 use colored::*;
-use env_logger::Builder;
+use env_logger::{Builder, WriteStyle};
 use indicatif::ProgressBar;
-use log::{Level, LevelFilter};
+use log::{Level, LevelFilter, Record};
 use std::{
     fmt,
+    io::Write,
+    str::FromStr,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc, RwLock,
+        Arc, Mutex, RwLock, TryLockError,
     },
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
 };
 
 /// The maximum window width of the terminal, given in characters possible.
 static MAX_WINDOW_WIDTH: AtomicUsize = AtomicUsize::new(0);
 
+/// The id handed out for the next progress bar that is registered.
+static NEXT_PROGRESS_BAR_ID: AtomicUsize = AtomicUsize::new(0);
+
 lazy_static::lazy_static! {
-    /// Stores the progress bar for the logging facility.
-    static ref PROGRESS_BAR: RwLock<Option<Arc<ProgressBar>>> = RwLock::new(None);
+    /// Stores the progress bars for the logging facility.
+    ///
+    /// Printing above a progress bar only needs shared access, so the write lock
+    /// is only taken to register or remove progress bars.
+    static ref PROGRESS_BARS: RwLock<Vec<RegisteredProgressBar>> = RwLock::new(Vec::new());
+
+    /// Stores the sink the log output is redirected to, if any.
+    static ref LOG_SINK: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+    /// Stores the rate limiter of the human readable output, if it is enabled.
+    static ref RATE_LIMITER: Mutex<Option<RateLimiter>> = Mutex::new(None);
+}
+
+/// Identifies a progress bar registered with [`set_progress_bar`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProgressBarId(usize);
+
+/// A progress bar registered with the logging facility.
+struct RegisteredProgressBar {
+    id: ProgressBarId,
+    /// The thread which registered the progress bar.
+    thread: ThreadId,
+    progress: Arc<ProgressBar>,
+}
+
+/// Selects the progress bar messages of the current thread should be printed above.
+///
+/// This is the most recently registered progress bar of the current thread, or
+/// if the thread has none, the most recently registered progress bar overall.
+fn current_progress_bar(bars: &[RegisteredProgressBar]) -> Option<&Arc<ProgressBar>> {
+    let thread = thread::current().id();
+
+    bars.iter()
+        .rev()
+        .find(|bar| bar.thread == thread)
+        .or_else(|| bars.last())
+        .map(|bar| &bar.progress)
 }
 
 /// A structure to hold a string with a padding attached to the start of it.
@@ -30,12 +72,13 @@ impl<T: fmt::Display> fmt::Display for Padded<T> {
     }
 }
 
-/// Get the maximum between the window width and the length of the given string.
+/// Get the maximum between the window width and the number of characters of the given string.
 fn max_target_width(target: &str) -> usize {
+    let width = target.chars().count();
     let max_width = MAX_WINDOW_WIDTH.load(Ordering::Relaxed);
-    if max_width < target.len() {
-        MAX_WINDOW_WIDTH.store(target.len(), Ordering::Relaxed);
-        target.len()
+    if max_width < width {
+        MAX_WINDOW_WIDTH.store(width, Ordering::Relaxed);
+        width
     } else {
         max_width
     }
@@ -52,6 +95,330 @@ fn colored_level(level: Level) -> ColoredString {
     }
 }
 
+/// The format in which log records are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Colored, human readable output.
+    #[default]
+    Human,
+    /// One JSON object per line, suitable for processing with tools like `jq`.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_ascii_lowercase()[..] {
+            "human" => Ok(LogFormat::Human),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow::anyhow!("Unknown log format '{}'", s)),
+        }
+    }
+}
+
+/// The timestamp shown in front of each record in the human readable format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogTimestamp {
+    /// Don't show a timestamp.
+    #[default]
+    None,
+    /// Show the time elapsed since the logger was initialized.
+    Elapsed,
+    /// Show the wall-clock time in ISO 8601 format.
+    Iso8601,
+}
+
+impl FromStr for LogTimestamp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_ascii_lowercase()[..] {
+            "none" => Ok(LogTimestamp::None),
+            "elapsed" => Ok(LogTimestamp::Elapsed),
+            "iso8601" | "iso-8601" => Ok(LogTimestamp::Iso8601),
+            _ => Err(anyhow::anyhow!("Unknown log timestamp '{}'", s)),
+        }
+    }
+}
+
+/// Formats the time elapsed since the logger was initialized, e.g. `2.431s`.
+fn format_elapsed(elapsed: Duration) -> String {
+    format!("{}.{:03}s", elapsed.as_secs(), elapsed.subsec_millis())
+}
+
+/// Predefined sets of per-module log filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogPreset {
+    /// Only show warnings and errors from probe-rs itself, while other crates log at the configured level.
+    Quiet,
+    /// Show debug output of the flash programming.
+    FlashVerbose,
+    /// Trace the communication with the probe and the debug port.
+    TransportTrace,
+}
+
+impl LogPreset {
+    /// The filter directives this preset expands to, in `env_logger` syntax.
+    fn directives(self) -> &'static str {
+        match self {
+            LogPreset::Quiet => "probe_rs=warn",
+            LogPreset::FlashVerbose => "probe_rs::flashing=debug",
+            LogPreset::TransportTrace => {
+                "probe_rs::probe=trace,\
+                 probe_rs::architecture::arm::communication_interface=trace,\
+                 probe_rs::architecture::arm::dp=trace,\
+                 probe_rs::architecture::riscv::communication_interface=trace"
+            }
+        }
+    }
+}
+
+/// Additional options for the logger.
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    /// The format of the log records.
+    ///
+    /// Can be overridden by the user with the `PROBE_RS_LOG_FORMAT` env var.
+    pub format: LogFormat,
+    /// The timestamp shown in the human readable format.
+    ///
+    /// Can be overridden by the user with the `PROBE_RS_LOG_TIMESTAMP` env var.
+    pub timestamp: LogTimestamp,
+    /// A preset of per-module filters, applied on top of the log level.
+    ///
+    /// Filters given in the `RUST_LOG` env var take precedence over the preset.
+    pub preset: Option<LogPreset>,
+    /// Never color the output, even if it is written to a terminal.
+    ///
+    /// Colors are also disabled if the user sets the `NO_COLOR` or `PROBE_RS_NO_COLOR` env var.
+    pub no_color: bool,
+    /// The maximum number of records printed per second in the human readable format.
+    ///
+    /// Identical consecutive messages are also printed only once, followed by the number of
+    /// repetitions. Errors are always printed. Records are not limited if this is `None`.
+    ///
+    /// Can be overridden by the user with the `PROBE_RS_LOG_RATE` env var.
+    pub rate_limit: Option<u32>,
+}
+
+/// Limits the number of log records printed, so bursts of records don't overwhelm the terminal.
+///
+/// Identical consecutive messages are printed once, followed by the number of repetitions
+/// once a different message is logged. Records exceeding the maximum number of records
+/// per second are dropped, and the number of dropped records is printed in the next second.
+/// Errors are always printed, and records are never reordered.
+///
+/// The summaries still pending are printed when the logger is flushed or the limiter is dropped.
+#[derive(Debug)]
+struct RateLimiter {
+    max_per_second: u32,
+    last_message: Option<String>,
+    repeated: usize,
+    window_start: Instant,
+    printed_in_window: u32,
+    dropped: usize,
+    /// Prints the pending summaries when the limiter is dropped.
+    output: fn(String),
+}
+
+impl RateLimiter {
+    fn new(max_per_second: u32) -> Self {
+        Self::with_output(max_per_second, print_log_line)
+    }
+
+    fn with_output(max_per_second: u32, output: fn(String)) -> Self {
+        Self {
+            max_per_second,
+            last_message: None,
+            repeated: 0,
+            window_start: Instant::now(),
+            printed_in_window: 0,
+            dropped: 0,
+            output,
+        }
+    }
+
+    /// Passes a record with the given level and message, logged at `now`, through the limiter.
+    ///
+    /// Returns the lines to print, which are summaries of the previous records and `line`
+    /// if the record is not suppressed.
+    fn limit(&mut self, level: Level, message: String, line: String, now: Instant) -> Vec<String> {
+        let mut lines = vec![];
+
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            if self.dropped > 0 {
+                lines.push(format!(
+                    "       {} log records were dropped",
+                    std::mem::take(&mut self.dropped)
+                ));
+            }
+            self.window_start = now;
+            self.printed_in_window = 0;
+        }
+
+        let is_error = level == Level::Error;
+        if !is_error && self.last_message.as_ref() == Some(&message) {
+            self.repeated += 1;
+            return lines;
+        }
+
+        if self.repeated > 0 {
+            lines.push(format!(
+                "       last message repeated {} times",
+                std::mem::take(&mut self.repeated)
+            ));
+        }
+
+        if !is_error && self.printed_in_window >= self.max_per_second {
+            self.dropped += 1;
+        } else {
+            // Only printed messages are coalesced, so the summary always refers to a printed line.
+            self.last_message = Some(message);
+            self.printed_in_window += 1;
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    /// Returns the summaries of the repeated and dropped records which were not printed yet.
+    fn flush(&mut self) -> Vec<String> {
+        let mut lines = vec![];
+
+        if self.repeated > 0 {
+            lines.push(format!(
+                "       last message repeated {} times",
+                std::mem::take(&mut self.repeated)
+            ));
+        }
+        if self.dropped > 0 {
+            lines.push(format!(
+                "       {} log records were dropped",
+                std::mem::take(&mut self.dropped)
+            ));
+        }
+
+        lines
+    }
+}
+
+impl Drop for RateLimiter {
+    fn drop(&mut self) {
+        for line in self.flush() {
+            (self.output)(line);
+        }
+    }
+}
+
+/// The logger installed by [`init_with_options`].
+///
+/// Wraps the `env_logger`, so flushing the logger also prints the summaries pending in the rate limiter.
+struct Logger(env_logger::Logger);
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.0.log(record)
+    }
+
+    fn flush(&self) {
+        if let Some(limiter) = &mut *RATE_LIMITER.lock().unwrap() {
+            for line in limiter.flush() {
+                print_log_line(line);
+            }
+        }
+
+        self.0.flush()
+    }
+}
+
+/// Formats a log line of the human readable format.
+///
+/// The target is padded to `width` characters before it is made bold,
+/// so the escape codes of the colors don't count towards the padding.
+fn human_line(
+    timestamp: &str,
+    level: Level,
+    target: &str,
+    width: usize,
+    message: impl fmt::Display,
+) -> String {
+    let target = Padded {
+        value: target,
+        width,
+    }
+    .to_string()
+    .bold();
+
+    format!(
+        "       {}{} {} > {}",
+        timestamp,
+        colored_level(level),
+        target,
+        message
+    )
+}
+
+/// Formats a log record as a single line of JSON.
+fn json_record(record: &Record, timestamp: impl fmt::Display) -> String {
+    serde_json::json!({
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "timestamp": timestamp.to_string(),
+        "module_path": record.module_path(),
+    })
+    .to_string()
+}
+
+/// Prints a formatted log line.
+///
+/// The line is written to the log sink if one is set, and otherwise to stdout,
+/// above the progress bar if one is displayed.
+fn print_log_line(line: String) {
+    if let Some(sink) = &mut *LOG_SINK.lock().unwrap() {
+        // There is nowhere to report a failing sink to, so errors are ignored.
+        let _ = writeln!(sink, "{}", line);
+        return;
+    }
+
+    let guard = PROGRESS_BARS.read().unwrap();
+    if let Some(pb) = current_progress_bar(&guard) {
+        pb.println(line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Reads a setting from the env var with the given name, falling back to `default`
+/// if it is not set or cannot be parsed.
+fn env_override<T>(name: &str, default: T) -> T
+where
+    T: FromStr<Err = anyhow::Error> + fmt::Debug,
+{
+    match ::std::env::var(name) {
+        Ok(s) => s.parse().unwrap_or_else(|e| {
+            eprintln!("{}, falling back to {:?}.", e, default);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// Checks if colors are disabled by `no_color` or by one of the env vars, which are read with `var`.
+///
+/// As defined by <https://no-color.org>, an env var disables colors if it is set to a non-empty value.
+fn colors_disabled(no_color: bool, var: impl Fn(&str) -> Option<String>) -> bool {
+    no_color
+        || ["NO_COLOR", "PROBE_RS_NO_COLOR"]
+            .iter()
+            .any(|name| matches!(var(name), Some(value) if !value.is_empty()))
+}
+
 /// Initialize the logger.
 ///
 /// There are two sources for log level configuration:
@@ -63,8 +430,19 @@ fn colored_level(level: Level) -> ColoredString {
 /// supports the full `env_logger` syntax, including filtering by crate and
 /// module.
 pub fn init(level: Option<Level>) {
-    let mut builder = Builder::new();
+    init_with_options(level, LogOptions::default())
+}
 
+/// Configures the filters of the logger.
+///
+/// The filters are applied in order, later ones overriding earlier ones:
+/// the log level, the preset and finally the user given `RUST_LOG` filters.
+fn configure_filters(
+    builder: &mut Builder,
+    level: Option<Level>,
+    preset: Option<LogPreset>,
+    rust_log: Option<&str>,
+) {
     // First, apply the log level given to this function.
     if let Some(level) = level {
         builder.filter_level(level.to_level_filter());
@@ -72,55 +450,188 @@ pub fn init(level: Option<Level>) {
         builder.filter_level(LevelFilter::Warn);
     }
 
+    // Then refine it with the filters of the preset.
+    if let Some(preset) = preset {
+        builder.parse_filters(preset.directives());
+    }
+
     // Then override that with the `RUST_LOG` env var, if set.
-    if let Ok(s) = ::std::env::var("RUST_LOG") {
-        builder.parse_filters(&s);
+    if let Some(s) = rust_log {
+        builder.parse_filters(s);
+    }
+}
+
+/// Initialize the logger with additional options.
+///
+/// The log level is configured the same way as for [`init`],
+/// with the filters of the preset applied after the log level but before `RUST_LOG`.
+pub fn init_with_options(level: Option<Level>, options: LogOptions) {
+    let mut builder = Builder::new();
+
+    configure_filters(
+        &mut builder,
+        level,
+        options.preset,
+        ::std::env::var("RUST_LOG").ok().as_deref(),
+    );
+
+    // The env vars override the format and timestamp given to this function.
+    let format = env_override("PROBE_RS_LOG_FORMAT", options.format);
+    let timestamp = env_override("PROBE_RS_LOG_TIMESTAMP", options.timestamp);
+
+    let rate_limit = match ::std::env::var("PROBE_RS_LOG_RATE") {
+        Ok(s) => s.parse().map(Some).unwrap_or_else(|e| {
+            eprintln!(
+                "Invalid log rate '{}': {}, falling back to {:?}.",
+                s, e, options.rate_limit
+            );
+            options.rate_limit
+        }),
+        Err(_) => options.rate_limit,
+    };
+    *RATE_LIMITER.lock().unwrap() = rate_limit.map(RateLimiter::new);
+
+    let start = Instant::now();
+
+    // Escape codes would corrupt the JSON output, so never emit any.
+    if format == LogFormat::Json
+        || colors_disabled(options.no_color, |name| ::std::env::var(name).ok())
+    {
+        colored::control::set_override(false);
+        builder.write_style(WriteStyle::Never);
     }
 
     // Define our custom log format.
     builder.format(move |f, record| {
+        if format == LogFormat::Json {
+            print_log_line(json_record(record, f.timestamp()));
+            return Ok(());
+        }
+
         let target = record.target();
         let max_width = max_target_width(target);
 
-        let level = colored_level(record.level());
+        let timestamp = match timestamp {
+            LogTimestamp::None => String::new(),
+            LogTimestamp::Elapsed => format!("{:>10} ", format_elapsed(start.elapsed())),
+            LogTimestamp::Iso8601 => format!("{} ", f.timestamp()),
+        };
 
-        let mut style = f.style();
-        let target = style.set_bold(true).value(Padded {
-            value: target,
-            width: max_width,
-        });
-
-        let guard = PROGRESS_BAR.write().unwrap();
-        if let Some(pb) = &*guard {
-            pb.println(format!("       {} {} > {}", level, target, record.args()));
-        } else {
-            println!("       {} {} > {}", level, target, record.args());
+        let line = human_line(&timestamp, record.level(), target, max_width, record.args());
+        match &mut *RATE_LIMITER.lock().unwrap() {
+            Some(limiter) => {
+                let message = format!("{} {}", target, record.args());
+                for line in limiter.limit(record.level(), message, line, Instant::now()) {
+                    print_log_line(line);
+                }
+            }
+            None => print_log_line(line),
         }
 
         Ok(())
     });
 
-    builder.init();
+    let logger = builder.build();
+    log::set_max_level(logger.filter());
+    // Like `Builder::init`, this panics if a logger was set before.
+    log::set_boxed_logger(Box::new(Logger(logger)))
+        .expect("The logger should only be initialized once");
 }
 
-/// Sets the currently displayed progress bar of the CLI.
-pub fn set_progress_bar(progress: Arc<ProgressBar>) {
-    let mut guard = PROGRESS_BAR.write().unwrap();
-    *guard = Some(progress);
+/// Adds a progress bar to the currently displayed progress bars of the CLI.
+///
+/// Messages logged from the calling thread are printed above this progress bar.
+/// The returned id has to be passed to [`clear_progress_bar`] once the progress bar is no longer displayed.
+pub fn set_progress_bar(progress: Arc<ProgressBar>) -> ProgressBarId {
+    let id = ProgressBarId(NEXT_PROGRESS_BAR_ID.fetch_add(1, Ordering::Relaxed));
+
+    let mut guard = PROGRESS_BARS.write().unwrap();
+    guard.push(RegisteredProgressBar {
+        id,
+        thread: thread::current().id(),
+        progress,
+    });
+
+    id
+}
+
+/// Redirects all log output to the given sink instead of stdout.
+///
+/// The sink receives the already formatted log lines. In the human readable format these
+/// contain ANSI color escape codes, unless colors are disabled via the `colored` crate.
+///
+/// While a sink is set, log output is not routed through the progress bar.
+pub fn set_log_sink(sink: Box<dyn Write + Send>) {
+    let mut guard = LOG_SINK.lock().unwrap();
+    *guard = Some(sink);
 }
 
-/// Disables the currently displayed progress bar of the CLI.
-pub fn clear_progress_bar() {
-    let mut guard = PROGRESS_BAR.write().unwrap();
+/// Removes the log sink, so log output is written to stdout again.
+pub fn clear_log_sink() {
+    let mut guard = LOG_SINK.lock().unwrap();
     *guard = None;
 }
 
+/// Removes the progress bar with the given id from the currently displayed progress bars of the CLI.
+pub fn clear_progress_bar(id: ProgressBarId) {
+    let mut guard = PROGRESS_BARS.write().unwrap();
+    guard.retain(|bar| bar.id != id);
+}
+
+/// How long the panic hook waits for access to the progress bars.
+const PANIC_HOOK_LOCK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Clears all displayed progress bars from the terminal, so they don't overwrite the following output.
+///
+/// Gives up after `timeout` if the progress bars are locked, which happens if the
+/// current thread panicked while registering or removing a progress bar.
+fn clear_all_progress_bars(timeout: Duration) {
+    let start = Instant::now();
+    loop {
+        let bars = match PROGRESS_BARS.try_read() {
+            Ok(bars) => bars,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            }
+            Err(TryLockError::WouldBlock) => return,
+        };
+
+        for bar in bars.iter() {
+            bar.progress.finish_and_clear();
+        }
+        return;
+    }
+}
+
+/// Installs a panic hook which clears the progress bars before the panic is printed.
+///
+/// Without it, the panic message and backtrace can be overwritten by a progress bar.
+/// The previously installed panic hook is called afterwards, so it still prints the panic.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        clear_all_progress_bars(PANIC_HOOK_LOCK_TIMEOUT);
+        // The progress bars might still be locked, so `eprintln` of this module can't be used.
+        eprintln!(
+            "{}",
+            format!(
+                "An unexpected error occured. This is a bug, please report it at {}/issues.",
+                env!("CARGO_PKG_REPOSITORY")
+            )
+            .red()
+        );
+        previous(info);
+    }));
+}
+
 /// Writes an error to stderr.
 /// This function respects the progress bars of the CLI that might be displayed and displays the message above it if any are.
 pub fn eprintln(message: impl AsRef<str>) {
-    let guard = PROGRESS_BAR.write().unwrap();
+    let guard = PROGRESS_BARS.read().unwrap();
 
-    match guard.as_ref() {
+    match current_progress_bar(&guard) {
         Some(pb) if !pb.is_finished() => {
             pb.println(message.as_ref());
         }
@@ -133,10 +644,324 @@ pub fn eprintln(message: impl AsRef<str>) {
 /// Writes a message to stdout.
 /// This function respects the progress bars of the CLI that might be displayed and displays the message above it if any are.
 pub fn println(message: impl AsRef<str>) {
-    let guard = PROGRESS_BAR.write().unwrap();
-    if let Some(pb) = &*guard {
+    let guard = PROGRESS_BARS.read().unwrap();
+    if let Some(pb) = current_progress_bar(&guard) {
         pb.println(message.as_ref());
     } else {
         println!("{}", message.as_ref());
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_record_contains_all_fields() {
+        let line = json_record(
+            &Record::builder()
+                .args(format_args!("Erasing sector {}", 3))
+                .level(Level::Info)
+                .target("probe_rs::flashing")
+                .module_path(Some("probe_rs::flashing::flasher"))
+                .build(),
+            "2020-01-01T00:00:00Z",
+        );
+        assert!(!line.contains('\n'));
+
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "INFO");
+        assert_eq!(value["target"], "probe_rs::flashing");
+        assert_eq!(value["message"], "Erasing sector 3");
+        assert_eq!(value["timestamp"], "2020-01-01T00:00:00Z");
+        assert_eq!(value["module_path"], "probe_rs::flashing::flasher");
+    }
+
+    /// A sink which can still be inspected after it was handed to the logger.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn log_lines_are_written_to_sink() {
+        let buffer = SharedBuffer::default();
+        set_log_sink(Box::new(buffer.clone()));
+
+        print_log_line("Flashing finished".to_owned());
+        clear_log_sink();
+
+        let captured = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(captured, "Flashing finished\n");
+    }
+
+    #[test]
+    fn progress_bar_is_selected_per_thread() {
+        let spawn = || {
+            thread::spawn(|| {
+                let progress = Arc::new(ProgressBar::hidden());
+                let id = set_progress_bar(progress.clone());
+
+                let selected = current_progress_bar(&PROGRESS_BARS.read().unwrap()).cloned();
+                clear_progress_bar(id);
+
+                Arc::ptr_eq(&progress, &selected.unwrap())
+            })
+        };
+
+        let a = spawn();
+        let b = spawn();
+
+        assert!(a.join().unwrap());
+        assert!(b.join().unwrap());
+    }
+
+    #[test]
+    fn clearing_progress_bars_does_not_wait_for_a_held_lock() {
+        let progress = Arc::new(ProgressBar::hidden());
+        let id = set_progress_bar(progress.clone());
+
+        {
+            let _guard = PROGRESS_BARS.write().unwrap();
+            let start = Instant::now();
+            clear_all_progress_bars(Duration::from_millis(10));
+            assert!(start.elapsed() < Duration::from_secs(1));
+            assert!(!progress.is_finished());
+        }
+
+        clear_all_progress_bars(Duration::from_millis(10));
+        assert!(progress.is_finished());
+        clear_progress_bar(id);
+    }
+
+    fn filter_matches(logger: &env_logger::Logger, target: &str, level: Level) -> bool {
+        logger.matches(&Record::builder().target(target).level(level).build())
+    }
+
+    #[test]
+    fn transport_trace_preset() {
+        let mut builder = Builder::new();
+        configure_filters(
+            &mut builder,
+            Some(Level::Info),
+            Some(LogPreset::TransportTrace),
+            None,
+        );
+        let logger = builder.build();
+
+        assert!(filter_matches(
+            &logger,
+            "probe_rs::architecture::arm::dp",
+            Level::Trace
+        ));
+        assert!(filter_matches(
+            &logger,
+            "probe_rs::probe::daplink",
+            Level::Trace
+        ));
+        assert!(!filter_matches(&logger, "probe_rs::flashing", Level::Debug));
+        assert!(filter_matches(&logger, "probe_rs::flashing", Level::Info));
+    }
+
+    #[test]
+    fn rust_log_overrides_preset() {
+        let mut builder = Builder::new();
+        configure_filters(
+            &mut builder,
+            None,
+            Some(LogPreset::Quiet),
+            Some("probe_rs=debug"),
+        );
+        let logger = builder.build();
+
+        assert!(filter_matches(&logger, "probe_rs::session", Level::Debug));
+    }
+
+    #[test]
+    fn no_color_env_vars() {
+        let env = |set: &'static str, value: &'static str| {
+            move |name: &str| Some(value.to_owned()).filter(|_| name == set)
+        };
+
+        assert!(!colors_disabled(false, |_| None));
+        assert!(colors_disabled(true, |_| None));
+        assert!(colors_disabled(false, env("NO_COLOR", "1")));
+        assert!(colors_disabled(false, env("PROBE_RS_NO_COLOR", "yes")));
+        // Empty values are ignored.
+        assert!(!colors_disabled(false, env("NO_COLOR", "")));
+    }
+
+    lazy_static::lazy_static! {
+        /// Serializes the tests which override the global color setting.
+        static ref COLOR_OVERRIDE: Mutex<()> = Mutex::new(());
+    }
+
+    #[test]
+    fn padding_ignores_colors() {
+        let _lock = COLOR_OVERRIDE.lock().unwrap();
+        colored::control::set_override(false);
+        let padded = Padded {
+            value: "probe_rs".bold(),
+            width: 12,
+        };
+        assert_eq!(padded.to_string(), "probe_rs    ");
+        assert_eq!(colored_level(Level::Info).to_string(), " INFO");
+        colored::control::unset_override();
+    }
+
+    #[test]
+    fn colored_targets_are_aligned() {
+        let _lock = COLOR_OVERRIDE.lock().unwrap();
+        colored::control::set_override(true);
+        let short = human_line("", Level::Info, "probe_rs", 18, "Erasing");
+        let long = human_line("", Level::Warn, "probe_rs::flashing", 18, "Erasing");
+        colored::control::unset_override();
+
+        assert!(short.contains('\u{1b}'));
+        assert_eq!(short.find(" > "), long.find(" > "));
+    }
+
+    #[test]
+    fn rate_limiter_coalesces_repeated_messages() {
+        let mut limiter = RateLimiter::new(100);
+        let now = Instant::now();
+        let mut log = |level, message: &str| {
+            limiter.limit(level, message.to_owned(), message.to_owned(), now)
+        };
+
+        assert_eq!(log(Level::Info, "a"), vec!["a"]);
+        assert!(log(Level::Info, "a").is_empty());
+        assert!(log(Level::Info, "a").is_empty());
+        // Errors are never coalesced.
+        assert_eq!(
+            log(Level::Error, "a"),
+            vec!["       last message repeated 2 times", "a"]
+        );
+        assert_eq!(log(Level::Error, "a"), vec!["a"]);
+        assert_eq!(log(Level::Info, "b"), vec!["b"]);
+    }
+
+    #[test]
+    fn rate_limiter_only_coalesces_printed_messages() {
+        let mut limiter = RateLimiter::new(1);
+        let now = Instant::now();
+        let mut log =
+            |message: &str| limiter.limit(Level::Info, message.to_owned(), message.to_owned(), now);
+
+        assert_eq!(log("a"), vec!["a"]);
+        // Dropped, so repeating it must not report repetitions of a line which was never printed.
+        assert!(log("b").is_empty());
+        assert!(log("b").is_empty());
+        assert_eq!(limiter.repeated, 0);
+        assert_eq!(limiter.dropped, 2);
+        assert_eq!(limiter.last_message.as_deref(), Some("a"));
+
+        assert_eq!(limiter.flush(), vec!["       2 log records were dropped"]);
+    }
+
+    #[test]
+    fn rate_limiter_flush_prints_pending_summaries() {
+        let mut limiter = RateLimiter::new(100);
+        let now = Instant::now();
+
+        assert_eq!(
+            limiter.limit(Level::Info, "a".to_owned(), "a".to_owned(), now),
+            vec!["a"]
+        );
+        assert!(limiter
+            .limit(Level::Info, "a".to_owned(), "a".to_owned(), now)
+            .is_empty());
+
+        assert_eq!(
+            limiter.flush(),
+            vec!["       last message repeated 1 times"]
+        );
+        assert!(limiter.flush().is_empty());
+    }
+
+    thread_local! {
+        static DROPPED_LIMITER_OUTPUT: std::cell::RefCell<Vec<String>> = Default::default();
+    }
+
+    #[test]
+    fn rate_limiter_prints_pending_summaries_when_dropped() {
+        let mut limiter = RateLimiter::with_output(100, |line| {
+            DROPPED_LIMITER_OUTPUT.with(|o| o.borrow_mut().push(line))
+        });
+        let now = Instant::now();
+
+        assert_eq!(
+            limiter.limit(Level::Info, "a".to_owned(), "a".to_owned(), now),
+            vec!["a"]
+        );
+        assert!(limiter
+            .limit(Level::Info, "a".to_owned(), "a".to_owned(), now)
+            .is_empty());
+        assert!(limiter
+            .limit(Level::Info, "a".to_owned(), "a".to_owned(), now)
+            .is_empty());
+        drop(limiter);
+
+        DROPPED_LIMITER_OUTPUT.with(|o| {
+            assert_eq!(*o.borrow(), vec!["       last message repeated 2 times"]);
+        });
+    }
+
+    #[test]
+    fn rate_limiter_drops_records_above_the_rate() {
+        let mut limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        let mut log = |level, message: &str, elapsed| {
+            limiter.limit(
+                level,
+                message.to_owned(),
+                message.to_owned(),
+                start + Duration::from_millis(elapsed),
+            )
+        };
+
+        assert_eq!(log(Level::Info, "a", 0), vec!["a"]);
+        assert_eq!(log(Level::Info, "b", 10), vec!["b"]);
+        assert!(log(Level::Info, "c", 20).is_empty());
+        assert_eq!(log(Level::Error, "d", 30), vec!["d"]);
+        assert_eq!(
+            log(Level::Info, "e", 1000),
+            vec!["       1 log records were dropped", "e"]
+        );
+    }
+
+    #[test]
+    fn elapsed_timestamp_format() {
+        assert_eq!(format_elapsed(Duration::from_millis(2431)), "2.431s");
+        assert_eq!(format_elapsed(Duration::from_micros(5_900)), "0.005s");
+        assert_eq!(format_elapsed(Duration::from_secs(61)), "61.000s");
+    }
+
+    #[test]
+    fn parse_log_timestamp() {
+        assert_eq!(
+            "elapsed".parse::<LogTimestamp>().unwrap(),
+            LogTimestamp::Elapsed
+        );
+        assert_eq!(
+            "ISO8601".parse::<LogTimestamp>().unwrap(),
+            LogTimestamp::Iso8601
+        );
+        assert!("later".parse::<LogTimestamp>().is_err());
+    }
+
+    #[test]
+    fn parse_log_format() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("Human".parse::<LogFormat>().unwrap(), LogFormat::Human);
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+}
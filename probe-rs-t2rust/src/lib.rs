@@ -40,7 +40,7 @@ pub fn run(input_dir: impl AsRef<Path>, output_file: impl AsRef<Path>) {
         quote::quote! {
             #[allow(unused_imports)]
             use jep106::JEP106Code;
-            use crate::config::{Chip, RawFlashAlgorithm, FlashRegion, MemoryRegion, RamRegion, SectorDescription, FlashProperties};
+            use crate::config::{ByteOrder, Chip, Endianness, RawFlashAlgorithm, ResetType, FlashRegion, MemoryRegion, RamRegion, SectorDescription, FlashProperties};
 
             use std::borrow::Cow;
         }
@@ -145,6 +145,12 @@ fn extract_algorithms(
                     .as_u64()
                     .map(|v| v as u32),
             );
+            let pc_crc32 = quote_option(
+                algorithm
+                    .get("pc_crc32")
+                    .and_then(|v| v.as_u64())
+                    .map(|v| v as u32),
+            );
             let data_section_offset = algorithm
                 .get("data_section_offset")
                 .unwrap()
@@ -198,6 +204,7 @@ fn extract_algorithms(
                     pc_program_page: #pc_program_page,
                     pc_erase_sector: #pc_erase_sector,
                     pc_erase_all: #pc_erase_all,
+                    pc_crc32: #pc_crc32,
                     data_section_offset: #data_section_offset,
                     flash_properties: FlashProperties {
                         address_range: #start..#end,
@@ -317,6 +324,9 @@ fn extract_variants(chip_family: &serde_yaml::Value) -> Vec<proc_macro2::TokenSt
                 .as_sequence()
                 .unwrap();
             let flash_algorithm_names = flash_algorithms.iter().map(|a| a.as_str().unwrap());
+
+            let byte_order = extract_byte_order(variant);
+            let default_reset_type = extract_reset_type(variant);
             quote::quote! {
                 Chip {
                     name: Cow::Borrowed(#name),
@@ -327,12 +337,50 @@ fn extract_variants(chip_family: &serde_yaml::Value) -> Vec<proc_macro2::TokenSt
                     flash_algorithms: Cow::Borrowed(&[
                         #(Cow::Borrowed(#flash_algorithm_names),)*
                     ]),
+                    byte_order: #byte_order,
+                    default_reset_type: #default_reset_type,
                 }
             }
         })
         .collect()
 }
 
+/// Extracts the byte order token stream from a yaml value, which is little endian if not specified.
+fn extract_byte_order(variant: &serde_yaml::Value) -> proc_macro2::TokenStream {
+    let endianness = |key: &str| match variant
+        .get("byte_order")
+        .and_then(|byte_order| byte_order.get(key))
+        .map(|endianness| endianness.as_str().unwrap())
+    {
+        None | Some("Little") => quote::quote! { Endianness::Little },
+        Some("Big") => quote::quote! { Endianness::Big },
+        Some(endianness) => panic!("Unknown endianness {}", endianness),
+    };
+    let data = endianness("data");
+    let instructions = endianness("instructions");
+
+    quote::quote! {
+        ByteOrder {
+            data: #data,
+            instructions: #instructions,
+        }
+    }
+}
+
+/// Extracts the reset type token stream from a yaml value, which is `SoftwareSysReset` if not specified.
+fn extract_reset_type(variant: &serde_yaml::Value) -> proc_macro2::TokenStream {
+    match variant
+        .get("default_reset_type")
+        .map(|reset_type| reset_type.as_str().unwrap())
+    {
+        None | Some("SoftwareSysReset") => quote::quote! { ResetType::SoftwareSysReset },
+        Some("SoftwareCoreReset") => quote::quote! { ResetType::SoftwareCoreReset },
+        Some("Hardware") => quote::quote! { ResetType::Hardware },
+        Some("VendorSpecific") => quote::quote! { ResetType::VendorSpecific },
+        Some(reset_type) => panic!("Unknown reset type {}", reset_type),
+    }
+}
+
 /// Extracts a chip family token stream from a yaml value.
 fn extract_chip_family(
     chip_family: &serde_yaml::Value,
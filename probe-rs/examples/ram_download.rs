@@ -70,7 +70,7 @@ fn main() -> Result<(), &'static str> {
         .expect("Halting failed");
 
     let write_start = Instant::now();
-    core.write_32(matches.address, &sample_data)
+    core.write_32(matches.address.into(), &sample_data)
         .expect("Writing the sample data failed");
 
     let write_duration = write_start.elapsed();
@@ -89,7 +89,7 @@ fn main() -> Result<(), &'static str> {
     let mut readback_data = vec![0u32; data_size_words];
 
     let read_start = Instant::now();
-    core.read_32(matches.address, &mut readback_data)
+    core.read_32(matches.address.into(), &mut readback_data)
         .expect("Reading the sample data failed");
     let read_duration = read_start.elapsed();
 
@@ -27,7 +27,7 @@ fn main() -> Result<(), Error> {
         .set_baud(2_000_000)
         .set_continuous_formatting(false);
 
-    session.setup_swv(&cfg)?;
+    session.enable_swo(&cfg)?;
 
     let mut timestamp: f64 = 0.0;
 
@@ -38,7 +38,7 @@ fn main() -> Result<(), Error> {
     println!("Starting SWO trace ...");
 
     loop {
-        let bytes = session.read_swo()?;
+        let bytes = session.read_swo_raw()?;
 
         decoder.feed(bytes);
         while let Some(packet) = decoder.pull() {
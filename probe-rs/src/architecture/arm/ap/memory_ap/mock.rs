@@ -1,5 +1,5 @@
 use super::super::{APAccess, Register};
-use super::{APRegister, AddressIncrement, DataSize, MemoryAP, CSW, DRW, TAR};
+use super::{APRegister, AddressIncrement, DataSize, MemoryAP, CSW, DRW, TAR, TAR2};
 use crate::{
     architecture::arm::dp::{DPAccess, DPRegister, DebugPortError},
     CommunicationInterface, DebugProbeError,
@@ -10,7 +10,7 @@ use thiserror::Error;
 
 pub struct MockMemoryAP {
     pub memory: Vec<u8>,
-    store: HashMap<(u8, u8), u32>,
+    pub store: HashMap<(u8, u8), u32>,
 }
 
 #[derive(Debug, Error)]
@@ -31,6 +31,7 @@ impl MockMemoryAP {
         let mut store = HashMap::new();
         store.insert((CSW::ADDRESS, CSW::APBANKSEL), 0);
         store.insert((TAR::ADDRESS, TAR::APBANKSEL), 0);
+        store.insert((TAR2::ADDRESS, TAR2::APBANKSEL), 0);
         store.insert((DRW::ADDRESS, DRW::APBANKSEL), 0);
         Self {
             memory: (1..=16).collect(),
@@ -181,6 +182,8 @@ where
                 self.store.insert((TAR::ADDRESS, TAR::APBANKSEL), value);
                 Ok(())
             }
+            // The mock memory ignores the upper word of the address.
+            (TAR2::ADDRESS, TAR2::APBANKSEL) => Ok(()),
             _ => Err(MockMemoryError::UnknownRegister),
         }
     }
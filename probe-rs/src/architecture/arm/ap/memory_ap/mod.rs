@@ -328,3 +328,17 @@ define_ap_register!(
     TAR { address: value },
     value.address
 );
+
+define_ap_register!(
+    /// Transfer Address Register, upper word
+    ///
+    /// Holds the bits 63 to 32 of the memory address accessed through DRW,
+    /// on memory APs which implement the *Large Physical Address Extension*.
+    MemoryAP,
+    TAR2,
+    0x08,
+    [(address: u32),],
+    value,
+    TAR2 { address: value },
+    value.address
+);
@@ -8,16 +8,20 @@ use crate::DebugProbeError;
 
 pub use generic_ap::{APClass, APType, GenericAP, IDR};
 pub use memory_ap::{
-    AddressIncrement, BaseaddrFormat, DataSize, MemoryAP, BASE, BASE2, CSW, DRW, TAR,
+    AddressIncrement, BaseaddrFormat, DataSize, MemoryAP, BASE, BASE2, CFG, CSW, DRW, TAR, TAR2,
 };
 
-use super::Register;
+use super::{memory::MemoryAccessAttributes, Register};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum AccessPortError {
     #[error("Failed to access address 0x{address:08x} as it is not aligned to the requirement of {alignment} bytes.")]
-    MemoryNotAligned { address: u32, alignment: usize },
+    MemoryNotAligned { address: u64, alignment: usize },
+    #[error(
+        "Failed to access address 0x{0:08x}, as the memory AP only supports 32 bit addresses."
+    )]
+    AddressTooLarge(u64),
     #[error("Failed to read register {name} at address 0x{address:08x}")]
     RegisterReadError {
         address: u8,
@@ -34,6 +38,12 @@ pub enum AccessPortError {
     },
     #[error("Out of bounds access")]
     OutOfBoundsError,
+    #[error("The memory AP does not support accesses with {0:?}")]
+    UnsupportedAccessAttributes(MemoryAccessAttributes),
+    #[error("The memory AP only supports 32 bit accesses, {0:?} accesses are not possible")]
+    UnsupportedDataSize(DataSize),
+    #[error("Secure memory accesses are not possible, as secure debug is disabled")]
+    SecureAccessDisabled,
     #[error("Error while communicating with debug port")]
     DebugPort(#[from] DebugPortError),
     #[error("Failed to flush batched writes")]
@@ -61,7 +71,7 @@ impl AccessPortError {
         }
     }
 
-    pub fn alignment_error(address: u32, alignment: usize) -> Self {
+    pub fn alignment_error(address: u64, alignment: usize) -> Self {
         AccessPortError::MemoryNotAligned { address, alignment }
     }
 }
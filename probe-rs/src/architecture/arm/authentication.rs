@@ -0,0 +1,349 @@
+//! Unlocking the debug access to targets which require an authentication first.
+//!
+//! Targets with secure debug keep their memory APs disabled until the debugger proved that it
+//! is allowed to debug them, usually by signing a challenge sent by the target. The
+//! [`DebugAuthenticator`] set with `Probe::set_debug_authenticator` is consulted while attaching,
+//! and again when reconnecting after a reset.
+
+use super::{
+    ap::{GenericAP, CSW},
+    communication_interface::ArmProbeInterface,
+    dp::Abort,
+    Register,
+};
+use crate::Error;
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// Describes why unlocking the debug access failed.
+#[derive(Error, Debug)]
+pub enum AuthenticationError {
+    #[error("The target rejected the authentication response, status: {status:#010x}")]
+    Rejected { status: u32 },
+    #[error("The target did not answer the authentication response within {0:?}")]
+    Timeout(Duration),
+    #[error("The authentication succeeded, but the debug access is still locked")]
+    StillLocked,
+    #[error("Signing the challenge of the target failed")]
+    Signer(#[source] anyhow::Error),
+    #[error("Accessing the authentication registers failed")]
+    Access(#[from] Error),
+}
+
+/// Unlocks the debug access to a target.
+pub trait DebugAuthenticator: Send + Sync + fmt::Debug {
+    /// Returns whether the debug access is locked, and has to be unlocked with `authenticate`.
+    ///
+    /// By default, the debug access is locked if the memory AP 0 reports that it is disabled
+    /// in `CSW.DeviceEn`.
+    fn is_locked(&mut self, interface: &mut dyn ArmProbeInterface) -> Result<bool, Error> {
+        let csw = CSW::from(interface.read_raw_ap_register(GenericAP::new(0), CSW::ADDRESS)?);
+        Ok(csw.DeviceEn == 0)
+    }
+
+    /// Unlocks the debug access.
+    fn authenticate(
+        &mut self,
+        interface: &mut dyn ArmProbeInterface,
+    ) -> Result<(), AuthenticationError>;
+}
+
+/// Does not authenticate, for targets without secure debug.
+///
+/// This is the default authenticator of a probe.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoAuthentication;
+
+impl DebugAuthenticator for NoAuthentication {
+    fn is_locked(&mut self, _interface: &mut dyn ArmProbeInterface) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn authenticate(
+        &mut self,
+        _interface: &mut dyn ArmProbeInterface,
+    ) -> Result<(), AuthenticationError> {
+        Ok(())
+    }
+}
+
+/// The registers of an AP which the target uses as a mailbox for the challenge and the response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthenticationMailbox {
+    /// The AP containing the mailbox registers.
+    pub access_port: u8,
+    /// The address of the status register.
+    pub status: u8,
+    /// The address of the register from which the words of the challenge are read.
+    pub challenge: u8,
+    /// The address of the register to which the words of the response are written.
+    pub response: u8,
+    /// The number of words of the challenge.
+    pub challenge_words: usize,
+    /// The bits of the status register which are set once the debug access is unlocked.
+    pub unlocked: u32,
+    /// The bits of the status register which are set if the response was rejected.
+    pub rejected: u32,
+}
+
+/// The function signing a challenge, which returns the words of the response.
+type Signer = Box<dyn FnMut(&[u32]) -> Result<Vec<u32>, anyhow::Error> + Send + Sync>;
+
+/// Unlocks the debug access by signing a challenge read from an [`AuthenticationMailbox`].
+///
+/// The challenge is read from the mailbox, signed on the host, and the response is written
+/// back. Afterwards, the status register is polled until the target accepted or rejected it.
+pub struct ChallengeResponse {
+    mailbox: AuthenticationMailbox,
+    sign: Signer,
+    timeout: Duration,
+}
+
+impl ChallengeResponse {
+    /// Creates a challenge-response authenticator, where `sign` returns the response for a challenge.
+    pub fn new(
+        mailbox: AuthenticationMailbox,
+        sign: impl FnMut(&[u32]) -> Result<Vec<u32>, anyhow::Error> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            mailbox,
+            sign: Box::new(sign),
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets how long the target may take to check the response, one second by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn run(
+        &mut self,
+        registers: &mut (impl ApRegisters + ?Sized),
+    ) -> Result<(), AuthenticationError> {
+        let mailbox = self.mailbox;
+
+        if registers.read(mailbox.access_port, mailbox.status)? & mailbox.unlocked != 0 {
+            return Ok(());
+        }
+
+        let challenge = (0..mailbox.challenge_words)
+            .map(|_| registers.read(mailbox.access_port, mailbox.challenge))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let response = (self.sign)(&challenge).map_err(AuthenticationError::Signer)?;
+        for word in response {
+            registers.write(mailbox.access_port, mailbox.response, word)?;
+        }
+
+        let start = Instant::now();
+        loop {
+            let status = registers.read(mailbox.access_port, mailbox.status)?;
+
+            if status & mailbox.rejected != 0 {
+                return Err(AuthenticationError::Rejected { status });
+            }
+            if status & mailbox.unlocked != 0 {
+                return Ok(());
+            }
+            if start.elapsed() >= self.timeout {
+                return Err(AuthenticationError::Timeout(self.timeout));
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ChallengeResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChallengeResponse")
+            .field("mailbox", &self.mailbox)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl DebugAuthenticator for ChallengeResponse {
+    fn is_locked(&mut self, interface: &mut dyn ArmProbeInterface) -> Result<bool, Error> {
+        let status = interface.read_raw_ap_register(
+            GenericAP::new(self.mailbox.access_port),
+            self.mailbox.status,
+        )?;
+        Ok(status & self.mailbox.unlocked == 0)
+    }
+
+    fn authenticate(
+        &mut self,
+        interface: &mut dyn ArmProbeInterface,
+    ) -> Result<(), AuthenticationError> {
+        self.run(interface)
+    }
+}
+
+/// The raw register accesses used by the authentication flows.
+trait ApRegisters {
+    fn read(&mut self, access_port: u8, address: u8) -> Result<u32, Error>;
+
+    fn write(&mut self, access_port: u8, address: u8, value: u32) -> Result<(), Error>;
+
+    /// Clears the sticky error flags of the DP.
+    fn clear_sticky_errors(&mut self) -> Result<(), Error>;
+}
+
+impl ApRegisters for dyn ArmProbeInterface + '_ {
+    fn read(&mut self, access_port: u8, address: u8) -> Result<u32, Error> {
+        self.read_raw_ap_register(GenericAP::new(access_port), address)
+    }
+
+    fn write(&mut self, access_port: u8, address: u8, value: u32) -> Result<(), Error> {
+        self.write_raw_ap_register(GenericAP::new(access_port), address, value)
+    }
+
+    fn clear_sticky_errors(&mut self) -> Result<(), Error> {
+        let mut abort = Abort(0);
+        abort.set_orunerrclr(true);
+        abort.set_wderrclr(true);
+        abort.set_stkerrclr(true);
+        abort.set_stkcmpclr(true);
+        self.write_raw_dp_register(0, Abort::ADDRESS, abort.into())
+    }
+}
+
+/// Unlocks the debug access with `authenticator`, if it is locked.
+///
+/// If the authentication fails, the sticky errors caused by the failed accesses are cleared,
+/// so the DP can still be used, e.g. to attach with another authenticator.
+pub(crate) fn unlock(
+    authenticator: &mut dyn DebugAuthenticator,
+    interface: &mut dyn ArmProbeInterface,
+) -> Result<(), Error> {
+    if !authenticator.is_locked(interface)? {
+        return Ok(());
+    }
+
+    log::info!(
+        "Debug access is locked, authenticating with {:?}",
+        authenticator
+    );
+    let result = authenticator.authenticate(interface).and_then(|_| {
+        match authenticator.is_locked(interface)? {
+            true => Err(AuthenticationError::StillLocked),
+            false => Ok(()),
+        }
+    });
+
+    if result.is_err() {
+        if let Err(e) = interface.clear_sticky_errors() {
+            log::warn!(
+                "Failed to clear the sticky errors after the authentication: {}",
+                e
+            );
+        }
+    }
+
+    result.map_err(Error::architecture_specific)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MAILBOX: AuthenticationMailbox = AuthenticationMailbox {
+        access_port: 2,
+        status: 0x08,
+        challenge: 0x0C,
+        response: 0x04,
+        challenge_words: 2,
+        unlocked: 1 << 0,
+        rejected: 1 << 1,
+    };
+
+    /// A target which accepts the response if it is the challenge with all bits inverted.
+    struct Target {
+        challenge: Vec<u32>,
+        challenge_read: usize,
+        response: Vec<u32>,
+        status: u32,
+    }
+
+    impl ApRegisters for Target {
+        fn read(&mut self, access_port: u8, address: u8) -> Result<u32, Error> {
+            assert_eq!(access_port, MAILBOX.access_port);
+            match address {
+                0x08 => Ok(self.status),
+                0x0C => {
+                    self.challenge_read += 1;
+                    Ok(self.challenge[self.challenge_read - 1])
+                }
+                _ => panic!("unexpected read of {:#04x}", address),
+            }
+        }
+
+        fn write(&mut self, access_port: u8, address: u8, value: u32) -> Result<(), Error> {
+            assert_eq!(
+                (access_port, address),
+                (MAILBOX.access_port, MAILBOX.response)
+            );
+            self.response.push(value);
+
+            if self.response.len() == self.challenge.len() {
+                let accepted = self
+                    .challenge
+                    .iter()
+                    .zip(&self.response)
+                    .all(|(challenge, response)| *response == !challenge);
+                self.status = if accepted { 0x1 } else { 0x2 };
+            }
+            Ok(())
+        }
+
+        fn clear_sticky_errors(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn target() -> Target {
+        Target {
+            challenge: vec![0x1234_5678, 0x9ABC_DEF0],
+            challenge_read: 0,
+            response: vec![],
+            status: 0,
+        }
+    }
+
+    #[test]
+    fn signed_challenge_unlocks() {
+        let mut target = target();
+        let mut authenticator = ChallengeResponse::new(MAILBOX, |challenge| {
+            Ok(challenge.iter().map(|word| !word).collect())
+        });
+
+        authenticator.run(&mut target).unwrap();
+        assert_eq!(target.response, vec![!0x1234_5678, !0x9ABC_DEF0]);
+    }
+
+    #[test]
+    fn rejected_response_is_reported() {
+        let mut target = target();
+        let mut authenticator = ChallengeResponse::new(MAILBOX, |challenge| Ok(challenge.to_vec()));
+
+        let error = authenticator.run(&mut target).unwrap_err();
+        assert!(matches!(
+            error,
+            AuthenticationError::Rejected { status: 0x2 }
+        ));
+    }
+
+    #[test]
+    fn signer_errors_are_reported() {
+        let mut target = target();
+        let mut authenticator = ChallengeResponse::new(MAILBOX, |_| Err(anyhow::anyhow!("no key")));
+
+        let error = authenticator.run(&mut target).unwrap_err();
+        assert!(matches!(error, AuthenticationError::Signer(_)));
+        assert!(target.response.is_empty());
+    }
+}
@@ -0,0 +1,396 @@
+//! Identifies the exact chip by reading vendor specific device ID registers.
+//!
+//! The ROM table only identifies the manufacturer and, for some vendors, the chip family.
+//! Most vendors additionally provide a device ID register, and often a register containing
+//! the size of the flash, which together narrow the target down to a few variants.
+
+use crate::config::{ChipFamily, MemoryRegion};
+use crate::{Core, MemoryInterface};
+use jep106::JEP106Code;
+
+const STMICROELECTRONICS: JEP106Code = JEP106Code::new(0x00, 0x20);
+const NORDIC_VLSI: JEP106Code = JEP106Code::new(0x02, 0x44);
+
+/// DBGMCU_IDCODE on most STM32 parts.
+const STM32_DBGMCU_IDCODE: u32 = 0xE004_2000;
+/// DBGMCU_IDCODE on the Cortex-M0(+) based STM32 parts.
+const STM32_DBGMCU_IDCODE_M0: u32 = 0x4001_5800;
+/// DBGMCU_IDCODE on the STM32H7.
+const STM32H7_DBGMCU_IDCODE: u32 = 0x5C00_1000;
+/// The DEV_ID field of DBGMCU_IDCODE.
+const STM32_DEV_ID_MASK: u32 = 0xFFF;
+
+/// FICR.INFO.PART on the nRF52 and nRF91.
+const NRF52_FICR_INFO_PART: u32 = 0x1000_0100;
+const NRF91_FICR_INFO_PART: u32 = 0x00FF_0140;
+/// FICR.INFO.FLASH on the nRF52, in KiB.
+const NRF52_FICR_INFO_FLASH: u32 = 0x1000_0110;
+
+/// A device ID register and the chips it identifies.
+struct KnownDevice {
+    manufacturer: JEP106Code,
+    id_address: u32,
+    id_mask: u32,
+    id: u32,
+    /// The prefixes of the names of the chips with this ID.
+    chips: &'static [&'static str],
+    /// The address of the 16 bit flash size in KiB, if the chip has one.
+    flash_size_address: Option<u32>,
+}
+
+const fn stm32(
+    id_address: u32,
+    id: u32,
+    chips: &'static [&'static str],
+    flash: u32,
+) -> KnownDevice {
+    KnownDevice {
+        manufacturer: STMICROELECTRONICS,
+        id_address,
+        id_mask: STM32_DEV_ID_MASK,
+        id,
+        chips,
+        flash_size_address: if flash == 0 { None } else { Some(flash) },
+    }
+}
+
+const fn nrf(id_address: u32, id: u32, chips: &'static [&'static str], flash: u32) -> KnownDevice {
+    KnownDevice {
+        manufacturer: NORDIC_VLSI,
+        id_address,
+        id_mask: 0xFFFF_FFFF,
+        id,
+        chips,
+        flash_size_address: if flash == 0 { None } else { Some(flash) },
+    }
+}
+
+/// The flash size registers of the STM32 families, `0` if unknown.
+const STM32F0_F3_FLASH_SIZE: u32 = 0x1FFF_F7CC;
+const STM32F1_FLASH_SIZE: u32 = 0x1FFF_F7E0;
+const STM32F4_FLASH_SIZE: u32 = 0x1FFF_7A22;
+const STM32L4_G0_G4_FLASH_SIZE: u32 = 0x1FFF_75E0;
+
+#[rustfmt::skip]
+const KNOWN_DEVICES: &[KnownDevice] = &[
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x440, &["STM32F030x8", "STM32F05"], STM32F0_F3_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x442, &["STM32F030xC", "STM32F09"], STM32F0_F3_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x444, &["STM32F030x4", "STM32F030x6", "STM32F03"], STM32F0_F3_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x445, &["STM32F04", "STM32F070x6"], STM32F0_F3_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x448, &["STM32F07"], STM32F0_F3_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x417, &["STM32L05", "STM32L06"], 0),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x425, &["STM32L03", "STM32L04"], 0),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x447, &["STM32L07", "STM32L08"], 0),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x457, &["STM32L01", "STM32L02"], 0),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x456, &["STM32G05", "STM32G06"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x460, &["STM32G07", "STM32G08"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x466, &["STM32G03", "STM32G04"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE_M0, 0x467, &["STM32G0B", "STM32G0C"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x410, &["STM32F10"], STM32F1_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x412, &["STM32F10"], STM32F1_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x414, &["STM32F10"], STM32F1_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x418, &["STM32F105", "STM32F107"], STM32F1_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x430, &["STM32F101", "STM32F103"], STM32F1_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x422, &["STM32F302xB", "STM32F302xC", "STM32F303xB", "STM32F303xC", "STM32F358"], STM32F0_F3_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x432, &["STM32F37"], STM32F0_F3_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x438, &["STM32F303x6", "STM32F303x8", "STM32F328", "STM32F334"], STM32F0_F3_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x439, &["STM32F301", "STM32F302x6", "STM32F302x8", "STM32F318"], STM32F0_F3_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x446, &["STM32F302xD", "STM32F302xE", "STM32F303xD", "STM32F303xE", "STM32F398"], STM32F0_F3_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x413, &["STM32F405", "STM32F407", "STM32F415", "STM32F417"], STM32F4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x419, &["STM32F427", "STM32F429", "STM32F437", "STM32F439"], STM32F4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x421, &["STM32F446"], STM32F4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x423, &["STM32F401xB", "STM32F401xC"], STM32F4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x431, &["STM32F411"], STM32F4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x433, &["STM32F401xD", "STM32F401xE"], STM32F4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x434, &["STM32F469", "STM32F479"], STM32F4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x441, &["STM32F412"], STM32F4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x458, &["STM32F410"], STM32F4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x463, &["STM32F413", "STM32F423"], STM32F4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x449, &["STM32F74", "STM32F75"], 0),
+    stm32(STM32_DBGMCU_IDCODE, 0x451, &["STM32F76", "STM32F77"], 0),
+    stm32(STM32_DBGMCU_IDCODE, 0x452, &["STM32F72", "STM32F73"], 0),
+    stm32(STM32_DBGMCU_IDCODE, 0x416, &["STM32L1"], 0),
+    stm32(STM32_DBGMCU_IDCODE, 0x427, &["STM32L1"], 0),
+    stm32(STM32_DBGMCU_IDCODE, 0x429, &["STM32L1"], 0),
+    stm32(STM32_DBGMCU_IDCODE, 0x436, &["STM32L1"], 0),
+    stm32(STM32_DBGMCU_IDCODE, 0x437, &["STM32L1"], 0),
+    stm32(STM32_DBGMCU_IDCODE, 0x415, &["STM32L47", "STM32L48"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x435, &["STM32L43", "STM32L44"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x461, &["STM32L496", "STM32L4A6"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x462, &["STM32L45", "STM32L46"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x464, &["STM32L41", "STM32L42"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x470, &["STM32L4R", "STM32L4S"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x471, &["STM32L4P", "STM32L4Q"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x468, &["STM32G431", "STM32G441"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x469, &["STM32G47", "STM32G48"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x479, &["STM32G491", "STM32G4A1"], STM32L4_G0_G4_FLASH_SIZE),
+    stm32(STM32_DBGMCU_IDCODE, 0x495, &["STM32WB"], 0),
+    stm32(STM32_DBGMCU_IDCODE, 0x497, &["STM32WL"], 0),
+    stm32(STM32H7_DBGMCU_IDCODE, 0x450, &["STM32H742", "STM32H743", "STM32H745", "STM32H747", "STM32H750", "STM32H753", "STM32H755", "STM32H757"], 0),
+    stm32(STM32H7_DBGMCU_IDCODE, 0x480, &["STM32H7A", "STM32H7B"], 0),
+    stm32(STM32H7_DBGMCU_IDCODE, 0x483, &["STM32H72", "STM32H73"], 0),
+    nrf(NRF52_FICR_INFO_PART, 0x52805, &["nRF52805"], NRF52_FICR_INFO_FLASH),
+    nrf(NRF52_FICR_INFO_PART, 0x52810, &["nRF52810"], NRF52_FICR_INFO_FLASH),
+    nrf(NRF52_FICR_INFO_PART, 0x52811, &["nRF52811"], NRF52_FICR_INFO_FLASH),
+    nrf(NRF52_FICR_INFO_PART, 0x52820, &["nRF52820"], NRF52_FICR_INFO_FLASH),
+    nrf(NRF52_FICR_INFO_PART, 0x52832, &["nRF52832"], NRF52_FICR_INFO_FLASH),
+    nrf(NRF52_FICR_INFO_PART, 0x52833, &["nRF52833"], NRF52_FICR_INFO_FLASH),
+    nrf(NRF52_FICR_INFO_PART, 0x52840, &["nRF52840"], NRF52_FICR_INFO_FLASH),
+    nrf(NRF91_FICR_INFO_PART, 0x9160, &["nRF9160"], 0),
+];
+
+/// A chip from the registry which matches the device ID read from the target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChipCandidate {
+    /// The name of the chip, which can be used as the target name of a session.
+    pub name: String,
+    /// How likely the target is this chip, between 0 and 1.
+    ///
+    /// The confidence of all candidates adds up to 1.
+    pub confidence: f32,
+}
+
+/// The value read from a device ID register.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdRegisterValue {
+    pub address: u32,
+    pub value: u32,
+}
+
+/// The result of [`Session::auto_detect_chip`](crate::Session::auto_detect_chip).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChipDetection {
+    /// The manufacturer from the ROM table, if it is known.
+    pub manufacturer: Option<JEP106Code>,
+    /// The values of all device ID registers which could be read.
+    ///
+    /// If no candidate is found, these values help adding support for the chip.
+    pub id_registers: Vec<IdRegisterValue>,
+    /// The matching chips, most likely first.
+    pub candidates: Vec<ChipCandidate>,
+}
+
+/// Reads the device ID registers of `manufacturer`, or of all known manufacturers
+/// if the ROM table has no manufacturer, and looks up the matching chips in `families`.
+pub(crate) fn detect_chip(
+    core: &mut Core,
+    manufacturer: Option<JEP106Code>,
+    families: &[ChipFamily],
+) -> ChipDetection {
+    let mut id_registers: Vec<IdRegisterValue> = vec![];
+    let mut candidates = vec![];
+
+    let devices = KNOWN_DEVICES
+        .iter()
+        .filter(|device| manufacturer.is_none() || manufacturer == Some(device.manufacturer));
+
+    for device in devices {
+        let value = match id_registers
+            .iter()
+            .find(|register| register.address == device.id_address)
+        {
+            Some(register) => register.value,
+            None => match core.read_word_32(device.id_address.into()) {
+                Ok(value) => {
+                    log::debug!(
+                        "Device ID register {:#010x}: {:#010x}",
+                        device.id_address,
+                        value
+                    );
+                    id_registers.push(IdRegisterValue {
+                        address: device.id_address,
+                        value,
+                    });
+                    value
+                }
+                Err(e) => {
+                    // The register does not exist on the chip, so it can not be this device.
+                    log::debug!(
+                        "Unable to read device ID register {:#010x}: {}",
+                        device.id_address,
+                        e
+                    );
+                    continue;
+                }
+            },
+        };
+
+        if value & device.id_mask != device.id {
+            continue;
+        }
+
+        let flash_size_kib = device
+            .flash_size_address
+            .and_then(|address| read_flash_size(core, address));
+
+        candidates = rank_candidates(device.chips, flash_size_kib, families);
+        break;
+    }
+
+    ChipDetection {
+        manufacturer,
+        id_registers,
+        candidates,
+    }
+}
+
+fn read_flash_size(core: &mut Core, address: u32) -> Option<u32> {
+    let mut size = [0u8; 2];
+
+    match core.read(address.into(), &mut size) {
+        Ok(()) => Some(u32::from(u16::from_le_bytes(size))),
+        Err(e) => {
+            log::debug!("Unable to read flash size at {:#010x}: {}", address, e);
+            None
+        }
+    }
+}
+
+/// Checks if `name` starts with `prefix`, ignoring the case.
+///
+/// A lower case `x` in the prefix matches any character, like in the names of the
+/// STM32 variants, where it stands for the package or flash size.
+fn matches_prefix(name: &str, prefix: &str) -> bool {
+    name.len() >= prefix.len()
+        && name
+            .chars()
+            .zip(prefix.chars())
+            .all(|(n, p)| p == 'x' || n.eq_ignore_ascii_case(&p))
+}
+
+/// Returns the chips in `families` starting with one of the `prefixes`,
+/// where chips with a total flash size of `flash_size_kib` are more likely.
+fn rank_candidates(
+    prefixes: &[&str],
+    flash_size_kib: Option<u32>,
+    families: &[ChipFamily],
+) -> Vec<ChipCandidate> {
+    // A chip with a different flash size can still match, as the size
+    // only describes the tested flash, and is sometimes larger.
+    const FLASH_MISMATCH_WEIGHT: f32 = 0.1;
+
+    let mut weighted: Vec<(String, f32)> = families
+        .iter()
+        .flat_map(|family| family.variants.iter())
+        .filter(|chip| {
+            prefixes
+                .iter()
+                .any(|prefix| matches_prefix(&chip.name, prefix))
+        })
+        .map(|chip| {
+            let flash_size: u32 = chip
+                .memory_map
+                .iter()
+                .filter_map(|region| match region {
+                    MemoryRegion::Flash(flash) => Some(flash.range.end - flash.range.start),
+                    _ => None,
+                })
+                .sum();
+
+            let weight = match flash_size_kib {
+                Some(kib) if kib * 1024 != flash_size => FLASH_MISMATCH_WEIGHT,
+                _ => 1.0,
+            };
+
+            (chip.name.to_string(), weight)
+        })
+        .collect();
+
+    let total: f32 = weighted.iter().map(|(_, weight)| weight).sum();
+
+    weighted.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    weighted
+        .into_iter()
+        .map(|(name, weight)| ChipCandidate {
+            name,
+            confidence: weight / total,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Chip, FlashRegion, RamRegion};
+    use std::borrow::Cow;
+
+    fn chip(name: &'static str, flash_kib: u32) -> Chip {
+        Chip {
+            name: Cow::Borrowed(name),
+            part: None,
+            memory_map: Cow::Owned(vec![
+                MemoryRegion::Ram(RamRegion {
+                    range: 0x2000_0000..0x2002_0000,
+                    is_boot_memory: false,
+                }),
+                MemoryRegion::Flash(FlashRegion {
+                    range: 0x0800_0000..0x0800_0000 + flash_kib * 1024,
+                    is_boot_memory: true,
+                }),
+            ]),
+            flash_algorithms: Cow::Borrowed(&[]),
+            byte_order: Default::default(),
+            default_reset_type: Default::default(),
+        }
+    }
+
+    fn stm32f4_family() -> ChipFamily {
+        ChipFamily {
+            name: Cow::Borrowed("STM32F4 Series"),
+            manufacturer: None,
+            variants: Cow::Owned(vec![
+                chip("STM32F401CCUx", 256),
+                chip("STM32F407VETx", 512),
+                chip("STM32F407VGTx", 1024),
+                chip("STM32F411CEUx", 512),
+            ]),
+            flash_algorithms: Cow::Borrowed(&[]),
+            core: Cow::Borrowed("M4"),
+        }
+    }
+
+    #[test]
+    fn candidates_are_ranked_by_flash_size() {
+        let candidates = rank_candidates(
+            &["STM32F405", "STM32F407", "STM32F415", "STM32F417"],
+            Some(1024),
+            &[stm32f4_family()],
+        );
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].name, "STM32F407VGTx");
+        assert_eq!(candidates[1].name, "STM32F407VETx");
+        assert!(candidates[0].confidence > 0.9);
+    }
+
+    #[test]
+    fn wildcards_in_prefix() {
+        assert!(matches_prefix("STM32F401CCUx", "STM32F401xC"));
+        assert!(!matches_prefix("STM32F401CEUx", "STM32F401xC"));
+        assert!(matches_prefix("nRF52832_xxAA", "nrf52832"));
+    }
+
+    #[test]
+    fn unknown_flash_size_spreads_confidence() {
+        let candidates = rank_candidates(&["STM32F407"], None, &[stm32f4_family()]);
+
+        assert_eq!(candidates.len(), 2);
+        assert!(candidates
+            .iter()
+            .all(|candidate| (candidate.confidence - 0.5).abs() < f32::EPSILON));
+    }
+
+    #[test]
+    fn device_ids_are_unique() {
+        for (i, device) in KNOWN_DEVICES.iter().enumerate() {
+            assert!(
+                !KNOWN_DEVICES[i + 1..]
+                    .iter()
+                    .any(|other| other.id_address == device.id_address && other.id == device.id),
+                "Duplicate device ID {:#x}",
+                device.id
+            );
+        }
+    }
+}
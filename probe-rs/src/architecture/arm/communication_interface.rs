@@ -1,17 +1,18 @@
 use super::{
     ap::{
-        valid_access_ports, APAccess, APClass, APRegister, AccessPort, BaseaddrFormat, DataSize,
-        GenericAP, MemoryAP, BASE, BASE2, CSW, IDR,
+        valid_access_ports, APAccess, APClass, APRegister, AccessPort, AccessPortError,
+        BaseaddrFormat, DataSize, GenericAP, MemoryAP, BASE, BASE2, CFG, CSW, IDR,
     },
     dp::{
         Abort, Ctrl, DPAccess, DPBankSel, DPRegister, DebugPortError, DebugPortId,
         DebugPortVersion, Select, DPIDR,
     },
-    memory::{adi_v5_memory_interface::ADIMemoryInterface, Component},
+    memory::{adi_v5_memory_interface::ADIMemoryInterface, Component, MemoryAccessAttributes},
     SwoAccess, SwoConfig,
 };
 use crate::{
-    CommunicationInterface, DebugProbe, DebugProbeError, Error as ProbeRsError, Memory, Probe,
+    AttachConfig, CommunicationInterface, DebugProbe, DebugProbeError, Error as ProbeRsError,
+    Memory, Probe,
 };
 use anyhow::anyhow;
 use jep106::JEP106Code;
@@ -27,12 +28,31 @@ pub enum DapError {
     FaultResponse,
     #[error("Target device responded with WAIT response to request.")]
     WaitResponse,
+    #[error("Target device responded with FAULT response to request, Ctrl/Stat register value: {ctrl_stat:#010x}")]
+    FaultResponseWithStatus {
+        /// The value of the Ctrl/Stat register when the first fault occured,
+        /// before its sticky error flags were cleared.
+        ctrl_stat: u32,
+    },
     #[error("Target power-up failed.")]
     TargetPowerUpFailed,
     #[error("Incorrect parity on READ request.")]
     IncorrectParity,
 }
 
+impl DapError {
+    /// Returns the `Ctrl/Stat` register of the DP, with the sticky error flags of the first fault,
+    /// if the target answered with FAULT.
+    ///
+    /// The flags are only known if they could be read before the fault was cleared.
+    pub fn fault_status(&self) -> Option<Ctrl> {
+        match self {
+            DapError::FaultResponseWithStatus { ctrl_stat } => Some(Ctrl::from(*ctrl_stat)),
+            _ => None,
+        }
+    }
+}
+
 impl From<DapError> for DebugProbeError {
     fn from(error: DapError) -> Self {
         DebugProbeError::ArchitectureSpecific(Box::new(error))
@@ -63,7 +83,11 @@ impl From<PortType> for u16 {
         }
     }
 }
-use std::{fmt::Debug, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    time::{Duration, Instant},
+};
 
 pub trait Register: Clone + From<u32> + Into<u32> + Sized + Debug {
     const ADDRESS: u8;
@@ -91,6 +115,20 @@ pub trait DAPAccess: DebugProbe + AsRef<dyn DebugProbe> + AsMut<dyn DebugProbe>
         Ok(())
     }
 
+    /// Reads multiple DAP registers, returning their values in order.
+    ///
+    /// Probes which queue transfers send all reads, together with the queued writes,
+    /// in as few packets as possible. The order of the transfers is always kept.
+    fn read_registers(
+        &mut self,
+        registers: &[(PortType, u16)],
+    ) -> Result<Vec<u32>, DebugProbeError> {
+        registers
+            .iter()
+            .map(|(port, addr)| self.read_register(*port, *addr))
+            .collect()
+    }
+
     /// Writes a value to the DAP register on the specified port and address
     fn write_register(
         &mut self,
@@ -119,7 +157,8 @@ pub trait DAPAccess: DebugProbe + AsRef<dyn DebugProbe> + AsMut<dyn DebugProbe>
     /// Flush any outstanding writes.
     ///
     /// By default, this does nothing -- but in probes that implement write
-    /// batching, this needs to flush any pending writes.
+    /// batching, this needs to flush any pending writes. Reads always flush
+    /// the pending writes first.
     fn flush(&mut self) -> Result<(), DebugProbeError> {
         Ok(())
     }
@@ -138,9 +177,95 @@ pub trait ArmProbeInterface:
 
     fn read_from_rom_table(&mut self) -> Result<Option<ArmChipInfo>, ProbeRsError>;
 
+    /// Reads the register at `address` of the given AP, where the upper four bits of
+    /// `address` select the bank of the register.
+    fn read_raw_ap_register(
+        &mut self,
+        access_port: GenericAP,
+        address: u8,
+    ) -> Result<u32, ProbeRsError>;
+
+    /// Writes `value` to the register at `address` of the given AP, where the upper four
+    /// bits of `address` select the bank of the register.
+    fn write_raw_ap_register(
+        &mut self,
+        access_port: GenericAP,
+        address: u8,
+        value: u32,
+    ) -> Result<(), ProbeRsError>;
+
+    /// Reads the DP register at `address`, selecting `bank` first if the register is banked.
+    fn read_raw_dp_register(&mut self, bank: u8, address: u8) -> Result<u32, ProbeRsError>;
+
+    /// Writes `value` to the DP register at `address`, selecting `bank` first if the register is banked.
+    ///
+    /// A write to `SELECT` updates the cached selection, so following accesses select
+    /// their AP and bank again if needed.
+    fn write_raw_dp_register(
+        &mut self,
+        bank: u8,
+        address: u8,
+        value: u32,
+    ) -> Result<(), ProbeRsError>;
+
+    /// Sets the attributes of the memory accesses through `access_port`, e.g. to access the
+    /// secure view of the memory.
+    ///
+    /// Returns an error if the AP does not support the attributes, instead of silently
+    /// accessing the memory with other attributes.
+    fn set_memory_access_attributes(
+        &mut self,
+        access_port: MemoryAP,
+        attributes: MemoryAccessAttributes,
+    ) -> Result<(), ProbeRsError>;
+
+    /// Configures how transfers answered with WAIT or FAULT are retried.
+    ///
+    /// Interfaces where the probe firmware handles retries itself ignore this.
+    fn set_retry_config(&mut self, _config: DapRetryConfig) {}
+
+    /// Re-establishes the connection to the debug port, after it was reset together with the target.
+    ///
+    /// The information about the access ports is kept, as the target itself has not changed.
+    fn reinitialize(&mut self) -> Result<(), ProbeRsError>;
+
     fn close(self: Box<Self>) -> Probe;
 }
 
+/// The delay before the first retry of a transfer which was answered with WAIT.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_micros(100);
+
+/// Configures how transfers answered with WAIT or FAULT are retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DapRetryConfig {
+    /// The number of times a transfer is retried before the error is returned.
+    pub max_retries: u32,
+    /// The upper limit for the delay between retries after a WAIT response.
+    ///
+    /// The delay starts at 100 µs and is doubled after every retry.
+    pub max_delay: Duration,
+}
+
+impl DapRetryConfig {
+    /// The delay before the retry with the given number, starting at zero.
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = INITIAL_RETRY_DELAY
+            .checked_mul(1 << retry.min(16))
+            .unwrap_or(self.max_delay);
+
+        delay.min(self.max_delay)
+    }
+}
+
+impl Default for DapRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            max_delay: Duration::from_millis(10),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct ArmCommunicationInterfaceState {
     pub debug_port_version: DebugPortVersion,
@@ -153,6 +278,15 @@ pub(crate) struct ArmCommunicationInterfaceState {
     /// Information about the APs of the target.
     /// APs are identified by a number, starting from zero.
     pub ap_information: Vec<ApInformation>,
+
+    /// How transfers answered with WAIT or FAULT are retried.
+    pub retry_config: DapRetryConfig,
+
+    /// The attributes of the memory accesses of the APs, APs without an entry use the default attributes.
+    pub access_attributes: HashMap<u8, MemoryAccessAttributes>,
+
+    /// The timeout and the retries used while powering up the debug port.
+    pub attach_config: AttachConfig,
 }
 
 #[derive(Debug)]
@@ -167,6 +301,9 @@ pub enum ApInformation {
         /// also support other widths. Based on this, 8 bit data access can either
         /// be performed directly, or has to be done as a 32 bit access.
         only_32bit_data_size: bool,
+        /// Memory APs with the Large Physical Address Extension support addresses above 4GB,
+        /// all others only support 32 bit addresses.
+        supports_large_address: bool,
         /// The Debug Base Address points to either the start of a set of debug register,
         /// or a ROM table which describes the connected debug components.
         ///
@@ -183,6 +320,13 @@ pub enum ApInformation {
 }
 
 impl ArmCommunicationInterfaceState {
+    /// Updates the cached selection after `select` was written to the DP.
+    pub(crate) fn select_written(&mut self, select: Select) {
+        self.current_apsel = select.ap_sel();
+        self.current_apbanksel = select.ap_bank_sel();
+        self.current_dpbanksel = select.dp_bank_sel();
+    }
+
     pub fn new() -> Self {
         Self {
             debug_port_version: DebugPortVersion::Unsupported(0xFF),
@@ -190,6 +334,9 @@ impl ArmCommunicationInterfaceState {
             current_apsel: 0,
             current_apbanksel: 0,
             ap_information: Vec::new(),
+            retry_config: DapRetryConfig::default(),
+            access_attributes: HashMap::new(),
+            attach_config: AttachConfig::default(),
         }
     }
 }
@@ -213,10 +360,109 @@ impl ArmProbeInterface for ArmCommunicationInterface {
         ArmCommunicationInterface::read_from_rom_table(self)
     }
 
+    fn read_raw_ap_register(
+        &mut self,
+        access_port: GenericAP,
+        address: u8,
+    ) -> Result<u32, ProbeRsError> {
+        log::debug!("Reading raw AP register {:#04x}", address);
+        self.select_ap_and_ap_bank(access_port.port_number(), address >> 4)?;
+
+        let port = PortType::AccessPort(u16::from(self.state.current_apsel));
+        Ok(self.retry_transfer(|probe| probe.read_register(port, u16::from(address)))?)
+    }
+
+    fn write_raw_ap_register(
+        &mut self,
+        access_port: GenericAP,
+        address: u8,
+        value: u32,
+    ) -> Result<(), ProbeRsError> {
+        log::debug!(
+            "Writing raw AP register {:#04x}, value=0x{:08x}",
+            address,
+            value
+        );
+        self.select_ap_and_ap_bank(access_port.port_number(), address >> 4)?;
+
+        let port = PortType::AccessPort(u16::from(self.state.current_apsel));
+        Ok(self.retry_transfer(|probe| probe.write_register(port, u16::from(address), value))?)
+    }
+
+    fn read_raw_dp_register(&mut self, bank: u8, address: u8) -> Result<u32, ProbeRsError> {
+        log::debug!("Reading raw DP register {:#04x}", address);
+        self.select_dp_bank(raw_dp_bank(bank, address))
+            .map_err(DebugProbeError::from)?;
+
+        Ok(self
+            .retry_transfer(|probe| probe.read_register(PortType::DebugPort, u16::from(address)))?)
+    }
+
+    fn write_raw_dp_register(
+        &mut self,
+        bank: u8,
+        address: u8,
+        value: u32,
+    ) -> Result<(), ProbeRsError> {
+        log::debug!(
+            "Writing raw DP register {:#04x}, value=0x{:08x}",
+            address,
+            value
+        );
+        self.select_dp_bank(raw_dp_bank(bank, address))
+            .map_err(DebugProbeError::from)?;
+
+        self.retry_transfer(|probe| {
+            probe.write_register(PortType::DebugPort, u16::from(address), value)
+        })?;
+
+        if address == Select::ADDRESS {
+            self.state.select_written(Select(value));
+        }
+
+        Ok(())
+    }
+
     fn num_access_ports(&self) -> usize {
         self.state.ap_information.len()
     }
 
+    fn set_memory_access_attributes(
+        &mut self,
+        access_port: MemoryAP,
+        attributes: MemoryAccessAttributes,
+    ) -> Result<(), ProbeRsError> {
+        if attributes != MemoryAccessAttributes::default() {
+            check_access_attributes(self, access_port, attributes)?;
+        }
+
+        self.state
+            .access_attributes
+            .insert(access_port.port_number(), attributes);
+        Ok(())
+    }
+
+    fn set_retry_config(&mut self, config: DapRetryConfig) {
+        self.state.retry_config = config;
+    }
+
+    fn reinitialize(&mut self) -> Result<(), ProbeRsError> {
+        self.probe.attach()?;
+
+        // The selected banks were reset together with the debug port.
+        self.state = ArmCommunicationInterfaceState {
+            ap_information: std::mem::take(&mut self.state.ap_information),
+            retry_config: self.state.retry_config,
+            access_attributes: std::mem::take(&mut self.state.access_attributes),
+            attach_config: self.state.attach_config,
+            ..ArmCommunicationInterfaceState::new()
+        };
+
+        self.enter_debug_mode()?;
+
+        Ok(())
+    }
+
     fn close(self: Box<Self>) -> Probe {
         Probe::from_attached_probe(self.probe.into_probe())
     }
@@ -235,12 +481,18 @@ impl<'a> AsMut<dyn DebugProbe + 'a> for ArmCommunicationInterface {
 }
 
 impl<'interface> ArmCommunicationInterface {
-    pub(crate) fn new(probe: Box<dyn DAPAccess>) -> Result<Self, DebugProbeError> {
-        let state = ArmCommunicationInterfaceState::new();
+    pub(crate) fn new(
+        probe: Box<dyn DAPAccess>,
+        config: &AttachConfig,
+    ) -> Result<Self, DebugProbeError> {
+        let state = ArmCommunicationInterfaceState {
+            attach_config: *config,
+            ..ArmCommunicationInterfaceState::new()
+        };
 
         let mut interface = Self { probe, state };
 
-        interface.enter_debug_mode()?;
+        interface.enter_debug_mode_with_retries()?;
 
         /* determine the number and type of available APs */
 
@@ -267,14 +519,27 @@ impl<'interface> ArmCommunicationInterface {
             ApInformation::MemoryAp {
                 port_number: _,
                 only_32bit_data_size,
+                supports_large_address,
                 debug_base_address: _,
             } => {
                 let only_32bit_data_size = *only_32bit_data_size;
-                let adi_v5_memory_interface = ADIMemoryInterface::<
-                    'interface,
-                    ArmCommunicationInterface,
-                >::new(self, only_32bit_data_size)
-                .map_err(ProbeRsError::architecture_specific)?;
+                let supports_large_address = *supports_large_address;
+                let max_block_size = self.probe.max_block_size();
+                let access_attributes = self
+                    .state
+                    .access_attributes
+                    .get(&access_port.port_number())
+                    .copied()
+                    .unwrap_or_default();
+                let adi_v5_memory_interface =
+                    ADIMemoryInterface::<'interface, ArmCommunicationInterface>::new(
+                        self,
+                        only_32bit_data_size,
+                        supports_large_address,
+                        max_block_size,
+                        access_attributes,
+                    )
+                    .map_err(ProbeRsError::architecture_specific)?;
 
                 Ok(Memory::new(adi_v5_memory_interface, access_port))
             }
@@ -285,6 +550,38 @@ impl<'interface> ArmCommunicationInterface {
         }
     }
 
+    /// Powers up the debug port, retrying as configured in the `AttachConfig`.
+    ///
+    /// Every retry attaches the probe again, which starts over with the line reset or
+    /// JTAG-to-SWD sequence, in case the target missed it.
+    fn enter_debug_mode_with_retries(&mut self) -> Result<(), DebugProbeError> {
+        let config = self.state.attach_config;
+        let mut retry = 0;
+
+        loop {
+            match self.enter_debug_mode() {
+                Err(e) if retry < config.retry_count => {
+                    retry += 1;
+                    log::warn!(
+                        "Powering up the debug port failed, retrying ({}/{}): {}",
+                        retry,
+                        config.retry_count,
+                        e
+                    );
+                    std::thread::sleep(config.retry_delay);
+
+                    self.probe.attach()?;
+                    self.state = ArmCommunicationInterfaceState {
+                        attach_config: config,
+                        retry_config: self.state.retry_config,
+                        ..ArmCommunicationInterfaceState::new()
+                    };
+                }
+                result => return result,
+            }
+        }
+    }
+
     fn enter_debug_mode(&mut self) -> Result<(), DebugProbeError> {
         // Assume that we have DebugPort v1 Interface!
         // Maybe change this in the future when other versions are released.
@@ -320,14 +617,94 @@ impl<'interface> ArmCommunicationInterface {
         ctrl_reg.set_cdbgpwrupreq(true);
         self.write_dp_register(ctrl_reg)?;
 
-        // Check the return value to see whether power up was ok.
-        let ctrl_reg: Ctrl = self.read_dp_register()?;
-        if !(ctrl_reg.csyspwrupack() && ctrl_reg.cdbgpwrupack()) {
-            log::error!("Debug power request failed");
-            return Err(DapError::TargetPowerUpFailed.into());
+        // Wait until the power up is acknowledged.
+        let start = Instant::now();
+        loop {
+            let ctrl_reg: Ctrl = self.read_dp_register()?;
+            if ctrl_reg.csyspwrupack() && ctrl_reg.cdbgpwrupack() {
+                return Ok(());
+            }
+
+            if start.elapsed() >= self.state.attach_config.connect_timeout {
+                log::error!("Debug power request failed");
+                return Err(DapError::TargetPowerUpFailed.into());
+            }
         }
+    }
 
-        Ok(())
+    /// Runs a single register transfer, retrying it if the target responds with WAIT or FAULT.
+    ///
+    /// After a WAIT, the transfer is retried with an exponential backoff. After a FAULT, the
+    /// sticky error flags are cleared before retrying. If the transfer still fails with a FAULT,
+    /// the Ctrl/Stat value captured at the first fault is returned in the error.
+    ///
+    /// Block transfers are not retried, as a partial transfer may already have incremented the
+    /// transfer address.
+    fn retry_transfer<T>(
+        &mut self,
+        mut transfer: impl FnMut(&mut dyn DAPAccess) -> Result<T, DebugProbeError>,
+    ) -> Result<T, DebugProbeError> {
+        let config = self.state.retry_config;
+        let mut fault_status = None;
+        let mut retry = 0;
+
+        loop {
+            let error = match transfer(&mut *self.probe) {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+
+            let is_fault = match dap_error(&error) {
+                Some(DapError::WaitResponse) => false,
+                Some(DapError::FaultResponse) => true,
+                _ => return Err(error),
+            };
+
+            if is_fault {
+                // The sticky flags are only readable as long as they are not cleared,
+                // so the status of the first fault is kept for the final error.
+                if fault_status.is_none() && self.state.current_dpbanksel == 0 {
+                    fault_status = self
+                        .probe
+                        .read_register(PortType::DebugPort, u16::from(Ctrl::ADDRESS))
+                        .ok();
+                }
+            }
+
+            if retry >= config.max_retries {
+                return match fault_status {
+                    Some(ctrl_stat) if is_fault => {
+                        log::error!(
+                            "DAP transfer failed after {} retries, Ctrl/Stat: {:#?}",
+                            retry,
+                            Ctrl::from(ctrl_stat)
+                        );
+                        Err(DapError::FaultResponseWithStatus { ctrl_stat }.into())
+                    }
+                    _ => Err(error),
+                };
+            }
+
+            if is_fault {
+                log::debug!("DAP transfer answered with FAULT, clearing sticky errors");
+                let mut abort_reg = Abort(0);
+                abort_reg.set_orunerrclr(true);
+                abort_reg.set_wderrclr(true);
+                abort_reg.set_stkerrclr(true);
+                abort_reg.set_stkcmpclr(true);
+                self.probe.write_register(
+                    PortType::DebugPort,
+                    u16::from(Abort::ADDRESS),
+                    abort_reg.into(),
+                )?;
+            } else {
+                let delay = config.delay(retry);
+                log::debug!("DAP transfer answered with WAIT, retrying in {:?}", delay);
+                std::thread::sleep(delay);
+            }
+
+            retry += 1;
+        }
     }
 
     fn select_ap_and_ap_bank(&mut self, port: u8, ap_bank: u8) -> Result<(), DebugProbeError> {
@@ -406,11 +783,10 @@ impl<'interface> ArmCommunicationInterface {
 
         self.select_ap_and_ap_bank(port.into().port_number(), R::APBANKSEL)?;
 
-        self.probe.write_register(
-            PortType::AccessPort(u16::from(self.state.current_apsel)),
-            u16::from(R::ADDRESS),
-            register_value,
-        )?;
+        let port = PortType::AccessPort(u16::from(self.state.current_apsel));
+        self.retry_transfer(|probe| {
+            probe.write_register(port, u16::from(R::ADDRESS), register_value)
+        })?;
         Ok(())
     }
 
@@ -458,10 +834,9 @@ impl<'interface> ArmCommunicationInterface {
         log::debug!("Reading register {}", R::NAME);
         self.select_ap_and_ap_bank(port.into().port_number(), R::APBANKSEL)?;
 
-        let result = self.probe.read_register(
-            PortType::AccessPort(u16::from(self.state.current_apsel)),
-            u16::from(R::ADDRESS),
-        )?;
+        let port = PortType::AccessPort(u16::from(self.state.current_apsel));
+        let result =
+            self.retry_transfer(|probe| probe.read_register(port, u16::from(R::ADDRESS)))?;
 
         log::debug!("Read register    {}, value=0x{:08x}", R::NAME, result);
 
@@ -532,10 +907,12 @@ impl<'interface> ArmCommunicationInterface {
             base_address |= u64::from(base_register.BASEADDR << 12);
 
             let only_32bit_data_size = ap_supports_only_32bit_access(self, access_port)?;
+            let cfg = self.read_ap_register(access_port, CFG::default())?;
 
             Ok(ApInformation::MemoryAp {
                 port_number: access_port.port_number(),
                 only_32bit_data_size,
+                supports_large_address: cfg.LA == 1,
                 debug_base_address: base_address,
             })
         } else {
@@ -552,6 +929,25 @@ impl<'interface> ArmCommunicationInterface {
     }
 }
 
+/// Returns the bank which has to be selected to access the DP register at `address`.
+///
+/// Only `CTRL/STAT` at address 0x4 is banked, the bank of all other registers does not matter.
+pub(crate) fn raw_dp_bank(bank: u8, address: u8) -> DPBankSel {
+    if address & 0xF == Ctrl::ADDRESS {
+        DPBankSel::Bank(bank)
+    } else {
+        DPBankSel::DontCare
+    }
+}
+
+/// Returns the `DapError` wrapped in `error`, if there is one.
+fn dap_error(error: &DebugProbeError) -> Option<&DapError> {
+    match error {
+        DebugProbeError::ArchitectureSpecific(error) => error.downcast_ref::<DapError>(),
+        _ => None,
+    }
+}
+
 impl CommunicationInterface for ArmCommunicationInterface {
     fn flush(&mut self) -> Result<(), DebugProbeError> {
         self.probe.flush()
@@ -570,9 +966,9 @@ impl DPAccess for ArmCommunicationInterface {
         self.select_dp_bank(R::DP_BANK)?;
 
         log::debug!("Reading DP register {}", R::NAME);
-        let result = self
-            .probe
-            .read_register(PortType::DebugPort, u16::from(R::ADDRESS))?;
+        let result = self.retry_transfer(|probe| {
+            probe.read_register(PortType::DebugPort, u16::from(R::ADDRESS))
+        })?;
 
         log::debug!("Read    DP register {}, value=0x{:08x}", R::NAME, result);
 
@@ -592,8 +988,9 @@ impl DPAccess for ArmCommunicationInterface {
         let value = register.into();
 
         log::debug!("Writing DP register {}, value=0x{:08x}", R::NAME, value);
-        self.probe
-            .write_register(PortType::DebugPort, R::ADDRESS as u16, value)?;
+        self.retry_transfer(|probe| {
+            probe.write_register(PortType::DebugPort, u16::from(R::ADDRESS), value)
+        })?;
 
         Ok(())
     }
@@ -603,21 +1000,21 @@ impl SwoAccess for ArmCommunicationInterface {
     fn enable_swo(&mut self, config: &SwoConfig) -> Result<(), ProbeRsError> {
         match self.probe.get_swo_interface_mut() {
             Some(interface) => interface.enable_swo(config),
-            None => Err(ProbeRsError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+            None => Err(ProbeRsError::NotSupported("SWO")),
         }
     }
 
     fn disable_swo(&mut self) -> Result<(), ProbeRsError> {
         match self.probe.get_swo_interface_mut() {
             Some(interface) => interface.disable_swo(),
-            None => Err(ProbeRsError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+            None => Err(ProbeRsError::NotSupported("SWO")),
         }
     }
 
     fn read_swo_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, ProbeRsError> {
         match self.probe.get_swo_interface_mut() {
             Some(interface) => interface.read_swo_timeout(timeout),
-            None => Err(ProbeRsError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+            None => Err(ProbeRsError::NotSupported("SWO")),
         }
     }
 }
@@ -712,13 +1109,49 @@ fn ap_supports_only_32bit_access(
     interface: &mut ArmCommunicationInterface,
     ap: MemoryAP,
 ) -> Result<bool, DebugProbeError> {
-    let csw = ADIMemoryInterface::<ArmCommunicationInterface>::build_csw_register(DataSize::U8);
+    let csw = ADIMemoryInterface::<ArmCommunicationInterface>::build_csw_register(
+        DataSize::U8,
+        MemoryAccessAttributes::default(),
+    );
     interface.write_ap_register(ap, csw)?;
     let csw = interface.read_ap_register(ap, CSW::default())?;
 
     Ok(csw.SIZE != DataSize::U8)
 }
 
+/// Check that the memory AP supports accesses with `attributes`.
+///
+/// Attributes which are not supported are read-only in CSW, so CSW is written and read back.
+/// Secure accesses also require secure debug to be enabled, as reported by `CSW.SPIDEN`.
+fn check_access_attributes(
+    interface: &mut ArmCommunicationInterface,
+    ap: MemoryAP,
+    attributes: MemoryAccessAttributes,
+) -> Result<(), ProbeRsError> {
+    let csw = interface.read_ap_register(ap, CSW::default())?;
+    if attributes.secure && csw.SPIDEN == 0 {
+        return Err(ProbeRsError::architecture_specific(
+            AccessPortError::SecureAccessDisabled,
+        ));
+    }
+
+    let expected = ADIMemoryInterface::<ArmCommunicationInterface>::build_csw_register(
+        DataSize::U32,
+        attributes,
+    );
+    interface.write_ap_register(ap, expected)?;
+    let csw = interface.read_ap_register(ap, CSW::default())?;
+
+    // Only HNONSEC and HPROT[3:1] are set from the attributes.
+    if (csw.PROT & 0b100, csw.CACHE & 0b1110) != (expected.PROT & 0b100, expected.CACHE & 0b1110) {
+        return Err(ProbeRsError::architecture_specific(
+            AccessPortError::UnsupportedAccessAttributes(attributes),
+        ));
+    }
+
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct ArmChipInfo {
     pub manufacturer: JEP106Code,
@@ -779,3 +1212,443 @@ impl std::fmt::Display for ArmChipInfo {
         write!(f, "{} 0x{:04x}", manu, self.part)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::architecture::arm::ap::{DRW, TAR};
+    use crate::architecture::arm::ArmError;
+    use crate::{DebugProbeSelector, WireProtocol};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// A DAP which stores the values written to its registers.
+    #[derive(Debug)]
+    struct RegisterDap {
+        select: Select,
+        dp: HashMap<u16, u32>,
+        ap: HashMap<(u8, u8, u16), u32>,
+        /// The bits of AP registers which are not changed by writes.
+        read_only: HashMap<(u8, u8, u16), u32>,
+        /// The number of attaches after which the debug port acknowledges power-up requests.
+        power_up_after_attaches: u32,
+        attaches: u32,
+        /// The values written to ABORT, shared with the test.
+        aborts: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl RegisterDap {
+        fn new() -> Self {
+            Self {
+                select: Select(0),
+                dp: HashMap::new(),
+                ap: HashMap::new(),
+                read_only: HashMap::new(),
+                power_up_after_attaches: 0,
+                attaches: 0,
+                aborts: Arc::default(),
+            }
+        }
+
+        fn ap_key(&self, port: PortType, addr: u16) -> (u8, u8, u16) {
+            assert_eq!(port, PortType::AccessPort(u16::from(self.select.ap_sel())));
+            (self.select.ap_sel(), self.select.ap_bank_sel(), addr & 0xF)
+        }
+    }
+
+    impl DebugProbe for RegisterDap {
+        fn new_from_selector(
+            _selector: impl Into<DebugProbeSelector>,
+        ) -> Result<Box<Self>, DebugProbeError> {
+            unimplemented!()
+        }
+
+        fn get_name(&self) -> &str {
+            "Register DAP for testing"
+        }
+
+        fn speed(&self) -> u32 {
+            unimplemented!()
+        }
+
+        fn set_speed(&mut self, _speed_khz: u32) -> Result<u32, DebugProbeError> {
+            unimplemented!()
+        }
+
+        fn attach(&mut self) -> Result<(), DebugProbeError> {
+            self.attaches += 1;
+            Ok(())
+        }
+
+        fn detach(&mut self) -> Result<(), DebugProbeError> {
+            Ok(())
+        }
+
+        fn target_reset(&mut self) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+
+        fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+
+        fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+
+        fn select_protocol(&mut self, _protocol: WireProtocol) -> Result<(), DebugProbeError> {
+            unimplemented!()
+        }
+    }
+
+    impl DAPAccess for RegisterDap {
+        fn read_register(&mut self, port: PortType, addr: u16) -> Result<u32, DebugProbeError> {
+            Ok(match port {
+                PortType::DebugPort if addr == u16::from(Ctrl::ADDRESS) => {
+                    let ctrl = self.dp.get(&addr).copied().unwrap_or(0);
+                    match self.attaches >= self.power_up_after_attaches {
+                        // Acknowledge the power-up requests in CSYSPWRUPACK and CDBGPWRUPACK.
+                        true => ctrl | (ctrl & 0x5000_0000) << 1,
+                        false => ctrl,
+                    }
+                }
+                PortType::DebugPort => self.dp.get(&addr).copied().unwrap_or(0),
+                _ => self.ap[&self.ap_key(port, addr)],
+            })
+        }
+
+        fn write_register(
+            &mut self,
+            port: PortType,
+            addr: u16,
+            value: u32,
+        ) -> Result<(), DebugProbeError> {
+            match port {
+                PortType::DebugPort if addr == u16::from(Select::ADDRESS) => {
+                    self.select = Select(value)
+                }
+                // Writes to ABORT do not change DPIDR at the same address.
+                PortType::DebugPort if addr == u16::from(Abort::ADDRESS) => {
+                    self.aborts.lock().unwrap().push(value)
+                }
+                PortType::DebugPort => {
+                    self.dp.insert(addr, value);
+                }
+                _ => {
+                    let key = self.ap_key(port, addr);
+                    let read_only = self.read_only.get(&key).copied().unwrap_or(0);
+                    let old = self.ap.get(&key).copied().unwrap_or(0);
+                    self.ap
+                        .insert(key, (old & read_only) | (value & !read_only));
+                }
+            }
+            Ok(())
+        }
+
+        fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+            self
+        }
+    }
+
+    impl<'a> AsRef<dyn DebugProbe + 'a> for RegisterDap {
+        fn as_ref(&self) -> &(dyn DebugProbe + 'a) {
+            self
+        }
+    }
+
+    impl<'a> AsMut<dyn DebugProbe + 'a> for RegisterDap {
+        fn as_mut(&mut self) -> &mut (dyn DebugProbe + 'a) {
+            self
+        }
+    }
+
+    #[test]
+    fn raw_csw_writes_are_overwritten_by_memory_accesses() {
+        let mut state = ArmCommunicationInterfaceState::new();
+        state
+            .ap_information
+            .push(ApInformation::Other { port_number: 0 });
+        state.ap_information.push(ApInformation::MemoryAp {
+            port_number: 1,
+            only_32bit_data_size: false,
+            supports_large_address: false,
+            debug_base_address: 0,
+        });
+        let mut interface = ArmCommunicationInterface {
+            probe: Box::new(RegisterDap::new()),
+            state,
+        };
+        let ap = GenericAP::new(1);
+
+        // Select the second AP and the bank of IDR behind the back of the interface.
+        let mut select = Select(0);
+        select.set_ap_sel(1);
+        select.set_ap_bank_sel(0xF);
+        interface
+            .write_raw_dp_register(0, Select::ADDRESS, select.into())
+            .unwrap();
+
+        let csw = CSW {
+            SIZE: DataSize::U8,
+            ..Default::default()
+        };
+        interface
+            .write_raw_ap_register(ap, CSW::ADDRESS, csw.into())
+            .unwrap();
+        let csw = CSW::from(interface.read_raw_ap_register(ap, CSW::ADDRESS).unwrap());
+        assert_eq!(csw.SIZE, DataSize::U8);
+
+        let csw = CSW {
+            SIZE: DataSize::U16,
+            ..Default::default()
+        };
+        interface
+            .write_raw_ap_register(ap, CSW::ADDRESS, csw.into())
+            .unwrap();
+        let csw = CSW::from(interface.read_raw_ap_register(ap, CSW::ADDRESS).unwrap());
+        assert_eq!(csw.SIZE, DataSize::U16);
+
+        interface
+            .write_raw_ap_register(ap, DRW::ADDRESS, 0x1234_5678)
+            .unwrap();
+        let mut memory = interface.memory_interface(MemoryAP::new(1)).unwrap();
+        assert_eq!(memory.read_word_32(0x2000_0000).unwrap(), 0x1234_5678);
+        drop(memory);
+
+        let csw = CSW::from(interface.read_raw_ap_register(ap, CSW::ADDRESS).unwrap());
+        assert_eq!(csw.SIZE, DataSize::U32);
+        assert_eq!(
+            interface.read_raw_ap_register(ap, TAR::ADDRESS).unwrap(),
+            0x2000_0000
+        );
+    }
+
+    /// An interface to a memory AP without TrustZone support, where HNONSEC is always set.
+    fn interface_without_trustzone(spiden: bool) -> ArmCommunicationInterface {
+        const HNONSEC: u32 = 1 << 30;
+        let spiden = u32::from(spiden) << 23;
+
+        let mut state = ArmCommunicationInterfaceState::new();
+        state.ap_information.push(ApInformation::MemoryAp {
+            port_number: 0,
+            only_32bit_data_size: false,
+            supports_large_address: false,
+            debug_base_address: 0,
+        });
+
+        let mut probe = RegisterDap::new();
+        let csw = (0, 0, u16::from(CSW::ADDRESS));
+        probe.ap.insert(csw, HNONSEC | spiden);
+        probe.read_only.insert(csw, HNONSEC | (1 << 23));
+        probe.ap.insert((0, 0, u16::from(DRW::ADDRESS)), 0);
+
+        ArmCommunicationInterface {
+            probe: Box::new(probe),
+            state,
+        }
+    }
+
+    fn access_port_error(error: ProbeRsError) -> AccessPortError {
+        match error {
+            ProbeRsError::Arm(ArmError::AccessPort(error)) => error,
+            error => panic!("unexpected error {}", error),
+        }
+    }
+
+    #[test]
+    fn unsupported_access_attributes_are_rejected() {
+        let ap = MemoryAP::new(0);
+        let secure = MemoryAccessAttributes {
+            secure: true,
+            ..Default::default()
+        };
+
+        let mut interface = interface_without_trustzone(false);
+        let error = interface
+            .set_memory_access_attributes(ap, secure)
+            .unwrap_err();
+        assert!(matches!(
+            access_port_error(error),
+            AccessPortError::SecureAccessDisabled
+        ));
+
+        let mut interface = interface_without_trustzone(true);
+        let error = interface
+            .set_memory_access_attributes(ap, secure)
+            .unwrap_err();
+        assert!(matches!(
+            access_port_error(error),
+            AccessPortError::UnsupportedAccessAttributes(_)
+        ));
+
+        // The non-secure attributes are supported, and used by the memory accesses.
+        let cacheable = MemoryAccessAttributes {
+            cacheable: true,
+            ..Default::default()
+        };
+        interface
+            .set_memory_access_attributes(ap, cacheable)
+            .unwrap();
+        interface
+            .write_raw_ap_register(GenericAP::new(0), CSW::ADDRESS, 0)
+            .unwrap();
+        let mut memory = interface.memory_interface(ap).unwrap();
+        memory.read_word_32(0x2000_0000).unwrap();
+        drop(memory);
+
+        let csw = CSW::from(
+            interface
+                .read_raw_ap_register(GenericAP::new(0), CSW::ADDRESS)
+                .unwrap(),
+        );
+        assert_eq!(csw.CACHE, 0b1111);
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        let config = DapRetryConfig {
+            max_retries: 8,
+            max_delay: Duration::from_millis(1),
+        };
+
+        assert_eq!(config.delay(0), Duration::from_micros(100));
+        assert_eq!(config.delay(1), Duration::from_micros(200));
+        assert_eq!(config.delay(3), Duration::from_micros(800));
+        assert_eq!(config.delay(4), Duration::from_millis(1));
+        assert_eq!(config.delay(40), Duration::from_millis(1));
+    }
+
+    /// An interface whose transfers are retried at most three times with a delay of at most 1 ms.
+    fn retrying_interface(probe: RegisterDap) -> ArmCommunicationInterface {
+        let mut state = ArmCommunicationInterfaceState::new();
+        state.retry_config = DapRetryConfig {
+            max_retries: 3,
+            max_delay: Duration::from_millis(1),
+        };
+
+        ArmCommunicationInterface {
+            probe: Box::new(probe),
+            state,
+        }
+    }
+
+    /// A transfer which fails with the errors of `responses`, and succeeds once all were returned.
+    fn scripted_transfer(
+        responses: Vec<DapError>,
+        attempts: &mut u32,
+    ) -> impl FnMut(&mut dyn DAPAccess) -> Result<u32, DebugProbeError> + '_ {
+        let mut responses = responses.into_iter();
+        move |_| {
+            *attempts += 1;
+            match responses.next() {
+                Some(error) => Err(error.into()),
+                None => Ok(0x1234_5678),
+            }
+        }
+    }
+
+    #[test]
+    fn transfers_are_retried_after_wait() {
+        let mut interface = retrying_interface(RegisterDap::new());
+        let mut attempts = 0;
+
+        let responses = vec![DapError::WaitResponse, DapError::WaitResponse];
+        let result = interface.retry_transfer(scripted_transfer(responses, &mut attempts));
+
+        assert_eq!(result.unwrap(), 0x1234_5678);
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn persistent_wait_is_limited_by_the_retry_config() {
+        let mut interface = retrying_interface(RegisterDap::new());
+        let mut attempts = 0;
+
+        let start = Instant::now();
+        let responses = (0..10).map(|_| DapError::WaitResponse).collect();
+        let error = interface
+            .retry_transfer(scripted_transfer(responses, &mut attempts))
+            .unwrap_err();
+
+        assert!(matches!(dap_error(&error), Some(DapError::WaitResponse)));
+        assert_eq!(attempts, 4);
+        // The delays of 100, 200 and 400 µs are all below the longest delay of 1 ms.
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_micros(700));
+        assert!(elapsed < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn persistent_fault_reports_the_first_ctrl_stat() {
+        const CTRL_STAT: u32 = 0x0000_0022;
+
+        let mut probe = RegisterDap::new();
+        probe.dp.insert(u16::from(Ctrl::ADDRESS), CTRL_STAT);
+        let aborts = probe.aborts.clone();
+        let mut interface = retrying_interface(probe);
+        let mut attempts = 0;
+
+        let responses = (0..10).map(|_| DapError::FaultResponse).collect();
+        let error = interface
+            .retry_transfer(scripted_transfer(responses, &mut attempts))
+            .unwrap_err();
+
+        assert!(matches!(
+            dap_error(&error),
+            Some(DapError::FaultResponseWithStatus {
+                ctrl_stat: CTRL_STAT
+            })
+        ));
+        assert_eq!(attempts, 4);
+
+        // The sticky errors are cleared before every retry.
+        let mut abort = Abort(0);
+        abort.set_orunerrclr(true);
+        abort.set_wderrclr(true);
+        abort.set_stkerrclr(true);
+        abort.set_stkcmpclr(true);
+        assert_eq!(*aborts.lock().unwrap(), vec![u32::from(abort); 3]);
+    }
+
+    #[test]
+    fn dap_errors_are_recognized() {
+        let wait: DebugProbeError = DapError::WaitResponse.into();
+
+        assert!(matches!(dap_error(&wait), Some(DapError::WaitResponse)));
+        assert!(dap_error(&DebugProbeError::Timeout).is_none());
+    }
+
+    fn interface_powering_up_after_attaches(
+        attaches: u32,
+        retry_count: u32,
+    ) -> ArmCommunicationInterface {
+        let mut probe = RegisterDap::new();
+        probe.dp.insert(u16::from(DPIDR::ADDRESS), 0x2BA0_1477);
+        probe.power_up_after_attaches = attaches;
+
+        ArmCommunicationInterface {
+            probe: Box::new(probe),
+            state: ArmCommunicationInterfaceState {
+                attach_config: AttachConfig {
+                    connect_timeout: Duration::from_millis(1),
+                    retry_count,
+                    retry_delay: Duration::from_millis(0),
+                },
+                ..ArmCommunicationInterfaceState::new()
+            },
+        }
+    }
+
+    #[test]
+    fn debug_port_power_up_is_retried() {
+        let mut interface = interface_powering_up_after_attaches(2, 2);
+        interface.enter_debug_mode_with_retries().unwrap();
+
+        let mut interface = interface_powering_up_after_attaches(2, 1);
+        let error = interface.enter_debug_mode_with_retries().unwrap_err();
+        assert!(matches!(
+            dap_error(&error),
+            Some(DapError::TargetPowerUpFailed)
+        ));
+    }
+}
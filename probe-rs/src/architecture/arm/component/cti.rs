@@ -0,0 +1,73 @@
+//! Module for using the CTI.
+//!
+//! CTI = Cross Trigger Interface
+
+use super::super::memory::romtable::Component;
+use crate::{Core, Error};
+
+const REGISTER_OFFSET_CTICONTROL: u32 = 0x000;
+const REGISTER_OFFSET_CTIINTACK: u32 = 0x010;
+const REGISTER_OFFSET_CTIAPPPULSE: u32 = 0x01C;
+const REGISTER_OFFSET_CTIOUTEN: u32 = 0x0A0;
+const REGISTER_OFFSET_CTIGATE: u32 = 0x140;
+const REGISTER_OFFSET_ACCESS: u32 = 0xFB0;
+
+/// The CTI trigger output connected to the debug request input of the core.
+pub const TRIGGER_OUT_DEBUG_REQUEST: u32 = 0;
+/// The CTI trigger output connected to the debug restart input of the core.
+pub const TRIGGER_OUT_DEBUG_RESTART: u32 = 1;
+
+/// CTI unit
+///
+/// Routes trigger events between the cores through the cross trigger matrix.
+pub struct Cti<'probe: 'core, 'core> {
+    component: &'core Component,
+    core: &'core mut Core<'probe>,
+}
+
+impl<'probe: 'core, 'core> Cti<'probe, 'core> {
+    pub fn new(core: &'core mut Core<'probe>, component: &'core Component) -> Self {
+        Cti { core, component }
+    }
+
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        self.component
+            .write_reg(self.core, REGISTER_OFFSET_ACCESS, 0xC5AC_CE55)?;
+
+        Ok(())
+    }
+
+    /// Enables the CTI and lets all channels pass to the cross trigger matrix.
+    pub fn enable(&mut self) -> Result<(), Error> {
+        self.component
+            .write_reg(self.core, REGISTER_OFFSET_CTIGATE, 0xF)?;
+        self.component
+            .write_reg(self.core, REGISTER_OFFSET_CTICONTROL, 1)?;
+
+        Ok(())
+    }
+
+    /// Connects the trigger output `trigger` to the channels set in the `channels` mask.
+    pub fn set_output_channels(&mut self, trigger: u32, channels: u32) -> Result<(), Error> {
+        self.component
+            .write_reg(self.core, REGISTER_OFFSET_CTIOUTEN + 4 * trigger, channels)?;
+
+        Ok(())
+    }
+
+    /// Deasserts the trigger output `trigger`.
+    pub fn acknowledge(&mut self, trigger: u32) -> Result<(), Error> {
+        self.component
+            .write_reg(self.core, REGISTER_OFFSET_CTIINTACK, 1 << trigger)?;
+
+        Ok(())
+    }
+
+    /// Generates a single event on `channel`, which reaches all the CTIs connected to the matrix.
+    pub fn pulse(&mut self, channel: u32) -> Result<(), Error> {
+        self.component
+            .write_reg(self.core, REGISTER_OFFSET_CTIAPPPULSE, 1 << channel)?;
+
+        Ok(())
+    }
+}
@@ -80,6 +80,27 @@ impl<'probe: 'core, 'core> Dwt<'probe, 'core> {
         ctrl.store(self.component, self.core)
     }
 
+    /// Enables periodic PC sampling.
+    ///
+    /// A PC sample is emitted every `64 * (postpreset + 1)` cycles when CYCTAP is cleared,
+    /// `postpreset` is limited to four bits.
+    pub fn enable_pc_sampling(&mut self, postpreset: u8) -> Result<(), Error> {
+        let mut ctrl = Ctrl::load(self.component, self.core)?;
+        ctrl.set_cyctap(false);
+        ctrl.set_postinit(postpreset & 0xF);
+        ctrl.set_postpreset(u32::from(postpreset & 0xF));
+        ctrl.set_cyccntena(true);
+        ctrl.set_pcsamplena(true);
+        ctrl.store(self.component, self.core)
+    }
+
+    /// Disables periodic PC sampling.
+    pub fn disable_pc_sampling(&mut self) -> Result<(), Error> {
+        let mut ctrl = Ctrl::load(self.component, self.core)?;
+        ctrl.set_pcsamplena(false);
+        ctrl.store(self.component, self.core)
+    }
+
     /// Disable exception tracing.
     pub fn disable_exception_trace(&mut self) -> Result<(), Error> {
         let mut ctrl = Ctrl::load(self.component, self.core)?;
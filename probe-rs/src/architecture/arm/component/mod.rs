@@ -1,3 +1,4 @@
+mod cti;
 mod dwt;
 mod itm;
 mod tpiu;
@@ -7,6 +8,7 @@ use crate::architecture::arm::core::m0::Demcr;
 use crate::architecture::arm::{SwoConfig, SwoMode};
 use crate::core::CoreRegister;
 use crate::{Core, Error, MemoryInterface};
+pub use cti::{Cti, TRIGGER_OUT_DEBUG_REQUEST, TRIGGER_OUT_DEBUG_RESTART};
 pub use dwt::Dwt;
 pub use itm::Itm;
 pub use tpiu::Tpiu;
@@ -90,7 +92,7 @@ fn setup_swv_vendor(
             // STMicroelectronics:
             // STM32 parts need TRACE_IOEN set to 1 and TRACE_MODE set to 00.
             log::debug!("STMicroelectronics part detected, configuring DBGMCU");
-            const DBGMCU: u32 = 0xE004_2004;
+            const DBGMCU: u64 = 0xE004_2004;
             let mut dbgmcu = core.read_word_32(DBGMCU)?;
             dbgmcu |= 1 << 5;
             dbgmcu &= !(0b00 << 6);
@@ -99,7 +101,7 @@ fn setup_swv_vendor(
         Some(id) if id == jep106::JEP106Code::new(0x02, 0x44) => {
             // Nordic VLSI ASA
             log::debug!("Nordic part detected, configuring CLOCK TRACECONFIG");
-            const CLOCK_TRACECONFIG: u32 = 0x4000_055C;
+            const CLOCK_TRACECONFIG: u64 = 0x4000_055C;
             let mut traceconfig: u32 = 0;
             traceconfig |= match config.tpiu_clk() {
                 4_000_000 => 3,
@@ -139,6 +141,22 @@ pub fn remove_swv_data_trace(
     dwt.disable_data_trace(unit)
 }
 
+/// Configures the DWT to emit a PC sample every `64 * (postpreset + 1)` cycles.
+pub fn enable_pc_sampling(
+    core: &mut Core,
+    component: &Component,
+    postpreset: u8,
+) -> Result<(), Error> {
+    let mut dwt = component.dwt(core).map_err(Error::architecture_specific)?;
+    dwt.enable_pc_sampling(postpreset)
+}
+
+/// Stops the DWT from emitting PC samples.
+pub fn disable_pc_sampling(core: &mut Core, component: &Component) -> Result<(), Error> {
+    let mut dwt = component.dwt(core).map_err(Error::architecture_specific)?;
+    dwt.disable_pc_sampling()
+}
+
 /// Sets TRCENA in DEMCR to begin trace generation.
 pub fn enable_tracing(core: &mut Core) -> Result<(), Error> {
     let mut demcr = Demcr(core.read_word_32(Demcr::ADDRESS)?);
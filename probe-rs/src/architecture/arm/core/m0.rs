@@ -1,7 +1,7 @@
-use super::{reset_catch_clear, reset_catch_set, CortexState, Dfsr, ARM_REGISTER_FILE};
+use super::{reset_catch_finish, reset_catch_set, CortexState, Dfsr, ARM_REGISTER_FILE};
 use crate::core::{
     Architecture, CoreInformation, CoreInterface, CoreRegister, CoreRegisterAddress,
-    RegisterDescription, RegisterFile, RegisterKind,
+    RegisterDescription, RegisterFile, RegisterKind, VectorCatchFlags,
 };
 use crate::error::Error;
 use crate::memory::Memory;
@@ -56,7 +56,7 @@ impl From<Dhcsr> for u32 {
 }
 
 impl CoreRegister for Dhcsr {
-    const ADDRESS: u32 = 0xE000_EDF0;
+    const ADDRESS: u64 = 0xE000_EDF0;
     const NAME: &'static str = "DHCSR";
 }
 
@@ -76,7 +76,7 @@ impl From<Dcrdr> for u32 {
 }
 
 impl CoreRegister for Dcrdr {
-    const ADDRESS: u32 = 0xE000_EDF8;
+    const ADDRESS: u64 = 0xE000_EDF8;
     const NAME: &'static str = "DCRDR";
 }
 
@@ -108,7 +108,7 @@ impl From<BpCtrl> for u32 {
 }
 
 impl CoreRegister for BpCtrl {
-    const ADDRESS: u32 = 0xE000_2000;
+    const ADDRESS: u64 = 0xE000_2000;
     const NAME: &'static str = "BP_CTRL";
 }
 
@@ -148,7 +148,7 @@ impl From<BpCompx> for u32 {
 }
 
 impl CoreRegister for BpCompx {
-    const ADDRESS: u32 = 0xE000_2008;
+    const ADDRESS: u64 = 0xE000_2008;
     const NAME: &'static str = "BP_CTRL0";
 }
 
@@ -185,7 +185,7 @@ impl Aircr {
 }
 
 impl CoreRegister for Aircr {
-    const ADDRESS: u32 = 0xE000_ED0C;
+    const ADDRESS: u64 = 0xE000_ED0C;
     const NAME: &'static str = "AIRCR";
 }
 
@@ -219,7 +219,7 @@ impl From<Demcr> for u32 {
 }
 
 impl CoreRegister for Demcr {
-    const ADDRESS: u32 = 0xe000_edfc;
+    const ADDRESS: u64 = 0xe000_edfc;
     const NAME: &'static str = "DEMCR";
 }
 
@@ -357,16 +357,11 @@ impl<'probe> CoreInterface for M0<'probe> {
     }
 
     fn step(&mut self) -> Result<CoreInformation, Error> {
-        let mut value = Dhcsr(0);
-        // Leave halted state.
-        // Step one instruction.
-        value.set_c_step(true);
-        value.set_c_halt(false);
-        value.set_c_debugen(true);
-        value.set_c_maskints(true);
-        value.enable_write();
+        self.step_with_interrupts(false)
+    }
 
-        self.memory.write_word_32(Dhcsr::ADDRESS, value.into())?;
+    fn step_with_interrupts(&mut self, interrupts: bool) -> Result<CoreInformation, Error> {
+        super::start_step(self, interrupts)?;
 
         self.wait_for_core_halted(Duration::from_millis(100))?;
 
@@ -390,11 +385,22 @@ impl<'probe> CoreInterface for M0<'probe> {
     }
 
     fn reset_and_halt(&mut self, timeout: Duration) -> Result<CoreInformation, Error> {
+        self.reset_and_halt_at_reset_vector(timeout)
+    }
+
+    fn reset_and_halt_at_reset_vector(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<CoreInformation, Error> {
+        // Set the vc_corereset bit in the DEMCR register.
+        // This will halt the core after reset.
         reset_catch_set(self)?;
 
-        self.reset()?;
+        let halted = self
+            .reset()
+            .and_then(|_| self.wait_for_core_halted(timeout));
 
-        self.wait_for_core_halted(timeout)?;
+        reset_catch_finish(self, halted)?;
 
         const XPSR_THUMB: u32 = 1 << 24;
         let xpsr_value = self.read_core_reg(XPSR.address)?;
@@ -402,8 +408,6 @@ impl<'probe> CoreInterface for M0<'probe> {
             self.write_core_reg(XPSR.address, xpsr_value | XPSR_THUMB)?;
         }
 
-        reset_catch_clear(self)?;
-
         // try to read the program counter
         let pc_value = self.read_core_reg(PC.address)?;
 
@@ -445,7 +449,7 @@ impl<'probe> CoreInterface for M0<'probe> {
         value.set_comp((addr >> 2) & 0x00FF_FFFF);
         value.set_enable(true);
 
-        let register_addr = BpCompx::ADDRESS + (bp_register_index * size_of::<u32>()) as u32;
+        let register_addr = BpCompx::ADDRESS + (bp_register_index * size_of::<u32>()) as u64;
 
         self.memory.write_word_32(register_addr, value.into())?;
 
@@ -457,7 +461,7 @@ impl<'probe> CoreInterface for M0<'probe> {
     }
 
     fn clear_breakpoint(&mut self, bp_unit_index: usize) -> Result<(), Error> {
-        let register_addr = BpCompx::ADDRESS + (bp_unit_index * size_of::<u32>()) as u32;
+        let register_addr = BpCompx::ADDRESS + (bp_unit_index * size_of::<u32>()) as u64;
 
         let mut value = BpCompx::from(0);
         value.set_enable(false);
@@ -471,6 +475,23 @@ impl<'probe> CoreInterface for M0<'probe> {
         self.state.hw_breakpoints_enabled
     }
 
+    fn set_vector_catch(&mut self, flags: VectorCatchFlags) -> Result<(), Error> {
+        // ARMv6-M only has a vector catch for HardFaults and resets.
+        super::set_vector_catch(
+            self,
+            flags,
+            VectorCatchFlags::HARDERR | VectorCatchFlags::CORERESET,
+        )
+    }
+
+    fn vector_catch(&mut self) -> Result<VectorCatchFlags, Error> {
+        super::vector_catch(self)
+    }
+
+    fn keep_alive(&mut self) -> Result<(), Error> {
+        self.memory.keep_alive()
+    }
+
     fn architecture(&self) -> Architecture {
         Architecture::Arm
     }
@@ -542,28 +563,28 @@ impl<'probe> CoreInterface for M0<'probe> {
 }
 
 impl<'probe> MemoryInterface for M0<'probe> {
-    fn read_word_32(&mut self, address: u32) -> Result<u32, Error> {
+    fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
         self.memory.read_word_32(address)
     }
-    fn read_word_8(&mut self, address: u32) -> Result<u8, Error> {
+    fn read_word_8(&mut self, address: u64) -> Result<u8, Error> {
         self.memory.read_word_8(address)
     }
-    fn read_32(&mut self, address: u32, data: &mut [u32]) -> Result<(), Error> {
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
         self.memory.read_32(address, data)
     }
-    fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), Error> {
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
         self.memory.read_8(address, data)
     }
-    fn write_word_32(&mut self, address: u32, data: u32) -> Result<(), Error> {
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), Error> {
         self.memory.write_word_32(address, data)
     }
-    fn write_word_8(&mut self, address: u32, data: u8) -> Result<(), Error> {
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), Error> {
         self.memory.write_word_8(address, data)
     }
-    fn write_32(&mut self, address: u32, data: &[u32]) -> Result<(), Error> {
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), Error> {
         self.memory.write_32(address, data)
     }
-    fn write_8(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
         self.memory.write_8(address, data)
     }
     fn flush(&mut self) -> Result<(), Error> {
@@ -6,7 +6,7 @@ use crate::memory::Memory;
 use crate::{
     core::{
         Architecture, CoreInformation, CoreInterface, CoreRegister, CoreRegisterAddress,
-        RegisterFile,
+        RegisterFile, VectorCatchFlags,
     },
     CoreStatus, DebugProbeError, HaltReason,
 };
@@ -16,7 +16,7 @@ use crate::{architecture::arm::core::register, MemoryInterface};
 
 use bitfield::bitfield;
 
-use super::{reset_catch_clear, reset_catch_set, CortexState, Dfsr, ARM_REGISTER_FILE};
+use super::{reset_catch_finish, reset_catch_set, CortexState, Dfsr, ARM_REGISTER_FILE};
 use std::{
     mem::size_of,
     time::{Duration, Instant},
@@ -128,13 +128,22 @@ impl<'probe> CoreInterface for M33<'probe> {
     }
 
     fn reset_and_halt(&mut self, timeout: Duration) -> Result<CoreInformation, Error> {
+        self.reset_and_halt_at_reset_vector(timeout)
+    }
+
+    fn reset_and_halt_at_reset_vector(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<CoreInformation, Error> {
         // Set the vc_corereset bit in the DEMCR register.
         // This will halt the core after reset.
         reset_catch_set(self)?;
 
-        self.reset()?;
+        let halted = self
+            .reset()
+            .and_then(|_| self.wait_for_core_halted(timeout));
 
-        self.wait_for_core_halted(timeout)?;
+        reset_catch_finish(self, halted)?;
 
         const XPSR_THUMB: u32 = 1 << 24;
         let xpsr_value = self.read_core_reg(register::XPSR.address)?;
@@ -142,8 +151,6 @@ impl<'probe> CoreInterface for M33<'probe> {
             self.write_core_reg(register::XPSR.address, xpsr_value | XPSR_THUMB)?;
         }
 
-        reset_catch_clear(self)?;
-
         // try to read the program counter
         let pc_value = self.read_core_reg(register::PC.address)?;
 
@@ -152,16 +159,11 @@ impl<'probe> CoreInterface for M33<'probe> {
     }
 
     fn step(&mut self) -> Result<CoreInformation, Error> {
-        let mut value = Dhcsr(0);
-        // Leave halted state.
-        // Step one instruction.
-        value.set_c_step(true);
-        value.set_c_halt(false);
-        value.set_c_debugen(true);
-        value.set_c_maskints(true);
-        value.enable_write();
+        self.step_with_interrupts(false)
+    }
 
-        self.memory.write_word_32(Dhcsr::ADDRESS, value.into())?;
+    fn step_with_interrupts(&mut self, interrupts: bool) -> Result<CoreInformation, Error> {
+        super::start_step(self, interrupts)?;
 
         self.wait_for_core_halted(Duration::from_millis(100))?;
 
@@ -210,7 +212,7 @@ impl<'probe> CoreInterface for M33<'probe> {
         val.set_bp_addr(comp_val);
         val.set_enable(true);
 
-        let reg_addr = FpCompX::ADDRESS + (bp_unit_index * size_of::<u32>()) as u32;
+        let reg_addr = FpCompX::ADDRESS + (bp_unit_index * size_of::<u32>()) as u64;
 
         self.memory.write_word_32(reg_addr, val.into())?;
 
@@ -226,7 +228,7 @@ impl<'probe> CoreInterface for M33<'probe> {
         val.set_enable(false);
         val.set_bp_addr(0);
 
-        let reg_addr = FpCompX::ADDRESS + (bp_unit_index * size_of::<u32>()) as u32;
+        let reg_addr = FpCompX::ADDRESS + (bp_unit_index * size_of::<u32>()) as u64;
 
         self.memory.write_word_32(reg_addr, val.into())?;
 
@@ -237,6 +239,18 @@ impl<'probe> CoreInterface for M33<'probe> {
         self.state.hw_breakpoints_enabled
     }
 
+    fn set_vector_catch(&mut self, flags: VectorCatchFlags) -> Result<(), Error> {
+        super::set_vector_catch(self, flags, VectorCatchFlags::all())
+    }
+
+    fn vector_catch(&mut self) -> Result<VectorCatchFlags, Error> {
+        super::vector_catch(self)
+    }
+
+    fn keep_alive(&mut self) -> Result<(), Error> {
+        self.memory.keep_alive()
+    }
+
     fn architecture(&self) -> Architecture {
         Architecture::Arm
     }
@@ -300,28 +314,28 @@ impl<'probe> CoreInterface for M33<'probe> {
 }
 
 impl<'probe> MemoryInterface for M33<'probe> {
-    fn read_word_32(&mut self, address: u32) -> Result<u32, Error> {
+    fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
         self.memory.read_word_32(address)
     }
-    fn read_word_8(&mut self, address: u32) -> Result<u8, Error> {
+    fn read_word_8(&mut self, address: u64) -> Result<u8, Error> {
         self.memory.read_word_8(address)
     }
-    fn read_32(&mut self, address: u32, data: &mut [u32]) -> Result<(), Error> {
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
         self.memory.read_32(address, data)
     }
-    fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), Error> {
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
         self.memory.read_8(address, data)
     }
-    fn write_word_32(&mut self, address: u32, data: u32) -> Result<(), Error> {
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), Error> {
         self.memory.write_word_32(address, data)
     }
-    fn write_word_8(&mut self, address: u32, data: u8) -> Result<(), Error> {
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), Error> {
         self.memory.write_word_8(address, data)
     }
-    fn write_32(&mut self, address: u32, data: &[u32]) -> Result<(), Error> {
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), Error> {
         self.memory.write_32(address, data)
     }
-    fn write_8(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
         self.memory.write_8(address, data)
     }
     fn flush(&mut self) -> Result<(), Error> {
@@ -391,7 +405,7 @@ impl From<Dhcsr> for u32 {
 }
 
 impl CoreRegister for Dhcsr {
-    const ADDRESS: u32 = 0xE000_EDF0;
+    const ADDRESS: u64 = 0xE000_EDF0;
     const NAME: &'static str = "DHCSR";
 }
 
@@ -429,7 +443,7 @@ impl Aircr {
 }
 
 impl CoreRegister for Aircr {
-    const ADDRESS: u32 = 0xE000_ED0C;
+    const ADDRESS: u64 = 0xE000_ED0C;
     const NAME: &'static str = "AIRCR";
 }
 
@@ -449,7 +463,7 @@ impl From<Dcrdr> for u32 {
 }
 
 impl CoreRegister for Dcrdr {
-    const ADDRESS: u32 = 0xE000_EDF8;
+    const ADDRESS: u64 = 0xE000_EDF8;
     const NAME: &'static str = "DCRDR";
 }
 
@@ -511,7 +525,7 @@ impl From<Demcr> for u32 {
 }
 
 impl CoreRegister for Demcr {
-    const ADDRESS: u32 = 0xe000_edfc;
+    const ADDRESS: u64 = 0xe000_edfc;
     const NAME: &'static str = "DEMCR";
 }
 
@@ -535,7 +549,7 @@ impl FpCtrl {
 }
 
 impl CoreRegister for FpCtrl {
-    const ADDRESS: u32 = 0xE000_2008;
+    const ADDRESS: u64 = 0xE000_2008;
     const NAME: &'static str = "FP_CTRL";
 }
 
@@ -561,7 +575,7 @@ bitfield! {
 }
 
 impl CoreRegister for FpCompX {
-    const ADDRESS: u32 = 0xE000_2000;
+    const ADDRESS: u64 = 0xE000_2000;
     const NAME: &'static str = "FP_CTRL";
 }
 
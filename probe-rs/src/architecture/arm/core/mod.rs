@@ -1,6 +1,9 @@
 use crate::{
-    core::{CoreRegister, CoreRegisterAddress, RegisterDescription, RegisterFile, RegisterKind},
-    CoreStatus, Error, HaltReason, MemoryInterface,
+    core::{
+        CoreRegister, CoreRegisterAddress, RegisterDescription, RegisterFile, RegisterKind,
+        VectorCatchFlags,
+    },
+    Core, CoreStatus, Error, HaltReason, MemoryInterface,
 };
 
 use bitfield::bitfield;
@@ -25,6 +28,74 @@ pub(crate) fn debug_core_start(core: &mut impl MemoryInterface) -> Result<(), Er
     Ok(())
 }
 
+/// Leaves the halted state to execute a single instruction, with interrupts masked
+/// unless `interrupts` is set.
+///
+/// The core halts again after the step, which has to be awaited by the caller.
+pub(crate) fn start_step(core: &mut impl MemoryInterface, interrupts: bool) -> Result<(), Error> {
+    use crate::architecture::arm::core::m4::Dhcsr;
+
+    // C_MASKINTS must only be changed while the core stays halted, so it is set
+    // before the write which leaves the halted state.
+    let mut dhcsr = Dhcsr(0);
+    dhcsr.set_c_halt(true);
+    dhcsr.set_c_debugen(true);
+    dhcsr.set_c_maskints(!interrupts);
+    dhcsr.enable_write();
+
+    core.write_word_32(Dhcsr::ADDRESS, dhcsr.into())?;
+
+    // Leave halted state.
+    // Step one instruction.
+    dhcsr.set_c_step(true);
+    dhcsr.set_c_halt(false);
+
+    core.write_word_32(Dhcsr::ADDRESS, dhcsr.into())?;
+
+    Ok(())
+}
+
+/// Returns `true` if the core was reset since the debug status was last read, or if debugging
+/// is disabled, which happens when the core loses power.
+///
+/// This has to be checked before debugging is enabled again with `debug_core_start`.
+pub(crate) fn core_was_reset(core: &mut impl MemoryInterface) -> Result<bool, Error> {
+    use crate::architecture::arm::core::m4::Dhcsr;
+
+    let dhcsr = Dhcsr(core.read_word_32(Dhcsr::ADDRESS)?);
+
+    Ok(dhcsr.s_reset_st() || !dhcsr.c_debugen())
+}
+
+/// Sets the vector catch bits of `DEMCR` to `flags`, and clears all others.
+///
+/// Returns `Error::NotSupported` if `flags` contains exceptions outside of `supported`.
+pub(crate) fn set_vector_catch(
+    core: &mut impl MemoryInterface,
+    flags: VectorCatchFlags,
+    supported: VectorCatchFlags,
+) -> Result<(), Error> {
+    use crate::architecture::arm::core::m4::Demcr;
+
+    if !supported.contains(flags) {
+        return Err(Error::NotSupported("vector catch of these exceptions"));
+    }
+
+    let demcr = core.read_word_32(Demcr::ADDRESS)?;
+    let demcr = (demcr & !VectorCatchFlags::all().bits()) | flags.bits();
+
+    core.write_word_32(Demcr::ADDRESS, demcr)
+}
+
+/// Returns the exceptions set to halt the core in `DEMCR`.
+pub(crate) fn vector_catch(core: &mut impl MemoryInterface) -> Result<VectorCatchFlags, Error> {
+    use crate::architecture::arm::core::m4::Demcr;
+
+    let demcr = core.read_word_32(Demcr::ADDRESS)?;
+
+    Ok(VectorCatchFlags::from_bits_truncate(demcr))
+}
+
 /// Setup the core to stop after reset. After this, the core will halt when it comes
 /// out of reset. This is based on the `ResetCatchSet` function from
 /// the [ARM SVD Debug Description].
@@ -61,6 +132,28 @@ pub(crate) fn reset_catch_clear(core: &mut impl MemoryInterface) -> Result<(), E
     Ok(())
 }
 
+/// Clears the reset catch set up by `reset_catch_set` once the core either halted or failed to halt,
+/// as given by `halted`.
+///
+/// If the core did not halt, debugging is enabled again, as some resets also reset the debug logic.
+/// The original error is returned in that case.
+pub(crate) fn reset_catch_finish(
+    core: &mut impl MemoryInterface,
+    halted: Result<(), Error>,
+) -> Result<(), Error> {
+    if let Err(error) = halted {
+        log::warn!("The core did not halt at the reset vector: {}", error);
+
+        if let Err(e) = debug_core_start(core).and_then(|_| reset_catch_clear(core)) {
+            log::warn!("Failed to restore the debug registers: {}", e);
+        }
+
+        return Err(error);
+    }
+
+    reset_catch_clear(core)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CortexDump {
     pub regs: [u32; 16],
@@ -107,6 +200,125 @@ pub(crate) mod register {
         kind: RegisterKind::General,
         address: CoreRegisterAddress(14),
     };
+
+    pub const FPSCR: RegisterDescription = RegisterDescription {
+        name: "FPSCR",
+        kind: RegisterKind::General,
+        address: CoreRegisterAddress(0b010_0001),
+    };
+
+    /// `CONTROL`, `FAULTMASK`, `BASEPRI` and `PRIMASK`, from the most to the least significant byte.
+    ///
+    /// ARMv6-M cores only have `CONTROL` and `PRIMASK`.
+    pub const SPECIAL: RegisterDescription = RegisterDescription {
+        name: "SPECIAL",
+        kind: RegisterKind::General,
+        address: CoreRegisterAddress(0b1_0100),
+    };
+
+    /// The register selector of the single precision register `S0`,
+    /// the other `S` registers follow consecutively.
+    pub const S0_ADDRESS: u16 = 0b100_0000;
+}
+
+/// A floating point register of a Cortex-M core with an FPU.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FpRegister {
+    /// One of the single precision registers `S0` to `S31`.
+    S(u8),
+    /// One of the double precision registers `D0` to `D15`.
+    ///
+    /// `Dn` is made up of `S2n`, which holds the lower word, and `S2n+1`.
+    D(u8),
+    /// The Floating-point Status and Control Register.
+    Fpscr,
+}
+
+impl FpRegister {
+    /// The registers which have to be accessed through the DCRSR, lowest word first.
+    fn addresses(self) -> Result<Vec<CoreRegisterAddress>, Error> {
+        match self {
+            FpRegister::S(index) if index < 32 => Ok(vec![single_register(index)]),
+            FpRegister::D(index) if index < 16 => Ok(vec![
+                single_register(2 * index),
+                single_register(2 * index + 1),
+            ]),
+            FpRegister::Fpscr => Ok(vec![register::FPSCR.address]),
+            _ => Err(Error::Other(anyhow::anyhow!(
+                "{:?} is not a valid floating point register",
+                self
+            ))),
+        }
+    }
+}
+
+fn single_register(index: u8) -> CoreRegisterAddress {
+    CoreRegisterAddress(register::S0_ADDRESS + u16::from(index))
+}
+
+/// Coprocessor Access Control Register
+const CPACR: u64 = 0xE000_ED88;
+/// Media and VFP Feature Register 0
+const MVFR0: u64 = 0xE000_EF40;
+
+/// Checks if the core has a floating point unit.
+///
+/// The single precision field of `MVFR0` is checked first. On some parts the feature
+/// registers read as zero, so an enabled coprocessor 10 in `CPACR` is accepted as well.
+pub(crate) fn fpu_present(core: &mut impl MemoryInterface) -> Result<bool, Error> {
+    let mvfr0 = core.read_word_32(MVFR0)?;
+    if (mvfr0 >> 4) & 0xf != 0 {
+        return Ok(true);
+    }
+
+    let cpacr = core.read_word_32(CPACR)?;
+    Ok((cpacr >> 20) & 0b11 != 0)
+}
+
+/// Reads a floating point register, `D` registers are composed of two `S` registers.
+pub(crate) fn read_fp_reg(core: &mut Core, register: FpRegister) -> Result<u64, Error> {
+    let addresses = register.addresses()?;
+
+    if !fpu_present(core)? {
+        return Err(Error::NotSupported("floating point registers"));
+    }
+
+    let mut value = 0;
+    for (i, address) in addresses.into_iter().enumerate() {
+        value |= u64::from(core.read_core_reg(address)?) << (32 * i);
+    }
+
+    Ok(value)
+}
+
+/// Writes a floating point register, `S` registers and the `FPSCR` only use the lower word of `value`.
+pub(crate) fn write_fp_reg(core: &mut Core, register: FpRegister, value: u64) -> Result<(), Error> {
+    let addresses = register.addresses()?;
+
+    if !fpu_present(core)? {
+        return Err(Error::NotSupported("floating point registers"));
+    }
+
+    for (i, address) in addresses.into_iter().enumerate() {
+        core.write_core_reg(address, (value >> (32 * i)) as u32)?;
+    }
+
+    Ok(())
+}
+
+/// The registers saved by `Core::registers_snapshot` in addition to the platform registers.
+///
+/// The floating point registers are only saved if the core has an FPU, as accessing them
+/// faults on cores without one.
+pub(crate) fn snapshot_registers(core: &mut Core) -> Result<Vec<CoreRegisterAddress>, Error> {
+    let mut addresses = vec![register::XPSR.address];
+
+    if fpu_present(core)? {
+        addresses.extend((0..32).map(single_register));
+        addresses.push(register::FPSCR.address);
+    }
+
+    Ok(addresses)
 }
 
 static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
@@ -234,6 +446,19 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
     ],
 };
 
+/// Translates a DWARF register number into the core register it refers to.
+///
+/// See the [DWARF for the ARM Architecture] document for the numbering. Only the
+/// core registers R0 to R15 are accessible through the debug interface.
+///
+/// [DWARF for the ARM Architecture]: https://developer.arm.com/documentation/ihi0040/latest/
+pub(crate) fn dwarf_register(dwarf_num: u16) -> Option<CoreRegisterAddress> {
+    match dwarf_num {
+        0..=15 => Some(CoreRegisterAddress(dwarf_num)),
+        _ => None,
+    }
+}
+
 bitfield! {
     #[derive(Copy, Clone)]
     pub struct Dfsr(u32);
@@ -288,7 +513,7 @@ impl From<Dfsr> for u32 {
 }
 
 impl CoreRegister for Dfsr {
-    const ADDRESS: u32 = 0xE000_ED30;
+    const ADDRESS: u64 = 0xE000_ED30;
     const NAME: &'static str = "DFSR";
 }
 
@@ -318,3 +543,159 @@ impl CortexState {
         self.initialized
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::architecture::arm::core::m4::{Demcr, Dhcsr};
+    use crate::DebugProbeError;
+    use std::collections::HashMap;
+
+    /// Memory mapped debug registers, which only support word accesses.
+    #[derive(Default)]
+    struct DebugRegisters(HashMap<u64, u32>);
+
+    impl MemoryInterface for DebugRegisters {
+        fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
+            Ok(self.0.get(&address).copied().unwrap_or(0))
+        }
+
+        fn read_word_8(&mut self, _address: u64) -> Result<u8, Error> {
+            unimplemented!()
+        }
+
+        fn read_32(&mut self, _address: u64, _data: &mut [u32]) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn read_8(&mut self, _address: u64, _data: &mut [u8]) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), Error> {
+            self.0.insert(address, data);
+            Ok(())
+        }
+
+        fn write_word_8(&mut self, _address: u64, _data: u8) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn write_32(&mut self, _address: u64, _data: &[u32]) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn write_8(&mut self, _address: u64, _data: &[u8]) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reset_catch_is_cleared_after_halt() {
+        let mut registers = DebugRegisters::default();
+
+        reset_catch_set(&mut registers).unwrap();
+        assert!(Demcr(registers.0[&Demcr::ADDRESS]).vc_corereset());
+
+        reset_catch_finish(&mut registers, Ok(())).unwrap();
+        assert!(!Demcr(registers.0[&Demcr::ADDRESS]).vc_corereset());
+    }
+
+    #[test]
+    fn debug_registers_are_restored_after_timeout() {
+        let mut registers = DebugRegisters::default();
+
+        reset_catch_set(&mut registers).unwrap();
+        // The reset cleared C_DEBUGEN.
+        registers.0.insert(Dhcsr::ADDRESS, 0);
+
+        let result = reset_catch_finish(&mut registers, Err(DebugProbeError::Timeout.into()));
+
+        assert!(matches!(
+            result,
+            Err(Error::Probe(DebugProbeError::Timeout))
+        ));
+        assert!(!Demcr(registers.0[&Demcr::ADDRESS]).vc_corereset());
+        assert!(Dhcsr(registers.0[&Dhcsr::ADDRESS]).c_debugen());
+    }
+
+    #[test]
+    fn vector_catch_keeps_other_bits() {
+        let mut registers = DebugRegisters::default();
+        // TRCENA and VC_INTERR are not touched.
+        registers
+            .0
+            .insert(Demcr::ADDRESS, 1 << 24 | 1 << 9 | 1 << 8);
+
+        let flags = VectorCatchFlags::HARDERR | VectorCatchFlags::MMERR;
+        set_vector_catch(&mut registers, flags, VectorCatchFlags::all()).unwrap();
+        assert_eq!(vector_catch(&mut registers).unwrap(), flags);
+
+        // Clearing all flags disables the vector catch completely.
+        set_vector_catch(
+            &mut registers,
+            VectorCatchFlags::empty(),
+            VectorCatchFlags::all(),
+        )
+        .unwrap();
+        assert_eq!(registers.0[&Demcr::ADDRESS], 1 << 24 | 1 << 9);
+        assert!(vector_catch(&mut registers).unwrap().is_empty());
+    }
+
+    #[test]
+    fn unsupported_vector_catch_is_rejected() {
+        let mut registers = DebugRegisters::default();
+        let armv6m = VectorCatchFlags::HARDERR | VectorCatchFlags::CORERESET;
+
+        let result = set_vector_catch(&mut registers, VectorCatchFlags::BUSERR, armv6m);
+
+        assert!(matches!(result, Err(Error::NotSupported(_))));
+        assert!(registers.0.is_empty());
+    }
+
+    #[test]
+    fn reset_is_detected() {
+        let mut registers = DebugRegisters::default();
+
+        // Debugging is disabled after a power loss.
+        assert!(core_was_reset(&mut registers).unwrap());
+
+        debug_core_start(&mut registers).unwrap();
+        assert!(!core_was_reset(&mut registers).unwrap());
+
+        // S_RESET_ST is set after a reset.
+        let dhcsr = registers.0[&Dhcsr::ADDRESS] | 1 << 25;
+        registers.0.insert(Dhcsr::ADDRESS, dhcsr);
+        assert!(core_was_reset(&mut registers).unwrap());
+    }
+
+    #[test]
+    fn d_register_is_made_of_two_s_registers() {
+        let addresses = FpRegister::D(1).addresses().unwrap();
+
+        assert_eq!(
+            addresses,
+            vec![
+                CoreRegisterAddress(0b100_0010),
+                CoreRegisterAddress(0b100_0011)
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_fp_registers() {
+        assert!(FpRegister::S(32).addresses().is_err());
+        assert!(FpRegister::D(16).addresses().is_err());
+    }
+
+    #[test]
+    fn dwarf_register_13_is_sp() {
+        assert_eq!(dwarf_register(13), Some(register::SP.address));
+        assert_eq!(dwarf_register(15), Some(register::PC.address));
+        assert_eq!(dwarf_register(16), None);
+    }
+}
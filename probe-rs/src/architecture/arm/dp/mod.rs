@@ -5,7 +5,7 @@ use super::Register;
 use bitfield::bitfield;
 use jep106::JEP106Code;
 
-use crate::DebugProbeError;
+use crate::{DebugProbeError, WireProtocol};
 use std::fmt::Display;
 use thiserror::Error;
 
@@ -26,6 +26,137 @@ impl From<DebugPortError> for DebugProbeError {
     }
 }
 
+/// The address of a debug port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DpAddress {
+    /// The only debug port on a point-to-point SWD or JTAG connection.
+    #[default]
+    Default,
+    /// A debug port on a SWD multi-drop bus, selected by writing the contained value to `TARGETSEL`.
+    ///
+    /// The value consists of the `TINSTANCE` field in bits 31..28 and the `TDESIGNER` and `TPARTNO`
+    /// fields of the `TARGETID` register.
+    Multidrop(u32),
+}
+
+/// The `TARGETSEL` write request, which is not acknowledged by the target.
+const TARGETSEL_REQUEST: u64 = 0x99;
+
+/// At least 50 cycles with SWDIO high, which reset the SWD interface.
+const LINE_RESET: (u8, u64) = (56, (1 << 56) - 1);
+
+/// 8 cycles with TMS high, which reset the JTAG TAP, and a cycle low to move it to Run-Test/Idle.
+const TAP_RESET: (u8, u64) = (9, 0xFF);
+
+/// The 128 bit selection alert sequence, which wakes a SWJ-DPv2 from the dormant state,
+/// see section B5.3.4 of the ADIv5.2 spec.
+const SELECTION_ALERT: [(u8, u64); 2] = [(64, 0x8685_2D95_6209_F392), (64, 0x19BC_0EA2_E3DD_AFE9)];
+
+/// A sequence on SWDIO/TMS which switches a SWJ-DP to another wire protocol.
+///
+/// See section B5.2 of the ADIv5.2 spec. Set it with `Probe::set_swj_sequence`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwjSequence {
+    /// Switches from JTAG to SWD with the `0xE79E` sequence.
+    JtagToSwd,
+    /// Switches from SWD to JTAG with the `0xE73C` sequence.
+    SwdToJtag,
+    /// Wakes a SWJ-DPv2 from the dormant state into SWD, with the selection alert
+    /// and the SWD activation code.
+    ///
+    /// The debug port is sent into the dormant state first, so this works from JTAG and SWD as well.
+    DormantToSwd,
+    /// Wakes a SWJ-DPv2 from the dormant state into JTAG, with the selection alert
+    /// and the JTAG activation code.
+    ///
+    /// The debug port is sent into the dormant state first, so this works from JTAG and SWD as well.
+    DormantToJtag,
+}
+
+impl SwjSequence {
+    /// Returns the legacy switching sequence to `protocol`, for SWJ-DPs which are not in the dormant state.
+    pub fn for_protocol(protocol: WireProtocol) -> Self {
+        match protocol {
+            WireProtocol::Swd => SwjSequence::JtagToSwd,
+            WireProtocol::Jtag => SwjSequence::SwdToJtag,
+        }
+    }
+
+    /// Returns the sequence as `(bit_len, bits)` pairs sent with the least significant bit first.
+    pub(crate) fn bits(self) -> Vec<(u8, u64)> {
+        let mut sequence = match self {
+            SwjSequence::JtagToSwd => return vec![LINE_RESET, (16, 0xE79E), LINE_RESET, (2, 0)],
+            // At least 5 cycles with TMS high afterwards reset the TAP.
+            SwjSequence::SwdToJtag => return vec![LINE_RESET, (16, 0xE73C), TAP_RESET],
+            SwjSequence::DormantToSwd | SwjSequence::DormantToJtag => vec![
+                // JTAG to dormant state.
+                (8, 0xFF),
+                (31, 0x33BB_BBBA),
+                // SWD to dormant state, which is ignored if the debug port is dormant already.
+                LINE_RESET,
+                (16, 0xE3BC),
+                // Leave the dormant state, with at least 8 cycles high and the selection alert.
+                (8, 0xFF),
+            ],
+        };
+        sequence.extend_from_slice(&SELECTION_ALERT);
+
+        match self {
+            // 4 cycles low, the SWD activation code, and a line reset followed by two idle cycles.
+            SwjSequence::DormantToSwd => {
+                sequence.extend_from_slice(&[(12, 0x1A << 4), LINE_RESET, (2, 0)])
+            }
+            // 4 cycles low and the JTAG activation code, followed by a TAP reset.
+            _ => sequence.extend_from_slice(&[(16, 0), TAP_RESET]),
+        }
+
+        sequence
+    }
+}
+
+impl std::str::FromStr for SwjSequence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_ascii_lowercase()[..] {
+            "jtag-to-swd" => Ok(SwjSequence::JtagToSwd),
+            "swd-to-jtag" => Ok(SwjSequence::SwdToJtag),
+            "dormant-to-swd" => Ok(SwjSequence::DormantToSwd),
+            "dormant-to-jtag" => Ok(SwjSequence::DormantToJtag),
+            _ => Err(format!(
+                "'{}' is not a valid SWJ sequence. Choose from [jtag-to-swd, swd-to-jtag, dormant-to-swd, dormant-to-jtag].",
+                s
+            )),
+        }
+    }
+}
+
+/// Returns the sequence which wakes up all SWDv2 debug ports and selects the one with the `targetsel` ID,
+/// as `(bit_len, bits)` pairs sent with the least significant bit first.
+///
+/// See section B4.3.4 of the ADIv5.2 spec.
+pub(crate) fn multidrop_selection_sequence(targetsel: u32) -> Vec<(u8, u64)> {
+    let parity = u64::from(targetsel.count_ones() % 2);
+
+    vec![
+        // Leave dormant state: at least 8 cycles high, the selection alert sequence,
+        // 4 cycles low and the SWD activation code.
+        (8, 0xFF),
+        SELECTION_ALERT[0],
+        SELECTION_ALERT[1],
+        (12, 0x1A << 4),
+        // Line reset, followed by two idle cycles.
+        LINE_RESET,
+        (2, 0),
+        // The TARGETSEL write. The target does not drive the line during
+        // the turnaround and ACK phases, so five cycles are skipped before the data.
+        (8, TARGETSEL_REQUEST),
+        (5, 0),
+        (33, u64::from(targetsel) | parity << 32),
+        (2, 0),
+    ]
+}
+
 pub trait DPAccess {
     fn read_dp_register<R: DPRegister>(&mut self) -> Result<R, DebugPortError>;
 
@@ -344,3 +475,60 @@ impl From<u8> for DebugPortVersion {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn multidrop_sequence_selects_target() {
+        let sequence = multidrop_selection_sequence(0x0100_2927);
+
+        let (bit_len, bits) = sequence[sequence.len() - 2];
+        assert_eq!(bit_len, 33);
+        assert_eq!(bits as u32, 0x0100_2927);
+        // 0x01002927 has an even number of ones.
+        assert_eq!(bits >> 32, 0);
+
+        assert!(sequence.iter().all(|(bit_len, _)| *bit_len <= 64));
+        assert!(sequence
+            .iter()
+            .all(|(bit_len, bits)| *bit_len == 64 || bits >> bit_len == 0));
+    }
+
+    #[test]
+    fn swj_sequences() {
+        assert_eq!(
+            SwjSequence::for_protocol(WireProtocol::Swd).bits()[1],
+            (16, 0xE79E)
+        );
+        assert_eq!(
+            SwjSequence::for_protocol(WireProtocol::Jtag).bits()[1],
+            (16, 0xE73C)
+        );
+
+        // Leaving the dormant state has to send the selection alert, and not the legacy switch.
+        for sequence in &[SwjSequence::DormantToSwd, SwjSequence::DormantToJtag] {
+            let bits = sequence.bits();
+            assert!(bits.windows(2).any(|pair| pair == SELECTION_ALERT));
+            assert!(!bits.contains(&(16, 0xE79E)));
+            assert!(bits
+                .iter()
+                .all(|(bit_len, bits)| *bit_len == 64 || bits >> bit_len == 0));
+        }
+
+        assert_eq!(
+            "Dormant-To-SWD".parse::<SwjSequence>(),
+            Ok(SwjSequence::DormantToSwd)
+        );
+        assert!("swd".parse::<SwjSequence>().is_err());
+    }
+
+    #[test]
+    fn multidrop_sequence_parity_odd() {
+        let sequence = multidrop_selection_sequence(0x1100_2927);
+
+        let (_, bits) = sequence[sequence.len() - 2];
+        assert_eq!(bits >> 32, 1);
+    }
+}
@@ -0,0 +1,286 @@
+//! Target assisted memory reads.
+//!
+//! A small helper routine is loaded into the target RAM, which copies the memory to be read
+//! into a buffer right behind it. The buffer is then read with large block transfers,
+//! which is a lot faster than reading slow memories, like external flash, through the AP.
+
+use crate::{Core, CoreRegisterAddress, CoreStatus, Error, MemoryInterface};
+use std::{ops::Range, time::Duration};
+
+/// Copies `r2` bytes from `r0` to `r1`, and halts at a breakpoint afterwards.
+///
+/// ```text
+/// loop: cmp   r2, #0
+///       beq   done
+///       ldrb  r3, [r0]
+///       strb  r3, [r1]
+///       adds  r0, #1
+///       adds  r1, #1
+///       subs  r2, #1
+///       b     loop
+/// done: bkpt  #0
+/// ```
+const HELPER: [u16; 10] = [
+    0x2a00, 0xd005, 0x7803, 0x700b, 0x3001, 0x3101, 0x3a01, 0xe7f7, 0xbe00, 0xbf00,
+];
+
+/// The offset of the breakpoint in `HELPER`, where the core halts once the copy is done.
+const HELPER_DONE_OFFSET: u32 = 16;
+
+/// The size of `HELPER` in bytes.
+const HELPER_SIZE: u32 = HELPER.len() as u32 * 2;
+
+/// The buffer has to be at least this large, otherwise plain reads are faster.
+const MIN_BUFFER_SIZE: u32 = 256;
+
+/// The largest buffer used, so a single run of the helper finishes quickly.
+const MAX_BUFFER_SIZE: u32 = 16 * 1024;
+
+/// The time a single run of the helper may take.
+const HELPER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The `DCRSR` register selector of `XPSR`.
+const XPSR: CoreRegisterAddress = CoreRegisterAddress(0b1_0000);
+const XPSR_THUMB: u32 = 1 << 24;
+
+/// The placement of the helper and its buffer in the target RAM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Layout {
+    helper: u32,
+    buffer: Range<u32>,
+}
+
+impl Layout {
+    /// Places the helper at the start of `ram`, to read `len` bytes at `address`.
+    ///
+    /// Returns `None` if `ram` is too small, or the memory to be read overlaps the used RAM.
+    fn new(ram: &Range<u32>, address: u32, len: usize) -> Option<Self> {
+        let helper = (ram.start + 3) & !3;
+        let buffer_start = helper + HELPER_SIZE;
+        let available = ram.end.checked_sub(buffer_start)? & !3;
+
+        if available < MIN_BUFFER_SIZE {
+            return None;
+        }
+
+        let used = helper..buffer_start + available.min(MAX_BUFFER_SIZE);
+        let read_end = address.checked_add(len as u32)?;
+        if address < used.end && used.start < read_end {
+            return None;
+        }
+
+        Some(Self {
+            helper,
+            buffer: buffer_start..used.end,
+        })
+    }
+
+    fn used(&self) -> Range<u32> {
+        self.helper..self.buffer.end
+    }
+}
+
+/// Reads `data.len()` bytes at `address` with the help of a routine loaded into `ram`.
+///
+/// The RAM and the registers used by the routine are restored afterwards.
+/// If the routine cannot be used, e.g. because the core is running or `ram` is too small,
+/// the memory is read directly instead.
+pub(crate) fn read_8(
+    core: &mut Core<'_>,
+    ram: &Range<u32>,
+    address: u32,
+    data: &mut [u8],
+) -> Result<(), Error> {
+    // Running the helper would interfere with a running program.
+    let halted = matches!(core.status()?, CoreStatus::Halted(_));
+
+    let layout = match Layout::new(ram, address, data.len()) {
+        Some(layout) if halted => layout,
+        _ => {
+            log::debug!("Fast read is not possible, reading the memory directly.");
+            return core.read(address.into(), data);
+        }
+    };
+
+    let used = layout.used();
+    let mut saved_ram = vec![0; (used.end - used.start) as usize / 4];
+    core.read_32(used.start.into(), &mut saved_ram)?;
+    let saved_registers = save_registers(core)?;
+
+    let result = read_with_helper(core, &layout, address, data);
+
+    // Always restore the target state, even if the helper failed.
+    let restored = core
+        .write_32(used.start.into(), &saved_ram)
+        .and_then(|_| restore_registers(core, &saved_registers));
+
+    match result {
+        Ok(true) => restored,
+        Ok(false) => {
+            restored?;
+            log::warn!("The fast read helper could not be loaded, reading the memory directly.");
+            core.read(address.into(), data)
+        }
+        Err(e) => {
+            if let Err(restore_error) = restored {
+                log::warn!(
+                    "Failed to restore the RAM after a fast read: {}",
+                    restore_error
+                );
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Loads the helper and reads `data` with it.
+///
+/// Returns `false` if the helper could not be loaded correctly, in which case nothing has been read.
+fn read_with_helper(
+    core: &mut Core<'_>,
+    layout: &Layout,
+    address: u32,
+    data: &mut [u8],
+) -> Result<bool, Error> {
+    let code: Vec<u32> = HELPER
+        .chunks(2)
+        .map(|c| u32::from(c[0]) | u32::from(c[1]) << 16)
+        .collect();
+    core.write_32(layout.helper.into(), &code)?;
+
+    // Don't trust a helper which was not written correctly, e.g. because the RAM is not writable.
+    let mut read_back = vec![0; code.len()];
+    core.read_32(layout.helper.into(), &mut read_back)?;
+    if read_back != code {
+        return Ok(false);
+    }
+
+    let registers = core.registers();
+    let (pc, r0, r1, r2) = (
+        registers.program_counter().address,
+        registers.argument_register(0).address,
+        registers.argument_register(1).address,
+        registers.argument_register(2).address,
+    );
+
+    let buffer_size = (layout.buffer.end - layout.buffer.start) as usize;
+    let mut source = address;
+
+    for chunk in data.chunks_mut(buffer_size) {
+        core.write_core_reg(pc, layout.helper)?;
+        core.write_core_reg(r0, source)?;
+        core.write_core_reg(r1, layout.buffer.start)?;
+        core.write_core_reg(r2, chunk.len() as u32)?;
+
+        let xpsr = core.read_core_reg(XPSR)?;
+        core.write_core_reg(XPSR, xpsr | XPSR_THUMB)?;
+
+        core.run()?;
+        core.wait_for_core_halted(HELPER_TIMEOUT)?;
+
+        // The helper has to stop at its breakpoint, with all bytes copied.
+        let done = core.read_core_reg(pc)? == layout.helper + HELPER_DONE_OFFSET
+            && core.read_core_reg(r2)? == 0;
+        if !done {
+            return Err(anyhow::anyhow!(
+                "The fast read helper did not finish copying the memory at {:#010x}.",
+                source
+            )
+            .into());
+        }
+
+        // The buffer is word aligned, so it can be read in words, even for odd lengths.
+        let aligned_len = (chunk.len() + 3) & !3;
+        let mut words = vec![0; aligned_len / 4];
+        core.read_32(layout.buffer.start.into(), &mut words)?;
+        let bytes = words.iter().flat_map(|w| w.to_le_bytes());
+        for (target, byte) in chunk.iter_mut().zip(bytes) {
+            *target = byte;
+        }
+
+        source += chunk.len() as u32;
+    }
+
+    Ok(true)
+}
+
+fn saved_register_addresses(core: &Core<'_>) -> Vec<CoreRegisterAddress> {
+    let registers = core.registers();
+    let mut addresses: Vec<_> = (0..4)
+        .map(|i| registers.argument_register(i).address)
+        .collect();
+    addresses.push(registers.program_counter().address);
+    addresses.push(XPSR);
+    addresses
+}
+
+fn save_registers(core: &mut Core<'_>) -> Result<Vec<(CoreRegisterAddress, u32)>, Error> {
+    saved_register_addresses(core)
+        .into_iter()
+        .map(|address| Ok((address, core.read_core_reg(address)?)))
+        .collect()
+}
+
+fn restore_registers(
+    core: &mut Core<'_>,
+    registers: &[(CoreRegisterAddress, u32)],
+) -> Result<(), Error> {
+    for (address, value) in registers {
+        core.write_core_reg(*address, *value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn helper_follows_ram_start() {
+        let layout = Layout::new(&(0x2000_0002..0x2000_1000), 0x9000_0000, 0x10_0000).unwrap();
+
+        assert_eq!(
+            layout,
+            Layout {
+                helper: 0x2000_0004,
+                buffer: 0x2000_0018..0x2000_1000,
+            }
+        );
+    }
+
+    #[test]
+    fn buffer_size_is_limited() {
+        let layout = Layout::new(&(0x2000_0000..0x2010_0000), 0x9000_0000, 0x10).unwrap();
+
+        assert_eq!(layout.buffer.end - layout.buffer.start, MAX_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn small_ram_is_not_used() {
+        assert_eq!(
+            Layout::new(&(0x2000_0000..0x2000_0100), 0x9000_0000, 0x10),
+            None
+        );
+    }
+
+    #[test]
+    fn read_overlapping_the_buffer_is_not_possible() {
+        assert_eq!(
+            Layout::new(&(0x2000_0000..0x2000_1000), 0x2000_0800, 0x10),
+            None
+        );
+        assert!(Layout::new(&(0x2000_0000..0x2001_0000), 0x2000_8000, 0x10).is_some());
+    }
+
+    #[test]
+    fn helper_branches() {
+        // beq done: the target is the instruction address + 4 + 2 * imm8.
+        assert_eq!(2 + 4 + 2 * u32::from(HELPER[1] & 0xff), HELPER_DONE_OFFSET);
+        assert_eq!(HELPER[HELPER_DONE_OFFSET as usize / 2], 0xbe00);
+
+        // b loop: the sign extended imm11 has to jump back to the start.
+        let imm11 = i32::from(HELPER[7] & 0x7ff);
+        let offset = (imm11 << 21) >> 20;
+        assert_eq!(14 + 4 + offset, 0);
+    }
+}
@@ -1,14 +1,14 @@
 use super::super::ap::{
     APAccess, APRegister, AccessPortError, AddressIncrement, DataSize, MemoryAP, CSW, DRW, TAR,
+    TAR2,
 };
-use crate::architecture::arm::{dp::DPAccess, ArmCommunicationInterface};
-use crate::{CommunicationInterface, CoreRegister, CoreRegisterAddress, DebugProbeError, Error};
-use scroll::{Pread, Pwrite, LE};
-use std::convert::TryInto;
-use std::{
-    ops::Range,
-    time::{Duration, Instant},
+use super::{transfer_blocks, MemoryAccessAttributes};
+use crate::architecture::arm::{
+    dp::{DPAccess, DPIDR},
+    ArmCommunicationInterface,
 };
+use crate::{CommunicationInterface, CoreRegister, CoreRegisterAddress, DebugProbeError, Error};
+use std::time::{Duration, Instant};
 
 use bitfield::bitfield;
 
@@ -21,13 +21,21 @@ pub trait ArmProbe {
         value: u32,
     ) -> Result<(), Error>;
 
-    fn read_8(&mut self, ap: MemoryAP, address: u32, data: &mut [u8]) -> Result<(), Error>;
-    fn read_32(&mut self, ap: MemoryAP, address: u32, data: &mut [u32]) -> Result<(), Error>;
+    fn read_8(&mut self, ap: MemoryAP, address: u64, data: &mut [u8]) -> Result<(), Error>;
+    fn read_32(&mut self, ap: MemoryAP, address: u64, data: &mut [u32]) -> Result<(), Error>;
 
-    fn write_8(&mut self, ap: MemoryAP, address: u32, data: &[u8]) -> Result<(), Error>;
-    fn write_32(&mut self, ap: MemoryAP, address: u32, data: &[u32]) -> Result<(), Error>;
+    fn write_8(&mut self, ap: MemoryAP, address: u64, data: &[u8]) -> Result<(), Error>;
+    fn write_32(&mut self, ap: MemoryAP, address: u64, data: &[u32]) -> Result<(), Error>;
 
     fn flush(&mut self) -> Result<(), Error>;
+
+    /// Causes some harmless traffic on the debug interface, without accessing an AP.
+    ///
+    /// This keeps probes and USB stacks from resetting the connection while the target runs
+    /// for a long time without being accessed.
+    fn keep_alive(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 /// A struct to give access to a targets memory using a certain DAP.
@@ -36,11 +44,16 @@ where
     AP: CommunicationInterface
         + APAccess<MemoryAP, CSW>
         + APAccess<MemoryAP, TAR>
+        + APAccess<MemoryAP, TAR2>
         + APAccess<MemoryAP, DRW>
         + DPAccess,
 {
     interface: &'interface mut AP,
     only_32bit_data_size: bool,
+    supports_large_address: bool,
+    /// The maximum number of bytes the probe transfers with one command, see `DebugProbe::max_block_size`.
+    max_block_size: usize,
+    access_attributes: MemoryAccessAttributes,
 }
 
 impl<'interface> ADIMemoryInterface<'interface, ArmCommunicationInterface> {
@@ -48,10 +61,16 @@ impl<'interface> ADIMemoryInterface<'interface, ArmCommunicationInterface> {
     pub fn new(
         interface: &'interface mut ArmCommunicationInterface,
         only_32bit_data_size: bool,
+        supports_large_address: bool,
+        max_block_size: usize,
+        access_attributes: MemoryAccessAttributes,
     ) -> Result<ADIMemoryInterface<'interface, ArmCommunicationInterface>, AccessPortError> {
         Ok(Self {
             interface,
             only_32bit_data_size,
+            supports_large_address,
+            max_block_size,
+            access_attributes,
         })
     }
 }
@@ -61,30 +80,31 @@ where
     AP: CommunicationInterface
         + APAccess<MemoryAP, CSW>
         + APAccess<MemoryAP, TAR>
+        + APAccess<MemoryAP, TAR2>
         + APAccess<MemoryAP, DRW>
         + DPAccess,
 {
     /// Build the correct CSW register for a memory access
     ///
     /// Currently, only AMBA AHB Access is supported.
-    pub fn build_csw_register(data_size: DataSize) -> CSW {
+    pub fn build_csw_register(data_size: DataSize, attributes: MemoryAccessAttributes) -> CSW {
         // The CSW Register is set for an AMBA AHB Acccess, according to
         // the ARM Debug Interface Architecture Specification.
         //
         // The PROT bits are set as follows:
-        //  BIT[30]              = 1  - Should be One, otherwise unpredictable
+        //  HNONSEC, bit[30]     = 1  - Non-secure access, should be one without TrustZone
         //  MasterType, bit [29] = 1  - Access as default AHB Master
         //  HPROT[4]             = 0  - Non-allocating access
         //
-        // The CACHE bits are set for the following AHB access:
+        // The CACHE bits are set for the following AHB access by default:
         //   HPROT[0] == 1   - data           access
         //   HPROT[1] == 1   - privileged     access
-        //   HPROT[2] == 0   - non-cacheable  access
-        //   HPROT[3] == 0   - non-bufferable access
+        //   HPROT[2] == 0   - non-bufferable access
+        //   HPROT[3] == 0   - non-cacheable  access
 
         CSW {
-            PROT: 0b110,
-            CACHE: 0b11,
+            PROT: attributes.prot(),
+            CACHE: attributes.cache(),
             AddrInc: AddressIncrement::Single,
             SIZE: data_size,
             ..Default::default()
@@ -174,6 +194,25 @@ where
             .map_err(AccessPortError::register_write_error::<R, _>)
     }
 
+    /// Writes `address` to the TAR register, and its upper word to TAR2 if the AP supports large addresses.
+    ///
+    /// Returns `AccessPortError::AddressTooLarge` for addresses above 4GB on all other APs.
+    fn write_tar(&mut self, access_port: MemoryAP, address: u64) -> Result<(), AccessPortError> {
+        let upper = (address >> 32) as u32;
+        if self.supports_large_address {
+            self.write_ap_register(access_port, TAR2 { address: upper })?;
+        } else if upper != 0 {
+            return Err(AccessPortError::AddressTooLarge(address));
+        }
+
+        self.write_ap_register(
+            access_port,
+            TAR {
+                address: address as u32,
+            },
+        )
+    }
+
     /// Read a 32bit word at `addr`.
     ///
     /// The address where the read should be performed at has to be word aligned.
@@ -181,49 +220,33 @@ where
     pub fn read_word_32(
         &mut self,
         access_port: MemoryAP,
-        address: u32,
+        address: u64,
     ) -> Result<u32, AccessPortError> {
         if (address % 4) != 0 {
             return Err(AccessPortError::alignment_error(address, 4));
         }
 
-        let csw = Self::build_csw_register(DataSize::U32);
+        let csw = Self::build_csw_register(DataSize::U32, self.access_attributes);
 
-        let tar = TAR { address };
         self.write_ap_register(access_port, csw)?;
-        self.write_ap_register(access_port, tar)?;
+        self.write_tar(access_port, address)?;
         let result = self.read_ap_register(access_port, DRW::default())?;
 
         Ok(result.data)
     }
 
-    /// Read an 8bit word at `addr`.
+    /// Read an 8bit word at `addr`, with a single 8 bit access.
+    ///
+    /// Returns `AccessPortError::UnsupportedDataSize` if the AP only supports 32 bit accesses.
     pub fn read_word_8(
         &mut self,
         access_port: MemoryAP,
-        address: u32,
+        address: u64,
     ) -> Result<u8, AccessPortError> {
-        let aligned = aligned_range(address, 1)?;
-
-        // Offset of byte in word (little endian)
-        let bit_offset = (address - aligned.start) * 8;
+        let mut data = [0];
+        self.read_8(access_port, address, &mut data)?;
 
-        let result = if self.only_32bit_data_size {
-            // Read 32-bit word and extract the correct byte
-            ((self.read_word_32(access_port, aligned.start)? >> bit_offset) & 0xFF) as u8
-        } else {
-            let csw = Self::build_csw_register(DataSize::U8);
-            let tar = TAR { address };
-            self.write_ap_register(access_port, csw)?;
-            self.write_ap_register(access_port, tar)?;
-            let result = self.read_ap_register(access_port, DRW::default())?;
-
-            // Extract the correct byte
-            // See "Arm Debug Interface Architecture Specification ADIv5.0 to ADIv5.2", C2.2.6
-            ((result.data >> bit_offset) & 0xFF) as u8
-        };
-
-        Ok(result)
+        Ok(data[0])
     }
 
     /// Read a block of words of the size defined by S at `addr`.
@@ -234,7 +257,7 @@ where
     pub fn read_32(
         &mut self,
         access_port: MemoryAP,
-        start_address: u32,
+        start_address: u64,
         data: &mut [u32],
     ) -> Result<(), AccessPortError> {
         if data.is_empty() {
@@ -245,72 +268,19 @@ where
             return Err(AccessPortError::alignment_error(start_address, 4));
         }
 
-        // Second we read in 32 bit reads until we have less than 32 bits left to read.
-        let csw = Self::build_csw_register(DataSize::U32);
+        let csw = Self::build_csw_register(DataSize::U32, self.access_attributes);
         self.write_ap_register(access_port, csw)?;
 
-        let mut address = start_address;
-        let tar = TAR { address };
-        self.write_ap_register(access_port, tar)?;
-
-        // figure out how many words we can write before the
-        // data overflows
-
-        // maximum chunk size
-        let max_chunk_size_bytes = 0x400;
-
-        let mut remaining_data_len = data.len();
-
-        let first_chunk_size_bytes = std::cmp::min(
-            max_chunk_size_bytes - (address as usize % max_chunk_size_bytes),
-            data.len() * 4,
-        );
-
-        let mut data_offset = 0;
-
-        log::debug!(
-            "Read first block with len {} at address {:#08x}",
-            first_chunk_size_bytes,
-            address
-        );
-
-        let first_chunk_size_words = first_chunk_size_bytes / 4;
-
-        self.read_ap_register_repeated(
-            access_port,
-            DRW { data: 0 },
-            &mut data[data_offset..first_chunk_size_words],
-        )?;
-
-        remaining_data_len -= first_chunk_size_words;
-        address += (4 * first_chunk_size_words) as u32;
-        data_offset += first_chunk_size_words;
-
-        while remaining_data_len > 0 {
-            // the autoincrement is limited to the 10 lowest bits so we need to write the address
-            // every time it overflows
-            let tar = TAR { address };
-            self.write_ap_register(access_port, tar)?;
-
-            let next_chunk_size_bytes = std::cmp::min(max_chunk_size_bytes, remaining_data_len * 4);
-
+        for (address, values) in transfer_blocks(start_address, data.len(), 4, self.max_block_size)
+        {
             log::debug!(
-                "Reading chunk with len {} at address {:#08x}",
-                next_chunk_size_bytes,
+                "Reading block with len {} at address {:#08x}",
+                values.len() * 4,
                 address
             );
 
-            let next_chunk_size_words = next_chunk_size_bytes / 4;
-
-            self.read_ap_register_repeated(
-                access_port,
-                DRW { data: 0 },
-                &mut data[data_offset..(data_offset + next_chunk_size_words)],
-            )?;
-
-            remaining_data_len -= next_chunk_size_words;
-            address += (4 * next_chunk_size_words) as u32;
-            data_offset += next_chunk_size_words;
+            self.write_tar(access_port, address)?;
+            self.read_ap_register_repeated(access_port, DRW { data: 0 }, &mut data[values])?;
         }
 
         log::debug!("Finished reading block");
@@ -318,31 +288,42 @@ where
         Ok(())
     }
 
+    /// Read a block of 8bit words at `addr`, with one 8 bit access per byte.
+    ///
+    /// The accesses are never widened, so this can be used for registers where the access size
+    /// matters. Returns `AccessPortError::UnsupportedDataSize` if the AP only supports 32 bit accesses.
     pub fn read_8(
         &mut self,
         access_port: MemoryAP,
-        address: u32,
+        start_address: u64,
         data: &mut [u8],
     ) -> Result<(), AccessPortError> {
         if data.is_empty() {
             return Ok(());
         }
 
-        let aligned = aligned_range(address, data.len())?;
+        if self.only_32bit_data_size {
+            return Err(AccessPortError::UnsupportedDataSize(DataSize::U8));
+        }
 
-        // Read aligned block of 32-bit words
-        let mut buf32 = vec![0u32; aligned.len() / 4];
-        self.read_32(access_port, aligned.start, &mut buf32)?;
+        let csw = Self::build_csw_register(DataSize::U8, self.access_attributes);
+        self.write_ap_register(access_port, csw)?;
 
-        // Convert 32-bit words to bytes
-        let mut buf8 = vec![0u8; aligned.len()];
-        for (i, word) in buf32.into_iter().enumerate() {
-            buf8.pwrite_with(word, i * 4, LE).unwrap();
-        }
+        // Every byte takes a whole transfer of DRW.
+        let max_block_size = self.max_block_size / 4;
+        for (address, bytes) in transfer_blocks(start_address, data.len(), 1, max_block_size) {
+            let mut values = vec![0u32; bytes.len()];
+
+            self.write_tar(access_port, address)?;
+            self.read_ap_register_repeated(access_port, DRW { data: 0 }, &mut values)?;
 
-        // Copy relevant part of aligned block to output data
-        let start = (address - aligned.start) as usize;
-        data.copy_from_slice(&buf8[start..start + data.len()]);
+            // The byte is returned in the byte lane of its address,
+            // see "Arm Debug Interface Architecture Specification ADIv5.0 to ADIv5.2", C2.2.6
+            for (offset, (byte, value)) in data[bytes].iter_mut().zip(values).enumerate() {
+                let bit_offset = ((address + offset as u64) % 4) * 8;
+                *byte = (value >> bit_offset) as u8;
+            }
+        }
 
         Ok(())
     }
@@ -354,18 +335,17 @@ where
     pub fn write_word_32(
         &mut self,
         access_port: MemoryAP,
-        address: u32,
+        address: u64,
         data: u32,
     ) -> Result<(), AccessPortError> {
         if (address % 4) != 0 {
             return Err(AccessPortError::alignment_error(address, 4));
         }
 
-        let csw = Self::build_csw_register(DataSize::U32);
+        let csw = Self::build_csw_register(DataSize::U32, self.access_attributes);
         let drw = DRW { data };
-        let tar = TAR { address };
         self.write_ap_register(access_port, csw)?;
-        self.write_ap_register(access_port, tar)?;
+        self.write_tar(access_port, address)?;
         self.write_ap_register(access_port, drw)?;
 
         // Ensure the write is actually performed.
@@ -374,37 +354,16 @@ where
         Ok(())
     }
 
-    /// Write an 8bit word at `addr`.
+    /// Write an 8bit word at `addr`, with a single 8 bit access.
+    ///
+    /// Returns `AccessPortError::UnsupportedDataSize` if the AP only supports 32 bit accesses.
     pub fn write_word_8(
         &mut self,
         access_port: MemoryAP,
-        address: u32,
+        address: u64,
         data: u8,
     ) -> Result<(), AccessPortError> {
-        let aligned = aligned_range(address, 1)?;
-
-        // Offset of byte in word (little endian)
-        let bit_offset = (address - aligned.start) * 8;
-
-        if self.only_32bit_data_size {
-            // Read the existing 32-bit word and insert the byte at the correct bit offset
-            // See "Arm Debug Interface Architecture Specification ADIv5.0 to ADIv5.2", C2.2.6
-            let word = self.read_word_32(access_port, aligned.start)?;
-            let word = word & !(0xFF << bit_offset) | (u32::from(data) << bit_offset);
-
-            self.write_word_32(access_port, aligned.start, word)?;
-        } else {
-            let csw = Self::build_csw_register(DataSize::U8);
-            let drw = DRW {
-                data: u32::from(data) << bit_offset,
-            };
-            let tar = TAR { address };
-            self.write_ap_register(access_port, csw)?;
-            self.write_ap_register(access_port, tar)?;
-            self.write_ap_register(access_port, drw)?;
-        }
-
-        Ok(())
+        self.write_8(access_port, address, &[data])
     }
 
     /// Write a block of 32bit words at `addr`.
@@ -415,7 +374,7 @@ where
     pub fn write_32(
         &mut self,
         access_port: MemoryAP,
-        start_address: u32,
+        start_address: u64,
         data: &[u32],
     ) -> Result<(), AccessPortError> {
         if data.is_empty() {
@@ -432,73 +391,19 @@ where
             start_address
         );
 
-        // Second we write in 32 bit reads until we have less than 32 bits left to write.
-        let csw = Self::build_csw_register(DataSize::U32);
-
+        let csw = Self::build_csw_register(DataSize::U32, self.access_attributes);
         self.write_ap_register(access_port, csw)?;
 
-        let mut address = start_address;
-        let tar = TAR { address };
-        self.write_ap_register(access_port, tar)?;
-
-        // figure out how many words we can write before the
-        // data overflows
-
-        // maximum chunk size
-        let max_chunk_size_bytes = 0x400_usize;
-
-        let mut remaining_data_len = data.len();
-
-        let first_chunk_size_bytes = std::cmp::min(
-            max_chunk_size_bytes - (address as usize % max_chunk_size_bytes),
-            data.len() * 4,
-        );
-
-        let mut data_offset = 0;
-
-        log::debug!(
-            "Write first block with len {} at address {:#08x}",
-            first_chunk_size_bytes,
-            address
-        );
-
-        let first_chunk_size_words = first_chunk_size_bytes / 4;
-
-        self.write_ap_register_repeated(
-            access_port,
-            DRW { data: 0 },
-            &data[data_offset..first_chunk_size_words],
-        )?;
-
-        remaining_data_len -= first_chunk_size_words;
-        address += (4 * first_chunk_size_words) as u32;
-        data_offset += first_chunk_size_words;
-
-        while remaining_data_len > 0 {
-            // the autoincrement is limited to the 10 lowest bits so we need to write the address
-            // every time it overflows
-            let tar = TAR { address };
-            self.write_ap_register(access_port, tar)?;
-
-            let next_chunk_size_bytes = std::cmp::min(max_chunk_size_bytes, remaining_data_len * 4);
-
+        for (address, values) in transfer_blocks(start_address, data.len(), 4, self.max_block_size)
+        {
             log::debug!(
-                "Writing chunk with len {} at address {:#08x}",
-                next_chunk_size_bytes,
+                "Writing block with len {} at address {:#08x}",
+                values.len() * 4,
                 address
             );
 
-            let next_chunk_size_words = next_chunk_size_bytes / 4;
-
-            self.write_ap_register_repeated(
-                access_port,
-                DRW { data: 0 },
-                &data[data_offset..(data_offset + next_chunk_size_words)],
-            )?;
-
-            remaining_data_len -= next_chunk_size_words;
-            address += (4 * next_chunk_size_words) as u32;
-            data_offset += next_chunk_size_words;
+            self.write_tar(access_port, address)?;
+            self.write_ap_register_repeated(access_port, DRW { data: 0 }, &data[values])?;
         }
 
         // Ensure the last write is actually performed
@@ -509,52 +414,45 @@ where
         Ok(())
     }
 
-    /// Write a block of 8bit words at `addr`.
+    /// Write a block of 8bit words at `addr`, with one 8 bit access per byte.
     ///
-    /// The number of words written is `data.len()`.
+    /// The number of words written is `data.len()`. The accesses are never widened, so the
+    /// neighbouring bytes are not rewritten. Returns `AccessPortError::UnsupportedDataSize`
+    /// if the AP only supports 32 bit accesses.
     pub fn write_8(
         &mut self,
         access_port: MemoryAP,
-        address: u32,
+        start_address: u64,
         data: &[u8],
     ) -> Result<(), AccessPortError> {
         if data.is_empty() {
             return Ok(());
         }
 
-        let aligned = aligned_range(address, data.len())?;
-
-        // Create buffer with aligned size
-        let mut buf8 = vec![0u8; aligned.len()];
-
-        // If the start of the range isn't aligned, read the first word in to avoid clobbering
-        if address != aligned.start {
-            buf8.pwrite_with(self.read_word_32(access_port, aligned.start)?, 0, LE)
-                .unwrap();
+        if self.only_32bit_data_size {
+            return Err(AccessPortError::UnsupportedDataSize(DataSize::U8));
         }
 
-        // If the end of the range isn't aligned, read the last word in to avoid clobbering
-        if address + data.len() as u32 != aligned.end {
-            buf8.pwrite_with(
-                self.read_word_32(access_port, aligned.end - 4)?,
-                aligned.len() - 4,
-                LE,
-            )
-            .unwrap();
-        }
+        let csw = Self::build_csw_register(DataSize::U8, self.access_attributes);
+        self.write_ap_register(access_port, csw)?;
 
-        // Copy input data into buffer at the correct location
-        let start = (address - aligned.start) as usize;
-        buf8[start..start + data.len()].copy_from_slice(&data);
+        // Every byte takes a whole transfer of DRW.
+        let max_block_size = self.max_block_size / 4;
+        for (address, bytes) in transfer_blocks(start_address, data.len(), 1, max_block_size) {
+            // The byte has to be placed in the byte lane of its address,
+            // see "Arm Debug Interface Architecture Specification ADIv5.0 to ADIv5.2", C2.2.6
+            let values: Vec<u32> = data[bytes]
+                .iter()
+                .enumerate()
+                .map(|(offset, byte)| u32::from(*byte) << (((address + offset as u64) % 4) * 8))
+                .collect();
 
-        // Convert buffer to 32-bit words
-        let mut buf32 = vec![0u32; aligned.len() / 4];
-        for (i, word) in buf32.iter_mut().enumerate() {
-            *word = buf8.pread_with(i * 4, LE).unwrap();
+            self.write_tar(access_port, address)?;
+            self.write_ap_register_repeated(access_port, DRW { data: 0 }, &values)?;
         }
 
-        // Write aligned block into memory
-        self.write_32(access_port, aligned.start, &buf32)?;
+        // Ensure the last write is actually performed
+        self.write_ap_register(access_port, csw)?;
 
         Ok(())
     }
@@ -565,6 +463,7 @@ where
     AP: CommunicationInterface
         + APAccess<MemoryAP, CSW>
         + APAccess<MemoryAP, TAR>
+        + APAccess<MemoryAP, TAR2>
         + APAccess<MemoryAP, DRW>
         + DPAccess,
 {
@@ -605,17 +504,20 @@ where
         Ok(())
     }
 
-    fn read_8(&mut self, ap: MemoryAP, address: u32, data: &mut [u8]) -> Result<(), Error> {
+    fn read_8(&mut self, ap: MemoryAP, address: u64, data: &mut [u8]) -> Result<(), Error> {
         if data.len() == 1 {
-            data[0] = self.read_word_8(ap, address).unwrap();
+            data[0] = self
+                .read_word_8(ap, address)
+                .map_err(Error::architecture_specific)?;
         } else {
-            self.read_8(ap, address, data).unwrap();
+            self.read_8(ap, address, data)
+                .map_err(Error::architecture_specific)?;
         }
 
         Ok(())
     }
 
-    fn read_32(&mut self, ap: MemoryAP, address: u32, data: &mut [u32]) -> Result<(), Error> {
+    fn read_32(&mut self, ap: MemoryAP, address: u64, data: &mut [u32]) -> Result<(), Error> {
         if data.len() == 1 {
             data[0] = self.read_word_32(ap, address).unwrap();
         } else {
@@ -625,17 +527,19 @@ where
         Ok(())
     }
 
-    fn write_8(&mut self, ap: MemoryAP, address: u32, data: &[u8]) -> Result<(), Error> {
+    fn write_8(&mut self, ap: MemoryAP, address: u64, data: &[u8]) -> Result<(), Error> {
         if data.len() == 1 {
-            self.write_word_8(ap, address, data[0]).unwrap();
+            self.write_word_8(ap, address, data[0])
+                .map_err(Error::architecture_specific)?;
         } else {
-            self.write_8(ap, address, data).unwrap();
+            self.write_8(ap, address, data)
+                .map_err(Error::architecture_specific)?;
         }
 
         Ok(())
     }
 
-    fn write_32(&mut self, ap: MemoryAP, address: u32, data: &[u32]) -> Result<(), Error> {
+    fn write_32(&mut self, ap: MemoryAP, address: u64, data: &[u32]) -> Result<(), Error> {
         if data.len() == 1 {
             self.write_word_32(ap, address, data[0]).unwrap();
         } else {
@@ -650,6 +554,14 @@ where
 
         Ok(())
     }
+
+    fn keep_alive(&mut self) -> Result<(), Error> {
+        // DPIDR is readable in every DP bank, so SELECT and the AP registers are not changed.
+        self.interface
+            .read_dp_register::<DPIDR>()
+            .map_err(Error::architecture_specific)?;
+        Ok(())
+    }
 }
 
 bitfield! {
@@ -694,7 +606,7 @@ impl From<Dhcsr> for u32 {
 }
 
 impl CoreRegister for Dhcsr {
-    const ADDRESS: u32 = 0xE000_EDF0;
+    const ADDRESS: u64 = 0xE000_EDF0;
     const NAME: &'static str = "DHCSR";
 }
 
@@ -703,7 +615,7 @@ bitfield! {
     pub struct Dcrsr(u32);
     impl Debug;
     pub _, set_regwnr: 16;
-    pub _, set_regsel: 4,0;
+    pub _, set_regsel: 6,0;
 }
 
 impl From<u32> for Dcrsr {
@@ -719,7 +631,7 @@ impl From<Dcrsr> for u32 {
 }
 
 impl CoreRegister for Dcrsr {
-    const ADDRESS: u32 = 0xE000_EDF4;
+    const ADDRESS: u64 = 0xE000_EDF4;
     const NAME: &'static str = "DCRSR";
 }
 
@@ -739,33 +651,14 @@ impl From<Dcrdr> for u32 {
 }
 
 impl CoreRegister for Dcrdr {
-    const ADDRESS: u32 = 0xE000_EDF8;
+    const ADDRESS: u64 = 0xE000_EDF8;
     const NAME: &'static str = "DCRDR";
 }
 
-/// Calculates a 32-bit word aligned range from an address/length pair.
-fn aligned_range(address: u32, len: usize) -> Result<Range<u32>, AccessPortError> {
-    // Round start address down to the nearest multiple of 4
-    let start = address - (address % 4);
-
-    let unaligned_end = len
-        .try_into()
-        .ok()
-        .and_then(|len: u32| len.checked_add(address))
-        .ok_or(AccessPortError::OutOfBoundsError)?;
-
-    // Round end address up to the nearest multiple of 4
-    let end = unaligned_end
-        .checked_add((4 - (unaligned_end % 4)) % 4)
-        .ok_or(AccessPortError::OutOfBoundsError)?;
-
-    Ok(Range { start, end })
-}
-
 #[cfg(test)]
 mod tests {
     use super::super::super::ap::memory_ap::mock::MockMemoryAP;
-    use super::ADIMemoryInterface;
+    use super::{ADIMemoryInterface, DataSize, MemoryAccessAttributes};
 
     impl<'interface> ADIMemoryInterface<'interface, MockMemoryAP> {
         /// Creates a new MemoryInterface for given AccessPort.
@@ -773,6 +666,9 @@ mod tests {
             Self {
                 interface: mock,
                 only_32bit_data_size: false,
+                supports_large_address: false,
+                max_block_size: usize::MAX,
+                access_attributes: MemoryAccessAttributes::default(),
             }
         }
 
@@ -789,6 +685,23 @@ mod tests {
     // DATA8 interpreted as little endian 32-bit words
     const DATA32: &[u32] = &[0x83828180, 0x87868584, 0x8b8a8988, 0x8f8e8d8c];
 
+    #[test]
+    fn csw_contains_the_access_attributes() {
+        let csw = ADIMemoryInterface::<MockMemoryAP>::build_csw_register(
+            DataSize::U32,
+            MemoryAccessAttributes::default(),
+        );
+        assert_eq!((csw.PROT, csw.CACHE), (0b110, 0b0011));
+
+        let secure = MemoryAccessAttributes {
+            secure: true,
+            privileged: false,
+            cacheable: true,
+        };
+        let csw = ADIMemoryInterface::<MockMemoryAP>::build_csw_register(DataSize::U32, secure);
+        assert_eq!((csw.PROT, csw.CACHE), (0b010, 0b1101));
+    }
+
     #[test]
     fn read_word_32() {
         let mut mock = MockMemoryAP::with_pattern();
@@ -846,7 +759,7 @@ mod tests {
             let mut expected = Vec::from(mi.mock_memory());
             expected[address] = DATA8[0];
 
-            mi.write_word_8(0.into(), address as u32, DATA8[0])
+            mi.write_word_8(0.into(), address as u64, DATA8[0])
                 .unwrap_or_else(|_| panic!("write_word_8 failed, address = {}", address));
             assert_eq!(
                 mi.mock_memory(),
@@ -981,16 +894,55 @@ mod tests {
         }
     }
 
-    use super::aligned_range;
+    #[test]
+    fn bytes_are_accessed_with_8_bit_transfers() {
+        use super::super::super::ap::{APRegister, AccessPortError, CSW};
+        use super::super::super::Register;
+
+        let mut mock = MockMemoryAP::with_pattern();
+        let mut mi = ADIMemoryInterface::<MockMemoryAP>::new(&mut mock);
+        mi.write_8(0.into(), 4, &DATA8[..8]).unwrap();
+        assert_eq!(
+            CSW::from(mock.store[&(CSW::ADDRESS, CSW::APBANKSEL)]).SIZE,
+            DataSize::U8
+        );
+
+        // A 32 bit only AP must not fall back to a read-modify-write of the whole word.
+        let mut mock = MockMemoryAP::with_pattern();
+        let expected = mock.memory.clone();
+        let mut mi = ADIMemoryInterface::<MockMemoryAP>::new(&mut mock);
+        mi.only_32bit_data_size = true;
+
+        assert!(matches!(
+            mi.write_word_8(0.into(), 1, 0xAA),
+            Err(AccessPortError::UnsupportedDataSize(DataSize::U8))
+        ));
+        assert!(matches!(
+            mi.read_8(0.into(), 0, &mut [0; 4]),
+            Err(AccessPortError::UnsupportedDataSize(DataSize::U8))
+        ));
+        assert_eq!(mock.memory, expected);
+    }
 
     #[test]
-    fn aligned_range_at_limit_does_not_panic() {
-        // The aligned range for address 0xfffffff9 with length
-        // 4 should not panic.
+    fn large_addresses() {
+        use super::super::super::ap::{APRegister, AccessPortError, TAR2};
+        use super::super::super::Register;
 
-        // Not sure what the best behaviour to handle this is, but
-        // for sure no panic
+        let mut mock = MockMemoryAP::with_pattern();
+        let mut mi = ADIMemoryInterface::<MockMemoryAP>::new(&mut mock);
+        assert!(matches!(
+            mi.read_word_32(0.into(), 0x1_0000_0004),
+            Err(AccessPortError::AddressTooLarge(0x1_0000_0004))
+        ));
 
-        let _ = aligned_range(0xfffffff9, 4);
+        mi.supports_large_address = true;
+        mi.write_word_32(0.into(), 0x1_0000_0004, 0xdead_beef)
+            .unwrap();
+        assert_eq!(
+            mi.read_word_32(0.into(), 0x1_0000_0004).unwrap(),
+            0xdead_beef
+        );
+        assert_eq!(mock.store[&(TAR2::ADDRESS, TAR2::APBANKSEL)], 1);
     }
 }
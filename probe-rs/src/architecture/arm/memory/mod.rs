@@ -2,7 +2,48 @@ pub(crate) mod adi_v5_memory_interface;
 pub(crate) mod romtable;
 
 use super::ap::AccessPortError;
-pub use romtable::Component;
+pub use romtable::{
+    Component, DebugComponent, DebugComponentKind, DebugComponentTree, RomTableError,
+};
+
+/// The attributes of the memory accesses through a memory AP, which are set in its `CSW` register.
+///
+/// The default attributes are the ones used by all memory accesses unless set otherwise:
+/// non-secure, privileged, non-cacheable data accesses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccessAttributes {
+    /// Access the secure view of the memory, `HNONSEC` is cleared.
+    ///
+    /// This requires secure debug to be enabled on the target.
+    pub secure: bool,
+    /// Make privileged accesses.
+    pub privileged: bool,
+    /// Make cacheable and bufferable accesses, instead of device accesses.
+    pub cacheable: bool,
+}
+
+impl Default for MemoryAccessAttributes {
+    fn default() -> Self {
+        Self {
+            secure: false,
+            privileged: true,
+            cacheable: false,
+        }
+    }
+}
+
+impl MemoryAccessAttributes {
+    /// The `PROT` field of `CSW`, which contains `HNONSEC` and the master type.
+    pub(crate) fn prot(&self) -> u8 {
+        (u8::from(!self.secure) << 2) | 0b010
+    }
+
+    /// The `CACHE` field of `CSW`, which contains `HPROT[3:0]`.
+    pub(crate) fn cache(&self) -> u8 {
+        let cacheable = if self.cacheable { 0b1100 } else { 0 };
+        cacheable | (u8::from(self.privileged) << 1) | 0b1
+    }
+}
 
 pub trait ToMemoryReadSize: Into<u32> + Copy {
     /// The alignment mask that is required to test for properly aligned memory.
@@ -130,3 +171,69 @@ where
         (*self).write_block8(address, data)
     }
 }
+
+/// The size of the blocks in which the auto-increment of TAR is guaranteed to work.
+///
+/// The address has to be written to TAR again at every boundary, see
+/// "Arm Debug Interface Architecture Specification ADIv5.0 to ADIv5.2", C2.2.2.
+pub(crate) const TAR_AUTO_INCREMENT_BLOCK_SIZE: u64 = 0x400;
+
+/// Splits a transfer of `count` values of `size` bytes at `address` into blocks
+/// of at most `max_block_size` bytes, which do not cross a TAR auto-increment boundary.
+///
+/// Returns the address of each block, and the range of values it contains.
+pub(crate) fn transfer_blocks(
+    address: u64,
+    count: usize,
+    size: usize,
+    max_block_size: usize,
+) -> Vec<(u64, std::ops::Range<usize>)> {
+    let max_values = (max_block_size / size).max(1);
+
+    let mut blocks = vec![];
+    let mut start = 0;
+    while start < count {
+        let block_address = address + (start * size) as u64;
+        let until_boundary =
+            TAR_AUTO_INCREMENT_BLOCK_SIZE - block_address % TAR_AUTO_INCREMENT_BLOCK_SIZE;
+        let values = ((until_boundary as usize / size).max(1))
+            .min(max_values)
+            .min(count - start);
+
+        blocks.push((block_address, start..start + values));
+        start += values;
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blocks_respect_the_tar_boundary_and_the_block_size() {
+        assert_eq!(
+            transfer_blocks(0x3f8, 4, 4, usize::MAX),
+            vec![(0x3f8, 0..2), (0x400, 2..4)]
+        );
+        assert_eq!(
+            transfer_blocks(0x0, 0x300, 4, 0x400),
+            vec![
+                (0x0, 0..0x100),
+                (0x400, 0x100..0x200),
+                (0x800, 0x200..0x300)
+            ]
+        );
+        // The block size is rounded down to whole values.
+        assert_eq!(
+            transfer_blocks(0x0, 5, 4, 10),
+            vec![(0x0, 0..2), (0x8, 2..4), (0x10, 4..5)]
+        );
+        assert_eq!(
+            transfer_blocks(0x3fe, 4, 1, 64),
+            vec![(0x3fe, 0..2), (0x400, 2..4)]
+        );
+        assert!(transfer_blocks(0x0, 0, 4, 64).is_empty());
+    }
+}
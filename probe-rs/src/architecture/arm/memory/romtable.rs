@@ -77,7 +77,7 @@ impl<'probe, 'memory, 'reader> Iterator for RomTableIterator<'probe, 'memory, 'r
         if let Err(e) = self
             .rom_table_reader
             .memory
-            .read_32(component_address as u32, &mut entry_data)
+            .read_32(component_address, &mut entry_data)
         {
             return Some(Err(RomTableError::Memory(e)));
         }
@@ -88,8 +88,7 @@ impl<'probe, 'memory, 'reader> Iterator for RomTableIterator<'probe, 'memory, 'r
             return None;
         }
 
-        let entry_data =
-            RomTableEntryRaw::new(self.rom_table_reader.base_address as u32, entry_data[0]);
+        let entry_data = RomTableEntryRaw::new(self.rom_table_reader.base_address, entry_data[0]);
 
         log::info!("ROM Table Entry: {:#x?}", entry_data);
         Some(Ok(entry_data))
@@ -108,6 +107,16 @@ pub struct RomTable {
     dwt: Option<usize>,
     /// The index of the ITM peripheral in the entries.
     itm: Option<usize>,
+    /// The entries which are present, but whose component could not be read,
+    /// e.g. because its power domain is disabled.
+    unreadable_entries: Vec<UnreadableRomTableEntry>,
+}
+
+/// A ROM table entry whose component could not be read.
+#[derive(Debug, PartialEq)]
+struct UnreadableRomTableEntry {
+    address: u64,
+    power_domain_id: Option<u8>,
 }
 
 impl RomTable {
@@ -120,6 +129,7 @@ impl RomTable {
         let mut tpiu = None;
         let mut dwt = None;
         let mut itm = None;
+        let mut unreadable_entries = vec![];
 
         log::info!("Parsing romtable at base_address {:x?}", base_address);
 
@@ -136,7 +146,25 @@ impl RomTable {
             log::info!("Parsing entry at {:x?}", entry_base_addr);
 
             if raw_entry.entry_present {
-                let component = Component::try_parse(memory, u64::from(entry_base_addr))?;
+                let component = match Component::try_parse(memory, entry_base_addr) {
+                    Ok(component) => component,
+                    Err(e) => {
+                        log::warn!(
+                            "Unable to read component at {:#010x}: {}",
+                            entry_base_addr,
+                            e
+                        );
+                        unreadable_entries.push(UnreadableRomTableEntry {
+                            address: entry_base_addr,
+                            power_domain_id: if raw_entry.power_domain_valid {
+                                Some(raw_entry.power_domain_id)
+                            } else {
+                                None
+                            },
+                        });
+                        continue;
+                    }
+                };
                 let component_id = component.id();
 
                 // Make sure we store known component locations so we can easily access them lateron.
@@ -167,6 +195,7 @@ impl RomTable {
             tpiu,
             dwt,
             itm,
+            unreadable_entries,
         })
     }
 }
@@ -195,12 +224,12 @@ struct RomTableEntryRaw {
     /// Indicates whether the ROM table behind the address offset is present.
     pub entry_present: bool,
     // Base address of the rom table
-    base_address: u32,
+    base_address: u64,
 }
 
 impl RomTableEntryRaw {
     /// Create a new RomTableEntryRaw from raw ROM table entry data in memory.
-    fn new(base_address: u32, raw: u32) -> Self {
+    fn new(base_address: u64, raw: u32) -> Self {
         log::debug!("Parsing raw rom table entry: 0x{:05x}", raw);
 
         let address_offset = ((raw >> 12) & 0xf_ff_ff) as i32;
@@ -220,8 +249,8 @@ impl RomTableEntryRaw {
     }
 
     /// Returns the address of the CoreSight component behind a ROM table entry.
-    pub fn component_address(&self) -> u32 {
-        (i64::from(self.base_address) + (i64::from(self.address_offset << 12))) as u32
+    pub fn component_address(&self) -> u64 {
+        (self.base_address as i64 + i64::from(self.address_offset << 12)) as u64
     }
 }
 
@@ -289,7 +318,7 @@ impl<'probe: 'memory, 'memory> ComponentInformationReader<'probe, 'memory> {
         let mut cidr = [0u32; 4];
 
         self.memory
-            .read_32(self.base_address as u32 + 0xFF0, &mut cidr)
+            .read_32(self.base_address + 0xFF0, &mut cidr)
             .map_err(RomTableError::Memory)?;
 
         log::debug!("CIDR: {:x?}", cidr);
@@ -333,10 +362,10 @@ impl<'probe: 'memory, 'memory> ComponentInformationReader<'probe, 'memory> {
         );
 
         self.memory
-            .read_32(self.base_address as u32 + 0xFD0, &mut data[4..])
+            .read_32(self.base_address + 0xFD0, &mut data[4..])
             .map_err(RomTableError::Memory)?;
         self.memory
-            .read_32(self.base_address as u32 + 0xFE0, &mut data[..4])
+            .read_32(self.base_address + 0xFE0, &mut data[..4])
             .map_err(RomTableError::Memory)?;
 
         log::debug!("Raw peripheral id: {:x?}", data);
@@ -437,13 +466,13 @@ impl Component {
 
     /// Reads a register of the component pointed to by this romtable entry.
     pub fn read_reg(&self, core: &mut Core, offset: u32) -> Result<u32, Error> {
-        let value = core.read_word_32(self.id().component_address as u32 + offset)?;
+        let value = core.read_word_32(self.id().component_address + u64::from(offset))?;
         Ok(value)
     }
 
     /// Writes a register of the component pointed to by this romtable entry.
     pub fn write_reg(&self, core: &mut Core, offset: u32, value: u32) -> Result<(), Error> {
-        core.write_word_32(self.id().component_address as u32 + offset, value)?;
+        core.write_word_32(self.id().component_address + u64::from(offset), value)?;
         Ok(())
     }
 
@@ -486,11 +515,151 @@ impl Component {
         Err(RomTableError::ComponentNotFound("TPIU".into()))
     }
 
+    /// Returns all the CTI components.
+    pub fn ctis(&self) -> Vec<&Component> {
+        self.iter()
+            .filter(|component| component.id().peripheral_id.is_cti())
+            .collect()
+    }
+
     pub fn iter(&self) -> ComponentIter {
         ComponentIter::new(vec![self])
     }
 }
 
+/// The kind of a CoreSight component, derived from its component and peripheral ID.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugComponentKind {
+    RomTable,
+    Itm,
+    Dwt,
+    Tpiu,
+    Etm,
+    Cti,
+    /// A component which is not known to probe-rs, see the part number of the component.
+    Other,
+    /// The ROM table lists a component which could not be read,
+    /// e.g. because its power domain was disabled while the ROM tables were read.
+    Unreadable,
+}
+
+/// A CoreSight component found while reading the ROM tables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugComponent {
+    pub kind: DebugComponentKind,
+    /// The address of the first register of the component.
+    pub base_address: u64,
+    /// The part number from the peripheral ID, `None` if the component could not be read.
+    pub part: Option<u16>,
+    /// The designer from the peripheral ID, `None` for legacy or unreadable components.
+    pub designer: Option<jep106::JEP106Code>,
+    /// The power domain of the component, if the ROM table entry specifies one.
+    pub power_domain_id: Option<u8>,
+    /// The components listed by this component, if it is a ROM table.
+    pub children: Vec<DebugComponent>,
+}
+
+impl DebugComponent {
+    fn from_component(component: &Component, power_domain_id: Option<u8>) -> Self {
+        let id = component.id();
+        let peripheral_id = &id.peripheral_id;
+
+        let kind = if let Component::Class1RomTable(..) = component {
+            DebugComponentKind::RomTable
+        } else if peripheral_id.is_itm() {
+            DebugComponentKind::Itm
+        } else if peripheral_id.is_dwt() {
+            DebugComponentKind::Dwt
+        } else if peripheral_id.is_tpiu() {
+            DebugComponentKind::Tpiu
+        } else if peripheral_id.is_etm() {
+            DebugComponentKind::Etm
+        } else if peripheral_id.is_cti() {
+            DebugComponentKind::Cti
+        } else {
+            DebugComponentKind::Other
+        };
+
+        let children = match component {
+            Component::Class1RomTable(_, rom_table) => rom_table
+                .entries
+                .iter()
+                .map(|entry| {
+                    let power_domain_id = if entry.power_domain_valid {
+                        Some(entry.power_domain_id)
+                    } else {
+                        None
+                    };
+                    DebugComponent::from_component(&entry.component, power_domain_id)
+                })
+                .chain(
+                    rom_table
+                        .unreadable_entries
+                        .iter()
+                        .map(|entry| DebugComponent {
+                            kind: DebugComponentKind::Unreadable,
+                            base_address: entry.address,
+                            part: None,
+                            designer: None,
+                            power_domain_id: entry.power_domain_id,
+                            children: vec![],
+                        }),
+                )
+                .collect(),
+            _ => vec![],
+        };
+
+        DebugComponent {
+            kind,
+            base_address: id.component_address,
+            part: Some(peripheral_id.part()),
+            designer: peripheral_id.jep106(),
+            power_domain_id,
+            children,
+        }
+    }
+}
+
+/// The hierarchy of CoreSight components, as described by the ROM tables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugComponentTree {
+    root: DebugComponent,
+}
+
+impl DebugComponentTree {
+    /// The component the debug base address of the access port points to,
+    /// usually the top level ROM table.
+    pub fn root(&self) -> &DebugComponent {
+        &self.root
+    }
+
+    /// Iterates over all components, depth first.
+    pub fn iter(&self) -> impl Iterator<Item = &DebugComponent> {
+        let mut components = vec![];
+        let mut stack = vec![&self.root];
+
+        while let Some(component) = stack.pop() {
+            components.push(component);
+            stack.extend(component.children.iter().rev());
+        }
+
+        components.into_iter()
+    }
+
+    /// Returns the first component of the given kind.
+    pub fn find(&self, kind: DebugComponentKind) -> Option<&DebugComponent> {
+        self.iter().find(|component| component.kind == kind)
+    }
+}
+
+impl From<&Component> for DebugComponentTree {
+    fn from(component: &Component) -> Self {
+        DebugComponentTree {
+            root: DebugComponent::from_component(component, None),
+        }
+    }
+}
+
 /// This is a recursive iterator over all CoreSight components.
 pub struct ComponentIter<'a> {
     /// The components of this iterator level.
@@ -617,6 +786,16 @@ impl PeripheralID {
         self.PART == 0x2
     }
 
+    /// Returns whether the peripheral is an ETM cell.
+    pub fn is_etm(&self) -> bool {
+        self.PART == 0x924 || self.PART == 0x925 || self.PART == 0x975
+    }
+
+    /// Returns whether the peripheral is a CTI cell.
+    pub fn is_cti(&self) -> bool {
+        self.PART == 0x906 || self.PART == 0x9ED
+    }
+
     /// Returns the JEP106 code of the peripheral ID register.
     pub fn jep106(&self) -> Option<jep106::JEP106Code> {
         self.JEP106
@@ -627,3 +806,127 @@ impl PeripheralID {
         self.PART
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn component_id(address: u64, class: RawComponent, part: u16) -> ComponentId {
+        let peripheral_id = PeripheralID::from_raw(&[
+            u32::from(part & 0xFF),
+            0xB0 | u32::from(part >> 8),
+            0x3B,
+            0x0,
+            0x4,
+            0x0,
+            0x0,
+            0x0,
+        ]);
+
+        ComponentId {
+            component_address: address,
+            class,
+            peripheral_id,
+        }
+    }
+
+    fn entry(address: u64, part: u16) -> RomTableEntry {
+        RomTableEntry {
+            power_domain_id: 0,
+            power_domain_valid: false,
+            format: true,
+            component: Component::CoreLinkOrPrimeCellOrSystemComponent(component_id(
+                address,
+                RawComponent::CoreLinkOrPrimeCellOrSystemComponent,
+                part,
+            )),
+        }
+    }
+
+    /// The ROM table of an STM32F4, with the ETM in a disabled power domain.
+    fn stm32f4_rom_table() -> Component {
+        let rom_table = RomTable {
+            entries: vec![
+                entry(0xE000_E000, 0x00C),
+                entry(0xE000_1000, 0x002),
+                entry(0xE000_2000, 0x003),
+                entry(0xE000_0000, 0x001),
+                entry(0xE004_0000, 0x9A1),
+            ],
+            tpiu: Some(4),
+            dwt: Some(1),
+            itm: Some(3),
+            unreadable_entries: vec![UnreadableRomTableEntry {
+                address: 0xE004_1000,
+                power_domain_id: Some(1),
+            }],
+        };
+
+        Component::Class1RomTable(
+            component_id(0xE00F_F000, RawComponent::RomTable, 0x411),
+            rom_table,
+        )
+    }
+
+    #[test]
+    fn stm32f4_debug_components() {
+        let tree = DebugComponentTree::from(&stm32f4_rom_table());
+
+        assert_eq!(tree.root().kind, DebugComponentKind::RomTable);
+        assert_eq!(tree.root().base_address, 0xE00F_F000);
+
+        let itm = tree.find(DebugComponentKind::Itm).unwrap();
+        assert_eq!(itm.base_address, 0xE000_0000);
+        assert_eq!(itm.part, Some(0x001));
+
+        let dwt = tree.find(DebugComponentKind::Dwt).unwrap();
+        assert_eq!(dwt.base_address, 0xE000_1000);
+
+        let tpiu = tree.find(DebugComponentKind::Tpiu).unwrap();
+        assert_eq!(tpiu.base_address, 0xE004_0000);
+    }
+
+    #[test]
+    fn unreadable_components_are_listed() {
+        let tree = DebugComponentTree::from(&stm32f4_rom_table());
+
+        let unreadable: Vec<_> = tree
+            .iter()
+            .filter(|component| component.kind == DebugComponentKind::Unreadable)
+            .collect();
+
+        assert_eq!(unreadable.len(), 1);
+        assert_eq!(unreadable[0].base_address, 0xE004_1000);
+        assert_eq!(unreadable[0].power_domain_id, Some(1));
+        assert_eq!(unreadable[0].part, None);
+    }
+
+    #[test]
+    fn nested_rom_tables_are_walked_depth_first() {
+        let nested = RomTable {
+            entries: vec![RomTableEntry {
+                power_domain_id: 0,
+                power_domain_valid: false,
+                format: true,
+                component: stm32f4_rom_table(),
+            }],
+            tpiu: None,
+            dwt: None,
+            itm: None,
+            unreadable_entries: vec![],
+        };
+        let root = Component::Class1RomTable(
+            component_id(0xE00F_E000, RawComponent::RomTable, 0x4C4),
+            nested,
+        );
+
+        let tree = DebugComponentTree::from(&root);
+        let addresses: Vec<_> = tree.iter().map(|c| c.base_address).take(3).collect();
+
+        assert_eq!(addresses, vec![0xE00F_E000, 0xE00F_F000, 0xE000_E000]);
+        assert_eq!(
+            tree.find(DebugComponentKind::Itm).unwrap().base_address,
+            0xE000_0000
+        );
+    }
+}
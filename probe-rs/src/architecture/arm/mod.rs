@@ -1,18 +1,92 @@
 pub mod ap;
+pub mod authentication;
+pub(crate) mod chip_detection;
 pub(crate) mod communication_interface;
 pub mod component;
 pub(crate) mod core;
 pub mod dp;
+pub(crate) mod fast_read;
 pub mod memory;
 pub mod swo;
 
+pub use chip_detection::{ChipCandidate, ChipDetection, IdRegisterValue};
 pub use communication_interface::{
-    ApInformation, ArmChipInfo, ArmCommunicationInterface, DAPAccess, DapError,
+    ApInformation, ArmChipInfo, ArmCommunicationInterface, DAPAccess, DapError, DapRetryConfig,
 };
-pub use communication_interface::{PortType, Register};
+pub use communication_interface::{ArmProbeInterface, PortType, Register};
+pub use dp::{DpAddress, SwjSequence};
 pub use swo::{SwoAccess, SwoConfig, SwoMode};
 
 pub use self::core::m0;
 pub use self::core::m33;
 pub use self::core::m4;
 pub use self::core::CortexDump;
+pub use self::core::FpRegister;
+
+use thiserror::Error;
+
+/// An error of the Arm debug interface, see `Error::Arm`.
+///
+/// This collects the errors of the different parts of the debug interface, so they can be
+/// told apart from errors of the probe itself. The errors which can contain an `Error`
+/// themselves are boxed.
+#[derive(Debug, Error)]
+pub enum ArmError {
+    #[error(transparent)]
+    Dap(#[from] DapError),
+    #[error(transparent)]
+    DebugPort(#[from] dp::DebugPortError),
+    #[error(transparent)]
+    AccessPort(#[from] ap::AccessPortError),
+    #[error(transparent)]
+    RomTable(Box<memory::RomTableError>),
+    #[error(transparent)]
+    Component(#[from] component::ComponentError),
+    #[error(transparent)]
+    Authentication(Box<authentication::AuthenticationError>),
+}
+
+impl From<memory::RomTableError> for ArmError {
+    fn from(error: memory::RomTableError) -> Self {
+        ArmError::RomTable(Box::new(error))
+    }
+}
+
+impl From<authentication::AuthenticationError> for ArmError {
+    fn from(error: authentication::AuthenticationError) -> Self {
+        ArmError::Authentication(Box::new(error))
+    }
+}
+
+impl ArmError {
+    /// Returns the error in `error` if it is one of the errors collected by `ArmError`.
+    pub(crate) fn from_boxed(
+        error: Box<dyn std::error::Error + Send + Sync>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        fn downcast<T: std::error::Error + Send + Sync + 'static>(
+            error: Box<dyn std::error::Error + Send + Sync>,
+        ) -> Result<ArmError, Box<dyn std::error::Error + Send + Sync>>
+        where
+            ArmError: From<T>,
+        {
+            error.downcast::<T>().map(|error| ArmError::from(*error))
+        }
+
+        downcast::<DapError>(error)
+            .or_else(downcast::<dp::DebugPortError>)
+            .or_else(downcast::<ap::AccessPortError>)
+            .or_else(downcast::<memory::RomTableError>)
+            .or_else(downcast::<component::ComponentError>)
+            .or_else(downcast::<authentication::AuthenticationError>)
+    }
+
+    /// Returns the `Ctrl/Stat` register of the DP, if the target answered a transfer with FAULT.
+    ///
+    /// The register was read before its sticky error flags were cleared, see `DapError::fault_status`.
+    pub fn fault_status(&self) -> Option<dp::Ctrl> {
+        match self {
+            ArmError::Dap(error) => error.fault_status(),
+            _ => None,
+        }
+    }
+}
@@ -9,7 +9,7 @@
 //! Also a good reference is itmdump.c from openocd:
 //! https://github.com/arduino/OpenOCD/blob/master/contrib/itmdump.c
 
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 
 use scroll::Pread;
 
@@ -113,12 +113,14 @@ pub enum MemoryAccessType {
 ///
 /// This is a sans-io style decoder.
 /// See also: https://sans-io.readthedocs.io/how-to-sans-io.html
+#[derive(Debug)]
 pub struct Decoder {
     incoming: VecDeque<u8>,
     packets: VecDeque<TracePacket>,
     state: DecoderState,
 }
 
+#[derive(Debug)]
 enum DecoderState {
     Header,
     Syncing(usize),
@@ -453,6 +455,22 @@ impl Decoder {
     }
 }
 
+/// Collects the payloads of all ITM data packets, grouped by their stimulus port.
+///
+/// The payloads of a port are concatenated in the order they were received,
+/// which restores e.g. a string written byte by byte to the port.
+pub fn group_stimulus_data(packets: &[TracePacket]) -> BTreeMap<usize, Vec<u8>> {
+    let mut ports: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+
+    for packet in packets {
+        if let TracePacket::ItmData { id, payload } = packet {
+            ports.entry(*id).or_default().extend(payload);
+        }
+    }
+
+    ports
+}
+
 impl Default for Decoder {
     fn default() -> Self {
         Self::new()
@@ -470,7 +488,9 @@ fn extract_size(c: u8) -> Result<usize, String> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Decoder, MemoryAccessType, TracePacket};
+    use super::{
+        group_stimulus_data, Decoder, ExceptionAction, ExceptionType, MemoryAccessType, TracePacket,
+    };
 
     #[test]
     fn example_capture1() {
@@ -592,4 +612,49 @@ mod tests {
         );
         assert_eq!(None, decoder.pull());
     }
+
+    #[test]
+    fn sync_exception_and_pc_sample() {
+        let trace_data: Vec<u8> = vec![
+            // Sync packet
+            0, 0, 0, 0, 0, 0x80, //
+            // Exception trace, SysTick entered
+            0x0E, 15, 0x10, //
+            // PC sample
+            0x17, 0x44, 0x02, 0x00, 0x08,
+        ];
+
+        let mut decoder = Decoder::new();
+
+        decoder.feed(trace_data);
+        assert_eq!(Some(TracePacket::Sync), decoder.pull());
+        assert_eq!(
+            Some(TracePacket::ExceptionTrace {
+                exception: ExceptionType::SysTick,
+                action: ExceptionAction::Entered,
+            }),
+            decoder.pull()
+        );
+        assert_eq!(
+            Some(TracePacket::PcSample { pc: 0x0800_0244 }),
+            decoder.pull()
+        );
+        assert_eq!(None, decoder.pull());
+    }
+
+    #[test]
+    fn stimulus_data_is_grouped_by_port() {
+        // "Hi" on port 0, interleaved with a single byte on port 1.
+        let trace_data: Vec<u8> = vec![0x01, b'H', 0x09, b'x', 0x01, b'i'];
+
+        let mut decoder = Decoder::new();
+        decoder.feed(trace_data);
+
+        let packets: Vec<_> = std::iter::from_fn(|| decoder.pull()).collect();
+        let ports = group_stimulus_data(&packets);
+
+        assert_eq!(ports.len(), 2);
+        assert_eq!(ports[&0], b"Hi");
+        assert_eq!(ports[&1], b"x");
+    }
 }
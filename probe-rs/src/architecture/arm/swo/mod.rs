@@ -1,7 +1,11 @@
 mod decoder;
+mod profile;
 mod publisher;
 
-pub use decoder::{Decoder, ExceptionAction, ExceptionType, TracePacket};
+pub use decoder::{
+    group_stimulus_data, Decoder, ExceptionAction, ExceptionType, MemoryAccessType, TracePacket,
+};
+pub use profile::{pc_sampling_interval, PcProfile, ProfileEntry, ProfileLocation};
 pub use publisher::{SwoPublisher, UpdaterChannel};
 
 use crate::Error;
@@ -0,0 +1,138 @@
+//! Statistical profiles of the code executed by a core, built from the PC samples of the DWT.
+
+use super::TracePacket;
+use std::collections::HashMap;
+
+/// Where the samples of a [`ProfileEntry`] were taken.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ProfileLocation {
+    /// The samples were taken in the function with the given name.
+    Function(String),
+    /// The sample was taken at an address without a known function.
+    Unknown(u32),
+}
+
+/// The number of samples taken at a location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileEntry {
+    pub location: ProfileLocation,
+    pub samples: usize,
+}
+
+/// Counts the PC samples received over SWO.
+///
+/// Use `Session::enable_swv_pc_sampling` to start sampling, and feed the packets returned by
+/// `Session::read_swo` into the profile.
+#[derive(Debug, Clone, Default)]
+pub struct PcProfile {
+    samples: HashMap<u32, usize>,
+    total: usize,
+}
+
+impl PcProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the PC samples in `packets`, other packets are ignored.
+    pub fn add_packets(&mut self, packets: &[TracePacket]) {
+        for packet in packets {
+            if let TracePacket::PcSample { pc } = packet {
+                self.add_sample(*pc);
+            }
+        }
+    }
+
+    /// Counts a sample of the PC.
+    pub fn add_sample(&mut self, pc: u32) {
+        *self.samples.entry(pc).or_default() += 1;
+        self.total += 1;
+    }
+
+    /// The number of samples counted.
+    pub fn sample_count(&self) -> usize {
+        self.total
+    }
+
+    /// Returns the samples per function, starting with the function with the most samples.
+    ///
+    /// `resolve` returns the name of the function containing an address, and is called once for
+    /// each sampled address. Samples at addresses without a function are listed per address.
+    pub fn entries(&self, mut resolve: impl FnMut(u32) -> Option<String>) -> Vec<ProfileEntry> {
+        let mut locations = HashMap::<ProfileLocation, usize>::new();
+        for (pc, samples) in &self.samples {
+            let location = match resolve(*pc) {
+                Some(function) => ProfileLocation::Function(function),
+                None => ProfileLocation::Unknown(*pc),
+            };
+            *locations.entry(location).or_default() += samples;
+        }
+
+        let mut entries: Vec<_> = locations
+            .into_iter()
+            .map(|(location, samples)| ProfileEntry { location, samples })
+            .collect();
+        entries.sort_by(|a, b| {
+            b.samples
+                .cmp(&a.samples)
+                .then_with(|| a.location.cmp(&b.location))
+        });
+        entries
+    }
+}
+
+/// The number of core cycles between two PC samples, for the `postpreset` passed to
+/// `Session::enable_swv_pc_sampling`.
+pub fn pc_sampling_interval(postpreset: u8) -> u32 {
+    64 * (u32::from(postpreset & 0xF) + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn samples_are_grouped_by_function() {
+        let mut profile = PcProfile::new();
+        profile.add_packets(&[
+            TracePacket::PcSample { pc: 0x100 },
+            TracePacket::PcSample { pc: 0x104 },
+            TracePacket::PcTrace { id: 0, value: 0 },
+            TracePacket::PcSample { pc: 0x200 },
+            TracePacket::PcSample { pc: 0x900 },
+            TracePacket::PcSample { pc: 0x900 },
+            TracePacket::PcSample { pc: 0x102 },
+        ]);
+        assert_eq!(profile.sample_count(), 6);
+
+        let entries = profile.entries(|pc| match pc {
+            0x100..=0x1FF => Some("main".to_string()),
+            0x200..=0x2FF => Some("idle".to_string()),
+            _ => None,
+        });
+        assert_eq!(
+            entries,
+            vec![
+                ProfileEntry {
+                    location: ProfileLocation::Function("main".to_string()),
+                    samples: 3,
+                },
+                ProfileEntry {
+                    location: ProfileLocation::Unknown(0x900),
+                    samples: 2,
+                },
+                ProfileEntry {
+                    location: ProfileLocation::Function("idle".to_string()),
+                    samples: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sampling_interval_uses_four_bits() {
+        assert_eq!(pc_sampling_interval(0), 64);
+        assert_eq!(pc_sampling_interval(15), 1024);
+        assert_eq!(pc_sampling_interval(16), 64);
+    }
+}
@@ -25,3 +25,32 @@ pub const fn addi(source: u32, destination: u32, immediate: u32) -> u32 {
 
     immediate << 20 | source << 15 | function << 12 | destination << 7 | opcode
 }
+
+/// Assemble a `csrrs` instruction.
+pub const fn csrrs(source: u32, destination: u32, csr: u16) -> u32 {
+    let opcode = 0b111_0011;
+    let function = 0b010;
+
+    (csr as u32 & 0xfff) << 20 | source << 15 | function << 12 | destination << 7 | opcode
+}
+
+/// Assemble a `csrrw` instruction.
+pub const fn csrrw(source: u32, destination: u32, csr: u16) -> u32 {
+    let opcode = 0b111_0011;
+    let function = 0b001;
+
+    (csr as u32 & 0xfff) << 20 | source << 15 | function << 12 | destination << 7 | opcode
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csr_instructions() {
+        // csrr s0, dcsr
+        assert_eq!(csrrs(0, 8, 0x7b0), 0x7b00_2473);
+        // csrw dcsr, s0
+        assert_eq!(csrrw(8, 0, 0x7b0), 0x7b04_1073);
+    }
+}
@@ -12,15 +12,16 @@ use crate::{MemoryInterface, Probe};
 use crate::{probe::JTAGAccess, CoreRegisterAddress, DebugProbe, Error as ProbeRsError};
 
 use std::{
-    convert::TryInto,
+    convert::{TryFrom, TryInto},
     time::{Duration, Instant},
 };
 
 use bitfield::bitfield;
 use thiserror::Error;
 
+/// An error of the RISC-V debug module, see `Error::Riscv`.
 #[derive(Error, Debug)]
-pub(crate) enum RiscvError {
+pub enum RiscvError {
     #[error("Error during read/write to the DMI register: {0:?}")]
     DmiTransfer(DmiOperationStatus),
     #[error("Debug Probe Error")]
@@ -41,20 +42,22 @@ pub(crate) enum RiscvError {
     UnsupportedBusAccessWidth(RiscvBusAccess),
     #[error("Unexpected trigger type {0} for address breakpoint.")]
     UnexpectedTriggerType(u32),
+    #[error("Address {0:#x} is above 4GB, but only 32 bit addresses are supported.")]
+    AddressTooLarge(u64),
 }
 
 impl From<RiscvError> for ProbeRsError {
     fn from(err: RiscvError) -> Self {
         match err {
             RiscvError::DebugProbe(e) => e.into(),
-            other => ProbeRsError::ArchitectureSpecific(Box::new(other)),
+            other => ProbeRsError::Riscv(other),
         }
     }
 }
 
 /// Errors which can occur while executing an abstract command
 #[derive(Debug)]
-pub(crate) enum AbstractCommandErrorKind {
+pub enum AbstractCommandErrorKind {
     None = 0,
     Busy = 1,
     NotSupported = 2,
@@ -116,6 +119,10 @@ pub struct RiscvCommunicationInterfaceState {
     nscratch: u8,
 
     supports_autoexec: bool,
+
+    /// Whether CSRs can be accessed using abstract commands,
+    /// `None` until the first CSR access.
+    supports_abstract_csr_access: Option<bool>,
 }
 
 /// Timeout for RISCV operations.
@@ -134,6 +141,8 @@ impl RiscvCommunicationInterfaceState {
             nscratch: 0,
 
             supports_autoexec: false,
+
+            supports_abstract_csr_access: None,
         }
     }
 }
@@ -629,6 +638,131 @@ impl<'probe> RiscvCommunicationInterface {
         Ok(())
     }
 
+    /// Reads a CSR, using an abstract command if the debug module supports it,
+    /// and the program buffer otherwise.
+    pub(crate) fn read_csr(&mut self, address: u16) -> Result<u32, RiscvError> {
+        if self.state.supports_abstract_csr_access != Some(false) {
+            match self.abstract_cmd_register_read(CoreRegisterAddress(address)) {
+                Ok(value) => {
+                    self.state.supports_abstract_csr_access = Some(true);
+                    return Ok(value);
+                }
+                Err(RiscvError::AbstractCommand(AbstractCommandErrorKind::NotSupported)) => {
+                    log::debug!(
+                        "Abstract access to CSR {:#05x} not supported, using the program buffer",
+                        address
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let result = self.read_csr_progbuf(address);
+
+        if result.is_ok() && self.state.supports_abstract_csr_access.is_none() {
+            self.state.supports_abstract_csr_access = Some(false);
+        }
+
+        result
+    }
+
+    /// Writes a CSR, using an abstract command if the debug module supports it,
+    /// and the program buffer otherwise.
+    pub(crate) fn write_csr(&mut self, address: u16, value: u32) -> Result<(), RiscvError> {
+        if self.state.supports_abstract_csr_access != Some(false) {
+            match self.abstract_cmd_register_write(CoreRegisterAddress(address), value) {
+                Ok(()) => {
+                    self.state.supports_abstract_csr_access = Some(true);
+                    return Ok(());
+                }
+                Err(RiscvError::AbstractCommand(AbstractCommandErrorKind::NotSupported)) => {
+                    log::debug!(
+                        "Abstract access to CSR {:#05x} not supported, using the program buffer",
+                        address
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let result = self.write_csr_progbuf(address, value);
+
+        if result.is_ok() && self.state.supports_abstract_csr_access.is_none() {
+            self.state.supports_abstract_csr_access = Some(false);
+        }
+
+        result
+    }
+
+    fn read_csr_progbuf(&mut self, address: u16) -> Result<u32, RiscvError> {
+        let s0 = self.abstract_cmd_register_read(&register::S0)?;
+
+        // We need to perform the csrr instruction, which reads a CSR.
+        // This is a pseudo instruction, which actually is encoded as a
+        // csrrs instruction, with the rs1 register being x0,
+        // so no bits are changed in the CSR, but the CSR is read into rd, i.e. s0.
+        //
+        // csrrs,
+        // with rd  = s0
+        //      rs1 = x0
+        //      csr = address
+
+        let csrrs_cmd = assembly::csrrs(0, 8, address);
+
+        self.setup_program_buffer(&[csrrs_cmd, assembly::EBREAK])?;
+
+        // command: postexec
+        let mut postexec_cmd = AccessRegisterCommand(0);
+        postexec_cmd.set_postexec(true);
+
+        // An illegal CSR causes an exception, which is reported in cmderr.
+        // s0 has to be restored in that case as well.
+        let result = self
+            .execute_abstract_command(postexec_cmd.0)
+            .and_then(|_| self.abstract_cmd_register_read(&register::S0));
+
+        // restore original value in s0
+        self.abstract_cmd_register_write(&register::S0, s0)?;
+
+        result
+    }
+
+    fn write_csr_progbuf(&mut self, address: u16, value: u32) -> Result<(), RiscvError> {
+        // Backup register s0
+        let s0 = self.abstract_cmd_register_read(&register::S0)?;
+
+        // We need to perform the csrw instruction, which writes a CSR.
+        // This is a pseudo instruction, which actually is encoded as a
+        // csrrw instruction, with the destination register being x0,
+        // so the read is ignored.
+        //
+        // csrrw,
+        // with rd  = x0
+        //      rs1 = s0
+        //      csr = address
+
+        // Write value into s0
+        self.abstract_cmd_register_write(&register::S0, value)?;
+
+        let csrrw_cmd = assembly::csrrw(8, 0, address);
+
+        // write progbuf0: csrr xxxxxx s0, (address) // lookup correct command
+        let result = self
+            .setup_program_buffer(&[csrrw_cmd, assembly::EBREAK])
+            .and_then(|_| {
+                // command: postexec
+                let mut postexec_cmd = AccessRegisterCommand(0);
+                postexec_cmd.set_postexec(true);
+
+                self.execute_abstract_command(postexec_cmd.0)
+            });
+
+        // restore original value in s0
+        self.abstract_cmd_register_write(&register::S0, s0)?;
+
+        result
+    }
+
     pub fn close(self) -> Probe {
         Probe::from_attached_probe(self.probe.into_probe())
     }
@@ -646,20 +780,28 @@ impl<'a> AsMut<dyn DebugProbe + 'a> for RiscvCommunicationInterface {
     }
 }
 
+/// The memory accesses use the 32 bit registers of the hart, so only 32 bit addresses are supported.
+fn address_32(address: u64) -> Result<u32, RiscvError> {
+    u32::try_from(address).map_err(|_| RiscvError::AddressTooLarge(address))
+}
+
 impl MemoryInterface for RiscvCommunicationInterface {
-    fn read_word_32(&mut self, address: u32) -> Result<u32, crate::Error> {
+    fn read_word_32(&mut self, address: u64) -> Result<u32, crate::Error> {
+        let address = address_32(address)?;
         let result = self.perform_memory_read(address, RiscvBusAccess::A32)?;
 
         Ok(result)
     }
 
-    fn read_word_8(&mut self, address: u32) -> Result<u8, crate::Error> {
+    fn read_word_8(&mut self, address: u64) -> Result<u8, crate::Error> {
+        let address = address_32(address)?;
         let value = self.perform_memory_read(address, RiscvBusAccess::A8)?;
 
         Ok((value & 0xff) as u8)
     }
 
-    fn read_32(&mut self, address: u32, data: &mut [u32]) -> Result<(), crate::Error> {
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), crate::Error> {
+        let address = address_32(address)?;
         //  lb s1, 0(s0)
 
         // Backup registers s0 and s1
@@ -729,7 +871,8 @@ impl MemoryInterface for RiscvCommunicationInterface {
         Ok(())
     }
 
-    fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), crate::Error> {
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), crate::Error> {
+        let address = address_32(address)?;
         // Backup registers s0 and s1
         let s0 = self.abstract_cmd_register_read(&register::S0)?;
         let s1 = self.abstract_cmd_register_read(&register::S1)?;
@@ -797,19 +940,22 @@ impl MemoryInterface for RiscvCommunicationInterface {
         Ok(())
     }
 
-    fn write_word_32(&mut self, address: u32, data: u32) -> Result<(), crate::Error> {
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), crate::Error> {
+        let address = address_32(address)?;
         self.perform_memory_write(address, RiscvBusAccess::A32, data)?;
 
         Ok(())
     }
 
-    fn write_word_8(&mut self, address: u32, data: u8) -> Result<(), crate::Error> {
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), crate::Error> {
+        let address = address_32(address)?;
         self.perform_memory_write(address, RiscvBusAccess::A8, data as u32)?;
 
         Ok(())
     }
 
-    fn write_32(&mut self, address: u32, data: &[u32]) -> Result<(), crate::Error> {
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), crate::Error> {
+        let address = address_32(address)?;
         let s0 = self.abstract_cmd_register_read(&register::S0)?;
         let s1 = self.abstract_cmd_register_read(&register::S1)?;
 
@@ -861,7 +1007,8 @@ impl MemoryInterface for RiscvCommunicationInterface {
         Ok(())
     }
 
-    fn write_8(&mut self, address: u32, data: &[u8]) -> Result<(), crate::Error> {
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), crate::Error> {
+        let address = address_32(address)?;
         //fn perform_memory_write(
         //    &mut self,
         //    address: u32,
@@ -1115,7 +1262,7 @@ data_register! { Sbdata3, 0x3f, "sbdata3" }
 /// Possible return values in the op field of
 /// the dmi register.
 #[derive(Debug)]
-pub(crate) enum DmiOperationStatus {
+pub enum DmiOperationStatus {
     Ok = 0,
     Reserved = 1,
     OperationFailed = 2,
@@ -0,0 +1,498 @@
+//! Decoding of RV32IMC instructions, for the disassembly of RISC-V code.
+//!
+//! Compressed instructions are expanded to the instructions they are equivalent to,
+//! and printed like these. Floating point and atomic instructions are not decoded.
+
+/// The ABI names of the integer registers.
+const REGISTERS: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Decodes the instruction at the start of `bytes`, which is located at `address`.
+///
+/// Returns the size of the instruction, its mnemonic and its operands, or `None` if the bytes
+/// are not a supported instruction.
+pub(crate) fn decode(address: u32, bytes: &[u8]) -> Option<(usize, &'static str, String)> {
+    let low = u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]);
+
+    if low & 0b11 != 0b11 {
+        let (mnemonic, operands) = decode_32(address, expand(low)?)?;
+        return Some((2, mnemonic, operands));
+    }
+
+    // Instructions longer than 32 bits.
+    if low & 0b1_1100 == 0b1_1100 {
+        return None;
+    }
+
+    let word = u32::from_le_bytes([low as u8, (low >> 8) as u8, *bytes.get(2)?, *bytes.get(3)?]);
+    let (mnemonic, operands) = decode_32(address, word)?;
+    Some((4, mnemonic, operands))
+}
+
+fn register(number: u32) -> &'static str {
+    REGISTERS[number as usize & 0b1_1111]
+}
+
+/// Sign extends the lowest `bits` bits of `value`.
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+/// Returns `bits` bits of `value`, starting at bit `start`.
+fn bits(value: u32, start: u32, bits: u32) -> u32 {
+    (value >> start) & ((1 << bits) - 1)
+}
+
+fn decode_32(address: u32, word: u32) -> Option<(&'static str, String)> {
+    let opcode = bits(word, 0, 7);
+    let rd = bits(word, 7, 5);
+    let funct3 = bits(word, 12, 3);
+    let rs1 = bits(word, 15, 5);
+    let rs2 = bits(word, 20, 5);
+    let funct7 = bits(word, 25, 7);
+
+    let imm_i = sign_extend(word >> 20, 12);
+    let imm_s = sign_extend(funct7 << 5 | rd, 12);
+    let imm_b = sign_extend(
+        bits(word, 31, 1) << 12
+            | bits(word, 7, 1) << 11
+            | bits(word, 25, 6) << 5
+            | bits(word, 8, 4) << 1,
+        13,
+    );
+    let imm_j = sign_extend(
+        bits(word, 31, 1) << 20
+            | bits(word, 12, 8) << 12
+            | bits(word, 20, 1) << 11
+            | bits(word, 21, 10) << 1,
+        21,
+    );
+    let target = |offset: i32| format!("{:#x}", address.wrapping_add(offset as u32));
+
+    let instruction = match opcode {
+        0b011_0111 => ("lui", format!("{}, {:#x}", register(rd), word >> 12)),
+        0b001_0111 => ("auipc", format!("{}, {:#x}", register(rd), word >> 12)),
+        0b110_1111 => match rd {
+            0 => ("j", target(imm_j)),
+            1 => ("jal", target(imm_j)),
+            _ => ("jal", format!("{}, {}", register(rd), target(imm_j))),
+        },
+        0b110_0111 if funct3 == 0 => match (rd, rs1, imm_i) {
+            (0, 1, 0) => ("ret", String::new()),
+            (0, _, 0) => ("jr", register(rs1).to_owned()),
+            _ => (
+                "jalr",
+                format!("{}, {}({})", register(rd), imm_i, register(rs1)),
+            ),
+        },
+        0b110_0011 => {
+            let mnemonic = match funct3 {
+                0b000 => "beq",
+                0b001 => "bne",
+                0b100 => "blt",
+                0b101 => "bge",
+                0b110 => "bltu",
+                0b111 => "bgeu",
+                _ => return None,
+            };
+            (
+                mnemonic,
+                format!("{}, {}, {}", register(rs1), register(rs2), target(imm_b)),
+            )
+        }
+        0b000_0011 => {
+            let mnemonic = match funct3 {
+                0b000 => "lb",
+                0b001 => "lh",
+                0b010 => "lw",
+                0b100 => "lbu",
+                0b101 => "lhu",
+                _ => return None,
+            };
+            (
+                mnemonic,
+                format!("{}, {}({})", register(rd), imm_i, register(rs1)),
+            )
+        }
+        0b010_0011 => {
+            let mnemonic = match funct3 {
+                0b000 => "sb",
+                0b001 => "sh",
+                0b010 => "sw",
+                _ => return None,
+            };
+            (
+                mnemonic,
+                format!("{}, {}({})", register(rs2), imm_s, register(rs1)),
+            )
+        }
+        0b001_0011 => {
+            let mnemonic = match (funct3, funct7) {
+                (0b000, _) if rd == 0 && rs1 == 0 && imm_i == 0 => {
+                    return Some(("nop", String::new()))
+                }
+                (0b000, _) if rs1 == 0 => {
+                    return Some(("li", format!("{}, {}", register(rd), imm_i)))
+                }
+                (0b000, _) if imm_i == 0 => {
+                    return Some(("mv", format!("{}, {}", register(rd), register(rs1))))
+                }
+                (0b000, _) => "addi",
+                (0b010, _) => "slti",
+                (0b011, _) => "sltiu",
+                (0b100, _) => "xori",
+                (0b110, _) => "ori",
+                (0b111, _) => "andi",
+                (0b001, 0) => "slli",
+                (0b101, 0) => "srli",
+                (0b101, 0b010_0000) => "srai",
+                _ => return None,
+            };
+            // The shifts only use the lowest 5 bits of the immediate.
+            let immediate = match funct3 {
+                0b001 | 0b101 => rs2 as i32,
+                _ => imm_i,
+            };
+            (
+                mnemonic,
+                format!("{}, {}, {}", register(rd), register(rs1), immediate),
+            )
+        }
+        0b011_0011 => {
+            let mnemonic = match (funct7, funct3) {
+                (0, 0b000) => "add",
+                (0, 0b001) => "sll",
+                (0, 0b010) => "slt",
+                (0, 0b011) => "sltu",
+                (0, 0b100) => "xor",
+                (0, 0b101) => "srl",
+                (0, 0b110) => "or",
+                (0, 0b111) => "and",
+                (0b010_0000, 0b000) => "sub",
+                (0b010_0000, 0b101) => "sra",
+                (1, 0b000) => "mul",
+                (1, 0b001) => "mulh",
+                (1, 0b010) => "mulhsu",
+                (1, 0b011) => "mulhu",
+                (1, 0b100) => "div",
+                (1, 0b101) => "divu",
+                (1, 0b110) => "rem",
+                (1, 0b111) => "remu",
+                _ => return None,
+            };
+            (
+                mnemonic,
+                format!("{}, {}, {}", register(rd), register(rs1), register(rs2)),
+            )
+        }
+        0b000_1111 => match funct3 {
+            0b000 => ("fence", String::new()),
+            0b001 => ("fence.i", String::new()),
+            _ => return None,
+        },
+        0b111_0011 => {
+            let csr = word >> 20;
+            let mnemonic = match funct3 {
+                0b000 => {
+                    return match word {
+                        0x0000_0073 => Some(("ecall", String::new())),
+                        0x0010_0073 => Some(("ebreak", String::new())),
+                        0x3020_0073 => Some(("mret", String::new())),
+                        0x7b20_0073 => Some(("dret", String::new())),
+                        0x1050_0073 => Some(("wfi", String::new())),
+                        _ => None,
+                    }
+                }
+                0b001 => "csrrw",
+                0b010 => "csrrs",
+                0b011 => "csrrc",
+                0b101 => "csrrwi",
+                0b110 => "csrrsi",
+                0b111 => "csrrci",
+                _ => return None,
+            };
+            // The immediate variants use the register field as an unsigned immediate.
+            let source = match funct3 {
+                0b101..=0b111 => rs1.to_string(),
+                _ => register(rs1).to_owned(),
+            };
+            (
+                mnemonic,
+                format!("{}, {:#x}, {}", register(rd), csr, source),
+            )
+        }
+        _ => return None,
+    };
+
+    Some(instruction)
+}
+
+/// Encodes an I-type instruction.
+fn i_type(opcode: u32, funct3: u32, rd: u32, rs1: u32, immediate: i32) -> u32 {
+    (immediate as u32 & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+/// Encodes an S-type instruction.
+fn s_type(opcode: u32, funct3: u32, rs1: u32, rs2: u32, immediate: i32) -> u32 {
+    let immediate = immediate as u32;
+    bits(immediate, 5, 7) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | funct3 << 12
+        | bits(immediate, 0, 5) << 7
+        | opcode
+}
+
+/// Encodes an R-type instruction.
+fn r_type(opcode: u32, funct3: u32, funct7: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+/// Encodes a `jal` instruction.
+fn jal(rd: u32, offset: i32) -> u32 {
+    let offset = offset as u32;
+    bits(offset, 20, 1) << 31
+        | bits(offset, 1, 10) << 21
+        | bits(offset, 11, 1) << 20
+        | bits(offset, 12, 8) << 12
+        | rd << 7
+        | 0b110_1111
+}
+
+/// Encodes a conditional branch instruction.
+fn branch(funct3: u32, rs1: u32, rs2: u32, offset: i32) -> u32 {
+    let offset = offset as u32;
+    bits(offset, 12, 1) << 31
+        | bits(offset, 5, 6) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | funct3 << 12
+        | bits(offset, 1, 4) << 8
+        | bits(offset, 11, 1) << 7
+        | 0b110_0011
+}
+
+/// Expands a compressed instruction to the 32-bit instruction it is equivalent to.
+fn expand(instruction: u16) -> Option<u32> {
+    const OP_IMM: u32 = 0b001_0011;
+    const OP: u32 = 0b011_0011;
+    const LOAD: u32 = 0b000_0011;
+    const STORE: u32 = 0b010_0011;
+    const SP: u32 = 2;
+
+    let c = instruction as u32;
+    let funct3 = bits(c, 13, 3);
+    // The full register fields, and the fields of the registers `x8` to `x15`.
+    let rd = bits(c, 7, 5);
+    let rs2 = bits(c, 2, 5);
+    let rd_short = 8 + bits(c, 2, 3);
+    let rs1_short = 8 + bits(c, 7, 3);
+    let imm6 = sign_extend(bits(c, 12, 1) << 5 | bits(c, 2, 5), 6);
+    let uimm_word = bits(c, 5, 1) << 6 | bits(c, 10, 3) << 3 | bits(c, 6, 1) << 2;
+
+    let expanded = match (c & 0b11, funct3) {
+        // All zero bits are defined to be an illegal instruction.
+        (0b00, 0b000) if c == 0 => return None,
+        (0b00, 0b000) => {
+            let immediate =
+                bits(c, 7, 4) << 6 | bits(c, 11, 2) << 4 | bits(c, 5, 1) << 3 | bits(c, 6, 1) << 2;
+            if immediate == 0 {
+                return None;
+            }
+            i_type(OP_IMM, 0b000, rd_short, SP, immediate as i32)
+        }
+        (0b00, 0b010) => i_type(LOAD, 0b010, rd_short, rs1_short, uimm_word as i32),
+        (0b00, 0b110) => s_type(STORE, 0b010, rs1_short, rd_short, uimm_word as i32),
+        (0b01, 0b000) => i_type(OP_IMM, 0b000, rd, rd, imm6),
+        (0b01, 0b001) => jal(1, jump_offset(c)),
+        (0b01, 0b010) => i_type(OP_IMM, 0b000, rd, 0, imm6),
+        (0b01, 0b011) if rd == SP => {
+            let immediate = sign_extend(
+                bits(c, 12, 1) << 9
+                    | bits(c, 3, 2) << 7
+                    | bits(c, 5, 1) << 6
+                    | bits(c, 2, 1) << 5
+                    | bits(c, 6, 1) << 4,
+                10,
+            );
+            if immediate == 0 {
+                return None;
+            }
+            i_type(OP_IMM, 0b000, SP, SP, immediate)
+        }
+        (0b01, 0b011) => {
+            if imm6 == 0 {
+                return None;
+            }
+            (imm6 as u32) << 12 | rd << 7 | 0b011_0111
+        }
+        (0b01, 0b100) => match bits(c, 10, 2) {
+            // Shift amounts above 31 are reserved for RV32.
+            0b00 | 0b01 if bits(c, 12, 1) != 0 => return None,
+            0b00 => i_type(OP_IMM, 0b101, rs1_short, rs1_short, rs2 as i32),
+            0b01 => i_type(
+                OP_IMM,
+                0b101,
+                rs1_short,
+                rs1_short,
+                0b0100_0000_0000 | rs2 as i32,
+            ),
+            0b10 => i_type(OP_IMM, 0b111, rs1_short, rs1_short, imm6),
+            _ => {
+                let (funct3, funct7) = match (bits(c, 12, 1), bits(c, 5, 2)) {
+                    (0, 0b00) => (0b000, 0b010_0000),
+                    (0, 0b01) => (0b100, 0),
+                    (0, 0b10) => (0b110, 0),
+                    (0, 0b11) => (0b111, 0),
+                    _ => return None,
+                };
+                r_type(OP, funct3, funct7, rs1_short, rs1_short, rd_short)
+            }
+        },
+        (0b01, 0b101) => jal(0, jump_offset(c)),
+        (0b01, 0b110) => branch(0b000, rs1_short, 0, branch_offset(c)),
+        (0b01, 0b111) => branch(0b001, rs1_short, 0, branch_offset(c)),
+        (0b10, 0b000) => {
+            if bits(c, 12, 1) != 0 {
+                return None;
+            }
+            i_type(OP_IMM, 0b001, rd, rd, rs2 as i32)
+        }
+        (0b10, 0b010) => {
+            if rd == 0 {
+                return None;
+            }
+            let offset = bits(c, 2, 2) << 6 | bits(c, 12, 1) << 5 | bits(c, 4, 3) << 2;
+            i_type(LOAD, 0b010, rd, SP, offset as i32)
+        }
+        (0b10, 0b100) => match (bits(c, 12, 1), rd, rs2) {
+            (0, 0, 0) => return None,
+            (0, _, 0) => i_type(0b110_0111, 0b000, 0, rd, 0),
+            (0, _, _) => r_type(OP, 0b000, 0, rd, 0, rs2),
+            (_, 0, 0) => 0x0010_0073,
+            (_, _, 0) => i_type(0b110_0111, 0b000, 1, rd, 0),
+            (_, _, _) => r_type(OP, 0b000, 0, rd, rd, rs2),
+        },
+        (0b10, 0b110) => {
+            let offset = bits(c, 7, 2) << 6 | bits(c, 9, 4) << 2;
+            s_type(STORE, 0b010, SP, rs2, offset as i32)
+        }
+        _ => return None,
+    };
+
+    Some(expanded)
+}
+
+/// Returns the offset of `c.j` and `c.jal`.
+fn jump_offset(c: u32) -> i32 {
+    sign_extend(
+        bits(c, 12, 1) << 11
+            | bits(c, 8, 1) << 10
+            | bits(c, 9, 2) << 8
+            | bits(c, 6, 1) << 7
+            | bits(c, 7, 1) << 6
+            | bits(c, 2, 1) << 5
+            | bits(c, 11, 1) << 4
+            | bits(c, 3, 3) << 1,
+        12,
+    )
+}
+
+/// Returns the offset of `c.beqz` and `c.bnez`.
+fn branch_offset(c: u32) -> i32 {
+    sign_extend(
+        bits(c, 12, 1) << 8
+            | bits(c, 5, 2) << 6
+            | bits(c, 2, 1) << 5
+            | bits(c, 10, 2) << 3
+            | bits(c, 3, 2) << 1,
+        9,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn disassemble(address: u32, bytes: &[u8]) -> (usize, String) {
+        let (size, mnemonic, operands) = decode(address, bytes).unwrap();
+        (
+            size,
+            format!("{} {}", mnemonic, operands).trim_end().to_owned(),
+        )
+    }
+
+    #[test]
+    fn instructions_are_decoded() {
+        // addi sp, sp, -16
+        assert_eq!(
+            disassemble(0, &[0x13, 0x01, 0x01, 0xff]),
+            (4, "addi sp, sp, -16".to_owned())
+        );
+        // sw ra, 12(sp)
+        assert_eq!(
+            disassemble(0, &[0x23, 0x26, 0x11, 0x00]),
+            (4, "sw ra, 12(sp)".to_owned())
+        );
+        // jal ra, -8
+        assert_eq!(
+            disassemble(0x100, &[0xef, 0xf0, 0x9f, 0xff]),
+            (4, "jal 0xf8".to_owned())
+        );
+        assert_eq!(
+            disassemble(0, &[0x73, 0x00, 0x10, 0x00]),
+            (4, "ebreak".to_owned())
+        );
+        assert_eq!(
+            disassemble(0, &[0xf3, 0x27, 0x10, 0x34]),
+            (4, "csrrs a5, 0x341, zero".to_owned())
+        );
+    }
+
+    #[test]
+    fn compressed_instructions_are_expanded() {
+        // c.addi sp, -16
+        assert_eq!(
+            disassemble(0, &[0x41, 0x11]),
+            (2, "addi sp, sp, -16".to_owned())
+        );
+        // c.swsp ra, 12(sp)
+        assert_eq!(
+            disassemble(0, &[0x06, 0xc6]),
+            (2, "sw ra, 12(sp)".to_owned())
+        );
+        // c.lw a0, 4(a1)
+        assert_eq!(
+            disassemble(0, &[0xc8, 0x41]),
+            (2, "lw a0, 4(a1)".to_owned())
+        );
+        // c.j -4
+        assert_eq!(disassemble(0x10, &[0xf5, 0xbf]), (2, "j 0xc".to_owned()));
+        // c.bnez a0, 8
+        assert_eq!(
+            disassemble(0x10, &[0x01, 0xe5]),
+            (2, "bne a0, zero, 0x18".to_owned())
+        );
+        assert_eq!(disassemble(0, &[0x82, 0x80]), (2, "ret".to_owned()));
+        assert_eq!(disassemble(0, &[0x02, 0x90]), (2, "ebreak".to_owned()));
+        // c.mv a0, a0
+        assert_eq!(
+            disassemble(0, &[0x2a, 0x85]),
+            (2, "add a0, zero, a0".to_owned())
+        );
+    }
+
+    #[test]
+    fn data_is_not_decoded() {
+        // The illegal all zero instruction, and an unused major opcode.
+        assert_eq!(decode(0, &[0x00, 0x00]), None);
+        assert_eq!(decode(0, &[0x7f, 0x00, 0x00, 0x00]), None);
+        // A 32-bit instruction cut off at the end of the data.
+        assert_eq!(decode(0, &[0x13, 0x01]), None);
+        assert_eq!(decode(0, &[0x13]), None);
+    }
+}
@@ -6,11 +6,10 @@ use crate::core::Architecture;
 use crate::CoreInterface;
 use anyhow::{anyhow, Result};
 use communication_interface::{
-    AbstractCommandErrorKind, AccessRegisterCommand, DebugRegister, RiscvCommunicationInterface,
-    RiscvError,
+    AbstractCommandErrorKind, DebugRegister, RiscvCommunicationInterface, RiscvError,
 };
 
-use crate::core::{CoreInformation, RegisterFile};
+use crate::core::{CoreInformation, RegisterFile, WatchKind};
 use crate::{CoreRegisterAddress, CoreStatus, Error, HaltReason, MemoryInterface};
 use bitfield::bitfield;
 use register::RISCV_REGISTERS;
@@ -19,10 +18,40 @@ use std::time::{Duration, Instant};
 #[macro_use]
 mod register;
 
+pub(crate) use register::dwarf_register;
+
 pub(crate) mod assembly;
+#[cfg(feature = "disassembly")]
+pub(crate) mod disassembly;
 
 pub mod communication_interface;
 
+/// Sets or clears the request to halt the harts when they come out of reset.
+///
+/// Returns `false` if the debug module does not support `resethaltreq`, in which case nothing is changed.
+pub(crate) fn set_reset_halt_request(
+    interface: &mut RiscvCommunicationInterface,
+    enabled: bool,
+) -> Result<bool, crate::Error> {
+    let dmstatus: Dmstatus = interface.read_dm_register()?;
+
+    if !dmstatus.hasresethaltreq() {
+        return Ok(false);
+    }
+
+    let mut dmcontrol = Dmcontrol(0);
+    dmcontrol.set_dmactive(true);
+    if enabled {
+        dmcontrol.set_resethaltreq(true);
+    } else {
+        dmcontrol.set_clrresethaltreq(true);
+    }
+
+    interface.write_dm_register(dmcontrol)?;
+
+    Ok(true)
+}
+
 pub struct Riscv32<'probe> {
     interface: &'probe mut RiscvCommunicationInterface,
 }
@@ -32,78 +61,20 @@ impl<'probe> Riscv32<'probe> {
         Self { interface }
     }
 
-    fn read_csr(&mut self, address: u16) -> Result<u32, RiscvError> {
-        let s0 = self.interface.abstract_cmd_register_read(&register::S0)?;
-
-        // We need to perform the csrr instruction, which reads a CSR.
-        // This is a pseudo instruction, which actually is encoded as a
-        // csrrs instruction, with the rs1 register being x0,
-        // so no bits are changed in the CSR, but the CSR is read into rd, i.e. s0.
-        //
-        // csrrs,
-        // with rd  = s0
-        //      rs1 = x0
-        //      csr = address
-
-        let mut csrrs_cmd: u32 = 0b_00000_010_01000_1110011;
-        csrrs_cmd |= ((address as u32) & 0xfff) << 20;
-
-        self.interface
-            .setup_program_buffer(&[csrrs_cmd, assembly::EBREAK])?;
-
-        // command: postexec
-        let mut postexec_cmd = AccessRegisterCommand(0);
-        postexec_cmd.set_postexec(true);
-
-        self.interface.execute_abstract_command(postexec_cmd.0)?;
-
-        // read the s0 value
-        let reg_value = self.interface.abstract_cmd_register_read(&register::S0)?;
-
-        // restore original value in s0
-        self.interface
-            .abstract_cmd_register_write(&register::S0, s0)?;
-
-        Ok(reg_value)
+    /// Reads the CSR with the given 12 bit address.
+    ///
+    /// The CSR is read with an abstract command if the debug module supports it,
+    /// otherwise a `csrr` instruction is executed from the program buffer. Reading
+    /// a CSR which does not exist on the hart returns an error.
+    pub fn read_csr(&mut self, address: u16) -> Result<u32, crate::Error> {
+        Ok(self.interface.read_csr(address)?)
     }
 
-    fn write_csr(&mut self, address: u16, value: u32) -> Result<(), RiscvError> {
-        // Backup register s0
-        let s0 = self.interface.abstract_cmd_register_read(&register::S0)?;
-
-        // We need to perform the csrw instruction, which writes a CSR.
-        // This is a pseudo instruction, which actually is encoded as a
-        // csrrw instruction, with the destination register being x0,
-        // so the read is ignored.
-        //
-        // csrrw,
-        // with rd  = x0
-        //      rs1 = s0
-        //      csr = address
-
-        // Write value into s0
-        self.interface
-            .abstract_cmd_register_write(&register::S0, value)?;
-
-        let mut csrrw_cmd: u32 = 0b_01000_001_00000_1110011;
-        csrrw_cmd |= ((address as u32) & 0xfff) << 20;
-
-        // write progbuf0: csrr xxxxxx s0, (address) // lookup correct command
-        self.interface
-            .setup_program_buffer(&[csrrw_cmd, assembly::EBREAK])?;
-
-        // command: postexec
-        let mut postexec_cmd = AccessRegisterCommand(0);
-        postexec_cmd.set_postexec(true);
-
-        self.interface.execute_abstract_command(postexec_cmd.0)?;
-
-        // command: transfer, regno = 0x1008
-        // restore original value in s0
-        self.interface
-            .abstract_cmd_register_write(&register::S0, s0)?;
-
-        Ok(())
+    /// Writes the CSR with the given 12 bit address.
+    ///
+    /// See [`Riscv32::read_csr`] for how the CSR is accessed.
+    pub fn write_csr(&mut self, address: u16, value: u32) -> Result<(), crate::Error> {
+        Ok(self.interface.write_csr(address, value)?)
     }
 }
 
@@ -308,12 +279,47 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
         Ok(CoreInformation { pc })
     }
 
+    fn reset_and_halt_at_reset_vector(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<crate::core::CoreInformation, crate::Error> {
+        if !set_reset_halt_request(self.interface, true)? {
+            log::debug!("The debug module does not support resethaltreq, using haltreq instead");
+            return self.reset_and_halt(timeout);
+        }
+
+        let halted = self
+            .reset()
+            .and_then(|_| self.wait_for_core_halted(timeout));
+
+        // The halt-on-reset request is sticky, so it has to be cleared in any case.
+        set_reset_halt_request(self.interface, false)?;
+
+        if let Err(error) = halted {
+            log::warn!("The core did not halt at the reset vector: {}", error);
+            return Err(error);
+        }
+
+        let pc = self.read_core_reg(CoreRegisterAddress(0x7b1))?;
+
+        Ok(CoreInformation { pc })
+    }
+
     fn step(&mut self) -> Result<crate::core::CoreInformation, crate::Error> {
+        self.step_with_interrupts(false)
+    }
+
+    fn step_with_interrupts(
+        &mut self,
+        interrupts: bool,
+    ) -> Result<crate::core::CoreInformation, crate::Error> {
         let mut dcsr = Dcsr(self.read_core_reg(CoreRegisterAddress(0x7b0))?);
+        let stepie = dcsr.stepie();
 
         dcsr.set_step(true);
+        dcsr.set_stepie(interrupts);
 
-        self.write_csr(0x7b0, dcsr.0)?;
+        self.interface.write_csr(0x7b0, dcsr.0)?;
 
         self.run()?;
 
@@ -325,8 +331,9 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
         let mut dcsr = Dcsr(self.read_core_reg(CoreRegisterAddress(0x7b0))?);
 
         dcsr.set_step(false);
+        dcsr.set_stepie(stepie);
 
-        self.write_csr(0x7b0, dcsr.0)?;
+        self.interface.write_csr(0x7b0, dcsr.0)?;
 
         Ok(CoreInformation { pc })
     }
@@ -346,7 +353,7 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
             let value = self.interface.abstract_cmd_register_read(address)?;
             Ok(value)
         } else {
-            let reg_value = self.read_csr(address.0)?;
+            let reg_value = self.interface.read_csr(address.0)?;
             Ok(reg_value)
         }
     }
@@ -355,7 +362,7 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
         if address.0 >= 0x1000 && address.0 <= 0x101f {
             self.interface.abstract_cmd_register_write(address, value)?;
         } else {
-            self.write_csr(address.0, value)?;
+            self.interface.write_csr(address.0, value)?;
         }
         Ok(())
     }
@@ -374,20 +381,20 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
         // These steps follow the debug specification 0.13, section 5.1 Enumeration
         loop {
             log::debug!("Trying tselect={}", tselect_index);
-            if let Err(e) = self.write_csr(tselect, tselect_index) {
+            if let Err(e) = self.interface.write_csr(tselect, tselect_index) {
                 match e {
                     RiscvError::AbstractCommand(AbstractCommandErrorKind::Exception) => break,
                     other_error => return Err(other_error.into()),
                 }
             }
 
-            let readback = self.read_csr(tselect)?;
+            let readback = self.interface.read_csr(tselect)?;
 
             if readback != tselect_index {
                 break;
             }
 
-            match self.read_csr(tinfo) {
+            match self.interface.read_csr(tinfo) {
                 Ok(tinfo_val) => {
                     if tinfo_val & 0xffff == 1 {
                         // Trigger doesn't exist, break the loop
@@ -402,7 +409,7 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
                 }
                 Err(RiscvError::AbstractCommand(AbstractCommandErrorKind::Exception)) => {
                     // An exception means we have to read tdata1 to discover the type
-                    let tdata_val = self.read_csr(tdata1)?;
+                    let tdata_val = self.interface.read_csr(tdata1)?;
 
                     // TODO: Proper handle xlen
                     let xlen = 32;
@@ -441,11 +448,11 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
         let tdata1 = 0x7a1;
         let tdata2 = 0x7a2;
 
-        self.write_csr(tselect, bp_unit_index as u32)?;
+        self.interface.write_csr(tselect, bp_unit_index as u32)?;
 
         // verify the trigger has the correct type
 
-        let tdata_value = Mcontrol(self.read_csr(tdata1)?);
+        let tdata_value = Mcontrol(self.interface.read_csr(tdata1)?);
 
         // This should not happen
         let trigger_type = tdata_value.type_();
@@ -467,8 +474,8 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
 
         instruction_breakpoint.set_dmode(true);
 
-        self.write_csr(tdata1, instruction_breakpoint.0)?;
-        self.write_csr(tdata2, addr)?;
+        self.interface.write_csr(tdata1, instruction_breakpoint.0)?;
+        self.interface.write_csr(tdata2, addr)?;
 
         Ok(())
     }
@@ -478,13 +485,23 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
         let tdata1 = 0x7a1;
         let tdata2 = 0x7a2;
 
-        self.write_csr(tselect, unit_index as u32)?;
-        self.write_csr(tdata1, 0)?;
-        self.write_csr(tdata2, 0)?;
+        self.interface.write_csr(tselect, unit_index as u32)?;
+        self.interface.write_csr(tdata1, 0)?;
+        self.interface.write_csr(tdata2, 0)?;
 
         Ok(())
     }
 
+    fn set_watchpoint(
+        &mut self,
+        unit_index: usize,
+        address: u32,
+        size: u32,
+        kind: WatchKind,
+    ) -> Result<bool, crate::Error> {
+        configure_watchpoint(self.interface, unit_index, address, size, kind)
+    }
+
     fn registers(&self) -> &'static RegisterFile {
         &RISCV_REGISTERS
     }
@@ -495,6 +512,18 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
         true
     }
 
+    fn enable_software_breakpoints(&mut self) -> Result<(), crate::Error> {
+        let mut dcsr = Dcsr(self.read_core_reg(CoreRegisterAddress(0x7b0))?);
+
+        if !dcsr.ebreakm() {
+            // Enter debug mode on an ebreak in machine mode, instead of raising an exception.
+            dcsr.set_ebreakm(true);
+            self.interface.write_csr(0x7b0, dcsr.0)?;
+        }
+
+        Ok(())
+    }
+
     fn architecture(&self) -> Architecture {
         Architecture::Riscv
     }
@@ -536,29 +565,120 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
     }
 }
 
+/// Access to the control and status registers of the hart.
+pub(crate) trait CsrAccess {
+    fn read_csr(&mut self, address: u16) -> Result<u32, RiscvError>;
+    fn write_csr(&mut self, address: u16, value: u32) -> Result<(), RiscvError>;
+}
+
+impl CsrAccess for RiscvCommunicationInterface {
+    fn read_csr(&mut self, address: u16) -> Result<u32, RiscvError> {
+        RiscvCommunicationInterface::read_csr(self, address)
+    }
+
+    fn write_csr(&mut self, address: u16, value: u32) -> Result<(), RiscvError> {
+        RiscvCommunicationInterface::write_csr(self, address, value)
+    }
+}
+
+/// Configures trigger `unit_index` as a watchpoint on `size` bytes at `address`.
+///
+/// Returns `false` if the trigger cannot be used as a watchpoint of the requested kind.
+fn configure_watchpoint(
+    interface: &mut impl CsrAccess,
+    unit_index: usize,
+    address: u32,
+    size: u32,
+    kind: WatchKind,
+) -> Result<bool, crate::Error> {
+    let tselect = 0x7a0;
+    let tdata1 = 0x7a1;
+    let tdata2 = 0x7a2;
+
+    let sizelo = match size {
+        1 => 1,
+        2 => 2,
+        4 => 3,
+        _ => {
+            return Err(anyhow!(
+                "Unsupported watchpoint size of {} bytes, only 1, 2 or 4 bytes are possible.",
+                size
+            )
+            .into())
+        }
+    };
+
+    interface.write_csr(tselect, unit_index as u32)?;
+
+    // Only address / data match triggers can be used as watchpoints.
+    let tdata_value = Mcontrol(interface.read_csr(tdata1)?);
+    if tdata_value.type_() != 0b10 {
+        return Ok(false);
+    }
+
+    let (load, store) = match kind {
+        WatchKind::Read => (true, false),
+        WatchKind::Write => (false, true),
+        WatchKind::ReadWrite => (true, true),
+    };
+
+    let mut watchpoint = Mcontrol(0);
+    watchpoint.set_type(0b10);
+    watchpoint.set_action(1);
+    watchpoint.set_match(0);
+    watchpoint.set_sizelo(sizelo);
+
+    watchpoint.set_load(load);
+    watchpoint.set_store(store);
+
+    watchpoint.set_dmode(true);
+
+    // Without any of the privilege mode bits set the trigger never
+    // matches, so it stays disabled until tdata2 holds the new address.
+    interface.write_csr(tdata1, watchpoint.0)?;
+
+    // The load and store bits are WARL, so if the trigger does not
+    // support matching on data accesses they read back as zero.
+    let readback = Mcontrol(interface.read_csr(tdata1)?);
+    if readback.load() != load || readback.store() != store {
+        interface.write_csr(tdata1, 0)?;
+        return Ok(false);
+    }
+
+    interface.write_csr(tdata2, address)?;
+
+    watchpoint.set_m(true);
+    watchpoint.set_s(true);
+    watchpoint.set_u(true);
+
+    interface.write_csr(tdata1, watchpoint.0)?;
+
+    Ok(true)
+}
+
 impl<'probe> MemoryInterface for Riscv32<'probe> {
-    fn read_word_32(&mut self, address: u32) -> Result<u32, Error> {
+    fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
         self.interface.read_word_32(address)
     }
-    fn read_word_8(&mut self, address: u32) -> Result<u8, Error> {
+    fn read_word_8(&mut self, address: u64) -> Result<u8, Error> {
         self.interface.read_word_8(address)
     }
-    fn read_32(&mut self, address: u32, data: &mut [u32]) -> Result<(), Error> {
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
         self.interface.read_32(address, data)
     }
-    fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), Error> {
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
         self.interface.read_8(address, data)
     }
-    fn write_word_32(&mut self, address: u32, data: u32) -> Result<(), Error> {
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), Error> {
         self.interface.write_word_32(address, data)
     }
-    fn write_word_8(&mut self, address: u32, data: u8) -> Result<(), Error> {
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), Error> {
         self.interface.write_word_8(address, data)
     }
-    fn write_32(&mut self, address: u32, data: &[u32]) -> Result<(), Error> {
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), Error> {
         self.interface.write_32(address, data)
     }
-    fn write_8(&mut self, address: u32, data: &[u8]) -> Result<(), Error> {
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
         self.interface.write_8(address, data)
     }
     fn flush(&mut self) -> Result<(), Error> {
@@ -771,3 +891,157 @@ bitfield! {
     store, set_store: 1;
     load, set_load: 0;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TSELECT: u16 = 0x7a0;
+    const TDATA1: u16 = 0x7a1;
+    const TDATA2: u16 = 0x7a2;
+
+    struct Trigger {
+        type_: u32,
+        data_match: bool,
+        tdata1: u32,
+        tdata2: u32,
+    }
+
+    impl Trigger {
+        fn new(type_: u32, data_match: bool) -> Self {
+            let mut tdata1 = Mcontrol(0);
+            tdata1.set_type(type_);
+
+            Self {
+                type_,
+                data_match,
+                tdata1: tdata1.0,
+                tdata2: 0,
+            }
+        }
+    }
+
+    /// The trigger CSRs of a hart, which records every CSR write.
+    struct MockTriggers {
+        tselect: usize,
+        triggers: Vec<Trigger>,
+        writes: Vec<(u16, u32)>,
+    }
+
+    impl MockTriggers {
+        fn new(triggers: Vec<Trigger>) -> Self {
+            Self {
+                tselect: 0,
+                triggers,
+                writes: Vec::new(),
+            }
+        }
+    }
+
+    impl CsrAccess for MockTriggers {
+        fn read_csr(&mut self, address: u16) -> Result<u32, RiscvError> {
+            let trigger = &self.triggers[self.tselect];
+
+            match address {
+                TSELECT => Ok(self.tselect as u32),
+                TDATA1 => Ok(trigger.tdata1),
+                TDATA2 => Ok(trigger.tdata2),
+                _ => panic!("Unexpected CSR read from {:#x}", address),
+            }
+        }
+
+        fn write_csr(&mut self, address: u16, value: u32) -> Result<(), RiscvError> {
+            self.writes.push((address, value));
+
+            let trigger = &mut self.triggers[self.tselect];
+
+            match address {
+                TSELECT => self.tselect = value as usize,
+                TDATA1 => {
+                    // The type is fixed, and the load and store bits are WARL.
+                    let mut tdata1 = Mcontrol(value);
+                    tdata1.set_type(trigger.type_);
+                    if !trigger.data_match {
+                        tdata1.set_load(false);
+                        tdata1.set_store(false);
+                    }
+                    trigger.tdata1 = tdata1.0;
+                }
+                TDATA2 => trigger.tdata2 = value,
+                _ => panic!("Unexpected CSR write to {:#x}", address),
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn watchpoint_address_is_set_before_the_trigger_is_enabled() {
+        let mut csrs = MockTriggers::new(vec![Trigger::new(0b10, true), Trigger::new(0b10, true)]);
+
+        let configured =
+            configure_watchpoint(&mut csrs, 1, 0x2000_0100, 4, WatchKind::ReadWrite).unwrap();
+        assert!(configured);
+
+        let tdata2_write = csrs
+            .writes
+            .iter()
+            .position(|&(address, _)| address == TDATA2)
+            .expect("tdata2 was not written");
+        assert_eq!(csrs.writes[tdata2_write], (TDATA2, 0x2000_0100));
+
+        for &(address, value) in &csrs.writes[..tdata2_write] {
+            if address == TDATA1 {
+                let tdata1 = Mcontrol(value);
+                assert!(
+                    !tdata1.m() && !tdata1.s() && !tdata1.u(),
+                    "Trigger enabled before the address was set: {:?}",
+                    tdata1
+                );
+            }
+        }
+
+        let watchpoint = Mcontrol(csrs.triggers[1].tdata1);
+        assert!(watchpoint.m() && watchpoint.s() && watchpoint.u());
+        assert!(watchpoint.load() && watchpoint.store());
+        assert!(!watchpoint.execute());
+        assert!(watchpoint.dmode());
+        assert_eq!(watchpoint.sizelo(), 3);
+        assert_eq!(csrs.triggers[1].tdata2, 0x2000_0100);
+
+        // The other trigger is left alone.
+        assert_eq!(csrs.triggers[0].tdata1, Trigger::new(0b10, true).tdata1);
+    }
+
+    #[test]
+    fn triggers_without_data_match_are_not_used_as_watchpoints() {
+        let mut csrs = MockTriggers::new(vec![Trigger::new(0b10, false)]);
+
+        let configured =
+            configure_watchpoint(&mut csrs, 0, 0x2000_0100, 1, WatchKind::Write).unwrap();
+        assert!(!configured);
+
+        assert_eq!(Mcontrol(csrs.triggers[0].tdata1).type_(), 0b10);
+        assert_eq!(csrs.triggers[0].tdata1 & 0x0fff_ffff, 0);
+        assert!(csrs.writes.iter().all(|&(address, _)| address != TDATA2));
+    }
+
+    #[test]
+    fn only_address_match_triggers_are_used_as_watchpoints() {
+        let mut csrs = MockTriggers::new(vec![Trigger::new(0b11, true)]);
+
+        let configured =
+            configure_watchpoint(&mut csrs, 0, 0x2000_0100, 2, WatchKind::Read).unwrap();
+        assert!(!configured);
+
+        assert_eq!(csrs.writes, vec![(TSELECT, 0)]);
+    }
+
+    #[test]
+    fn unsupported_watchpoint_sizes_are_rejected() {
+        let mut csrs = MockTriggers::new(vec![Trigger::new(0b10, true)]);
+
+        assert!(configure_watchpoint(&mut csrs, 0, 0x2000_0100, 8, WatchKind::Read).is_err());
+        assert!(csrs.writes.is_empty());
+    }
+}
@@ -83,6 +83,23 @@ pub static S1: RegisterDescription = RegisterDescription {
     address: CoreRegisterAddress(0x1009),
 };
 
+/// Translates a DWARF register number into the core register it refers to.
+///
+/// See the [RISC-V ELF psABI] for the numbering. The general purpose registers
+/// and the CSRs are accessible through the debug interface, the floating point
+/// registers are not.
+///
+/// [RISC-V ELF psABI]: https://github.com/riscv/riscv-elf-psabi-doc/blob/master/riscv-elf.md#dwarf-register-numbers
+pub(crate) fn dwarf_register(dwarf_num: u16) -> Option<CoreRegisterAddress> {
+    match dwarf_num {
+        // x0 - x31
+        0..=31 => Some(CoreRegisterAddress(0x1000 + dwarf_num)),
+        // CSRs
+        4096..=8191 => Some(CoreRegisterAddress(dwarf_num - 4096)),
+        _ => None,
+    }
+}
+
 pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
     platform_registers: &[
         RegisterDescription {
@@ -224,3 +241,18 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
         },
     ],
 };
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dwarf_register_2_is_sp() {
+        assert_eq!(dwarf_register(2), Some(SP.address));
+        assert_eq!(dwarf_register(1), Some(RA.address));
+        // f0 is not accessible
+        assert_eq!(dwarf_register(32), None);
+        // dpc
+        assert_eq!(dwarf_register(4096 + 0x7b1), Some(PC.address));
+    }
+}
@@ -0,0 +1,349 @@
+//! A wrapper around `Session` for async code.
+//!
+//! All accesses to the probe block, so the session is owned by a dedicated thread,
+//! and the async methods of `AsyncSession` send commands to that thread.
+//! This works with any async runtime, as no runtime specific types are used.
+
+use crate::flashing::{
+    download_file_with_options, DownloadOptions, FileDownloadError, FlashError, FlashEvent,
+    FlashSummary, Format,
+};
+use crate::{CoreInformation, Error, MemoryInterface, Session};
+use anyhow::anyhow;
+use std::{
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::Duration,
+};
+
+type Command = Box<dyn FnOnce(&mut Session) + Send>;
+
+/// A `Session` which is owned by a dedicated thread, and can be used from async code.
+///
+/// Commands are executed one after the other, in the order they were issued.
+///
+/// # Cancellation
+///
+/// Dropping the future of a command which has not started yet cancels it, and it is never executed.
+/// Once a command has started, it always runs to completion, even if its future is dropped.
+/// This means that cancelling a `flash` future never leaves the flash half erased or programmed,
+/// the flashing process either never starts, or finishes before the next command is executed.
+///
+/// Dropping the `AsyncSession` lets all started and queued commands finish, before the session is closed.
+pub struct AsyncSession {
+    commands: Mutex<Sender<Command>>,
+}
+
+impl AsyncSession {
+    /// Creates a new `AsyncSession`, whose session is opened by `open` on the session thread.
+    ///
+    /// ```no_run
+    /// # async fn example() -> Result<(), probe_rs::Error> {
+    /// use probe_rs::{AsyncSession, Session};
+    ///
+    /// let session = AsyncSession::new(|| Session::auto_attach("nrf51822")).await?;
+    /// let words = session.read_32(0, 0x2000_0000, 4).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn new(
+        open: impl FnOnce() -> Result<Session, Error> + Send + 'static,
+    ) -> Result<Self, Error> {
+        let (commands, receiver) = channel::<Command>();
+        let (opened, reply) = reply_channel();
+
+        thread::Builder::new()
+            .name("probe-rs session".to_owned())
+            .spawn(move || {
+                let mut session = match open() {
+                    Ok(session) => {
+                        opened.send(Ok(()));
+                        session
+                    }
+                    Err(e) => {
+                        opened.send(Err(e));
+                        return;
+                    }
+                };
+
+                for command in receiver {
+                    command(&mut session);
+                }
+            })
+            .map_err(|e| anyhow!("Failed to start the session thread: {}", e))?;
+
+        reply.await??;
+
+        Ok(Self {
+            commands: Mutex::new(commands),
+        })
+    }
+
+    /// Executes `f` with the session on the session thread, and returns its result.
+    ///
+    /// This can be used for all operations which have no dedicated method.
+    pub async fn execute<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&mut Session) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, reply) = reply_channel();
+
+        let command: Command = Box::new(move |session| {
+            // Nobody waits for the result anymore, so the command is not executed at all.
+            if sender.is_canceled() {
+                return;
+            }
+
+            sender.send(f(session));
+        });
+
+        // If the session thread has stopped, the command is dropped, and the reply reports it.
+        let _ = self.commands.lock().unwrap().send(command);
+
+        reply.await
+    }
+
+    /// Reads `len` words from the memory of core `core_index`, starting at `address`.
+    pub async fn read_32(
+        &self,
+        core_index: usize,
+        address: u32,
+        len: usize,
+    ) -> Result<Vec<u32>, Error> {
+        self.execute(move |session| {
+            let mut data = vec![0; len];
+            session
+                .core(core_index)?
+                .read_32(address.into(), &mut data)?;
+            Ok(data)
+        })
+        .await?
+    }
+
+    /// Reads `len` bytes from the memory of core `core_index`, starting at `address`.
+    ///
+    /// The memory is read with 32 bit accesses, see `MemoryInterface::read`.
+    pub async fn read(
+        &self,
+        core_index: usize,
+        address: u32,
+        len: usize,
+    ) -> Result<Vec<u8>, Error> {
+        self.execute(move |session| {
+            let mut data = vec![0; len];
+            session.core(core_index)?.read(address.into(), &mut data)?;
+            Ok(data)
+        })
+        .await?
+    }
+
+    /// Writes `data` to the memory of core `core_index`, starting at `address`.
+    pub async fn write_32(
+        &self,
+        core_index: usize,
+        address: u32,
+        data: Vec<u32>,
+    ) -> Result<(), Error> {
+        self.execute(move |session| session.core(core_index)?.write_32(address.into(), &data))
+            .await?
+    }
+
+    /// Writes `data` to the memory of core `core_index`, starting at `address`.
+    ///
+    /// The memory is written with 32 bit accesses, see `MemoryInterface::write`.
+    pub async fn write(&self, core_index: usize, address: u32, data: Vec<u8>) -> Result<(), Error> {
+        self.execute(move |session| session.core(core_index)?.write(address.into(), &data))
+            .await?
+    }
+
+    /// Halts core `core_index`, see `Core::halt`.
+    pub async fn halt(
+        &self,
+        core_index: usize,
+        timeout: Duration,
+    ) -> Result<CoreInformation, Error> {
+        self.execute(move |session| session.core(core_index)?.halt(timeout))
+            .await?
+    }
+
+    /// Resumes core `core_index`, see `Core::run`.
+    pub async fn run(&self, core_index: usize) -> Result<(), Error> {
+        self.execute(move |session| session.core(core_index)?.run())
+            .await?
+    }
+
+    /// Resets and halts core `core_index`, see `Core::reset_and_halt`.
+    pub async fn reset_and_halt(
+        &self,
+        core_index: usize,
+        timeout: Duration,
+    ) -> Result<CoreInformation, Error> {
+        self.execute(move |session| session.core(core_index)?.reset_and_halt(timeout))
+            .await?
+    }
+
+    /// Downloads the file at `path` to the flash, see `download_file_with_options`.
+    ///
+    /// If `events` is set, it receives the events of the flashing process.
+    /// The flashing process is never interrupted once it has started, see the
+    /// [cancellation](#cancellation) notes. For other options, use `execute` with `download_file_with_options`.
+    pub async fn flash(
+        &self,
+        path: PathBuf,
+        format: Format,
+        events: Option<Sender<FlashEvent>>,
+    ) -> Result<FlashSummary, FileDownloadError> {
+        self.execute(move |session| {
+            download_file_with_options(
+                session,
+                &path,
+                format,
+                DownloadOptions {
+                    events,
+                    ..Default::default()
+                },
+            )
+        })
+        .await
+        .map_err(|e| FileDownloadError::Flash(FlashError::Core(e)))?
+    }
+}
+
+/// The state shared between a `ReplySender` and its `Reply`.
+struct ReplyState<T> {
+    value: Option<T>,
+    /// Set once the `ReplySender` is dropped, with or without sending a value.
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+/// Sends the result of a command executed on the session thread to its `Reply`.
+struct ReplySender<T>(Arc<Mutex<ReplyState<T>>>);
+
+/// The future of a command executed on the session thread.
+///
+/// Resolves to an error if the command is dropped without a result,
+/// e.g. because the session thread has stopped.
+struct Reply<T>(Arc<Mutex<ReplyState<T>>>);
+
+fn reply_channel<T>() -> (ReplySender<T>, Reply<T>) {
+    let state = Arc::new(Mutex::new(ReplyState {
+        value: None,
+        closed: false,
+        waker: None,
+    }));
+
+    (ReplySender(state.clone()), Reply(state))
+}
+
+impl<T> ReplySender<T> {
+    fn send(self, value: T) {
+        self.0.lock().unwrap().value = Some(value);
+    }
+
+    /// Checks if the `Reply` was dropped, so nobody is interested in the result anymore.
+    fn is_canceled(&self) -> bool {
+        Arc::strong_count(&self.0) == 1
+    }
+}
+
+impl<T> Drop for ReplySender<T> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().unwrap();
+        state.closed = true;
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for Reply<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.lock().unwrap();
+
+        if let Some(value) = state.value.take() {
+            Poll::Ready(Ok(value))
+        } else if state.closed {
+            Poll::Ready(Err(anyhow!("The session thread has stopped.").into()))
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::task::Wake;
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Polls `future` on the current thread until it is ready.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn async_session_can_be_shared() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<AsyncSession>();
+    }
+
+    #[test]
+    fn reply_from_other_thread() {
+        let (sender, reply) = reply_channel();
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            sender.send(42);
+        });
+
+        assert_eq!(block_on(reply).unwrap(), 42);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn dropped_sender_is_an_error() {
+        let (sender, reply) = reply_channel::<u32>();
+        drop(sender);
+
+        assert!(block_on(reply).is_err());
+    }
+
+    #[test]
+    fn dropped_reply_cancels() {
+        let (sender, reply) = reply_channel::<u32>();
+        assert!(!sender.is_canceled());
+
+        drop(reply);
+        assert!(sender.is_canceled());
+    }
+}
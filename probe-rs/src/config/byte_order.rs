@@ -0,0 +1,92 @@
+/// The order of the bytes of a value in the memory of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Endianness {
+    /// The least significant byte is stored at the lowest address.
+    #[default]
+    Little,
+    /// The most significant byte is stored at the lowest address.
+    Big,
+}
+
+impl Endianness {
+    /// Converts a word read from the memory bus, where the byte at the lowest address is
+    /// the least significant byte, into the value stored in memory.
+    ///
+    /// The conversion is its own inverse, so it also converts a value into the word to write.
+    pub fn convert_word_32(self, word: u32) -> u32 {
+        match self {
+            Endianness::Little => word,
+            Endianness::Big => word.swap_bytes(),
+        }
+    }
+
+    /// Returns the value stored in `bytes`.
+    pub fn u32_from_bytes(self, bytes: [u8; 4]) -> u32 {
+        match self {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        }
+    }
+
+    /// Returns the bytes storing `value`.
+    pub fn u32_to_bytes(self, value: u32) -> [u8; 4] {
+        match self {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        }
+    }
+}
+
+/// The byte order of a target.
+///
+/// Instructions and data can be stored in a different order, for example on Arm cores
+/// using the BE-8 model, where instructions are always little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct ByteOrder {
+    /// The byte order of data, used by the word accesses of the memory interface.
+    #[serde(default)]
+    pub data: Endianness,
+    /// The byte order of instructions, used when inserting breakpoint instructions.
+    #[serde(default)]
+    pub instructions: Endianness,
+}
+
+impl ByteOrder {
+    /// Instructions and data are stored in little endian order.
+    pub const LITTLE_ENDIAN: ByteOrder = ByteOrder {
+        data: Endianness::Little,
+        instructions: Endianness::Little,
+    };
+
+    /// Whether both instructions and data are stored in little endian order.
+    pub fn is_little_endian(&self) -> bool {
+        *self == Self::LITTLE_ENDIAN
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn words_are_converted() {
+        assert_eq!(Endianness::Little.convert_word_32(0x1122_3344), 0x1122_3344);
+        assert_eq!(Endianness::Big.convert_word_32(0x1122_3344), 0x4433_2211);
+        assert_eq!(
+            Endianness::Big.u32_from_bytes([0x11, 0x22, 0x33, 0x44]),
+            0x1122_3344
+        );
+        assert_eq!(
+            Endianness::Big.u32_to_bytes(0x1122_3344),
+            [0x11, 0x22, 0x33, 0x44]
+        );
+    }
+
+    #[test]
+    fn byte_order_defaults_to_little_endian() {
+        let byte_order: ByteOrder = serde_yaml::from_str("data: Big").unwrap();
+        assert_eq!(byte_order.data, Endianness::Big);
+        assert_eq!(byte_order.instructions, Endianness::Little);
+        assert!(ByteOrder::default().is_little_endian());
+    }
+}
@@ -1,4 +1,6 @@
+use super::byte_order::ByteOrder;
 use super::memory::MemoryRegion;
+use super::reset_type::ResetType;
 use std::borrow::Cow;
 
 /// This describes a single chip model.
@@ -17,4 +19,10 @@ pub struct Chip {
     pub memory_map: Cow<'static, [MemoryRegion]>,
 
     pub flash_algorithms: Cow<'static, [Cow<'static, str>]>,
+    /// The byte order of the chip, which is little endian if not specified.
+    #[serde(default, skip_serializing_if = "ByteOrder::is_little_endian")]
+    pub byte_order: ByteOrder,
+    /// The reset recommended for the chip, which is `SoftwareSysReset` if not specified.
+    #[serde(default, skip_serializing_if = "ResetType::is_default")]
+    pub default_reset_type: ResetType,
 }
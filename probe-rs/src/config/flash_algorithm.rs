@@ -1,9 +1,10 @@
 use super::flash_properties::FlashProperties;
-use super::memory::{PageInfo, RamRegion, SectorInfo};
+use super::memory::{FlashRegion, MemoryRange, PageInfo, RamRegion, SectorInfo};
 use crate::architecture::riscv;
 use crate::core::Architecture;
-use crate::flashing::FlashError;
-use std::{borrow::Cow, convert::TryInto};
+use crate::flashing::{FlashError, Flasher};
+use crate::Session;
+use std::{borrow::Cow, convert::TryInto, ops::Range};
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct FlashAlgorithm {
@@ -25,6 +26,12 @@ pub struct FlashAlgorithm {
     pub pc_erase_sector: u32,
     /// Address of the `EraseAll()` entry point. Optional.
     pub pc_erase_all: Option<u32>,
+    /// Address of an in-target CRC32 routine. Optional.
+    ///
+    /// The routine is called with the start address of the range in `r0` and its size in `r1`,
+    /// and returns the IEEE CRC32 of the range. ARM algorithms without their own routine
+    /// use a built-in one, which is loaded after the algorithm code.
+    pub pc_crc32: Option<u32>,
     /// Initial value of the R9 register for calling flash algo entry points, which
     /// determines where the position-independent data resides.
     pub static_base: u32,
@@ -91,6 +98,109 @@ impl FlashAlgorithm {
         }
         true
     }
+
+    /// Checks the algorithm and runs its `Init` and `UnInit` routines, without erasing or programming anything.
+    ///
+    /// The layout of the algorithm is checked against the RAM region of `session` it is loaded to first.
+    /// `Init` is called exactly as before verifying the flash, so algorithms which set up clocks there can do so.
+    pub fn validate(&self, session: &mut Session) -> Result<(), FlashError> {
+        let ram = session
+            .ram_regions()
+            .find(|ram| ram.range.contains(&self.load_address))
+            .cloned()
+            .ok_or_else(|| {
+                self.invalid("the algorithm is not loaded into the RAM of the target")
+            })?;
+        self.check_layout(&ram)?;
+
+        let region = FlashRegion {
+            range: self.flash_properties.address_range.clone(),
+            is_boot_memory: false,
+        };
+        Flasher::new(session, self.clone(), region).run_init_uninit()
+    }
+
+    /// Checks that the code, the stack and the page buffers of the algorithm fit into `ram` without
+    /// overlapping each other, and that all entry points and the data are inside the code.
+    pub fn check_layout(&self, ram: &RamRegion) -> Result<(), FlashError> {
+        use std::mem::size_of;
+
+        let code = self.load_address
+            ..self.load_address + (self.instructions.len() * size_of::<u32>()) as u32;
+        if !ram.range.contains_range(&code) {
+            return Err(self.invalid("the algorithm code does not fit into RAM"));
+        }
+
+        // The stack grows downwards, and gets at least as much space as `assemble` leaves for it.
+        let stack = self
+            .begin_stack
+            .checked_sub(RawFlashAlgorithm::FLASH_ALGO_STACK_DECREMENT)
+            .map(|bottom| bottom..self.begin_stack)
+            .filter(|stack| ram.range.contains_range(stack))
+            .ok_or_else(|| self.invalid("the algorithm stack does not fit into RAM"))?;
+        if stack.intersects_range(&code) {
+            return Err(self.invalid("the algorithm stack overlaps code"));
+        }
+
+        let buffers: Vec<Range<u32>> = self
+            .page_buffers
+            .iter()
+            .map(|start| *start..*start + self.flash_properties.page_size)
+            .collect();
+        for (i, buffer) in buffers.iter().enumerate() {
+            let problem = if !ram.range.contains_range(buffer) {
+                "does not fit into RAM"
+            } else if buffer.intersects_range(&code) {
+                "overlaps code"
+            } else if buffer.intersects_range(&stack) {
+                "overlaps the algorithm stack"
+            } else if buffers[..i]
+                .iter()
+                .any(|other| other.intersects_range(buffer))
+            {
+                "overlaps another page buffer"
+            } else {
+                continue;
+            };
+            return Err(self.invalid(format!(
+                "the page buffer at {:#010x} {}",
+                buffer.start, problem
+            )));
+        }
+
+        if !(code.start..=code.end).contains(&self.static_base) {
+            return Err(self.invalid("the algorithm data is outside of its code"));
+        }
+
+        let entry_points = [
+            ("Init", self.pc_init),
+            ("UnInit", self.pc_uninit),
+            ("ProgramPage", Some(self.pc_program_page)),
+            ("EraseSector", Some(self.pc_erase_sector)),
+            ("EraseChip", self.pc_erase_all),
+            ("CRC32", self.pc_crc32),
+        ];
+        for (name, address) in entry_points.iter() {
+            if let Some(address) = address {
+                // The lowest bit selects the Thumb state on ARM.
+                if !code.contains(&(address & !1)) {
+                    return Err(self.invalid(format!(
+                        "the entry point {} at {:#010x} is outside of the algorithm code",
+                        name, address
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn invalid(&self, reason: impl Into<String>) -> FlashError {
+        FlashError::InvalidFlashAlgorithm {
+            name: self.name.clone(),
+            reason: reason.into(),
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -115,6 +225,12 @@ pub struct RawFlashAlgorithm {
     pub pc_erase_sector: u32,
     /// Address of the `EraseAll()` entry point. Optional.
     pub pc_erase_all: Option<u32>,
+    /// Address of an in-target CRC32 routine, relative to the start of the instructions. Optional.
+    ///
+    /// The routine is called with the start address of the range in `r0` and its size in `r1`,
+    /// and returns the IEEE CRC32 of the range. If it is not set, ARM algorithms are assembled
+    /// with a built-in routine.
+    pub pc_crc32: Option<u32>,
     /// The offset from the start of RAM to the data section.
     pub data_section_offset: u32,
     /// The properties of the flash on the device.
@@ -172,6 +288,49 @@ impl RawFlashAlgorithm {
         0x0477_0D1F,
     ];
 
+    /// A position-independent Thumb routine computing the IEEE CRC32 of `r1` bytes at `r0`,
+    /// which is added to ARM algorithms without their own CRC32 routine.
+    ///
+    /// ```text
+    ///     push {r4}
+    ///     movs r2, #0
+    ///     mvns r2, r2             ; crc = 0xffffffff
+    ///     ldr r3, =0xedb88320
+    ///     adds r1, r0, r1         ; end of the range
+    /// byte:
+    ///     cmp r0, r1
+    ///     beq done
+    ///     ldrb r4, [r0]
+    ///     adds r0, #1
+    ///     eors r2, r4
+    ///     movs r4, #8
+    /// bit:
+    ///     lsrs r2, r2, #1
+    ///     bcc next
+    ///     eors r2, r3
+    /// next:
+    ///     subs r4, #1
+    ///     bne bit
+    ///     b byte
+    /// done:
+    ///     mvns r0, r2
+    ///     pop {r4}
+    ///     bx lr
+    /// ```
+    const ARM_CRC32_ROUTINE: [u32; 11] = [
+        0x2200_B410,
+        0x4B08_43D2,
+        0x4288_1841,
+        0x7804_D009,
+        0x4062_3001,
+        0x0852_2408,
+        0x405A_D300,
+        0xD1FA_3C01,
+        0x43D0_E7F3,
+        0x4770_BC10,
+        0xEDB8_8320,
+    ];
+
     fn get_algorithm_header(&self, architecture: Architecture) -> &[u32] {
         match architecture {
             Architecture::Arm => &Self::ARM_FLASH_BLOB_HEADER,
@@ -180,6 +339,8 @@ impl RawFlashAlgorithm {
     }
 
     /// Constructs a complete flash algorithm, tailored to the flash and RAM sizes given.
+    ///
+    /// The code of the algorithm is position-independent, so it can be placed anywhere in `ram_region`.
     pub fn assemble(
         &self,
         ram_region: &RamRegion,
@@ -194,12 +355,32 @@ impl RawFlashAlgorithm {
         }
 
         let header = self.get_algorithm_header(architecture);
+        let crc32_routine: &[u32] = match (architecture, self.pc_crc32) {
+            (Architecture::Arm, None) => &Self::ARM_CRC32_ROUTINE,
+            _ => &[],
+        };
+
+        // The code has to fit together with the smallest stack and one page buffer.
+        let required = ((std::mem::size_of_val(header)
+            + self.instructions.len()
+            + std::mem::size_of_val(crc32_routine)) as u32)
+            + Self::FLASH_ALGO_STACK_DECREMENT
+            + self.flash_properties.page_size;
+        let available = ram_region.range.end - ram_region.range.start;
+        if required > available {
+            return Err(FlashError::AlgorithmRamTooSmall {
+                required,
+                available,
+            });
+        }
+
         let instructions: Vec<u32> = header
             .iter()
             .copied()
             .chain(
                 assembled_instructions.map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap())),
             )
+            .chain(crc32_routine.iter().copied())
             .collect();
 
         let mut offset = 0;
@@ -250,6 +431,10 @@ impl RawFlashAlgorithm {
             pc_program_page: code_start + self.pc_program_page,
             pc_erase_sector: code_start + self.pc_erase_sector,
             pc_erase_all: self.pc_erase_all.map(|v| code_start + v),
+            pc_crc32: match crc32_routine {
+                [] => self.pc_crc32.map(|v| code_start + v),
+                _ => Some(code_start + self.instructions.len() as u32),
+            },
             static_base: code_start + self.data_section_offset,
             begin_stack: addr_stack,
             begin_data: page_buffers[0],
@@ -369,3 +554,225 @@ fn flash_sector_multiple_sizes() {
     assert_eq!(Some(expected_b), config.sector_info(0x801_0000));
     assert_eq!(Some(expected_c), config.sector_info(0x80A_0000));
 }
+
+#[test]
+fn assembled_algorithm_layout_is_valid() {
+    let raw = RawFlashAlgorithm {
+        name: Cow::Borrowed("test"),
+        instructions: Cow::Borrowed(&[0; 0x40]),
+        pc_init: Some(0x1),
+        pc_program_page: 0x11,
+        pc_erase_sector: 0x21,
+        data_section_offset: 0x30,
+        flash_properties: FlashProperties {
+            page_size: 0x100,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ram = RamRegion {
+        range: 0x2000_0000..0x2000_1000,
+        is_boot_memory: false,
+    };
+    let algorithm = raw.assemble(&ram, Architecture::Arm).unwrap();
+    assert!(algorithm.check_layout(&ram).is_ok());
+
+    let small_ram = RamRegion {
+        range: 0x2000_0000..0x2000_0100,
+        is_boot_memory: false,
+    };
+    assert!(matches!(
+        raw.assemble(&small_ram, Architecture::Arm),
+        Err(FlashError::AlgorithmRamTooSmall {
+            required: 0x1cc,
+            available: 0x100
+        })
+    ));
+
+    let reason = |algorithm: &FlashAlgorithm| match algorithm.check_layout(&ram) {
+        Err(FlashError::InvalidFlashAlgorithm { reason, .. }) => reason,
+        result => panic!("unexpected result {:?}", result),
+    };
+
+    let mut broken = algorithm.clone();
+    broken.begin_stack += 0x10;
+    assert_eq!(reason(&broken), "the algorithm stack overlaps code");
+
+    let mut broken = algorithm.clone();
+    broken.page_buffers[0] = 0x2000_0f80;
+    assert_eq!(
+        reason(&broken),
+        "the page buffer at 0x20000f80 does not fit into RAM"
+    );
+
+    let mut broken = algorithm;
+    broken.pc_erase_sector = 0x2000_0f01;
+    assert_eq!(
+        reason(&broken),
+        "the entry point EraseSector at 0x20000f01 is outside of the algorithm code"
+    );
+}
+
+/// Executes the Thumb instructions used by `RawFlashAlgorithm::ARM_CRC32_ROUTINE` at `pc`,
+/// until it returns to the breakpoint at the start of `code`, and returns `r0`.
+#[cfg(test)]
+fn run_thumb(code: &[u32], load_address: u32, pc: u32, args: [u32; 2], data: &[u8]) -> u32 {
+    const DATA: u32 = 0x1000_0000;
+    const STACK: u32 = 0x3000_0000;
+
+    let code: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let read_code = |address: u32, len: usize| {
+        let offset = (address - load_address) as usize;
+        code[offset..offset + len].to_vec()
+    };
+
+    let mut r = [0u32; 16];
+    r[0] = args[0];
+    r[1] = args[1];
+    r[13] = STACK;
+    r[14] = load_address | 1;
+    let mut pc = pc;
+    let mut stack = Vec::new();
+    let (mut z, mut c) = (false, false);
+
+    for _ in 0..1_000_000 {
+        let bytes = read_code(pc, 2);
+        let op = u16::from_le_bytes([bytes[0], bytes[1]]);
+        let (rd, rm) = ((op & 0b111) as usize, ((op >> 3) & 0b111) as usize);
+        let imm8 = u32::from(op & 0xff);
+        let mut next = pc + 2;
+
+        match op >> 11 {
+            0b00001 => {
+                // lsrs rd, rm, #imm5
+                let shift = u32::from((op >> 6) & 0x1f);
+                c = r[rm] >> (shift - 1) & 1 != 0;
+                r[rd] = r[rm] >> shift;
+                z = r[rd] == 0;
+            }
+            0b00011 if op >> 9 == 0b0001100 => {
+                // adds rd, rn, rm
+                let rm = ((op >> 6) & 0b111) as usize;
+                let (result, carry) = r[rm].overflowing_add(r[(op >> 3 & 0b111) as usize]);
+                r[rd] = result;
+                z = result == 0;
+                c = carry;
+            }
+            0b00100 => {
+                r[(op >> 8 & 0b111) as usize] = imm8;
+                z = imm8 == 0;
+            }
+            0b00110 | 0b00111 => {
+                // adds / subs rd, #imm8
+                let rd = (op >> 8 & 0b111) as usize;
+                let (result, borrow) = if op >> 11 == 0b00110 {
+                    r[rd].overflowing_add(imm8)
+                } else {
+                    let (result, borrow) = r[rd].overflowing_sub(imm8);
+                    (result, !borrow)
+                };
+                r[rd] = result;
+                z = result == 0;
+                c = borrow;
+            }
+            0b01000 => match op >> 6 {
+                0b0100000001 => {
+                    r[rd] ^= r[rm];
+                    z = r[rd] == 0;
+                }
+                0b0100001010 => {
+                    z = r[rd] == r[rm];
+                    c = r[rd] >= r[rm];
+                }
+                0b0100001111 => {
+                    r[rd] = !r[rm];
+                    z = r[rd] == 0;
+                }
+                _ if op == 0x4770 => {
+                    if r[14] == load_address | 1 {
+                        return r[0];
+                    }
+                    next = r[14] & !1;
+                }
+                _ => panic!("unsupported instruction {:#06x} at {:#010x}", op, pc),
+            },
+            0b01001 => {
+                let address = ((pc + 4) & !0b11) + imm8 * 4;
+                let bytes = read_code(address, 4);
+                r[(op >> 8 & 0b111) as usize] =
+                    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            }
+            0b01111 => {
+                let address = r[rm] + u32::from((op >> 6) & 0x1f);
+                r[rd] = u32::from(data[(address - DATA) as usize]);
+            }
+            0b10110 if op & 0xff00 == 0xb400 => {
+                stack.extend((0..8).filter(|i| op & 1 << i != 0).map(|i| r[i]));
+            }
+            0b10111 if op & 0xff00 == 0xbc00 => {
+                for i in (0..8).rev().filter(|i| op & 1 << i != 0) {
+                    r[i] = stack.pop().unwrap();
+                }
+            }
+            0b11010 | 0b11011 => {
+                let taken = match op >> 8 & 0xf {
+                    0b0000 => z,
+                    0b0001 => !z,
+                    0b0011 => !c,
+                    cond => panic!("unsupported condition {:#x}", cond),
+                };
+                if taken {
+                    next = (pc as i32 + 4 + 2 * i32::from(op as u8 as i8)) as u32;
+                }
+            }
+            0b11100 => {
+                let offset = (i32::from(op << 5) << 16) >> 20;
+                next = (pc as i32 + 4 + offset) as u32;
+            }
+            _ => panic!("unsupported instruction {:#06x} at {:#010x}", op, pc),
+        }
+
+        pc = next;
+    }
+
+    panic!("the routine did not return");
+}
+
+#[test]
+fn builtin_crc32_routine_computes_the_ieee_crc32() {
+    let raw = RawFlashAlgorithm {
+        name: Cow::Borrowed("test"),
+        instructions: Cow::Borrowed(&[0; 0x40]),
+        pc_program_page: 0x11,
+        pc_erase_sector: 0x21,
+        flash_properties: FlashProperties {
+            page_size: 0x100,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let ram = RamRegion {
+        range: 0x2000_0000..0x2000_1000,
+        is_boot_memory: false,
+    };
+
+    let algorithm = raw.assemble(&ram, Architecture::Arm).unwrap();
+    let pc_crc32 = algorithm.pc_crc32.unwrap();
+    assert!(algorithm.check_layout(&ram).is_ok());
+
+    let crc32 = |data: &[u8]| {
+        run_thumb(
+            &algorithm.instructions,
+            algorithm.load_address,
+            pc_crc32,
+            [0x1000_0000, data.len() as u32],
+            data,
+        )
+    };
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc32(&[]), 0);
+
+    // RISC-V algorithms only have the CRC32 routine they provide themselves.
+    let algorithm = raw.assemble(&ram, Architecture::Riscv).unwrap();
+    assert_eq!(algorithm.pc_crc32, None);
+}
@@ -94,10 +94,82 @@ pub enum MemoryRegion {
     Flash(FlashRegion),
 }
 
+/// The kinds of accesses a memory region supports.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MemoryAccess {
+    pub read: bool,
+    /// Whether the region can be written with plain memory writes.
+    /// Flash has to be written with a flash algorithm instead.
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl MemoryRegion {
+    /// Returns the address range of the region.
+    pub fn range(&self) -> &Range<u32> {
+        match self {
+            MemoryRegion::Ram(r) => &r.range,
+            MemoryRegion::Generic(r) => &r.range,
+            MemoryRegion::Flash(r) => &r.range,
+        }
+    }
+
+    /// Returns the kinds of accesses the region supports.
+    ///
+    /// Target descriptions do not contain access information,
+    /// so this is derived from the kind of the region.
+    pub fn access(&self) -> MemoryAccess {
+        match self {
+            MemoryRegion::Ram(_) => MemoryAccess {
+                read: true,
+                write: true,
+                execute: true,
+            },
+            MemoryRegion::Flash(_) => MemoryAccess {
+                read: true,
+                write: false,
+                execute: true,
+            },
+            MemoryRegion::Generic(_) => MemoryAccess {
+                read: true,
+                write: true,
+                execute: false,
+            },
+        }
+    }
+
+    /// Returns `true` if the region is normal memory, which the target may cache.
+    ///
+    /// Generic regions usually describe peripherals, which are never cached.
+    pub fn is_cacheable(&self) -> bool {
+        match self {
+            MemoryRegion::Ram(_) | MemoryRegion::Flash(_) => true,
+            MemoryRegion::Generic(_) => false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn region_attributes() {
+        let flash = MemoryRegion::Flash(FlashRegion {
+            range: 0..0x1000,
+            is_boot_memory: true,
+        });
+        let peripheral = MemoryRegion::Generic(GenericRegion {
+            range: 0x4000_0000..0x5000_0000,
+        });
+
+        assert_eq!(flash.range(), &(0..0x1000));
+        assert!(!flash.access().write);
+        assert!(flash.is_cacheable());
+        assert!(!peripheral.access().execute);
+        assert!(!peripheral.is_cacheable());
+    }
+
     #[test]
     fn contains_range1() {
         let range1 = 0..1;
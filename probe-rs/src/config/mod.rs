@@ -1,19 +1,26 @@
+mod byte_order;
 mod chip;
 mod chip_family;
 mod chip_info;
 mod flash_algorithm;
 mod flash_properties;
 mod memory;
+mod pack;
 pub mod registry;
+mod reset_type;
 mod target;
 
+pub use byte_order::{ByteOrder, Endianness};
 pub use chip::Chip;
 pub use chip_family::ChipFamily;
 pub use chip_info::ChipInfo;
 pub use flash_algorithm::{FlashAlgorithm, RawFlashAlgorithm};
 pub use flash_properties::FlashProperties;
 pub use memory::{
-    FlashRegion, MemoryRange, MemoryRegion, PageInfo, RamRegion, SectorDescription, SectorInfo,
+    FlashRegion, GenericRegion, MemoryAccess, MemoryRange, MemoryRegion, PageInfo, RamRegion,
+    SectorDescription, SectorInfo,
 };
+pub use pack::PackError;
 pub use registry::RegistryError;
+pub use reset_type::ResetType;
 pub use target::{Target, TargetParseError, TargetSelector};
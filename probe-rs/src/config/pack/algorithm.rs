@@ -0,0 +1,168 @@
+//! Extracting flash algorithms from the `.FLM` files of a pack.
+//!
+//! A `.FLM` file is an ELF file with the position independent code in the `PrgCode` section,
+//! its data in the `PrgData` section and the description of the flash in the `DevDscr` section,
+//! as defined by the `FlashDevice` structure of the CMSIS `FlashOS.h` header.
+
+use crate::config::{FlashProperties, RawFlashAlgorithm, SectorDescription};
+use std::borrow::Cow;
+use std::convert::TryInto;
+
+/// The offsets of the fields of the `FlashDevice` structure.
+const DEVICE_NAME: usize = 2;
+const DEVICE_NAME_LENGTH: usize = 128;
+const DEVICE_ADDRESS: usize = 132;
+const DEVICE_SIZE: usize = 136;
+const PAGE_SIZE: usize = 140;
+const ERASED_VALUE: usize = 148;
+const PROGRAM_TIMEOUT: usize = 152;
+const ERASE_TIMEOUT: usize = 156;
+const SECTORS: usize = 160;
+
+/// Marks the end of the sector list.
+const SECTOR_END: u32 = 0xffff_ffff;
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| "the flash device description is truncated".to_owned())
+}
+
+/// Parses the `FlashDevice` structure, and returns the name of the device and its properties.
+fn flash_device(data: &[u8]) -> Result<(String, FlashProperties), String> {
+    let name = data
+        .get(DEVICE_NAME..DEVICE_NAME + DEVICE_NAME_LENGTH)
+        .ok_or("the flash device description is truncated")?;
+    let name = String::from_utf8_lossy(name.split(|byte| *byte == 0).next().unwrap_or(&[]));
+
+    let start = u32_at(data, DEVICE_ADDRESS)?;
+
+    let mut sectors = vec![];
+    let mut offset = SECTORS;
+    loop {
+        let size = u32_at(data, offset)?;
+        let address = u32_at(data, offset + 4)?;
+        if size == SECTOR_END && address == SECTOR_END {
+            break;
+        }
+        if size == 0 {
+            return Err("the flash device has a sector of size 0".to_owned());
+        }
+        sectors.push(SectorDescription { size, address });
+        offset += 8;
+    }
+
+    let properties = FlashProperties {
+        address_range: start..start + u32_at(data, DEVICE_SIZE)?,
+        page_size: u32_at(data, PAGE_SIZE)?,
+        erased_byte_value: *data
+            .get(ERASED_VALUE)
+            .ok_or("the flash device description is truncated")?,
+        program_page_timeout: u32_at(data, PROGRAM_TIMEOUT)?,
+        erase_sector_timeout: u32_at(data, ERASE_TIMEOUT)?,
+        sectors: Cow::Owned(sectors),
+    };
+
+    Ok((name.trim().to_owned(), properties))
+}
+
+/// Extracts the flash algorithm called `name` from the contents of a `.FLM` file.
+pub(crate) fn from_elf(name: String, data: &[u8]) -> Result<RawFlashAlgorithm, String> {
+    use goblin::elf::section_header::SHT_NOBITS;
+
+    let elf = goblin::elf::Elf::parse(data).map_err(|e| e.to_string())?;
+
+    let section = |section_name: &str| {
+        elf.section_headers
+            .iter()
+            .find(|header| &elf.shdr_strtab[header.sh_name] == section_name)
+            .ok_or_else(|| format!("the section {} is missing", section_name))
+    };
+    let contents = |header: &goblin::elf::SectionHeader| -> Result<Vec<u8>, String> {
+        if header.sh_type == SHT_NOBITS {
+            return Ok(vec![0; header.sh_size as usize]);
+        }
+        data.get(header.sh_offset as usize..(header.sh_offset + header.sh_size) as usize)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| "a section is outside of the file".to_owned())
+    };
+
+    let code = section("PrgCode")?;
+    let program_data = section("PrgData")?;
+    let (description, flash_properties) = flash_device(&contents(section("DevDscr")?)?)?;
+
+    // The data follows the code, at the offset it was linked to.
+    let data_section_offset = program_data
+        .sh_addr
+        .checked_sub(code.sh_addr)
+        .ok_or("the data section is placed before the code")? as u32;
+    let mut instructions = contents(code)?;
+    instructions.resize(data_section_offset as usize, 0);
+    instructions.extend(contents(program_data)?);
+    // The instructions are loaded as words.
+    while instructions.len() % 4 != 0 {
+        instructions.push(0);
+    }
+
+    let symbol = |symbol_name: &str| {
+        elf.syms
+            .iter()
+            .find(|symbol| &elf.strtab[symbol.st_name] == symbol_name)
+            .map(|symbol| (symbol.st_value - code.sh_addr) as u32)
+    };
+
+    Ok(RawFlashAlgorithm {
+        name: Cow::Owned(name),
+        description: Cow::Owned(description),
+        default: false,
+        instructions: Cow::Owned(instructions),
+        pc_init: symbol("Init"),
+        pc_uninit: symbol("UnInit"),
+        pc_program_page: symbol("ProgramPage").ok_or("the function ProgramPage is missing")?,
+        pc_erase_sector: symbol("EraseSector").ok_or("the function EraseSector is missing")?,
+        pc_erase_all: symbol("EraseChip"),
+        pc_crc32: None,
+        data_section_offset,
+        flash_properties,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flash_device_description() {
+        let mut data = vec![0; SECTORS];
+        data[DEVICE_NAME..DEVICE_NAME + 4].copy_from_slice(b"Test");
+        data[DEVICE_ADDRESS..DEVICE_ADDRESS + 4].copy_from_slice(&0x0800_0000u32.to_le_bytes());
+        data[DEVICE_SIZE..DEVICE_SIZE + 4].copy_from_slice(&0x4000u32.to_le_bytes());
+        data[PAGE_SIZE..PAGE_SIZE + 4].copy_from_slice(&0x100u32.to_le_bytes());
+        data[ERASED_VALUE] = 0xff;
+        for value in &[0x1000u32, 0, 0x2000, 0x2000, SECTOR_END, SECTOR_END] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let (name, properties) = flash_device(&data).unwrap();
+        assert_eq!(name, "Test");
+        assert_eq!(properties.address_range, 0x0800_0000..0x0800_4000);
+        assert_eq!(properties.page_size, 0x100);
+        assert_eq!(properties.erased_byte_value, 0xff);
+        assert_eq!(
+            properties.sectors.to_vec(),
+            vec![
+                SectorDescription {
+                    size: 0x1000,
+                    address: 0
+                },
+                SectorDescription {
+                    size: 0x2000,
+                    address: 0x2000
+                }
+            ]
+        );
+
+        // The sector list has to be terminated.
+        assert!(flash_device(&data[..data.len() - 8]).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! Reading the files of a pack, which is a ZIP archive.
+//!
+//! Only the features used by packs are supported: stored and deflated files, without ZIP64 extensions.
+
+use super::PackError;
+use std::convert::TryInto;
+use std::io::Read;
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+
+/// The size of the end of central directory record without the comment.
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+/// A ZIP archive read into memory.
+#[derive(Debug)]
+pub(crate) struct Archive {
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, PackError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(PackError::InvalidArchive("unexpected end of the archive"))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, PackError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(PackError::InvalidArchive("unexpected end of the archive"))
+}
+
+/// Normalizes a file name of a pack, which can use backslashes and differ in case.
+fn normalize(name: &str) -> String {
+    name.replace('\\', "/")
+        .trim_start_matches("./")
+        .to_ascii_lowercase()
+}
+
+impl Archive {
+    pub(crate) fn new(data: Vec<u8>) -> Result<Self, PackError> {
+        // The record is at the end of the archive, followed only by a comment.
+        let end = (0..=data.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE))
+            .rev()
+            .find(|offset| matches!(u32_at(&data, *offset), Ok(END_OF_CENTRAL_DIRECTORY)))
+            .ok_or(PackError::InvalidArchive("not a ZIP archive"))?;
+
+        let count = u16_at(&data, end + 10)?;
+        let mut offset = u32_at(&data, end + 16)? as usize;
+
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            if u32_at(&data, offset)? != CENTRAL_DIRECTORY_HEADER {
+                return Err(PackError::InvalidArchive("invalid central directory"));
+            }
+
+            let name_length = u16_at(&data, offset + 28)? as usize;
+            let extra_length = u16_at(&data, offset + 30)? as usize;
+            let comment_length = u16_at(&data, offset + 32)? as usize;
+            let name = data
+                .get(offset + 46..offset + 46 + name_length)
+                .ok_or(PackError::InvalidArchive("unexpected end of the archive"))?;
+
+            let entry = Entry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: u16_at(&data, offset + 10)?,
+                crc: u32_at(&data, offset + 16)?,
+                compressed_size: u32_at(&data, offset + 20)? as usize,
+                size: u32_at(&data, offset + 24)? as usize,
+                header_offset: u32_at(&data, offset + 42)? as usize,
+            };
+            if entry.compressed_size == u32::MAX as usize
+                || entry.header_offset == u32::MAX as usize
+            {
+                return Err(PackError::InvalidArchive(
+                    "ZIP64 archives are not supported",
+                ));
+            }
+            entries.push(entry);
+
+            offset += 46 + name_length + extra_length + comment_length;
+        }
+
+        Ok(Self { data, entries })
+    }
+
+    /// Returns the names of all files in the archive.
+    pub(crate) fn file_names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.name.as_str())
+    }
+
+    /// Returns the contents of the file `name`, which is compared ignoring case and path separators.
+    pub(crate) fn read(&self, name: &str) -> Result<Vec<u8>, PackError> {
+        let normalized = normalize(name);
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| normalize(&entry.name) == normalized)
+            .ok_or_else(|| PackError::MissingFile(name.to_owned()))?;
+
+        if u32_at(&self.data, entry.header_offset)? != LOCAL_FILE_HEADER {
+            return Err(PackError::InvalidArchive("invalid local file header"));
+        }
+        let start = entry.header_offset
+            + 30
+            + u16_at(&self.data, entry.header_offset + 26)? as usize
+            + u16_at(&self.data, entry.header_offset + 28)? as usize;
+        let compressed = self
+            .data
+            .get(start..start + entry.compressed_size)
+            .ok_or(PackError::InvalidArchive("unexpected end of the archive"))?;
+
+        let contents = match entry.method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATED => {
+                let mut contents = Vec::with_capacity(entry.size);
+                flate2::read::DeflateDecoder::new(compressed).read_to_end(&mut contents)?;
+                contents
+            }
+            _ => return Err(PackError::InvalidArchive("unsupported compression method")),
+        };
+
+        let mut crc = flate2::Crc::new();
+        crc.update(&contents);
+        if contents.len() != entry.size || crc.sum() != entry.crc {
+            return Err(PackError::InvalidArchive("checksum mismatch"));
+        }
+
+        Ok(contents)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stored_and_deflated_files() {
+        let archive = Archive::new(std::fs::read("tests/Test.pack").unwrap()).unwrap();
+
+        assert!(archive.file_names().any(|name| name == "Test.Device.pdsc"));
+        // The flash algorithm is deflated, and referenced with a backslash in the description.
+        assert!(archive
+            .read("Flash\\TEST.flm")
+            .unwrap()
+            .starts_with(b"\x7fELF"));
+        assert!(matches!(
+            archive.read("missing.txt"),
+            Err(PackError::MissingFile(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_archives() {
+        assert!(Archive::new(vec![0; 100]).is_err());
+    }
+}
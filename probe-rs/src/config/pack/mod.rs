@@ -0,0 +1,481 @@
+//! Loading targets from CMSIS packs.
+//!
+//! A pack is a ZIP archive with a description of its devices in a `.pdsc` file,
+//! and the flash algorithms of the devices as `.FLM` files.
+//! See <https://arm-software.github.io/CMSIS_5/Pack/html/packFormat.html>.
+
+mod algorithm;
+mod archive;
+mod xml;
+
+use super::{
+    ByteOrder, Chip, ChipFamily, Endianness, FlashRegion, GenericRegion, MemoryRegion, RamRegion,
+    RawFlashAlgorithm, ResetType,
+};
+use archive::Archive;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use xml::Element;
+
+#[derive(Debug, Error)]
+pub enum PackError {
+    #[error("The pack archive is invalid: {0}")]
+    InvalidArchive(&'static str),
+    #[error("The file {0} is missing from the pack")]
+    MissingFile(String),
+    #[error("The pack description is invalid: {0}")]
+    InvalidDescription(String),
+    #[error("The flash algorithm {0} is invalid: {1}")]
+    InvalidAlgorithm(String, String),
+    #[error("An IO error was encountered")]
+    Io(#[from] std::io::Error),
+}
+
+/// The files of a pack.
+enum PackFiles {
+    Archive(Archive),
+    /// The directory of an extracted pack.
+    Directory(PathBuf),
+}
+
+impl PackFiles {
+    fn read(&self, name: &str) -> Result<Vec<u8>, PackError> {
+        match self {
+            PackFiles::Archive(archive) => archive.read(name),
+            PackFiles::Directory(directory) => {
+                std::fs::read(directory.join(name.replace('\\', "/")))
+                    .map_err(|_| PackError::MissingFile(name.to_owned()))
+            }
+        }
+    }
+}
+
+/// Loads the chip families described by the pack at `path`.
+///
+/// `path` is either a `.pack` file, or the `.pdsc` file of an extracted pack.
+pub(crate) fn families_from_pack(path: &Path) -> Result<Vec<ChipFamily>, PackError> {
+    let is_description =
+        matches!(path.extension(), Some(extension) if extension.eq_ignore_ascii_case("pdsc"));
+
+    let (files, description) = if is_description {
+        let directory = path.parent().unwrap_or_else(|| Path::new("")).to_owned();
+        (PackFiles::Directory(directory), std::fs::read(path)?)
+    } else {
+        let archive = Archive::new(std::fs::read(path)?)?;
+        // The description is in the root directory of the pack.
+        let name = archive
+            .file_names()
+            .find(|name| !name.contains('/') && name.to_ascii_lowercase().ends_with(".pdsc"))
+            .ok_or_else(|| PackError::MissingFile("*.pdsc".to_owned()))?
+            .to_owned();
+        let description = archive.read(&name)?;
+        (PackFiles::Archive(archive), description)
+    };
+
+    let description = String::from_utf8_lossy(&description);
+    let root = xml::parse(description.trim_start_matches('\u{feff}'))
+        .map_err(PackError::InvalidDescription)?;
+
+    families(&root, &files)
+}
+
+/// The properties of a device, which are inherited from its family, sub family and device elements.
+#[derive(Debug, Clone, Default)]
+struct Properties<'a> {
+    core: Option<&'a str>,
+    endian: Option<&'a str>,
+    reset_sequence: Option<&'a str>,
+    memories: Vec<&'a Element>,
+    algorithms: Vec<&'a Element>,
+}
+
+impl<'a> Properties<'a> {
+    /// Returns the properties of `element`, which extend the inherited ones.
+    fn inherit(&self, element: &'a Element) -> Self {
+        let mut properties = self.clone();
+        if let Some(processor) = element.child("processor") {
+            if let Some(core) = processor.attribute("Dcore") {
+                properties.core = Some(core);
+            }
+            if let Some(endian) = processor.attribute("Dendian") {
+                properties.endian = Some(endian);
+            }
+        }
+        if let Some(reset_sequence) = element
+            .child("debug")
+            .and_then(|debug| debug.attribute("defaultResetSequence"))
+        {
+            properties.reset_sequence = Some(reset_sequence);
+        }
+        properties.memories.extend(element.children("memory"));
+        properties.algorithms.extend(element.children("algorithm"));
+        properties
+    }
+}
+
+/// Returns the name of the core type for the `Dcore` attribute of a processor.
+fn core_type(core: &str) -> Option<&'static str> {
+    match core {
+        "Cortex-M0" | "Cortex-M0+" | "Cortex-M1" => Some("M0"),
+        "Cortex-M3" => Some("M3"),
+        "Cortex-M4" => Some("M4"),
+        "Cortex-M7" => Some("M7"),
+        "Cortex-M33" => Some("M33"),
+        _ => None,
+    }
+}
+
+fn invalid(message: impl Into<String>) -> PackError {
+    PackError::InvalidDescription(message.into())
+}
+
+fn attribute<'a>(element: &'a Element, name: &str) -> Result<&'a str, PackError> {
+    element
+        .attribute(name)
+        .ok_or_else(|| invalid(format!("'{}' without the attribute {}", element.name, name)))
+}
+
+/// Parses the attribute `name`, a decimal or `0x` prefixed hexadecimal number.
+fn number(element: &Element, name: &str) -> Result<u32, PackError> {
+    let value = attribute(element, name)?;
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|_| invalid(format!("the {} '{}' is not a number", name, value)))
+}
+
+/// Collects the devices and variants below `element`, with their properties.
+fn collect_devices<'a>(
+    element: &'a Element,
+    inherited: &Properties<'a>,
+    devices: &mut Vec<(&'a str, Properties<'a>)>,
+) -> Result<(), PackError> {
+    for device in element.children("device") {
+        let properties = inherited.inherit(device);
+
+        let mut variants = device.children("variant").peekable();
+        if variants.peek().is_none() {
+            devices.push((attribute(device, "Dname")?, properties));
+            continue;
+        }
+        for variant in variants {
+            devices.push((attribute(variant, "Dvariant")?, properties.inherit(variant)));
+        }
+    }
+
+    Ok(())
+}
+
+fn families(root: &Element, files: &PackFiles) -> Result<Vec<ChipFamily>, PackError> {
+    let devices = root
+        .child("devices")
+        .ok_or_else(|| invalid("the pack does not contain devices"))?;
+
+    let mut families = vec![];
+    for family in devices.children("family") {
+        let family_name = attribute(family, "Dfamily")?;
+
+        let properties = Properties::default().inherit(family);
+        let mut devices = vec![];
+        collect_devices(family, &properties, &mut devices)?;
+        for sub_family in family.children("subFamily") {
+            collect_devices(sub_family, &properties.inherit(sub_family), &mut devices)?;
+        }
+
+        // All chips of a family share the core type.
+        let core = match devices.iter().find_map(|(_, properties)| properties.core) {
+            Some(core) => core,
+            None => {
+                return Err(invalid(format!(
+                    "the family {} has no processor",
+                    family_name
+                )))
+            }
+        };
+        let core_type = match core_type(core) {
+            Some(core_type) => core_type,
+            None => {
+                log::warn!(
+                    "Skipping the family {}, the core {} is not supported",
+                    family_name,
+                    core
+                );
+                continue;
+            }
+        };
+
+        let mut flash_algorithms = vec![];
+        let mut variants = vec![];
+        for (name, properties) in devices {
+            if properties.core.and_then(self::core_type) != Some(core_type) {
+                log::warn!(
+                    "Skipping the chip {}, its core differs from the one of the family {}",
+                    name,
+                    family_name
+                );
+                continue;
+            }
+
+            let mut algorithms = vec![];
+            for algorithm in &properties.algorithms {
+                algorithms.push(Cow::Owned(load_algorithm(
+                    algorithm,
+                    files,
+                    &mut flash_algorithms,
+                )?));
+            }
+
+            variants.push(Chip {
+                name: Cow::Owned(name.to_owned()),
+                part: None,
+                memory_map: Cow::Owned(memory_map(&properties)?),
+                flash_algorithms: Cow::Owned(algorithms),
+                byte_order: byte_order(&properties),
+                default_reset_type: reset_type(&properties),
+            });
+        }
+
+        families.push(ChipFamily {
+            name: Cow::Owned(family_name.to_owned()),
+            manufacturer: None,
+            variants: Cow::Owned(variants),
+            flash_algorithms: Cow::Owned(flash_algorithms),
+            core: Cow::Borrowed(core_type),
+        });
+    }
+
+    Ok(families)
+}
+
+/// Returns the byte order for the `Dendian` attribute of the processor.
+///
+/// Cortex-M cores always fetch instructions in little endian order, so only the order
+/// of data is big endian. Configurable cores are assumed to be little endian.
+fn byte_order(properties: &Properties) -> ByteOrder {
+    match properties.endian {
+        Some("Big-endian") => ByteOrder {
+            data: Endianness::Big,
+            instructions: Endianness::Little,
+        },
+        _ => ByteOrder::LITTLE_ENDIAN,
+    }
+}
+
+/// Returns the reset type for the `defaultResetSequence` attribute of the debug element.
+///
+/// Custom sequences of the pack are not executed, so they are all vendor specific.
+fn reset_type(properties: &Properties) -> ResetType {
+    match properties.reset_sequence {
+        None | Some("ResetSystem") => ResetType::SoftwareSysReset,
+        Some("ResetProcessor") => ResetType::SoftwareCoreReset,
+        Some("ResetHardware") => ResetType::Hardware,
+        Some(_) => ResetType::VendorSpecific,
+    }
+}
+
+/// Returns the memory map, where memories of a device replace the inherited ones with the same name.
+fn memory_map(properties: &Properties) -> Result<Vec<MemoryRegion>, PackError> {
+    let mut regions: Vec<(&str, MemoryRegion)> = vec![];
+
+    for memory in &properties.memories {
+        // Older packs use `id`, where the name also determines the kind of the memory.
+        let name = memory
+            .attribute("name")
+            .or_else(|| memory.attribute("id"))
+            .ok_or_else(|| invalid("'memory' without a name"))?;
+        let start = number(memory, "start")?;
+        let range = start
+            ..start
+                .checked_add(number(memory, "size")?)
+                .ok_or_else(|| invalid(format!("the memory {} exceeds the address space", name)))?;
+        let is_boot_memory = memory.attribute("startup") == Some("1");
+
+        let region = match memory.attribute("access") {
+            Some(access) if access.contains('w') => MemoryRegion::Ram(RamRegion {
+                range,
+                is_boot_memory,
+            }),
+            Some(_) => MemoryRegion::Flash(FlashRegion {
+                range,
+                is_boot_memory,
+            }),
+            None if name.starts_with("IRAM") => MemoryRegion::Ram(RamRegion {
+                range,
+                is_boot_memory,
+            }),
+            None if name.starts_with("IROM") => MemoryRegion::Flash(FlashRegion {
+                range,
+                is_boot_memory,
+            }),
+            None => MemoryRegion::Generic(GenericRegion { range }),
+        };
+
+        regions.retain(|(existing, _)| *existing != name);
+        regions.push((name, region));
+    }
+
+    Ok(regions.into_iter().map(|(_, region)| region).collect())
+}
+
+/// Loads the algorithm referenced by `element` into `algorithms`, unless it is already loaded,
+/// and returns its name.
+///
+/// Algorithms are named after their file, with the start address appended
+/// if a file is used for multiple address ranges.
+fn load_algorithm(
+    element: &Element,
+    files: &PackFiles,
+    algorithms: &mut Vec<RawFlashAlgorithm>,
+) -> Result<String, PackError> {
+    let path = attribute(element, "name")?;
+    let start = number(element, "start")?;
+    let range = start..start.saturating_add(number(element, "size")?);
+
+    let normalized = path.replace('\\', "/");
+    let file_name = Path::new(&normalized)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_ascii_lowercase())
+        .ok_or_else(|| invalid(format!("invalid algorithm file name {}", path)))?;
+
+    for name in &[file_name.clone(), format!("{}_{:x}", file_name, start)] {
+        match algorithms
+            .iter()
+            .find(|algorithm| algorithm.name == name.as_str())
+        {
+            Some(algorithm) if algorithm.flash_properties.address_range == range => {
+                return Ok(name.clone())
+            }
+            Some(_) => continue,
+            None => {
+                let data = files.read(path)?;
+                let mut algorithm = algorithm::from_elf(name.clone(), &data)
+                    .map_err(|reason| PackError::InvalidAlgorithm(path.to_owned(), reason))?;
+                // The range of the description takes precedence over the one of the algorithm.
+                algorithm.flash_properties.address_range = range;
+                algorithm.default = element.attribute("default") == Some("1");
+                algorithms.push(algorithm);
+                return Ok(name.clone());
+            }
+        }
+    }
+
+    Err(invalid(format!(
+        "the algorithm {} is used for too many address ranges",
+        path
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn families_from_the_test_pack() {
+        let families = families_from_pack(Path::new("tests/Test.pack")).unwrap();
+        assert_eq!(families.len(), 1);
+
+        let family = &families[0];
+        assert_eq!(family.name, "Test Family");
+        assert_eq!(family.core, "M4");
+
+        // Devices with variants are only available as these variants.
+        let names: Vec<_> = family.variants().iter().map(|chip| &chip.name).collect();
+        assert_eq!(names, vec!["TEST1", "TEST2A", "TEST2B"]);
+
+        // The RAM of TEST2 replaces the one of the family.
+        let test2 = &family.variants()[1];
+        assert_eq!(
+            test2.memory_map.to_vec(),
+            vec![
+                MemoryRegion::Flash(FlashRegion {
+                    range: 0x0800_0000..0x0800_4000,
+                    is_boot_memory: true
+                }),
+                MemoryRegion::Ram(RamRegion {
+                    range: 0x2000_0000..0x2000_2000,
+                    is_boot_memory: false
+                }),
+            ]
+        );
+
+        let algorithm = family.get_algorithm("test").unwrap();
+        assert!(algorithm.default);
+        assert_eq!(algorithm.description, "Test Flash");
+        assert_eq!(
+            algorithm.flash_properties.address_range,
+            0x0800_0000..0x0800_4000
+        );
+        assert_eq!(algorithm.pc_init, Some(1));
+        assert_eq!(algorithm.pc_erase_sector, 9);
+        assert_eq!(algorithm.pc_erase_all, None);
+        assert_eq!(algorithm.data_section_offset, 0x10);
+        assert_eq!(test2.flash_algorithms.to_vec(), vec!["test"]);
+    }
+
+    #[test]
+    fn big_endian_processors() {
+        let root =
+            xml::parse(r#"<device><processor Dcore="Cortex-M3" Dendian="Big-endian"/></device>"#)
+                .unwrap();
+        let big_endian = byte_order(&Properties::default().inherit(&root));
+
+        assert_eq!(big_endian.data, Endianness::Big);
+        assert_eq!(big_endian.instructions, Endianness::Little);
+        assert!(byte_order(&Properties::default()).is_little_endian());
+    }
+
+    #[test]
+    fn default_reset_sequences() {
+        let family =
+            xml::parse(r#"<family><debug defaultResetSequence="ResetHardware"/></family>"#)
+                .unwrap();
+        let device =
+            xml::parse(r#"<device><debug defaultResetSequence="MyReset"/></device>"#).unwrap();
+        let inherited = Properties::default().inherit(&family);
+
+        assert_eq!(reset_type(&inherited), ResetType::Hardware);
+        assert_eq!(
+            reset_type(&inherited.inherit(&device)),
+            ResetType::VendorSpecific
+        );
+        assert_eq!(
+            reset_type(&Properties::default()),
+            ResetType::SoftwareSysReset
+        );
+    }
+
+    #[test]
+    fn memory_kinds() {
+        let root = xml::parse(
+            r#"<device>
+                <memory id="IROM1" start="0x0" size="0x100" startup="1"/>
+                <memory name="SRAM" access="rwx" start="0x1000" size="256"/>
+                <memory id="OTHER" start="0x2000" size="0x10"/>
+            </device>"#,
+        )
+        .unwrap();
+        let properties = Properties::default().inherit(&root);
+
+        assert_eq!(
+            memory_map(&properties).unwrap(),
+            vec![
+                MemoryRegion::Flash(FlashRegion {
+                    range: 0..0x100,
+                    is_boot_memory: true
+                }),
+                MemoryRegion::Ram(RamRegion {
+                    range: 0x1000..0x1100,
+                    is_boot_memory: false
+                }),
+                MemoryRegion::Generic(GenericRegion {
+                    range: 0x2000..0x2010
+                }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! A minimal XML parser for pack descriptions.
+//!
+//! Only elements and their attributes are parsed. Text content, comments, processing
+//! instructions and the document type are skipped, as the pack description stores
+//! everything needed in attributes.
+
+/// An element of an XML document, without its text content.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    pub(crate) attributes: Vec<(String, String)>,
+    pub(crate) children: Vec<Element>,
+}
+
+impl Element {
+    /// Returns the value of the attribute `name`.
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the first child element called `name`.
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// Returns all child elements called `name`.
+    pub(crate) fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+/// Parses `document`, and returns its root element.
+pub(crate) fn parse(document: &str) -> Result<Element, String> {
+    let mut open: Vec<Element> = vec![];
+    let mut root = None;
+    let mut rest = document;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+
+        // Markup which does not contain elements.
+        let skipped = [
+            ("<?", "?>"),
+            ("<!--", "-->"),
+            ("<![CDATA[", "]]>"),
+            ("<!", ">"),
+        ]
+        .iter()
+        .find(|(start, _)| rest.starts_with(start));
+        if let Some((start, end)) = skipped {
+            let length = rest[start.len()..]
+                .find(end)
+                .ok_or_else(|| format!("unterminated '{}'", start))?;
+            rest = &rest[start.len() + length + end.len()..];
+            continue;
+        }
+
+        if let Some(closing) = rest.strip_prefix("</") {
+            let end = closing.find('>').ok_or("unterminated closing tag")?;
+            let name = closing[..end].trim();
+            rest = &closing[end + 1..];
+
+            let element = open
+                .pop()
+                .ok_or_else(|| format!("unexpected closing tag '{}'", name))?;
+            if element.name != name {
+                return Err(format!(
+                    "closing tag '{}' does not match '{}'",
+                    name, element.name
+                ));
+            }
+            close(element, &mut open, &mut root)?;
+            continue;
+        }
+
+        let (element, self_closing, remaining) = parse_tag(&rest[1..])?;
+        rest = remaining;
+        if self_closing {
+            close(element, &mut open, &mut root)?;
+        } else {
+            open.push(element);
+        }
+    }
+
+    if let Some(element) = open.last() {
+        return Err(format!("unclosed element '{}'", element.name));
+    }
+
+    root.ok_or_else(|| "no root element".to_owned())
+}
+
+/// Adds the finished `element` to its parent, or makes it the root element.
+fn close(element: Element, open: &mut [Element], root: &mut Option<Element>) -> Result<(), String> {
+    match open.last_mut() {
+        Some(parent) => parent.children.push(element),
+        None if root.is_none() => *root = Some(element),
+        None => return Err("multiple root elements".to_owned()),
+    }
+    Ok(())
+}
+
+/// Parses the tag after `<`, and returns the element, whether it is self-closing and the rest of the input.
+fn parse_tag(input: &str) -> Result<(Element, bool, &str), String> {
+    let is_delimiter = |c: char| c.is_whitespace() || c == '/' || c == '>';
+
+    let end = input.find(is_delimiter).ok_or("unterminated tag")?;
+    let mut element = Element {
+        name: input[..end].to_owned(),
+        ..Default::default()
+    };
+    if element.name.is_empty() {
+        return Err("tag without name".to_owned());
+    }
+
+    let mut rest = &input[end..];
+    loop {
+        rest = rest.trim_start();
+
+        if let Some(remaining) = rest.strip_prefix("/>") {
+            return Ok((element, true, remaining));
+        }
+        if let Some(remaining) = rest.strip_prefix('>') {
+            return Ok((element, false, remaining));
+        }
+
+        let equals = rest
+            .find('=')
+            .ok_or_else(|| format!("attribute without value in '{}'", element.name))?;
+        let name = rest[..equals].trim().to_owned();
+        rest = rest[equals + 1..].trim_start();
+
+        let quote = rest
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| format!("unquoted value of attribute '{}'", name))?;
+        let length = rest[1..]
+            .find(quote)
+            .ok_or_else(|| format!("unterminated value of attribute '{}'", name))?;
+        let value = unescape(&rest[1..1 + length])?;
+        rest = &rest[length + 2..];
+
+        element.attributes.push((name, value));
+    }
+}
+
+/// Replaces the entity and character references in `value`.
+fn unescape(value: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        let end = rest.find(';').ok_or("unterminated reference")?;
+        let reference = &rest[..end];
+        let character = match reference {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match reference.strip_prefix("#x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => reference.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+            }
+            .and_then(std::char::from_u32),
+        };
+        result.push(character.ok_or_else(|| format!("unknown reference '&{};'", reference))?);
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn elements_and_attributes() {
+        let document = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!-- A comment with <tags> -->
+            <package schemaVersion="1.4">
+              <description>Text is <![CDATA[skipped]]></description>
+              <devices>
+                <device Dname='A&amp;B' size="0x&#49;0"/>
+              </devices>
+            </package>"#;
+
+        let root = parse(document).unwrap();
+        assert_eq!(root.name, "package");
+        assert_eq!(root.attribute("schemaVersion"), Some("1.4"));
+
+        let device = root.child("devices").unwrap().child("device").unwrap();
+        assert_eq!(device.attribute("Dname"), Some("A&B"));
+        assert_eq!(device.attribute("size"), Some("0x10"));
+        assert!(device.children.is_empty());
+    }
+
+    #[test]
+    fn malformed_documents() {
+        assert!(parse("<a><b></a>").is_err());
+        assert!(parse("<a>").is_err());
+        assert!(parse("<a/><b/>").is_err());
+        assert!(parse("<a b=c/>").is_err());
+        assert!(parse("<a b=\"&unknown;\"/>").is_err());
+    }
+}
@@ -1,5 +1,6 @@
+use super::pack::{families_from_pack, PackError};
 use super::target::Target;
-use crate::config::{Chip, ChipFamily, ChipInfo};
+use crate::config::{ByteOrder, Chip, ChipFamily, ChipInfo, ResetType};
 use crate::core::CoreType;
 use lazy_static::lazy_static;
 use std::fs::File;
@@ -33,6 +34,8 @@ pub enum RegistryError {
     Io(#[from] std::io::Error),
     #[error("Deserializing the yaml encountered an error")]
     Yaml(#[from] serde_yaml::Error),
+    #[error("Loading the CMSIS pack encountered an error")]
+    Pack(#[from] PackError),
     #[error("Unable to lock registry")]
     LockUnavailable,
 }
@@ -52,6 +55,8 @@ const GENERIC_TARGETS: [ChipFamily; 6] = [
             part: None,
             memory_map: Cow::Borrowed(&[]),
             flash_algorithms: Cow::Borrowed(&[]),
+            byte_order: ByteOrder::LITTLE_ENDIAN,
+            default_reset_type: ResetType::SoftwareSysReset,
         }]),
         flash_algorithms: Cow::Borrowed(&[]),
         core: Cow::Borrowed("M0"),
@@ -64,6 +69,8 @@ const GENERIC_TARGETS: [ChipFamily; 6] = [
             part: None,
             memory_map: Cow::Borrowed(&[]),
             flash_algorithms: Cow::Borrowed(&[]),
+            byte_order: ByteOrder::LITTLE_ENDIAN,
+            default_reset_type: ResetType::SoftwareSysReset,
         }]),
         flash_algorithms: Cow::Borrowed(&[]),
         core: Cow::Borrowed("M4"),
@@ -76,6 +83,8 @@ const GENERIC_TARGETS: [ChipFamily; 6] = [
             part: None,
             memory_map: Cow::Borrowed(&[]),
             flash_algorithms: Cow::Borrowed(&[]),
+            byte_order: ByteOrder::LITTLE_ENDIAN,
+            default_reset_type: ResetType::SoftwareSysReset,
         }]),
         flash_algorithms: Cow::Borrowed(&[]),
         core: Cow::Borrowed("M3"),
@@ -88,6 +97,8 @@ const GENERIC_TARGETS: [ChipFamily; 6] = [
             part: None,
             memory_map: Cow::Borrowed(&[]),
             flash_algorithms: Cow::Borrowed(&[]),
+            byte_order: ByteOrder::LITTLE_ENDIAN,
+            default_reset_type: ResetType::SoftwareSysReset,
         }]),
         flash_algorithms: Cow::Borrowed(&[]),
         core: Cow::Borrowed("M33"),
@@ -100,6 +111,8 @@ const GENERIC_TARGETS: [ChipFamily; 6] = [
             part: None,
             memory_map: Cow::Borrowed(&[]),
             flash_algorithms: Cow::Borrowed(&[]),
+            byte_order: ByteOrder::LITTLE_ENDIAN,
+            default_reset_type: ResetType::SoftwareSysReset,
         }]),
         flash_algorithms: Cow::Borrowed(&[]),
         core: Cow::Borrowed("M7"),
@@ -112,6 +125,8 @@ const GENERIC_TARGETS: [ChipFamily; 6] = [
             part: None,
             memory_map: Cow::Borrowed(&[]),
             flash_algorithms: Cow::Borrowed(&[]),
+            byte_order: ByteOrder::LITTLE_ENDIAN,
+            default_reset_type: ResetType::SoftwareSysReset,
         }]),
         flash_algorithms: Cow::Borrowed(&[]),
         core: Cow::Borrowed("riscv"),
@@ -261,6 +276,32 @@ impl Registry {
 
         Ok(())
     }
+
+    fn load_pack(&mut self, path: &Path) -> Result<(), RegistryError> {
+        for family in families_from_pack(path)? {
+            for chip in family.variants() {
+                let existing = self
+                    .families
+                    .iter()
+                    .flat_map(|family| family.variants().iter())
+                    .any(|existing| existing.name.eq_ignore_ascii_case(&chip.name));
+                if existing {
+                    log::warn!(
+                        "The chip {} from the pack {} replaces the existing target with the same name",
+                        chip.name,
+                        path.display()
+                    );
+                }
+            }
+
+            // Families added later take precedence when searching for a chip.
+            self.families
+                .retain(|existing| existing.name != family.name);
+            self.families.push(family);
+        }
+
+        Ok(())
+    }
 }
 
 pub fn get_target_by_name(name: impl AsRef<str>) -> Result<Target, RegistryError> {
@@ -275,6 +316,14 @@ pub fn add_target_from_yaml(path_to_yaml: &Path) -> Result<(), RegistryError> {
     REGISTRY.try_lock()?.add_target_from_yaml(path_to_yaml)
 }
 
+/// Adds the devices of a CMSIS pack to the registry, together with their flash algorithms.
+///
+/// `path` is either a `.pack` file, or the `.pdsc` file of an extracted pack.
+/// Devices from the pack take precedence over existing targets with the same name.
+pub fn load_pack(path: &Path) -> Result<(), RegistryError> {
+    REGISTRY.try_lock()?.load_pack(path)
+}
+
 pub fn families() -> Result<Vec<ChipFamily>, RegistryError> {
     Ok(REGISTRY.try_lock()?.families().clone())
 }
@@ -321,4 +370,14 @@ mod tests {
         let registry = Registry::from_builtin_families();
         assert!(registry.get_target_by_name("nrf51822_Xxaa").is_ok());
     }
+
+    #[test]
+    fn load_pack() {
+        let mut registry = Registry::from_builtin_families();
+        registry.load_pack(Path::new("tests/Test.pack")).unwrap();
+
+        let target = registry.get_target_by_name("TEST2B").unwrap();
+        assert_eq!(target.flash_algorithms.len(), 1);
+        assert!(registry.get_target_by_name("nrf51822_Xxaa").is_ok());
+    }
 }
@@ -0,0 +1,21 @@
+/// The way a target is reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum ResetType {
+    /// The reset pin of the probe is asserted, which also resets external peripherals
+    /// connected to it.
+    Hardware,
+    /// The core requests a reset of the whole system, with `SYSRESETREQ` on ARM cores.
+    #[default]
+    SoftwareSysReset,
+    /// Only the core is reset, with `VECTRESET` on ARMv7-M cores. Peripherals keep their state.
+    SoftwareCoreReset,
+    /// A reset specific to the vendor of the chip, which is not supported yet.
+    VendorSpecific,
+}
+
+impl ResetType {
+    /// Whether this is the reset used if the target description does not recommend one.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
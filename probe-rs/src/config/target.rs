@@ -1,7 +1,9 @@
+use super::byte_order::ByteOrder;
 use super::chip::Chip;
 use super::flash_algorithm::RawFlashAlgorithm;
 use super::memory::MemoryRegion;
 use super::registry::TargetIdentifier;
+use super::reset_type::ResetType;
 use crate::core::{Architecture, CoreType};
 
 /// This describes a complete target with a fixed chip model and variant.
@@ -15,6 +17,10 @@ pub struct Target {
     pub core_type: CoreType,
     /// The memory map of the target.
     pub memory_map: Vec<MemoryRegion>,
+    /// The byte order of the target.
+    pub byte_order: ByteOrder,
+    /// The reset recommended for the target, used by `Session::reset`.
+    pub default_reset_type: ResetType,
 }
 
 impl std::fmt::Debug for Target {
@@ -25,8 +31,14 @@ impl std::fmt::Debug for Target {
             identifier: {:?},
             flash_algorithms: {:?},
             memory_map: {:?},
+            byte_order: {:?},
+            default_reset_type: {:?},
         }}",
-            self.identifier, self.flash_algorithms, self.memory_map
+            self.identifier,
+            self.flash_algorithms,
+            self.memory_map,
+            self.byte_order,
+            self.default_reset_type
         )
     }
 }
@@ -46,6 +58,8 @@ impl Target {
             flash_algorithms,
             core_type,
             memory_map: chip.memory_map.clone().into_owned(),
+            byte_order: chip.byte_order,
+            default_reset_type: chip.default_reset_type,
         }
     }
 
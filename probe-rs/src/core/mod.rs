@@ -6,19 +6,24 @@ use crate::error;
 use crate::DebugProbeError;
 use crate::{
     architecture::{
-        arm::core::CortexState, riscv::communication_interface::RiscvCommunicationInterface,
+        arm::core::{CortexState, FpRegister},
+        riscv::communication_interface::RiscvCommunicationInterface,
     },
+    config::{ByteOrder, Endianness, ResetType},
+    memory::MemoryCache,
     Error, Memory, MemoryInterface,
 };
 use anyhow::{anyhow, Result};
-use std::time::Duration;
+use std::convert::TryFrom;
+use std::ops::{Deref, DerefMut, Range};
+use std::time::{Duration, Instant};
 
 pub trait CoreRegister: Clone + From<u32> + Into<u32> + Sized + std::fmt::Debug {
-    const ADDRESS: u32;
+    const ADDRESS: u64;
     const NAME: &'static str;
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct CoreRegisterAddress(pub u16);
 
 impl From<CoreRegisterAddress> for u32 {
@@ -155,19 +160,69 @@ pub trait CoreInterface: MemoryInterface {
     /// [`reset_and_halt`]: trait.Core.html#tymethod.reset_and_halt
     fn reset(&mut self) -> Result<(), error::Error>;
 
+    /// Reset the target with `reset_type`, and then continue to execute instructions.
+    ///
+    /// Cores only perform the software resets they support, see `unsupported_reset`.
+    fn reset_with(&mut self, reset_type: ResetType) -> Result<(), error::Error> {
+        match reset_type {
+            ResetType::SoftwareSysReset => self.reset(),
+            reset_type => Err(unsupported_reset(reset_type)),
+        }
+    }
+
     /// Reset the core, and then immediately halt. To continue execution after
     /// reset, use the [`reset`] function.
     ///
     /// [`reset`]: trait.Core.html#tymethod.reset
     fn reset_and_halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error>;
 
+    /// Reset the core, and halt it at the reset vector, before the first instruction is executed.
+    ///
+    /// If the core does not halt within `timeout`, the debug registers are restored
+    /// before the error is returned, so the core keeps running normally.
+    fn reset_and_halt_at_reset_vector(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<CoreInformation, error::Error> {
+        self.reset_and_halt(timeout)
+    }
+
     /// Steps one instruction and then enters halted state again.
     fn step(&mut self) -> Result<CoreInformation, error::Error>;
 
+    /// Steps one instruction, during which interrupts are only taken if `interrupts` is set.
+    ///
+    /// On Arm cores, interrupts are masked with `C_MASKINTS` of `DHCSR`, on RISC-V cores
+    /// with `stepie` of `dcsr`.
+    fn step_with_interrupts(&mut self, interrupts: bool) -> Result<CoreInformation, error::Error>;
+
     fn read_core_reg(&mut self, address: CoreRegisterAddress) -> Result<u32, error::Error>;
 
     fn write_core_reg(&mut self, address: CoreRegisterAddress, value: u32) -> Result<()>;
 
+    /// Reads the core registers at `addresses`, in order.
+    ///
+    /// The registers are read one by one, unless the core can transfer them in fewer accesses.
+    fn read_core_regs(
+        &mut self,
+        addresses: &[CoreRegisterAddress],
+    ) -> Result<Vec<u32>, error::Error> {
+        addresses
+            .iter()
+            .map(|address| self.read_core_reg(*address))
+            .collect()
+    }
+
+    /// Writes the values to the core registers, in order.
+    ///
+    /// The registers are written one by one, unless the core can transfer them in fewer accesses.
+    fn write_core_regs(&mut self, registers: &[(CoreRegisterAddress, u32)]) -> Result<()> {
+        for (address, value) in registers {
+            self.write_core_reg(*address, *value)?;
+        }
+        Ok(())
+    }
+
     fn get_available_breakpoint_units(&mut self) -> Result<u32, error::Error>;
 
     fn enable_breakpoints(&mut self, state: bool) -> Result<(), error::Error>;
@@ -176,53 +231,142 @@ pub trait CoreInterface: MemoryInterface {
 
     fn clear_breakpoint(&mut self, unit_index: usize) -> Result<(), error::Error>;
 
+    /// Configure the breakpoint unit `unit_index` as a data watchpoint
+    /// for `size` bytes at `address`.
+    ///
+    /// Returns `false` if the unit does not support matching on data accesses.
+    /// The watchpoint is removed again with [`CoreInterface::clear_breakpoint`].
+    fn set_watchpoint(
+        &mut self,
+        _unit_index: usize,
+        _address: u32,
+        _size: u32,
+        _kind: WatchKind,
+    ) -> Result<bool, error::Error> {
+        Err(error::Error::ArchitectureRequired(&["riscv"]))
+    }
+
     fn registers(&self) -> &'static RegisterFile;
 
     fn hw_breakpoints_enabled(&self) -> bool;
 
+    /// Configure the core to enter debug state when it executes a breakpoint instruction,
+    /// instead of raising an exception.
+    fn enable_software_breakpoints(&mut self) -> Result<(), error::Error> {
+        Ok(())
+    }
+
+    /// Halt the core when one of the exceptions in `flags` is raised.
+    ///
+    /// The vector catch of all other exceptions is disabled.
+    fn set_vector_catch(&mut self, _flags: VectorCatchFlags) -> Result<(), error::Error> {
+        Err(error::Error::NotSupported("vector catch"))
+    }
+
+    /// Returns the exceptions which currently halt the core.
+    fn vector_catch(&mut self) -> Result<VectorCatchFlags, error::Error> {
+        Err(error::Error::NotSupported("vector catch"))
+    }
+
+    /// Causes some harmless traffic on the debug interface, which does not disturb the core.
+    fn keep_alive(&mut self) -> Result<(), error::Error> {
+        Ok(())
+    }
+
     /// Get the `Architecture` of the Core.
     fn architecture(&self) -> Architecture;
 }
 
 impl<'probe> MemoryInterface for Core<'probe> {
-    fn read_word_32(&mut self, address: u32) -> Result<u32, Error> {
-        self.inner.read_word_32(address)
+    fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
+        let data_order = self.byte_order.data;
+        let mut data = 0;
+        if self.read_cached(address, 4, |bytes| {
+            data = words_from_bytes::<Vec<u32>>(bytes, data_order)[0]
+        })? {
+            return Ok(data);
+        }
+        Ok(data_order.convert_word_32(self.inner.read_word_32(address)?))
     }
 
-    fn read_word_8(&mut self, address: u32) -> Result<u8, Error> {
+    fn read_word_8(&mut self, address: u64) -> Result<u8, Error> {
+        let mut data = 0;
+        if self.read_cached(address, 1, |bytes| data = bytes[0])? {
+            return Ok(data);
+        }
         self.inner.read_word_8(address)
     }
 
-    fn read_32(&mut self, address: u32, data: &mut [u32]) -> Result<(), Error> {
-        self.inner.read_32(address, data)
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
+        let data_order = self.byte_order.data;
+        if self.read_cached(address, data.len() * 4, |bytes| {
+            data.copy_from_slice(&words_from_bytes::<Vec<u32>>(bytes, data_order))
+        })? {
+            return Ok(());
+        }
+        self.inner.read_32(address, data)?;
+        for word in data.iter_mut() {
+            *word = data_order.convert_word_32(*word);
+        }
+        Ok(())
     }
 
-    fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), Error> {
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
+        if self.read_cached(address, data.len(), |bytes| data.copy_from_slice(bytes))? {
+            return Ok(());
+        }
         self.inner.read_8(address, data)
     }
 
-    fn write_word_32(&mut self, addr: u32, data: u32) -> Result<(), Error> {
-        self.inner.write_word_32(addr, data)
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
+        if self.read_cached(address, data.len(), |bytes| data.copy_from_slice(bytes))? {
+            return Ok(());
+        }
+        // The bytes are read in the order of the bus, independent of the data endianness.
+        self.inner.read(address, data)
+    }
+
+    fn write_word_32(&mut self, addr: u64, data: u32) -> Result<(), Error> {
+        self.invalidate_cached(addr, 4);
+        self.inner
+            .write_word_32(addr, self.byte_order.data.convert_word_32(data))
     }
 
-    fn write_word_8(&mut self, addr: u32, data: u8) -> Result<(), Error> {
+    fn write_word_8(&mut self, addr: u64, data: u8) -> Result<(), Error> {
+        self.invalidate_cached(addr, 1);
         self.inner.write_word_8(addr, data)
     }
 
-    fn write_32(&mut self, addr: u32, data: &[u32]) -> Result<(), Error> {
-        self.inner.write_32(addr, data)
+    fn write_32(&mut self, addr: u64, data: &[u32]) -> Result<(), Error> {
+        self.invalidate_cached(addr, data.len() * 4);
+        match self.byte_order.data {
+            Endianness::Little => self.inner.write_32(addr, data),
+            endianness => {
+                let words: Vec<u32> = data
+                    .iter()
+                    .map(|word| endianness.convert_word_32(*word))
+                    .collect();
+                self.inner.write_32(addr, &words)
+            }
+        }
     }
 
-    fn write_8(&mut self, addr: u32, data: &[u8]) -> Result<(), Error> {
+    fn write_8(&mut self, addr: u64, data: &[u8]) -> Result<(), Error> {
+        self.invalidate_cached(addr, data.len());
         self.inner.write_8(addr, data)
     }
 
+    fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), Error> {
+        self.invalidate_cached(addr, data.len());
+        self.inner.write(addr, data)
+    }
+
     fn flush(&mut self) -> Result<(), Error> {
         self.inner.flush()
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum CoreType {
     M3,
     M4,
@@ -245,6 +389,14 @@ impl CoreType {
         }
     }
 
+    /// Returns the architecture of the core type.
+    pub fn architecture(&self) -> Architecture {
+        match self {
+            CoreType::Riscv => Architecture::Riscv,
+            _ => Architecture::Arm,
+        }
+    }
+
     pub(crate) fn from(value: &SpecificCoreState) -> Self {
         match value {
             SpecificCoreState::M0(_) => CoreType::M0,
@@ -261,6 +413,8 @@ impl CoreType {
 pub struct CoreState {
     id: usize,
     breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    sw_breakpoints: Vec<SoftwareBreakpoint>,
 }
 
 impl CoreState {
@@ -268,6 +422,8 @@ impl CoreState {
         Self {
             id,
             breakpoints: vec![],
+            watchpoints: vec![],
+            sw_breakpoints: vec![],
         }
     }
 }
@@ -341,6 +497,52 @@ impl SpecificCoreState {
 pub struct Core<'probe> {
     inner: Box<dyn CoreInterface + 'probe>,
     state: &'probe mut CoreState,
+    memory_cache: Option<&'probe mut MemoryCache>,
+    byte_order: ByteOrder,
+}
+
+/// Keeps a core halted while it is alive, see `Core::halt_scoped`.
+///
+/// When dropped, the core is resumed if it was running before it was halted by the guard.
+/// Errors while resuming the core are logged, as they can't be returned from `drop`.
+pub struct HaltGuard<'core, 'probe> {
+    core: &'core mut Core<'probe>,
+    was_running: bool,
+}
+
+impl HaltGuard<'_, '_> {
+    /// Whether the core was running before, and is resumed when the guard is dropped.
+    pub fn was_running(&self) -> bool {
+        self.was_running
+    }
+}
+
+impl<'probe> Deref for HaltGuard<'_, 'probe> {
+    type Target = Core<'probe>;
+
+    fn deref(&self) -> &Self::Target {
+        self.core
+    }
+}
+
+impl DerefMut for HaltGuard<'_, '_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.core
+    }
+}
+
+impl Drop for HaltGuard<'_, '_> {
+    fn drop(&mut self) {
+        if self.was_running {
+            if let Err(e) = self.core.run() {
+                log::warn!(
+                    "Failed to resume core {} after halting it: {}",
+                    self.core.id(),
+                    e
+                );
+            }
+        }
+    }
 }
 
 impl<'probe> Core<'probe> {
@@ -348,6 +550,73 @@ impl<'probe> Core<'probe> {
         Self {
             inner: Box::new(core),
             state,
+            memory_cache: None,
+            byte_order: ByteOrder::LITTLE_ENDIAN,
+        }
+    }
+
+    /// Accesses the memory in the byte order `byte_order` instead of little endian order.
+    ///
+    /// The word accesses of `MemoryInterface` convert between the byte order of the data and
+    /// the value of the word, while byte accesses are not affected.
+    pub fn with_byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.byte_order = byte_order;
+        self
+    }
+
+    /// The byte order of the memory accessed through this core.
+    pub fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+
+    /// Serves memory reads from `cache` while the core is halted.
+    pub(crate) fn with_memory_cache(mut self, cache: &'probe mut MemoryCache) -> Self {
+        self.memory_cache = Some(cache);
+        self
+    }
+
+    /// Reads `len` bytes at `address` through the memory cache, and passes them to `f`.
+    ///
+    /// Returns `false` if the memory is not cached, in which case `f` is not called.
+    /// Memory above 4GB is never cached, as the memory map only describes 32 bit addresses.
+    fn read_cached(
+        &mut self,
+        address: u64,
+        len: usize,
+        f: impl FnOnce(&[u8]),
+    ) -> Result<bool, error::Error> {
+        let Core {
+            inner,
+            memory_cache,
+            ..
+        } = self;
+
+        let (cache, address) = match (memory_cache, u32::try_from(address)) {
+            (Some(cache), Ok(address)) => (cache, address),
+            _ => return Ok(false),
+        };
+
+        let mut bytes = vec![0; len];
+        if cache.read(address, &mut bytes, |address, words| {
+            inner.read_32(address.into(), words)
+        })? {
+            f(&bytes);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn invalidate_cached(&mut self, address: u64, len: usize) {
+        if let (Some(cache), Ok(address)) = (&mut self.memory_cache, u32::try_from(address)) {
+            cache.invalidate_range(address, len);
+        }
+    }
+
+    /// Tells the memory cache whether the core is halted, see `MemoryCache::set_halted`.
+    fn set_cache_halted(&mut self, halted: bool) {
+        if let Some(cache) = &mut self.memory_cache {
+            cache.set_halted(halted);
         }
     }
 
@@ -364,7 +633,9 @@ impl<'probe> Core<'probe> {
     ///
     /// [`DebugProbeError::Timeout`]: ../probe/debug_probe/enum.DebugProbeError.html#variant.Timeout
     pub fn wait_for_core_halted(&mut self, timeout: Duration) -> Result<(), error::Error> {
-        self.inner.wait_for_core_halted(timeout)
+        self.inner.wait_for_core_halted(timeout)?;
+        self.set_cache_halted(true);
+        Ok(())
     }
 
     /// Check if the core is halted. If the core does not halt on its own,
@@ -372,7 +643,9 @@ impl<'probe> Core<'probe> {
     ///
     /// [`CoreError::Timeout`]: ../probe/debug_probe/enum.CoreError.html#variant.Timeout
     pub fn core_halted(&mut self) -> Result<bool, error::Error> {
-        self.inner.core_halted()
+        let halted = self.inner.core_halted()?;
+        self.set_cache_halted(halted);
+        Ok(halted)
     }
 
     /// Try to halt the core. This function ensures the core is actually halted, and
@@ -380,10 +653,38 @@ impl<'probe> Core<'probe> {
     ///
     /// [`CoreError::Timeout`]: ../probe/debug_probe/enum.CoreError.html#variant.Timeout
     pub fn halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error> {
-        self.inner.halt(timeout)
+        let information = self.inner.halt(timeout)?;
+        self.set_cache_halted(true);
+        Ok(information)
     }
 
+    /// Halts the core until the returned guard is dropped.
+    ///
+    /// The core is only halted if it is running, and only resumed by the guard if it was
+    /// running before, so a core which is already halted stays halted. The core can be
+    /// used through the guard in the meantime.
+    pub fn halt_scoped(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<HaltGuard<'_, 'probe>, error::Error> {
+        let was_running = !self.core_halted()?;
+        if was_running {
+            self.halt(timeout)?;
+        }
+
+        Ok(HaltGuard {
+            core: self,
+            was_running,
+        })
+    }
+
+    /// Resume the core.
+    ///
+    /// When the core is halted on a software breakpoint, the original instruction
+    /// is executed first, and the breakpoint is inserted again.
     pub fn run(&mut self) -> Result<(), error::Error> {
+        self.step_over_sw_breakpoint(false)?;
+        self.set_cache_halted(false);
         self.inner.run()
     }
 
@@ -392,24 +693,368 @@ impl<'probe> Core<'probe> {
     ///
     /// [`reset_and_halt`]: trait.Core.html#tymethod.reset_and_halt
     pub fn reset(&mut self) -> Result<(), error::Error> {
+        self.set_cache_halted(false);
         self.inner.reset()
     }
 
+    /// Reset the target with `reset_type`, and then continue to execute instructions.
+    ///
+    /// `ResetType::SoftwareCoreReset` is only supported by ARMv7-M cores, which have `VECTRESET`.
+    /// A `ResetType::Hardware` reset uses the reset pin of the probe, see `Session::reset_with`.
+    pub fn reset_with(&mut self, reset_type: ResetType) -> Result<(), error::Error> {
+        self.set_cache_halted(false);
+        self.inner.reset_with(reset_type)
+    }
+
     /// Reset the core, and then immediately halt. To continue execution after
     /// reset, use the [`reset`] function.
     ///
     /// [`reset`]: trait.Core.html#tymethod.reset
     pub fn reset_and_halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error> {
-        self.inner.reset_and_halt(timeout)
+        self.set_cache_halted(false);
+        let information = self.inner.reset_and_halt(timeout)?;
+        self.set_cache_halted(true);
+        Ok(information)
+    }
+
+    /// Reset the core, and halt it at the reset vector, before the first instruction is executed.
+    ///
+    /// On ARM cores, this uses the reset vector catch and a `SYSRESETREQ` reset.
+    /// On RISC-V cores, the halt is requested with `resethaltreq`, if the debug module supports it.
+    /// If the core does not halt within `timeout`, the debug registers are restored
+    /// before the error is returned. See `Session::reset_catch` to reset the target with the reset pin instead.
+    pub fn reset_and_halt_at_reset_vector(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<CoreInformation, error::Error> {
+        self.set_cache_halted(false);
+        let information = self.inner.reset_and_halt_at_reset_vector(timeout)?;
+        self.set_cache_halted(true);
+        Ok(information)
     }
 
     /// Steps one instruction and then enters halted state again.
+    ///
+    /// Interrupts are masked by the debugger during the step, see `InterruptMasking::Debugger`.
     pub fn step(&mut self) -> Result<CoreInformation, error::Error> {
-        self.inner.step()
+        self.step_with(InterruptMasking::default())
+    }
+
+    /// Steps one instruction, masking interrupts as given by `masking`, and then enters halted state again.
+    ///
+    /// If the core is halted on a software breakpoint, the original instruction is executed.
+    pub fn step_with(
+        &mut self,
+        masking: InterruptMasking,
+    ) -> Result<CoreInformation, error::Error> {
+        // The stepped instruction might write to memory.
+        if let Some(cache) = &mut self.memory_cache {
+            cache.invalidate();
+        }
+
+        let mask = match (masking, self.architecture()) {
+            (InterruptMasking::Primask, Architecture::Arm) => Some(PRIMASK),
+            (InterruptMasking::Faultmask, Architecture::Arm) => Some(FAULTMASK),
+            _ => None,
+        };
+        let original = match mask {
+            Some(mask) => self.set_interrupt_mask(mask)?,
+            None => None,
+        };
+
+        let interrupts = masking == InterruptMasking::None;
+        let information = match self.step_over_sw_breakpoint(interrupts) {
+            Ok(Some(information)) => Ok(information),
+            Ok(None) => self.inner.step_with_interrupts(interrupts),
+            Err(e) => Err(e),
+        };
+
+        if let Some((mask, original)) = original {
+            self.restore_interrupt_mask(mask, original)?;
+        }
+
+        information
     }
 
+    /// Sets the bit `mask` of the `SPECIAL` register for a step, unless the instruction at the program
+    /// counter accesses the special registers itself.
+    ///
+    /// Returns the set bit and the original value of the register. On ARMv6-M cores, which have no
+    /// `FAULTMASK`, `PRIMASK` is set instead.
+    fn set_interrupt_mask(&mut self, mask: u32) -> Result<Option<(u32, u32)>, error::Error> {
+        use crate::architecture::arm::core::register;
+
+        let pc = self.read_core_reg(register::PC.address)?;
+        let mut instruction = [0; 2];
+        match self.state.sw_breakpoints.iter().find(|bp| bp.address == pc) {
+            Some(bp) => instruction.copy_from_slice(&bp.original[..2]),
+            None => self.read(pc.into(), &mut instruction)?,
+        }
+        let instruction = match self.byte_order.instructions {
+            Endianness::Little => u16::from_le_bytes(instruction),
+            Endianness::Big => u16::from_be_bytes(instruction),
+        };
+        if accesses_special_registers(instruction) {
+            log::debug!(
+                "Not setting the interrupt mask for the instruction {:#06x} at {:#010x}",
+                instruction,
+                pc
+            );
+            return Ok(None);
+        }
+
+        let original = self.read_core_reg(register::SPECIAL.address)?;
+        self.write_core_reg(register::SPECIAL.address, original | mask)?;
+
+        if mask == FAULTMASK && self.read_core_reg(register::SPECIAL.address)? & mask == 0 {
+            self.write_core_reg(register::SPECIAL.address, original | PRIMASK)?;
+            return Ok(Some((PRIMASK, original)));
+        }
+
+        Ok(Some((mask, original)))
+    }
+
+    /// Restores the bit `mask` of the `SPECIAL` register to its value in `original`.
+    ///
+    /// The other bits keep the value they have after the step.
+    fn restore_interrupt_mask(&mut self, mask: u32, original: u32) -> Result<(), error::Error> {
+        use crate::architecture::arm::core::register;
+
+        let value = self.read_core_reg(register::SPECIAL.address)?;
+        self.write_core_reg(
+            register::SPECIAL.address,
+            (value & !mask) | (original & mask),
+        )
+    }
+
+    /// Runs the core until it halts at `address`, using a temporary breakpoint.
+    ///
+    /// A free hardware breakpoint unit is used for the temporary breakpoint, or a software breakpoint
+    /// if all units are in use. If the core halts somewhere else first, e.g. at another breakpoint or
+    /// because of a fault, the reason and the address are returned instead. The temporary breakpoint
+    /// is always removed, a breakpoint which was already set at `address` is kept.
+    ///
+    /// If the core does not halt within `timeout`, a [`DebugProbeError::Timeout`] error is returned,
+    /// and the core keeps running.
+    ///
+    /// [`DebugProbeError::Timeout`]: ../probe/debug_probe/enum.DebugProbeError.html#variant.Timeout
+    pub fn run_to_address(
+        &mut self,
+        address: u32,
+        timeout: Duration,
+    ) -> Result<RunToAddressResult, error::Error> {
+        let already_set = self
+            .state
+            .breakpoints
+            .iter()
+            .any(|bp| bp.address == address)
+            || self
+                .state
+                .sw_breakpoints
+                .iter()
+                .any(|bp| bp.address == address);
+
+        if !already_set {
+            match self.set_hw_breakpoint(address) {
+                Err(error::Error::Probe(DebugProbeError::BreakpointUnitsExceeded)) => {
+                    self.set_sw_breakpoint(address)?
+                }
+                result => result?,
+            }
+        }
+
+        let result = self.run().and_then(|_| {
+            let reason = self.wait_for_halt(timeout)?;
+            let pc = self.read_core_reg(self.registers().program_counter().address)?;
+
+            Ok(match pc == address {
+                true => RunToAddressResult::Reached,
+                false => RunToAddressResult::Stopped { reason, pc },
+            })
+        });
+
+        // Remove the breakpoint even if the core did not halt, so it does not stay behind.
+        if !already_set {
+            let cleared = self.clear_sw_breakpoint(address);
+            if result.is_ok() {
+                cleared?;
+            } else if let Err(e) = cleared {
+                log::warn!(
+                    "Failed to remove the temporary breakpoint at {:#010x}: {}",
+                    address,
+                    e
+                );
+            }
+        }
+
+        result
+    }
+
+    /// Steps the core until the program counter leaves `range`, e.g. the instructions of a source line.
+    ///
+    /// The core is always stepped at least once, and then single stepped while the program counter
+    /// stays in `range`, as none of the supported cores can step a range in hardware. Interrupts are
+    /// masked during the steps, see `InterruptMasking::Debugger`.
+    ///
+    /// A call from inside of `range` leaves it, and `mode` selects whether to stop at the first
+    /// instruction of the called function, or to run it to its return and continue stepping.
+    /// Calls are detected by the return address register, which is set to the instruction after the call.
+    ///
+    /// If the program counter does not leave the range within `timeout`, a [`DebugProbeError::Timeout`]
+    /// error is returned. The core stays halted, unless it is running a called function to its return.
+    ///
+    /// [`DebugProbeError::Timeout`]: ../probe/debug_probe/enum.DebugProbeError.html#variant.Timeout
+    pub fn step_range(
+        &mut self,
+        range: Range<u32>,
+        mode: RangeStepMode,
+        timeout: Duration,
+    ) -> Result<RangeStepResult, error::Error> {
+        let start = Instant::now();
+        let registers = self.registers();
+        let read_return_address = |core: &mut Self| match mode {
+            RangeStepMode::Into => Ok(0),
+            RangeStepMode::Over => core.read_core_reg(registers.return_address().address),
+        };
+
+        let mut pc = self.read_core_reg(registers.program_counter().address)?;
+        let mut return_address = read_return_address(self)?;
+
+        loop {
+            let next = self.step()?.pc;
+            let previous_return_address = return_address;
+            return_address = read_return_address(self)?;
+
+            if !range.contains(&next) {
+                let returns_to = match mode {
+                    RangeStepMode::Into => None,
+                    RangeStepMode::Over => {
+                        call_return_address(&range, pc, previous_return_address, return_address)
+                    }
+                };
+                let returns_to = match returns_to {
+                    Some(returns_to) => returns_to,
+                    None => return Ok(RangeStepResult::Left { pc: next }),
+                };
+
+                log::debug!(
+                    "Stepping over the call at {:#010x}, returning to {:#010x}",
+                    pc,
+                    returns_to
+                );
+                // The call does not change the stack pointer, but a recursive call reaches
+                // the return address with a lower stack pointer first.
+                let stack_pointer = self.read_core_reg(registers.stack_pointer().address)?;
+                loop {
+                    let remaining = timeout
+                        .checked_sub(start.elapsed())
+                        .ok_or(error::Error::Probe(DebugProbeError::Timeout))?;
+                    match self.run_to_address(returns_to, remaining)? {
+                        RunToAddressResult::Reached => (),
+                        RunToAddressResult::Stopped { reason, pc } => {
+                            return Ok(RangeStepResult::Stopped { reason, pc })
+                        }
+                    }
+
+                    if self.read_core_reg(registers.stack_pointer().address)? >= stack_pointer {
+                        break;
+                    }
+                }
+                return_address = read_return_address(self)?;
+                pc = returns_to;
+            } else {
+                pc = next;
+            }
+
+            if start.elapsed() > timeout {
+                return Err(error::Error::Probe(DebugProbeError::Timeout));
+            }
+        }
+    }
+
+    /// Returns the current status of the core.
+    ///
+    /// A halt at a semihosting call is reported as `HaltReason::Semihosting`,
+    /// and not as a regular breakpoint.
     pub fn status(&mut self) -> Result<CoreStatus, error::Error> {
-        self.inner.status()
+        let status = self.inner.status()?;
+        self.set_cache_halted(status.is_halted());
+
+        match status {
+            CoreStatus::Halted(HaltReason::Breakpoint) if self.halted_at_semihosting_call()? => {
+                Ok(CoreStatus::Halted(HaltReason::Semihosting))
+            }
+            status => Ok(status),
+        }
+    }
+
+    /// Waits until the core is halted, and returns the reason for the halt.
+    ///
+    /// If the core does not halt within `timeout`, a [`DebugProbeError::Timeout`] error is returned.
+    ///
+    /// [`DebugProbeError::Timeout`]: ../probe/debug_probe/enum.DebugProbeError.html#variant.Timeout
+    pub fn wait_for_halt(&mut self, timeout: Duration) -> Result<HaltReason, error::Error> {
+        match self.wait_for_status(timeout, CoreStatus::is_halted)? {
+            CoreStatus::Halted(reason) => Ok(reason),
+            status => unreachable!("The core status {:?} is not halted.", status),
+        }
+    }
+
+    /// Waits until the status of the core matches `predicate`, and returns that status.
+    ///
+    /// The status is polled with an increasing interval, to avoid keeping the probe busy.
+    /// If the status does not match within `timeout`, a [`DebugProbeError::Timeout`] error is returned.
+    ///
+    /// [`DebugProbeError::Timeout`]: ../probe/debug_probe/enum.DebugProbeError.html#variant.Timeout
+    pub fn wait_for_status(
+        &mut self,
+        timeout: Duration,
+        mut predicate: impl FnMut(&CoreStatus) -> bool,
+    ) -> Result<CoreStatus, error::Error> {
+        let start = Instant::now();
+        let mut interval = MIN_POLL_INTERVAL;
+
+        loop {
+            let status = self.status()?;
+            if predicate(&status) {
+                return Ok(status);
+            }
+
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return Err(error::Error::Probe(DebugProbeError::Timeout));
+            }
+
+            std::thread::sleep(interval.min(timeout - elapsed));
+            interval = next_poll_interval(interval);
+        }
+    }
+
+    /// Checks if the core is halted at the breakpoint instruction of a semihosting call.
+    fn halted_at_semihosting_call(&mut self) -> Result<bool, error::Error> {
+        let pc = self.registers().program_counter().address;
+        let pc = self.read_core_reg(pc)?;
+
+        match self.architecture() {
+            Architecture::Arm => {
+                let mut instruction = [0; 2];
+                self.read(pc.into(), &mut instruction)?;
+                if self.byte_order.instructions == Endianness::Big {
+                    instruction.reverse();
+                }
+                Ok(instruction == ARM_SEMIHOSTING_INSTRUCTION)
+            }
+            Architecture::Riscv => {
+                // The `ebreak` is surrounded by two marker instructions.
+                let start = match pc.checked_sub(4) {
+                    Some(start) => start,
+                    None => return Ok(false),
+                };
+                let mut sequence = [0; 12];
+                self.read(start.into(), &mut sequence)?;
+                Ok(sequence == RISCV_SEMIHOSTING_SEQUENCE)
+            }
+        }
     }
 
     pub fn read_core_reg(
@@ -427,6 +1072,181 @@ impl<'probe> Core<'probe> {
         Ok(self.inner.write_core_reg(address, value)?)
     }
 
+    /// Saves the core registers, to restore them with [`Core::restore`] after running code on the core.
+    ///
+    /// This includes the general purpose registers and the program counter. On ARM cores, the
+    /// `XPSR` is saved as well, and the floating point registers if the core has an FPU.
+    pub fn registers_snapshot(&mut self) -> Result<RegisterSnapshot, error::Error> {
+        let mut addresses: Vec<CoreRegisterAddress> = self
+            .registers()
+            .registers()
+            .map(|register| register.address)
+            .collect();
+        let program_counter = self.registers().program_counter().address;
+        if !addresses.contains(&program_counter) {
+            addresses.push(program_counter);
+        }
+        if self.architecture() == Architecture::Arm {
+            addresses.extend(crate::architecture::arm::core::snapshot_registers(self)?);
+        }
+
+        let values = self.inner.read_core_regs(&addresses)?;
+
+        Ok(RegisterSnapshot {
+            registers: addresses.into_iter().zip(values).collect(),
+        })
+    }
+
+    /// Writes the registers saved with [`Core::registers_snapshot`] back to the core.
+    pub fn restore(&mut self, snapshot: &RegisterSnapshot) -> Result<(), error::Error> {
+        Ok(self.inner.write_core_regs(&snapshot.registers)?)
+    }
+
+    /// Calls the function at `address` on a halted ARM core, and returns its result.
+    ///
+    /// The arguments are passed as defined by the AAPCS: `CallArg::U32` arguments are passed
+    /// as words, `CallArg::U64` ones in an even register pair or aligned on the stack. Arguments which do not fit into `R0` to `R3` are pushed to the stack.
+    ///
+    /// The function returns to a `BKPT` instruction placed below the current stack pointer.
+    /// The result is `R0` in the lower and `R1` in the upper word, so functions returning a
+    /// 32 bit value only use the lower word.
+    ///
+    /// The registers are restored afterwards, also if the function does not return within
+    /// `return_timeout`, in which case the core is halted and an error is returned.
+    pub fn call(
+        &mut self,
+        address: u32,
+        args: &[CallArg],
+        return_timeout: Duration,
+    ) -> Result<u64, error::Error> {
+        if self.architecture() != Architecture::Arm {
+            return Err(error::Error::ArchitectureRequired(&[
+                "ARMv6", "ARMv7", "ARMv8",
+            ]));
+        }
+        if !self.core_halted()? {
+            return Err(error::Error::Other(anyhow!(
+                "The core has to be halted to call a function"
+            )));
+        }
+
+        let snapshot = self.registers_snapshot()?;
+        let result = self.call_and_wait(address, args, return_timeout);
+        if result.is_err() && !self.core_halted()? {
+            self.halt(Duration::from_millis(100))?;
+        }
+        self.restore(&snapshot)?;
+
+        result
+    }
+
+    fn call_and_wait(
+        &mut self,
+        address: u32,
+        args: &[CallArg],
+        return_timeout: Duration,
+    ) -> Result<u64, error::Error> {
+        use crate::architecture::arm::core::register;
+
+        let (registers, stack) = aapcs_arguments(args);
+
+        // The trampoline and the arguments on the stack are placed below the stack pointer,
+        // which has to be aligned to 8 bytes when the function is called.
+        let sp = self.read_core_reg(register::SP.address)?;
+        let (trampoline, stack_pointer) = sp
+            .checked_sub(4)
+            .map(|trampoline| trampoline & !0b11)
+            .and_then(|trampoline| {
+                let size = u32::try_from(4 * stack.len()).ok()?;
+                Some((trampoline, trampoline.checked_sub(size)? & !0b111))
+            })
+            .ok_or_else(|| {
+                error::Error::Other(anyhow!(
+                    "The stack pointer {:#010x} leaves no room for the trampoline and arguments",
+                    sp
+                ))
+            })?;
+
+        self.write(trampoline.into(), &ARM_BREAKPOINT_INSTRUCTION)?;
+        self.write_32(stack_pointer.into(), &stack)?;
+
+        for (index, value) in registers.iter().enumerate() {
+            self.write_core_reg(CoreRegisterAddress(index as u16), *value)?;
+        }
+        self.write_core_reg(register::SP.address, stack_pointer)?;
+        // The return address is a Thumb address.
+        self.write_core_reg(register::LR.address, trampoline | 1)?;
+        self.write_core_reg(register::PC.address, address & !1)?;
+        // Functions are always executed in the Thumb state.
+        let xpsr = self.read_core_reg(register::XPSR.address)?;
+        self.write_core_reg(register::XPSR.address, xpsr | (1 << 24))?;
+
+        self.run()?;
+        self.wait_for_core_halted(return_timeout)?;
+
+        let pc = self.read_core_reg(register::PC.address)?;
+        if pc != trampoline {
+            return Err(error::Error::Other(anyhow!(
+                "The function at {:#010x} halted at {:#010x} instead of returning",
+                address,
+                pc
+            )));
+        }
+
+        let low = self.read_core_reg(CoreRegisterAddress(0))?;
+        let high = self.read_core_reg(CoreRegisterAddress(1))?;
+        Ok(u64::from(low) | u64::from(high) << 32)
+    }
+
+    /// Reads a floating point register of an ARM core.
+    ///
+    /// Only `D` registers use the full 64 bit value. Returns `Error::NotSupported`
+    /// if the core has no floating point unit.
+    pub fn read_fp_reg(&mut self, register: FpRegister) -> Result<u64, error::Error> {
+        match self.architecture() {
+            Architecture::Arm => crate::architecture::arm::core::read_fp_reg(self, register),
+            Architecture::Riscv => Err(error::Error::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+        }
+    }
+
+    /// Writes a floating point register of an ARM core.
+    ///
+    /// For `S` registers and the `FPSCR`, only the lower 32 bits of `value` are written.
+    /// Returns `Error::NotSupported` if the core has no floating point unit.
+    pub fn write_fp_reg(&mut self, register: FpRegister, value: u64) -> Result<(), error::Error> {
+        match self.architecture() {
+            Architecture::Arm => {
+                crate::architecture::arm::core::write_fp_reg(self, register, value)
+            }
+            Architecture::Riscv => Err(error::Error::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+        }
+    }
+
+    /// Reads the core register with the given DWARF register number.
+    ///
+    /// Returns `Error::UnknownDwarfRegister` if the register can not be accessed by the debugger.
+    pub fn read_core_reg_dwarf(&mut self, dwarf_num: u16) -> Result<u32, error::Error> {
+        let address = self.dwarf_register(dwarf_num)?;
+        self.read_core_reg(address)
+    }
+
+    /// Writes the core register with the given DWARF register number.
+    ///
+    /// Returns `Error::UnknownDwarfRegister` if the register can not be accessed by the debugger.
+    pub fn write_core_reg_dwarf(&mut self, dwarf_num: u16, value: u32) -> Result<(), error::Error> {
+        let address = self.dwarf_register(dwarf_num)?;
+        self.write_core_reg(address, value)
+    }
+
+    fn dwarf_register(&self, dwarf_num: u16) -> Result<CoreRegisterAddress, error::Error> {
+        let address = match self.architecture() {
+            Architecture::Arm => crate::architecture::arm::core::dwarf_register(dwarf_num),
+            Architecture::Riscv => crate::architecture::riscv::dwarf_register(dwarf_num),
+        };
+
+        address.ok_or(error::Error::UnknownDwarfRegister(dwarf_num))
+    }
+
     pub fn get_available_breakpoint_units(&mut self) -> Result<u32, error::Error> {
         self.inner.get_available_breakpoint_units()
     }
@@ -452,7 +1272,7 @@ impl<'probe> Core<'probe> {
 
         log::debug!("{} HW breakpoints are supported.", num_hw_breakpoints);
 
-        if num_hw_breakpoints <= self.state.breakpoints.len() {
+        if num_hw_breakpoints <= self.state.breakpoints.len() + self.state.watchpoints.len() {
             // We cannot set additional breakpoints
             log::warn!("Maximum number of breakpoints ({}) reached, unable to set additional HW breakpoint.", num_hw_breakpoints);
 
@@ -502,6 +1322,225 @@ impl<'probe> Core<'probe> {
         }
     }
 
+    /// Set a hardware watchpoint
+    ///
+    /// This function will try to configure one of the free breakpoint units to halt
+    /// the core when the `size` bytes at `address` are accessed as given by `kind`.
+    /// Watchpoints share the breakpoint units with hardware breakpoints.
+    ///
+    /// Currently this is only supported on RISC-V, using the trigger module.
+    pub fn set_hw_watchpoint(
+        &mut self,
+        address: u32,
+        size: u32,
+        kind: WatchKind,
+    ) -> Result<(), error::Error> {
+        log::debug!(
+            "Trying to set HW watchpoint for {} bytes at address {:#08x}",
+            size,
+            address
+        );
+
+        let num_hw_breakpoints = self.get_available_breakpoint_units()? as usize;
+
+        let free_units: Vec<_> = (0..num_hw_breakpoints)
+            .filter(|unit| !self.breakpoint_unit_in_use(*unit))
+            .collect();
+
+        if free_units.is_empty() {
+            log::warn!("Maximum number of breakpoint units ({}) in use, unable to set additional HW watchpoint.", num_hw_breakpoints);
+
+            return Err(error::Error::Probe(
+                DebugProbeError::BreakpointUnitsExceeded,
+            ));
+        }
+
+        for unit in free_units {
+            if self.inner.set_watchpoint(unit, address, size, kind)? {
+                log::debug!("Using unit {} for the watchpoint", unit);
+
+                self.state.watchpoints.push(Watchpoint {
+                    address,
+                    register_hw: unit,
+                });
+
+                return Ok(());
+            }
+
+            log::debug!("Breakpoint unit {} does not support data watchpoints", unit);
+        }
+
+        Err(error::Error::Other(anyhow!(
+            "None of the free breakpoint units supports data watchpoints"
+        )))
+    }
+
+    pub fn clear_hw_watchpoint(&mut self, address: u32) -> Result<(), error::Error> {
+        let wp_position = self
+            .state
+            .watchpoints
+            .iter()
+            .position(|wp| wp.address == address);
+
+        match wp_position {
+            Some(wp_position) => {
+                let wp = &self.state.watchpoints[wp_position];
+                self.inner.clear_breakpoint(wp.register_hw)?;
+
+                // We only remove the watchpoint if we have actually managed to clear it.
+                self.state.watchpoints.swap_remove(wp_position);
+                Ok(())
+            }
+            None => Err(error::Error::Other(anyhow!(
+                "No watchpoint found at address {}",
+                address
+            ))),
+        }
+    }
+
+    /// Set a software breakpoint
+    ///
+    /// The instruction at `address` is replaced by a breakpoint instruction,
+    /// so the number of software breakpoints is not limited by the breakpoint units.
+    /// If the instruction can not be overwritten, e.g. because it is located in flash,
+    /// a hardware breakpoint is set instead.
+    pub fn set_sw_breakpoint(&mut self, address: u32) -> Result<(), error::Error> {
+        if self
+            .state
+            .sw_breakpoints
+            .iter()
+            .any(|bp| bp.address == address)
+            || self
+                .state
+                .breakpoints
+                .iter()
+                .any(|bp| bp.address == address)
+        {
+            return Ok(());
+        }
+
+        self.inner.enable_software_breakpoints()?;
+
+        let mut first_halfword = [0u8; 2];
+        self.read(address.into(), &mut first_halfword)?;
+
+        let trap = breakpoint_instruction(
+            self.architecture(),
+            self.byte_order.instructions,
+            first_halfword,
+        );
+        let mut original = vec![0u8; trap.len()];
+        self.read(address.into(), &mut original)?;
+
+        if !self.insert_breakpoint_instruction(address, trap, &original)? {
+            log::debug!(
+                "Instruction at {:#010x} is not writable, using a HW breakpoint instead",
+                address
+            );
+            return self.set_hw_breakpoint(address);
+        }
+
+        self.state
+            .sw_breakpoints
+            .push(SoftwareBreakpoint { address, original });
+
+        Ok(())
+    }
+
+    /// Remove a breakpoint set with [`Core::set_sw_breakpoint`],
+    /// restoring the original instruction.
+    pub fn clear_sw_breakpoint(&mut self, address: u32) -> Result<(), error::Error> {
+        let bp_position = self
+            .state
+            .sw_breakpoints
+            .iter()
+            .position(|bp| bp.address == address);
+
+        match bp_position {
+            Some(bp_position) => {
+                let original = self.state.sw_breakpoints[bp_position].original.clone();
+                self.write(address.into(), &original)?;
+
+                // We only remove the breakpoint if we have actually managed to restore the instruction.
+                self.state.sw_breakpoints.swap_remove(bp_position);
+                Ok(())
+            }
+            // The breakpoint might have been set in hardware instead.
+            None => self.clear_hw_breakpoint(address),
+        }
+    }
+
+    /// Remove all software breakpoints, restoring the original instructions.
+    pub fn clear_all_sw_breakpoints(&mut self) -> Result<(), error::Error> {
+        while let Some(bp) = self.state.sw_breakpoints.last().cloned() {
+            self.write(bp.address.into(), &bp.original)?;
+            self.state.sw_breakpoints.pop();
+        }
+
+        Ok(())
+    }
+
+    /// Writes the breakpoint instruction `trap` to `address`,
+    /// returning `false` if the memory could not be written.
+    fn insert_breakpoint_instruction(
+        &mut self,
+        address: u32,
+        trap: &[u8],
+        original: &[u8],
+    ) -> Result<bool, error::Error> {
+        if let Err(e) = self.write(address.into(), trap) {
+            log::debug!("Failed to write breakpoint instruction: {}", e);
+            return Ok(false);
+        }
+
+        let mut readback = vec![0u8; trap.len()];
+        self.read(address.into(), &mut readback)?;
+
+        if readback != trap {
+            // Make sure a partial write does not corrupt the instruction.
+            if readback != original {
+                self.write(address.into(), original)?;
+            }
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// If the core is halted on a software breakpoint, executes the original
+    /// instruction and inserts the breakpoint again.
+    ///
+    /// Returns `None` if the core was not halted on a software breakpoint.
+    fn step_over_sw_breakpoint(
+        &mut self,
+        interrupts: bool,
+    ) -> Result<Option<CoreInformation>, error::Error> {
+        if self.state.sw_breakpoints.is_empty() || !self.core_halted()? {
+            return Ok(None);
+        }
+
+        let pc = self.read_core_reg(self.registers().program_counter())?;
+
+        let bp = match self.state.sw_breakpoints.iter().find(|bp| bp.address == pc) {
+            Some(bp) => bp.clone(),
+            None => return Ok(None),
+        };
+
+        log::debug!("Stepping over software breakpoint at {:#010x}", pc);
+
+        self.write(bp.address.into(), &bp.original)?;
+        let information = self.inner.step_with_interrupts(interrupts);
+
+        let trap = breakpoint_instruction(
+            self.architecture(),
+            self.byte_order.instructions,
+            [bp.original[0], bp.original[1]],
+        );
+        self.write(bp.address.into(), trap)?;
+
+        information.map(Some)
+    }
+
     pub fn clear_all_hw_breakpoints(&mut self) -> Result<(), error::Error> {
         let num_hw_breakpoints = self.get_available_breakpoint_units()? as usize;
 
@@ -510,16 +1549,51 @@ impl<'probe> Core<'probe> {
             .collect()
     }
 
+    /// Configures the core to halt when one of the exceptions in `flags` is raised,
+    /// e.g. to debug faults during bring-up.
+    ///
+    /// Passing `VectorCatchFlags::empty()` disables the vector catch completely.
+    /// Cores which have no vector catch return `Error::NotSupported`.
+    pub fn set_vector_catch(&mut self, flags: VectorCatchFlags) -> Result<(), error::Error> {
+        self.inner.set_vector_catch(flags)
+    }
+
+    /// Returns the exceptions which currently halt the core.
+    pub fn vector_catch(&mut self) -> Result<VectorCatchFlags, error::Error> {
+        self.inner.vector_catch()
+    }
+
+    /// Causes some harmless traffic on the debug interface, to keep the probe connection alive
+    /// while the core runs for a long time without being accessed.
+    ///
+    /// On Arm targets, this reads `DPIDR`. It does nothing for other architectures.
+    pub fn keep_alive(&mut self) -> Result<(), error::Error> {
+        self.inner.keep_alive()
+    }
+
     pub fn architecture(&self) -> Architecture {
         self.inner.architecture()
     }
 
+    fn breakpoint_unit_in_use(&self, unit: usize) -> bool {
+        self.state
+            .breakpoints
+            .iter()
+            .any(|bp| bp.register_hw == unit)
+            || self
+                .state
+                .watchpoints
+                .iter()
+                .any(|wp| wp.register_hw == unit)
+    }
+
     fn find_free_breakpoint_unit(&self) -> usize {
         let mut used_bp: Vec<_> = self
             .state
             .breakpoints
             .iter()
             .map(|bp| bp.register_hw)
+            .chain(self.state.watchpoints.iter().map(|wp| wp.register_hw))
             .collect();
         used_bp.sort_unstable();
 
@@ -567,6 +1641,258 @@ pub struct Breakpoint {
     register_hw: usize,
 }
 
+/// An argument of a function called with [`Core::call`].
+///
+/// The type of the parameter decides how the argument is passed, not its value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallArg {
+    /// A parameter of at most 32 bits, like `u32` or a pointer.
+    U32(u32),
+    /// A 64 bit parameter, like `u64`.
+    U64(u64),
+}
+
+impl From<u32> for CallArg {
+    fn from(value: u32) -> Self {
+        CallArg::U32(value)
+    }
+}
+
+impl From<u64> for CallArg {
+    fn from(value: u64) -> Self {
+        CallArg::U64(value)
+    }
+}
+
+/// The values of the core registers, saved with [`Core::registers_snapshot`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisterSnapshot {
+    registers: Vec<(CoreRegisterAddress, u32)>,
+}
+
+impl RegisterSnapshot {
+    /// Returns the saved value of the register at `address`, if it was saved.
+    pub fn get(&self, address: impl Into<CoreRegisterAddress>) -> Option<u32> {
+        let address = address.into();
+        self.registers
+            .iter()
+            .find(|(register, _)| *register == address)
+            .map(|(_, value)| *value)
+    }
+
+    /// Returns the saved registers with their values.
+    pub fn registers(&self) -> impl Iterator<Item = (CoreRegisterAddress, u32)> + '_ {
+        self.registers.iter().copied()
+    }
+}
+
+/// A breakpoint instruction written to memory, with the instruction it replaced.
+#[derive(Clone, Debug)]
+struct SoftwareBreakpoint {
+    address: u32,
+    original: Vec<u8>,
+}
+
+/// `BKPT #0`
+const ARM_BREAKPOINT_INSTRUCTION: [u8; 2] = [0x00, 0xBE];
+/// `BKPT #0`, for cores fetching instructions in big endian order.
+const ARM_BIG_ENDIAN_BREAKPOINT_INSTRUCTION: [u8; 2] = [0xBE, 0x00];
+/// `c.ebreak`
+const RISCV_COMPRESSED_BREAKPOINT_INSTRUCTION: [u8; 2] = [0x02, 0x90];
+/// `ebreak`
+const RISCV_BREAKPOINT_INSTRUCTION: [u8; 4] = [0x73, 0x00, 0x10, 0x00];
+
+/// Returns the error for a `reset_type` which a core cannot perform.
+pub(crate) fn unsupported_reset(reset_type: ResetType) -> Error {
+    match reset_type {
+        ResetType::Hardware => anyhow!(
+            "A hardware reset uses the reset pin of the probe, it can only be done with `Session::reset_with`"
+        )
+        .into(),
+        ResetType::SoftwareSysReset => Error::NotSupported("a reset of the system"),
+        ResetType::SoftwareCoreReset => Error::NotSupported("a reset of only the core"),
+        ResetType::VendorSpecific => Error::NotSupported("vendor specific resets"),
+    }
+}
+
+/// Returns the breakpoint instruction replacing the instruction starting with `first_halfword`.
+///
+/// On RISC-V, the breakpoint has the same length as the replaced instruction, so that
+/// cores without the compressed instruction set extension can execute it.
+/// RISC-V instructions are always little endian.
+fn breakpoint_instruction(
+    architecture: Architecture,
+    instructions: Endianness,
+    first_halfword: [u8; 2],
+) -> &'static [u8] {
+    match architecture {
+        Architecture::Arm if instructions == Endianness::Big => {
+            &ARM_BIG_ENDIAN_BREAKPOINT_INSTRUCTION
+        }
+        Architecture::Arm => &ARM_BREAKPOINT_INSTRUCTION,
+        Architecture::Riscv if first_halfword[0] & 0b11 == 0b11 => &RISCV_BREAKPOINT_INSTRUCTION,
+        Architecture::Riscv => &RISCV_COMPRESSED_BREAKPOINT_INSTRUCTION,
+    }
+}
+
+/// Assigns `args` to the argument registers `R0` to `R3` and the stack, as defined by the AAPCS.
+///
+/// 32 bit arguments use one register or stack word. 64 bit arguments use an even register pair,
+/// or two stack words aligned to 8 bytes. Once an argument is passed on the stack, all following
+/// arguments are as well.
+fn aapcs_arguments(args: &[CallArg]) -> (Vec<u32>, Vec<u32>) {
+    let mut registers = vec![];
+    let mut stack = vec![];
+
+    for arg in args {
+        let words = match *arg {
+            CallArg::U32(word) => vec![word],
+            CallArg::U64(value) => vec![value as u32, (value >> 32) as u32],
+        };
+
+        if words.len() == 2 && registers.len() % 2 == 1 && registers.len() < 4 {
+            registers.push(0);
+        }
+        if stack.is_empty() && registers.len() + words.len() <= 4 {
+            registers.extend(words);
+        } else {
+            // Once the stack is used, no more registers are assigned.
+            registers.resize(4, 0);
+            if words.len() == 2 && stack.len() % 2 == 1 {
+                stack.push(0);
+            }
+            stack.extend(words);
+        }
+    }
+
+    (registers, stack)
+}
+
+/// Converts `bytes` stored in the byte order `endianness` to words.
+fn words_from_bytes<W: std::iter::FromIterator<u32>>(bytes: &[u8], endianness: Endianness) -> W {
+    bytes
+        .chunks_exact(4)
+        .map(|w| endianness.u32_from_bytes([w[0], w[1], w[2], w[3]]))
+        .collect()
+}
+
+/// `BKPT #0xAB`, which is used for semihosting calls.
+const ARM_SEMIHOSTING_INSTRUCTION: [u8; 2] = [0xAB, 0xBE];
+/// `slli zero, zero, 0x1f`, `ebreak`, `srai zero, zero, 7`, which is used for semihosting calls.
+const RISCV_SEMIHOSTING_SEQUENCE: [u8; 12] = [
+    0x13, 0x10, 0xF0, 0x01, 0x73, 0x00, 0x10, 0x00, 0x13, 0x50, 0x70, 0x40,
+];
+
+/// The first interval between two status polls of `Core::wait_for_status`.
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// The longest interval between two status polls of `Core::wait_for_status`.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Returns the interval to the next status poll, after waiting for `interval`.
+fn next_poll_interval(interval: Duration) -> Duration {
+    (interval * 2).min(MAX_POLL_INTERVAL)
+}
+
+bitflags::bitflags! {
+    /// The exceptions which halt the core when they are raised, see `Core::set_vector_catch`.
+    ///
+    /// The values are the vector catch bits of the ARM `DEMCR` register.
+    /// ARMv6-M cores only support `HARDERR` and `CORERESET`.
+    pub struct VectorCatchFlags: u32 {
+        /// HardFault.
+        const HARDERR = 1 << 10;
+        /// BusFault.
+        const BUSERR = 1 << 8;
+        /// UsageFault caused by a state information error, e.g. an undefined instruction.
+        const STATERR = 1 << 7;
+        /// UsageFault caused by a checking error, e.g. an unaligned access.
+        const CHKERR = 1 << 6;
+        /// UsageFault caused by an access to a coprocessor which is not present or disabled.
+        const NOCPERR = 1 << 5;
+        /// MemManage fault.
+        const MMERR = 1 << 4;
+        /// Core reset. This is also used by `Core::reset_and_halt`, which clears it afterwards.
+        const CORERESET = 1 << 0;
+    }
+}
+
+/// How interrupts are kept from being taken during `Core::step_with`.
+///
+/// Without masking, a pending interrupt is taken during the step, which then ends at the first
+/// instruction of the interrupt handler instead of the next instruction of the stepped code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum InterruptMasking {
+    /// Interrupts are taken during the step.
+    None,
+    /// The debugger masks interrupts, using `C_MASKINTS` of `DHCSR` on Arm cores
+    /// and `stepie` of `dcsr` on RISC-V cores.
+    #[default]
+    Debugger,
+    /// `PRIMASK` is set during the step, and restored to its original value afterwards.
+    ///
+    /// This also masks SysTick and PendSV, and is independent of the debug logic. A fault raised by
+    /// the stepped instruction is escalated to a HardFault. On RISC-V cores, this is the same as `Debugger`.
+    ///
+    /// The mask is not set for instructions which access the special registers, like `cpsie` or `mrs`,
+    /// so they see and change the real value.
+    Primask,
+    /// `FAULTMASK` is set during the step, and restored to its original value afterwards.
+    ///
+    /// This masks all exceptions except NMI, but a fault raised by the stepped instruction locks up
+    /// the core. ARMv6-M cores have no `FAULTMASK`, so `PRIMASK` is set instead.
+    /// On RISC-V cores, this is the same as `Debugger`.
+    Faultmask,
+}
+
+/// The `PRIMASK` bit of the `SPECIAL` register of Cortex-M cores.
+const PRIMASK: u32 = 1;
+/// The `FAULTMASK` bit of the `SPECIAL` register of Cortex-M cores.
+const FAULTMASK: u32 = 1 << 16;
+
+/// Returns the address a call returns to, if the instruction at `pc` was a call out of `range`.
+///
+/// A call sets the return address register to the instruction after it, which is in `range`,
+/// while a return keeps the return address register unchanged.
+fn call_return_address(
+    range: &Range<u32>,
+    pc: u32,
+    previous_return_address: u32,
+    return_address: u32,
+) -> Option<u32> {
+    if return_address == previous_return_address {
+        return None;
+    }
+
+    // The LSB of the Arm return address is set for Thumb code.
+    let returns_to = return_address & !1;
+
+    if range.contains(&returns_to) && returns_to > pc && returns_to - pc <= 4 {
+        Some(returns_to)
+    } else {
+        None
+    }
+}
+
+/// Whether the Thumb instruction starting with `halfword` reads or writes `PRIMASK` or `FAULTMASK`.
+fn accesses_special_registers(halfword: u16) -> bool {
+    // `cps`, and the first halfword of `msr` and `mrs`.
+    halfword & 0xffec == 0xb660 || halfword & 0xffe0 == 0xf380 || halfword == 0xf3ef
+}
+
+/// The kind of memory access a watchpoint halts the core on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Clone, Debug)]
+struct Watchpoint {
+    address: u32,
+    register_hw: usize,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Architecture {
     Arm,
@@ -603,7 +1929,454 @@ pub enum HaltReason {
     Request,
     /// External halt request
     External,
+    /// Core halted at the breakpoint instruction of a semihosting call,
+    /// which has to be handled before the core is resumed.
+    Semihosting,
     /// Unknown reason for halt. This can happen for
     /// example when the core is already halted when we connect.
     Unknown,
 }
+
+/// Where the core halted, see [`Core::run_to_address`].
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RunToAddressResult {
+    /// The core halted at the requested address.
+    Reached,
+    /// The core halted at `pc` for `reason`, before reaching the requested address.
+    Stopped { reason: HaltReason, pc: u32 },
+}
+
+/// What `Core::step_range` does at a call from inside of the stepped range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeStepMode {
+    /// Stop at the first instruction of the called function, like the `step` command of a debugger.
+    Into,
+    /// Run the called function until it returns into the range, and continue stepping,
+    /// like the `next` command of a debugger.
+    Over,
+}
+
+/// The result of `Core::step_range`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RangeStepResult {
+    /// The program counter left the range, and is at `pc` now.
+    Left { pc: u32 },
+    /// While running a called function, the core halted at `pc` for `reason`, e.g. at a breakpoint.
+    Stopped { reason: HaltReason, pc: u32 },
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::architecture::arm::{
+        ap::MemoryAP, core::CortexState, m0::M0, m4::M4, memory::adi_v5_memory_interface::ArmProbe,
+    };
+    use crate::config::{Chip, Target};
+    use std::collections::HashMap;
+
+    /// The memory and core registers of a target, where unwritten values read as zero.
+    #[derive(Default)]
+    struct FakeProbe {
+        memory: HashMap<u64, u8>,
+        registers: HashMap<u16, u32>,
+        /// The PC at which the core halts on a breakpoint right after it is resumed from the halted state.
+        breaks_at: Option<u32>,
+    }
+
+    impl ArmProbe for FakeProbe {
+        fn read_core_reg(
+            &mut self,
+            _ap: MemoryAP,
+            addr: CoreRegisterAddress,
+        ) -> Result<u32, Error> {
+            Ok(*self.registers.get(&addr.0).unwrap_or(&0))
+        }
+
+        fn write_core_reg(
+            &mut self,
+            _ap: MemoryAP,
+            addr: CoreRegisterAddress,
+            value: u32,
+        ) -> Result<(), Error> {
+            self.registers.insert(addr.0, value);
+            Ok(())
+        }
+
+        fn read_8(&mut self, _ap: MemoryAP, address: u64, data: &mut [u8]) -> Result<(), Error> {
+            for (offset, byte) in data.iter_mut().enumerate() {
+                *byte = *self.memory.get(&(address + offset as u64)).unwrap_or(&0);
+            }
+            Ok(())
+        }
+
+        fn read_32(&mut self, ap: MemoryAP, address: u64, data: &mut [u32]) -> Result<(), Error> {
+            // The byte at the lowest address is on the least significant lane of the bus.
+            for (offset, word) in data.iter_mut().enumerate() {
+                let mut bytes = [0; 4];
+                self.read_8(ap, address + 4 * offset as u64, &mut bytes)?;
+                *word = u32::from_le_bytes(bytes);
+            }
+            Ok(())
+        }
+
+        fn write_8(&mut self, _ap: MemoryAP, address: u64, data: &[u8]) -> Result<(), Error> {
+            for (offset, byte) in data.iter().enumerate() {
+                self.memory.insert(address + offset as u64, *byte);
+            }
+            Ok(())
+        }
+
+        fn write_32(&mut self, ap: MemoryAP, address: u64, data: &[u32]) -> Result<(), Error> {
+            for (offset, word) in data.iter().enumerate() {
+                let mut word = *word;
+                if address + 4 * offset as u64 == DHCSR {
+                    let mut old = [0; 4];
+                    self.read_8(ap, DHCSR, &mut old)?;
+                    let was_halted = u32::from_le_bytes(old) & (1 << 17) != 0;
+
+                    // The core halts as soon as it is requested, so S_HALT follows C_HALT.
+                    // The upper half is written with the debug key, and reads as the status bits.
+                    word = (word & 0xFFFF) | ((word & (1 << 1)) << 16);
+
+                    if let (true, 0, Some(pc)) = (was_halted, word & (1 << 17), self.breaks_at) {
+                        word |= 1 << 17;
+                        self.registers.insert(15, pc);
+                        // DFSR.BKPT
+                        self.write_8(ap, 0xE000_ED30, &[0b10, 0, 0, 0])?;
+                    }
+                }
+                self.write_8(ap, address + 4 * offset as u64, &word.to_le_bytes())?;
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    const DHCSR: u64 = 0xE000_EDF0;
+
+    #[test]
+    fn halt_guard_restores_the_run_state() {
+        let mut cortex_state = CortexState::new();
+        let mut core_state = CoreState::new(0);
+        let memory = Memory::new(FakeProbe::default(), MemoryAP::new(0));
+        let mut core = Core::new(M0::new(memory, &mut cortex_state).unwrap(), &mut core_state);
+        let timeout = Duration::from_millis(100);
+
+        assert!(!core.core_halted().unwrap());
+        {
+            let mut guard = core.halt_scoped(timeout).unwrap();
+            assert!(guard.was_running());
+            assert!(guard.core_halted().unwrap());
+        }
+        assert!(!core.core_halted().unwrap());
+
+        core.halt(timeout).unwrap();
+        {
+            let guard = core.halt_scoped(timeout).unwrap();
+            assert!(!guard.was_running());
+        }
+        assert!(core.core_halted().unwrap());
+    }
+
+    #[test]
+    fn run_to_address_removes_the_temporary_breakpoint() {
+        for (breaks_at, expected) in [
+            (0x100, RunToAddressResult::Reached),
+            (
+                0x200,
+                RunToAddressResult::Stopped {
+                    reason: HaltReason::Breakpoint,
+                    pc: 0x200,
+                },
+            ),
+        ] {
+            let probe = FakeProbe {
+                breaks_at: Some(breaks_at),
+                ..Default::default()
+            };
+            let mut cortex_state = CortexState::new();
+            let mut core_state = CoreState::new(0);
+            let memory = Memory::new(probe, MemoryAP::new(0));
+            let mut core = Core::new(M0::new(memory, &mut cortex_state).unwrap(), &mut core_state);
+            core.halt(Duration::from_millis(100)).unwrap();
+
+            // Without breakpoint units, a software breakpoint is used.
+            let result = core.run_to_address(0x100, Duration::from_millis(100));
+            assert_eq!(result.unwrap(), expected);
+            assert!(core.state.sw_breakpoints.is_empty());
+            assert_eq!(core.read_word_32(0x100).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn arguments_are_assigned_per_aapcs() {
+        use CallArg::{U32, U64};
+
+        assert_eq!(aapcs_arguments(&[U32(1), U32(2)]), (vec![1, 2], vec![]));
+        // 64 bit values use an even register pair.
+        assert_eq!(
+            aapcs_arguments(&[U32(1), U64(0x2_0000_0003)]),
+            (vec![1, 0, 3, 2], vec![])
+        );
+        assert_eq!(
+            aapcs_arguments(&[U32(1), U32(2), U32(3), U32(4), U32(5), U32(6)]),
+            (vec![1, 2, 3, 4], vec![5, 6])
+        );
+        // A 64 bit value which does not fit into the registers moves all following
+        // arguments to the stack, aligned to 8 bytes.
+        assert_eq!(
+            aapcs_arguments(&[U32(1), U32(2), U32(3), U64(0x5_0000_0004), U32(6)]),
+            (vec![1, 2, 3, 0], vec![4, 5, 6])
+        );
+        assert_eq!(
+            aapcs_arguments(&[U32(1), U32(2), U32(3), U32(4), U32(5), U64(0x7_0000_0006)]),
+            (vec![1, 2, 3, 4], vec![5, 0, 6, 7])
+        );
+    }
+
+    #[test]
+    fn small_64_bit_arguments_still_use_a_register_pair() {
+        use CallArg::{U32, U64};
+
+        assert_eq!(
+            aapcs_arguments(&[U32(1), U64(2), U32(3)]),
+            (vec![1, 0, 2, 0], vec![3])
+        );
+    }
+
+    #[test]
+    fn only_armv7m_cores_reset_only_the_core() {
+        const AIRCR: u64 = 0xE000_ED0C;
+
+        let mut cortex_state = CortexState::new();
+        let mut core_state = CoreState::new(0);
+        let memory = Memory::new(FakeProbe::default(), MemoryAP::new(0));
+        let mut core = Core::new(M4::new(memory, &mut cortex_state).unwrap(), &mut core_state);
+
+        core.reset_with(ResetType::SoftwareCoreReset).unwrap();
+        assert_eq!(core.read_word_32(AIRCR).unwrap(), 0x05FA_0001);
+        core.reset_with(ResetType::SoftwareSysReset).unwrap();
+        assert_eq!(core.read_word_32(AIRCR).unwrap(), 0x05FA_0004);
+        // The reset pin is driven by the probe, not the core.
+        assert!(core.reset_with(ResetType::Hardware).is_err());
+
+        let mut cortex_state = CortexState::new();
+        let mut core_state = CoreState::new(0);
+        let memory = Memory::new(FakeProbe::default(), MemoryAP::new(0));
+        let mut core = Core::new(M0::new(memory, &mut cortex_state).unwrap(), &mut core_state);
+
+        assert!(matches!(
+            core.reset_with(ResetType::SoftwareCoreReset),
+            Err(Error::NotSupported(_))
+        ));
+    }
+
+    #[test]
+    fn registers_are_restored_from_a_snapshot() {
+        for fpu in [false, true] {
+            let mut probe = FakeProbe::default();
+            if fpu {
+                // Coprocessor 10 is enabled in CPACR.
+                probe
+                    .write_32(MemoryAP::new(0), 0xE000_ED88, &[0b11 << 20])
+                    .unwrap();
+            }
+            for register in 0..16 {
+                probe.registers.insert(register, u32::from(register) + 1);
+            }
+
+            let mut cortex_state = CortexState::new();
+            let mut core_state = CoreState::new(0);
+            let memory = Memory::new(probe, MemoryAP::new(0));
+            let mut core = Core::new(M0::new(memory, &mut cortex_state).unwrap(), &mut core_state);
+
+            let snapshot = core.registers_snapshot().unwrap();
+            assert_eq!(snapshot.get(CoreRegisterAddress(15)), Some(16));
+            assert_eq!(snapshot.get(CoreRegisterAddress(0b1_0000)), Some(0));
+            // S0 and the FPSCR are only saved if there is an FPU.
+            assert_eq!(snapshot.get(CoreRegisterAddress(0b100_0000)).is_some(), fpu);
+            assert_eq!(snapshot.registers().count(), if fpu { 17 + 33 } else { 17 });
+
+            core.write_core_reg(CoreRegisterAddress(0), 0xdead_beef)
+                .unwrap();
+            core.write_core_reg(CoreRegisterAddress(15), 0x0800_0000)
+                .unwrap();
+            core.restore(&snapshot).unwrap();
+
+            assert_eq!(core.read_core_reg(CoreRegisterAddress(0)).unwrap(), 1);
+            assert_eq!(core.read_core_reg(CoreRegisterAddress(15)).unwrap(), 16);
+        }
+    }
+
+    fn big_endian_target() -> Target {
+        let chip: Chip = serde_yaml::from_str(
+            "name: big-endian
+memory_map: []
+flash_algorithms: []
+byte_order:
+  data: Big",
+        )
+        .unwrap();
+        Target::new(&chip, vec![], CoreType::M0)
+    }
+
+    #[test]
+    fn words_are_read_in_the_byte_order_of_the_target() {
+        let target = big_endian_target();
+        assert_eq!(target.byte_order.data, Endianness::Big);
+        assert_eq!(target.byte_order.instructions, Endianness::Little);
+
+        let mut probe = FakeProbe::default();
+        probe
+            .write_8(MemoryAP::new(0), 0x2000_0000, &[0x11, 0x22, 0x33, 0x44])
+            .unwrap();
+
+        let mut cortex_state = CortexState::new();
+        let mut core_state = CoreState::new(0);
+        let memory = Memory::new(probe, MemoryAP::new(0));
+        let mut core = Core::new(M0::new(memory, &mut cortex_state).unwrap(), &mut core_state)
+            .with_byte_order(target.byte_order);
+
+        assert_eq!(core.read_word_32(0x2000_0000).unwrap(), 0x1122_3344);
+        let mut bytes = [0; 4];
+        core.read_8(0x2000_0000, &mut bytes).unwrap();
+        assert_eq!(bytes, [0x11, 0x22, 0x33, 0x44]);
+
+        core.write_32(0x2000_0004, &[0x5566_7788]).unwrap();
+        core.read_8(0x2000_0004, &mut bytes).unwrap();
+        assert_eq!(bytes, [0x55, 0x66, 0x77, 0x88]);
+    }
+
+    #[test]
+    fn poll_interval_backs_off() {
+        let mut interval = MIN_POLL_INTERVAL;
+        let mut intervals = vec![];
+        for _ in 0..8 {
+            intervals.push(interval.as_millis());
+            interval = next_poll_interval(interval);
+        }
+
+        assert_eq!(intervals, vec![1, 2, 4, 8, 16, 32, 50, 50]);
+    }
+
+    #[test]
+    fn semihosting_instructions() {
+        // bkpt 0xab
+        assert_eq!(u16::from_le_bytes(ARM_SEMIHOSTING_INSTRUCTION), 0xBEAB);
+        assert_ne!(ARM_SEMIHOSTING_INSTRUCTION, ARM_BREAKPOINT_INSTRUCTION);
+
+        let words: Vec<_> = RISCV_SEMIHOSTING_SEQUENCE
+            .chunks(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        assert_eq!(words, vec![0x01F0_1013, 0x0010_0073, 0x4070_5013]);
+        assert_eq!(
+            RISCV_SEMIHOSTING_SEQUENCE[4..8],
+            RISCV_BREAKPOINT_INSTRUCTION
+        );
+    }
+
+    #[test]
+    fn riscv_breakpoint_matches_instruction_length() {
+        // addi sp, sp, -16
+        assert_eq!(
+            breakpoint_instruction(Architecture::Riscv, Endianness::Little, [0x13, 0x01]),
+            RISCV_BREAKPOINT_INSTRUCTION
+        );
+        // c.addi sp, -16
+        assert_eq!(
+            breakpoint_instruction(Architecture::Riscv, Endianness::Little, [0x41, 0x11]),
+            RISCV_COMPRESSED_BREAKPOINT_INSTRUCTION
+        );
+    }
+
+    #[test]
+    fn arm_uses_bkpt() {
+        assert_eq!(
+            breakpoint_instruction(Architecture::Arm, Endianness::Little, [0x80, 0xB5]),
+            ARM_BREAKPOINT_INSTRUCTION
+        );
+        assert_eq!(
+            breakpoint_instruction(Architecture::Arm, Endianness::Big, [0xB5, 0x80]),
+            ARM_BIG_ENDIAN_BREAKPOINT_INSTRUCTION
+        );
+    }
+
+    #[test]
+    fn steps_mask_interrupts_as_configured() {
+        for masking in [
+            InterruptMasking::None,
+            InterruptMasking::Debugger,
+            InterruptMasking::Primask,
+        ] {
+            let mut probe = FakeProbe {
+                breaks_at: Some(0x102),
+                ..Default::default()
+            };
+            // A `nop` at the PC, with `CONTROL.nPRIV` set and `PRIMASK` cleared.
+            probe.memory.insert(0x100, 0x00);
+            probe.memory.insert(0x101, 0xbf);
+            probe.registers.insert(15, 0x100);
+            probe.registers.insert(0b1_0100, 0x0100_0000);
+
+            let mut cortex_state = CortexState::new();
+            let mut core_state = CoreState::new(0);
+            let memory = Memory::new(probe, MemoryAP::new(0));
+            let mut core = Core::new(M0::new(memory, &mut cortex_state).unwrap(), &mut core_state);
+            core.halt(Duration::from_millis(100)).unwrap();
+
+            assert_eq!(core.step_with(masking).unwrap().pc, 0x102);
+
+            // C_MASKINTS
+            let dhcsr = core.read_word_32(DHCSR).unwrap();
+            assert_eq!(dhcsr & (1 << 3) != 0, masking != InterruptMasking::None);
+            assert_eq!(
+                core.read_core_reg(CoreRegisterAddress(0b1_0100)).unwrap(),
+                0x0100_0000
+            );
+        }
+    }
+
+    #[test]
+    fn special_register_accesses_are_detected() {
+        // cpsid i, cpsie i, cpsid f, msr primask, r0 and mrs r0, primask
+        for instruction in [0xb672, 0xb662, 0xb671, 0xf380, 0xf3ef] {
+            assert!(
+                accesses_special_registers(instruction),
+                "{:#x}",
+                instruction
+            );
+        }
+        // nop, push {r7, lr} and bl
+        for instruction in [0xbf00, 0xb580, 0xf000] {
+            assert!(
+                !accesses_special_registers(instruction),
+                "{:#x}",
+                instruction
+            );
+        }
+    }
+
+    #[test]
+    fn calls_out_of_a_range_are_detected() {
+        let range = 0x100..0x110;
+
+        // `bl` at 0x104, and `c.jal` at 0x108 on RISC-V.
+        assert_eq!(
+            call_return_address(&range, 0x104, 0x201, 0x109),
+            Some(0x108)
+        );
+        assert_eq!(
+            call_return_address(&range, 0x108, 0x200, 0x10a),
+            Some(0x10a)
+        );
+        // `pop {r7, pc}` after a call in the range, which keeps the return address from that call.
+        assert_eq!(call_return_address(&range, 0x10c, 0x109, 0x109), None);
+        // `bx lr` of a leaf function, and a branch to a function after loading the return address.
+        assert_eq!(call_return_address(&range, 0x10e, 0x301, 0x301), None);
+        assert_eq!(call_return_address(&range, 0x104, 0x201, 0x10d), None);
+    }
+}
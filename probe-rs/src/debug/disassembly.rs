@@ -0,0 +1,238 @@
+//! Disassembly of the code in the target memory, e.g. at the location the core stopped at.
+
+use super::DebugError;
+use crate::architecture::{arm::core::register, riscv::disassembly as riscv};
+use crate::{core::Architecture, Core, MemoryInterface};
+use capstone::{arch::arm::ArchExtraMode, arch::arm::ArchMode, prelude::*, Capstone, Endian};
+use std::fmt;
+
+/// The `T` bit of `XPSR`, which is set while the core executes Thumb instructions.
+const XPSR_THUMB: u32 = 1 << 24;
+
+/// The instruction set a piece of code is decoded as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionSet {
+    /// The Thumb instructions of Arm cores, which are the only instructions of Cortex-M cores.
+    Thumb,
+    /// The 32-bit Arm instructions.
+    Arm,
+    /// RISC-V instructions of RV32IMC.
+    Riscv,
+}
+
+impl InstructionSet {
+    /// Returns the instruction set the core currently executes.
+    ///
+    /// For Arm cores, this is selected by the `T` bit of `XPSR`.
+    pub fn of_core(core: &mut Core) -> Result<Self, crate::Error> {
+        match core.architecture() {
+            Architecture::Riscv => Ok(InstructionSet::Riscv),
+            Architecture::Arm => {
+                let xpsr = core.read_core_reg(register::XPSR.address)?;
+                if xpsr & XPSR_THUMB != 0 {
+                    Ok(InstructionSet::Thumb)
+                } else {
+                    Ok(InstructionSet::Arm)
+                }
+            }
+        }
+    }
+
+    /// The alignment of the instructions, which is the size of the smallest instruction.
+    fn alignment(self) -> u32 {
+        match self {
+            InstructionSet::Thumb | InstructionSet::Riscv => 2,
+            InstructionSet::Arm => 4,
+        }
+    }
+}
+
+/// A single disassembled instruction.
+///
+/// Bytes which cannot be decoded, like data placed between functions, are returned as
+/// `.short` or `.word` directives of the smallest instruction size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    /// The address of the first byte of the instruction.
+    pub address: u32,
+    /// The encoding of the instruction.
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    pub operands: String,
+}
+
+impl Instruction {
+    /// Creates a directive for bytes which are not a valid instruction.
+    fn data(address: u32, bytes: &[u8]) -> Self {
+        let (mnemonic, value) = match *bytes {
+            [a, b, c, d] => (".word", u32::from_le_bytes([a, b, c, d])),
+            [a, b] => (".short", u16::from_le_bytes([a, b]).into()),
+            _ => (
+                ".byte",
+                bytes
+                    .iter()
+                    .fold(0, |value, byte| value << 8 | *byte as u32),
+            ),
+        };
+
+        Instruction {
+            address,
+            bytes: bytes.to_vec(),
+            mnemonic: mnemonic.to_owned(),
+            operands: format!("{:#x}", value),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Formats the instruction like `0x08000140: 80 b5        push {r7, lr}`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let text = format!("{} {}", self.mnemonic, self.operands);
+
+        write!(
+            f,
+            "{:#010x}: {:<12} {}",
+            self.address,
+            bytes.join(" "),
+            text.trim_end()
+        )
+    }
+}
+
+/// Reads `len` bytes of code at `address`, and disassembles it with the instruction set the core executes.
+///
+/// An address with the lowest bit set, like the address of a Thumb function, is always disassembled
+/// as Thumb code. Addresses which are not aligned to an instruction are rounded down.
+///
+/// The disassembly starts at the given address, so if it is in the middle of an instruction,
+/// the first instructions are decoded from the wrong bytes. Starting at a known location,
+/// like the start of a function or the program counter, and disassembling forward avoids this.
+pub fn disassemble_memory(
+    core: &mut Core,
+    address: u32,
+    len: u32,
+) -> Result<Vec<Instruction>, DebugError> {
+    let instruction_set = match (address & 1, core.architecture()) {
+        (1, Architecture::Arm) => InstructionSet::Thumb,
+        _ => InstructionSet::of_core(core)?,
+    };
+
+    let start = address & !(instruction_set.alignment() - 1);
+    let mut code = vec![0; (address + len - start) as usize];
+    core.read(start.into(), &mut code)?;
+
+    disassemble(instruction_set, start, &code)
+}
+
+/// Disassembles `code`, which is located at `address`.
+///
+/// The last instruction is returned as data if it does not fit into `code`.
+pub fn disassemble(
+    instruction_set: InstructionSet,
+    address: u32,
+    code: &[u8],
+) -> Result<Vec<Instruction>, DebugError> {
+    let capstone = match instruction_set {
+        InstructionSet::Thumb => Some(
+            Capstone::new()
+                .arm()
+                .mode(ArchMode::Thumb)
+                .extra_mode(std::iter::once(ArchExtraMode::MClass)),
+        ),
+        InstructionSet::Arm => Some(Capstone::new().arm().mode(ArchMode::Arm)),
+        InstructionSet::Riscv => None,
+    }
+    .map(|builder| builder.endian(Endian::Little).build())
+    .transpose()
+    .map_err(DebugError::Disassembler)?;
+
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+
+    while offset < code.len() {
+        let instruction_address = address + offset as u32;
+        let remaining = &code[offset..];
+
+        let decoded =
+            match &capstone {
+                Some(capstone) => capstone
+                    .disasm_count(remaining, instruction_address.into(), 1)
+                    .map_err(DebugError::Disassembler)?
+                    .iter()
+                    .next()
+                    .map(|instruction| Instruction {
+                        address: instruction_address,
+                        bytes: instruction.bytes().to_vec(),
+                        mnemonic: instruction.mnemonic().unwrap_or_default().to_owned(),
+                        operands: instruction.op_str().unwrap_or_default().to_owned(),
+                    }),
+                None => riscv::decode(instruction_address, remaining).map(
+                    |(size, mnemonic, operands)| Instruction {
+                        address: instruction_address,
+                        bytes: remaining[..size].to_vec(),
+                        mnemonic: mnemonic.to_owned(),
+                        operands,
+                    },
+                ),
+            };
+
+        let instruction = decoded.unwrap_or_else(|| {
+            let size = (instruction_set.alignment() as usize).min(remaining.len());
+            Instruction::data(instruction_address, &remaining[..size])
+        });
+        offset += instruction.bytes.len();
+        instructions.push(instruction);
+    }
+
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(instructions: &[Instruction]) -> Vec<String> {
+        instructions
+            .iter()
+            .map(|instruction| {
+                format!("{} {}", instruction.mnemonic, instruction.operands)
+                    .trim_end()
+                    .to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn thumb_code_is_disassembled() {
+        // push {r7, lr}; bl.w; bx lr
+        let code = [0x80, 0xb5, 0x00, 0xf0, 0x02, 0xf8, 0x70, 0x47];
+        let instructions = disassemble(InstructionSet::Thumb, 0x0800_0100, &code).unwrap();
+
+        assert_eq!(instructions[0].mnemonic, "push");
+        assert_eq!(instructions[1].address, 0x0800_0102);
+        assert_eq!(instructions[1].bytes.len(), 4);
+        assert_eq!(instructions[1].mnemonic, "bl");
+        assert_eq!(instructions.last().unwrap().mnemonic, "bx");
+        assert_eq!(
+            instructions.last().unwrap().to_string(),
+            "0x08000106: 70 47        bx lr"
+        );
+    }
+
+    #[test]
+    fn undecodable_bytes_are_data() {
+        // The first half of a 32-bit Thumb instruction at the end of the code.
+        let instructions =
+            disassemble(InstructionSet::Thumb, 0x100, &[0x70, 0x47, 0x00, 0xf0]).unwrap();
+        assert_eq!(text(&instructions), vec!["bx lr", ".short 0xf000"]);
+
+        let instructions = disassemble(
+            InstructionSet::Riscv,
+            0x100,
+            &[0x00, 0x00, 0x82, 0x80, 0x13],
+        )
+        .unwrap();
+        assert_eq!(text(&instructions), vec![".short 0x0", "ret", ".byte 0x13"]);
+    }
+}
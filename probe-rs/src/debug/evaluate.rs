@@ -0,0 +1,907 @@
+//! Evaluation of variables, formatted according to their DWARF type.
+
+use super::{extract_name, DebugInfo, FunctionDie, Registers, StackFrame, UnitInfo, R};
+use crate::MemoryInterface;
+use gimli::{Reader, UnitOffset};
+
+/// The largest number of array elements which are evaluated.
+const MAX_ARRAY_ELEMENTS: u64 = 64;
+
+/// The deepest nesting of types which is evaluated.
+const MAX_TYPE_DEPTH: usize = 16;
+
+/// A variable of a stack frame, with its value formatted according to its type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableValue {
+    pub name: String,
+    pub type_name: String,
+    /// The formatted value, or the reason why the value is not available.
+    pub value: String,
+    /// The members of structs, and the elements of arrays.
+    pub children: Vec<VariableValue>,
+}
+
+/// The reason why the value of a variable is not available.
+#[derive(Debug)]
+pub(super) enum Unavailable {
+    OptimizedOut,
+    Memory(u64),
+    Register(u16),
+    Unsupported(&'static str),
+    Dwarf(gimli::Error),
+}
+
+impl std::fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Unavailable::OptimizedOut => write!(f, "<optimized out>"),
+            Unavailable::Memory(address) => {
+                write!(f, "<unable to read memory at {:#010x}>", address)
+            }
+            Unavailable::Register(register) => write!(f, "<register {} not available>", register),
+            Unavailable::Unsupported(what) => write!(f, "<unsupported: {}>", what),
+            Unavailable::Dwarf(e) => write!(f, "<invalid debug information: {}>", e),
+        }
+    }
+}
+
+impl From<gimli::Error> for Unavailable {
+    fn from(error: gimli::Error) -> Self {
+        Unavailable::Dwarf(error)
+    }
+}
+
+/// The registers and addresses used to evaluate DWARF expressions in a stack frame.
+pub(super) struct FrameContext<'frame> {
+    pub(super) registers: &'frame Registers,
+    pub(super) cfa: Option<u32>,
+    pub(super) frame_base: Option<u64>,
+}
+
+impl DebugInfo {
+    /// Evaluates the variables and parameters in scope at the program counter of `frame`,
+    /// and formats their values according to their types.
+    ///
+    /// Variables whose value cannot be determined, for example because they were optimized out,
+    /// are returned with the reason as their value.
+    pub fn evaluate_variables(
+        &self,
+        memory: &mut impl MemoryInterface,
+        frame: &StackFrame,
+    ) -> Vec<VariableValue> {
+        let address = u64::from(frame.pc);
+
+        let mut units = self.get_units();
+        while let Some(unit_info) = self.get_next_unit_info(&mut units) {
+            if let Some(die_cursor_state) = unit_info.get_function_die(address) {
+                let function_die = &die_cursor_state.function_die;
+                let mut context = FrameContext {
+                    registers: &frame.registers,
+                    cfa: frame.cfa,
+                    frame_base: None,
+                };
+                context.frame_base = unit_info.frame_base(memory, &context, function_die);
+
+                let mut variables = vec![];
+                unit_info.collect_variables(function_die.offset(), address, &mut variables);
+
+                return variables
+                    .into_iter()
+                    .map(|(_, variable)| {
+                        unit_info.evaluate_variable(memory, &context, variable, address)
+                    })
+                    .collect();
+            }
+        }
+
+        vec![]
+    }
+}
+
+impl<'debuginfo> UnitInfo<'debuginfo> {
+    pub(super) fn children(&self, offset: UnitOffset) -> Result<Vec<UnitOffset>, Unavailable> {
+        let mut tree = self.unit.entries_tree(Some(offset))?;
+        let mut children = tree.root()?.children();
+
+        let mut offsets = vec![];
+        while let Some(child) = children.next()? {
+            offsets.push(child.entry().offset());
+        }
+        Ok(offsets)
+    }
+
+    /// Returns the value of the attribute `name` of the entry at `offset`,
+    /// following `DW_AT_abstract_origin` references.
+    fn origin_attr(
+        &self,
+        offset: UnitOffset,
+        name: gimli::DwAt,
+    ) -> Option<gimli::AttributeValue<R>> {
+        let mut offset = offset;
+
+        for _ in 0..super::MAX_ORIGIN_DEPTH {
+            let entry = self.unit.entry(offset).ok()?;
+            if let Some(value) = entry.attr_value(name).ok().flatten() {
+                return Some(value);
+            }
+
+            offset = match entry
+                .attr_value(gimli::DW_AT_abstract_origin)
+                .ok()
+                .flatten()
+            {
+                Some(gimli::AttributeValue::UnitRef(origin)) => origin,
+                _ => return None,
+            };
+        }
+
+        None
+    }
+
+    pub(super) fn attr(
+        &self,
+        offset: UnitOffset,
+        name: gimli::DwAt,
+    ) -> Option<gimli::AttributeValue<R>> {
+        self.unit
+            .entry(offset)
+            .ok()?
+            .attr_value(name)
+            .ok()
+            .flatten()
+    }
+
+    pub(super) fn attr_type(&self, offset: UnitOffset) -> Option<UnitOffset> {
+        match self.origin_attr(offset, gimli::DW_AT_type)? {
+            gimli::AttributeValue::UnitRef(type_offset) => Some(type_offset),
+            _ => None,
+        }
+    }
+
+    pub(super) fn attr_name(&self, offset: UnitOffset) -> Option<String> {
+        extract_name(
+            self.debug_info,
+            self.origin_attr(offset, gimli::DW_AT_name)?,
+        )
+    }
+
+    /// Collects the variables and parameters of the function at `offset`,
+    /// including the ones of the lexical blocks containing `address`.
+    ///
+    /// Each variable is returned together with the function or block it is defined in.
+    pub(super) fn collect_variables(
+        &self,
+        offset: UnitOffset,
+        address: u64,
+        variables: &mut Vec<(UnitOffset, UnitOffset)>,
+    ) {
+        for child in self.children(offset).unwrap_or_default() {
+            let entry = match self.unit.entry(child) {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+
+            match entry.tag() {
+                gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
+                    variables.push((offset, child))
+                }
+                gimli::DW_TAG_lexical_block if self.contains_address(&entry, address) => {
+                    self.collect_variables(child, address, variables)
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Evaluates `DW_AT_frame_base` of a function, which usually is a register or the CFA.
+    pub(super) fn frame_base(
+        &self,
+        memory: &mut impl MemoryInterface,
+        context: &FrameContext,
+        function_die: &FunctionDie,
+    ) -> Option<u64> {
+        let expression = match function_die.attr_value(gimli::DW_AT_frame_base).ok()?? {
+            gimli::AttributeValue::Exprloc(expression) => expression,
+            _ => return None,
+        };
+
+        let pieces = self.evaluate_expression(memory, context, expression).ok()?;
+        match &pieces.first()?.location {
+            gimli::Location::Register { register } => {
+                context.registers.get(register.0 as usize).map(u64::from)
+            }
+            gimli::Location::Address { address } => Some(*address),
+            gimli::Location::Value { value } => value.to_u64(u64::MAX).ok(),
+            _ => None,
+        }
+    }
+
+    fn evaluate_expression(
+        &self,
+        memory: &mut impl MemoryInterface,
+        context: &FrameContext,
+        expression: gimli::Expression<R>,
+    ) -> Result<Vec<gimli::Piece<R>>, Unavailable> {
+        let mut evaluation = expression.evaluation(self.unit.encoding());
+        let mut result = evaluation.evaluate()?;
+
+        loop {
+            use gimli::EvaluationResult::*;
+
+            result = match result {
+                Complete => break,
+                RequiresMemory { address, size, .. } => {
+                    let mut buff = vec![0u8; size as usize];
+                    memory
+                        .read(address, &mut buff)
+                        .map_err(|_| Unavailable::Memory(address))?;
+
+                    let value = match size {
+                        1 => gimli::Value::U8(buff[0]),
+                        2 => gimli::Value::U16(read_unsigned(&buff) as u16),
+                        4 => gimli::Value::U32(read_unsigned(&buff) as u32),
+                        8 => gimli::Value::U64(read_unsigned(&buff)),
+                        _ => return Err(Unavailable::Unsupported("memory access size")),
+                    };
+                    evaluation.resume_with_memory(value)?
+                }
+                RequiresRegister {
+                    register,
+                    base_type,
+                } => {
+                    if base_type != UnitOffset(0) {
+                        return Err(Unavailable::Unsupported("typed registers"));
+                    }
+
+                    let value = context
+                        .registers
+                        .get(register.0 as usize)
+                        .ok_or(Unavailable::Register(register.0))?;
+                    evaluation.resume_with_register(gimli::Value::Generic(u64::from(value)))?
+                }
+                RequiresFrameBase => evaluation.resume_with_frame_base(
+                    context
+                        .frame_base
+                        .ok_or(Unavailable::Unsupported("missing frame base"))?,
+                )?,
+                RequiresCallFrameCfa => evaluation.resume_with_call_frame_cfa(u64::from(
+                    context
+                        .cfa
+                        .ok_or(Unavailable::Unsupported("missing call frame address"))?,
+                ))?,
+                // Firmware is not relocated.
+                RequiresRelocatedAddress(address) => {
+                    evaluation.resume_with_relocated_address(address)?
+                }
+                _ => return Err(Unavailable::Unsupported("DWARF expression operation")),
+            }
+        }
+
+        Ok(evaluation.result())
+    }
+
+    /// Returns the `size` bytes stored in the location described by `pieces`.
+    ///
+    /// Variables can be split into several pieces, for example a 64 bit value stored in two registers.
+    fn read_pieces(
+        &self,
+        memory: &mut impl MemoryInterface,
+        context: &FrameContext,
+        pieces: &[gimli::Piece<R>],
+        size: usize,
+    ) -> Result<Vec<u8>, Unavailable> {
+        let mut bytes = vec![];
+
+        for piece in pieces {
+            let piece_size = match piece.size_in_bits {
+                Some(bits) if bits % 8 == 0 => (bits / 8) as usize,
+                Some(_) => return Err(Unavailable::Unsupported("pieces smaller than a byte")),
+                None => size.saturating_sub(bytes.len()),
+            };
+
+            let mut piece_bytes = match &piece.location {
+                gimli::Location::Empty => return Err(Unavailable::OptimizedOut),
+                gimli::Location::Address { address } => {
+                    let mut buff = vec![0; piece_size];
+                    memory
+                        .read(*address, &mut buff)
+                        .map_err(|_| Unavailable::Memory(*address))?;
+                    buff
+                }
+                gimli::Location::Register { register } => context
+                    .registers
+                    .get(register.0 as usize)
+                    .ok_or(Unavailable::Register(register.0))?
+                    .to_le_bytes()
+                    .to_vec(),
+                gimli::Location::Value { value } => value
+                    .to_u64(u64::MAX)
+                    .map_err(|_| Unavailable::Unsupported("floating point values"))?
+                    .to_le_bytes()
+                    .to_vec(),
+                gimli::Location::Bytes { value } => value.to_slice()?.to_vec(),
+                gimli::Location::ImplicitPointer { .. } => {
+                    return Err(Unavailable::Unsupported("implicit pointers"))
+                }
+            };
+
+            piece_bytes.resize(piece_size, 0);
+            bytes.extend(piece_bytes);
+        }
+
+        bytes.resize(size, 0);
+        Ok(bytes)
+    }
+
+    /// Returns the bytes of the variable at `offset`, from its location or its constant value.
+    pub(super) fn variable_bytes(
+        &self,
+        memory: &mut impl MemoryInterface,
+        context: &FrameContext,
+        offset: UnitOffset,
+        address: u64,
+        size: usize,
+    ) -> Result<Vec<u8>, Unavailable> {
+        if let Some(value) = self.attr(offset, gimli::DW_AT_const_value) {
+            let mut bytes = match value {
+                gimli::AttributeValue::Block(block) => block.to_slice()?.to_vec(),
+                value => value
+                    .udata_value()
+                    .or_else(|| value.sdata_value().map(|value| value as u64))
+                    .ok_or(Unavailable::Unsupported("constant value"))?
+                    .to_le_bytes()
+                    .to_vec(),
+            };
+            bytes.resize(size, 0);
+            return Ok(bytes);
+        }
+
+        let location = self
+            .attr(offset, gimli::DW_AT_location)
+            .ok_or(Unavailable::OptimizedOut)?;
+
+        let expression = match location {
+            gimli::AttributeValue::Exprloc(expression) => expression,
+            location => {
+                // Optimized code stores variables in different locations over the function.
+                let mut locations = self
+                    .debug_info
+                    .dwarf
+                    .attr_locations(&self.unit, location)?
+                    .ok_or(Unavailable::Unsupported("location"))?;
+
+                let mut expression = None;
+                while let Some(entry) = locations.next()? {
+                    if entry.range.begin <= address && address < entry.range.end {
+                        expression = Some(entry.data);
+                        break;
+                    }
+                }
+                expression.ok_or(Unavailable::OptimizedOut)?
+            }
+        };
+
+        let pieces = self.evaluate_expression(memory, context, expression)?;
+        self.read_pieces(memory, context, &pieces, size)
+    }
+
+    fn evaluate_variable(
+        &self,
+        memory: &mut impl MemoryInterface,
+        context: &FrameContext,
+        offset: UnitOffset,
+        address: u64,
+    ) -> VariableValue {
+        let name = self
+            .attr_name(offset)
+            .unwrap_or_else(|| "<unnamed>".to_owned());
+
+        let type_offset = match self.attr_type(offset) {
+            Some(type_offset) => type_offset,
+            None => {
+                return unavailable(
+                    name,
+                    "<unknown type>".to_owned(),
+                    Unavailable::Unsupported("missing type"),
+                )
+            }
+        };
+        let type_name = self.type_name(type_offset);
+
+        let size = match self.type_size(type_offset, 0) {
+            Some(size) => size as usize,
+            None => return unavailable(name, type_name, Unavailable::Unsupported("unsized type")),
+        };
+
+        match self.variable_bytes(memory, context, offset, address, size) {
+            Ok(bytes) => self.format_value(name, type_offset, &bytes, 0),
+            Err(reason) => unavailable(name, type_name, reason),
+        }
+    }
+
+    /// Returns the type at `offset`, skipping typedefs and qualifiers.
+    pub(super) fn resolve_type(&self, offset: UnitOffset) -> Option<(UnitOffset, gimli::DwTag)> {
+        let mut offset = offset;
+
+        for _ in 0..MAX_TYPE_DEPTH {
+            let entry = self.unit.entry(offset).ok()?;
+            match entry.tag() {
+                gimli::DW_TAG_typedef
+                | gimli::DW_TAG_const_type
+                | gimli::DW_TAG_volatile_type
+                | gimli::DW_TAG_atomic_type => offset = self.attr_type(offset)?,
+                tag => return Some((offset, tag)),
+            }
+        }
+
+        None
+    }
+
+    pub(super) fn type_name(&self, offset: UnitOffset) -> String {
+        if let Some(name) = self.attr_name(offset) {
+            return name;
+        }
+
+        let entry = match self.unit.entry(offset) {
+            Ok(entry) => entry,
+            Err(_) => return "<unknown type>".to_owned(),
+        };
+        let inner = || {
+            self.attr_type(offset)
+                .map(|inner| self.type_name(inner))
+                .unwrap_or_else(|| "void".to_owned())
+        };
+
+        match entry.tag() {
+            gimli::DW_TAG_pointer_type => format!("*{}", inner()),
+            gimli::DW_TAG_reference_type => format!("&{}", inner()),
+            gimli::DW_TAG_const_type => format!("const {}", inner()),
+            gimli::DW_TAG_volatile_type => format!("volatile {}", inner()),
+            gimli::DW_TAG_array_type => match self.array_length(offset) {
+                Some(length) => format!("[{}; {}]", inner(), length),
+                None => format!("[{}]", inner()),
+            },
+            _ => "<unnamed type>".to_owned(),
+        }
+    }
+
+    pub(super) fn type_size(&self, offset: UnitOffset, depth: usize) -> Option<u64> {
+        if depth > MAX_TYPE_DEPTH {
+            return None;
+        }
+
+        let (offset, tag) = self.resolve_type(offset)?;
+        if let Some(size) = self.attr(offset, gimli::DW_AT_byte_size) {
+            return size.udata_value();
+        }
+
+        match tag {
+            gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => {
+                Some(u64::from(self.unit.encoding().address_size))
+            }
+            gimli::DW_TAG_array_type => {
+                let element = self.type_size(self.attr_type(offset)?, depth + 1)?;
+                Some(element * self.array_length(offset)?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the number of elements of the array type at `offset`.
+    pub(super) fn array_length(&self, offset: UnitOffset) -> Option<u64> {
+        let subrange = self
+            .children(offset)
+            .ok()?
+            .into_iter()
+            .find(|child| matches!(self.unit.entry(*child), Ok(entry) if entry.tag() == gimli::DW_TAG_subrange_type))?;
+
+        if let Some(count) = self.attr(subrange, gimli::DW_AT_count) {
+            return count.udata_value();
+        }
+
+        let lower_bound = self
+            .attr(subrange, gimli::DW_AT_lower_bound)
+            .and_then(|bound| bound.sdata_value())
+            .unwrap_or(0);
+        let upper_bound = self
+            .attr(subrange, gimli::DW_AT_upper_bound)?
+            .sdata_value()?;
+
+        Some((upper_bound - lower_bound + 1).max(0) as u64)
+    }
+
+    /// Formats `bytes` according to the type at `offset`.
+    pub(super) fn format_value(
+        &self,
+        name: String,
+        offset: UnitOffset,
+        bytes: &[u8],
+        depth: usize,
+    ) -> VariableValue {
+        let type_name = self.type_name(offset);
+
+        if depth > MAX_TYPE_DEPTH {
+            return unavailable(
+                name,
+                type_name,
+                Unavailable::Unsupported("deeply nested type"),
+            );
+        }
+
+        let (offset, tag) = match self.resolve_type(offset) {
+            Some(resolved) => resolved,
+            None => return unavailable(name, type_name, Unavailable::Unsupported("type")),
+        };
+
+        let mut value = VariableValue {
+            name,
+            type_name,
+            value: String::new(),
+            children: vec![],
+        };
+
+        let result = match tag {
+            gimli::DW_TAG_base_type => self.format_base_type(offset, bytes),
+            gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => {
+                Ok(format!("{:#010x}", read_unsigned(bytes)))
+            }
+            gimli::DW_TAG_enumeration_type => self.format_enumeration(offset, bytes),
+            gimli::DW_TAG_array_type => self.format_array(offset, bytes, depth, &mut value),
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type | gimli::DW_TAG_union_type => {
+                self.format_struct(offset, bytes, depth, &mut value)
+            }
+            _ => Err(Unavailable::Unsupported("type")),
+        };
+
+        value.value = result.unwrap_or_else(|reason| reason.to_string());
+        value
+    }
+
+    fn format_base_type(&self, offset: UnitOffset, bytes: &[u8]) -> Result<String, Unavailable> {
+        let encoding = match self.attr(offset, gimli::DW_AT_encoding) {
+            Some(gimli::AttributeValue::Encoding(encoding)) => encoding,
+            _ => return Err(Unavailable::Unsupported("base type without encoding")),
+        };
+
+        format_base_value(encoding, bytes)
+    }
+
+    fn format_enumeration(&self, offset: UnitOffset, bytes: &[u8]) -> Result<String, Unavailable> {
+        let value = read_unsigned(bytes);
+
+        for child in self.children(offset)? {
+            let enumerator = self
+                .attr(child, gimli::DW_AT_const_value)
+                .and_then(|value| {
+                    value
+                        .udata_value()
+                        .or_else(|| value.sdata_value().map(|value| value as u64))
+                })
+                .map(|enumerator| truncate(enumerator, bytes.len()));
+
+            if enumerator == Some(value) {
+                return Ok(self
+                    .attr_name(child)
+                    .unwrap_or_else(|| format!("{}", value)));
+            }
+        }
+
+        Ok(format!("<invalid value {}>", value))
+    }
+
+    fn format_array(
+        &self,
+        offset: UnitOffset,
+        bytes: &[u8],
+        depth: usize,
+        value: &mut VariableValue,
+    ) -> Result<String, Unavailable> {
+        let element_type = self
+            .attr_type(offset)
+            .ok_or(Unavailable::Unsupported("array without element type"))?;
+        let element_size =
+            self.type_size(element_type, depth + 1)
+                .ok_or(Unavailable::Unsupported("unsized array element"))? as usize;
+        let length = self.array_length(offset).unwrap_or(0);
+
+        for index in 0..length.min(MAX_ARRAY_ELEMENTS) as usize {
+            let start = index * element_size;
+            let element = bytes
+                .get(start..start + element_size)
+                .ok_or(Unavailable::Unsupported("array larger than its type"))?;
+
+            value.children.push(self.format_value(
+                format!("[{}]", index),
+                element_type,
+                element,
+                depth + 1,
+            ));
+        }
+
+        let mut elements: Vec<&str> = value.children.iter().map(|c| c.value.as_str()).collect();
+        if length > MAX_ARRAY_ELEMENTS {
+            elements.push("...");
+        }
+        Ok(format!("[{}]", elements.join(", ")))
+    }
+
+    fn format_struct(
+        &self,
+        offset: UnitOffset,
+        bytes: &[u8],
+        depth: usize,
+        value: &mut VariableValue,
+    ) -> Result<String, Unavailable> {
+        for child in self.children(offset)? {
+            let tag = self.unit.entry(child)?.tag();
+            match tag {
+                gimli::DW_TAG_member => {
+                    value
+                        .children
+                        .push(self.format_member(child, bytes, depth)?);
+                }
+                // Rust enums are structs with a variant part, containing the active variant.
+                gimli::DW_TAG_variant_part => {
+                    let variant = self.format_variant_part(child, bytes, depth)?;
+                    let formatted = variant.value.clone();
+                    value.children = variant.children;
+                    return Ok(formatted);
+                }
+                _ => (),
+            }
+        }
+
+        if value.children.is_empty() {
+            return Ok(value.type_name.clone());
+        }
+
+        let members: Vec<String> = value
+            .children
+            .iter()
+            .map(|member| format!("{}: {}", member.name, member.value))
+            .collect();
+        Ok(format!("{} {{ {} }}", value.type_name, members.join(", ")))
+    }
+
+    fn format_member(
+        &self,
+        member: UnitOffset,
+        bytes: &[u8],
+        depth: usize,
+    ) -> Result<VariableValue, Unavailable> {
+        let name = self
+            .attr_name(member)
+            .unwrap_or_else(|| "<unnamed>".to_owned());
+        let member_type = self
+            .attr_type(member)
+            .ok_or(Unavailable::Unsupported("member without type"))?;
+
+        let start = match self.attr(member, gimli::DW_AT_data_member_location) {
+            Some(location) => location
+                .udata_value()
+                .ok_or(Unavailable::Unsupported("member location expressions"))?
+                as usize,
+            // Members of unions do not have a location.
+            None => 0,
+        };
+        let size = self
+            .type_size(member_type, depth + 1)
+            .ok_or(Unavailable::Unsupported("unsized member"))? as usize;
+
+        let member_bytes = bytes
+            .get(start..start + size)
+            .ok_or(Unavailable::Unsupported("member outside of its struct"))?;
+
+        Ok(self.format_value(name, member_type, member_bytes, depth + 1))
+    }
+
+    /// Returns the active variant of a variant part, selected by its discriminant.
+    fn format_variant_part(
+        &self,
+        variant_part: UnitOffset,
+        bytes: &[u8],
+        depth: usize,
+    ) -> Result<VariableValue, Unavailable> {
+        let discriminant = match self.attr(variant_part, gimli::DW_AT_discr) {
+            Some(gimli::AttributeValue::UnitRef(discriminant)) => {
+                let discriminant = self.format_member(discriminant, bytes, depth)?;
+                discriminant.value.parse::<i128>().ok()
+            }
+            _ => None,
+        };
+
+        let mut default = None;
+        for child in self.children(variant_part)? {
+            if self.unit.entry(child)?.tag() != gimli::DW_TAG_variant {
+                continue;
+            }
+
+            let discriminant_value = self
+                .attr(child, gimli::DW_AT_discr_value)
+                .and_then(|value| {
+                    value
+                        .udata_value()
+                        .map(i128::from)
+                        .or_else(|| value.sdata_value().map(i128::from))
+                });
+
+            match discriminant_value {
+                Some(value) if Some(value) != discriminant => continue,
+                Some(_) => return self.format_variant(child, bytes, depth),
+                None => default = Some(child),
+            }
+        }
+
+        match default {
+            Some(variant) => self.format_variant(variant, bytes, depth),
+            None => Err(Unavailable::Unsupported("enum without matching variant")),
+        }
+    }
+
+    fn format_variant(
+        &self,
+        variant: UnitOffset,
+        bytes: &[u8],
+        depth: usize,
+    ) -> Result<VariableValue, Unavailable> {
+        for child in self.children(variant)? {
+            if self.unit.entry(child)?.tag() == gimli::DW_TAG_member {
+                return self.format_member(child, bytes, depth);
+            }
+        }
+
+        Err(Unavailable::Unsupported("empty enum variant"))
+    }
+}
+
+pub(super) fn unavailable(name: String, type_name: String, reason: Unavailable) -> VariableValue {
+    VariableValue {
+        name,
+        type_name,
+        value: reason.to_string(),
+        children: vec![],
+    }
+}
+
+/// Reads a little endian value of up to 8 bytes.
+fn read_unsigned(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .take(8)
+        .rev()
+        .fold(0, |value, byte| value << 8 | u64::from(*byte))
+}
+
+/// Removes the bits of `value` not contained in `size` bytes.
+fn truncate(value: u64, size: usize) -> u64 {
+    if size >= 8 {
+        value
+    } else {
+        value & ((1 << (size * 8)) - 1)
+    }
+}
+
+/// Formats a value of a base type with `encoding`.
+fn format_base_value(encoding: gimli::DwAte, bytes: &[u8]) -> Result<String, Unavailable> {
+    let value = read_unsigned(bytes);
+
+    Ok(match encoding {
+        gimli::DW_ATE_boolean => (value != 0).to_string(),
+        gimli::DW_ATE_signed | gimli::DW_ATE_signed_char => {
+            let shift = 64 - 8 * bytes.len().min(8);
+            (((value << shift) as i64) >> shift).to_string()
+        }
+        gimli::DW_ATE_unsigned | gimli::DW_ATE_unsigned_char => value.to_string(),
+        gimli::DW_ATE_float => match bytes.len() {
+            4 => f32::from_bits(value as u32).to_string(),
+            8 => f64::from_bits(value).to_string(),
+            _ => return Err(Unavailable::Unsupported("float size")),
+        },
+        gimli::DW_ATE_UTF => match std::char::from_u32(value as u32) {
+            Some(c) => format!("{:?}", c),
+            None => format!("<invalid char {:#x}>", value),
+        },
+        _ => format!("{:#x}", value),
+    })
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use super::*;
+
+    #[test]
+    fn base_values() {
+        let format = |encoding, bytes: &[u8]| format_base_value(encoding, bytes).unwrap();
+
+        assert_eq!(format(gimli::DW_ATE_signed, &[0xfe, 0xff]), "-2");
+        assert_eq!(format(gimli::DW_ATE_unsigned, &[0xfe, 0xff]), "65534");
+        assert_eq!(format(gimli::DW_ATE_boolean, &[1]), "true");
+        assert_eq!(format(gimli::DW_ATE_float, &1.5f32.to_le_bytes()), "1.5");
+        assert_eq!(format(gimli::DW_ATE_UTF, &[0x41, 0, 0, 0]), "'A'");
+        assert_eq!(
+            format(gimli::DW_ATE_signed, &i64::MIN.to_le_bytes()),
+            i64::MIN.to_string()
+        );
+    }
+
+    #[test]
+    fn values_are_truncated_to_their_size() {
+        assert_eq!(read_unsigned(&[0x78, 0x56, 0x34, 0x12]), 0x1234_5678);
+        assert_eq!(truncate(u64::MAX, 1), 0xff);
+        assert_eq!(truncate(u64::MAX, 8), u64::MAX);
+    }
+
+    /// Memory where every byte contains the lowest byte of its address.
+    pub(crate) struct AddressPattern;
+
+    impl MemoryInterface for AddressPattern {
+        fn read_word_32(&mut self, address: u64) -> Result<u32, crate::Error> {
+            let word = [0, 1, 2, 3].map(|offset| address.wrapping_add(offset) as u8);
+            Ok(u32::from_le_bytes(word))
+        }
+
+        fn read_word_8(&mut self, address: u64) -> Result<u8, crate::Error> {
+            Ok(address as u8)
+        }
+
+        fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), crate::Error> {
+            for (i, word) in data.iter_mut().enumerate() {
+                *word = self.read_word_32(address + 4 * i as u64)?;
+            }
+            Ok(())
+        }
+
+        fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), crate::Error> {
+            for (i, byte) in data.iter_mut().enumerate() {
+                *byte = address.wrapping_add(i as u64) as u8;
+            }
+            Ok(())
+        }
+
+        fn write_word_32(&mut self, _address: u64, _data: u32) -> Result<(), crate::Error> {
+            unimplemented!()
+        }
+
+        fn write_word_8(&mut self, _address: u64, _data: u8) -> Result<(), crate::Error> {
+            unimplemented!()
+        }
+
+        fn write_32(&mut self, _address: u64, _data: &[u32]) -> Result<(), crate::Error> {
+            unimplemented!()
+        }
+
+        fn write_8(&mut self, _address: u64, _data: &[u8]) -> Result<(), crate::Error> {
+            unimplemented!()
+        }
+
+        fn flush(&mut self) -> Result<(), crate::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn variables_of_a_frame() {
+        let debug_info = DebugInfo::from_file("tests/gpio_hal_blinky").unwrap();
+        let frame = StackFrame {
+            id: 0,
+            function_name: "set<u32>".to_owned(),
+            source_location: None,
+            inlined_functions: vec![],
+            registers: Registers([Some(0x2000_0100); 16]),
+            pc: 0x910,
+            cfa: Some(0x2000_0200),
+            variables: vec![],
+        };
+
+        let variables = debug_info.evaluate_variables(&mut AddressPattern, &frame);
+        let names: Vec<&str> = variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["self", "f", "w"]);
+
+        let w = &variables[2];
+        assert_eq!(w.type_name, "W");
+        assert_eq!(w.children.len(), 1);
+        assert_eq!(w.children[0].name, "bits");
+        assert_eq!(w.children[0].type_name, "u32");
+        assert_eq!(w.value, format!("W {{ bits: {} }}", w.children[0].value));
+    }
+}
@@ -3,8 +3,19 @@
 //! The `debug` module contains various debug functionality, which can be
 //! used to implement a debugger based on `probe-rs`.
 
+#[cfg(feature = "disassembly")]
+mod disassembly;
+mod evaluate;
+mod symbols;
 mod typ;
 mod variable;
+mod watch;
+
+#[cfg(feature = "disassembly")]
+pub use disassembly::{disassemble, disassemble_memory, Instruction, InstructionSet};
+pub use evaluate::VariableValue;
+pub use symbols::{Symbol, SymbolLocation, SymbolMap};
+pub use watch::WatchExpression;
 
 use crate::{core::Core, MemoryInterface};
 use typ::Type;
@@ -18,7 +29,7 @@ use std::{
 };
 
 use gimli::{FileEntry, LineProgramHeader};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use object::read::{Object, ObjectSection};
 use thiserror::Error;
 
@@ -34,6 +45,20 @@ pub enum DebugError {
     NonUtf8(#[from] Utf8Error),
     #[error("Error using the probe")]
     Probe(#[from] crate::Error),
+    #[error("Invalid watch expression {0}")]
+    InvalidWatchExpression(String),
+    #[error("{0} is not in scope")]
+    NotInScope(String),
+    #[error("The build ID {executable} of the executable does not match the build ID {debug_file} of the debug file")]
+    BuildIdMismatch {
+        executable: String,
+        debug_file: String,
+    },
+    #[error("The {0} has no build ID to match the executable and the debug file")]
+    MissingBuildId(&'static str),
+    #[cfg(feature = "disassembly")]
+    #[error("Error in the disassembler: {0}")]
+    Disassembler(capstone::Error),
 }
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ColumnType {
@@ -55,14 +80,35 @@ pub struct StackFrame {
     pub id: u64,
     pub function_name: String,
     pub source_location: Option<SourceLocation>,
+    /// The functions inlined at the program counter of the frame, starting with the innermost one.
+    pub inlined_functions: Vec<InlinedFunction>,
     registers: Registers,
     pc: u32,
+    /// The canonical frame address, used to evaluate the locations of variables.
+    cfa: Option<u32>,
     pub variables: Vec<Variable>,
 }
 
+impl StackFrame {
+    /// The program counter of the frame.
+    ///
+    /// For all frames except the innermost one, this is the address of the calling instruction.
+    pub fn pc(&self) -> u32 {
+        self.pc
+    }
+
+    /// Returns the value of the core register `index` in this frame, if it could be recovered.
+    pub fn register(&self, index: usize) -> Option<u32> {
+        self.registers.get(index)
+    }
+}
+
 impl std::fmt::Display for StackFrame {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         writeln!(f, "{}: {}", self.id, self.function_name)?;
+        for inlined_function in &self.inlined_functions {
+            writeln!(f, "\t{}", inlined_function)?;
+        }
         if let Some(si) = &self.source_location {
             write!(
                 f,
@@ -108,6 +154,11 @@ impl Registers {
         registers
     }
 
+    /// Returns the value of register `index`, if it is known.
+    pub fn get(&self, index: usize) -> Option<u32> {
+        self.0.get(index).copied().flatten()
+    }
+
     pub fn get_call_frame_address(&self) -> Option<u32> {
         self.0[13]
     }
@@ -154,6 +205,57 @@ pub struct SourceLocation {
     pub directory: Option<PathBuf>,
 }
 
+/// A function which was inlined into its caller, see `DebugInfo::get_inlined_functions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlinedFunction {
+    pub function_name: String,
+    /// The file of the call which was inlined.
+    pub call_file: Option<String>,
+    /// The line of the call which was inlined.
+    pub call_line: Option<u64>,
+}
+
+impl std::fmt::Display for InlinedFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} inlined at {}",
+            self.function_name,
+            self.call_file.as_deref().unwrap_or("<unknown file>")
+        )?;
+        if let Some(line) = self.call_line {
+            write!(f, ":{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The largest number of `DW_AT_abstract_origin` and `DW_AT_specification` references followed
+/// to find the name of a function.
+const MAX_ORIGIN_DEPTH: usize = 8;
+
+/// The largest number of frames unwound, so that a corrupt stack is not unwound forever.
+const MAX_FRAMES: u64 = 64;
+
+/// The function where unwinding stops, as there is nothing interesting above it.
+const ENTRY_FUNCTION: &str = "main";
+
+/// Return addresses from this value upwards are `EXC_RETURN` values, which return from an exception.
+const EXC_RETURN_START: u32 = 0xf000_0000;
+
+/// Returns `true` if the caller at `caller_pc` with the stack pointer `caller_sp` can be unwound further,
+/// coming from the frame at `pc` with the stack pointer `sp`.
+///
+/// The stack grows downwards, so the stack pointer of a caller is never below the one of the callee.
+/// It is only the same for frameless functions, in which case the program counter has to change.
+fn is_valid_caller(pc: u32, sp: u32, caller_pc: u32, caller_sp: u32) -> bool {
+    if caller_pc == 0 || caller_pc >= EXC_RETURN_START {
+        return false;
+    }
+
+    caller_sp > sp || (caller_sp == sp && caller_pc != pc)
+}
+
 pub struct StackFrameIterator<'debuginfo, 'probe, 'core> {
     debug_info: &'debuginfo DebugInfo,
     core: &'core mut Core<'probe>,
@@ -169,6 +271,30 @@ impl<'debuginfo, 'probe, 'core> StackFrameIterator<'debuginfo, 'probe, 'core> {
         address: u64,
     ) -> Self {
         let registers = Registers::from_core(core);
+
+        Self::with_registers(debug_info, core, address, registers)
+    }
+
+    /// Starts unwinding at `pc` with the stack pointer `sp`, instead of the registers of the core.
+    pub fn with_pc_and_sp(
+        debug_info: &'debuginfo DebugInfo,
+        core: &'core mut Core<'probe>,
+        pc: u32,
+        sp: u32,
+    ) -> Self {
+        let mut registers = Registers::from_core(core);
+        registers.set_call_frame_address(Some(sp));
+        registers[15] = Some(pc);
+
+        Self::with_registers(debug_info, core, u64::from(pc), registers)
+    }
+
+    fn with_registers(
+        debug_info: &'debuginfo DebugInfo,
+        core: &'core mut Core<'probe>,
+        address: u64,
+        registers: Registers,
+    ) -> Self {
         let pc = address;
 
         Self {
@@ -179,24 +305,16 @@ impl<'debuginfo, 'probe, 'core> StackFrameIterator<'debuginfo, 'probe, 'core> {
             registers,
         }
     }
-}
 
-impl<'debuginfo, 'probe, 'core> Iterator for StackFrameIterator<'debuginfo, 'probe, 'core> {
-    type Item = StackFrame;
-
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Calculates the registers of the caller of the frame at `pc`, and the CFA of the frame.
+    ///
+    /// Functions without call frame information are assumed to be frameless leaf functions,
+    /// which is only possible for the innermost frame.
+    fn unwind_frame(&mut self, pc: u64) -> Option<(Registers, u32)> {
         use gimli::UnwindSection;
         let mut ctx = gimli::UninitializedUnwindContext::new();
         let bases = gimli::BaseAddresses::default();
 
-        let pc = match self.pc {
-            Some(pc) => pc,
-            None => {
-                debug!("Unable to determine next frame, program counter is zero");
-                return None;
-            }
-        };
-
         let unwind_info = self.debug_info.frame_section.unwind_info_for_address(
             &bases,
             &mut ctx,
@@ -206,6 +324,15 @@ impl<'debuginfo, 'probe, 'core> Iterator for StackFrameIterator<'debuginfo, 'pro
 
         let unwind_info = match unwind_info {
             Ok(uw) => uw,
+            Err(e) if self.frame_count == 0 => {
+                debug!(
+                    "No call frame information for program counter {:#x} ({}), assuming a frameless function",
+                    pc, e
+                );
+                let mut caller = self.registers.clone();
+                caller[15] = caller[14].map(|lr| lr & !1);
+                return Some((caller, self.registers.get_call_frame_address()?));
+            }
             Err(e) => {
                 info!(
                     "Failed to retrieve debug information for program counter {:#x}: {}",
@@ -215,12 +342,12 @@ impl<'debuginfo, 'probe, 'core> Iterator for StackFrameIterator<'debuginfo, 'pro
             }
         };
 
-        let current_cfa = match unwind_info.cfa() {
+        let cfa = match unwind_info.cfa() {
             gimli::CfaRule::RegisterAndOffset { register, offset } => {
                 let reg_val = self.registers[register.0 as usize];
 
                 match reg_val {
-                    Some(reg_val) => Some((i64::from(reg_val) + offset) as u32),
+                    Some(reg_val) => (i64::from(reg_val) + offset) as u32,
                     None => {
                         log::warn!(
                             "Unable to calculate CFA: Missing value of register {}",
@@ -230,14 +357,16 @@ impl<'debuginfo, 'probe, 'core> Iterator for StackFrameIterator<'debuginfo, 'pro
                     }
                 }
             }
-            gimli::CfaRule::Expression(_) => unimplemented!(),
+            gimli::CfaRule::Expression(_) => {
+                log::warn!("Unable to calculate CFA: DWARF expressions are not supported");
+                return None;
+            }
         };
 
-        if let Some(ref cfa) = &current_cfa {
-            debug!("Current CFA: {:#x}", cfa);
-        }
+        debug!("Current CFA: {:#x}", cfa);
 
         // generate previous registers
+        let mut caller = self.registers.clone();
         for i in 0..16 {
             if i == 13 {
                 continue;
@@ -249,12 +378,14 @@ impl<'debuginfo, 'probe, 'core> Iterator for StackFrameIterator<'debuginfo, 'pro
 
             log::trace!("Register {}: {:?}", i, &register_rule);
 
-            self.registers[i] = match register_rule {
+            caller[i] = match register_rule {
                 Undefined => {
                     // If we get undefined for the LR register (register 14) or any callee saved register,
                     // we assume that it is unchanged. Gimli doesn't allow us
                     // to distinguish if  a rule is not present or actually set to Undefined
                     // in the call frame information.
+                    //
+                    // This is also the case in the prologue, before the registers are saved.
 
                     match i {
                         4 | 5 | 6 | 7 | 8 | 10 | 11 | 14 => self.registers[i],
@@ -263,27 +394,67 @@ impl<'debuginfo, 'probe, 'core> Iterator for StackFrameIterator<'debuginfo, 'pro
                 }
                 SameValue => self.registers[i],
                 Offset(o) => {
-                    let addr = i64::from(current_cfa.unwrap()) + o;
+                    let addr = (i64::from(cfa) + o) as u32;
                     let mut buff = [0u8; 4];
-                    self.core.read_8(addr as u32, &mut buff).unwrap();
+                    match self.core.read(addr.into(), &mut buff) {
+                        Ok(()) => {
+                            let val = u32::from_le_bytes(buff);
+                            debug!("reg[{: >}]={:#08x}", i, val);
+                            Some(val)
+                        }
+                        Err(e) => {
+                            log::warn!("Unable to read register {} from {:#010x}: {}", i, addr, e);
+                            None
+                        }
+                    }
+                }
+                ValOffset(o) => Some((i64::from(cfa) + o) as u32),
+                Register(r) => self.registers[r.0 as usize],
+                _ => {
+                    log::debug!("Unsupported rule for register {}: {:?}", i, register_rule);
+                    None
+                }
+            }
+        }
 
-                    let val = u32::from_le_bytes(buff);
+        // The stack pointer of the caller is the CFA, and it continues at the return address.
+        caller.set_call_frame_address(Some(cfa));
+        caller[15] = caller[14].map(|lr| lr & !1);
 
-                    debug!("reg[{: >}]={:#08x}", i, val);
+        Some((caller, cfa))
+    }
+}
 
-                    Some(val)
-                }
-                _ => unimplemented!(),
+impl<'debuginfo, 'probe, 'core> Iterator for StackFrameIterator<'debuginfo, 'probe, 'core> {
+    type Item = StackFrame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pc = match self.pc {
+            Some(pc) => pc,
+            None => {
+                debug!("Unable to determine next frame, program counter is zero");
+                return None;
             }
+        };
+
+        if self.frame_count >= MAX_FRAMES {
+            log::warn!(
+                "Stopped unwinding after {} frames, the stack might be corrupt",
+                MAX_FRAMES
+            );
+            return None;
         }
 
-        self.registers.set_call_frame_address(current_cfa);
+        let caller = self.unwind_frame(pc);
+        let sp = self.registers.get_call_frame_address();
+        let frame_base = caller.as_ref().map(|(_, cfa)| *cfa).or(sp);
 
         let return_frame = match self.debug_info.get_stackframe_info(
             &mut self.core,
             pc,
             self.frame_count,
             self.registers.clone(),
+            frame_base,
         ) {
             Ok(frame) => Some(frame),
             Err(e) => {
@@ -293,13 +464,24 @@ impl<'debuginfo, 'probe, 'core> Iterator for StackFrameIterator<'debuginfo, 'pro
         };
 
         self.frame_count += 1;
-
-        // Next function is where our current return register is pointing to.
-        // We just have to remove the lowest bit (indicator for Thumb mode).
-        //
-        // We also have to subtract one, as we want the calling instruction for
-        // a backtrace, not the next instruction to be executed.
-        self.pc = self.registers[14].map(|pc| u64::from(pc & !1) - 1);
+        self.pc = None;
+
+        let reached_entry =
+            matches!(&return_frame, Some(frame) if frame.function_name == ENTRY_FUNCTION);
+
+        if let (Some((caller, _)), false) = (caller, reached_entry) {
+            match (sp, caller[15], caller.get_call_frame_address()) {
+                (Some(sp), Some(caller_pc), Some(caller_sp))
+                    if is_valid_caller(pc as u32, sp, caller_pc, caller_sp) =>
+                {
+                    // We also have to subtract one, as we want the calling instruction for
+                    // a backtrace, not the next instruction to be executed.
+                    self.pc = Some(u64::from(caller_pc) - 1);
+                    self.registers = caller;
+                }
+                _ => debug!("Stopped unwinding at an invalid caller frame"),
+            }
+        }
 
         return_frame
     }
@@ -325,6 +507,8 @@ type UnitIter =
 pub struct DebugInfo {
     dwarf: gimli::Dwarf<DwarfReader>,
     frame_section: gimli::DebugFrame<DwarfReader>,
+    /// The functions of the symbol table, used if there is no DWARF information for an address.
+    symbols: SymbolMap,
 }
 
 impl DebugInfo {
@@ -339,11 +523,53 @@ impl DebugInfo {
     pub fn from_raw(data: &[u8]) -> Result<Self, DebugError> {
         let object = object::File::parse(data)?;
 
+        DebugInfo::from_objects(&object, None)
+    }
+
+    /// Read debug info from a stripped ELF file, and the separate debug file containing its DWARF information.
+    ///
+    /// See [`DebugInfo::from_split_raw`] for how the files are matched.
+    pub fn from_split_files(
+        executable: impl AsRef<Path>,
+        debug_file: impl AsRef<Path>,
+        allow_mismatch: bool,
+    ) -> Result<DebugInfo, DebugError> {
+        let executable = std::fs::read(executable)?;
+        let debug_file = std::fs::read(debug_file)?;
+
+        DebugInfo::from_split_raw(&executable, &debug_file, allow_mismatch)
+    }
+
+    /// Parse debug information from a stripped ELF file, and the separate debug file containing its DWARF information.
+    ///
+    /// The files have to contain the same GNU build ID. If `allow_mismatch` is set, files with different
+    /// or missing build IDs are used anyway, with a warning. Sections missing in the debug file are
+    /// taken from the executable.
+    pub fn from_split_raw(
+        executable: &[u8],
+        debug_file: &[u8],
+        allow_mismatch: bool,
+    ) -> Result<Self, DebugError> {
+        let executable = object::File::parse(executable)?;
+        let debug_file = object::File::parse(debug_file)?;
+
+        match check_build_ids(executable.build_id()?, debug_file.build_id()?) {
+            Err(e) if allow_mismatch => warn!("Using the debug file anyway: {}", e),
+            result => result?,
+        }
+
+        DebugInfo::from_objects(&debug_file, Some(&executable))
+    }
+
+    /// Loads the DWARF sections of `object`, falling back to the ones of `fallback` if a section is missing.
+    fn from_objects(
+        object: &object::File,
+        fallback: Option<&object::File>,
+    ) -> Result<Self, DebugError> {
         // Load a section and return as `Cow<[u8]>`.
         let load_section = |id: gimli::SectionId| -> Result<DwarfReader, gimli::Error> {
-            let data = object
-                .section_by_name(id.name())
-                .and_then(|section| section.uncompressed_data().ok())
+            let data = find_section(object, id)
+                .or_else(|| fallback.and_then(|fallback| find_section(fallback, id)))
                 .unwrap_or_else(|| borrow::Cow::Borrowed(&[][..]));
 
             Ok(gimli::read::EndianRcSlice::new(
@@ -370,10 +596,16 @@ impl DebugInfo {
         // we have to set the address size here.
         frame_section.set_address_size(4);
 
+        let mut symbols = SymbolMap::from_object(object);
+        if let (true, Some(fallback)) = (symbols.is_empty(), fallback) {
+            symbols = SymbolMap::from_object(fallback);
+        }
+
         Ok(DebugInfo {
             //object,
             dwarf: dwarf_cow,
             frame_section,
+            symbols,
         })
     }
 
@@ -472,6 +704,43 @@ impl DebugInfo {
         None
     }
 
+    /// Returns the functions inlined at `address`, starting with the innermost one.
+    ///
+    /// Each entry contains the location of the call which was inlined into the next entry,
+    /// or into the function containing `address` for the last one.
+    pub fn get_inlined_functions(&self, address: u64) -> Vec<InlinedFunction> {
+        let mut units = self.get_units();
+        while let Some(unit_info) = self.get_next_unit_info(&mut units) {
+            if let Some(inlined_functions) = unit_info.get_inlined_functions(address) {
+                return inlined_functions;
+            }
+        }
+
+        vec![]
+    }
+
+    /// Returns the name of the function containing `address`.
+    ///
+    /// For code inlined into another function, the name of the function containing the inlined code is returned.
+    /// Without DWARF information for `address`, the name is looked up in the ELF symbol table.
+    pub fn function_name(&self, address: u64) -> Option<String> {
+        let mut units = self.get_units();
+        while let Some(unit_info) = self.get_next_unit_info(&mut units) {
+            if let Some(die_cursor_state) = unit_info.get_function_die(address) {
+                return unit_info.get_function_name(&die_cursor_state.function_die);
+            }
+        }
+
+        self.symbols
+            .resolve(address as u32)
+            .map(|location| location.symbol.name.clone())
+    }
+
+    /// Returns the functions of the ELF symbol table.
+    pub fn symbols(&self) -> &SymbolMap {
+        &self.symbols
+    }
+
     fn get_units(&self) -> UnitIter {
         self.dwarf.units()
     }
@@ -494,6 +763,7 @@ impl DebugInfo {
         address: u64,
         frame_count: u64,
         registers: Registers,
+        frame_base: Option<u32>,
     ) -> Result<StackFrame, DebugError> {
         let mut units = self.get_units();
         let unknown_function = format!("<unknown_function_{}>", frame_count);
@@ -503,11 +773,12 @@ impl DebugInfo {
                     .get_function_name(&die_cursor_state.function_die)
                     .unwrap_or(unknown_function);
 
-                let variables = unit_info.get_variables(
-                    core,
-                    die_cursor_state,
-                    u64::from(registers.get_call_frame_address().unwrap()),
-                )?;
+                let variables = match frame_base {
+                    Some(frame_base) => {
+                        unit_info.get_variables(core, die_cursor_state, u64::from(frame_base))?
+                    }
+                    None => vec![],
+                };
 
                 // dbg!(&variables);
 
@@ -515,19 +786,30 @@ impl DebugInfo {
                     id: frame_count,
                     function_name,
                     source_location: self.get_source_location(address),
+                    inlined_functions: unit_info.get_inlined_functions(address).unwrap_or_default(),
                     registers,
                     pc: address as u32,
+                    cfa: frame_base,
                     variables,
                 });
             }
         }
 
+        // Without DWARF information, the symbol table still names the function.
+        let function_name = self
+            .symbols
+            .resolve(address as u32)
+            .map(|location| location.to_string())
+            .unwrap_or(unknown_function);
+
         Ok(StackFrame {
             id: frame_count,
-            function_name: unknown_function,
+            function_name,
             source_location: self.get_source_location(address),
+            inlined_functions: vec![],
             registers,
             pc: address as u32,
+            cfa: frame_base,
             variables: vec![],
         })
     }
@@ -540,6 +822,14 @@ impl DebugInfo {
         StackFrameIterator::new(&self, core, address)
     }
 
+    /// Returns the backtrace starting at `initial_pc` with the stack pointer `sp`.
+    ///
+    /// Unwinding stops after `main`, at the first frame which cannot be unwound,
+    /// or after 64 frames if the stack is corrupt.
+    pub fn unwind(&self, core: &mut Core<'_>, initial_pc: u32, sp: u32) -> Vec<StackFrame> {
+        StackFrameIterator::with_pc_and_sp(self, core, initial_pc, sp).collect()
+    }
+
     /// Find the program counter where a breakpoint should be set,
     /// given a source file, a line and optionally a column.
     pub fn get_breakpoint_location(
@@ -721,6 +1011,94 @@ impl<'debuginfo> UnitInfo<'debuginfo> {
         None
     }
 
+    /// Returns the functions inlined at `address`, starting with the innermost one,
+    /// or `None` if no function of this unit contains `address`.
+    fn get_inlined_functions(&self, address: u64) -> Option<Vec<InlinedFunction>> {
+        let mut entries_cursor = self.unit.entries();
+        let mut depth = 0;
+        // The depth of the innermost function containing `address`.
+        let mut function_depth = None;
+        let mut inlined_functions = vec![];
+
+        while let Ok(Some((delta_depth, current))) = entries_cursor.next_dfs() {
+            depth += delta_depth;
+
+            match function_depth {
+                // Functions inlined at `address` can only be found inside of the innermost function.
+                Some(function_depth) if depth <= function_depth => break,
+                Some(_) if current.tag() == gimli::DW_TAG_inlined_subroutine => (),
+                None if current.tag() == gimli::DW_TAG_subprogram => (),
+                _ => continue,
+            }
+
+            if !self.contains_address(current, address) {
+                continue;
+            }
+
+            if function_depth.is_some() {
+                inlined_functions.push(InlinedFunction {
+                    function_name: self
+                        .get_origin_name(current)
+                        .unwrap_or_else(|| "<unknown_function>".to_owned()),
+                    call_file: current
+                        .attr_value(gimli::DW_AT_call_file)
+                        .ok()
+                        .flatten()
+                        .and_then(|file| extract_file(self.debug_info, &self.unit, file)),
+                    call_line: current
+                        .attr_value(gimli::DW_AT_call_line)
+                        .ok()
+                        .flatten()
+                        .and_then(|line| line.udata_value()),
+                });
+            }
+            function_depth = Some(depth);
+        }
+
+        function_depth?;
+        inlined_functions.reverse();
+        Some(inlined_functions)
+    }
+
+    fn contains_address(&self, die: &FunctionDie, address: u64) -> bool {
+        let mut ranges = match self.debug_info.dwarf.die_ranges(&self.unit, die) {
+            Ok(ranges) => ranges,
+            Err(_) => return false,
+        };
+
+        while let Ok(Some(range)) = ranges.next() {
+            if (range.begin <= address) && (address < range.end) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the name of a function, following `DW_AT_abstract_origin` and `DW_AT_specification`
+    /// references to the entry containing the name, as used for inlined functions.
+    fn get_origin_name(&self, function_die: &FunctionDie) -> Option<String> {
+        let mut entry = function_die.clone();
+
+        for _ in 0..MAX_ORIGIN_DEPTH {
+            if let Some(name) = entry.attr_value(gimli::DW_AT_name).ok().flatten() {
+                return extract_name(self.debug_info, name);
+            }
+
+            let origin = entry
+                .attr_value(gimli::DW_AT_abstract_origin)
+                .ok()
+                .flatten()
+                .or_else(|| entry.attr_value(gimli::DW_AT_specification).ok().flatten());
+
+            entry = match origin {
+                Some(gimli::AttributeValue::UnitRef(offset)) => self.unit.entry(offset).ok()?,
+                _ => return None,
+            };
+        }
+
+        None
+    }
+
     fn get_function_name(&self, function_die: &FunctionDie) -> Option<String> {
         if let Some(fn_name_attr) = function_die
             .attr(gimli::DW_AT_name)
@@ -754,7 +1132,7 @@ impl<'debuginfo> UnitInfo<'debuginfo> {
                 Complete => break,
                 RequiresMemory { address, size, .. } => {
                     let mut buff = vec![0u8; size as usize];
-                    core.read_8(address as u32, &mut buff)
+                    core.read(address, &mut buff)
                         .expect("Failed to read memory");
                     match size {
                         1 => evaluation.resume_with_memory(gimli::Value::U8(buff[0]))?,
@@ -1023,7 +1401,7 @@ pub(crate) fn _print_all_attributes(
                         Complete => break,
                         RequiresMemory { address, size, .. } => {
                             let mut buff = vec![0u8; size as usize];
-                            core.read_8(address as u32, &mut buff)
+                            core.read(address, &mut buff)
                                 .expect("Failed to read memory");
                             match size {
                                 1 => evaluation
@@ -1075,3 +1453,73 @@ pub(crate) fn _print_all_attributes(
         }
     }
 }
+
+/// Returns the data of the section `id` of `object`, if it exists and is not empty.
+fn find_section<'data>(
+    object: &object::File<'data>,
+    id: gimli::SectionId,
+) -> Option<borrow::Cow<'data, [u8]>> {
+    object
+        .section_by_name(id.name())
+        .and_then(|section| section.uncompressed_data().ok())
+        .filter(|data| !data.is_empty())
+}
+
+/// Checks that the build IDs of an executable and its debug file are the same.
+fn check_build_ids(executable: Option<&[u8]>, debug_file: Option<&[u8]>) -> Result<(), DebugError> {
+    let hex = |id: &[u8]| {
+        id.iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>()
+    };
+
+    match (executable, debug_file) {
+        (Some(executable), Some(debug_file)) if executable == debug_file => Ok(()),
+        (Some(executable), Some(debug_file)) => Err(DebugError::BuildIdMismatch {
+            executable: hex(executable),
+            debug_file: hex(debug_file),
+        }),
+        (None, _) => Err(DebugError::MissingBuildId("executable")),
+        (_, None) => Err(DebugError::MissingBuildId("debug file")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_ids_are_matched() {
+        assert!(check_build_ids(Some(&[0xab, 0x01]), Some(&[0xab, 0x01])).is_ok());
+
+        let error = check_build_ids(Some(&[0xab, 0x01]), Some(&[0xcd, 0x02])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The build ID ab01 of the executable does not match the build ID cd02 of the debug file"
+        );
+
+        assert!(matches!(
+            check_build_ids(Some(&[0xab]), None),
+            Err(DebugError::MissingBuildId("debug file"))
+        ));
+    }
+
+    #[test]
+    fn invalid_callers_stop_unwinding() {
+        // A regular caller, higher up on the stack.
+        assert!(is_valid_caller(0x100, 0x2000_0f00, 0x200, 0x2000_0f10));
+        // A frameless leaf function, returning into its caller.
+        assert!(is_valid_caller(0x100, 0x2000_0f00, 0x200, 0x2000_0f00));
+        // No progress, or a stack growing upwards.
+        assert!(!is_valid_caller(0x100, 0x2000_0f00, 0x100, 0x2000_0f00));
+        assert!(!is_valid_caller(0x100, 0x2000_0f00, 0x200, 0x2000_0e00));
+        // Reset and exception returns.
+        assert!(!is_valid_caller(0x100, 0x2000_0f00, 0, 0x2000_0f10));
+        assert!(!is_valid_caller(
+            0x100,
+            0x2000_0f00,
+            0xffff_fff9,
+            0x2000_0f10
+        ));
+    }
+}
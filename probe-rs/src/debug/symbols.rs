@@ -0,0 +1,178 @@
+//! Resolving addresses to functions with the ELF symbol table, without DWARF information.
+
+use super::DebugError;
+use object::read::{Object, ObjectSymbol};
+use object::{SymbolKind, SymbolScope};
+use std::{fmt, path::Path};
+
+/// A function of the ELF symbol table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// The name of the function, as stored in the symbol table.
+    pub name: String,
+    /// The address of the first instruction of the function, without the Thumb bit.
+    pub address: u32,
+    /// The size of the function in bytes.
+    pub size: u32,
+    /// Whether the symbol is only visible in its compilation unit, e.g. a `static` C function.
+    pub local: bool,
+}
+
+impl Symbol {
+    fn contains(&self, address: u32) -> bool {
+        address >= self.address && address - self.address < self.size
+    }
+}
+
+/// The function containing an address, see [`SymbolMap::resolve`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymbolLocation<'map> {
+    pub symbol: &'map Symbol,
+    /// The offset of the address from the start of the function.
+    pub offset: u32,
+}
+
+impl fmt::Display for SymbolLocation<'_> {
+    /// Formats the location as `name+0x1c`, or only as `name` at the start of the function.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.offset {
+            0 => write!(f, "{}", self.symbol.name),
+            offset => write!(f, "{}+{:#x}", self.symbol.name, offset),
+        }
+    }
+}
+
+/// Resolves addresses to the functions of the ELF symbol table.
+///
+/// This is much cheaper than [`DebugInfo`](super::DebugInfo), and also works for files without
+/// DWARF information. Only defined function symbols with a size are used, which skips labels
+/// and the `$t`/`$a` mapping symbols of Arm files.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolMap {
+    /// The functions sorted by their address.
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolMap {
+    /// Read the symbol table of an ELF file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, DebugError> {
+        let data = std::fs::read(path)?;
+
+        SymbolMap::from_raw(&data)
+    }
+
+    /// Parse the symbol table of a buffer containing an ELF file.
+    pub fn from_raw(data: &[u8]) -> Result<Self, DebugError> {
+        let object = object::File::parse(data)?;
+
+        Ok(SymbolMap::from_object(&object))
+    }
+
+    /// Uses the functions of both `.symtab` and `.dynsym`.
+    pub(super) fn from_object(object: &object::File) -> Self {
+        let symbols = object
+            .symbols()
+            .chain(object.dynamic_symbols())
+            .filter(|symbol| {
+                symbol.kind() == SymbolKind::Text && symbol.is_definition() && symbol.size() != 0
+            })
+            .filter_map(|symbol| {
+                Some(Symbol {
+                    name: symbol.name().ok()?.to_owned(),
+                    // The LSB of Arm function symbols is set for Thumb code.
+                    address: symbol.address() as u32 & !1,
+                    size: symbol.size() as u32,
+                    local: symbol.scope() == SymbolScope::Compilation,
+                })
+            });
+
+        SymbolMap::new(symbols)
+    }
+
+    /// Creates a map from `symbols`.
+    ///
+    /// If several symbols start at the same address, e.g. a function listed in both `.symtab`
+    /// and `.dynsym`, a global symbol is preferred over a local one.
+    pub fn new(symbols: impl IntoIterator<Item = Symbol>) -> Self {
+        let mut symbols: Vec<_> = symbols.into_iter().collect();
+
+        symbols.sort_by_key(|symbol| (symbol.address, symbol.local));
+        symbols.dedup_by_key(|symbol| symbol.address);
+
+        SymbolMap { symbols }
+    }
+
+    /// Returns the function containing `address`, together with the offset of `address` in it.
+    pub fn resolve(&self, address: u32) -> Option<SymbolLocation<'_>> {
+        let index = match self
+            .symbols
+            .binary_search_by_key(&address, |symbol| symbol.address)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+
+        let symbol = &self.symbols[index];
+        symbol.contains(address).then(|| SymbolLocation {
+            symbol,
+            offset: address - symbol.address,
+        })
+    }
+
+    /// Returns the functions, sorted by their address.
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// Whether the symbol table contains no functions.
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn symbol(name: &str, address: u32, size: u32, local: bool) -> Symbol {
+        Symbol {
+            name: name.to_owned(),
+            address,
+            size,
+            local,
+        }
+    }
+
+    #[test]
+    fn addresses_are_resolved() {
+        let map = SymbolMap::new(vec![
+            symbol("main", 0x100, 0x40, false),
+            symbol("helper", 0x200, 0x10, true),
+            symbol("helper_alias", 0x200, 0x10, false),
+        ]);
+
+        assert_eq!(map.symbols().len(), 2);
+        assert_eq!(map.resolve(0x100).unwrap().to_string(), "main");
+        assert_eq!(map.resolve(0x11c).unwrap().to_string(), "main+0x1c");
+        assert_eq!(map.resolve(0x20e).unwrap().to_string(), "helper_alias+0xe");
+
+        // Before the first function, and between two functions.
+        assert_eq!(map.resolve(0x80), None);
+        assert_eq!(map.resolve(0x140), None);
+        assert_eq!(map.resolve(0x210), None);
+    }
+
+    #[test]
+    fn functions_are_read_from_elf() {
+        let map = SymbolMap::from_file("tests/gpio_hal_blinky").unwrap();
+
+        let main = map
+            .symbols()
+            .iter()
+            .find(|symbol| symbol.name == "main")
+            .unwrap();
+        assert_eq!(main.address & 1, 0);
+        assert_eq!(map.resolve(main.address + 2).unwrap().symbol, main);
+    }
+}
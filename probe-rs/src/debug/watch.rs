@@ -0,0 +1,390 @@
+//! Watch expressions, which are resolved once and evaluated whenever the core halts.
+
+use super::evaluate::{unavailable, FrameContext};
+use super::{DebugError, DebugInfo, StackFrame, UnitInfo, VariableValue};
+use crate::MemoryInterface;
+use gimli::UnitOffset;
+use std::ops::Range;
+
+/// A step of a watch expression, after the variable.
+#[derive(Debug, Clone, PartialEq)]
+enum PathStep {
+    Member(String),
+    Index(u64),
+}
+
+/// The characters starting a new step of a watch expression.
+const SEPARATORS: &[char] = &['.', '['];
+
+/// Splits `expression` into the name of the variable and the members and elements accessed on it.
+fn parse_path(expression: &str) -> Option<(&str, Vec<PathStep>)> {
+    let expression = expression.trim();
+    let end = expression.find(SEPARATORS).unwrap_or(expression.len());
+    let (variable, mut rest) = expression.split_at(end);
+    if variable.is_empty() {
+        return None;
+    }
+
+    let mut steps = vec![];
+    while !rest.is_empty() {
+        if let Some(member) = rest.strip_prefix('.') {
+            let end = member.find(SEPARATORS).unwrap_or(member.len());
+            if end == 0 {
+                return None;
+            }
+            steps.push(PathStep::Member(member[..end].to_owned()));
+            rest = &member[end..];
+        } else {
+            let index = rest.strip_prefix('[')?;
+            let end = index.find(']')?;
+            steps.push(PathStep::Index(index[..end].trim().parse().ok()?));
+            rest = &index[end + 1..];
+        }
+    }
+
+    Some((variable, steps))
+}
+
+/// A watch expression such as `foo.bar[3].baz`, see `DebugInfo::watch_expression`.
+///
+/// The members and elements of the expression are resolved when it is created,
+/// so evaluating it on every halt only reads the variable.
+#[derive(Debug, Clone)]
+pub struct WatchExpression {
+    expression: String,
+    unit: gimli::DebugInfoOffset,
+    variable: UnitOffset,
+    /// The function the variable is defined in, or `None` for static variables.
+    function: Option<UnitOffset>,
+    /// The address ranges in which the variable is in scope, or `None` for static variables.
+    scope: Option<Vec<Range<u64>>>,
+    variable_size: usize,
+    /// The location of the watched value inside of the variable.
+    offset: usize,
+    size: usize,
+    value_type: UnitOffset,
+}
+
+impl DebugInfo {
+    /// Resolves `expression` for the scope at `pc`, falling back to static variables.
+    ///
+    /// The expression is the name of a variable, followed by members (`.bar`) and array elements (`[3]`).
+    pub fn watch_expression(
+        &self,
+        expression: &str,
+        pc: u32,
+    ) -> Result<WatchExpression, DebugError> {
+        let invalid = |reason: &str| {
+            DebugError::InvalidWatchExpression(format!("{}: {}", expression, reason))
+        };
+
+        let (name, steps) = parse_path(expression).ok_or_else(|| invalid("syntax error"))?;
+
+        let mut units = self.get_units();
+        while let Some(unit_info) = self.get_next_unit_info(&mut units) {
+            if let Some(watch) = unit_info
+                .find_local(name, u64::from(pc))
+                .or_else(|| unit_info.find_static(name))
+            {
+                let (function, scope, variable) = watch;
+                let unit = match unit_info.unit.header.offset() {
+                    gimli::UnitSectionOffset::DebugInfoOffset(unit) => unit,
+                    gimli::UnitSectionOffset::DebugTypesOffset(_) => continue,
+                };
+
+                let variable_type = unit_info
+                    .attr_type(variable)
+                    .ok_or_else(|| invalid("variable without type"))?;
+                let variable_size = unit_info
+                    .type_size(variable_type, 0)
+                    .ok_or_else(|| invalid("unsized variable"))?;
+
+                let (offset, value_type) = unit_info
+                    .resolve_path(variable_type, &steps)
+                    .map_err(invalid)?;
+                let size = unit_info
+                    .type_size(value_type, 0)
+                    .ok_or_else(|| invalid("unsized value"))?;
+
+                return Ok(WatchExpression {
+                    expression: expression.to_owned(),
+                    unit,
+                    variable,
+                    function,
+                    scope,
+                    variable_size: variable_size as usize,
+                    offset: offset as usize,
+                    size: size as usize,
+                    value_type,
+                });
+            }
+        }
+
+        Err(invalid("no such variable"))
+    }
+}
+
+type LocalVariable = (Option<UnitOffset>, Option<Vec<Range<u64>>>, UnitOffset);
+
+impl<'debuginfo> UnitInfo<'debuginfo> {
+    /// Finds the innermost variable `name` in scope at `address`, together with its function and scope.
+    fn find_local(&self, name: &str, address: u64) -> Option<LocalVariable> {
+        let function = self.get_function_die(address)?.function_die.offset();
+
+        let mut variables = vec![];
+        self.collect_variables(function, address, &mut variables);
+
+        // Variables of nested blocks are collected after the ones of their parents.
+        let (scope, variable) = variables
+            .into_iter()
+            .rev()
+            .find(|(_, variable)| self.attr_name(*variable).as_deref() == Some(name))?;
+
+        let mut ranges = vec![];
+        let scope_entry = self.unit.entry(scope).ok()?;
+        let mut scope_ranges = self
+            .debug_info
+            .dwarf
+            .die_ranges(&self.unit, &scope_entry)
+            .ok()?;
+        while let Ok(Some(range)) = scope_ranges.next() {
+            ranges.push(range.begin..range.end);
+        }
+
+        Some((Some(function), Some(ranges), variable))
+    }
+
+    /// Finds the static variable `name`, which can be inside of namespaces.
+    fn find_static(&self, name: &str) -> Option<LocalVariable> {
+        let mut entries = self.unit.entries();
+        while let Ok(Some((_, entry))) = entries.next_dfs() {
+            if entry.tag() == gimli::DW_TAG_variable
+                && entry
+                    .attr_value(gimli::DW_AT_location)
+                    .ok()
+                    .flatten()
+                    .is_some()
+                && self.attr_name(entry.offset()).as_deref() == Some(name)
+                && self.is_static(entry.offset())
+            {
+                return Some((None, None, entry.offset()));
+            }
+        }
+
+        None
+    }
+
+    /// Returns `true` if the variable at `offset` is not defined inside of a function.
+    fn is_static(&self, offset: UnitOffset) -> bool {
+        let mut tree = match self.unit.entries_tree(None) {
+            Ok(tree) => tree,
+            Err(_) => return false,
+        };
+        match tree.root() {
+            Ok(root) => self.is_static_below(root, offset),
+            Err(_) => false,
+        }
+    }
+
+    fn is_static_below(&self, node: gimli::EntriesTreeNode<super::R>, offset: UnitOffset) -> bool {
+        let mut children = node.children();
+        while let Ok(Some(child)) = children.next() {
+            let entry = child.entry();
+            if entry.offset() == offset {
+                return true;
+            }
+            if entry.tag() == gimli::DW_TAG_namespace && self.is_static_below(child, offset) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the offset of the value selected by `steps` inside of `variable_type`, and its type.
+    fn resolve_path(
+        &self,
+        variable_type: UnitOffset,
+        steps: &[PathStep],
+    ) -> Result<(u64, UnitOffset), &'static str> {
+        let mut offset = 0;
+        let mut value_type = variable_type;
+
+        for step in steps {
+            let (resolved, tag) = self.resolve_type(value_type).ok_or("unknown type")?;
+
+            match step {
+                PathStep::Member(name) => {
+                    if !matches!(
+                        tag,
+                        gimli::DW_TAG_structure_type
+                            | gimli::DW_TAG_class_type
+                            | gimli::DW_TAG_union_type
+                    ) {
+                        return Err("member of a value which is not a struct");
+                    }
+
+                    let member = self
+                        .children(resolved)
+                        .map_err(|_| "invalid type")?
+                        .into_iter()
+                        .find(|child| {
+                            matches!(self.unit.entry(*child), Ok(entry) if entry.tag() == gimli::DW_TAG_member)
+                                && self.attr_name(*child).as_deref() == Some(name.as_str())
+                        })
+                        .ok_or("no such member")?;
+
+                    offset += match self.attr(member, gimli::DW_AT_data_member_location) {
+                        Some(location) => location
+                            .udata_value()
+                            .ok_or("unsupported member location")?,
+                        None => 0,
+                    };
+                    value_type = self.attr_type(member).ok_or("member without type")?;
+                }
+                PathStep::Index(index) => {
+                    if tag != gimli::DW_TAG_array_type {
+                        return Err("index into a value which is not an array");
+                    }
+
+                    if *index >= self.array_length(resolved).ok_or("array without length")? {
+                        return Err("index out of bounds");
+                    }
+
+                    value_type = self
+                        .attr_type(resolved)
+                        .ok_or("array without element type")?;
+                    offset += index * self.type_size(value_type, 0).ok_or("unsized element")?;
+                }
+            }
+        }
+
+        Ok((offset, value_type))
+    }
+}
+
+impl WatchExpression {
+    /// The expression, as it was passed to `DebugInfo::watch_expression`.
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Evaluates the expression in `frame`.
+    ///
+    /// Returns `DebugError::NotInScope` if the variable of a local expression is not in scope
+    /// at the program counter of `frame`.
+    pub fn evaluate(
+        &self,
+        debug_info: &DebugInfo,
+        memory: &mut impl MemoryInterface,
+        frame: &StackFrame,
+    ) -> Result<VariableValue, DebugError> {
+        let address = u64::from(frame.pc);
+
+        if let Some(scope) = &self.scope {
+            if !scope.iter().any(|range| range.contains(&address)) {
+                return Err(DebugError::NotInScope(self.expression.clone()));
+            }
+        }
+
+        let header = debug_info.dwarf.debug_info.header_from_offset(self.unit)?;
+        let unit_info = UnitInfo {
+            debug_info,
+            unit: debug_info.dwarf.unit(header)?,
+        };
+
+        let mut context = FrameContext {
+            registers: &frame.registers,
+            cfa: frame.cfa,
+            frame_base: None,
+        };
+        if let Some(function) = self.function {
+            let function_die = unit_info.unit.entry(function)?;
+            context.frame_base = unit_info.frame_base(memory, &context, &function_die);
+        }
+
+        let bytes = match unit_info.variable_bytes(
+            memory,
+            &context,
+            self.variable,
+            address,
+            self.variable_size,
+        ) {
+            Ok(bytes) => bytes,
+            Err(reason) => {
+                return Ok(unavailable(
+                    self.expression.clone(),
+                    unit_info.type_name(self.value_type),
+                    reason,
+                ))
+            }
+        };
+
+        Ok(unit_info.format_value(
+            self.expression.clone(),
+            self.value_type,
+            &bytes[self.offset..self.offset + self.size],
+            0,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::evaluate::test::AddressPattern;
+    use super::super::Registers;
+    use super::*;
+
+    fn frame_at(pc: u32) -> StackFrame {
+        StackFrame {
+            id: 0,
+            function_name: String::new(),
+            source_location: None,
+            inlined_functions: vec![],
+            registers: Registers([Some(0x2000_0100); 16]),
+            pc,
+            cfa: Some(0x2000_0200),
+            variables: vec![],
+        }
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(
+            parse_path("foo.bar[3].baz"),
+            Some((
+                "foo",
+                vec![
+                    PathStep::Member("bar".to_owned()),
+                    PathStep::Index(3),
+                    PathStep::Member("baz".to_owned())
+                ]
+            ))
+        );
+        assert_eq!(parse_path("foo"), Some(("foo", vec![])));
+        assert_eq!(parse_path("foo..bar"), None);
+        assert_eq!(parse_path("foo[x]"), None);
+        assert_eq!(parse_path("[1]"), None);
+    }
+
+    #[test]
+    fn members_are_resolved_once() {
+        let debug_info = DebugInfo::from_file("tests/gpio_hal_blinky").unwrap();
+
+        let watch = debug_info.watch_expression("w.bits", 0x910).unwrap();
+        let value = watch
+            .evaluate(&debug_info, &mut AddressPattern, &frame_at(0x910))
+            .unwrap();
+        assert_eq!(value.name, "w.bits");
+        assert_eq!(value.type_name, "u32");
+
+        assert!(matches!(
+            watch.evaluate(&debug_info, &mut AddressPattern, &frame_at(0x956)),
+            Err(DebugError::NotInScope(_))
+        ));
+
+        assert!(matches!(
+            debug_info.watch_expression("w.missing", 0x910),
+            Err(DebugError::InvalidWatchExpression(_))
+        ));
+    }
+}
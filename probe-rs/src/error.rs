@@ -1,13 +1,27 @@
+use crate::architecture::{arm::ArmError, riscv::communication_interface::RiscvError};
 use crate::config::RegistryError;
+use crate::flashing::FlashError;
 use crate::DebugProbeError;
 use thiserror::Error;
 
+/// The errors of probe-rs.
+///
+/// Errors are sorted by the layer in which they occured: `Probe` for the probe and its
+/// connection to the host, `Arm` and `Riscv` for the debug interface of the target, and
+/// `Flash` for the flash algorithms. Architecture specific errors which are converted into
+/// an `Error` end up in `Arm` or `Riscv`, if they are one of the errors collected there.
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("An error with the usage of the probe occured")]
-    Probe(#[from] DebugProbeError),
+    Probe(#[source] DebugProbeError),
+    #[error("An error of the Arm debug interface occured")]
+    Arm(#[source] ArmError),
+    #[error("An error of the RISC-V debug module occured")]
+    Riscv(#[source] RiscvError),
+    #[error("An error during flashing occured")]
+    Flash(#[source] Box<FlashError>),
     #[error("A core architecture specific error occured")]
-    ArchitectureSpecific(#[from] Box<dyn std::error::Error + Send + Sync>),
+    ArchitectureSpecific(#[source] Box<dyn std::error::Error + Send + Sync>),
     #[error("Probe could not be opened: {0}")]
     UnableToOpenProbe(&'static str),
     #[error("Core {0} does not exist")]
@@ -16,12 +30,113 @@ pub enum Error {
     ChipNotFound(#[from] RegistryError),
     #[error("This feature requires one of the following architectures: {0:?}")]
     ArchitectureRequired(&'static [&'static str]),
+    #[error("The target does not support {0}")]
+    NotSupported(&'static str),
+    #[error("DWARF register {0} does not map to a register accessible by the debugger")]
+    UnknownDwarfRegister(u16),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
+impl From<DebugProbeError> for Error {
+    fn from(error: DebugProbeError) -> Self {
+        match error {
+            DebugProbeError::ArchitectureSpecific(error) => match lift_architecture_specific(error)
+            {
+                Ok(error) => error,
+                Err(error) => Error::Probe(DebugProbeError::ArchitectureSpecific(error)),
+            },
+            error => Error::Probe(error),
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+    fn from(error: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        lift_architecture_specific(error).unwrap_or_else(Error::ArchitectureSpecific)
+    }
+}
+
+impl From<ArmError> for Error {
+    fn from(error: ArmError) -> Self {
+        Error::Arm(error)
+    }
+}
+
+impl From<FlashError> for Error {
+    fn from(error: FlashError) -> Self {
+        Error::Flash(Box::new(error))
+    }
+}
+
+/// Returns the matching `Error` variant for the boxed `error`, if it is an Arm or RISC-V error.
+fn lift_architecture_specific(
+    error: Box<dyn std::error::Error + Send + Sync>,
+) -> Result<Error, Box<dyn std::error::Error + Send + Sync>> {
+    ArmError::from_boxed(error)
+        .map(Error::Arm)
+        .or_else(|error| {
+            error
+                .downcast::<RiscvError>()
+                .map(|error| Error::from(*error))
+        })
+}
+
 impl Error {
     pub fn architecture_specific(e: impl std::error::Error + Send + Sync + 'static) -> Self {
-        Self::ArchitectureSpecific(Box::new(e))
+        Self::from(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+    }
+
+    /// Returns `true` if the error was caused by the probe being disconnected from USB.
+    pub fn is_probe_disconnected(&self) -> bool {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(self);
+
+        while let Some(error) = source {
+            if let Some(rusb::Error::NoDevice) = error.downcast_ref::<rusb::Error>() {
+                return true;
+            }
+            source = error.source();
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn disconnect_is_detected_in_source() {
+        let disconnected =
+            Error::Probe(DebugProbeError::USB(Some(Box::new(rusb::Error::NoDevice))));
+        assert!(disconnected.is_probe_disconnected());
+
+        let timeout = Error::Probe(DebugProbeError::USB(Some(Box::new(rusb::Error::Timeout))));
+        assert!(!timeout.is_probe_disconnected());
+        assert!(!Error::Probe(DebugProbeError::Timeout).is_probe_disconnected());
+    }
+
+    #[test]
+    fn target_errors_are_sorted_by_layer() {
+        use crate::architecture::arm::DapError;
+
+        let fault = Error::from(DebugProbeError::from(DapError::FaultResponseWithStatus {
+            // STICKYERR and STICKYORUN are set.
+            ctrl_stat: 0xf000_0022,
+        }));
+        let status = match &fault {
+            Error::Arm(error) => error.fault_status().unwrap(),
+            error => panic!("unexpected error {:?}", error),
+        };
+        assert!(status.sticky_err());
+        assert!(status.sticky_orun());
+        assert!(!status.stick_cmp());
+
+        let flash = Error::from(FlashError::FlashAlgorithmNotLoaded);
+        assert!(matches!(flash, Error::Flash(_)));
+
+        let other = Error::architecture_specific(std::fmt::Error);
+        assert!(matches!(other, Error::ArchitectureSpecific(_)));
     }
 }
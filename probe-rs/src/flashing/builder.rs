@@ -1,4 +1,7 @@
-use std::fmt::{Debug, Formatter};
+use std::{
+    fmt::{Debug, Formatter},
+    ops::Range,
+};
 
 use super::{FlashError, FlashVisualizer};
 use crate::config::{FlashAlgorithm, MemoryRange, PageInfo, SectorInfo};
@@ -146,9 +149,139 @@ impl FlashLayout {
         &self.data_blocks
     }
 
+    /// Creates a layout which only contains all sectors in `range`, e.g. to erase a whole flash region.
+    pub(super) fn sectors_in_range(
+        flash_algorithm: &FlashAlgorithm,
+        range: &Range<u32>,
+    ) -> Result<Self, FlashError> {
+        let mut sectors = Vec::new();
+
+        let mut address = range.start;
+        while address < range.end {
+            let sector = add_sector(flash_algorithm, address, &mut sectors)?;
+            address = sector.address + sector.size;
+        }
+
+        Ok(Self {
+            sectors,
+            pages: Vec::new(),
+            fills: Vec::new(),
+            data_blocks: Vec::new(),
+        })
+    }
+
     pub fn visualize(&self) -> FlashVisualizer {
         FlashVisualizer::new(&self)
     }
+
+    /// Checks whether erasing and programming `sector` would leave its `current` contents unchanged.
+    ///
+    /// Bytes of the sector which are not covered by any page are erased but never programmed,
+    /// so they have to contain `erased_byte_value` already.
+    pub(super) fn sector_unchanged(
+        &self,
+        sector: &FlashSector,
+        current: &[u8],
+        erased_byte_value: u8,
+    ) -> bool {
+        let sector_end = sector.address + sector.size;
+        let mut covered = vec![false; current.len()];
+
+        for page in &self.pages {
+            let page_end = page.address + page.size();
+            if !(page.address..page_end).intersects_range(&(sector.address..sector_end)) {
+                continue;
+            }
+
+            let start = page.address.max(sector.address);
+            let end = page_end.min(sector_end);
+            let page_slice =
+                &page.data[(start - page.address) as usize..(end - page.address) as usize];
+            let current_range = (start - sector.address) as usize..(end - sector.address) as usize;
+
+            if page_slice != &current[current_range.clone()] {
+                return false;
+            }
+
+            covered[current_range].iter_mut().for_each(|c| *c = true);
+        }
+
+        covered
+            .iter()
+            .zip(current)
+            .all(|(covered, byte)| *covered || *byte == erased_byte_value)
+    }
+
+    /// Removes the sectors flagged in `skip` from the layout, together with all pages and fills which lie within them.
+    ///
+    /// A page which overlaps a sector that still has to be erased will be programmed again,
+    /// so all the other sectors it overlaps are kept as well.
+    ///
+    /// Returns the removed sectors and pages.
+    pub(super) fn remove_sectors(&mut self, skip: &[bool]) -> (Vec<FlashSector>, Vec<FlashPage>) {
+        let mut skip = skip.to_vec();
+        let overlapping_sectors = |page: &FlashPage, sectors: &[FlashSector]| {
+            let page_range = page.address..page.address + page.size();
+            sectors
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| page_range.intersects_range(&(s.address..s.address + s.size)))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+
+        loop {
+            let mut changed = false;
+            for page in &self.pages {
+                let overlapping = overlapping_sectors(page, &self.sectors);
+                if overlapping.iter().any(|&i| !skip[i]) {
+                    for i in overlapping {
+                        changed |= skip[i];
+                        skip[i] = false;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let mut removed_sectors = Vec::new();
+        let mut kept_sectors = Vec::new();
+        for (sector, skip) in self.sectors.drain(..).zip(&skip) {
+            if *skip {
+                removed_sectors.push(sector);
+            } else {
+                kept_sectors.push(sector);
+            }
+        }
+        self.sectors = kept_sectors;
+
+        // Maps the old page indices to the new ones, `None` if the page was removed.
+        let mut page_indices = Vec::with_capacity(self.pages.len());
+        let mut removed_pages = Vec::new();
+        let mut kept_pages = Vec::new();
+        for page in std::mem::take(&mut self.pages) {
+            if overlapping_sectors(&page, &self.sectors).is_empty() {
+                page_indices.push(None);
+                removed_pages.push(page);
+            } else {
+                page_indices.push(Some(kept_pages.len()));
+                kept_pages.push(page);
+            }
+        }
+        self.pages = kept_pages;
+
+        self.fills = self
+            .fills
+            .drain(..)
+            .filter_map(|fill| {
+                page_indices[fill.page_index].map(|page_index| FlashFill { page_index, ..fill })
+            })
+            .collect();
+
+        (removed_sectors, removed_pages)
+    }
 }
 
 /// A block of data that is to be written to flash.
@@ -173,6 +306,11 @@ impl<'data> FlashDataBlock<'data> {
     pub(super) fn size(&self) -> u32 {
         self.data.len() as u32
     }
+
+    /// Returns the data of the block.
+    pub(super) fn data(&self) -> &'data [u8] {
+        self.data
+    }
 }
 
 /// A block of data that is to be written to flash.
@@ -271,6 +409,11 @@ impl<'data> FlashBuilder<'data> {
         Ok(())
     }
 
+    /// Returns the added blocks of data, sorted by their address.
+    pub(super) fn data_blocks(&self) -> &[FlashDataBlock<'data>] {
+        &self.data_blocks
+    }
+
     /// Layouts the contents of a flash memory according to the contents of the flash builder.
     pub(super) fn build_sectors_and_pages(
         &self,
@@ -1243,4 +1386,94 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn unchanged_sector_is_detected() {
+        let flash_algorithm = assemble_demo_flash1();
+        let mut flash_builder = FlashBuilder::new();
+        flash_builder.add_data(0, &[42; 4096]).unwrap();
+        let flash_layout = flash_builder
+            .build_sectors_and_pages(&flash_algorithm, true)
+            .unwrap();
+        let sector = &flash_layout.sectors()[0];
+
+        assert!(flash_layout.sector_unchanged(sector, &[42; 4096], 255));
+
+        let mut current = vec![42; 4096];
+        current[4095] = 43;
+        assert!(!flash_layout.sector_unchanged(sector, &current, 255));
+    }
+
+    #[test]
+    fn partially_written_sector_requires_erased_tail() {
+        let flash_algorithm = assemble_demo_flash1();
+        let mut flash_builder = FlashBuilder::new();
+        flash_builder.add_data(0, &[42; 1024]).unwrap();
+        let flash_layout = flash_builder
+            .build_sectors_and_pages(&flash_algorithm, false)
+            .unwrap();
+        let sector = &flash_layout.sectors()[0];
+
+        let mut current = vec![255; 4096];
+        current[..1024].copy_from_slice(&[42; 1024]);
+        assert!(flash_layout.sector_unchanged(sector, &current, 255));
+
+        // An older, longer image left data behind, which an erase would clear.
+        current[2048] = 42;
+        assert!(!flash_layout.sector_unchanged(sector, &current, 255));
+    }
+
+    #[test]
+    fn remove_sectors_drops_pages_and_fills() {
+        let flash_algorithm = assemble_demo_flash1();
+        let mut flash_builder = FlashBuilder::new();
+        flash_builder.add_data(0, &[42; 4096]).unwrap();
+        flash_builder.add_data(4096, &[42; 1]).unwrap();
+        let mut flash_layout = flash_builder
+            .build_sectors_and_pages(&flash_algorithm, true)
+            .unwrap();
+        assert_eq!(flash_layout.sectors().len(), 2);
+
+        let (sectors, pages) = flash_layout.remove_sectors(&[true, false]);
+
+        assert_eq!(sectors.len(), 1);
+        assert_eq!(pages.len(), 4);
+        assert_eq!(flash_layout.sectors()[0].address(), 0x1000);
+        assert_eq!(flash_layout.pages().len(), 4);
+        assert!(flash_layout
+            .fills()
+            .iter()
+            .all(|fill| fill.page_index() < flash_layout.pages().len()
+                && flash_layout.pages()[fill.page_index()].address() <= fill.address()));
+    }
+
+    #[test]
+    fn remove_sectors_keeps_sectors_sharing_a_programmed_page() {
+        let flash_algorithm = assemble_demo_flash2();
+        let mut flash_builder = FlashBuilder::new();
+        flash_builder.add_data(0, &[42; 1024]).unwrap();
+        let mut flash_layout = flash_builder
+            .build_sectors_and_pages(&flash_algorithm, true)
+            .unwrap();
+        assert_eq!(flash_layout.sectors().len(), 8);
+
+        let mut skip = vec![true; 8];
+        skip[3] = false;
+        let (sectors, pages) = flash_layout.remove_sectors(&skip);
+
+        assert!(sectors.is_empty());
+        assert!(pages.is_empty());
+        assert_eq!(flash_layout.sectors().len(), 8);
+    }
+
+    #[test]
+    fn all_sectors_in_range() {
+        let flash_algorithm = assemble_demo_flash1();
+        let flash_layout =
+            FlashLayout::sectors_in_range(&flash_algorithm, &(0x1000..0x5000)).unwrap();
+
+        let addresses: Vec<_> = flash_layout.sectors().iter().map(|s| s.address()).collect();
+        assert_eq!(addresses, vec![0x1000, 0x2000, 0x3000, 0x4000]);
+        assert!(flash_layout.pages().is_empty());
+    }
 }
@@ -1,9 +1,10 @@
-use ihex::Record::*;
-
 use std::{
     fs::File,
     io::{Read, Seek, SeekFrom},
+    ops::Range,
     path::Path,
+    sync::mpsc::Sender,
+    time::Duration,
 };
 
 use super::*;
@@ -12,12 +13,12 @@ use crate::{config::MemoryRange, session::Session};
 use thiserror::Error;
 
 /// Extended options for flashing a binary file.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct BinOptions {
     /// The address in memory where the binary will be put at.
-    base_address: Option<u32>,
+    pub base_address: Option<u32>,
     /// The number of bytes to skip at the start of the binary file.
-    skip: u32,
+    pub skip: u32,
 }
 
 /// A finite list of all the available binary formats probe-rs understands.
@@ -46,6 +47,91 @@ pub enum FileDownloadError {
     Elf(#[from] goblin::error::Error),
     #[error("No loadable ELF sections were found.")]
     NoLoadableSegments,
+    #[error("The ELF segment at {start:#010x}..{end:#010x} is only partially selected by the segment filter.")]
+    SegmentCrossesFilterBoundary { start: u32, end: u32 },
+}
+
+/// Selects which loadable segments of an ELF file are flashed.
+///
+/// A segment is flashed if it matches the include filters, or there are none,
+/// and does not match any exclude filter. Segments are never split, a segment which
+/// is only partially selected results in an error.
+#[derive(Debug, Clone, Default)]
+pub struct SegmentFilter {
+    /// Only flash segments which are inside one of these address ranges.
+    pub include_ranges: Vec<Range<u32>>,
+    /// Skip segments which are inside one of these address ranges.
+    pub exclude_ranges: Vec<Range<u32>>,
+    /// Only flash segments containing a section with a name matching one of these patterns.
+    ///
+    /// A `*` in the pattern matches any number of characters, e.g. `.boot*`.
+    pub include_sections: Vec<String>,
+    /// Skip segments containing a section with a name matching one of these patterns.
+    pub exclude_sections: Vec<String>,
+}
+
+impl SegmentFilter {
+    /// Determines if the segment at `range` containing the sections `section_names` is flashed.
+    pub(super) fn selects(
+        &self,
+        range: &Range<u32>,
+        section_names: &[&str],
+    ) -> Result<bool, FileDownloadError> {
+        let crosses_boundary = || FileDownloadError::SegmentCrossesFilterBoundary {
+            start: range.start,
+            end: range.end,
+        };
+
+        let in_ranges = |ranges: &[Range<u32>]| -> Result<bool, FileDownloadError> {
+            if ranges.iter().any(|r| r.contains_range(range)) {
+                Ok(true)
+            } else if ranges.iter().any(|r| r.intersects_range(range)) {
+                Err(crosses_boundary())
+            } else {
+                Ok(false)
+            }
+        };
+
+        let matching_sections = |patterns: &[String]| {
+            section_names
+                .iter()
+                .filter(|name| patterns.iter().any(|p| matches_pattern(name, p)))
+                .count()
+        };
+
+        let mut selected = self.include_ranges.is_empty() || in_ranges(&self.include_ranges)?;
+        selected &= !in_ranges(&self.exclude_ranges)?;
+
+        if !self.include_sections.is_empty() {
+            match matching_sections(&self.include_sections) {
+                0 => selected = false,
+                n if n < section_names.len() => return Err(crosses_boundary()),
+                _ => (),
+            }
+        }
+
+        match matching_sections(&self.exclude_sections) {
+            0 => (),
+            n if n < section_names.len() => return Err(crosses_boundary()),
+            _ => selected = false,
+        }
+
+        Ok(selected)
+    }
+}
+
+/// Matches `name` against `pattern`, where a `*` matches any number of characters.
+fn matches_pattern(name: &str, pattern: &str) -> bool {
+    match pattern.find('*') {
+        None => name == pattern,
+        Some(star) => {
+            let (prefix, rest) = (&pattern[..star], &pattern[star + 1..]);
+
+            name.starts_with(prefix)
+                && (prefix.len()..=name.len())
+                    .any(|i| name.is_char_boundary(i) && matches_pattern(&name[i..], rest))
+        }
+    }
 }
 
 /// Options for downloading a file onto a target chip.
@@ -53,9 +139,60 @@ pub enum FileDownloadError {
 pub struct DownloadOptions<'progress> {
     /// An optional progress reporter which is used if this argument is set to Some(...).
     pub progress: Option<&'progress FlashProgress>,
+    /// An optional channel which receives a typed `FlashEvent` for every step of the flashing process.
+    ///
+    /// This can be used together with `progress`, both receive all events.
+    pub events: Option<Sender<FlashEvent>>,
     /// If `keep_unwritten_bytes` is `true`, erased portions that are not overwritten by the ELF data
     /// are restored afterwards, such that the old contents are untouched.
     pub keep_unwritten_bytes: bool,
+    /// If `skip_unchanged` is `true`, the current flash contents are read back first and all sectors
+    /// which already contain the new data are neither erased nor programmed.
+    pub skip_unchanged: bool,
+    /// If set, all programmed pages are verified with the given method.
+    ///
+    /// `VerifyMethod::Crc32` falls back to `VerifyMethod::Full` if the flash algorithm has no CRC32 routine.
+    /// The method which is actually used is reported by the `StartedVerifying` progress event.
+    pub verify: Option<VerifyMethod>,
+    /// Selects which segments of an ELF file are flashed, all of them by default.
+    ///
+    /// This is ignored for other formats.
+    pub segment_filter: SegmentFilter,
+    /// If `dry_run` is `true`, nothing is erased or programmed. The progress events are only simulated,
+    /// and the returned `FlashSummary` describes what would have been done.
+    ///
+    /// The flash is still read if this is combined with `skip_unchanged`.
+    pub dry_run: bool,
+    /// The RAM range the flash algorithms are loaded into, instead of the first RAM region of the target.
+    ///
+    /// The range has to be inside a RAM region of the target, and large enough for the code,
+    /// the stack and a page buffer of the flash algorithm.
+    pub algorithm_ram: Option<Range<u32>>,
+    /// If `double_buffering` is `true`, the next page is transferred to the target while the
+    /// current one is programmed.
+    ///
+    /// This needs an Arm core and a flash algorithm RAM region which fits a second page buffer,
+    /// otherwise every page is transferred and programmed one after another.
+    pub double_buffering: bool,
+    /// The interval in which the probe connection is kept alive while a flash algorithm routine
+    /// runs for a long time, `DEFAULT_KEEP_ALIVE_INTERVAL` if `None`.
+    ///
+    /// Some probes and USB stacks reset the connection if it is idle during a long chip erase.
+    pub keep_alive_interval: Option<Duration>,
+}
+
+impl DownloadOptions<'_> {
+    pub(super) fn program_options(&self) -> ProgramOptions {
+        ProgramOptions {
+            restore_unwritten_bytes: self.keep_unwritten_bytes,
+            skip_unchanged: self.skip_unchanged,
+            verify: self.verify,
+            dry_run: self.dry_run,
+            enable_double_buffering: self.double_buffering,
+            keep_alive_interval: self.keep_alive_interval,
+            ..Default::default()
+        }
+    }
 }
 
 /// Downloads a file of given `format` at `path` to the flash of the target given in `session`.
@@ -68,160 +205,188 @@ pub fn download_file(
     path: &Path,
     format: Format,
 ) -> Result<(), FileDownloadError> {
-    download_file_with_options(session, path, format, DownloadOptions::default())
+    download_file_with_options(session, path, format, DownloadOptions::default())?;
+
+    Ok(())
 }
 
 /// Downloads a file of given `format` at `path` to the flash of the target given in `session`.
 ///
 /// This will ensure that memory bounderies are honored and does unlocking, erasing and programming of the flash for you.
+///
+/// Returns a summary of the erased and programmed flash, see `DownloadOptions::dry_run`.
 pub fn download_file_with_options(
     session: &mut Session,
     path: &Path,
     format: Format,
     options: DownloadOptions<'_>,
-) -> Result<(), FileDownloadError> {
-    let mut file = match File::open(path) {
-        Ok(file) => file,
-        Err(e) => return Err(FileDownloadError::IO(e)),
-    };
+) -> Result<FlashSummary, FileDownloadError> {
     let mut buffer = vec![];
     let mut buffer_vec = vec![];
     // IMPORTANT: Change this to an actual memory map of a real chip
     let memory_map = session.memory_map().to_vec();
-    let mut loader = FlashLoader::new(&memory_map, options.keep_unwritten_bytes);
+    let mut loader = FlashLoader::new(&memory_map, options.program_options());
 
-    match format {
-        Format::Bin(options) => download_bin(&mut buffer, &mut file, &mut loader, options),
-        Format::Elf => download_elf(&mut buffer, &mut file, &mut loader),
-        Format::Hex => download_hex(&mut buffer_vec, &mut file, &mut loader),
-    }?;
+    load_file(
+        &mut loader,
+        path,
+        format,
+        &options.segment_filter,
+        &mut buffer,
+        &mut buffer_vec,
+    )?;
 
-    loader
-        // TODO: hand out chip erase flag
-        .commit(
-            session,
-            options.progress.unwrap_or(&FlashProgress::new(|_| {})),
-            false,
-        )
-        .map_err(FileDownloadError::Flash)
+    commit(session, &mut loader, options)
 }
 
-/// Starts the download of a binary file.
-fn download_bin<'buffer, T: Read + Seek>(
-    buffer: &'buffer mut Vec<u8>,
-    file: &'buffer mut T,
-    loader: &mut FlashLoader<'_, 'buffer>,
-    options: BinOptions,
+/// Stages the contents of the file of given `format` at `path` in `loader`.
+///
+/// The file is read into `buffer`, or parsed into `buffer_vec` for HEX files.
+pub(super) fn load_file<'data>(
+    loader: &mut FlashLoader<'_, 'data>,
+    path: &Path,
+    format: Format,
+    segment_filter: &SegmentFilter,
+    buffer: &'data mut Vec<u8>,
+    buffer_vec: &'data mut Vec<(u32, Vec<u8>)>,
 ) -> Result<(), FileDownloadError> {
-    // Skip the specified bytes.
-    file.seek(SeekFrom::Start(u64::from(options.skip)))?;
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(FileDownloadError::IO(e)),
+    };
 
-    file.read_to_end(buffer)?;
+    match format {
+        Format::Bin(bin_options) => {
+            // Skip the specified bytes.
+            file.seek(SeekFrom::Start(u64::from(bin_options.skip)))?;
+            file.read_to_end(buffer)?;
 
-    loader.add_data(
-        if let Some(address) = options.base_address {
-            address
-        } else {
             // If no base address is specified use the start of the boot memory.
             // TODO: Implement this as soon as we know targets.
-            0
-        },
-        buffer.as_slice(),
-    )?;
+            loader.load_bin_data(bin_options.base_address.unwrap_or(0), buffer)?;
+        }
+        Format::Elf => {
+            file.read_to_end(buffer)?;
+            loader.load_elf_data(buffer, segment_filter)?;
+        }
+        Format::Hex => {
+            let mut data = String::new();
+            file.read_to_string(&mut data)?;
+            loader.load_hex_data(buffer_vec, &data)?;
+        }
+    }
 
     Ok(())
 }
 
-/// Starts the download of a hex file.
-fn download_hex<'buffer, T: Read + Seek>(
-    buffer: &'buffer mut Vec<(u32, Vec<u8>)>,
-    file: &mut T,
-    loader: &mut FlashLoader<'_, 'buffer>,
-) -> Result<(), FileDownloadError> {
-    let mut _extended_segment_address = 0;
-    let mut extended_linear_address = 0;
-
-    let mut data = String::new();
-    file.read_to_string(&mut data)?;
-
-    for record in ihex::Reader::new(&data) {
-        let record = record?;
-        match record {
-            Data { offset, value } => {
-                let offset = extended_linear_address | offset as u32;
-                buffer.push((offset, value));
-            }
-            EndOfFile => return Ok(()),
-            ExtendedSegmentAddress(address) => {
-                _extended_segment_address = address * 16;
-            }
-            StartSegmentAddress { .. } => (),
-            ExtendedLinearAddress(address) => {
-                extended_linear_address = (address as u32) << 16;
-            }
-            StartLinearAddress(_) => (),
-        };
+/// Downloads the data `blocks`, given as their start address and data, to the flash of the target given in `session`.
+///
+/// All blocks have to be inside of flash regions, and must not overlap.
+pub fn download_data(
+    session: &mut Session,
+    blocks: &[(u32, Vec<u8>)],
+    options: DownloadOptions<'_>,
+) -> Result<FlashSummary, FileDownloadError> {
+    let memory_map = session.memory_map().to_vec();
+    let mut loader = FlashLoader::new(&memory_map, options.program_options());
+
+    for (address, data) in blocks {
+        loader.add_data(*address, data)?;
     }
-    for (offset, data) in buffer {
-        loader.add_data(*offset, data.as_slice())?;
+
+    commit(session, &mut loader, options)
+}
+
+pub(super) fn commit(
+    session: &mut Session,
+    loader: &mut FlashLoader,
+    options: DownloadOptions<'_>,
+) -> Result<FlashSummary, FileDownloadError> {
+    loader.set_algorithm_ram(options.algorithm_ram);
+
+    let mut progress = options.progress.cloned().unwrap_or_default();
+    if let Some(sender) = options.events {
+        progress = progress.with_sender(sender);
     }
-    Ok(())
+
+    loader
+        // TODO: hand out chip erase flag
+        .commit(session, &progress, false)
+        .map_err(FileDownloadError::Flash)
 }
 
-/// Starts the download of a elf file.
-fn download_elf<'buffer, T: Read + Seek>(
-    buffer: &'buffer mut Vec<u8>,
-    file: &'buffer mut T,
-    loader: &mut FlashLoader<'_, 'buffer>,
-) -> Result<(), FileDownloadError> {
-    use goblin::elf::program_header::*;
-
-    file.read_to_end(buffer)?;
-
-    let binary = goblin::elf::Elf::parse(&buffer.as_slice())?;
-    let mut added_sections = vec![];
-    for ph in &binary.program_headers {
-        if ph.p_type == PT_LOAD && ph.p_filesz > 0 {
-            log::debug!("Found loadable segment.");
-
-            let sector: core::ops::Range<u32> =
-                ph.p_offset as u32..ph.p_offset as u32 + ph.p_filesz as u32;
-
-            for sh in &binary.section_headers {
-                if sector
-                    .contains_range(&(sh.sh_offset as u32..sh.sh_offset as u32 + sh.sh_size as u32))
-                {
-                    #[cfg(feature = "hexdump")]
-                    for line in hexdump::hexdump_iter(
-                        &buffer[sh.sh_offset as usize..][..sh.sh_size as usize],
-                    ) {
-                        log::trace!("{}", line);
-                    }
-
-                    added_sections.push((&binary.shdr_strtab[sh.sh_name], sh.sh_addr, sh.sh_size));
-                }
-            }
+#[cfg(test)]
+mod test {
+    use super::*;
 
-            loader.add_data(
-                ph.p_paddr as u32,
-                &buffer[ph.p_offset as usize..][..ph.p_filesz as usize],
-            )?;
-        }
+    #[test]
+    fn empty_filter_selects_everything() {
+        let filter = SegmentFilter::default();
+
+        assert!(filter
+            .selects(&(0x0800_0000..0x0800_4000), &[".text"])
+            .unwrap());
     }
-    if added_sections.is_empty() {
-        log::warn!("No loadable segments were found in the ELF file.");
-        Err(FileDownloadError::NoLoadableSegments)
-    } else {
-        log::info!("Found {} loadable sections:", added_sections.len());
-        for section in added_sections {
-            log::info!(
-                "    {} at {:08X?} ({} byte{})",
-                section.0,
-                section.1,
-                section.2,
-                if section.2 == 1 { "" } else { "0" }
-            );
-        }
-        Ok(())
+
+    #[test]
+    fn address_ranges() {
+        let filter = SegmentFilter {
+            include_ranges: vec![0x0800_0000..0x0801_0000, 0x2000_0000..0x2001_0000],
+            ..Default::default()
+        };
+
+        assert!(filter.selects(&(0x0800_0000..0x0800_4000), &[]).unwrap());
+        assert!(!filter.selects(&(0x0802_0000..0x0802_4000), &[]).unwrap());
+
+        let filter = SegmentFilter {
+            exclude_ranges: vec![0x0800_0000..0x0801_0000, 0x2000_0000..0x2001_0000],
+            ..Default::default()
+        };
+
+        assert!(!filter.selects(&(0x0800_0000..0x0800_4000), &[]).unwrap());
+        assert!(filter.selects(&(0x0802_0000..0x0802_4000), &[]).unwrap());
+    }
+
+    #[test]
+    fn segment_crossing_boundary_is_an_error() {
+        let filter = SegmentFilter {
+            include_ranges: vec![0x0800_0000..0x0801_0000, 0x2000_0000..0x2001_0000],
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            filter.selects(&(0x0800_c000..0x0801_4000), &[]),
+            Err(FileDownloadError::SegmentCrossesFilterBoundary {
+                start: 0x0800_c000,
+                end: 0x0801_4000
+            })
+        ));
+    }
+
+    #[test]
+    fn section_patterns() {
+        let filter = SegmentFilter {
+            exclude_sections: vec![".boot*".to_owned()],
+            ..Default::default()
+        };
+
+        assert!(!filter
+            .selects(&(0x0800_0000..0x0800_4000), &[".bootloader"])
+            .unwrap());
+        assert!(filter
+            .selects(&(0x0800_4000..0x0800_8000), &[".vector_table", ".text"])
+            .unwrap());
+        assert!(filter
+            .selects(&(0x0800_0000..0x0800_8000), &[".bootloader", ".text"])
+            .is_err());
+    }
+
+    #[test]
+    fn wildcard_pattern() {
+        assert!(matches_pattern(".text", ".text"));
+        assert!(matches_pattern(".text.main", ".text*"));
+        assert!(matches_pattern(".app.rodata", "*.rodata"));
+        assert!(matches_pattern(".app.rodata", ".*.*"));
+        assert!(!matches_pattern(".data", ".text*"));
     }
 }
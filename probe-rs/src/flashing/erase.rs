@@ -0,0 +1,49 @@
+use super::{FlashError, FlashLoader, FlashProgress, ProgramOptions};
+use crate::session::Session;
+
+/// Erases the complete flash of the target given in `session`.
+///
+/// Every flash region is erased with the `EraseChip` routine of its flash algorithm if available,
+/// otherwise all of its sectors are erased one by one. The progress of the erase is reported to `progress`.
+///
+/// On some parts a mass erase has side effects beyond the flash contents, e.g. it also clears the
+/// readout protection and user configuration. Use `erase_all_side_effects` to inform the user about them
+/// before erasing. Parts which are locked by readout protection have to be unlocked with their
+/// vendor specific unlock sequence first, which is not supported yet.
+pub fn erase_all(session: &mut Session, progress: &FlashProgress) -> Result<(), FlashError> {
+    let memory_map = session.memory_map().to_vec();
+    let loader = FlashLoader::new(&memory_map, ProgramOptions::default());
+
+    if let Some(side_effects) = erase_all_side_effects(session) {
+        log::warn!("{}", side_effects);
+    }
+
+    loader.erase_all(session, progress)
+}
+
+/// Describes the side effects of `erase_all` on the target given in `session`, if it has any.
+pub fn erase_all_side_effects(session: &Session) -> Option<&'static str> {
+    side_effects(session.chip_name())
+}
+
+fn side_effects(chip_name: &str) -> Option<&'static str> {
+    let chip_name = chip_name.to_ascii_lowercase();
+
+    if chip_name.starts_with("nrf") {
+        Some("Erasing the whole flash also erases the UICR, which contains the readout protection (APPROTECT) and the user configuration.")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nrf_erase_clears_uicr() {
+        assert!(side_effects("nRF52840_xxAA").is_some());
+        assert!(side_effects("nrf51822").is_some());
+        assert!(side_effects("STM32F401CCUx").is_none());
+    }
+}
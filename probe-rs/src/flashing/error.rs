@@ -20,6 +20,12 @@ pub enum FlashError {
     AddressNotInRegion { address: u32, region: FlashRegion },
     #[error("Flash algorithm length is not 32 bit aligned.")]
     InvalidFlashAlgorithmLength,
+    #[error("The flash algorithm '{name}' is invalid: {reason}.")]
+    InvalidFlashAlgorithm { name: String, reason: String },
+    #[error("The flash algorithm needs {required} bytes of RAM, but only {available} bytes are available.")]
+    AlgorithmRamTooSmall { required: u32, available: u32 },
+    #[error("The range {start:#010x}..{end:#010x} for the flash algorithm is not inside a RAM region of the target.")]
+    InvalidAlgorithmRam { start: u32, end: u32 },
     #[error(
         "The RAM contents did not match the expected contents after loading the flash algorithm."
     )]
@@ -28,6 +34,23 @@ pub enum FlashError {
         "The page write of the page at address {page_address:#08X} failed with error code {error_code}."
     )]
     PageWrite { page_address: u32, error_code: u32 },
+    #[error(
+        "Verification failed, the flash contents at {address:#010x} do not match the written data."
+    )]
+    VerifyMismatch { address: u32 },
+    #[error("Verification failed, the CRC32 of {size} bytes at {address:#010x} is {actual:#010x} instead of {expected:#010x}.")]
+    VerifyChecksumMismatch {
+        address: u32,
+        size: u32,
+        expected: u32,
+        actual: u32,
+    },
+    #[error("Reading the flash at {address:#010x} failed, the flash may be read protected.")]
+    VerifyReadFailed {
+        address: u32,
+        #[source]
+        source: error::Error,
+    },
     #[error("Overlap in data, address {0:#010x} was already written earlier.")]
     DataOverlap(u32),
     #[error("Address {0:#010x} is not a valid address in the flash area.")]
@@ -1,14 +1,17 @@
 use super::FlashProgress;
 use super::{FlashBuilder, FlashError, FlashFill, FlashLayout, FlashPage};
-use crate::config::{FlashAlgorithm, FlashRegion, MemoryRange};
+use crate::config::{FlashAlgorithm, FlashProperties, FlashRegion, MemoryRange};
 use crate::memory::MemoryInterface;
 use crate::{
     core::{Architecture, RegisterFile},
     session::Session,
-    Core, CoreRegisterAddress,
+    Core, CoreRegisterAddress, DebugProbeError,
 };
 use anyhow::{anyhow, Result};
-use std::{fmt::Debug, time::Duration};
+use std::{
+    fmt::Debug,
+    time::{Duration, Instant},
+};
 
 pub(super) trait Operation {
     fn operation() -> u32;
@@ -46,6 +49,127 @@ impl Operation for Verify {
     }
 }
 
+/// The method used to verify the flash contents after programming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyMethod {
+    /// Read back all programmed data and compare it on the host.
+    Full,
+    /// Compute the CRC32 of every programmed range in-target and only compare the result.
+    ///
+    /// This requires a flash algorithm with a CRC32 routine, otherwise `Full` is used instead.
+    Crc32,
+}
+
+/// Options which control how `Flasher::program` writes the contents of a `FlashBuilder`.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct ProgramOptions {
+    /// Erase the entire flash instead of single sectors, if the flash algorithm supports it.
+    pub(super) do_chip_erase: bool,
+    /// Read all bytes of a sector, that are not to be written during flashing, from the flash first
+    /// and write them again once the sector is erased.
+    pub(super) restore_unwritten_bytes: bool,
+    /// Use double buffering, if the flash algorithm supports it.
+    pub(super) enable_double_buffering: bool,
+    /// Neither erase nor program sectors which already contain the data to be written.
+    /// This has no effect if a chip erase is done.
+    pub(super) skip_unchanged: bool,
+    /// Verify all programmed pages with the given method.
+    pub(super) verify: Option<VerifyMethod>,
+    /// Only report what would be erased and programmed, without erasing or programming anything.
+    ///
+    /// The flash is still read if this is combined with `skip_unchanged`.
+    pub(super) dry_run: bool,
+    /// The interval of the keep-alive accesses while a flash algorithm routine runs for a long time,
+    /// `DEFAULT_KEEP_ALIVE_INTERVAL` if not set.
+    pub(super) keep_alive_interval: Option<Duration>,
+}
+
+/// A summary of the flash operations done by `Flasher::program`, or planned during a dry run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FlashSummary {
+    /// The number of erased sectors.
+    pub sectors_erased: usize,
+    /// The number of programmed pages.
+    pub pages_programmed: usize,
+    /// The total number of bytes in the programmed pages.
+    pub bytes_programmed: u32,
+    /// The time erasing and programming takes, estimated from the flash properties of the flash algorithm.
+    pub estimated_time: Duration,
+}
+
+impl FlashSummary {
+    /// Creates the summary for erasing and programming `flash_layout` with `flash_algorithm`.
+    fn new(flash_layout: &FlashLayout, flash_algorithm: &FlashAlgorithm) -> Self {
+        let properties = &flash_algorithm.flash_properties;
+        let sectors_erased = flash_layout.sectors().len();
+        let pages_programmed = flash_layout.pages().len();
+
+        Self {
+            sectors_erased,
+            pages_programmed,
+            bytes_programmed: flash_layout.pages().iter().map(|p| p.size()).sum(),
+            estimated_time: erase_estimate(properties) * sectors_erased as u32
+                + program_estimate(properties) * pages_programmed as u32,
+        }
+    }
+}
+
+impl std::ops::AddAssign for FlashSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.sectors_erased += other.sectors_erased;
+        self.pages_programmed += other.pages_programmed;
+        self.bytes_programmed += other.bytes_programmed;
+        self.estimated_time += other.estimated_time;
+    }
+}
+
+/// The shortest time the `EraseChip` routine of a flash algorithm is given to finish.
+const CHIP_ERASE_MIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The interval of the keep-alive accesses while a flash algorithm routine runs for a long time,
+/// unless `DownloadOptions::keep_alive_interval` is set.
+pub const DEFAULT_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Once a flash algorithm routine runs for this long, its completion is only polled every
+/// `LONG_ROUTINE_POLL_INTERVAL`, and the probe connection is kept alive in between.
+const LONG_ROUTINE: Duration = Duration::from_millis(100);
+
+const LONG_ROUTINE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Decides when the next keep-alive access is due while waiting for a flash algorithm routine.
+#[derive(Debug)]
+struct KeepAlive {
+    interval: Duration,
+    last: Instant,
+}
+
+impl KeepAlive {
+    fn new(interval: Duration, start: Instant) -> Self {
+        Self {
+            interval,
+            last: start,
+        }
+    }
+
+    /// Returns whether a keep-alive access is due at `now`, and starts the next interval if it is.
+    fn due(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.last) < self.interval {
+            return false;
+        }
+
+        self.last = now;
+        true
+    }
+}
+
+fn erase_estimate(properties: &FlashProperties) -> Duration {
+    Duration::from_millis(u64::from(properties.erase_sector_timeout))
+}
+
+fn program_estimate(properties: &FlashProperties) -> Duration {
+    Duration::from_millis(u64::from(properties.program_page_timeout))
+}
+
 /// A structure to control the flash of an attached microchip.
 ///
 /// Once constructed it can be used to program date to the flash.
@@ -56,7 +180,7 @@ pub struct Flasher<'session> {
     session: &'session mut Session,
     flash_algorithm: FlashAlgorithm,
     region: FlashRegion,
-    double_buffering_supported: bool,
+    keep_alive_interval: Duration,
 }
 
 impl<'session> Flasher<'session> {
@@ -69,16 +193,25 @@ impl<'session> Flasher<'session> {
             session,
             flash_algorithm,
             region,
-            double_buffering_supported: false,
+            keep_alive_interval: DEFAULT_KEEP_ALIVE_INTERVAL,
         }
     }
 
+    /// Sets the interval of the keep-alive accesses, `DEFAULT_KEEP_ALIVE_INTERVAL` if `None`.
+    pub(super) fn set_keep_alive_interval(&mut self, interval: Option<Duration>) {
+        self.keep_alive_interval = interval.unwrap_or(DEFAULT_KEEP_ALIVE_INTERVAL);
+    }
+
     pub(super) fn flash_algorithm(&self) -> &FlashAlgorithm {
         &self.flash_algorithm
     }
 
+    /// Whether the flash algorithm has a second page buffer, which is needed for double buffering.
+    ///
+    /// The buffer is written while the core runs the flash algorithm, so this is only supported on Arm cores.
     pub(super) fn double_buffering_supported(&self) -> bool {
-        self.double_buffering_supported
+        self.flash_algorithm.page_buffers.len() > 1
+            && self.session.architecture() == Architecture::Arm
     }
 
     pub(super) fn init<O: Operation>(
@@ -114,15 +247,23 @@ impl<'session> Flasher<'session> {
             algo.load_address
         );
 
-        core.write_32(algo.load_address, algo.instructions.as_slice())
+        // The instructions are the words of the memory bus, which must not be converted
+        // to the byte order of the data.
+        let data_order = core.byte_order().data;
+        let instructions: Vec<u32> = algo
+            .instructions
+            .iter()
+            .map(|word| data_order.convert_word_32(*word))
+            .collect();
+
+        core.write_32(algo.load_address.into(), &instructions)
             .map_err(FlashError::Memory)?;
 
-        let mut data = vec![0; algo.instructions.len()];
-        core.read_32(algo.load_address, &mut data)
+        let mut data = vec![0; instructions.len()];
+        core.read_32(algo.load_address.into(), &mut data)
             .map_err(FlashError::Memory)?;
 
-        for (offset, (original, read_back)) in algo.instructions.iter().zip(data.iter()).enumerate()
-        {
+        for (offset, (original, read_back)) in instructions.iter().zip(data.iter()).enumerate() {
             if original != read_back {
                 log::error!(
                     "Failed to verify flash algorithm. Data mismatch at address {:#08x}",
@@ -131,7 +272,7 @@ impl<'session> Flasher<'session> {
                 log::error!("Original instruction: {:#08x}", original);
                 log::error!("Readback instruction: {:#08x}", read_back);
 
-                log::error!("Original: {:x?}", &algo.instructions);
+                log::error!("Original: {:x?}", &instructions);
                 log::error!("Readback: {:x?}", &data);
 
                 return Err(anyhow!(FlashError::FlashAlgorithmNotLoaded));
@@ -142,14 +283,10 @@ impl<'session> Flasher<'session> {
 
         log::debug!("Preparing Flasher for region:");
         log::debug!("{:#?}", &self.region);
-        log::debug!(
-            "Double buffering enabled: {}",
-            self.double_buffering_supported
-        );
         let mut flasher = ActiveFlasher::<O> {
             core,
             flash_algorithm: self.flash_algorithm.clone(),
-            _double_buffering_supported: self.double_buffering_supported,
+            keep_alive_interval: self.keep_alive_interval,
             _operation: core::marker::PhantomData,
         };
 
@@ -194,6 +331,16 @@ impl<'session> Flasher<'session> {
         Ok(r)
     }
 
+    /// Loads the flash algorithm, and runs only its `Init` and `UnInit` routines.
+    pub(crate) fn run_init_uninit(&mut self) -> Result<(), FlashError> {
+        self.run_verify(|_| Ok(())).map_err(|error: anyhow::Error| {
+            match error.downcast::<FlashError>() {
+                Ok(error) => error,
+                Err(error) => FlashError::Other(error),
+            }
+        })
+    }
+
     /// Writes a single block of data to a given address in the flash.
     ///
     /// This will not check any physical flash boundaries.
@@ -220,29 +367,45 @@ impl<'session> Flasher<'session> {
 
         let mut fb = FlashBuilder::new();
         fb.add_data(address, data)?;
-        self.program(&fb, do_chip_erase, true, false, progress)?;
+        self.program(
+            &fb,
+            ProgramOptions {
+                do_chip_erase,
+                restore_unwritten_bytes: true,
+                ..Default::default()
+            },
+            progress,
+        )?;
 
         Ok(())
     }
 
-    /// Program the contents of given `FlashBuilder` to the flash.
+    /// Program the contents of given `FlashBuilder` to the flash, as configured by `options`.
     ///
-    /// If `restore_unwritten_bytes` is `true`, all bytes of a sector,
-    /// that are not to be written during flashing will be read from the flash first
-    /// and written again once the sector is erased.
+    /// During a dry run, the flash algorithm is never loaded and nothing is erased or programmed.
+    /// The returned summary then describes what would have been done.
     pub(super) fn program(
         &mut self,
         flash_builder: &FlashBuilder,
-        mut do_chip_erase: bool,
-        restore_unwritten_bytes: bool,
-        enable_double_buffering: bool,
+        options: ProgramOptions,
         progress: &FlashProgress,
-    ) -> Result<()> {
+    ) -> Result<FlashSummary> {
+        let ProgramOptions {
+            mut do_chip_erase,
+            restore_unwritten_bytes,
+            enable_double_buffering,
+            skip_unchanged,
+            verify,
+            dry_run,
+            keep_alive_interval,
+        } = options;
+        self.set_keep_alive_interval(keep_alive_interval);
+
         // Convert the list of flash operations into flash sectors and pages.
         let mut flash_layout = flash_builder
             .build_sectors_and_pages(&self.flash_algorithm().clone(), restore_unwritten_bytes)?;
 
-        progress.initialized(flash_layout.clone());
+        progress.initialized(flash_layout.clone(), dry_run);
 
         // If the flash algo doesn't support erase all, disable chip erase.
         if self.flash_algorithm().pc_erase_all.is_none() {
@@ -251,23 +414,31 @@ impl<'session> Flasher<'session> {
 
         log::debug!("Full Chip Erase enabled: {:?}", do_chip_erase);
         log::debug!("Double Buffering enabled: {:?}", enable_double_buffering);
+        log::debug!("Dry run: {:?}", dry_run);
 
         // Read all fill areas from the flash.
         progress.started_filling();
 
         if restore_unwritten_bytes {
+            // A dry run only needs the fills to compare the sectors with the current flash contents.
+            let read_fills = !dry_run || skip_unchanged;
+
             let fills = flash_layout.fills().to_vec();
             for fill in fills {
                 let t = std::time::Instant::now();
-                let page = &mut flash_layout.pages_mut()[fill.page_index()];
-                let result = self.fill_page(page, &fill);
+                let result = if read_fills {
+                    let page = &mut flash_layout.pages_mut()[fill.page_index()];
+                    self.fill_page(page, &fill, dry_run)
+                } else {
+                    Ok(())
+                };
 
                 // If we encounter an error, catch it, gracefully report the failure and return the error.
                 if result.is_err() {
                     progress.failed_filling();
-                    return result;
+                    return result.map(|_| FlashSummary::default());
                 } else {
-                    progress.page_filled(fill.size(), t.elapsed());
+                    progress.page_filled(fill.address(), fill.size(), t.elapsed());
                 }
             }
         }
@@ -275,6 +446,23 @@ impl<'session> Flasher<'session> {
         // We successfully finished filling.
         progress.finished_filling();
 
+        if skip_unchanged && !do_chip_erase {
+            self.skip_unchanged_sectors(&mut flash_layout, progress, dry_run)?;
+        }
+
+        let summary = FlashSummary::new(&flash_layout, &self.flash_algorithm);
+
+        if dry_run {
+            simulate(&flash_layout, &self.flash_algorithm, progress);
+
+            log::info!(
+                "Dry run: {} sectors would be erased and {} pages programmed.",
+                summary.sectors_erased,
+                summary.pages_programmed
+            );
+            return Ok(summary);
+        }
+
         // Erase all necessary sectors.
         if do_chip_erase {
             self.chip_erase(&flash_layout, progress)?;
@@ -284,13 +472,62 @@ impl<'session> Flasher<'session> {
 
         // Flash all necessary pages.
 
+        if enable_double_buffering && !self.double_buffering_supported() {
+            log::info!(
+                "Double buffering is not supported for the flash algorithm '{}', programming without it.",
+                self.flash_algorithm.name
+            );
+        }
+
         if self.double_buffering_supported() && enable_double_buffering {
             self.program_double_buffer(&flash_layout, progress)?;
         } else {
             self.program_simple(&flash_layout, progress)?;
         };
 
-        Ok(())
+        // Pages which were skipped as unchanged have been compared already.
+        if let Some(method) = verify {
+            self.verify(&flash_layout, method, progress)?;
+        }
+
+        Ok(summary)
+    }
+
+    /// Erases the whole flash region of this flasher.
+    ///
+    /// This uses the `EraseChip` routine of the flash algorithm if it has one, which might erase
+    /// other flash regions covered by the same algorithm as well. Otherwise all sectors of the region
+    /// are erased one after the other. Returns `true` if the chip erase routine was used.
+    pub(super) fn erase_all(&mut self, progress: &FlashProgress) -> Result<bool> {
+        let flash_layout =
+            FlashLayout::sectors_in_range(&self.flash_algorithm, &self.region.range)?;
+
+        progress.initialized(flash_layout.clone(), false);
+
+        if self.flash_algorithm.pc_erase_all.is_some() {
+            self.chip_erase(&flash_layout, progress)?;
+            Ok(true)
+        } else {
+            self.sector_erase(&flash_layout, progress)?;
+            Ok(false)
+        }
+    }
+
+    /// Runs `f`, which only reads from the flash.
+    ///
+    /// This loads the flash algorithm first, unless `dry_run` is set. Loading it writes
+    /// to the target RAM, so a dry run reads the memory mapped flash directly instead.
+    fn run_read<T>(
+        &mut self,
+        dry_run: bool,
+        f: impl FnOnce(&mut Core<'_>) -> Result<T>,
+    ) -> Result<T> {
+        if dry_run {
+            let mut core = self.session.core(0).map_err(FlashError::Memory)?;
+            f(&mut core)
+        } else {
+            self.run_verify(|active| f(&mut active.core))
+        }
     }
 
     /// Fills all the bytes of `current_page`.
@@ -298,10 +535,169 @@ impl<'session> Flasher<'session> {
     /// If `restore_unwritten_bytes` is `true`, all bytes of the page,
     /// that are not to be written during flashing will be read from the flash first
     /// and written again once the page is programmed.
-    pub(super) fn fill_page(&mut self, page: &mut FlashPage, fill: &FlashFill) -> Result<()> {
+    pub(super) fn fill_page(
+        &mut self,
+        page: &mut FlashPage,
+        fill: &FlashFill,
+        dry_run: bool,
+    ) -> Result<()> {
         let page_offset = (fill.address() - page.address()) as usize;
         let page_slice = &mut page.data_mut()[page_offset..page_offset + fill.size() as usize];
-        self.run_verify(|active| active.read_block8(fill.address(), page_slice))
+        self.run_read(dry_run, |core| {
+            core.read(u64::from(fill.address()), page_slice)
+                .map_err(FlashError::Memory)?;
+            Ok(())
+        })
+    }
+
+    /// Removes all sectors from `flash_layout` whose contents in flash already match the data to be written.
+    fn skip_unchanged_sectors(
+        &mut self,
+        flash_layout: &mut FlashLayout,
+        progress: &FlashProgress,
+        dry_run: bool,
+    ) -> Result<()> {
+        let erased_byte_value = self.flash_algorithm.flash_properties.erased_byte_value;
+        let sectors = flash_layout.sectors().to_vec();
+
+        let layout = &*flash_layout;
+        let unchanged = self.run_read(dry_run, |core| {
+            let mut unchanged = Vec::with_capacity(sectors.len());
+            for sector in &sectors {
+                progress.verifying(sector.address(), sector.size());
+                let mut current = vec![0; sector.size() as usize];
+                core.read(u64::from(sector.address()), &mut current)
+                    .map_err(FlashError::Memory)?;
+                unchanged.push(layout.sector_unchanged(sector, &current, erased_byte_value));
+            }
+            Ok::<_, anyhow::Error>(unchanged)
+        })?;
+
+        let (skipped_sectors, skipped_pages) = flash_layout.remove_sectors(&unchanged);
+
+        log::debug!(
+            "Skipping {} of {} sectors, their contents are unchanged.",
+            skipped_sectors.len(),
+            sectors.len()
+        );
+
+        for sector in &skipped_sectors {
+            progress.sector_skipped(sector.address(), sector.size());
+        }
+        for page in &skipped_pages {
+            progress.page_skipped(page.address(), page.size());
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the flash contains the pages given in `flash_layout`.
+    ///
+    /// Falls back to `VerifyMethod::Full` if `VerifyMethod::Crc32` is requested,
+    /// but the flash algorithm has no CRC32 routine.
+    fn verify(
+        &mut self,
+        flash_layout: &FlashLayout,
+        mut method: VerifyMethod,
+        progress: &FlashProgress,
+    ) -> Result<()> {
+        if method == VerifyMethod::Crc32 && self.flash_algorithm.pc_crc32.is_none() {
+            log::warn!("The flash algorithm has no CRC32 routine, verifying by reading back the flash contents instead.");
+            method = VerifyMethod::Full;
+        }
+
+        log::info!("Verifying flash contents using {:?}.", method);
+        progress.started_verifying(method);
+
+        let result = self.run_verify(|active| {
+            for (address, data) in contiguous_ranges(flash_layout.pages()) {
+                progress.verifying(address, data.len() as u32);
+
+                match method {
+                    VerifyMethod::Full => {
+                        let mut current = vec![0; data.len()];
+                        active.read_block8(address, &mut current)?;
+
+                        if let Some(offset) = current.iter().zip(&data).position(|(c, d)| c != d) {
+                            return Err(anyhow!(FlashError::VerifyMismatch {
+                                address: address + offset as u32,
+                            }));
+                        }
+                    }
+                    VerifyMethod::Crc32 => {
+                        let expected = crc32(&data);
+                        let actual = active.crc32(address, data.len() as u32)?;
+
+                        if actual != expected {
+                            return Err(anyhow!(FlashError::VerifyChecksumMismatch {
+                                address,
+                                size: data.len() as u32,
+                                expected,
+                                actual,
+                            }));
+                        }
+                    }
+                }
+            }
+            Ok(())
+        });
+
+        if result.is_ok() {
+            progress.finished_verifying();
+        } else {
+            progress.failed_verifying();
+        }
+
+        result
+    }
+
+    /// Compares the data of `flash_builder` with the flash contents, without erasing or programming anything.
+    ///
+    /// Returns the first address at which the flash differs from the data. With `VerifyMethod::Crc32`,
+    /// only the blocks whose checksum differs are read back to find it, which requires a
+    /// CRC32 routine of the flash algorithm.
+    pub(super) fn verify_data(
+        &mut self,
+        flash_builder: &FlashBuilder,
+        method: VerifyMethod,
+        progress: &FlashProgress,
+    ) -> Result<Option<u32>> {
+        log::info!("Comparing the flash contents using {:?}.", method);
+        progress.started_verifying(method);
+
+        let result = self.run_verify(|active| {
+            for block in flash_builder.data_blocks() {
+                progress.verifying(block.address(), block.size());
+
+                if method == VerifyMethod::Crc32
+                    && active.crc32(block.address(), block.size())? == crc32(block.data())
+                {
+                    continue;
+                }
+
+                let mut current = vec![0; block.data().len()];
+                active
+                    .core
+                    .read(block.address().into(), &mut current)
+                    .map_err(|source| FlashError::VerifyReadFailed {
+                        address: block.address(),
+                        source,
+                    })?;
+
+                if let Some(address) = first_mismatch(block.address(), block.data(), &current) {
+                    return Ok(Some(address));
+                }
+            }
+            Ok(None)
+        });
+
+        if result.is_ok() {
+            progress.finished_verifying();
+        } else {
+            progress.failed_verifying();
+        }
+
+        result
     }
 
     /// Erase the entire flash of the chip.
@@ -311,10 +707,15 @@ impl<'session> Flasher<'session> {
     fn chip_erase(&mut self, flash_layout: &FlashLayout, progress: &FlashProgress) -> Result<()> {
         progress.started_erasing();
 
+        // Erasing the whole chip takes at least as long as erasing all of its sectors.
+        let timeout = (erase_estimate(&self.flash_algorithm.flash_properties)
+            * flash_layout.sectors().len() as u32)
+            .max(CHIP_ERASE_MIN_TIMEOUT);
+
         let mut t = std::time::Instant::now();
-        let result = self.run_erase(|active| active.erase_all());
+        let result = self.run_erase(|active| active.erase_all(timeout));
         for sector in flash_layout.sectors() {
-            progress.sector_erased(sector.size(), t.elapsed());
+            progress.sector_erased(sector.address(), sector.size(), t.elapsed());
             t = std::time::Instant::now();
         }
 
@@ -338,7 +739,7 @@ impl<'session> Flasher<'session> {
         let result = self.run_program(|active| {
             for page in flash_layout.pages() {
                 active.program_page(page.address(), page.data())?;
-                progress.page_programmed(page.size(), t.elapsed());
+                progress.page_programmed(page.address(), page.size(), t.elapsed());
                 t = std::time::Instant::now();
             }
             Ok(())
@@ -361,7 +762,7 @@ impl<'session> Flasher<'session> {
         let result = self.run_erase(|active| {
             for sector in flash_layout.sectors() {
                 active.erase_sector(sector.address())?;
-                progress.sector_erased(sector.size(), t.elapsed());
+                progress.sector_erased(sector.address(), sector.size(), t.elapsed());
                 t = std::time::Instant::now();
             }
             Ok(())
@@ -378,43 +779,40 @@ impl<'session> Flasher<'session> {
 
     /// Flash a program using double buffering.
     ///
-    /// UNTESTED
+    /// While a page is programmed from one page buffer, the next page is transferred into the other one.
     fn program_double_buffer(
         &mut self,
         flash_layout: &FlashLayout,
         progress: &FlashProgress,
     ) -> Result<()> {
-        let mut current_buf = 0;
-
         progress.started_programming();
 
-        let mut t = std::time::Instant::now();
-        let result = self.run_program(|active| {
-            for page in flash_layout.pages() {
-                // At the start of each loop cycle load the next page buffer into RAM.
-                active.load_page_buffer(page.address(), page.data(), current_buf)?;
+        let result = self.run_program(|active| -> Result<()> {
+            // The page which is currently programmed, and when programming it started.
+            let mut programming: Option<(&FlashPage, Instant)> = None;
 
-                // Then wait for the active RAM -> Flash copy process to finish.
-                // Also check if it finished properly. If it didn't, return an error.
-                let result = active.wait_for_completion(Duration::from_secs(2))?;
-                progress.page_programmed(page.size(), t.elapsed());
-                t = std::time::Instant::now();
-                if result != 0 {
-                    return Err(FlashError::PageWrite {
-                        page_address: page.address(),
-                        error_code: result,
-                    });
-                }
+            for (index, page) in flash_layout.pages().iter().enumerate() {
+                let buffer = index % 2;
 
-                // Start the next copy process.
-                active.start_program_page_with_buffer(page.address(), current_buf)?;
+                // Transfer the page into the buffer which is not being programmed from.
+                active.load_page_buffer(page.address(), page.data(), buffer)?;
 
-                // Swap the buffers
-                if current_buf == 1 {
-                    current_buf = 0;
-                } else {
-                    current_buf = 1;
+                if let Some((previous, started)) = programming.take() {
+                    active.finish_program_page(previous.address())?;
+                    progress.page_programmed(
+                        previous.address(),
+                        previous.size(),
+                        started.elapsed(),
+                    );
                 }
+
+                active.start_program_page_with_buffer(page.address(), page.size(), buffer)?;
+                programming = Some((page, Instant::now()));
+            }
+
+            if let Some((previous, started)) = programming {
+                active.finish_program_page(previous.address())?;
+                progress.page_programmed(previous.address(), previous.size(), started.elapsed());
             }
 
             Ok(())
@@ -426,8 +824,69 @@ impl<'session> Flasher<'session> {
             progress.failed_programming();
         }
 
-        Ok(result?)
+        result
+    }
+}
+
+/// Reports erasing and programming `flash_layout` to `progress`, without accessing the target.
+///
+/// The reported durations are estimated from the flash properties of `flash_algorithm`.
+fn simulate(
+    flash_layout: &FlashLayout,
+    flash_algorithm: &FlashAlgorithm,
+    progress: &FlashProgress,
+) {
+    let properties = &flash_algorithm.flash_properties;
+
+    progress.started_erasing();
+    for sector in flash_layout.sectors() {
+        progress.sector_erased(sector.address(), sector.size(), erase_estimate(properties));
+    }
+    progress.finished_erasing();
+
+    progress.started_programming();
+    for page in flash_layout.pages() {
+        progress.page_programmed(page.address(), page.size(), program_estimate(properties));
+    }
+    progress.finished_programming();
+}
+
+/// Joins the data of directly adjacent pages, returning the start address and data of every contiguous range.
+fn contiguous_ranges(pages: &[FlashPage]) -> Vec<(u32, Vec<u8>)> {
+    let mut ranges: Vec<(u32, Vec<u8>)> = Vec::new();
+
+    for page in pages {
+        match ranges.last_mut() {
+            Some((address, data)) if *address + data.len() as u32 == page.address() => {
+                data.extend_from_slice(page.data());
+            }
+            _ => ranges.push((page.address(), page.data().to_vec())),
+        }
+    }
+
+    ranges
+}
+
+/// Returns the address of the first byte of `actual` which differs from `expected`, both starting at `address`.
+fn first_mismatch(address: u32, expected: &[u8], actual: &[u8]) -> Option<u32> {
+    expected
+        .iter()
+        .zip(actual)
+        .position(|(e, a)| e != a)
+        .map(|offset| address + offset as u32)
+}
+
+/// Computes the IEEE CRC32 of `data`, as produced by the in-target CRC32 routine of a flash algorithm.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
     }
+    !crc
 }
 
 struct Registers {
@@ -451,7 +910,7 @@ impl Debug for Registers {
 pub(super) struct ActiveFlasher<'probe, O: Operation> {
     core: Core<'probe>,
     flash_algorithm: FlashAlgorithm,
-    _double_buffering_supported: bool,
+    keep_alive_interval: Duration,
     _operation: core::marker::PhantomData<O>,
 }
 
@@ -596,10 +1055,31 @@ impl<'probe, O: Operation> ActiveFlasher<'probe, O> {
         Ok(())
     }
 
+    /// Waits for the running routine to finish, and returns its result.
+    ///
+    /// If the routine runs for a long time, e.g. a chip erase, the probe connection is kept alive
+    /// with `Core::keep_alive`, which does not touch the AP used by the flash algorithm.
     pub(super) fn wait_for_completion(&mut self, timeout: Duration) -> Result<u32> {
         log::debug!("Waiting for routine call completion.");
         let regs = self.core.registers();
 
+        let start = Instant::now();
+        let mut keep_alive = KeepAlive::new(self.keep_alive_interval, start);
+        while !self.core.core_halted().map_err(FlashError::Core)? {
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return Err(anyhow!(FlashError::Core(DebugProbeError::Timeout.into())));
+            }
+
+            if elapsed >= LONG_ROUTINE {
+                if keep_alive.due(Instant::now()) {
+                    log::trace!("Keeping the probe connection alive");
+                    self.core.keep_alive().map_err(FlashError::Core)?;
+                }
+                std::thread::sleep(LONG_ROUTINE_POLL_INTERVAL);
+            }
+        }
+        // The core is halted already, this only updates its state.
         self.core
             .wait_for_core_halted(timeout)
             .map_err(FlashError::Core)?;
@@ -613,14 +1093,36 @@ impl<'probe, O: Operation> ActiveFlasher<'probe, O> {
 
     pub(super) fn read_block8(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
         self.core
-            .read_8(address, data)
+            .read(address.into(), data)
             .map_err(FlashError::Memory)?;
         Ok(())
     }
 }
 
+impl<'probe> ActiveFlasher<'probe, Verify> {
+    /// Computes the CRC32 of `size` bytes at `address` with the CRC32 routine of the flash algorithm.
+    pub(super) fn crc32(&mut self, address: u32, size: u32) -> Result<u32> {
+        let pc_crc32 = self
+            .flash_algorithm
+            .pc_crc32
+            .ok_or_else(|| anyhow!(FlashError::RoutineNotSupported("crc32")))?;
+
+        self.call_function_and_wait(
+            &Registers {
+                pc: pc_crc32,
+                r0: Some(address),
+                r1: Some(size),
+                r2: None,
+                r3: None,
+            },
+            false,
+            Duration::from_secs(5),
+        )
+    }
+}
+
 impl<'probe> ActiveFlasher<'probe, Erase> {
-    pub(super) fn erase_all(&mut self) -> Result<()> {
+    pub(super) fn erase_all(&mut self, timeout: Duration) -> Result<()> {
         log::debug!("Erasing entire chip.");
         let flasher = self;
         let algo = &flasher.flash_algorithm;
@@ -635,7 +1137,7 @@ impl<'probe> ActiveFlasher<'probe, Erase> {
                     r3: None,
                 },
                 false,
-                Duration::from_secs(5),
+                timeout,
             )?;
 
             if result != 0 {
@@ -695,7 +1197,7 @@ impl<'p> ActiveFlasher<'p, Program> {
 
         // Transfer the bytes to RAM.
         self.core
-            .write_8(self.flash_algorithm.begin_data, bytes)
+            .write(self.flash_algorithm.begin_data.into(), bytes)
             .map_err(FlashError::Memory)?;
 
         let result = self.call_function_and_wait(
@@ -721,13 +1223,16 @@ impl<'p> ActiveFlasher<'p, Program> {
         }
     }
 
+    /// Starts programming `size` bytes from page buffer `buffer_number` to `address`, without waiting
+    /// for the flash algorithm to finish, see `finish_program_page`.
     pub(super) fn start_program_page_with_buffer(
         &mut self,
         address: u32,
+        size: u32,
         buffer_number: usize,
     ) -> Result<()> {
         // Check the buffer number.
-        if buffer_number < self.flash_algorithm.page_buffers.len() {
+        if buffer_number >= self.flash_algorithm.page_buffers.len() {
             return Err(anyhow!(FlashError::InvalidBufferNumber {
                 n: buffer_number,
                 max: self.flash_algorithm.page_buffers.len(),
@@ -738,8 +1243,8 @@ impl<'p> ActiveFlasher<'p, Program> {
             &Registers {
                 pc: self.flash_algorithm.pc_program_page,
                 r0: Some(address),
-                r1: Some(self.flash_algorithm.flash_properties.page_size),
-                r2: Some(self.flash_algorithm.page_buffers[buffer_number as usize]),
+                r1: Some(size),
+                r2: Some(self.flash_algorithm.page_buffers[buffer_number]),
                 r3: None,
             },
             false,
@@ -748,6 +1253,20 @@ impl<'p> ActiveFlasher<'p, Program> {
         Ok(())
     }
 
+    /// Waits for the page programming started by `start_program_page_with_buffer` to finish.
+    pub(super) fn finish_program_page(&mut self, address: u32) -> Result<()> {
+        let result = self.wait_for_completion(Duration::from_secs(2))?;
+
+        if result != 0 {
+            Err(anyhow!(FlashError::PageWrite {
+                page_address: address,
+                error_code: result,
+            }))
+        } else {
+            Ok(())
+        }
+    }
+
     pub(super) fn load_page_buffer(
         &mut self,
         _address: u32,
@@ -758,7 +1277,7 @@ impl<'p> ActiveFlasher<'p, Program> {
         let algo = &flasher.flash_algorithm;
 
         // Check the buffer number.
-        if buffer_number < algo.page_buffers.len() {
+        if buffer_number >= algo.page_buffers.len() {
             return Err(anyhow!(FlashError::InvalidBufferNumber {
                 n: buffer_number,
                 max: algo.page_buffers.len(),
@@ -770,9 +1289,117 @@ impl<'p> ActiveFlasher<'p, Program> {
         // Transfer the buffer bytes to RAM.
         flasher
             .core
-            .write_8(algo.page_buffers[buffer_number as usize], bytes)
+            .write(u64::from(algo.page_buffers[buffer_number]), bytes)
             .map_err(FlashError::Memory)?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::SectorDescription;
+    use crate::flashing::FlashEvent;
+    use std::sync::mpsc::channel;
+
+    fn demo_flash() -> FlashAlgorithm {
+        FlashAlgorithm {
+            flash_properties: FlashProperties {
+                address_range: 0..1 << 16,
+                page_size: 1024,
+                erased_byte_value: 255,
+                program_page_timeout: 20,
+                erase_sector_timeout: 100,
+                sectors: std::borrow::Cow::Owned(vec![SectorDescription {
+                    size: 4096,
+                    address: 0,
+                }]),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn summary_of_layout() {
+        let flash_algorithm = demo_flash();
+        let data = [0x42; 5000];
+        let mut flash_builder = FlashBuilder::new();
+        flash_builder.add_data(0x1000, &data).unwrap();
+        let flash_layout = flash_builder
+            .build_sectors_and_pages(&flash_algorithm, false)
+            .unwrap();
+
+        let summary = FlashSummary::new(&flash_layout, &flash_algorithm);
+
+        assert_eq!(
+            summary,
+            FlashSummary {
+                sectors_erased: 2,
+                pages_programmed: 5,
+                bytes_programmed: 5 * 1024,
+                estimated_time: Duration::from_millis(2 * 100 + 5 * 20),
+            }
+        );
+    }
+
+    #[test]
+    fn simulated_events() {
+        let flash_algorithm = demo_flash();
+        let mut flash_builder = FlashBuilder::new();
+        flash_builder.add_data(0x1000, &[0x42; 1024]).unwrap();
+        let flash_layout = flash_builder
+            .build_sectors_and_pages(&flash_algorithm, false)
+            .unwrap();
+
+        let (sender, receiver) = channel();
+        simulate(
+            &flash_layout,
+            &flash_algorithm,
+            &FlashProgress::from_sender(sender),
+        );
+
+        let events = receiver.try_iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 6);
+        assert!(matches!(
+            events[1],
+            FlashEvent::SectorErased {
+                address: 0x1000,
+                size: 4096,
+                ..
+            }
+        ));
+        assert!(matches!(
+            events[4],
+            FlashEvent::PageProgrammed {
+                address: 0x1000,
+                bytes: 1024,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn crc32_matches_reference() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn first_mismatch_is_found() {
+        assert_eq!(first_mismatch(0x1000, &[1, 2, 3], &[1, 2, 3]), None);
+        assert_eq!(first_mismatch(0x1000, &[1, 2, 3], &[1, 0, 0]), Some(0x1001));
+    }
+
+    #[test]
+    fn keep_alive_is_due_after_the_interval() {
+        let start = Instant::now();
+        let mut keep_alive = KeepAlive::new(Duration::from_millis(500), start);
+
+        assert!(!keep_alive.due(start + Duration::from_millis(100)));
+        assert!(keep_alive.due(start + Duration::from_millis(600)));
+        // The next interval starts at the last keep-alive access.
+        assert!(!keep_alive.due(start + Duration::from_millis(900)));
+        assert!(keep_alive.due(start + Duration::from_millis(1100)));
+    }
+}
@@ -1,28 +1,73 @@
-use super::{FlashBuilder, FlashError, FlashProgress, Flasher};
-use crate::config::{FlashRegion, MemoryRange, MemoryRegion};
+use super::{
+    FileDownloadError, FlashBuilder, FlashError, FlashProgress, FlashSummary, Flasher,
+    ProgramOptions, RegionPlan, RegionVerification, SegmentFilter, VerifyMethod,
+};
+use crate::config::{
+    FlashAlgorithm, FlashProperties, FlashRegion, MemoryRange, MemoryRegion, RamRegion,
+    RawFlashAlgorithm,
+};
 use crate::session::Session;
 use anyhow::anyhow;
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
 
 /// `FlashLoader` is a struct which manages the flashing of any chunks of data onto any sections of flash.
-/// Use `add_data()` to add a chunks of data.
+/// Use `add_data()` to add a chunks of data, or one of the `load_*_data()` functions to add
+/// the contents of a file.
 /// Once you are done adding all your data, use `commit()` to flash the data.
 /// The flash loader will make sure to select the appropriate flash region for the right data chunks.
 /// Region crossing data chunks are allowed as long as the regions are contiguous.
 pub(super) struct FlashLoader<'mmap, 'data> {
     memory_map: &'mmap [MemoryRegion],
     builders: HashMap<FlashRegion, FlashBuilder<'data>>,
-    keep_unwritten: bool,
+    options: ProgramOptions,
+    algorithm_ram: Option<Range<u32>>,
 }
 
 impl<'mmap, 'data> FlashLoader<'mmap, 'data> {
-    pub(super) fn new(memory_map: &'mmap [MemoryRegion], keep_unwritten: bool) -> Self {
+    pub(super) fn new(memory_map: &'mmap [MemoryRegion], options: ProgramOptions) -> Self {
         Self {
             memory_map,
             builders: HashMap::new(),
-            keep_unwritten,
+            options,
+            algorithm_ram: None,
         }
     }
+
+    /// Loads the flash algorithms into `ram` instead of the first RAM region of the target.
+    pub(super) fn set_algorithm_ram(&mut self, ram: Option<Range<u32>>) {
+        self.algorithm_ram = ram;
+    }
+
+    /// Returns the RAM region the flash algorithms are loaded into.
+    fn algorithm_ram(&self) -> Result<RamRegion, FlashError> {
+        let range = match &self.algorithm_ram {
+            Some(range) => range,
+            None => {
+                return self
+                    .memory_map
+                    .iter()
+                    .find_map(|region| match region {
+                        MemoryRegion::Ram(ram) => Some(ram.clone()),
+                        _ => None,
+                    })
+                    .ok_or_else(|| anyhow!("No RAM defined for chip.").into())
+            }
+        };
+
+        self.memory_map
+            .iter()
+            .find_map(|region| match region {
+                MemoryRegion::Ram(ram) if ram.range.contains_range(range) => Some(RamRegion {
+                    range: range.clone(),
+                    is_boot_memory: ram.is_boot_memory,
+                }),
+                _ => None,
+            })
+            .ok_or(FlashError::InvalidAlgorithmRam {
+                start: range.start,
+                end: range.end,
+            })
+    }
     /// Stages a chunk of data to be programmed.
     ///
     /// The chunk can cross flash boundaries as long as one flash region connects to another flash region.
@@ -44,13 +89,14 @@ impl<'mmap, 'data> FlashLoader<'mmap, 'data> {
                 };
 
                 // Determine how much more data can be contained by this region.
-                let program_length =
-                    usize::min(remaining, (region.range.end - address + 1) as usize);
+                let program_length = usize::min(remaining, (region.range.end - address) as usize);
+                let offset = size - remaining;
 
                 // Add as much data to the builder as can be contained by this region.
                 self.builders
                     .get_mut(&region)
-                    .map(|r| r.add_data(address, &data[size - remaining..program_length]));
+                    .unwrap()
+                    .add_data(address, &data[offset..offset + program_length])?;
 
                 // Advance the cursors.
                 remaining -= program_length;
@@ -58,13 +104,118 @@ impl<'mmap, 'data> FlashLoader<'mmap, 'data> {
             } else {
                 return Err(FlashError::NoSuitableFlash {
                     start: address,
-                    end: address + data.len() as u32,
+                    end: address + remaining as u32,
                 });
             }
         }
         Ok(())
     }
 
+    /// Stages a raw binary, which is loaded at `base_address`.
+    pub(super) fn load_bin_data(
+        &mut self,
+        base_address: u32,
+        data: &'data [u8],
+    ) -> Result<(), FlashError> {
+        self.add_data(base_address, data)
+    }
+
+    /// Stages the data records of the Intel HEX file `data`.
+    ///
+    /// The records are parsed into `buffer`, which has to outlive the loader.
+    /// Overlapping records are reported as `FlashError::DataOverlap`.
+    pub(super) fn load_hex_data(
+        &mut self,
+        buffer: &'data mut Vec<(u32, Vec<u8>)>,
+        data: &str,
+    ) -> Result<(), FileDownloadError> {
+        *buffer = parse_hex(data)?;
+
+        for (address, data) in buffer.iter() {
+            self.add_data(*address, data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stages the loadable segments of an ELF file which are selected by `filter`.
+    pub(super) fn load_elf_data(
+        &mut self,
+        buffer: &'data [u8],
+        filter: &SegmentFilter,
+    ) -> Result<(), FileDownloadError> {
+        use goblin::elf::program_header::*;
+
+        let binary = goblin::elf::Elf::parse(buffer)?;
+        let mut added_sections = vec![];
+        for ph in &binary.program_headers {
+            if ph.p_type == PT_LOAD && ph.p_filesz > 0 {
+                log::debug!("Found loadable segment.");
+
+                let sector: core::ops::Range<u32> =
+                    ph.p_offset as u32..ph.p_offset as u32 + ph.p_filesz as u32;
+
+                let sections: Vec<_> = binary
+                    .section_headers
+                    .iter()
+                    .filter(|sh| {
+                        sector.contains_range(
+                            &(sh.sh_offset as u32..sh.sh_offset as u32 + sh.sh_size as u32),
+                        )
+                    })
+                    .collect();
+
+                let address_range = ph.p_paddr as u32..ph.p_paddr as u32 + ph.p_filesz as u32;
+                let section_names: Vec<_> = sections
+                    .iter()
+                    .map(|sh| &binary.shdr_strtab[sh.sh_name])
+                    .collect();
+
+                if !filter.selects(&address_range, &section_names)? {
+                    log::warn!(
+                        "Skipping segment at {:#010x}..{:#010x} ({}), it is excluded by the segment filter.",
+                        address_range.start,
+                        address_range.end,
+                        section_names.join(", ")
+                    );
+                    continue;
+                }
+
+                for sh in sections {
+                    #[cfg(feature = "hexdump")]
+                    for line in hexdump::hexdump_iter(
+                        &buffer[sh.sh_offset as usize..][..sh.sh_size as usize],
+                    ) {
+                        log::trace!("{}", line);
+                    }
+
+                    added_sections.push((&binary.shdr_strtab[sh.sh_name], sh.sh_addr, sh.sh_size));
+                }
+
+                self.add_data(
+                    ph.p_paddr as u32,
+                    &buffer[ph.p_offset as usize..][..ph.p_filesz as usize],
+                )?;
+            }
+        }
+        if added_sections.is_empty() {
+            log::warn!("No loadable segments were found in the ELF file.");
+            Err(FileDownloadError::NoLoadableSegments)
+        } else {
+            log::info!("Found {} loadable sections:", added_sections.len());
+            for section in added_sections {
+                log::info!(
+                    "    {} at {:08X?} ({} byte{})",
+                    section.0,
+                    section.1,
+                    section.2,
+                    if section.2 == 1 { "" } else { "0" }
+                );
+            }
+            Ok(())
+        }
+    }
+
     pub(super) fn get_region_for_address(
         memory_map: &[MemoryRegion],
         address: u32,
@@ -92,65 +243,384 @@ impl<'mmap, 'data> FlashLoader<'mmap, 'data> {
         session: &mut Session,
         progress: &FlashProgress,
         do_chip_erase: bool,
-    ) -> Result<(), FlashError> {
-        // Iterate over builders we've created and program the data.
+    ) -> Result<FlashSummary, FlashError> {
+        let mut summary = FlashSummary::default();
+        let ram = self.algorithm_ram()?;
+
+        // Select the algorithms up front, so the progress of all regions is known before the first one is written.
+        let mut regions = Vec::with_capacity(self.builders.len());
+        let mut plan = Vec::with_capacity(self.builders.len());
         for (region, builder) in &self.builders {
+            let flash_algorithm = Self::flash_algorithm(session, region, &ram)?;
+            let flash_layout = builder
+                .build_sectors_and_pages(&flash_algorithm, self.options.restore_unwritten_bytes)?;
+
+            plan.push(RegionPlan::new(
+                region.range.clone(),
+                &flash_layout,
+                &flash_algorithm.flash_properties,
+            ));
+            regions.push((region, builder, flash_algorithm));
+        }
+        progress.planned(plan);
+
+        // Iterate over builders we've created and program the data.
+        for (region, builder, flash_algorithm) in regions {
             log::debug!(
                 "Using builder for region (0x{:08x}..0x{:08x})",
                 region.range.start,
                 region.range.end
             );
 
-            // Try to find a flash algorithm for the range of the current builder
-            for algorithm in session.flash_algorithms() {
-                log::debug!(
-                    "Algorithm {} - start: {:#08x} - size: {:#08x}",
-                    algorithm.name,
-                    algorithm.flash_properties.address_range.start,
-                    algorithm.flash_properties.address_range.end
-                        - algorithm.flash_properties.address_range.start
-                );
+            // Program the data.
+            let mut flasher = Flasher::new(session, flash_algorithm, region.clone());
+            summary += flasher.program(
+                builder,
+                ProgramOptions {
+                    do_chip_erase,
+                    ..self.options
+                },
+                progress,
+            )?;
+        }
+
+        Ok(summary)
+    }
+
+    /// Compares the stored data chunks with the contents of the flash, without erasing or programming anything.
+    ///
+    /// Only the flash regions containing data are compared, sorted by their address.
+    pub(super) fn verify(
+        &self,
+        session: &mut Session,
+        method: VerifyMethod,
+        progress: &FlashProgress,
+    ) -> Result<Vec<RegionVerification>, FlashError> {
+        let ram = self.algorithm_ram()?;
+
+        let mut builders: Vec<_> = self.builders.iter().collect();
+        builders.sort_by_key(|(region, _)| region.range.start);
+
+        let mut regions = Vec::with_capacity(builders.len());
+        for (region, builder) in builders {
+            let flash_algorithm = Self::flash_algorithm(session, region, &ram)?;
+            // Unlike after flashing, there is no silent fallback to a full readback.
+            if method == VerifyMethod::Crc32 && flash_algorithm.pc_crc32.is_none() {
+                return Err(FlashError::RoutineNotSupported("crc32"));
             }
 
-            let algorithms = session.flash_algorithms();
-            let algorithms = algorithms
-                .iter()
-                .filter(|fa| {
-                    fa.flash_properties
-                        .address_range
-                        .contains_range(&region.range)
-                })
-                .collect::<Vec<_>>();
-
-            log::debug!("Algorithms: {:?}", &algorithms);
-
-            let raw_flash_algorithm = match algorithms.len() {
-                0 => {
-                    return Err(FlashError::NoFlashLoaderAlgorithmAttached);
-                }
-                1 => &algorithms[0],
-                _ => algorithms
-                    .iter()
-                    .find(|a| a.default)
-                    .ok_or(FlashError::NoFlashLoaderAlgorithmAttached)?,
+            let mut flasher = Flasher::new(session, flash_algorithm, region.clone());
+            flasher.set_keep_alive_interval(self.options.keep_alive_interval);
+            let first_mismatch = flasher.verify_data(builder, method, progress)?;
+
+            regions.push(RegionVerification {
+                region: region.range.clone(),
+                bytes: builder.data_blocks().iter().map(|b| b.size()).sum(),
+                first_mismatch,
+            });
+        }
+
+        Ok(regions)
+    }
+
+    /// Erases all flash regions of the memory map.
+    ///
+    /// Regions which were already erased by the chip erase routine of the flash algorithm
+    /// of a previous region are skipped.
+    pub(super) fn erase_all(
+        &self,
+        session: &mut Session,
+        progress: &FlashProgress,
+    ) -> Result<(), FlashError> {
+        let mut erased: Vec<Range<u32>> = Vec::new();
+        let ram = self.algorithm_ram()?;
+
+        for region in self.memory_map {
+            let region = match region {
+                MemoryRegion::Flash(region) => region,
+                _ => continue,
             };
 
-            let mm = session.memory_map();
-            let ram = mm
-                .iter()
-                .find_map(|mm| match mm {
-                    MemoryRegion::Ram(ram) => Some(ram),
-                    _ => None,
-                })
-                .ok_or_else(|| anyhow!("No RAM defined for chip."))?;
+            if erased.iter().any(|r| r.contains_range(&region.range)) {
+                log::debug!(
+                    "Region (0x{:08x}..0x{:08x}) was already erased by the chip erase.",
+                    region.range.start,
+                    region.range.end
+                );
+                continue;
+            }
 
-            let flash_algorithm = raw_flash_algorithm.assemble(ram, session.architecture())?;
+            let flash_algorithm = Self::flash_algorithm(session, region, &ram)?;
+            let algorithm_range = flash_algorithm.flash_properties.address_range.clone();
 
-            // Program the data.
             let mut flasher = Flasher::new(session, flash_algorithm, region.clone());
-            flasher.program(builder, do_chip_erase, self.keep_unwritten, false, progress)?
+            flasher.set_keep_alive_interval(self.options.keep_alive_interval);
+            if flasher.erase_all(progress)? {
+                erased.push(algorithm_range);
+            }
         }
 
         Ok(())
     }
+
+    /// Selects the flash algorithm for `region`, and assembles it for `ram`.
+    fn flash_algorithm(
+        session: &Session,
+        region: &FlashRegion,
+        ram: &RamRegion,
+    ) -> Result<FlashAlgorithm, FlashError> {
+        let raw_flash_algorithm = raw_flash_algorithm(session, region)?;
+
+        raw_flash_algorithm.assemble(ram, session.architecture())
+    }
+}
+
+/// Selects the flash algorithm covering `region`, preferring the default one if there are several.
+fn raw_flash_algorithm<'session>(
+    session: &'session Session,
+    region: &FlashRegion,
+) -> Result<&'session RawFlashAlgorithm, FlashError> {
+    // Try to find a flash algorithm for the range of the region
+    for algorithm in session.flash_algorithms() {
+        log::debug!(
+            "Algorithm {} - start: {:#08x} - size: {:#08x}",
+            algorithm.name,
+            algorithm.flash_properties.address_range.start,
+            algorithm.flash_properties.address_range.end
+                - algorithm.flash_properties.address_range.start
+        );
+    }
+
+    let algorithms = session.flash_algorithms();
+    let algorithms = algorithms
+        .iter()
+        .filter(|fa| {
+            fa.flash_properties
+                .address_range
+                .contains_range(&region.range)
+        })
+        .collect::<Vec<_>>();
+
+    log::debug!("Algorithms: {:?}", &algorithms);
+
+    match algorithms.len() {
+        0 => Err(FlashError::NoFlashLoaderAlgorithmAttached),
+        1 => Ok(algorithms[0]),
+        _ => algorithms
+            .into_iter()
+            .find(|a| a.default)
+            .ok_or(FlashError::NoFlashLoaderAlgorithmAttached),
+    }
+}
+
+/// Returns the sectors of the flash `region`, as ranges of equally sized sectors together with their size.
+///
+/// This is empty if there is no flash algorithm for the region.
+pub fn sector_layout(session: &Session, region: &FlashRegion) -> Vec<(Range<u32>, u32)> {
+    match raw_flash_algorithm(session, region) {
+        Ok(algorithm) => layout_of(&algorithm.flash_properties, &region.range),
+        Err(_) => vec![],
+    }
+}
+
+fn layout_of(properties: &FlashProperties, region: &Range<u32>) -> Vec<(Range<u32>, u32)> {
+    let flash_start = properties.address_range.start;
+    let sectors = &properties.sectors;
+
+    sectors
+        .iter()
+        .enumerate()
+        .filter_map(|(i, sector)| {
+            // Each description applies until the next one starts.
+            let start = flash_start + sector.address;
+            let end = sectors
+                .get(i + 1)
+                .map(|next| flash_start + next.address)
+                .unwrap_or(properties.address_range.end);
+
+            let range = start.max(region.start)..end.min(region.end);
+            if range.start < range.end {
+                Some((range, sector.size))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Parses the data records of an Intel HEX file.
+///
+/// Contiguous records are merged into a single block, sorted by address.
+fn parse_hex(data: &str) -> Result<Vec<(u32, Vec<u8>)>, FileDownloadError> {
+    use ihex::Record::*;
+
+    let mut extended_segment_address = 0;
+    let mut extended_linear_address = 0;
+
+    let mut records = vec![];
+
+    for record in ihex::Reader::new(data) {
+        match record? {
+            Data { offset, value } => {
+                let address = extended_linear_address + extended_segment_address + offset as u32;
+                records.push((address, value));
+            }
+            EndOfFile => break,
+            ExtendedSegmentAddress(address) => {
+                extended_segment_address = (address as u32) * 16;
+            }
+            StartSegmentAddress { .. } => (),
+            ExtendedLinearAddress(address) => {
+                extended_linear_address = (address as u32) << 16;
+            }
+            StartLinearAddress(_) => (),
+        };
+    }
+
+    records.sort_by_key(|(address, _)| *address);
+
+    let mut blocks: Vec<(u32, Vec<u8>)> = Vec::with_capacity(records.len());
+
+    for (address, value) in records {
+        if let Some((last_address, last_data)) = blocks.last_mut() {
+            let last_end = *last_address + last_data.len() as u32;
+
+            if address < last_end {
+                return Err(FileDownloadError::Flash(FlashError::DataOverlap(address)));
+            }
+
+            if address == last_end {
+                last_data.extend(value);
+                continue;
+            }
+        }
+
+        blocks.push((address, value));
+    }
+
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hex_records_are_merged() {
+        let hex = ":020000040800F2\n:0400000001020304F2\n:0400040005060708DE\n:00000001FF\n";
+
+        let blocks = parse_hex(hex).unwrap();
+
+        assert_eq!(blocks, vec![(0x0800_0000, vec![1, 2, 3, 4, 5, 6, 7, 8])]);
+    }
+
+    #[test]
+    fn overlapping_hex_records() {
+        let hex = ":0400000001020304F2\n:0400020005060708E0\n:00000001FF\n";
+
+        assert!(matches!(
+            parse_hex(hex),
+            Err(FileDownloadError::Flash(FlashError::DataOverlap(0x2)))
+        ));
+    }
+
+    fn memory_map() -> Vec<MemoryRegion> {
+        vec![
+            MemoryRegion::Flash(FlashRegion {
+                range: 0x0000..0x1000,
+                is_boot_memory: true,
+            }),
+            MemoryRegion::Flash(FlashRegion {
+                range: 0x1000..0x2000,
+                is_boot_memory: false,
+            }),
+            MemoryRegion::Ram(RamRegion {
+                range: 0x2000..0x3000,
+                is_boot_memory: false,
+            }),
+        ]
+    }
+
+    #[test]
+    fn algorithm_ram_is_inside_a_ram_region() {
+        let memory_map = memory_map();
+        let mut loader = FlashLoader::new(&memory_map, ProgramOptions::default());
+        assert_eq!(loader.algorithm_ram().unwrap().range, 0x2000..0x3000);
+
+        loader.set_algorithm_ram(Some(0x2800..0x3000));
+        assert_eq!(loader.algorithm_ram().unwrap().range, 0x2800..0x3000);
+
+        loader.set_algorithm_ram(Some(0x2800..0x3800));
+        assert!(matches!(
+            loader.algorithm_ram(),
+            Err(FlashError::InvalidAlgorithmRam {
+                start: 0x2800,
+                end: 0x3800
+            })
+        ));
+    }
+
+    #[test]
+    fn data_across_contiguous_flash_regions() {
+        let memory_map = memory_map();
+        let mut loader = FlashLoader::new(&memory_map, ProgramOptions::default());
+        let data = [0xAA; 0x100];
+
+        loader.add_data(0x0F80, &data).unwrap();
+
+        assert_eq!(loader.builders.len(), 2);
+    }
+
+    #[test]
+    fn data_crossing_into_ram() {
+        let memory_map = memory_map();
+        let mut loader = FlashLoader::new(&memory_map, ProgramOptions::default());
+        let data = [0xAA; 0x100];
+
+        assert!(matches!(
+            loader.add_data(0x1F80, &data),
+            Err(FlashError::NoSuitableFlash {
+                start: 0x2000,
+                end: 0x2080
+            })
+        ));
+    }
+
+    #[test]
+    fn sector_layout_is_clipped_to_the_region() {
+        use crate::config::SectorDescription;
+        use std::borrow::Cow;
+
+        let properties = FlashProperties {
+            address_range: 0x0800_0000..0x0810_0000,
+            sectors: Cow::Owned(vec![
+                SectorDescription {
+                    size: 0x4000,
+                    address: 0,
+                },
+                SectorDescription {
+                    size: 0x1_0000,
+                    address: 0x1_0000,
+                },
+                SectorDescription {
+                    size: 0x2_0000,
+                    address: 0x2_0000,
+                },
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            layout_of(&properties, &(0x0800_8000..0x0804_0000)),
+            vec![
+                (0x0800_8000..0x0801_0000, 0x4000),
+                (0x0801_0000..0x0802_0000, 0x1_0000),
+                (0x0802_0000..0x0804_0000, 0x2_0000),
+            ]
+        );
+        assert_eq!(
+            layout_of(&properties, &(0x0804_0000..0x0810_0000)),
+            vec![(0x0804_0000..0x0810_0000, 0x2_0000)]
+        );
+    }
 }
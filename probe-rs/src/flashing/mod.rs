@@ -7,16 +7,28 @@
 
 mod builder;
 mod download;
+mod erase;
 mod error;
 mod flasher;
 mod loader;
 mod progress;
+mod run;
+mod sectors;
+mod verify;
 mod visualizer;
 
 use builder::*;
 pub use download::*;
+pub use erase::*;
 pub use error::*;
 pub use flasher::*;
+pub use loader::sector_layout;
 use loader::*;
 pub use progress::*;
+pub(crate) use run::flash_and_run;
+pub use run::{AfterFlashing, FlashAndRunError, FlashAndRunOptions, FlashAndRunSummary};
+pub(crate) use sectors::flash_sectors;
+pub use sectors::{SectorProtection, SectorStatus};
+pub(crate) use verify::verify_image;
+pub use verify::{RegionVerification, VerifySummary};
 pub use visualizer::*;
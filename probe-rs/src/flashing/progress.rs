@@ -1,98 +1,458 @@
-use super::FlashLayout;
-use std::time::Duration;
+use super::{FlashLayout, VerifyMethod};
+use crate::config::FlashProperties;
+use std::{ops::Range, rc::Rc, sync::mpsc::Sender, time::Duration};
 
 /// A structure to manage the flashing procedure progress reporting.
 ///
 /// This struct stores a handler closure which will be called everytime an event happens during the flashing process.
 /// Such an event can be start or finish of the flashing procedure or a progress report, as well as some more events.
 ///
+/// Additionally, all events can be forwarded as typed [`FlashEvent`]s to a channel with [`FlashProgress::with_sender`].
+///
 /// ```
 /// use probe_rs::flashing::FlashProgress;
 ///
 /// // Print events
 /// let progress = FlashProgress::new(|event| println!("Event: {:#?}", event));
 /// ```
-
+#[derive(Clone)]
 pub struct FlashProgress {
-    handler: Box<dyn Fn(ProgressEvent)>,
+    handler: Option<Rc<dyn Fn(ProgressEvent)>>,
+    sender: Option<Sender<FlashEvent>>,
 }
 
 impl FlashProgress {
     /// Create a new `FlashProgress` structure with a given `handler` to be called on events.
     pub fn new(handler: impl Fn(ProgressEvent) + 'static) -> Self {
         Self {
-            handler: Box::new(handler),
+            handler: Some(Rc::new(handler)),
+            sender: None,
         }
     }
 
+    /// Create a new `FlashProgress` structure which sends all events to `sender`.
+    ///
+    /// Sending stops silently once the receiving end of the channel is dropped.
+    pub fn from_sender(sender: Sender<FlashEvent>) -> Self {
+        Self {
+            handler: None,
+            sender: Some(sender),
+        }
+    }
+
+    /// Additionally send all events to `sender`, replacing any previously set sender.
+    pub fn with_sender(mut self, sender: Sender<FlashEvent>) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
     /// Emit a flashing progress event.
-    fn emit(&self, event: ProgressEvent) {
-        (self.handler)(event);
+    fn emit(&self, event: FlashEvent) {
+        if let Some(sender) = &self.sender {
+            // A dropped receiver only means nobody is interested in the events anymore.
+            let _ = sender.send(event.clone());
+        }
+
+        if let Some(handler) = &self.handler {
+            if let Some(event) = event.into_progress_event() {
+                handler(event);
+            }
+        }
     }
 
     /// Signalize that the flashing algorithm was set up and is initialized.
-    pub(super) fn initialized(&self, flash_layout: FlashLayout) {
-        self.emit(ProgressEvent::Initialized { flash_layout });
+    ///
+    /// If `simulated` is `true`, all following events are only simulated for a dry run.
+    pub(super) fn initialized(&self, flash_layout: FlashLayout, simulated: bool) {
+        self.emit(FlashEvent::Initialized {
+            flash_layout,
+            simulated,
+        });
+    }
+
+    /// Signalize which flash regions are about to be erased and programmed.
+    pub(super) fn planned(&self, regions: Vec<RegionPlan>) {
+        self.emit(FlashEvent::Planned { regions });
     }
 
     /// Signalize that the erasing procedure started.
     pub(super) fn started_erasing(&self) {
-        self.emit(ProgressEvent::StartedErasing);
+        self.emit(FlashEvent::StartedErasing);
     }
 
     /// Signalize that the filling procedure started.
     pub(super) fn started_filling(&self) {
-        self.emit(ProgressEvent::StartedFilling);
+        self.emit(FlashEvent::StartedFilling);
     }
 
     /// Signalize that the programing procedure started.
     pub(super) fn started_programming(&self) {
-        self.emit(ProgressEvent::StartedProgramming);
+        self.emit(FlashEvent::StartedProgramming);
     }
 
     /// Signalize that the page programming procedure has made progress.
-    pub(super) fn page_programmed(&self, size: u32, time: Duration) {
-        self.emit(ProgressEvent::PageProgrammed { size, time });
+    pub(super) fn page_programmed(&self, address: u32, bytes: u32, duration: Duration) {
+        self.emit(FlashEvent::PageProgrammed {
+            address,
+            bytes,
+            duration,
+        });
     }
 
     /// Signalize that the sector erasing procedure has made progress.
-    pub(super) fn sector_erased(&self, size: u32, time: Duration) {
-        self.emit(ProgressEvent::SectorErased { size, time });
+    pub(super) fn sector_erased(&self, address: u32, size: u32, duration: Duration) {
+        self.emit(FlashEvent::SectorErased {
+            address,
+            size,
+            duration,
+        });
+    }
+
+    /// Signalize that a sector already contained the new data and will neither be erased nor programmed.
+    pub(super) fn sector_skipped(&self, address: u32, size: u32) {
+        self.emit(FlashEvent::SectorSkipped { address, size });
+    }
+
+    /// Signalize that a page already contained the new data and will not be programmed.
+    pub(super) fn page_skipped(&self, address: u32, bytes: u32) {
+        self.emit(FlashEvent::PageSkipped { address, bytes });
+    }
+
+    /// Signalize that the verification of the programmed data started, using `method`.
+    pub(super) fn started_verifying(&self, method: VerifyMethod) {
+        self.emit(FlashEvent::StartedVerifying { method });
+    }
+
+    /// Signalize that the verification failed.
+    pub(super) fn failed_verifying(&self) {
+        self.emit(FlashEvent::FailedVerifying);
+    }
+
+    /// Signalize that the verification completed successfully.
+    pub(super) fn finished_verifying(&self) {
+        self.emit(FlashEvent::FinishedVerifying);
+    }
+
+    /// Signalize that the flash contents of a range are checked against the new data.
+    pub(super) fn verifying(&self, address: u32, size: u32) {
+        self.emit(FlashEvent::Verifying { address, size });
     }
 
     /// Signalize that the page filling procedure has made progress.
-    pub(super) fn page_filled(&self, size: u32, time: Duration) {
-        self.emit(ProgressEvent::PageFilled { size, time });
+    pub(super) fn page_filled(&self, address: u32, bytes: u32, duration: Duration) {
+        self.emit(FlashEvent::PageFilled {
+            address,
+            bytes,
+            duration,
+        });
     }
 
     /// Signalize that the programming procedure failed.
     pub(super) fn failed_programming(&self) {
-        self.emit(ProgressEvent::FailedProgramming);
+        self.emit(FlashEvent::FailedProgramming);
     }
 
     /// Signalize that the programming procedure completed successfully.
     pub(super) fn finished_programming(&self) {
-        self.emit(ProgressEvent::FinishedProgramming);
+        self.emit(FlashEvent::FinishedProgramming);
     }
 
     /// Signalize that the erasing procedure failed.
     pub(super) fn failed_erasing(&self) {
-        self.emit(ProgressEvent::FailedErasing);
+        self.emit(FlashEvent::FailedErasing);
     }
 
     /// Signalize that the erasing procedure completed successfully.
     pub(super) fn finished_erasing(&self) {
-        self.emit(ProgressEvent::FinishedErasing);
+        self.emit(FlashEvent::FinishedErasing);
     }
 
     /// Signalize that the filling procedure failed.
     pub(super) fn failed_filling(&self) {
-        self.emit(ProgressEvent::FailedFilling);
+        self.emit(FlashEvent::FailedFilling);
     }
 
     /// Signalize that the filling procedure completed successfully.
     pub(super) fn finished_filling(&self) {
-        self.emit(ProgressEvent::FinishedFilling);
+        self.emit(FlashEvent::FinishedFilling);
+    }
+}
+
+impl Default for FlashProgress {
+    /// A `FlashProgress` which discards all events.
+    fn default() -> Self {
+        Self {
+            handler: None,
+            sender: None,
+        }
+    }
+}
+
+/// A typed event emitted during the flashing process, see [`FlashProgress::from_sender`].
+///
+/// The events arrive in the same order as the corresponding [`ProgressEvent`]s,
+/// but carry the address of the affected flash range as well.
+#[derive(Debug, Clone)]
+pub enum FlashEvent {
+    /// All flash regions which are about to be written, sent once before the first region is `Initialized`.
+    ///
+    /// Use a [`WeightedProgress`] to turn the following events into the progress of the whole download.
+    Planned { regions: Vec<RegionPlan> },
+    /// The flash algorithm was set up and `flash_layout` is about to be written.
+    ///
+    /// If `simulated` is `true`, this is a dry run. The following events only report
+    /// what would be done, and their durations are estimates.
+    Initialized {
+        flash_layout: FlashLayout,
+        simulated: bool,
+    },
+    /// Filling of flash pages has started.
+    StartedFilling,
+    /// `bytes` bytes at `address` were read back from flash to be restored later.
+    PageFilled {
+        address: u32,
+        bytes: u32,
+        duration: Duration,
+    },
+    /// Filling of the pages has failed.
+    FailedFilling,
+    /// Filling of the pages has finished successfully.
+    FinishedFilling,
+    /// The flash contents of the given range are checked against the new data.
+    Verifying { address: u32, size: u32 },
+    /// The sector at `address` already contains the new data and is neither erased nor programmed.
+    SectorSkipped { address: u32, size: u32 },
+    /// The page at `address` already contains the new data and is not programmed.
+    PageSkipped { address: u32, bytes: u32 },
+    /// Erasing of flash has started.
+    StartedErasing,
+    /// The sector at `address` has been erased successfully.
+    SectorErased {
+        address: u32,
+        size: u32,
+        duration: Duration,
+    },
+    /// Erasing of the flash has failed.
+    FailedErasing,
+    /// Erasing of the flash has finished successfully.
+    FinishedErasing,
+    /// Programming of the flash has started.
+    StartedProgramming,
+    /// The page at `address` has been programmed successfully.
+    PageProgrammed {
+        address: u32,
+        bytes: u32,
+        duration: Duration,
+    },
+    /// Programming of the flash failed.
+    FailedProgramming,
+    /// Programming of the flash has finished successfully.
+    FinishedProgramming,
+    /// Verification of the programmed data has started.
+    ///
+    /// `method` is the method which is actually used, which might differ from the requested one.
+    StartedVerifying { method: VerifyMethod },
+    /// Verification of the programmed data failed.
+    FailedVerifying,
+    /// Verification of the programmed data has finished successfully.
+    FinishedVerifying,
+}
+
+impl FlashEvent {
+    /// Converts the event into the `ProgressEvent` passed to `FlashProgress` closures.
+    ///
+    /// Returns `None` for events which have no `ProgressEvent` counterpart.
+    fn into_progress_event(self) -> Option<ProgressEvent> {
+        Some(match self {
+            FlashEvent::Planned { .. } => return None,
+            FlashEvent::Initialized {
+                flash_layout,
+                simulated,
+            } => ProgressEvent::Initialized {
+                flash_layout,
+                simulated,
+            },
+            FlashEvent::StartedFilling => ProgressEvent::StartedFilling,
+            FlashEvent::PageFilled {
+                bytes, duration, ..
+            } => ProgressEvent::PageFilled {
+                size: bytes,
+                time: duration,
+            },
+            FlashEvent::FailedFilling => ProgressEvent::FailedFilling,
+            FlashEvent::FinishedFilling => ProgressEvent::FinishedFilling,
+            FlashEvent::Verifying { .. } => return None,
+            FlashEvent::SectorSkipped { size, .. } => ProgressEvent::SectorSkipped { size },
+            FlashEvent::PageSkipped { bytes, .. } => ProgressEvent::PageSkipped { size: bytes },
+            FlashEvent::StartedErasing => ProgressEvent::StartedErasing,
+            FlashEvent::SectorErased { size, duration, .. } => ProgressEvent::SectorErased {
+                size,
+                time: duration,
+            },
+            FlashEvent::FailedErasing => ProgressEvent::FailedErasing,
+            FlashEvent::FinishedErasing => ProgressEvent::FinishedErasing,
+            FlashEvent::StartedProgramming => ProgressEvent::StartedProgramming,
+            FlashEvent::PageProgrammed {
+                bytes, duration, ..
+            } => ProgressEvent::PageProgrammed {
+                size: bytes,
+                time: duration,
+            },
+            FlashEvent::FailedProgramming => ProgressEvent::FailedProgramming,
+            FlashEvent::FinishedProgramming => ProgressEvent::FinishedProgramming,
+            FlashEvent::StartedVerifying { method } => ProgressEvent::StartedVerifying { method },
+            FlashEvent::FailedVerifying => ProgressEvent::FailedVerifying,
+            FlashEvent::FinishedVerifying => ProgressEvent::FinishedVerifying,
+        })
+    }
+}
+
+/// The sectors and pages of a flash region which are about to be written, see [`FlashEvent::Planned`].
+///
+/// Unchanged sectors and pages which are skipped later on are included as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionPlan {
+    /// The address range of the flash region.
+    pub range: Range<u32>,
+    /// The number of sectors to erase.
+    pub sectors: u32,
+    /// The total size of the sectors to erase, in bytes.
+    pub sector_bytes: u32,
+    /// The number of pages to program.
+    pub pages: u32,
+    /// The total size of the pages to program, in bytes.
+    pub page_bytes: u32,
+    /// The time erasing a sector takes, `None` if the flash algorithm does not declare it.
+    pub sector_erase_time: Option<Duration>,
+    /// The time programming a page takes, `None` if the flash algorithm does not declare it.
+    pub page_program_time: Option<Duration>,
+}
+
+impl RegionPlan {
+    /// Plans writing `flash_layout` to the flash `range`, using the timings declared in `properties`.
+    pub(super) fn new(
+        range: Range<u32>,
+        flash_layout: &FlashLayout,
+        properties: &FlashProperties,
+    ) -> Self {
+        let declared = |ms: u32| (ms != 0).then(|| Duration::from_millis(u64::from(ms)));
+
+        Self {
+            range,
+            sectors: flash_layout.sectors().len() as u32,
+            sector_bytes: flash_layout.sectors().iter().map(|s| s.size()).sum(),
+            pages: flash_layout.pages().len() as u32,
+            page_bytes: flash_layout.pages().iter().map(|p| p.size()).sum(),
+            sector_erase_time: declared(properties.erase_sector_timeout),
+            page_program_time: declared(properties.program_page_timeout),
+        }
+    }
+
+    fn has_timings(&self) -> bool {
+        self.sector_erase_time.is_some() && self.page_program_time.is_some()
+    }
+}
+
+/// Tracks the progress of a whole download, weighted by the estimated time of each operation.
+///
+/// Erasing a sector and programming a page count with the time declared by the flash algorithm
+/// of their region, so slow regions take up a larger part of the progress than fast ones.
+/// If any region has no declared timings, every erased and programmed byte counts the same instead.
+///
+/// ```
+/// use probe_rs::flashing::{FlashEvent, WeightedProgress};
+///
+/// fn show(progress: &mut WeightedProgress, event: &FlashEvent) {
+///     progress.update(event);
+///     println!("{:.0}%", progress.fraction() * 100.0);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WeightedProgress {
+    regions: Vec<RegionPlan>,
+    time_weighted: bool,
+    total: f64,
+    done: f64,
+}
+
+impl WeightedProgress {
+    /// Creates the progress for the planned `regions`, usually from a [`FlashEvent::Planned`] event.
+    pub fn new(regions: Vec<RegionPlan>) -> Self {
+        let time_weighted = !regions.is_empty() && regions.iter().all(RegionPlan::has_timings);
+
+        let mut progress = Self {
+            regions,
+            time_weighted,
+            total: 0.0,
+            done: 0.0,
+        };
+        progress.total = progress
+            .regions
+            .iter()
+            .map(|region| {
+                progress.erase_weight(region, region.sectors, region.sector_bytes)
+                    + progress.program_weight(region, region.pages, region.page_bytes)
+            })
+            .sum();
+
+        progress
+    }
+
+    /// Whether the progress is weighted by the declared timings, instead of by the number of bytes.
+    pub fn is_time_weighted(&self) -> bool {
+        self.time_weighted
+    }
+
+    /// Advances the progress by `event`.
+    ///
+    /// A `Planned` event starts over with the new plan. Events for addresses outside of
+    /// the planned regions are ignored.
+    pub fn update(&mut self, event: &FlashEvent) {
+        let weight = match event {
+            FlashEvent::Planned { regions } => {
+                *self = Self::new(regions.clone());
+                return;
+            }
+            FlashEvent::SectorErased { address, size, .. }
+            | FlashEvent::SectorSkipped { address, size } => self
+                .region(*address)
+                .map(|region| self.erase_weight(region, 1, *size)),
+            FlashEvent::PageProgrammed { address, bytes, .. }
+            | FlashEvent::PageSkipped { address, bytes } => self
+                .region(*address)
+                .map(|region| self.program_weight(region, 1, *bytes)),
+            _ => None,
+        };
+
+        self.done += weight.unwrap_or(0.0);
+    }
+
+    /// The part of the download which is done, from `0.0` to `1.0`.
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0.0 {
+            return 0.0;
+        }
+        (self.done / self.total).min(1.0)
+    }
+
+    fn region(&self, address: u32) -> Option<&RegionPlan> {
+        self.regions.iter().find(|r| r.range.contains(&address))
+    }
+
+    /// The weight of erasing `count` sectors of `region` with a total size of `bytes`.
+    fn erase_weight(&self, region: &RegionPlan, count: u32, bytes: u32) -> f64 {
+        match region.sector_erase_time {
+            Some(time) if self.time_weighted => f64::from(count) * time.as_secs_f64(),
+            _ => f64::from(bytes),
+        }
+    }
+
+    /// The weight of programming `count` pages of `region` with a total size of `bytes`.
+    fn program_weight(&self, region: &RegionPlan, count: u32, bytes: u32) -> f64 {
+        match region.page_program_time {
+            Some(time) if self.time_weighted => f64::from(count) * time.as_secs_f64(),
+            _ => f64::from(bytes),
+        }
     }
 }
 
@@ -105,38 +465,52 @@ impl FlashProgress {
 /// * `StartedFilling`
 /// * `PageFilled` for every page
 /// * `FinishedFilling`
+/// * `SectorSkipped` and `PageSkipped` for every unchanged sector and page,
+///   if `skip_unchanged` is enabled
 /// * `StartedErasing`
 /// * `SectorErased` for every sector
 /// * `FinishedErasing`
 /// * `StartedProgramming`
 /// * `PageProgrammed` for every page
 /// * `FinishedProgramming`
+/// * `StartedVerifying` and `FinishedVerifying`, if verification is enabled
+///
+/// During a dry run, the same events are emitted, but no verification is done.
 ///
 /// If an erorr occurs in any stage, one of the `Failed*` event will be returned,
 /// and no further events will be returned.
 #[derive(Debug)]
 pub enum ProgressEvent {
+    /// The flash algorithm was set up and `flash_layout` is about to be written.
+    ///
+    /// If `simulated` is `true`, this is a dry run. The following events only report
+    /// what would be done, and their durations are estimates.
     Initialized {
         flash_layout: FlashLayout,
+        simulated: bool,
     },
     /// Filling of flash pages has started.
     StartedFilling,
     /// A page has been filled successfully.
-    PageFilled {
-        size: u32,
-        time: Duration,
-    },
+    PageFilled { size: u32, time: Duration },
     /// Filling of the pages has failed.
     FailedFilling,
     /// Filling of the pages has finished successfully.
     FinishedFilling,
+    /// A sector already contains the new data and is neither erased nor programmed.
+    ///
+    /// The sectors in the `Initialized` layout which are skipped this way will not be reported
+    /// through `SectorErased`.
+    SectorSkipped { size: u32 },
+    /// A page already contains the new data and is not programmed.
+    ///
+    /// The pages in the `Initialized` layout which are skipped this way will not be reported
+    /// through `PageProgrammed`.
+    PageSkipped { size: u32 },
     /// Erasing of flash has started.
     StartedErasing,
     /// A sector has been erased successfully.
-    SectorErased {
-        size: u32,
-        time: Duration,
-    },
+    SectorErased { size: u32, time: Duration },
     /// Erasing of the flash has failed.
     FailedErasing,
     /// Erasing of the flash has finished successfully.
@@ -144,12 +518,123 @@ pub enum ProgressEvent {
     /// Programming of the flash has started.
     StartedProgramming,
     /// A flash page has been programmed successfully.
-    PageProgrammed {
-        size: u32,
-        time: Duration,
-    },
+    PageProgrammed { size: u32, time: Duration },
     /// Programming of the flash failed.
     FailedProgramming,
     /// Programming of the flash has finished successfully.
     FinishedProgramming,
+    /// Verification of the programmed data has started.
+    ///
+    /// `method` is the method which is actually used, which might differ from the requested one.
+    StartedVerifying { method: VerifyMethod },
+    /// Verification of the programmed data failed.
+    FailedVerifying,
+    /// Verification of the programmed data has finished successfully.
+    FinishedVerifying,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{cell::RefCell, sync::mpsc::channel};
+
+    #[test]
+    fn events_reach_closure_and_channel() {
+        let (sender, receiver) = channel();
+        let sizes = Rc::new(RefCell::new(Vec::new()));
+        let closure_sizes = sizes.clone();
+        let progress = FlashProgress::new(move |event| {
+            if let ProgressEvent::SectorErased { size, .. } = event {
+                closure_sizes.borrow_mut().push(size);
+            }
+        })
+        .with_sender(sender);
+
+        progress.verifying(0x1000, 0x400);
+        progress.sector_erased(0x1000, 0x400, Duration::from_millis(3));
+
+        assert_eq!(*sizes.borrow(), vec![0x400]);
+
+        let events = receiver.try_iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            FlashEvent::Verifying {
+                address: 0x1000,
+                size: 0x400
+            }
+        ));
+        assert!(matches!(
+            events[1],
+            FlashEvent::SectorErased {
+                address: 0x1000,
+                size: 0x400,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn dropped_receiver_is_ignored() {
+        let (sender, receiver) = channel();
+        drop(receiver);
+
+        FlashProgress::from_sender(sender).started_erasing();
+    }
+
+    fn region(start: u32, erase_ms: u64, program_ms: Option<u64>) -> RegionPlan {
+        RegionPlan {
+            range: start..start + 0x1000,
+            sectors: 1,
+            sector_bytes: 0x1000,
+            pages: 4,
+            page_bytes: 0x1000,
+            sector_erase_time: Some(Duration::from_millis(erase_ms)),
+            page_program_time: program_ms.map(Duration::from_millis),
+        }
+    }
+
+    #[test]
+    fn progress_is_weighted_by_time() {
+        let internal = region(0x0800_0000, 10, Some(1));
+        let qspi = region(0x9000_0000, 100, Some(10));
+        let mut progress = WeightedProgress::new(vec![internal, qspi]);
+        assert!(progress.is_time_weighted());
+
+        progress.update(&FlashEvent::SectorErased {
+            address: 0x0800_0000,
+            size: 0x1000,
+            duration: Duration::from_millis(8),
+        });
+        assert!((progress.fraction() - 10.0 / 154.0).abs() < 1e-9);
+
+        progress.update(&FlashEvent::PageSkipped {
+            address: 0x9000_0400,
+            bytes: 0x400,
+        });
+        assert!((progress.fraction() - 20.0 / 154.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unknown_timings_weight_by_bytes() {
+        let internal = region(0x0800_0000, 10, Some(1));
+        let qspi = region(0x9000_0000, 100, None);
+        let mut progress = WeightedProgress::new(vec![internal, qspi]);
+        assert!(!progress.is_time_weighted());
+
+        progress.update(&FlashEvent::SectorErased {
+            address: 0x0800_0000,
+            size: 0x1000,
+            duration: Duration::from_millis(8),
+        });
+        assert_eq!(progress.fraction(), 0.25);
+
+        // Events outside of the plan do not count.
+        progress.update(&FlashEvent::SectorErased {
+            address: 0x2000_0000,
+            size: 0x1000,
+            duration: Duration::from_millis(8),
+        });
+        assert_eq!(progress.fraction(), 0.25);
+    }
 }
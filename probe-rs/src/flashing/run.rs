@@ -0,0 +1,193 @@
+use std::{ops::Range, path::Path, time::Duration};
+
+use super::*;
+use crate::{config::MemoryRegion, session::Session, CoreStatus, Error};
+
+use thiserror::Error;
+
+/// What the target does once it is flashed and verified by `Session::flash_and_run`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AfterFlashing {
+    /// Reset the target, and halt the core at the `main` function of the ELF file.
+    ///
+    /// The core has to reach `main` within the given time.
+    HaltAtMain(Duration),
+    /// Reset the target, and let it run.
+    #[default]
+    Run,
+    /// Reset the target, let it run for the given time, and report the status of the core afterwards.
+    ///
+    /// This detects firmware which halts, e.g. on a breakpoint or a semihosting call, soon after starting.
+    RunFor(Duration),
+}
+
+/// Options for `Session::flash_and_run`.
+#[derive(Default)]
+pub struct FlashAndRunOptions<'progress> {
+    /// The options for flashing the ELF file.
+    ///
+    /// The flash is always verified, with `VerifyMethod::Full` unless `verify` selects another method.
+    pub download: DownloadOptions<'progress>,
+    /// What the target does once it is flashed.
+    pub after_flashing: AfterFlashing,
+    /// The core which is halted or whose status is reported.
+    pub core_index: usize,
+}
+
+/// The result of `Session::flash_and_run`.
+#[derive(Debug, Clone)]
+pub struct FlashAndRunSummary {
+    /// What was erased and programmed.
+    pub flash: FlashSummary,
+    /// The status of the core once the `AfterFlashing` step is done.
+    pub status: CoreStatus,
+}
+
+/// Describes any error that happened in `Session::flash_and_run`.
+///
+/// The target is never reset or started once flashing or verifying failed.
+#[derive(Debug, Error)]
+pub enum FlashAndRunError {
+    #[error("Flashing the ELF file failed")]
+    Download(#[source] FileDownloadError),
+    #[error("Verification of the flash region {:#010x}..{:#010x} failed", .region.start, .region.end)]
+    Verify {
+        region: Range<u32>,
+        #[source]
+        source: FileDownloadError,
+    },
+    #[error("The ELF file has no `main` symbol to halt at")]
+    MainNotFound,
+    #[error("Resetting or running the target failed")]
+    Run(#[from] Error),
+}
+
+/// Flashes the ELF file at `path`, verifies it and starts the target as given by `options.after_flashing`.
+pub(crate) fn flash_and_run(
+    session: &mut Session,
+    path: &Path,
+    options: FlashAndRunOptions<'_>,
+) -> Result<FlashAndRunSummary, FlashAndRunError> {
+    let elf =
+        std::fs::read(path).map_err(|e| FlashAndRunError::Download(FileDownloadError::IO(e)))?;
+
+    // Look up `main` before flashing, so the flash is not changed for an unusable file.
+    let main = match options.after_flashing {
+        AfterFlashing::HaltAtMain(_) => Some(
+            main_address(&elf)
+                .map_err(|e| FlashAndRunError::Download(e.into()))?
+                .ok_or(FlashAndRunError::MainNotFound)?,
+        ),
+        _ => None,
+    };
+
+    let FlashAndRunOptions {
+        mut download,
+        after_flashing,
+        core_index,
+    } = options;
+    download.verify = Some(download.verify.unwrap_or(VerifyMethod::Full));
+
+    let memory_map = session.memory_map().to_vec();
+    let mut loader = FlashLoader::new(&memory_map, download.program_options());
+    let flash = loader
+        .load_elf_data(&elf, &download.segment_filter)
+        .and_then(|_| commit(session, &mut loader, download))
+        .map_err(
+            |error| match mismatched_region(&error, session.memory_map()) {
+                Some(region) => FlashAndRunError::Verify {
+                    region,
+                    source: error,
+                },
+                None => FlashAndRunError::Download(error),
+            },
+        )?;
+
+    let status = match after_flashing {
+        AfterFlashing::HaltAtMain(timeout) => {
+            let main = main.ok_or(FlashAndRunError::MainNotFound)?;
+            let mut core = session.core(core_index)?;
+
+            core.reset_and_halt(timeout)?;
+            core.run_to_address(main, timeout)?;
+
+            core.status()?
+        }
+        AfterFlashing::Run => {
+            session.reset(core_index)?;
+            session.core(core_index)?.status()?
+        }
+        AfterFlashing::RunFor(duration) => {
+            session.reset(core_index)?;
+            std::thread::sleep(duration);
+            session.core(core_index)?.status()?
+        }
+    };
+
+    Ok(FlashAndRunSummary { flash, status })
+}
+
+/// Returns the address of the `main` function in `elf`, without the Thumb bit.
+fn main_address(elf: &[u8]) -> Result<Option<u32>, goblin::error::Error> {
+    let elf = goblin::elf::Elf::parse(elf)?;
+
+    Ok(elf
+        .syms
+        .iter()
+        .find(|symbol| symbol.is_function() && &elf.strtab[symbol.st_name] == "main")
+        .map(|symbol| symbol.st_value as u32 & !1))
+}
+
+/// Returns the flash region of `memory_map` containing the address at which the verification failed,
+/// if `error` is a verification error.
+fn mismatched_region(error: &FileDownloadError, memory_map: &[MemoryRegion]) -> Option<Range<u32>> {
+    let error = match error {
+        FileDownloadError::Flash(FlashError::Other(error)) => error.downcast_ref::<FlashError>()?,
+        FileDownloadError::Flash(error) => error,
+        _ => return None,
+    };
+
+    let address = match error {
+        FlashError::VerifyMismatch { address } => *address,
+        FlashError::VerifyChecksumMismatch { address, .. } => *address,
+        _ => return None,
+    };
+
+    memory_map.iter().find_map(|region| match region {
+        MemoryRegion::Flash(flash) if flash.range.contains(&address) => Some(flash.range.clone()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::FlashRegion;
+
+    #[test]
+    fn verify_errors_name_the_region() {
+        let memory_map = [
+            MemoryRegion::Flash(FlashRegion {
+                range: 0x0000_0000..0x0001_0000,
+                is_boot_memory: true,
+            }),
+            MemoryRegion::Flash(FlashRegion {
+                range: 0x1000_0000..0x1000_1000,
+                is_boot_memory: false,
+            }),
+        ];
+
+        let mismatch = FileDownloadError::Flash(FlashError::Other(anyhow::anyhow!(
+            FlashError::VerifyMismatch {
+                address: 0x1000_0010
+            }
+        )));
+        assert_eq!(
+            mismatched_region(&mismatch, &memory_map),
+            Some(0x1000_0000..0x1000_1000)
+        );
+
+        let other = FileDownloadError::Flash(FlashError::NoFlashLoaderAlgorithmAttached);
+        assert_eq!(mismatched_region(&other, &memory_map), None);
+    }
+}
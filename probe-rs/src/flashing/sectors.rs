@@ -0,0 +1,164 @@
+//! The individual sectors of the flash, and whether they are write protected.
+
+use super::sector_layout;
+use crate::config::MemoryRegion;
+use crate::option_bytes::OptionBytesError;
+use crate::{Error, Session};
+use std::ops::Range;
+
+/// Whether a flash sector is write protected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectorProtection {
+    /// The sector can be erased and programmed.
+    Unprotected,
+    /// The sector is write protected, and has to be unprotected before it can be erased.
+    Protected,
+    /// The protection registers of the family are not supported, or do not cover the sector.
+    Unknown,
+}
+
+/// A sector of the flash, as returned by `Session::flash_sectors`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectorStatus {
+    /// The start address of the sector.
+    pub address: u32,
+    /// The size of the sector in bytes.
+    pub size: u32,
+    /// Whether the sector is write protected.
+    pub protection: SectorProtection,
+}
+
+impl SectorStatus {
+    /// Returns the address range of the sector.
+    pub fn range(&self) -> Range<u32> {
+        self.address..self.address + self.size
+    }
+}
+
+/// Splits the ranges of equally sized sectors returned by `sector_layout` into individual sectors.
+///
+/// A range which is not a multiple of its sector size ends with a smaller sector.
+fn split_sectors(layout: &[(Range<u32>, u32)]) -> Vec<(u32, u32)> {
+    layout
+        .iter()
+        .filter(|(_, size)| *size != 0)
+        .flat_map(|(range, size)| {
+            let (end, size) = (range.end, *size);
+            (range.start..end)
+                .step_by(size as usize)
+                .map(move |address| (address, size.min(end - address)))
+        })
+        .collect()
+}
+
+/// Returns the sectors of all flash regions of the target, sorted by address.
+///
+/// The sector layout is taken from the flash algorithms of the target, so a region without
+/// an algorithm has no sectors. The write protection is read from the option bytes of the
+/// families which support it, where the sectors of the boot memory are numbered from its start.
+pub(crate) fn flash_sectors(session: &mut Session) -> Result<Vec<SectorStatus>, Error> {
+    let memory_map = session.memory_map().to_vec();
+    let mut sectors = vec![];
+
+    for region in memory_map.iter().filter_map(|region| match region {
+        MemoryRegion::Flash(region) => Some(region),
+        _ => None,
+    }) {
+        let layout = sector_layout(session, region);
+        if layout.is_empty() {
+            log::debug!(
+                "No flash algorithm for the region {:#010x}..{:#010x}, its sectors are unknown",
+                region.range.start,
+                region.range.end
+            );
+        }
+
+        let region_sectors = split_sectors(&layout);
+        let protection = match region.is_boot_memory {
+            true => write_protection(session, region_sectors.len())?,
+            false => vec![],
+        };
+
+        sectors.extend(
+            region_sectors
+                .into_iter()
+                .enumerate()
+                .map(|(index, (address, size))| SectorStatus {
+                    address,
+                    size,
+                    protection: protection
+                        .get(index)
+                        .copied()
+                        .unwrap_or(SectorProtection::Unknown),
+                }),
+        );
+    }
+
+    sectors.sort_by_key(|sector| sector.address);
+    Ok(sectors)
+}
+
+/// Reads the write protection of the first `count` sectors of the boot memory.
+///
+/// Returns no protection at all for the families whose option bytes are not supported.
+fn write_protection(session: &mut Session, count: usize) -> Result<Vec<SectorProtection>, Error> {
+    let mut option_bytes = match session.option_bytes() {
+        Some(option_bytes) => option_bytes,
+        None => return Ok(vec![]),
+    };
+
+    let protection = match option_bytes.family().write_protection() {
+        Some(protection) => protection,
+        None => return Ok(vec![]),
+    };
+
+    let options = option_bytes.read().map_err(|error| match error {
+        OptionBytesError::Core(error) => error,
+        error => Error::Other(error.into()),
+    })?;
+
+    Ok((0..count)
+        .map(|index| match protection.is_protected(options, index) {
+            Some(true) => SectorProtection::Protected,
+            Some(false) => SectorProtection::Unprotected,
+            None => SectorProtection::Unknown,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sectors_of_different_sizes_are_split() {
+        // The layout of a 512 KiB STM32F4, with 16, 64 and 128 KiB sectors.
+        let layout = [
+            (0x0800_0000..0x0801_0000, 0x4000),
+            (0x0801_0000..0x0802_0000, 0x1_0000),
+            (0x0802_0000..0x0808_0000, 0x2_0000),
+        ];
+
+        let sectors = split_sectors(&layout);
+        assert_eq!(sectors.len(), 8);
+        assert_eq!(
+            &sectors[..2],
+            &[(0x0800_0000, 0x4000), (0x0800_4000, 0x4000)]
+        );
+        assert_eq!(
+            &sectors[3..6],
+            &[
+                (0x0800_C000, 0x4000),
+                (0x0801_0000, 0x1_0000),
+                (0x0802_0000, 0x2_0000)
+            ]
+        );
+        assert_eq!(sectors[7], (0x0806_0000, 0x2_0000));
+
+        // A region which ends within a sector.
+        assert_eq!(
+            split_sectors(&[(0x1000..0x2800, 0x1000)]),
+            vec![(0x1000, 0x1000), (0x2000, 0x800)]
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use std::{ops::Range, path::Path};
+
+use super::*;
+use crate::session::Session;
+
+/// The result of comparing a flash region with an image, see `Session::verify_image`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionVerification {
+    /// The address range of the flash region.
+    pub region: Range<u32>,
+    /// The number of bytes of the image inside the region.
+    pub bytes: u32,
+    /// The first address at which the flash differs from the image, `None` if the region matches.
+    pub first_mismatch: Option<u32>,
+}
+
+/// The result of `Session::verify_image`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifySummary {
+    /// The flash regions containing data of the image, sorted by their address.
+    ///
+    /// Regions which the image does not touch are not compared, and not listed.
+    pub regions: Vec<RegionVerification>,
+}
+
+impl VerifySummary {
+    /// Whether the flash contains the image.
+    pub fn matches(&self) -> bool {
+        self.regions
+            .iter()
+            .all(|region| region.first_mismatch.is_none())
+    }
+}
+
+/// Compares the file of given `format` at `path` with the contents of the flash.
+pub(crate) fn verify_image(
+    session: &mut Session,
+    path: &Path,
+    format: Format,
+    method: VerifyMethod,
+) -> Result<VerifySummary, FileDownloadError> {
+    let mut buffer = vec![];
+    let mut buffer_vec = vec![];
+    let memory_map = session.memory_map().to_vec();
+    let mut loader = FlashLoader::new(&memory_map, ProgramOptions::default());
+
+    load_file(
+        &mut loader,
+        path,
+        format,
+        &SegmentFilter::default(),
+        &mut buffer,
+        &mut buffer_vec,
+    )?;
+
+    let regions = loader.verify(session, method, &FlashProgress::default())?;
+    Ok(VerifySummary { regions })
+}
@@ -52,9 +52,9 @@
 //! let buff = [0u32;50];
 //! core.write_32(0x2000_0000, &buff)?;
 //!
-//! // of course we can also write 8bit words.
+//! // of course we can also write bytes.
 //! let buff = [0u8;50];
-//! core.write_8(0x2000_0000, &buff)?;
+//! core.write(0x2000_0000, &buff)?;
 //!
 //! # Ok::<(), Error>(())
 //! ```
@@ -71,25 +71,37 @@
 extern crate serde;
 
 pub mod architecture;
+mod async_session;
 pub mod config;
 mod core;
 pub mod debug;
 mod error;
 pub mod flashing;
 mod memory;
+pub mod option_bytes;
 mod probe;
+pub mod reset_reason;
+pub mod semihosting;
 mod session;
 
+pub use crate::async_session::AsyncSession;
 pub use crate::config::Target;
 pub use crate::core::CoreType;
 pub use crate::core::{
-    Architecture, Breakpoint, BreakpointId, CommunicationInterface, Core, CoreInformation,
-    CoreInterface, CoreList, CoreRegister, CoreRegisterAddress, CoreStatus, HaltReason,
+    Architecture, Breakpoint, BreakpointId, CallArg, CommunicationInterface, Core, CoreInformation,
+    CoreInterface, CoreList, CoreRegister, CoreRegisterAddress, CoreStatus, HaltGuard, HaltReason,
+    InterruptMasking, RangeStepMode, RangeStepResult, RegisterSnapshot, RunToAddressResult,
+    VectorCatchFlags, WatchKind,
 };
 pub use crate::error::Error;
-pub use crate::memory::{Memory, MemoryInterface, MemoryList};
+pub use crate::memory::{
+    AccessWidth, DumpError, DumpFormat, DumpOptions, DumpSummary, Memory, MemoryInterface,
+    MemoryList, TargetMemory, TargetMemoryError, ValueChange, ValueWatch,
+};
+#[cfg(feature = "ftdi")]
+pub use crate::probe::ftdi::{FtdiInterface, FtdiPinout};
 pub use crate::probe::{
-    AttachMethod, DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector, DebugProbeType,
-    Probe, WireProtocol,
+    AttachConfig, AttachMethod, DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector,
+    DebugProbeType, JtagDevice, Probe, StableProbeId, WireProtocol,
 };
-pub use crate::session::Session;
+pub use crate::session::{CoreAvailability, CoreInfo, ReadOptions, ReconnectEvent, Session};
@@ -0,0 +1,288 @@
+use crate::error;
+use std::{collections::BTreeMap, ops::Range};
+
+/// The size of a cache line in bytes. Memory is always fetched in whole lines.
+const LINE_SIZE: u32 = 32;
+
+/// The largest number of lines kept in the cache, all lines are dropped once it is full.
+const MAX_LINES: usize = 4096;
+
+/// A read cache for the memory of a halted target, see `Session::enable_memory_cache`.
+///
+/// Only memory inside of `regions` is cached, so that reads of peripheral registers,
+/// which might have side effects, are never fetched in advance or served from the cache.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryCache {
+    enabled: bool,
+    /// Set while the core is known to be halted, the cache is only used then.
+    halted: bool,
+    regions: Vec<Range<u32>>,
+    lines: BTreeMap<u32, [u8; LINE_SIZE as usize]>,
+    /// Counts the invalidations, so copies of the memory made elsewhere, like in `TargetMemory`,
+    /// know when they are outdated.
+    generation: u64,
+}
+
+impl MemoryCache {
+    /// Creates a disabled cache for the memory in `regions`.
+    pub(crate) fn new(regions: Vec<Range<u32>>) -> Self {
+        Self {
+            regions,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.invalidate();
+    }
+
+    /// Drops all cached memory.
+    pub(crate) fn invalidate(&mut self) {
+        self.lines.clear();
+        self.generation += 1;
+    }
+
+    /// Drops the cached memory overlapping the `len` bytes at `address`.
+    pub(crate) fn invalidate_range(&mut self, address: u32, len: usize) {
+        if len != 0 {
+            self.generation += 1;
+        }
+        if self.lines.is_empty() || len == 0 {
+            return;
+        }
+
+        let first = address & !(LINE_SIZE - 1);
+        let end = address.saturating_add(len as u32);
+
+        let overlapping: Vec<u32> = self
+            .lines
+            .range(first..end)
+            .map(|(line, _)| *line)
+            .collect();
+        for line in overlapping {
+            self.lines.remove(&line);
+        }
+    }
+
+    /// Updates whether the core is halted.
+    ///
+    /// The memory might have changed whenever the core was not halted,
+    /// so all cached memory is dropped if the state changes.
+    pub(crate) fn set_halted(&mut self, halted: bool) {
+        if halted != self.halted {
+            self.invalidate();
+        }
+        self.halted = halted;
+    }
+
+    /// Returns a number which changes whenever the memory might have changed, if the core is
+    /// known to be halted.
+    ///
+    /// This is independent of whether the cache is enabled.
+    pub(crate) fn generation(&self) -> Option<u64> {
+        self.halted.then_some(self.generation)
+    }
+
+    /// Returns the whole cache lines covering the `len` bytes at `address`, if they can be cached.
+    fn lines_for(&self, address: u32, len: usize) -> Option<Range<u32>> {
+        if !self.enabled || !self.halted || len == 0 {
+            return None;
+        }
+
+        let start = address & !(LINE_SIZE - 1);
+        let end = address
+            .checked_add(len as u32)?
+            .checked_add(LINE_SIZE - 1)?
+            & !(LINE_SIZE - 1);
+
+        if self
+            .regions
+            .iter()
+            .any(|region| region.start <= start && end <= region.end)
+        {
+            Some(start..end)
+        } else {
+            None
+        }
+    }
+
+    /// Reads `data` at `address` from the cache, fetching lines which are not cached with `fetch`.
+    ///
+    /// Consecutive missing lines are fetched with a single call. Returns `false` if the memory
+    /// cannot be cached, in which case nothing was read.
+    pub(crate) fn read(
+        &mut self,
+        address: u32,
+        data: &mut [u8],
+        mut fetch: impl FnMut(u32, &mut [u32]) -> Result<(), error::Error>,
+    ) -> Result<bool, error::Error> {
+        let lines = match self.lines_for(address, data.len()) {
+            Some(lines) => lines,
+            None => return Ok(false),
+        };
+
+        let line_addresses = (lines.start..lines.end).step_by(LINE_SIZE as usize);
+        let missing: Vec<u32> = line_addresses
+            .clone()
+            .filter(|line| !self.lines.contains_key(line))
+            .collect();
+
+        if self.lines.len() + missing.len() > MAX_LINES {
+            self.lines.clear();
+        }
+
+        // Fetch each run of consecutive missing lines at once.
+        let mut runs: Vec<Range<u32>> = Vec::new();
+        for line in missing {
+            match runs.last_mut() {
+                Some(run) if run.end == line => run.end += LINE_SIZE,
+                _ => runs.push(line..line + LINE_SIZE),
+            }
+        }
+
+        for run in runs {
+            let mut words = vec![0; ((run.end - run.start) / 4) as usize];
+            fetch(run.start, &mut words)?;
+
+            let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+            for (line, chunk) in line_addresses
+                .clone()
+                .filter(|line| run.contains(line))
+                .zip(bytes.chunks(LINE_SIZE as usize))
+            {
+                let mut contents = [0; LINE_SIZE as usize];
+                contents.copy_from_slice(chunk);
+                self.lines.insert(line, contents);
+            }
+        }
+
+        for (i, byte) in data.iter_mut().enumerate() {
+            let address = address + i as u32;
+            let line = &self.lines[&(address & !(LINE_SIZE - 1))];
+            *byte = line[(address % LINE_SIZE) as usize];
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Creates an enabled cache for a halted core, with a RAM at 0x2000_0000.
+    fn halted_cache() -> MemoryCache {
+        let ram = 0x2000_0000..0x2001_0000;
+        let mut cache = MemoryCache::new(vec![ram]);
+        cache.set_enabled(true);
+        cache.set_halted(true);
+        cache
+    }
+
+    /// Fills the words with their address, and records the fetched ranges.
+    fn fetch_into(
+        fetched: &mut Vec<(u32, usize)>,
+    ) -> impl FnMut(u32, &mut [u32]) -> Result<(), error::Error> + '_ {
+        move |address, words| {
+            fetched.push((address, words.len()));
+            for (i, word) in words.iter_mut().enumerate() {
+                *word = address + 4 * i as u32;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn repeated_reads_are_cached() {
+        let mut cache = halted_cache();
+        let mut fetched = vec![];
+
+        let mut data = [0; 4];
+        assert!(cache
+            .read(0x2000_0104, &mut data, fetch_into(&mut fetched))
+            .unwrap());
+        assert_eq!(u32::from_le_bytes(data), 0x2000_0104);
+
+        let mut data = [0; 2];
+        assert!(cache
+            .read(0x2000_0110, &mut data, fetch_into(&mut fetched))
+            .unwrap());
+        assert_eq!(data, [0x10, 0x01]);
+
+        assert_eq!(fetched, vec![(0x2000_0100, 8)]);
+    }
+
+    #[test]
+    fn missing_lines_are_coalesced() {
+        let mut cache = halted_cache();
+        let mut fetched = vec![];
+
+        let mut data = [0; 4];
+        cache
+            .read(0x2000_0020, &mut data, fetch_into(&mut fetched))
+            .unwrap();
+
+        // The first and the last two lines are missing.
+        let mut data = [0; 0x70];
+        cache
+            .read(0x2000_0008, &mut data, fetch_into(&mut fetched))
+            .unwrap();
+        assert_eq!(&data[0x18..0x1c], &0x2000_0020u32.to_le_bytes());
+
+        assert_eq!(
+            fetched,
+            vec![(0x2000_0020, 8), (0x2000_0000, 8), (0x2000_0040, 16)]
+        );
+    }
+
+    #[test]
+    fn only_halted_memory_regions_are_cached() {
+        let mut cache = halted_cache();
+        let mut fetched = vec![];
+        let mut data = [0; 4];
+
+        // Peripherals are never cached.
+        assert!(!cache
+            .read(0x4000_0000, &mut data, fetch_into(&mut fetched))
+            .unwrap());
+        // Reads crossing the end of the region are not cached either.
+        assert!(!cache
+            .read(0x2000_fffe, &mut data, fetch_into(&mut fetched))
+            .unwrap());
+
+        cache.set_halted(false);
+        assert!(!cache
+            .read(0x2000_0000, &mut data, fetch_into(&mut fetched))
+            .unwrap());
+
+        assert!(fetched.is_empty());
+    }
+
+    #[test]
+    fn writes_and_resumes_invalidate() {
+        let mut cache = halted_cache();
+        let mut fetched = vec![];
+        let mut data = [0; 0x40];
+
+        cache
+            .read(0x2000_0000, &mut data, fetch_into(&mut fetched))
+            .unwrap();
+
+        cache.invalidate_range(0x2000_0021, 1);
+        cache
+            .read(0x2000_0000, &mut data, fetch_into(&mut fetched))
+            .unwrap();
+
+        cache.set_halted(false);
+        cache.set_halted(true);
+        cache
+            .read(0x2000_0000, &mut data, fetch_into(&mut fetched))
+            .unwrap();
+
+        assert_eq!(
+            fetched,
+            vec![(0x2000_0000, 16), (0x2000_0020, 8), (0x2000_0000, 16)]
+        );
+    }
+}
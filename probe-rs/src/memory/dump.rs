@@ -0,0 +1,198 @@
+//! Dumping a region of the target memory to a binary or Intel HEX file.
+
+use crate::error;
+use std::{io::Write, ops::Range};
+use thiserror::Error;
+
+/// The number of bytes read at once while dumping.
+///
+/// If a chunk cannot be read, it is read again word by word to find the inaccessible addresses.
+const CHUNK_SIZE: u32 = 4096;
+
+/// The number of data bytes in each Intel HEX record.
+const HEX_RECORD_SIZE: u32 = 16;
+
+/// The file format of `Session::dump_region`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// The raw bytes of the region.
+    Bin,
+    /// Intel HEX records, which contain the addresses of the bytes.
+    Hex,
+}
+
+/// Options for `Session::dump_region`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DumpOptions {
+    /// How the memory is read.
+    pub read: crate::ReadOptions,
+    /// Fill the memory which cannot be read with this byte, instead of stopping at the first
+    /// inaccessible address.
+    pub fill: Option<u8>,
+}
+
+/// The result of `Session::dump_region`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DumpSummary {
+    /// The number of bytes written to the dump.
+    pub bytes: u32,
+    /// The ranges which could not be read, and were filled with `DumpOptions::fill` instead.
+    pub holes: Vec<Range<u32>>,
+}
+
+/// Describes any error that happened in `Session::dump_region`.
+#[derive(Debug, Error)]
+pub enum DumpError {
+    #[error("The memory at {address:#010x} cannot be read")]
+    Inaccessible {
+        address: u32,
+        #[source]
+        source: error::Error,
+    },
+    #[error("Writing the dump failed")]
+    Io(#[from] std::io::Error),
+    #[error("Creating the Intel HEX records failed")]
+    Hex(#[from] ihex::WriterError),
+    #[error("Accessing the target failed")]
+    Target(#[from] error::Error),
+}
+
+/// Reads `range` with `read`, which reads the bytes of a slice starting at an address.
+///
+/// Inaccessible memory is filled with `fill` and returned as holes, or reported as an error
+/// with its address if `fill` is not set.
+pub(crate) fn read_region(
+    mut read: impl FnMut(u32, &mut [u8]) -> Result<(), error::Error>,
+    range: Range<u32>,
+    fill: Option<u8>,
+) -> Result<(Vec<u8>, Vec<Range<u32>>), DumpError> {
+    let mut data = vec![0; (range.end - range.start) as usize];
+    let mut holes: Vec<Range<u32>> = Vec::new();
+
+    for (chunk_index, chunk) in data.chunks_mut(CHUNK_SIZE as usize).enumerate() {
+        let chunk_address = range.start + chunk_index as u32 * CHUNK_SIZE;
+        if read(chunk_address, chunk).is_ok() {
+            continue;
+        }
+
+        // Find the inaccessible words of the chunk.
+        let mut offset = 0;
+        while offset < chunk.len() {
+            let address = chunk_address + offset as u32;
+            let size = (4 - address % 4).min((chunk.len() - offset) as u32) as usize;
+            let word = &mut chunk[offset..offset + size];
+
+            if let Err(source) = read(address, word) {
+                let fill = fill.ok_or(DumpError::Inaccessible { address, source })?;
+                word.iter_mut().for_each(|byte| *byte = fill);
+
+                let end = address + size as u32;
+                match holes.last_mut() {
+                    Some(hole) if hole.end == address => hole.end = end,
+                    _ => holes.push(address..end),
+                }
+            }
+            offset += size;
+        }
+    }
+
+    Ok((data, holes))
+}
+
+/// Writes `data`, which starts at `address`, to `output` in `format`.
+pub(crate) fn write_dump(
+    output: &mut impl Write,
+    address: u32,
+    data: &[u8],
+    format: DumpFormat,
+) -> Result<(), DumpError> {
+    match format {
+        DumpFormat::Bin => output.write_all(data)?,
+        DumpFormat::Hex => output.write_all(hex_records(address, data)?.as_bytes())?,
+    }
+
+    Ok(())
+}
+
+/// Creates the Intel HEX representation of `data` at `address`.
+fn hex_records(address: u32, data: &[u8]) -> Result<String, ihex::WriterError> {
+    let mut records = Vec::new();
+    let mut upper = None;
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let record_address = address + offset as u32;
+        if upper != Some(record_address >> 16) {
+            upper = Some(record_address >> 16);
+            records.push(ihex::Record::ExtendedLinearAddress(
+                (record_address >> 16) as u16,
+            ));
+        }
+
+        // Records must not cross a 64 KiB boundary.
+        let size = HEX_RECORD_SIZE
+            .min(0x1_0000 - (record_address & 0xffff))
+            .min((data.len() - offset) as u32) as usize;
+        records.push(ihex::Record::Data {
+            offset: record_address as u16,
+            value: data[offset..offset + size].to_vec(),
+        });
+        offset += size;
+    }
+    records.push(ihex::Record::EndOfFile);
+
+    ihex::create_object_file_representation(&records)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Reads a memory where each byte contains its address, with a hole at 0x106..0x10b.
+    fn read_memory(address: u32, data: &mut [u8]) -> Result<(), error::Error> {
+        let end = address + data.len() as u32;
+        if address < 0x10b && end > 0x106 {
+            return Err(error::Error::NotSupported("reading a hole"));
+        }
+
+        for (offset, byte) in data.iter_mut().enumerate() {
+            *byte = (address as usize + offset) as u8;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn holes_are_filled() {
+        let (data, holes) = read_region(read_memory, 0x102..0x110, Some(0xee)).unwrap();
+
+        // Holes are found with word accesses.
+        assert_eq!(holes, vec![0x104..0x10c]);
+        assert_eq!(&data[..2], &[0x02, 0x03]);
+        assert_eq!(&data[2..10], &[0xee; 8]);
+        assert_eq!(&data[10..], &[0x0c, 0x0d, 0x0e, 0x0f]);
+    }
+
+    #[test]
+    fn holes_are_reported_without_fill() {
+        let error = read_region(read_memory, 0x100..0x110, None).unwrap_err();
+
+        assert!(matches!(
+            error,
+            DumpError::Inaccessible { address: 0x104, .. }
+        ));
+    }
+
+    #[test]
+    fn hex_records_do_not_cross_64k_boundaries() {
+        let hex = hex_records(0x0800_fffc, &[1, 2, 3, 4, 5, 6]).unwrap();
+
+        assert_eq!(
+            hex,
+            ":020000040800F2\n\
+             :04FFFC0001020304F7\n\
+             :020000040801F1\n\
+             :020000000506F3\n\
+             :00000001FF\n"
+        );
+    }
+}
@@ -6,44 +6,235 @@ use crate::{
 
 use anyhow::Result;
 
+mod cache;
+mod dump;
+mod target_memory;
+mod value;
+mod watch;
+
+pub(crate) use cache::MemoryCache;
+pub(crate) use dump::{read_region, write_dump};
+pub use dump::{DumpError, DumpFormat, DumpOptions, DumpSummary};
+pub use target_memory::{TargetMemory, TargetMemoryError};
+pub use value::AccessWidth;
+pub(crate) use value::{is_architecture_register, read_values, resolve_address, write_value};
+pub use watch::{ValueChange, ValueWatch};
+
+/// The largest gap in bytes between two addresses that are still fetched with a single
+/// block read by [`MemoryInterface::read_32_batched`].
+///
+/// Reading a few unused words is a lot cheaper than the round trip of an additional transfer.
+const MAX_BATCH_GAP: u64 = 64;
+
+/// The number of bytes written with a single block write by [`MemoryInterface::fill_32`]
+/// and [`MemoryInterface::fill_8`].
+const FILL_CHUNK_SIZE: usize = 4096;
+
+/// Returns the range of the whole 32bit words containing the `len` bytes at `address`.
+fn aligned_words(address: u64, len: usize) -> std::ops::Range<u64> {
+    let start = address & !3;
+    let end = (address + len as u64 + 3) & !3;
+
+    start..end
+}
+
 pub trait MemoryInterface {
     /// Read a 32bit word of at `address`.
     ///
     /// The address where the read should be performed at has to be word aligned.
     /// Returns `AccessPortError::MemoryNotAligned` if this does not hold true.
-    fn read_word_32(&mut self, address: u32) -> Result<u32, error::Error>;
+    fn read_word_32(&mut self, address: u64) -> Result<u32, error::Error>;
 
-    /// Read an 8bit word of at `address`.
-    fn read_word_8(&mut self, address: u32) -> Result<u8, error::Error>;
+    /// Read an 8bit word of at `address`, with a single 8 bit access.
+    ///
+    /// See [`MemoryInterface::read_8`] for the supported probes.
+    fn read_word_8(&mut self, address: u64) -> Result<u8, error::Error>;
 
     /// Read a block of 32bit words at `address`.
     ///
     /// The number of words read is `data.len()`.
     /// The address where the read should be performed at has to be word aligned.
     /// Returns `AccessPortError::MemoryNotAligned` if this does not hold true.
-    fn read_32(&mut self, address: u32, data: &mut [u32]) -> Result<(), error::Error>;
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), error::Error>;
 
-    /// Read a block of 8bit words at `address`.
-    fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), error::Error>;
+    /// Read a block of 8bit words at `address`, with one 8 bit access per byte.
+    ///
+    /// The accesses are never widened to 32 bits, so this is the right choice for peripheral
+    /// registers where the access size matters. To read memory like RAM or flash, use
+    /// [`MemoryInterface::read`], which is a lot faster.
+    ///
+    /// All probes do 8 bit accesses with the MEM-AP of Arm targets. The ST-Link cannot read a single
+    /// byte without also reading the byte next to it, so reading one byte with it returns
+    /// `DebugProbeError::NotSupported`, the same as a read with a single byte on one side of a
+    /// 1 KB boundary. If the MEM-AP only supports 32 bit accesses,
+    /// `AccessPortError::UnsupportedDataSize` is returned instead.
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), error::Error>;
+
+    /// Read a block of bytes of memory at `address`, as fast as possible.
+    ///
+    /// The memory is read with 32 bit accesses, including the whole words at an unaligned start
+    /// or end of the block. This must not be used for peripheral registers, see [`MemoryInterface::read_8`].
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), error::Error> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let words = aligned_words(address, data.len());
+        let mut buffer = vec![0u32; (words.end - words.start) as usize / 4];
+        self.read_32(words.start, &mut buffer)?;
+
+        let bytes: Vec<u8> = buffer.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let start = (address - words.start) as usize;
+        data.copy_from_slice(&bytes[start..start + data.len()]);
+
+        Ok(())
+    }
+
+    /// Read the 32bit words at a list of arbitrary `addresses`.
+    ///
+    /// Addresses which are close to each other are combined into a single block read,
+    /// isolated addresses are read on their own. Each address is read only once, even if it
+    /// is contained in the list multiple times.
+    ///
+    /// The values are returned in the order of `addresses`.
+    /// All addresses have to be word aligned, the same as for [`MemoryInterface::read_word_32`].
+    fn read_32_batched(&mut self, addresses: &[u64]) -> Result<Vec<u32>, error::Error> {
+        let mut sorted = addresses.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+
+        let mut values = std::collections::HashMap::with_capacity(sorted.len());
+
+        let mut remaining = &sorted[..];
+        while let Some(&start) = remaining.first() {
+            // Unaligned addresses are passed on as they are, so they fail like a single read would.
+            let mut count = 1;
+            if start % 4 == 0 {
+                while let Some(&next) = remaining.get(count) {
+                    if next % 4 != 0 || next - remaining[count - 1] > MAX_BATCH_GAP {
+                        break;
+                    }
+                    count += 1;
+                }
+            }
+
+            let (batch, rest) = remaining.split_at(count);
+            remaining = rest;
+
+            if let [address] = batch {
+                values.insert(*address, self.read_word_32(*address)?);
+                continue;
+            }
+
+            let end = batch[batch.len() - 1];
+            let mut data = vec![0u32; ((end - start) / 4 + 1) as usize];
+            self.read_32(start, &mut data)?;
+
+            for address in batch {
+                values.insert(*address, data[((address - start) / 4) as usize]);
+            }
+        }
+
+        Ok(addresses.iter().map(|address| values[address]).collect())
+    }
 
     /// Write a 32bit word at `address`.
     ///
     /// The address where the write should be performed at has to be word aligned.
     /// Returns `AccessPortError::MemoryNotAligned` if this does not hold true.
-    fn write_word_32(&mut self, address: u32, data: u32) -> Result<(), error::Error>;
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), error::Error>;
 
-    /// Write an 8bit word at `address`.
-    fn write_word_8(&mut self, address: u32, data: u8) -> Result<(), error::Error>;
+    /// Write an 8bit word at `address`, with a single 8 bit access.
+    ///
+    /// See [`MemoryInterface::read_8`] for the supported probes.
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), error::Error>;
 
     /// Write a block of 32bit words at `address`.
     ///
     /// The number of words written is `data.len()`.
     /// The address where the write should be performed at has to be word aligned.
     /// Returns `AccessPortError::MemoryNotAligned` if this does not hold true.
-    fn write_32(&mut self, address: u32, data: &[u32]) -> Result<(), error::Error>;
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), error::Error>;
+
+    /// Write a block of 8bit words at `address`, with one 8 bit access per byte.
+    ///
+    /// The neighbouring bytes are never rewritten, so this is the right choice for peripheral
+    /// registers. To write memory like RAM, use [`MemoryInterface::write`], which is a lot faster.
+    /// See [`MemoryInterface::read_8`] for the supported probes.
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), error::Error>;
+
+    /// Write a block of bytes of memory at `address`, as fast as possible.
+    ///
+    /// The memory is written with 32 bit accesses. The bytes around an unaligned start or end
+    /// of the block are read first and written back unchanged. This must not be used for
+    /// peripheral registers, see [`MemoryInterface::write_8`].
+    fn write(&mut self, address: u64, data: &[u8]) -> Result<(), error::Error> {
+        if data.is_empty() {
+            return Ok(());
+        }
 
-    /// Write a block of 8bit words at `address`.
-    fn write_8(&mut self, address: u32, data: &[u8]) -> Result<(), error::Error>;
+        let words = aligned_words(address, data.len());
+        let mut bytes = vec![0u8; (words.end - words.start) as usize];
+
+        if address != words.start {
+            let first = self.read_word_32(words.start)?;
+            bytes[..4].copy_from_slice(&first.to_le_bytes());
+        }
+        if address + data.len() as u64 != words.end {
+            let last = self.read_word_32(words.end - 4)?;
+            let len = bytes.len();
+            bytes[len - 4..].copy_from_slice(&last.to_le_bytes());
+        }
+
+        let start = (address - words.start) as usize;
+        bytes[start..start + data.len()].copy_from_slice(data);
+
+        let buffer: Vec<u32> = bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        self.write_32(words.start, &buffer)
+    }
+
+    /// Fill `count` 32bit words starting at `address` with `value`.
+    ///
+    /// The words are written in chunks with block writes, so the whole region never has
+    /// to be held in memory. The address has to be word aligned, the same as for
+    /// [`MemoryInterface::write_32`].
+    fn fill_32(&mut self, address: u64, value: u32, count: usize) -> Result<(), error::Error> {
+        let chunk = vec![value; FILL_CHUNK_SIZE / 4];
+
+        let mut address = address;
+        let mut remaining = count;
+        while remaining > 0 {
+            let words = remaining.min(chunk.len());
+            self.write_32(address, &chunk[..words])?;
+
+            address = address.wrapping_add(4 * words as u64);
+            remaining -= words;
+        }
+
+        Ok(())
+    }
+
+    /// Fill `count` bytes starting at `address` with `value`.
+    ///
+    /// The bytes are written in chunks with block writes, the same as for [`MemoryInterface::fill_32`].
+    fn fill_8(&mut self, address: u64, value: u8, count: usize) -> Result<(), error::Error> {
+        let chunk = vec![value; FILL_CHUNK_SIZE];
+
+        let mut address = address;
+        let mut remaining = count;
+        while remaining > 0 {
+            let bytes = remaining.min(chunk.len());
+            self.write_8(address, &chunk[..bytes])?;
+
+            address = address.wrapping_add(bytes as u64);
+            remaining -= bytes;
+        }
+
+        Ok(())
+    }
 
     /// Flush any outstanding operations.
     ///
@@ -58,38 +249,46 @@ impl<T> MemoryInterface for &mut T
 where
     T: MemoryInterface,
 {
-    fn read_word_32(&mut self, address: u32) -> Result<u32, error::Error> {
+    fn read_word_32(&mut self, address: u64) -> Result<u32, error::Error> {
         (*self).read_word_32(address)
     }
 
-    fn read_word_8(&mut self, address: u32) -> Result<u8, error::Error> {
+    fn read_word_8(&mut self, address: u64) -> Result<u8, error::Error> {
         (*self).read_word_8(address)
     }
 
-    fn read_32(&mut self, address: u32, data: &mut [u32]) -> Result<(), error::Error> {
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), error::Error> {
         (*self).read_32(address, data)
     }
 
-    fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), error::Error> {
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), error::Error> {
         (*self).read_8(address, data)
     }
 
-    fn write_word_32(&mut self, addr: u32, data: u32) -> Result<(), error::Error> {
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), error::Error> {
+        (*self).read(address, data)
+    }
+
+    fn write_word_32(&mut self, addr: u64, data: u32) -> Result<(), error::Error> {
         (*self).write_word_32(addr, data)
     }
 
-    fn write_word_8(&mut self, addr: u32, data: u8) -> Result<(), error::Error> {
+    fn write_word_8(&mut self, addr: u64, data: u8) -> Result<(), error::Error> {
         (*self).write_word_8(addr, data)
     }
 
-    fn write_32(&mut self, addr: u32, data: &[u32]) -> Result<(), error::Error> {
+    fn write_32(&mut self, addr: u64, data: &[u32]) -> Result<(), error::Error> {
         (*self).write_32(addr, data)
     }
 
-    fn write_8(&mut self, addr: u32, data: &[u8]) -> Result<(), error::Error> {
+    fn write_8(&mut self, addr: u64, data: &[u8]) -> Result<(), error::Error> {
         (*self).write_8(addr, data)
     }
 
+    fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), error::Error> {
+        (*self).write(addr, data)
+    }
+
     fn flush(&mut self) -> Result<(), error::Error> {
         (*self).flush()
     }
@@ -108,41 +307,41 @@ impl<'probe> Memory<'probe> {
         }
     }
 
-    pub fn read_word_32(&mut self, address: u32) -> Result<u32, error::Error> {
+    pub fn read_word_32(&mut self, address: u64) -> Result<u32, error::Error> {
         let mut buff = [0];
         self.inner.read_32(self.ap_sel, address, &mut buff)?;
 
         Ok(buff[0])
     }
 
-    pub fn read_word_8(&mut self, address: u32) -> Result<u8, error::Error> {
+    pub fn read_word_8(&mut self, address: u64) -> Result<u8, error::Error> {
         let mut buff = [0];
         self.inner.read_8(self.ap_sel, address, &mut buff)?;
 
         Ok(buff[0])
     }
 
-    pub fn read_32(&mut self, address: u32, data: &mut [u32]) -> Result<(), error::Error> {
+    pub fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), error::Error> {
         self.inner.read_32(self.ap_sel, address, data)
     }
 
-    pub fn read_8(&mut self, address: u32, data: &mut [u8]) -> Result<(), error::Error> {
+    pub fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), error::Error> {
         self.inner.read_8(self.ap_sel, address, data)
     }
 
-    pub fn write_word_32(&mut self, addr: u32, data: u32) -> Result<(), error::Error> {
+    pub fn write_word_32(&mut self, addr: u64, data: u32) -> Result<(), error::Error> {
         self.inner.write_32(self.ap_sel, addr, &[data])
     }
 
-    pub fn write_word_8(&mut self, addr: u32, data: u8) -> Result<(), error::Error> {
+    pub fn write_word_8(&mut self, addr: u64, data: u8) -> Result<(), error::Error> {
         self.inner.write_8(self.ap_sel, addr, &[data])
     }
 
-    pub fn write_32(&mut self, addr: u32, data: &[u32]) -> Result<(), error::Error> {
+    pub fn write_32(&mut self, addr: u64, data: &[u32]) -> Result<(), error::Error> {
         self.inner.write_32(self.ap_sel, addr, data)
     }
 
-    pub fn write_8(&mut self, addr: u32, data: &[u8]) -> Result<(), error::Error> {
+    pub fn write_8(&mut self, addr: u64, data: &[u8]) -> Result<(), error::Error> {
         self.inner.write_8(self.ap_sel, addr, data)
     }
 
@@ -150,6 +349,11 @@ impl<'probe> Memory<'probe> {
         self.inner.flush()
     }
 
+    /// Causes some harmless traffic on the debug interface, see `ArmProbe::keep_alive`.
+    pub fn keep_alive(&mut self) -> Result<(), error::Error> {
+        self.inner.keep_alive()
+    }
+
     pub fn read_core_reg(&mut self, addr: CoreRegisterAddress) -> Result<u32, error::Error> {
         self.inner.read_core_reg(self.ap_sel, addr)
     }
@@ -177,3 +381,192 @@ impl<'probe> std::ops::Deref for MemoryList<'probe> {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A memory which records the reads performed on it.
+    struct RecordingMemory {
+        block_reads: Vec<(u64, usize)>,
+        word_reads: Vec<u64>,
+        block_writes: Vec<(u64, Vec<u32>)>,
+        byte_writes: Vec<(u64, Vec<u8>)>,
+    }
+
+    impl RecordingMemory {
+        fn new() -> Self {
+            Self {
+                block_reads: vec![],
+                word_reads: vec![],
+                block_writes: vec![],
+                byte_writes: vec![],
+            }
+        }
+    }
+
+    impl MemoryInterface for RecordingMemory {
+        fn read_word_32(&mut self, address: u64) -> Result<u32, error::Error> {
+            if address % 4 != 0 {
+                return Err(anyhow::anyhow!("unaligned").into());
+            }
+            self.word_reads.push(address);
+            Ok(address as u32)
+        }
+
+        fn read_word_8(&mut self, _address: u64) -> Result<u8, error::Error> {
+            unimplemented!()
+        }
+
+        fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), error::Error> {
+            self.block_reads.push((address, data.len()));
+            for (i, word) in data.iter_mut().enumerate() {
+                *word = address as u32 + 4 * i as u32;
+            }
+            Ok(())
+        }
+
+        fn read_8(&mut self, _address: u64, _data: &mut [u8]) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn write_word_32(&mut self, _address: u64, _data: u32) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn write_word_8(&mut self, _address: u64, _data: u8) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), error::Error> {
+            if address % 4 != 0 {
+                return Err(anyhow::anyhow!("unaligned").into());
+            }
+            self.block_writes.push((address, data.to_vec()));
+            Ok(())
+        }
+
+        fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), error::Error> {
+            self.byte_writes.push((address, data.to_vec()));
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), error::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn batched_read_coalesces_nearby_addresses() {
+        let mut memory = RecordingMemory::new();
+
+        let addresses = [0x2000_0010, 0x4000_0000, 0x2000_0000, 0x2000_0008];
+        let values = memory.read_32_batched(&addresses).unwrap();
+
+        assert_eq!(
+            values,
+            addresses.iter().map(|a| *a as u32).collect::<Vec<_>>()
+        );
+        assert_eq!(memory.block_reads, vec![(0x2000_0000, 5)]);
+        assert_eq!(memory.word_reads, vec![0x4000_0000]);
+    }
+
+    #[test]
+    fn batched_read_splits_large_gaps() {
+        let mut memory = RecordingMemory::new();
+
+        let addresses = [0x2000_0000, 0x2000_0004, 0x2000_1000, 0x2000_1004];
+        let values = memory.read_32_batched(&addresses).unwrap();
+
+        assert_eq!(
+            values,
+            addresses.iter().map(|a| *a as u32).collect::<Vec<_>>()
+        );
+        assert_eq!(memory.block_reads, vec![(0x2000_0000, 2), (0x2000_1000, 2)]);
+        assert!(memory.word_reads.is_empty());
+    }
+
+    #[test]
+    fn batched_read_duplicates_are_read_once() {
+        let mut memory = RecordingMemory::new();
+
+        let addresses = [0x1000, 0x1000, 0x1000];
+        let values = memory.read_32_batched(&addresses).unwrap();
+
+        assert_eq!(
+            values,
+            addresses.iter().map(|a| *a as u32).collect::<Vec<_>>()
+        );
+        assert_eq!(memory.word_reads, vec![0x1000]);
+    }
+
+    #[test]
+    fn batched_read_unaligned_address_fails() {
+        let mut memory = RecordingMemory::new();
+
+        assert!(memory.read_32_batched(&[0x1000, 0x1002]).is_err());
+    }
+
+    #[test]
+    fn unaligned_memory_is_accessed_with_words() {
+        let mut memory = RecordingMemory::new();
+
+        let mut data = [0; 6];
+        memory.read(0x1001, &mut data).unwrap();
+        assert_eq!(data, [0x10, 0x00, 0x00, 0x04, 0x10, 0x00]);
+        assert_eq!(memory.block_reads, vec![(0x1000, 2)]);
+
+        // The bytes around the written ones keep their values.
+        memory.write(0x1002, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(memory.word_reads, vec![0x1000, 0x1004]);
+        assert_eq!(
+            memory.block_writes,
+            vec![(0x1000, vec![0x0201_1000, 0x0000_0403])]
+        );
+        assert!(memory.byte_writes.is_empty());
+    }
+
+    #[test]
+    fn fill_32_is_written_in_chunks() {
+        let mut memory = RecordingMemory::new();
+
+        memory.fill_32(0x2000_0000, 0xdead_beef, 2500).unwrap();
+
+        let chunks: Vec<_> = memory
+            .block_writes
+            .iter()
+            .map(|(address, data)| (*address, data.len()))
+            .collect();
+        assert_eq!(
+            chunks,
+            vec![(0x2000_0000, 1024), (0x2000_1000, 1024), (0x2000_2000, 452)]
+        );
+        assert!(memory
+            .block_writes
+            .iter()
+            .all(|(_, data)| data.iter().all(|word| *word == 0xdead_beef)));
+    }
+
+    #[test]
+    fn fill_8_is_written_in_chunks() {
+        let mut memory = RecordingMemory::new();
+
+        memory.fill_8(0x2000_0001, 0xaa, 5000).unwrap();
+
+        let chunks: Vec<_> = memory
+            .byte_writes
+            .iter()
+            .map(|(address, data)| (*address, data.len()))
+            .collect();
+        assert_eq!(chunks, vec![(0x2000_0001, 4096), (0x2000_1001, 904)]);
+    }
+
+    #[test]
+    fn fill_32_unaligned_address_fails() {
+        let mut memory = RecordingMemory::new();
+
+        assert!(memory.fill_32(0x2000_0002, 0, 4).is_err());
+        memory.fill_32(0x2000_0000, 0, 0).unwrap();
+        assert!(memory.block_writes.is_empty());
+    }
+}
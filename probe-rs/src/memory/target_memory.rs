@@ -0,0 +1,262 @@
+//! A lazily read view of the memory of a halted target.
+
+use crate::{error, ReadOptions, Session};
+use std::{borrow::Cow, collections::BTreeMap, ops::Range};
+use thiserror::Error;
+
+/// The size of the pages in which the memory is read and kept.
+const PAGE_SIZE: u32 = 1024;
+
+/// Describes any error that happened while accessing a `TargetMemory`.
+#[derive(Debug, Error)]
+pub enum TargetMemoryError {
+    #[error("The memory at {address:#010x} is neither RAM nor flash of the target")]
+    Unmapped { address: u32 },
+    #[error("The core is running, the memory can only be accessed while it is halted")]
+    Running,
+    #[error("Reading the memory at {address:#010x} failed")]
+    Read {
+        address: u32,
+        #[source]
+        source: error::Error,
+    },
+    #[error("Accessing the core failed")]
+    Core(#[from] error::Error),
+}
+
+/// The memory of a halted core, which is read when it is first accessed and kept afterwards.
+///
+/// This allows tools like a disassembler or a DWARF evaluator to access the target memory
+/// as if it was a slice, without reading all of it in advance. The memory is read in pages of
+/// 1 KiB. Only RAM and flash regions of the memory map can be accessed, as reading peripherals
+/// in whole pages might have side effects.
+///
+/// All pages are dropped whenever the memory might have changed, like the read cache of the session:
+/// when the core is resumed, reset or stepped, or the memory is written through the session.
+#[derive(Debug)]
+pub struct TargetMemory {
+    core_index: usize,
+    regions: Vec<Range<u32>>,
+    pages: BTreeMap<u32, Page>,
+    /// The generation of the memory cache of the session the pages were read in.
+    generation: Option<u64>,
+}
+
+impl TargetMemory {
+    /// Creates a view of the memory of core `core_index` of `session`.
+    ///
+    /// The memory is only read once it is accessed with `get`.
+    pub fn new(session: &Session, core_index: usize) -> Self {
+        Self::with_regions(
+            crate::session::cacheable_regions(session.memory_map()),
+            core_index,
+        )
+    }
+
+    fn with_regions(regions: Vec<Range<u32>>, core_index: usize) -> Self {
+        Self {
+            core_index,
+            regions,
+            pages: BTreeMap::new(),
+            generation: None,
+        }
+    }
+
+    /// Returns the memory in `range`, reading the pages which were not read yet.
+    ///
+    /// The memory is borrowed if it is inside of a single page, and copied otherwise.
+    pub fn get(
+        &mut self,
+        session: &mut Session,
+        range: Range<u32>,
+    ) -> Result<Cow<'_, [u8]>, TargetMemoryError> {
+        let generation = match session.memory_generation() {
+            Some(generation) => generation,
+            // The state of the core is not known, which updates the generation once it is read.
+            None => {
+                if !session.core(self.core_index)?.core_halted()? {
+                    return Err(TargetMemoryError::Running);
+                }
+                session
+                    .memory_generation()
+                    .ok_or(TargetMemoryError::Running)?
+            }
+        };
+
+        let core_index = self.core_index;
+        self.get_with(generation, range, |address, data| {
+            session.read_memory(core_index, address, data, ReadOptions::default())
+        })
+    }
+
+    /// Drops all pages, so they are read again on the next access.
+    pub fn invalidate(&mut self) {
+        self.pages.clear();
+    }
+
+    /// Returns the memory in `range`, reading missing pages with `read`.
+    fn get_with(
+        &mut self,
+        generation: u64,
+        range: Range<u32>,
+        mut read: impl FnMut(u32, &mut [u8]) -> Result<(), error::Error>,
+    ) -> Result<Cow<'_, [u8]>, TargetMemoryError> {
+        if self.generation != Some(generation) {
+            self.pages.clear();
+            self.generation = Some(generation);
+        }
+
+        if range.is_empty() {
+            return Ok(Cow::Borrowed(&[]));
+        }
+
+        let first_page = range.start & !(PAGE_SIZE - 1);
+        let pages: Vec<u32> = (first_page..range.end)
+            .step_by(PAGE_SIZE as usize)
+            .collect();
+
+        for address in &pages {
+            if !self.pages.contains_key(address) {
+                let page = self.read_page(*address, range.start, &mut read)?;
+                self.pages.insert(*address, page);
+            }
+
+            // The accessed part of the page has to be inside of the region it was read from.
+            let page = &self.pages[address];
+            let start = range.start.max(*address);
+            let end = range.end.min(address.saturating_add(PAGE_SIZE));
+            if start < page.start {
+                return Err(TargetMemoryError::Unmapped { address: start });
+            }
+            if end > page.end() {
+                return Err(TargetMemoryError::Unmapped {
+                    address: page.end(),
+                });
+            }
+        }
+
+        if let [address] = pages[..] {
+            return Ok(Cow::Borrowed(self.pages[&address].slice(range)));
+        }
+
+        let mut data = Vec::with_capacity((range.end - range.start) as usize);
+        for address in &pages {
+            let end = range.end.min(address.saturating_add(PAGE_SIZE));
+            data.extend_from_slice(self.pages[address].slice(range.start.max(*address)..end));
+        }
+        Ok(Cow::Owned(data))
+    }
+
+    /// Reads the page at `address`, clipped to the region containing `accessed`, its first accessed byte.
+    fn read_page(
+        &self,
+        address: u32,
+        accessed: u32,
+        read: &mut impl FnMut(u32, &mut [u8]) -> Result<(), error::Error>,
+    ) -> Result<Page, TargetMemoryError> {
+        let accessed = accessed.max(address);
+        let region = self
+            .regions
+            .iter()
+            .find(|region| region.contains(&accessed))
+            .ok_or(TargetMemoryError::Unmapped { address: accessed })?;
+
+        let start = address.max(region.start);
+        let end = address.saturating_add(PAGE_SIZE).min(region.end);
+        let mut data = vec![0; (end - start) as usize];
+        read(start, &mut data).map_err(|source| TargetMemoryError::Read {
+            address: start,
+            source,
+        })?;
+
+        Ok(Page { start, data })
+    }
+}
+
+/// The memory of one page, which only covers a part of the page at the boundaries of a region.
+#[derive(Debug)]
+struct Page {
+    start: u32,
+    data: Vec<u8>,
+}
+
+impl Page {
+    fn end(&self) -> u32 {
+        self.start + self.data.len() as u32
+    }
+
+    /// Returns the bytes in `range`, which has to be inside of the page.
+    fn slice(&self, range: Range<u32>) -> &[u8] {
+        &self.data[(range.start - self.start) as usize..(range.end - self.start) as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Reads a memory where each byte contains its address, and counts the reads.
+    fn memory(reads: &mut Vec<u32>) -> impl FnMut(u32, &mut [u8]) -> Result<(), error::Error> + '_ {
+        move |address, data| {
+            reads.push(address);
+            for (offset, byte) in data.iter_mut().enumerate() {
+                *byte = (address as usize + offset) as u8;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pages_are_read_once_per_generation() {
+        let mut memory =
+            TargetMemory::with_regions(vec![0x0800_0000..0x0810_0000, 0x2000_0000..0x2000_1000], 0);
+        let mut reads = Vec::new();
+
+        let data = memory
+            .get_with(1, 0x2000_0010..0x2000_0014, self::memory(&mut reads))
+            .unwrap();
+        assert!(matches!(data, Cow::Borrowed(&[0x10, 0x11, 0x12, 0x13])));
+
+        // Crossing into the next page only reads that page.
+        let data = memory
+            .get_with(1, 0x2000_03fe..0x2000_0402, self::memory(&mut reads))
+            .unwrap();
+        assert_eq!(&data[..], &[0xfe, 0xff, 0x00, 0x01]);
+        assert_eq!(reads, vec![0x2000_0000, 0x2000_0400]);
+
+        // The core was resumed in between.
+        memory
+            .get_with(2, 0x2000_0010..0x2000_0014, self::memory(&mut reads))
+            .unwrap();
+        assert_eq!(reads, vec![0x2000_0000, 0x2000_0400, 0x2000_0000]);
+    }
+
+    #[test]
+    fn unmapped_memory_is_reported() {
+        let mut memory =
+            TargetMemory::with_regions(vec![0x0800_0000..0x0810_0000, 0x2000_0000..0x2000_0100], 0);
+        let mut reads = Vec::new();
+
+        let error = memory
+            .get_with(1, 0x4000_0000..0x4000_0004, self::memory(&mut reads))
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            TargetMemoryError::Unmapped {
+                address: 0x4000_0000
+            }
+        ));
+
+        // The page is clipped to the region, and the access runs past its end.
+        let error = memory
+            .get_with(1, 0x2000_00fc..0x2000_0104, self::memory(&mut reads))
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            TargetMemoryError::Unmapped {
+                address: 0x2000_0100
+            }
+        ));
+        assert_eq!(reads, vec![0x2000_0000]);
+    }
+}
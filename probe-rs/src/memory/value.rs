@@ -0,0 +1,201 @@
+use super::MemoryInterface;
+use crate::{error, Architecture};
+use anyhow::anyhow;
+
+/// The width of the values read and written with `Session::read_values` and `Session::write_value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessWidth {
+    Bits8,
+    Bits16,
+    Bits32,
+    Bits64,
+}
+
+impl AccessWidth {
+    /// Returns the width with `bits` bits, if it is one of 8, 16, 32 or 64.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            8 => Some(AccessWidth::Bits8),
+            16 => Some(AccessWidth::Bits16),
+            32 => Some(AccessWidth::Bits32),
+            64 => Some(AccessWidth::Bits64),
+            _ => None,
+        }
+    }
+
+    /// Returns the size of a value in bytes.
+    pub fn bytes(self) -> usize {
+        match self {
+            AccessWidth::Bits8 => 1,
+            AccessWidth::Bits16 => 2,
+            AccessWidth::Bits32 => 4,
+            AccessWidth::Bits64 => 8,
+        }
+    }
+
+    fn max_value(self) -> u64 {
+        match self {
+            AccessWidth::Bits64 => u64::MAX,
+            width => (1 << (width.bytes() * 8)) - 1,
+        }
+    }
+}
+
+/// The system control and debug registers defined by the ARMv6-M and ARMv7-M architectures.
+const ARM_SYSTEM_REGISTERS: &[(&str, u32)] = &[
+    ("ACTLR", 0xe000_e008),
+    ("SYST_CSR", 0xe000_e010),
+    ("SYST_RVR", 0xe000_e014),
+    ("SYST_CVR", 0xe000_e018),
+    ("CPUID", 0xe000_ed00),
+    ("ICSR", 0xe000_ed04),
+    ("VTOR", 0xe000_ed08),
+    ("AIRCR", 0xe000_ed0c),
+    ("SCR", 0xe000_ed10),
+    ("CCR", 0xe000_ed14),
+    ("SHCSR", 0xe000_ed24),
+    ("CFSR", 0xe000_ed28),
+    ("HFSR", 0xe000_ed2c),
+    ("DFSR", 0xe000_ed30),
+    ("MMFAR", 0xe000_ed34),
+    ("BFAR", 0xe000_ed38),
+    ("CPACR", 0xe000_ed88),
+    ("DHCSR", 0xe000_edf0),
+    ("DCRSR", 0xe000_edf4),
+    ("DCRDR", 0xe000_edf8),
+    ("DEMCR", 0xe000_edfc),
+];
+
+/// The private peripheral bus of ARM cores, which is not part of the memory map of the targets.
+const ARM_PPB_START: u32 = 0xe000_0000;
+const ARM_PPB_END: u32 = 0xe010_0000;
+
+/// Resolves `location` to an address.
+///
+/// `location` is either a hexadecimal address, with or without a `0x` prefix,
+/// or the name of a register defined by the architecture, like `DHCSR`.
+pub(crate) fn resolve_address(architecture: Architecture, location: &str) -> Option<u32> {
+    if architecture == Architecture::Arm {
+        let register = ARM_SYSTEM_REGISTERS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(location));
+        if let Some((_, address)) = register {
+            return Some(*address);
+        }
+    }
+
+    let digits = location
+        .strip_prefix("0x")
+        .or_else(|| location.strip_prefix("0X"))
+        .unwrap_or(location)
+        .replace('_', "");
+
+    u32::from_str_radix(&digits, 16).ok()
+}
+
+/// Returns `true` if `address` is always accessible on `architecture`, even if it is not in the memory map.
+pub(crate) fn is_architecture_register(architecture: Architecture, address: u32) -> bool {
+    architecture == Architecture::Arm && (ARM_PPB_START..ARM_PPB_END).contains(&address)
+}
+
+/// Reads `count` values of `width` at `address`.
+///
+/// 8 and 16 bit values are read as bytes of whole words, the `MemoryInterface` has no 16 bit accesses.
+pub(crate) fn read_values(
+    memory: &mut impl MemoryInterface,
+    address: u32,
+    width: AccessWidth,
+    count: usize,
+) -> Result<Vec<u64>, error::Error> {
+    match width {
+        AccessWidth::Bits8 | AccessWidth::Bits16 => {
+            let mut bytes = vec![0; count * width.bytes()];
+            memory.read(address.into(), &mut bytes)?;
+
+            Ok(bytes
+                .chunks(width.bytes())
+                .map(|value| {
+                    value
+                        .iter()
+                        .rev()
+                        .fold(0, |value, byte| value << 8 | u64::from(*byte))
+                })
+                .collect())
+        }
+        AccessWidth::Bits32 | AccessWidth::Bits64 => {
+            let mut words = vec![0; count * width.bytes() / 4];
+            memory.read_32(address.into(), &mut words)?;
+
+            Ok(words
+                .chunks(width.bytes() / 4)
+                .map(|value| {
+                    value
+                        .iter()
+                        .rev()
+                        .fold(0, |value, word| value << 32 | u64::from(*word))
+                })
+                .collect())
+        }
+    }
+}
+
+/// Writes `value` with `width` to `address`.
+///
+/// 16 bit values are written as two bytes of a whole word, 64 bit values as two words starting with the lower one.
+pub(crate) fn write_value(
+    memory: &mut impl MemoryInterface,
+    address: u32,
+    width: AccessWidth,
+    value: u64,
+) -> Result<(), error::Error> {
+    if value > width.max_value() {
+        return Err(anyhow!(
+            "The value {:#x} does not fit into {} bits",
+            value,
+            width.bytes() * 8
+        )
+        .into());
+    }
+
+    match width {
+        AccessWidth::Bits8 => memory.write_word_8(address.into(), value as u8),
+        AccessWidth::Bits16 => memory.write(address.into(), &(value as u16).to_le_bytes()),
+        AccessWidth::Bits32 => memory.write_word_32(address.into(), value as u32),
+        AccessWidth::Bits64 => {
+            memory.write_32(address.into(), &[value as u32, (value >> 32) as u32])
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn addresses_and_names_are_resolved() {
+        assert_eq!(
+            resolve_address(Architecture::Arm, "dhcsr"),
+            Some(0xe000_edf0)
+        );
+        assert_eq!(
+            resolve_address(Architecture::Arm, "0x2000_0000"),
+            Some(0x2000_0000)
+        );
+        assert_eq!(
+            resolve_address(Architecture::Riscv, "20000010"),
+            Some(0x2000_0010)
+        );
+        // Register names are only known for ARM.
+        assert_eq!(resolve_address(Architecture::Riscv, "DHCSR"), None);
+        assert_eq!(resolve_address(Architecture::Arm, "UNKNOWN"), None);
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!(AccessWidth::from_bits(16), Some(AccessWidth::Bits16));
+        assert_eq!(AccessWidth::from_bits(24), None);
+        assert_eq!(AccessWidth::Bits8.max_value(), 0xff);
+        assert_eq!(AccessWidth::Bits32.max_value(), 0xffff_ffff);
+        assert_eq!(AccessWidth::Bits64.max_value(), u64::MAX);
+    }
+}
@@ -0,0 +1,156 @@
+//! Polling a value in memory, and reporting when it changes.
+
+use super::{read_values, AccessWidth};
+use crate::{error, Session};
+use std::time::{Duration, Instant};
+
+/// How long a core may take to halt, if it has to be halted to read the watched value.
+const HALT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A new value of a watched location, see `Session::watch_value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueChange {
+    /// The value read from the target.
+    pub value: u64,
+    /// The time since the watch was started, at which the value was read.
+    pub elapsed: Duration,
+}
+
+/// Remembers the last value read, and filters out unchanged ones.
+#[derive(Debug, Default)]
+struct ChangeFilter {
+    last: Option<u64>,
+}
+
+impl ChangeFilter {
+    /// Returns `value` if it differs from the previous one, or if it is the first one.
+    fn update(&mut self, value: u64) -> Option<u64> {
+        if self.last == Some(value) {
+            return None;
+        }
+
+        self.last = Some(value);
+        Some(value)
+    }
+}
+
+/// Polls a value in the memory of the target, and yields it whenever it changes.
+///
+/// Created with `Session::watch_value`. Each call to `next` blocks until a value different
+/// from the previous one was read, the first call returns the current value. Errors are
+/// returned as they occur, and the watch continues with the next poll afterwards.
+///
+/// The value is read while the core is running, which does not disturb it on targets where
+/// the debugger can access the memory in the background, like Arm cores. On targets where
+/// such a read fails, e.g. RISC-V cores accessing memory through the program buffer, the core
+/// is halted for each read instead, and resumed right afterwards. This changes the timing of
+/// the firmware, and `halts_to_read` reports once the watch switched to it.
+pub struct ValueWatch<'session> {
+    session: &'session mut Session,
+    core_index: usize,
+    address: u32,
+    width: AccessWidth,
+    interval: Duration,
+    start: Instant,
+    next_poll: Instant,
+    filter: ChangeFilter,
+    halt_to_read: bool,
+}
+
+impl<'session> ValueWatch<'session> {
+    pub(crate) fn new(
+        session: &'session mut Session,
+        core_index: usize,
+        address: u32,
+        width: AccessWidth,
+        interval: Duration,
+    ) -> Self {
+        let start = Instant::now();
+
+        Self {
+            session,
+            core_index,
+            address,
+            width,
+            interval,
+            start,
+            next_poll: start,
+            filter: ChangeFilter::default(),
+            halt_to_read: false,
+        }
+    }
+
+    /// Whether the core is halted for every read, because reading while it runs failed.
+    pub fn halts_to_read(&self) -> bool {
+        self.halt_to_read
+    }
+
+    /// Reads the value once, falling back to halting the core if it cannot be read while running.
+    fn read(&mut self) -> Result<u64, error::Error> {
+        let mut core = self.session.core(self.core_index)?;
+
+        if !self.halt_to_read {
+            match read_values(&mut core, self.address, self.width, 1) {
+                Ok(values) => return Ok(values[0]),
+                // The read did not fail because the core is running.
+                Err(e) if core.core_halted()? => return Err(e),
+                Err(e) => {
+                    log::warn!(
+                        "Reading {:#010x} while the core is running failed ({}), halting the core for every read from now on",
+                        self.address,
+                        e
+                    );
+                    self.halt_to_read = true;
+                }
+            }
+        }
+
+        let mut core = core.halt_scoped(HALT_TIMEOUT)?;
+        Ok(read_values(&mut *core, self.address, self.width, 1)?[0])
+    }
+}
+
+impl Iterator for ValueWatch<'_> {
+    type Item = Result<ValueChange, error::Error>;
+
+    /// Blocks until the value changed, this never returns `None`.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let now = Instant::now();
+            if now < self.next_poll {
+                std::thread::sleep(self.next_poll - now);
+            }
+            // Don't try to catch up on polls which were missed because reading took too long.
+            self.next_poll = (self.next_poll + self.interval).max(Instant::now());
+
+            match self.read() {
+                Ok(value) => {
+                    if let Some(value) = self.filter.update(value) {
+                        return Some(Ok(ValueChange {
+                            value,
+                            elapsed: self.start.elapsed(),
+                        }));
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_changes_are_reported() {
+        let mut filter = ChangeFilter::default();
+
+        let reported: Vec<_> = [5, 5, 7, 7, 7, 5, 0]
+            .iter()
+            .filter_map(|value| filter.update(*value))
+            .collect();
+
+        assert_eq!(reported, vec![5, 7, 5, 0]);
+    }
+}
@@ -0,0 +1,291 @@
+//! Access to the option bytes of a target, which configure e.g. its readout protection.
+//!
+//! Option bytes are only supported for some families, use `Session::option_bytes` to access them:
+//!
+//! - STM32F2, STM32F4 and STM32F7
+//! - STM32G0, STM32G4 and STM32L4
+//! - STM32WB and STM32WL
+
+mod stm32;
+
+use self::stm32::FlashController;
+use crate::{error, Session};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Decreasing the readout protection level erases the whole flash, which can take a while.
+const PROGRAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The time the target needs to come out of the reset which loads the new option bytes.
+const RELOAD_DELAY: Duration = Duration::from_millis(100);
+
+/// Describes any error that happened while reading or writing the option bytes.
+#[derive(Error, Debug)]
+pub enum OptionBytesError {
+    #[error(
+        "Setting readout protection level 2 is irreversible, and has to be confirmed explicitly."
+    )]
+    Level2NotConfirmed,
+    #[error("The option bytes could not be unlocked. The flash controller stays locked until the next reset.")]
+    Locked,
+    #[error("Timed out waiting for the flash controller.")]
+    Timeout,
+    #[error("The option bytes were written, but the connection to the target could not be re-established")]
+    Reconnect(#[source] error::Error),
+    #[error("Something during the interaction with the core went wrong")]
+    Core(#[from] error::Error),
+}
+
+/// The readout protection level of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadoutProtection {
+    /// The memory can be read and written by the debugger.
+    Level0,
+    /// The flash cannot be accessed by the debugger. Going back to level 0 erases the whole flash.
+    Level1,
+    /// The debug interface is disabled permanently. This cannot be undone.
+    Level2,
+}
+
+impl ReadoutProtection {
+    fn from_rdp(rdp: u8) -> Self {
+        match rdp {
+            0xAA => ReadoutProtection::Level0,
+            0xCC => ReadoutProtection::Level2,
+            _ => ReadoutProtection::Level1,
+        }
+    }
+
+    fn rdp(self) -> u8 {
+        match self {
+            ReadoutProtection::Level0 => 0xAA,
+            ReadoutProtection::Level1 => 0xBB,
+            ReadoutProtection::Level2 => 0xCC,
+        }
+    }
+}
+
+/// The families with supported option bytes, which differ in the layout of the option register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionBytesFamily {
+    /// STM32F2, STM32F4 and STM32F7, where the option register is `FLASH_OPTCR`.
+    Stm32F4,
+    /// STM32G0, STM32G4 and STM32L4, where the option register is `FLASH_OPTR`.
+    Stm32L4,
+    /// STM32WB and STM32WL, where the option register is `FLASH_OPTR`.
+    Stm32Wx,
+}
+
+impl OptionBytesFamily {
+    /// Determines the family of the chip with the given name, if its option bytes are supported.
+    pub(crate) fn from_chip_name(chip_name: &str) -> Option<Self> {
+        let chip_name = chip_name.to_ascii_uppercase();
+        let prefix = |prefixes: &[&str]| prefixes.iter().any(|p| chip_name.starts_with(p));
+
+        if prefix(&["STM32F2", "STM32F4", "STM32F7"]) {
+            Some(OptionBytesFamily::Stm32F4)
+        } else if prefix(&["STM32G0", "STM32G4", "STM32L4"]) {
+            Some(OptionBytesFamily::Stm32L4)
+        } else if prefix(&["STM32WB", "STM32WL"]) {
+            Some(OptionBytesFamily::Stm32Wx)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the write protection bits in the option register, if they are supported for the family.
+    pub(crate) fn write_protection(self) -> Option<WriteProtection> {
+        match self {
+            OptionBytesFamily::Stm32F4 => Some(WriteProtection::F4),
+            OptionBytesFamily::Stm32L4 | OptionBytesFamily::Stm32Wx => None,
+        }
+    }
+
+    fn controller(self) -> FlashController {
+        match self {
+            OptionBytesFamily::Stm32F4 => FlashController::F4,
+            OptionBytesFamily::Stm32L4 => FlashController::L4,
+            OptionBytesFamily::Stm32Wx => FlashController::WX,
+        }
+    }
+}
+
+/// The write protection bits of the option register, with one bit per sector of the main flash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct WriteProtection {
+    /// The position of the bit of the first sector.
+    shift: u32,
+    /// The number of sectors covered by the bits.
+    sectors: usize,
+    /// The bit which inverts the meaning of the protection bits, if it is set.
+    inverted: Option<u32>,
+}
+
+impl WriteProtection {
+    /// `nWRP` in `FLASH_OPTCR` of STM32F2, STM32F4 and STM32F7, where a cleared bit protects the
+    /// sector. If `SPRMOD` of the STM32F42x and STM32F446 is set, a set bit protects the sector
+    /// against reading and writing instead. Only the sectors of the first bank are covered.
+    const F4: WriteProtection = WriteProtection {
+        shift: 16,
+        sectors: 12,
+        inverted: Some(1 << 31),
+    };
+
+    /// Returns whether the sector `index` is protected for the option register `options`,
+    /// or `None` if the sector is not covered by the bits.
+    pub(crate) fn is_protected(&self, options: u32, index: usize) -> Option<bool> {
+        if index >= self.sectors {
+            return None;
+        }
+
+        let bit = options & (1 << (self.shift + index as u32)) != 0;
+        let inverted = self.inverted.is_some_and(|mask| options & mask != 0);
+        Some(bit == inverted)
+    }
+}
+
+/// The option bytes of a target, as returned by `Session::option_bytes`.
+///
+/// The options are read and written as the raw value of the option register of the family,
+/// see the reference manual of the chip for its layout.
+pub struct OptionBytes<'session> {
+    session: &'session mut Session,
+    family: OptionBytesFamily,
+}
+
+impl<'session> OptionBytes<'session> {
+    pub(crate) fn new(session: &'session mut Session, family: OptionBytesFamily) -> Self {
+        Self { session, family }
+    }
+
+    /// Returns the family of the target, which determines the layout of the option register.
+    pub fn family(&self) -> OptionBytesFamily {
+        self.family
+    }
+
+    /// Reads the current value of the option register.
+    pub fn read(&mut self) -> Result<u32, OptionBytesError> {
+        let mut core = self.session.core(0)?;
+        self.family.controller().read_options(&mut core)
+    }
+
+    /// Reads the current readout protection level.
+    pub fn readout_protection(&mut self) -> Result<ReadoutProtection, OptionBytesError> {
+        let options = self.read()?;
+        Ok(ReadoutProtection::from_rdp(
+            self.family.controller().rdp(options),
+        ))
+    }
+
+    /// Programs `options` into the option register, and loads them.
+    ///
+    /// Loading the option bytes resets the target, the connection to it is re-established afterwards.
+    /// If the readout protection level is decreased from level 1 to level 0, the whole flash is erased.
+    ///
+    /// Setting readout protection level 2 disables the debug interface permanently, so it is only
+    /// done if `confirm_level2` is set. The session cannot be used anymore afterwards.
+    pub fn write(&mut self, options: u32, confirm_level2: bool) -> Result<(), OptionBytesError> {
+        let controller = self.family.controller();
+        let level = ReadoutProtection::from_rdp(controller.rdp(options));
+
+        if level == ReadoutProtection::Level2 && !confirm_level2 {
+            return Err(OptionBytesError::Level2NotConfirmed);
+        }
+
+        {
+            // The firmware must not access the flash controller at the same time.
+            let mut core = self.session.core(0)?;
+            if !core.core_halted()? {
+                core.halt(Duration::from_millis(100))?;
+            }
+
+            controller.program(&mut core, options, PROGRAM_TIMEOUT)?;
+            controller.reload(&mut core)?;
+
+            if !controller.has_obl_launch() {
+                core.reset()?;
+            }
+        }
+
+        if level == ReadoutProtection::Level2 {
+            log::warn!("Readout protection level 2 is set, the target cannot be debugged anymore.");
+            return Ok(());
+        }
+
+        std::thread::sleep(RELOAD_DELAY);
+
+        self.session
+            .reconnect()
+            .map_err(OptionBytesError::Reconnect)
+    }
+
+    /// Sets the readout protection to `level`, keeping all other options.
+    ///
+    /// See `write` for the side effects, and the confirmation required for level 2.
+    pub fn set_readout_protection(
+        &mut self,
+        level: ReadoutProtection,
+        confirm_level2: bool,
+    ) -> Result<(), OptionBytesError> {
+        let options = self.read()?;
+        let options = self.family.controller().with_rdp(options, level.rdp());
+
+        self.write(options, confirm_level2)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn family_of_chip() {
+        assert_eq!(
+            OptionBytesFamily::from_chip_name("STM32F401CCUx"),
+            Some(OptionBytesFamily::Stm32F4)
+        );
+        assert_eq!(
+            OptionBytesFamily::from_chip_name("stm32l476rgtx"),
+            Some(OptionBytesFamily::Stm32L4)
+        );
+        assert_eq!(
+            OptionBytesFamily::from_chip_name("STM32WB55RGVx"),
+            Some(OptionBytesFamily::Stm32Wx)
+        );
+        assert_eq!(OptionBytesFamily::from_chip_name("STM32L552ZETx"), None);
+        assert_eq!(OptionBytesFamily::from_chip_name("nRF52840_xxAA"), None);
+    }
+
+    #[test]
+    fn write_protection_bits() {
+        let protection = OptionBytesFamily::Stm32F4.write_protection().unwrap();
+
+        // The default options, where no sector is protected.
+        let options = 0x0FFF_AAED;
+        assert_eq!(protection.is_protected(options, 0), Some(false));
+        assert_eq!(protection.is_protected(options & !(1 << 17), 1), Some(true));
+        assert_eq!(protection.is_protected(options, 12), None);
+
+        // With `SPRMOD`, only the sectors with a set bit are protected.
+        let options = (1 << 31) | (1 << 18) | 0xAAED;
+        assert_eq!(protection.is_protected(options, 2), Some(true));
+        assert_eq!(protection.is_protected(options, 3), Some(false));
+
+        assert_eq!(OptionBytesFamily::Stm32L4.write_protection(), None);
+    }
+
+    #[test]
+    fn readout_protection_levels() {
+        assert_eq!(ReadoutProtection::from_rdp(0xAA), ReadoutProtection::Level0);
+        assert_eq!(ReadoutProtection::from_rdp(0x12), ReadoutProtection::Level1);
+        assert_eq!(ReadoutProtection::from_rdp(0xCC), ReadoutProtection::Level2);
+
+        for level in &[
+            ReadoutProtection::Level0,
+            ReadoutProtection::Level1,
+            ReadoutProtection::Level2,
+        ] {
+            assert_eq!(ReadoutProtection::from_rdp(level.rdp()), *level);
+        }
+    }
+}
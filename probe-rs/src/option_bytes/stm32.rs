@@ -0,0 +1,392 @@
+//! Option byte programming through the flash controller of STM32 parts.
+
+use super::OptionBytesError;
+use crate::MemoryInterface;
+use std::time::{Duration, Instant};
+
+/// The keys which have to be written to `FLASH_KEYR` to unlock the flash control register.
+const FLASH_KEYS: [u32; 2] = [0x4567_0123, 0xCDEF_89AB];
+/// The keys which have to be written to `FLASH_OPTKEYR` to unlock the option bytes.
+const OPTION_KEYS: [u32; 2] = [0x0819_2A3B, 0x4C5D_6E7F];
+
+/// `FLASH_SR.BSY`, which is the same on all supported families.
+const SR_BSY: u32 = 1 << 16;
+
+/// The register layout of a flash controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct FlashController {
+    base: u32,
+    /// The offset of `FLASH_KEYR`, if the control register has to be unlocked
+    /// before the option bytes can be unlocked.
+    keyr: Option<u32>,
+    optkeyr: u32,
+    sr: u32,
+    /// The offset and the `LOCK`, `OPTLOCK` and `OPTSTRT` bits of the register which controls
+    /// the option byte programming.
+    cr: u32,
+    cr_lock: Option<u32>,
+    cr_optlock: u32,
+    cr_optstrt: u32,
+    /// The `OBL_LAUNCH` bit in the control register, if the option bytes can be reloaded without a reset.
+    cr_obl_launch: Option<u32>,
+    /// The offset of the register containing the user options and the readout protection.
+    opt: u32,
+    /// The position of the readout protection byte in the option register.
+    rdp_shift: u32,
+    /// The bits of the option register which are control bits, and not options.
+    control_bits: u32,
+}
+
+impl FlashController {
+    /// STM32F2, STM32F4 and STM32F7, where the options are set in `FLASH_OPTCR`,
+    /// which contains the control bits as well.
+    pub(super) const F4: FlashController = FlashController {
+        base: 0x4002_3C00,
+        keyr: None,
+        optkeyr: 0x08,
+        sr: 0x0C,
+        cr: 0x14,
+        cr_lock: None,
+        cr_optlock: 1 << 0,
+        cr_optstrt: 1 << 1,
+        cr_obl_launch: None,
+        opt: 0x14,
+        rdp_shift: 8,
+        control_bits: 0b11,
+    };
+
+    /// STM32G0, STM32G4 and STM32L4, where the options are set in `FLASH_OPTR`.
+    pub(super) const L4: FlashController = FlashController {
+        base: 0x4002_2000,
+        keyr: Some(0x08),
+        optkeyr: 0x0C,
+        sr: 0x10,
+        cr: 0x14,
+        cr_lock: Some(1 << 31),
+        cr_optlock: 1 << 30,
+        cr_optstrt: 1 << 17,
+        cr_obl_launch: Some(1 << 27),
+        opt: 0x20,
+        rdp_shift: 0,
+        control_bits: 0,
+    };
+
+    /// STM32WB and STM32WL, which use the same layout as the STM32L4 at a different address.
+    pub(super) const WX: FlashController = FlashController {
+        base: 0x5800_4000,
+        ..FlashController::L4
+    };
+
+    /// Returns the readout protection byte contained in the option register `value`.
+    pub(super) fn rdp(&self, value: u32) -> u8 {
+        (value >> self.rdp_shift) as u8
+    }
+
+    /// Replaces the readout protection byte in the option register `value` with `rdp`.
+    pub(super) fn with_rdp(&self, value: u32, rdp: u8) -> u32 {
+        (value & !(0xFF << self.rdp_shift)) | u32::from(rdp) << self.rdp_shift
+    }
+
+    /// Returns the options contained in the option register `value`, without the control bits.
+    pub(super) fn options(&self, value: u32) -> u32 {
+        value & !self.control_bits
+    }
+
+    /// Checks if the option bytes are reloaded by `OBL_LAUNCH`, otherwise a system reset is required.
+    pub(super) fn has_obl_launch(&self) -> bool {
+        self.cr_obl_launch.is_some()
+    }
+
+    fn read(
+        &self,
+        memory: &mut impl MemoryInterface,
+        offset: u32,
+    ) -> Result<u32, OptionBytesError> {
+        Ok(memory.read_word_32(u64::from(self.base + offset))?)
+    }
+
+    fn write(
+        &self,
+        memory: &mut impl MemoryInterface,
+        offset: u32,
+        value: u32,
+    ) -> Result<(), OptionBytesError> {
+        Ok(memory.write_word_32(u64::from(self.base + offset), value)?)
+    }
+
+    /// Reads the option register.
+    pub(super) fn read_options(
+        &self,
+        memory: &mut impl MemoryInterface,
+    ) -> Result<u32, OptionBytesError> {
+        let value = self.read(memory, self.opt)?;
+        Ok(self.options(value))
+    }
+
+    /// Waits until the flash controller has finished the current operation.
+    fn wait_until_ready(
+        &self,
+        memory: &mut impl MemoryInterface,
+        timeout: Duration,
+    ) -> Result<(), OptionBytesError> {
+        let start = Instant::now();
+
+        while self.read(memory, self.sr)? & SR_BSY != 0 {
+            if start.elapsed() > timeout {
+                return Err(OptionBytesError::Timeout);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        Ok(())
+    }
+
+    /// Unlocks the flash control register and the option bytes.
+    fn unlock(&self, memory: &mut impl MemoryInterface) -> Result<(), OptionBytesError> {
+        if let (Some(keyr), Some(lock)) = (self.keyr, self.cr_lock) {
+            if self.read(memory, self.cr)? & lock != 0 {
+                for key in &FLASH_KEYS {
+                    self.write(memory, keyr, *key)?;
+                }
+            }
+        }
+
+        if self.read(memory, self.cr)? & self.cr_optlock != 0 {
+            for key in &OPTION_KEYS {
+                self.write(memory, self.optkeyr, *key)?;
+            }
+        }
+
+        // A wrong unlock sequence locks the flash controller until the next reset.
+        let cr = self.read(memory, self.cr)?;
+        if cr & self.cr_optlock != 0 || matches!(self.cr_lock, Some(lock) if cr & lock != 0) {
+            return Err(OptionBytesError::Locked);
+        }
+
+        Ok(())
+    }
+
+    /// Locks the option bytes and the flash control register again.
+    fn lock(&self, memory: &mut impl MemoryInterface) -> Result<(), OptionBytesError> {
+        let lock = self.cr_optlock | self.cr_lock.unwrap_or(0);
+        let cr = self.read(memory, self.cr)?;
+        self.write(memory, self.cr, cr | lock)
+    }
+
+    /// Programs `options` into the option register.
+    ///
+    /// The new options only become effective after `reload` or a system reset.
+    /// Programming can take a long time, as decreasing the readout protection level
+    /// erases the whole flash.
+    pub(super) fn program(
+        &self,
+        memory: &mut impl MemoryInterface,
+        options: u32,
+        timeout: Duration,
+    ) -> Result<(), OptionBytesError> {
+        self.wait_until_ready(memory, timeout)?;
+        self.unlock(memory)?;
+
+        let result = self.program_unlocked(memory, options, timeout);
+
+        // Don't leave the option bytes unlocked, even if programming failed.
+        if result.is_err() {
+            let _ = self.lock(memory);
+        }
+
+        result
+    }
+
+    fn program_unlocked(
+        &self,
+        memory: &mut impl MemoryInterface,
+        options: u32,
+        timeout: Duration,
+    ) -> Result<(), OptionBytesError> {
+        if self.opt == self.cr {
+            // The control bits are part of the option register, so both are set at once.
+            let value = self.options(options);
+            self.write(memory, self.opt, value)?;
+            self.write(memory, self.cr, value | self.cr_optstrt)?;
+        } else {
+            self.write(memory, self.opt, options)?;
+            let cr = self.read(memory, self.cr)?;
+            self.write(memory, self.cr, cr | self.cr_optstrt)?;
+        }
+
+        self.wait_until_ready(memory, timeout)
+    }
+
+    /// Reloads the programmed option bytes, which resets the device.
+    ///
+    /// If the option bytes cannot be reloaded without a system reset, they are only locked again,
+    /// and the caller has to reset the device.
+    pub(super) fn reload(&self, memory: &mut impl MemoryInterface) -> Result<(), OptionBytesError> {
+        match self.cr_obl_launch {
+            Some(obl_launch) => {
+                let cr = self.read(memory, self.cr)?;
+
+                // The device resets immediately, so the write is usually not acknowledged.
+                if let Err(e) = self.write(memory, self.cr, cr | obl_launch) {
+                    log::debug!("Option byte reload was not acknowledged: {}", e);
+                }
+                Ok(())
+            }
+            None => self.lock(memory),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Error;
+    use std::collections::HashMap;
+
+    /// A flash controller which unlocks the option bytes with the correct keys,
+    /// and applies the options once `OPTSTRT` is set.
+    struct MockController {
+        layout: FlashController,
+        registers: HashMap<u32, u32>,
+        keys: Vec<u32>,
+        /// Ignores all keys, like a controller locked by a previous wrong unlock sequence.
+        ignore_keys: bool,
+        programmed: Option<u32>,
+    }
+
+    impl MockController {
+        fn new(layout: FlashController, cr: u32) -> Self {
+            let mut registers = HashMap::new();
+            registers.insert(layout.base + layout.cr, cr);
+
+            Self {
+                layout,
+                registers,
+                keys: Vec::new(),
+                ignore_keys: false,
+                programmed: None,
+            }
+        }
+
+        fn register(&self, offset: u32) -> u32 {
+            self.registers
+                .get(&(self.layout.base + offset))
+                .copied()
+                .unwrap_or(0)
+        }
+    }
+
+    impl MemoryInterface for MockController {
+        fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
+            let address = address as u32;
+            Ok(self.registers.get(&address).copied().unwrap_or(0))
+        }
+
+        fn read_word_8(&mut self, _address: u64) -> Result<u8, Error> {
+            unimplemented!()
+        }
+
+        fn read_32(&mut self, _address: u64, _data: &mut [u32]) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn read_8(&mut self, _address: u64, _data: &mut [u8]) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), Error> {
+            let address = address as u32;
+            let layout = self.layout;
+            let cr = layout.base + layout.cr;
+
+            if address == layout.base + layout.optkeyr {
+                self.keys.push(data);
+                if self.keys.ends_with(&OPTION_KEYS) && !self.ignore_keys {
+                    *self.registers.get_mut(&cr).unwrap() &= !layout.cr_optlock;
+                }
+                return Ok(());
+            }
+
+            if Some(address - layout.base) == layout.keyr {
+                self.keys.push(data);
+                if self.keys.ends_with(&FLASH_KEYS) && !self.ignore_keys {
+                    *self.registers.get_mut(&cr).unwrap() &= !layout.cr_lock.unwrap();
+                }
+                return Ok(());
+            }
+
+            self.registers.insert(address, data);
+
+            if address == cr && data & layout.cr_optstrt != 0 {
+                self.programmed = Some(layout.options(self.register(layout.opt)));
+            }
+
+            Ok(())
+        }
+
+        fn write_word_8(&mut self, _address: u64, _data: u8) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn write_32(&mut self, _address: u64, _data: &[u32]) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn write_8(&mut self, _address: u64, _data: &[u8]) -> Result<(), Error> {
+            unimplemented!()
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn rdp_position() {
+        assert_eq!(FlashController::F4.rdp(0x0FFF_AAED), 0xAA);
+        assert_eq!(FlashController::L4.rdp(0xFFEF_F8AA), 0xAA);
+        assert_eq!(FlashController::F4.with_rdp(0x0FFF_AAED, 0x55), 0x0FFF_55ED);
+        assert_eq!(FlashController::L4.with_rdp(0xFFEF_F8AA, 0xCC), 0xFFEF_F8CC);
+    }
+
+    #[test]
+    fn program_unlocks_flash_controller() {
+        let layout = FlashController::L4;
+        let mut controller = MockController::new(layout, (1 << 31) | (1 << 30));
+
+        layout
+            .program(&mut controller, 0xFFEF_F8BB, Duration::from_secs(1))
+            .unwrap();
+
+        assert_eq!(controller.keys, [FLASH_KEYS, OPTION_KEYS].concat());
+        assert_eq!(controller.programmed, Some(0xFFEF_F8BB));
+    }
+
+    #[test]
+    fn program_keeps_control_bits_in_option_register() {
+        let layout = FlashController::F4;
+        let mut controller = MockController::new(layout, 0x0FFF_AAED);
+
+        // The control bits of the new options are ignored.
+        layout
+            .program(&mut controller, 0x0FFF_BBEF, Duration::from_secs(1))
+            .unwrap();
+
+        assert_eq!(controller.keys, OPTION_KEYS);
+        assert_eq!(controller.programmed, Some(0x0FFF_BBEC));
+        assert_eq!(controller.register(layout.cr), 0x0FFF_BBEE);
+    }
+
+    #[test]
+    fn failed_unlock_is_an_error() {
+        let layout = FlashController::L4;
+        let mut controller = MockController::new(layout, 1 << 30);
+        controller.ignore_keys = true;
+
+        assert!(matches!(
+            layout.program(&mut controller, 0, Duration::from_secs(1)),
+            Err(OptionBytesError::Locked)
+        ));
+        assert_eq!(controller.programmed, None);
+    }
+}
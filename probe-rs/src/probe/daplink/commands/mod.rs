@@ -158,48 +158,115 @@ pub(crate) trait Response: Sized {
     fn from_bytes(buffer: &[u8], offset: usize) -> Result<Self>;
 }
 
+/// On CMSIS-DAP v2 USB HS devices, a single request might be up to 1024 bytes,
+/// plus we need one extra byte for the always-written HID report ID.
+const BUFFER_LEN: usize = 1025;
+
 pub(crate) fn send_command<Req: Request, Res: Response>(
     device: &mut std::sync::Mutex<DAPLinkDevice>,
     request: Req,
 ) -> Result<Res> {
-    // On CMSIS-DAP v2 USB HS devices, a single request might be up to 1024 bytes,
-    // plus we need one extra byte for the always-written HID report ID.
-    const BUFFER_LEN: usize = 1025;
+    if let Ok(device) = device.get_mut() {
+        write_request(device, &request)?;
+        read_response::<Req, Res>(device)
+    } else {
+        Err(anyhow!(CmsisDapError::ErrorResponse)).context("failed while sending command")
+    }
+}
+
+/// Sends all `requests`, while keeping up to `depth` of them queued on the probe.
+///
+/// Probes buffer up to `PacketCount` requests, so sending the next request before the response
+/// to the previous one was read saves a USB round trip per request. The requests are still
+/// executed in order, and the responses are returned in the order of the requests.
+///
+/// If a request fails, no further requests are sent, and the first error is returned.
+pub(crate) fn send_pipelined<Req: Request, Res: Response>(
+    device: &mut std::sync::Mutex<DAPLinkDevice>,
+    requests: &[Req],
+    depth: usize,
+) -> Result<Vec<Res>> {
+    let device = match device.get_mut() {
+        Ok(device) => device,
+        Err(_) => {
+            return Err(anyhow!(CmsisDapError::ErrorResponse))
+                .context("failed while sending command")
+        }
+    };
+
+    let mut responses = Vec::with_capacity(requests.len());
+    let mut result = Ok(());
+    let mut requests = requests.iter();
+    let mut in_flight = 0;
+
+    loop {
+        while result.is_ok() && in_flight < depth.max(1) {
+            match requests.next() {
+                Some(request) => {
+                    result = write_request(device, request);
+                    if result.is_ok() {
+                        in_flight += 1;
+                    }
+                }
+                None => break,
+            }
+        }
 
+        if in_flight == 0 {
+            break;
+        }
+
+        // The responses to all sent requests have to be read, even after an error,
+        // otherwise the following commands would receive them.
+        in_flight -= 1;
+        match read_response::<Req, Res>(device) {
+            Ok(response) => responses.push(response),
+            Err(e) => {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+    }
+
+    result.map(|_| responses)
+}
+
+fn write_request<Req: Request>(device: &DAPLinkDevice, request: &Req) -> Result<()> {
     // Write the command & request to the buffer.
     let mut write_buffer = [0; BUFFER_LEN];
     write_buffer[1] = *Req::CATEGORY;
     let mut size = request.to_bytes(&mut write_buffer, 1 + 1)?;
     size += 2;
 
-    if let Ok(device) = device.get_mut() {
-        // On Windows, HID writes must write exactly the size of the
-        // largest report for the device, but there's no way to query
-        // this in hidapi. Almost all known CMSIS-DAP devices use 64-byte
-        // HID reports (the maximum permitted), so ensure we always
-        // write exactly 64 (+1 for report ID) bytes for HID.
-        // For v2 devices, we can write the precise request size.
-        if let DAPLinkDevice::V1(_) = device {
-            size = 65;
-        }
+    // On Windows, HID writes must write exactly the size of the
+    // largest report for the device, but there's no way to query
+    // this in hidapi. Almost all known CMSIS-DAP devices use 64-byte
+    // HID reports (the maximum permitted), so ensure we always
+    // write exactly 64 (+1 for report ID) bytes for HID.
+    // For v2 devices, we can write the precise request size.
+    if let DAPLinkDevice::V1(_) = device {
+        size = 65;
+    }
 
-        // Send buffer to the device.
-        device.write(&write_buffer[..size])?;
-        trace_buffer("Transmit buffer", &write_buffer[..size]);
+    // Send buffer to the device.
+    device.write(&write_buffer[..size])?;
+    trace_buffer("Transmit buffer", &write_buffer[..size]);
 
-        // Read back resonse.
-        let mut read_buffer = [0; BUFFER_LEN];
-        device.read(&mut read_buffer)?;
-        trace_buffer("Receive buffer", &read_buffer[..]);
+    Ok(())
+}
 
-        if read_buffer[0] == *Req::CATEGORY {
-            Res::from_bytes(&read_buffer, 1)
-        } else {
-            Err(anyhow!(CmsisDapError::UnexpectedAnswer))
-                .with_context(|| format!("Received invalid data for {:?}", *Req::CATEGORY))
-        }
+fn read_response<Req: Request, Res: Response>(device: &DAPLinkDevice) -> Result<Res> {
+    // Read back resonse.
+    let mut read_buffer = [0; BUFFER_LEN];
+    device.read(&mut read_buffer)?;
+    trace_buffer("Receive buffer", &read_buffer[..]);
+
+    if read_buffer[0] == *Req::CATEGORY {
+        Res::from_bytes(&read_buffer, 1)
     } else {
-        Err(anyhow!(CmsisDapError::ErrorResponse)).context("failed while sending command")
+        Err(anyhow!(CmsisDapError::UnexpectedAnswer))
+            .with_context(|| format!("Received invalid data for {:?}", *Req::CATEGORY))
     }
 }
 
@@ -1,18 +1,39 @@
 use super::super::{Category, Request, Response, Result, Status};
 
+/// The DAP_SWD_Configure Command sets the parameters for the SWD communication.
 #[derive(Debug)]
-pub struct ConfigureRequest;
+pub struct ConfigureRequest {
+    /// The length of the turnaround period in clock cycles, between 1 and 4.
+    pub turnaround: u8,
+    /// Whether a data phase is generated on WAIT and FAULT responses.
+    pub data_phase: bool,
+}
 
 impl Request for ConfigureRequest {
     const CATEGORY: Category = Category(0x13);
 
     fn to_bytes(&self, buffer: &mut [u8], offset: usize) -> Result<usize> {
-        // TODO: Allow configuration
-        buffer[offset] = 0;
+        buffer[offset] = (self.turnaround - 1) & 0b11 | (self.data_phase as u8) << 2;
         Ok(1)
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn configuration_byte() {
+        let mut buffer = [0xff; 1];
+        let request = ConfigureRequest {
+            turnaround: 3,
+            data_phase: true,
+        };
+        assert_eq!(request.to_bytes(&mut buffer, 0).unwrap(), 1);
+        assert_eq!(buffer[0], 0b110);
+    }
+}
+
 #[derive(Debug)]
 pub struct ConfigureResponse(pub(crate) Status);
 
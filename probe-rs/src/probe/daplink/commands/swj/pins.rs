@@ -56,7 +56,7 @@ impl SWJPinsRequestBuilder {
         self
     }
 
-    pub fn _wait(&mut self, value: u32) -> &mut Self {
+    pub fn wait(&mut self, value: u32) -> &mut Self {
         self.timeout = value;
         self
     }
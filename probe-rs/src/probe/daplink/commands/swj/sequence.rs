@@ -57,6 +57,20 @@ impl SequenceRequest {
     }
 }
 
+impl SequenceRequest {
+    /// Creates a request for the `bit_count` lowest bits of `bits`, sent starting with the least significant bit.
+    pub(crate) fn from_bits(bit_count: u8, bits: u64) -> Result<SequenceRequest> {
+        if bit_count == 0 || bit_count > 64 {
+            return Err(anyhow!(CmsisDapError::TooMuchData));
+        }
+
+        let mut data = [0u8; 32];
+        data[..8].copy_from_slice(&bits.to_le_bytes());
+
+        Ok(SequenceRequest { bit_count, data })
+    }
+}
+
 #[derive(Debug)]
 pub struct SequenceResponse(pub(crate) Status);
 
@@ -167,7 +167,9 @@ pub struct TransferResponse {
     /// register value or match value in the order of the Transfer Request.
     ///- for Read Register transfer request: the register value of the CoreSight register.
     ///- no data is sent for other operations.
-    pub transfer_data: u32,
+    ///
+    /// Only the values of the executed reads are valid, the rest is padding of the response.
+    pub transfer_data: Vec<u32>,
 }
 
 impl Response for TransferResponse {
@@ -187,9 +189,13 @@ impl Response for TransferResponse {
             },
             // TODO: implement this properly.
             td_timestamp: 0, // scroll::pread_with(buffer[offset + 2..offset + 2 + 4], LE),
-            transfer_data: buffer
-                .pread_with(offset + 2, LE)
-                .map_err(|_| anyhow!("This is a bug. Please report it."))?,
+            transfer_data: buffer[offset + 2..]
+                .chunks_exact(4)
+                .map(|word| {
+                    word.pread_with::<u32>(0, LE)
+                        .map_err(|_| anyhow!("This is a bug. Please report it."))
+                })
+                .collect::<Result<_>>()?,
         })
     }
 }
@@ -326,3 +332,18 @@ impl Response for TransferBlockResponse {
         })
     }
 }
+
+#[test]
+fn transfer_response_with_multiple_reads() {
+    let mut buffer = [0; 16];
+    buffer[1] = 2;
+    buffer[2] = 1;
+    buffer[3..7].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+    buffer[7..11].copy_from_slice(&0xdead_beefu32.to_le_bytes());
+
+    let response = TransferResponse::from_bytes(&buffer, 1).unwrap();
+
+    assert_eq!(response.transfer_count, 2);
+    assert!(matches!(response.transfer_response.ack, Ack::Ok));
+    assert_eq!(&response.transfer_data[..2], &[0x1234_5678, 0xdead_beef]);
+}
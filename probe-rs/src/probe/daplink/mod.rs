@@ -4,11 +4,12 @@ pub mod tools;
 use crate::{
     architecture::arm::{
         communication_interface::ArmProbeInterface,
-        dp::{DPAccess, DPRegister, DebugPortError},
+        dp::{Abort, DPAccess, DPRegister, DebugPortError},
         swo::poll_interval_from_buf_size,
-        ArmCommunicationInterface, DAPAccess, DapError, PortType, SwoAccess, SwoConfig, SwoMode,
+        ArmCommunicationInterface, DAPAccess, DapError, PortType, Register, SwoAccess, SwoConfig,
+        SwoMode,
     },
-    probe::{daplink::commands::CmsisDapError, BatchCommand},
+    probe::{daplink::commands::CmsisDapError, AttachConfig, BatchCommand},
     DebugProbe, DebugProbeError, DebugProbeSelector, Error as ProbeRsError, WireProtocol,
 };
 
@@ -42,6 +43,21 @@ use std::time::Duration;
 
 use anyhow::anyhow;
 
+/// The time in microseconds the reset pin is given to settle, before its level is read back.
+const RESET_PIN_SETTLE_TIME_US: u32 = 1000;
+
+/// The largest number of transfers in a single `DAP_Transfer` command.
+const MAX_BATCH_LEN: usize = 255;
+
+/// Returns the sizes in bytes of the `DAP_Transfer` request and response for `batch`,
+/// including the command byte.
+fn transfer_sizes<'a>(batch: impl Iterator<Item = &'a BatchCommand>) -> (usize, usize) {
+    batch.fold((3, 3), |(request, response), command| match command {
+        BatchCommand::Read(_, _) => (request + 1, response + 4),
+        BatchCommand::Write(_, _, _) => (request + 5, response),
+    })
+}
+
 pub struct DAPLink {
     pub device: Mutex<DAPLinkDevice>,
     _hw_version: u8,
@@ -57,6 +73,9 @@ pub struct DAPLink {
 
     /// Speed in kHz
     speed_khz: u32,
+    /// The idle cycles after each transfer, and the length of the turnaround period.
+    swd_idle_cycles: u8,
+    swd_turnaround: u8,
 
     batch: Vec<BatchCommand>,
 }
@@ -102,6 +121,8 @@ impl DAPLink {
             swo_active: false,
             swo_streaming: false,
             speed_khz: 1_000,
+            swd_idle_cycles: 0,
+            swd_turnaround: 1,
             batch: Vec::new(),
         }
     }
@@ -145,6 +166,34 @@ impl DAPLink {
         Ok(())
     }
 
+    /// Sends the changed idle cycles or turnaround period to the probe, if it is already attached.
+    ///
+    /// Otherwise they are sent when attaching.
+    fn reconfigure_swd_timing(&mut self) -> Result<(), DebugProbeError> {
+        if self.capabilities.is_none() {
+            return Ok(());
+        }
+
+        self.process_batch()?;
+        self.configure_swd_timing()
+    }
+
+    /// Sends the idle cycles and the turnaround period to the probe.
+    fn configure_swd_timing(&mut self) -> Result<(), DebugProbeError> {
+        self.transfer_configure(ConfigureRequest {
+            idle_cycles: self.swd_idle_cycles,
+            wait_retry: 80,
+            match_retry: 0,
+        })?;
+
+        self.configure_swd(swd::configure::ConfigureRequest {
+            turnaround: self.swd_turnaround,
+            data_phase: false,
+        })?;
+
+        Ok(())
+    }
+
     fn send_swj_sequences(&mut self, request: SequenceRequest) -> Result<(), CmsisDapError> {
         /* 12 38 FF FF FF FF FF FF FF -> 12 00 // SWJ Sequence
         12 10 9E E7 -> 12 00 // SWJ Sequence
@@ -162,14 +211,14 @@ impl DAPLink {
     /// Immediately send whatever is in our batch if it is not empty.
     ///
     /// This will ensure any pending writes are processed and errors from them
-    /// raised if necessary.
-    fn process_batch(&mut self) -> Result<u32, DebugProbeError> {
+    /// raised if necessary. Returns the values of the reads in the batch, in order.
+    fn process_batch(&mut self) -> Result<Vec<u32>, DebugProbeError> {
         if self.batch.is_empty() {
-            return Ok(0);
+            return Ok(vec![]);
         }
         debug!("Processing batch of {} items", self.batch.len());
 
-        let batch = std::mem::replace(&mut self.batch, Vec::new());
+        let batch = std::mem::take(&mut self.batch);
 
         let transfers: Vec<InnerTransferRequest> = batch
             .iter()
@@ -196,7 +245,15 @@ impl DAPLink {
                     Err(DapError::SwdProtocol.into())
                 } else {
                     match response.transfer_response.ack {
-                        Ack::Ok => Ok(response.transfer_data),
+                        Ack::Ok => {
+                            let reads = batch
+                                .iter()
+                                .filter(|command| matches!(command, BatchCommand::Read(_, _)))
+                                .count();
+                            let mut values = response.transfer_data;
+                            values.truncate(reads);
+                            Ok(values)
+                        }
                         Ack::NoAck => Err(DapError::NoAcknowledge.into()),
                         Ack::Fault => Err(DapError::FaultResponse.into()),
                         Ack::Wait => Err(DapError::WaitResponse.into()),
@@ -213,23 +270,38 @@ impl DAPLink {
 
     /// Add a BatchCommand to our current batch.
     ///
-    /// If the BatchCommand is a Read, this will immediately process the batch
-    /// and return the read value. If the BatchCommand is a write, the write is
-    /// executed immediately if the batch is full, otherwise it is queued for
-    /// later execution.
-    fn batch_add(&mut self, command: BatchCommand) -> Result<u32, DebugProbeError> {
+    /// The batch is sent first if the command does not fit into the same packet,
+    /// and the values of the reads in it are returned. The transfers are always
+    /// executed in the order they were added.
+    fn batch_add(&mut self, command: BatchCommand) -> Result<Vec<u32>, DebugProbeError> {
         debug!("Adding command to batch: {}", command);
 
+        let packet_size = self.packet_size.unwrap_or(32) as usize;
+        let (request_len, response_len) =
+            transfer_sizes(self.batch.iter().chain(std::iter::once(&command)));
+
+        let values = if request_len > packet_size
+            || response_len > packet_size
+            || self.batch.len() == MAX_BATCH_LEN
+        {
+            self.process_batch()?
+        } else {
+            vec![]
+        };
+
         self.batch.push(command);
 
-        // We always immediately process any reads, which means there will never
-        // be more than one read in a batch. We also process whenever the batch
-        // is as long as can fit in one packet.
-        let max_writes = (self.packet_size.unwrap_or(32) as usize - 3) / (1 + 4);
-        match command {
-            BatchCommand::Read(_, _) => self.process_batch(),
-            _ if self.batch.len() == max_writes => self.process_batch(),
-            _ => Ok(0),
+        Ok(values)
+    }
+
+    /// The number of requests which are queued on the probe at once for block transfers.
+    ///
+    /// Requests are only queued on CMSIS-DAP v2 probes, v1 probes are always sent one
+    /// request at a time.
+    fn pipeline_depth(&mut self) -> usize {
+        match self.device.get_mut() {
+            Ok(DAPLinkDevice::V2 { .. }) => self.packet_count.unwrap_or(1) as usize,
+            _ => 1,
         }
     }
 
@@ -383,6 +455,16 @@ impl DebugProbe for DAPLink {
         self.speed_khz
     }
 
+    fn set_swd_idle_cycles(&mut self, cycles: u8) -> Result<(), DebugProbeError> {
+        self.swd_idle_cycles = cycles;
+        self.reconfigure_swd_timing()
+    }
+
+    fn set_swd_turnaround(&mut self, periods: u8) -> Result<(), DebugProbeError> {
+        self.swd_turnaround = periods;
+        self.reconfigure_swd_timing()
+    }
+
     /// For CMSIS-DAP, we can set the maximum speed. The actual speed
     /// used by the probe cannot be determined, but it will not be
     /// higher than this value.
@@ -435,13 +517,7 @@ impl DebugProbe for DAPLink {
         // Set speed after connecting as it can be reset during protocol selection
         self.set_speed(self.speed_khz)?;
 
-        self.transfer_configure(ConfigureRequest {
-            idle_cycles: 0,
-            wait_retry: 80,
-            match_retry: 0,
-        })?;
-
-        self.configure_swd(swd::configure::ConfigureRequest {})?;
+        self.configure_swd_timing()?;
 
         self.send_swj_sequences(SequenceRequest::new(&[
             0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
@@ -500,6 +576,12 @@ impl DebugProbe for DAPLink {
         }
     }
 
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
+        self.process_batch()?;
+        self.send_swj_sequences(SequenceRequest::from_bits(bit_len, bits)?)?;
+        Ok(())
+    }
+
     /// Asserts the nRESET pin.
     fn target_reset(&mut self) -> Result<(), DebugProbeError> {
         commands::send_command(&mut self.device, ResetRequest).map(|v: ResetResponse| {
@@ -509,11 +591,20 @@ impl DebugProbe for DAPLink {
     }
 
     fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
-        let request = SWJPinsRequestBuilder::new().nreset(false).build();
+        // Wait for the pin to settle before it is read back.
+        let request = SWJPinsRequestBuilder::new()
+            .nreset(false)
+            .wait(RESET_PIN_SETTLE_TIME_US)
+            .build();
+
+        let pins: SWJPinsResponse = commands::send_command(&mut self.device, request)?;
+        log::info!("Pin response: {:?}", pins);
+
+        // A probe without a reset pin, or one which is not connected, reads the pulled up level.
+        if pins.nreset() {
+            return Err(DebugProbeError::ResetPinNotAvailable);
+        }
 
-        commands::send_command(&mut self.device, request).map(|v: SWJPinsResponse| {
-            log::info!("Pin response: {:?}", v);
-        })?;
         Ok(())
     }
 
@@ -534,10 +625,17 @@ impl DebugProbe for DAPLink {
         Some(self as _)
     }
 
+    /// Blocks are split into `DAP_TransferBlock` commands of the packet size by `read_block`
+    /// and `write_block` already.
+    fn max_block_size(&self) -> usize {
+        usize::MAX
+    }
+
     fn get_arm_interface<'probe>(
         self: Box<Self>,
+        config: &AttachConfig,
     ) -> Result<Option<Box<dyn ArmProbeInterface + 'probe>>, DebugProbeError> {
-        let interface = ArmCommunicationInterface::new(self)?;
+        let interface = ArmCommunicationInterface::new(self, config)?;
 
         Ok(Some(Box::new(interface)))
     }
@@ -561,8 +659,29 @@ impl<'a> AsMut<dyn DebugProbe + 'a> for DAPLink {
 
 impl DAPAccess for DAPLink {
     /// Reads the DAP register on the specified port and address.
+    ///
+    /// The queued writes are sent in the same packet as the read.
     fn read_register(&mut self, port: PortType, addr: u16) -> Result<u32, DebugProbeError> {
-        self.batch_add(BatchCommand::Read(port, addr))
+        self.batch_add(BatchCommand::Read(port, addr))?;
+
+        self.process_batch()?.pop().ok_or_else(|| {
+            DebugProbeError::Other(anyhow!("The probe did not return the read value"))
+        })
+    }
+
+    /// Reads all registers with as few packets as possible, together with the queued writes.
+    fn read_registers(
+        &mut self,
+        registers: &[(PortType, u16)],
+    ) -> Result<Vec<u32>, DebugProbeError> {
+        let mut values = Vec::with_capacity(registers.len());
+
+        for (port, addr) in registers {
+            values.extend(self.batch_add(BatchCommand::Read(*port, *addr))?);
+        }
+        values.extend(self.process_batch()?);
+
+        Ok(values)
     }
 
     /// Writes a value to the DAP register on the specified port and address.
@@ -572,8 +691,15 @@ impl DAPAccess for DAPLink {
         addr: u16,
         value: u32,
     ) -> Result<(), DebugProbeError> {
-        self.batch_add(BatchCommand::Write(port, addr, value))
-            .map(|_| ())
+        self.batch_add(BatchCommand::Write(port, addr, value))?;
+
+        // Writes to ABORT are used to recover from errors of previous transfers,
+        // so they are not queued behind further transfers.
+        if port == PortType::DebugPort && addr == u16::from(Abort::ADDRESS) {
+            self.process_batch()?;
+        }
+
+        Ok(())
     }
 
     fn write_block(
@@ -598,21 +724,30 @@ impl DAPAccess for DAPLink {
 
         let data_chunk_len = max_packet_size_words as usize;
 
-        for (i, chunk) in values.chunks(data_chunk_len).enumerate() {
-            let request = TransferBlockRequest::write_request(
-                register_address as u8,
-                port.into(),
-                Vec::from(chunk),
-            );
+        let requests: Vec<_> = values
+            .chunks(data_chunk_len)
+            .map(|chunk| {
+                TransferBlockRequest::write_request(
+                    register_address as u8,
+                    port.into(),
+                    Vec::from(chunk),
+                )
+            })
+            .collect();
 
-            debug!("Transfer block: chunk={}, len={} bytes", i, chunk.len() * 4);
+        debug!(
+            "Transfer block: chunks={}, len={} bytes",
+            requests.len(),
+            values.len() * 4
+        );
 
-            let resp: TransferBlockResponse =
-                commands::send_command(&mut self.device, request).map_err(DebugProbeError::from)?;
+        let depth = self.pipeline_depth();
+        let responses: Vec<TransferBlockResponse> =
+            commands::send_pipelined(&mut self.device, &requests, depth)
+                .map_err(DebugProbeError::from)?;
 
-            if resp.transfer_response != 1 {
-                return Err(CmsisDapError::ErrorResponse.into());
-            }
+        if responses.iter().any(|resp| resp.transfer_response != 1) {
+            return Err(CmsisDapError::ErrorResponse.into());
         }
 
         Ok(())
@@ -640,18 +775,29 @@ impl DAPAccess for DAPLink {
 
         let data_chunk_len = max_packet_size_words as usize;
 
-        for (i, chunk) in values.chunks_mut(data_chunk_len).enumerate() {
-            let request = TransferBlockRequest::read_request(
-                register_address as u8,
-                port.into(),
-                chunk.len() as u16,
-            );
+        let requests: Vec<_> = values
+            .chunks(data_chunk_len)
+            .map(|chunk| {
+                TransferBlockRequest::read_request(
+                    register_address as u8,
+                    port.into(),
+                    chunk.len() as u16,
+                )
+            })
+            .collect();
 
-            debug!("Transfer block: chunk={}, len={} bytes", i, chunk.len() * 4);
+        debug!(
+            "Transfer block: chunks={}, len={} bytes",
+            requests.len(),
+            values.len() * 4
+        );
 
-            let resp: TransferBlockResponse =
-                commands::send_command(&mut self.device, request).map_err(DebugProbeError::from)?;
+        let depth = self.pipeline_depth();
+        let responses: Vec<TransferBlockResponse> =
+            commands::send_pipelined(&mut self.device, &requests, depth)
+                .map_err(DebugProbeError::from)?;
 
+        for (chunk, resp) in values.chunks_mut(data_chunk_len).zip(responses) {
             if resp.transfer_response != 1 {
                 return Err(CmsisDapError::ErrorResponse.into());
             }
@@ -801,3 +947,20 @@ impl Drop for DAPLink {
         let _ = self.detach();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transfer_sizes_of_batch() {
+        let batch = [
+            BatchCommand::Write(PortType::DebugPort, 8, 0),
+            BatchCommand::Read(PortType::AccessPort(0), 0xc),
+            BatchCommand::Read(PortType::DebugPort, 4),
+        ];
+
+        assert_eq!(transfer_sizes(batch.iter()), (3 + 5 + 1 + 1, 3 + 4 + 4));
+        assert_eq!(transfer_sizes([].iter()), (3, 3));
+    }
+}
@@ -1,6 +1,6 @@
 use super::DAPLinkDevice;
 use crate::{
-    probe::{DebugProbeInfo, DebugProbeType, ProbeCreationError},
+    probe::{usb_path, DebugProbeInfo, DebugProbeType, ProbeCreationError},
     DebugProbeSelector,
 };
 use rusb::{Device, DeviceDescriptor, UsbContext};
@@ -46,6 +46,7 @@ fn get_daplink_info(device: &Device<rusb::Context>) -> Option<DebugProbeInfo> {
             product_id: d_desc.product_id(),
             serial_number: sn_str,
             probe_type: DebugProbeType::DAPLink,
+            usb_path: usb_path(device),
         })
     } else {
         None
@@ -62,6 +63,8 @@ fn get_daplink_hid_info(device: &hidapi::DeviceInfo) -> Option<DebugProbeInfo> {
                 product_id: device.product_id(),
                 serial_number: device.serial_number().map(|s| s.to_owned()),
                 probe_type: DebugProbeType::DAPLink,
+                // The paths of HID devices depend on the backend, and are not stable.
+                usb_path: None,
             });
         }
     }
@@ -156,12 +159,14 @@ pub fn open_v2_device(device: Device<rusb::Context>) -> Option<DAPLinkDevice> {
 }
 
 fn device_matches(
+    device: &Device<rusb::Context>,
     device_descriptor: DeviceDescriptor,
     selector: &DebugProbeSelector,
     serial_str: Option<String>,
 ) -> bool {
     if device_descriptor.vendor_id() == selector.vendor_id
         && device_descriptor.product_id() == selector.product_id
+        && selector.matches_usb_path(usb_path(device).as_deref())
     {
         if selector.serial_number.is_some() {
             serial_str == selector.serial_number
@@ -220,7 +225,9 @@ pub fn open_device_from_selector(
             // multiple open handles are not allowed on Windows.
             drop(handle);
 
-            if device_matches(d_desc, &selector, sn_str) && get_daplink_info(&device).is_some() {
+            if device_matches(&device, d_desc, &selector, sn_str)
+                && get_daplink_info(&device).is_some()
+            {
                 // If the VID, PID, and potentially SN all match,
                 // and the device is a valid CMSIS-DAP probe,
                 // attempt to open the device in v2 mode.
@@ -251,6 +258,19 @@ pub fn open_device_from_selector(
     // Attempt to open provided VID/PID/SN with hidapi
     let hid_device = match sn {
         Some(sn) => hidapi::HidApi::new().and_then(|api| api.open_serial(vid, pid, &sn)),
+        None if selector.usb_path.is_some() => {
+            // HID paths cannot be matched against the USB path, so the probe can only be
+            // opened if it is the only one with this VID and PID.
+            let api = hidapi::HidApi::new()?;
+            let mut devices = api
+                .device_list()
+                .filter(|device| device.vendor_id() == vid && device.product_id() == pid);
+
+            match (devices.next(), devices.next()) {
+                (Some(device), None) => device.open_device(&api),
+                _ => return Err(ProbeCreationError::NotFound),
+            }
+        }
         None => hidapi::HidApi::new().and_then(|api| api.open(vid, pid)),
     };
 
@@ -6,7 +6,7 @@ use libftdi1_sys as ffi;
 use std::convert::TryInto;
 use std::io::{self, ErrorKind, Read, Write};
 
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::{mem, ptr};
 use thiserror::Error;
 
@@ -83,8 +83,32 @@ impl Builder {
         }
     }
 
-    pub fn usb_open(mut self, vendor: u16, product: u16) -> Result<Device> {
-        let result = unsafe { ffi::ftdi_usb_open(self.context, vendor as i32, product as i32) };
+    pub fn usb_open(self, vendor: u16, product: u16) -> Result<Device> {
+        self.usb_open_serial(vendor, product, None)
+    }
+
+    /// Opens the device with the given serial number, or the first matching one if it is `None`.
+    pub fn usb_open_serial(
+        mut self,
+        vendor: u16,
+        product: u16,
+        serial: Option<&str>,
+    ) -> Result<Device> {
+        let serial = serial
+            .map(CString::new)
+            .transpose()
+            .map_err(|_| Error::InvalidInput("serial number contains a nul byte"))?;
+        let serial_ptr = serial.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+
+        let result = unsafe {
+            ffi::ftdi_usb_open_desc(
+                self.context,
+                vendor as i32,
+                product as i32,
+                ptr::null(),
+                serial_ptr,
+            )
+        };
         match result {
             0 => Ok(Device {
                 context: mem::replace(&mut self.context, ptr::null_mut()),
@@ -1,5 +1,8 @@
+use crate::architecture::arm::{
+    communication_interface::ArmProbeInterface, ArmCommunicationInterface,
+};
 use crate::architecture::riscv::communication_interface::RiscvCommunicationInterface;
-use crate::probe::{JTAGAccess, ProbeCreationError};
+use crate::probe::{jtag, usb_path, AttachConfig, JTAGAccess, JtagDevice, ProbeCreationError};
 use crate::{
     DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector, DebugProbeType, WireProtocol,
 };
@@ -11,8 +14,100 @@ use std::sync::Mutex;
 use std::time::Duration;
 
 mod ftdi_impl;
+mod swd;
 use ftdi_impl as ftdi;
 
+/// The vendor ID of FTDI.
+const FTDI_VID: u16 = 0x0403;
+
+/// The product IDs of the FTDI chips with an MPSSE: FT2232C/D/H, FT4232H and FT232H.
+const MPSSE_PIDS: [u16; 3] = [0x6010, 0x6011, 0x6014];
+
+/// The MPSSE clock of the H-series chips, with the divide-by-5 prescaler disabled.
+const MPSSE_CLOCK_KHZ: u32 = 60_000;
+
+/// The speed used until `set_speed` is called.
+const DEFAULT_SPEED_KHZ: u32 = 1_000;
+
+/// The MPSSE interfaces of an FTDI chip.
+///
+/// The FT2232 and FT4232H have their MPSSEs on the interfaces A and B, the FT232H only on A.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FtdiInterface {
+    A,
+    B,
+}
+
+impl From<FtdiInterface> for ftdi::Interface {
+    fn from(interface: FtdiInterface) -> Self {
+        match interface {
+            FtdiInterface::A => ftdi::Interface::A,
+            FtdiInterface::B => ftdi::Interface::B,
+        }
+    }
+}
+
+/// The pins of an FTDI based probe, see `Probe::set_ftdi_pinout`.
+///
+/// The MPSSE always uses the pins 0 to 3 of the low byte (`xDBUS0` to `xDBUS3`) for TCK, TDI, TDO and TMS.
+/// For SWD, SWCLK is on TCK, and SWDIO is driven on TDI and read on TDO, so the target SWDIO has
+/// to be connected to both, usually with a resistor on TDI.
+///
+/// All other pins of the low byte and the high byte (`xCBUS`) are GPIOs, which e.g. enable the
+/// output buffers of a probe. They are set to `output` and `direction` when attaching.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FtdiPinout {
+    /// The MPSSE interface the target is connected to.
+    pub interface: FtdiInterface,
+    /// The initial level of the pins, the low byte is `xDBUS` and the high byte `xCBUS`.
+    pub output: u16,
+    /// The direction of the pins, set bits are outputs.
+    pub direction: u16,
+    /// The pin connected to the active low reset of the target (nRST), as a bit index like in `output`.
+    ///
+    /// The pin is driven low to assert the reset, and is an input otherwise,
+    /// so the target needs a pull-up on its reset line.
+    pub nrst: Option<u8>,
+}
+
+impl FtdiPinout {
+    /// TCK, TDI and TMS are outputs, TDO is an input.
+    const JTAG_DIRECTION: u16 = 0x000b;
+    /// SWDIO is driven on TDI.
+    const SWDIO_OUT: u16 = 0x0002;
+
+    fn nrst_mask(&self) -> u16 {
+        self.nrst.map_or(0, |pin| 1 << pin)
+    }
+}
+
+impl Default for FtdiPinout {
+    /// Minimal values with TMS high and no reset pin, which may not work with all probes.
+    fn default() -> Self {
+        Self {
+            interface: FtdiInterface::A,
+            output: 0x0008,
+            direction: Self::JTAG_DIRECTION,
+            nrst: None,
+        }
+    }
+}
+
+/// Returns the MPSSE clock divisor for the highest speed not above `speed_khz`,
+/// together with the resulting speed.
+fn clock_divisor(speed_khz: u32) -> Option<(u16, u32)> {
+    if speed_khz == 0 {
+        return None;
+    }
+
+    // TCK = MPSSE clock / ((1 + divisor) * 2)
+    let half_clock = MPSSE_CLOCK_KHZ / 2;
+    let divisor = ((half_clock + speed_khz - 1) / speed_khz).saturating_sub(1);
+    let divisor: u16 = divisor.try_into().ok()?;
+
+    Some((divisor, half_clock / (u32::from(divisor) + 1)))
+}
+
 #[derive(Debug)]
 struct JtagChainItem {
     idcode: u32,
@@ -32,36 +127,66 @@ struct ChainParams {
 pub struct JtagAdapter {
     device: ftdi::Device,
     chain_params: Option<ChainParams>,
+    pinout: FtdiPinout,
+    /// The current level and direction of all pins.
+    output: u16,
+    direction: u16,
+    clock_divisor: u16,
+    /// Set once the MPSSE is enabled, before that commands would be sent as serial data.
+    mpsse_enabled: bool,
+    /// The idle cycles clocked after each SWD transfer.
+    swd_idle_cycles: u8,
+    /// The length of the SWD turnaround period in clock cycles, between 1 and 4.
+    swd_turnaround: u8,
 }
 
 impl JtagAdapter {
-    pub fn open(vid: u16, pid: u16) -> Result<Self, ftdi::Error> {
+    pub fn open(
+        vid: u16,
+        pid: u16,
+        serial: Option<&str>,
+        pinout: FtdiPinout,
+    ) -> Result<Self, ftdi::Error> {
         let mut builder = ftdi::Builder::new();
-        builder.set_interface(ftdi::Interface::A)?;
-        let device = builder.usb_open(vid, pid)?;
+        builder.set_interface(pinout.interface.into())?;
+        let mut device = builder.usb_open_serial(vid, pid, serial)?;
+
+        // By default, the chip waits up to 16 ms for more data before sending a partial packet,
+        // which would delay every response.
+        device.set_latency_timer(1)?;
 
         Ok(Self {
             device,
             chain_params: None,
+            output: pinout.output,
+            direction: pinout.direction,
+            pinout,
+            clock_divisor: 0,
+            mpsse_enabled: false,
+            swd_idle_cycles: 8,
+            swd_turnaround: 1,
         })
     }
 
     pub fn attach(&mut self) -> Result<(), ftdi::Error> {
         self.device.usb_reset()?;
-        self.device.set_latency_timer(1)?;
-        self.device.set_bitmode(0x0b, ftdi::BitMode::Mpsse)?;
+        self.device
+            .set_bitmode(FtdiPinout::JTAG_DIRECTION as u8, ftdi::BitMode::Mpsse)?;
         self.device.usb_purge_buffers()?;
 
         let mut junk = vec![];
         let _ = self.device.read_to_end(&mut junk);
+        self.mpsse_enabled = true;
 
-        // Minimal values, may not work with all probes
-        let output: u16 = 0x0008;
-        let direction: u16 = 0x000b;
-        self.device
-            .write_all(&[0x80, output as u8, direction as u8])?;
-        self.device
-            .write_all(&[0x82, (output >> 8) as u8, (direction >> 8) as u8])?;
+        // Disable the divide-by-5 prescaler, so the MPSSE clock is 60 MHz.
+        self.device.write_all(&[0x8a])?;
+        self.set_clock_divisor(self.clock_divisor)?;
+
+        self.output = self.pinout.output;
+        self.direction = self.pinout.direction | FtdiPinout::JTAG_DIRECTION;
+        // The reset is released until it is asserted explicitly.
+        self.direction &= !self.pinout.nrst_mask();
+        self.write_pins()?;
 
         // Disable loopback
         self.device.write_all(&[0x85])?;
@@ -69,6 +194,66 @@ impl JtagAdapter {
         Ok(())
     }
 
+    /// Uses `pinout` from now on, the pins are set up again if the adapter is attached already.
+    pub fn set_pinout(&mut self, pinout: FtdiPinout) -> io::Result<()> {
+        self.pinout = pinout;
+        self.output = self.pinout.output;
+        self.direction =
+            (self.pinout.direction | FtdiPinout::JTAG_DIRECTION) & !self.pinout.nrst_mask();
+
+        if self.mpsse_enabled {
+            self.write_pins()?;
+        }
+        Ok(())
+    }
+
+    /// Sets the TCK divisor, which is applied once the MPSSE is enabled.
+    pub fn set_clock_divisor(&mut self, divisor: u16) -> io::Result<()> {
+        self.clock_divisor = divisor;
+
+        if self.mpsse_enabled {
+            let [low, high] = divisor.to_le_bytes();
+            self.device.write_all(&[0x86, low, high])?;
+        }
+        Ok(())
+    }
+
+    /// Writes the level and direction of all pins.
+    fn write_pins(&mut self) -> io::Result<()> {
+        let [output_low, output_high] = self.output.to_le_bytes();
+        let [direction_low, direction_high] = self.direction.to_le_bytes();
+
+        self.device.write_all(&[
+            0x80,
+            output_low,
+            direction_low,
+            0x82,
+            output_high,
+            direction_high,
+        ])
+    }
+
+    /// Drives the reset pin low, or releases it.
+    pub fn set_reset(&mut self, asserted: bool) -> io::Result<()> {
+        let mask = match self.pinout.nrst {
+            Some(pin) => 1 << pin,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "the pinout has no reset pin",
+                ))
+            }
+        };
+
+        self.output &= !mask;
+        if asserted {
+            self.direction |= mask;
+        } else {
+            self.direction &= !mask;
+        }
+        self.write_pins()
+    }
+
     fn read_response(&mut self, size: usize) -> io::Result<Vec<u8>> {
         let timeout = Duration::from_millis(10);
         let mut result = Vec::new();
@@ -286,6 +471,29 @@ impl JtagAdapter {
         Ok(targets)
     }
 
+    /// Reset the TAPs, shift `tdi` through IR or DR and return the bits shifted out
+    fn transfer_after_reset(&mut self, ir: bool, tdi: &[bool]) -> io::Result<Vec<bool>> {
+        let request: BitVec<Lsb0, u8> = tdi.iter().copied().collect();
+
+        self.reset()?;
+        let reply = if ir {
+            self.transfer_ir(request.as_slice(), tdi.len())?
+        } else {
+            self.transfer_dr(request.as_slice(), tdi.len())?
+        };
+
+        let reply = BitVec::<Lsb0, u8>::from_vec(reply);
+        Ok(reply.iter().take(tdi.len()).copied().collect())
+    }
+
+    /// List all TAPs on the chain, including the ones without an IDCODE
+    pub fn scan_chain(&mut self) -> io::Result<Vec<JtagDevice>> {
+        let dr_bits = self.transfer_after_reset(false, &jtag::dr_scan_input())?;
+        let ir_bits = self.transfer_after_reset(true, &jtag::ir_scan_input())?;
+
+        Ok(jtag::decode_chain(&dr_bits, &ir_bits))
+    }
+
     pub fn select_target(&mut self, idcode: u32) -> io::Result<()> {
         let taps = self.scan()?;
 
@@ -389,6 +597,51 @@ pub struct FtdiProbe {
     adapter: Mutex<JtagAdapter>,
     speed_khz: u32,
     idle_cycles: u8,
+    protocol: WireProtocol,
+}
+
+impl FtdiProbe {
+    /// Uses `pinout` instead of the default pins, see `FtdiPinout`.
+    pub fn set_pinout(&mut self, pinout: FtdiPinout) -> Result<(), DebugProbeError> {
+        self.adapter
+            .get_mut()
+            .unwrap()
+            .set_pinout(pinout)
+            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))
+    }
+
+    fn attach_jtag(&mut self) -> Result<(), DebugProbeError> {
+        let adapter = self.adapter.get_mut().unwrap();
+
+        let taps = adapter
+            .scan()
+            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
+        if taps.is_empty() {
+            log::warn!("no JTAG taps detected");
+            return Err(DebugProbeError::TargetNotFound);
+        }
+        if taps.len() == 1 {
+            adapter
+                .select_target(taps[0].idcode)
+                .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
+        } else {
+            let known_idcodes = [
+                0x1000563d, // GD32VF103
+            ];
+            let idcode = taps
+                .iter()
+                .map(|tap| tap.idcode)
+                .find(|idcode| known_idcodes.iter().any(|v| v == idcode));
+            if let Some(idcode) = idcode {
+                adapter
+                    .select_target(idcode)
+                    .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
+            } else {
+                return Err(DebugProbeError::TargetNotFound);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl DebugProbe for FtdiProbe {
@@ -401,19 +654,30 @@ impl DebugProbe for FtdiProbe {
         let selector = selector.into();
 
         // Only open FTDI probes
-        if selector.vendor_id != 0x0403 {
+        if selector.vendor_id != FTDI_VID {
             return Err(DebugProbeError::ProbeCouldNotBeCreated(
                 ProbeCreationError::NotFound,
             ));
         }
 
-        let adapter = JtagAdapter::open(selector.vendor_id, selector.product_id)
+        let mut adapter = JtagAdapter::open(
+            selector.vendor_id,
+            selector.product_id,
+            selector.serial_number.as_deref(),
+            FtdiPinout::default(),
+        )
+        .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
+
+        let (divisor, speed_khz) = clock_divisor(DEFAULT_SPEED_KHZ).unwrap();
+        adapter
+            .set_clock_divisor(divisor)
             .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
 
         let probe = FtdiProbe {
             adapter: Mutex::new(adapter),
-            speed_khz: 0,
+            speed_khz,
             idle_cycles: 0,
+            protocol: WireProtocol::Jtag,
         };
         log::debug!("opened probe: {:?}", probe);
         Ok(Box::new(probe))
@@ -428,9 +692,17 @@ impl DebugProbe for FtdiProbe {
     }
 
     fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
-        self.speed_khz = speed_khz;
-        // TODO
-        Ok(speed_khz)
+        let (divisor, actual_speed_khz) =
+            clock_divisor(speed_khz).ok_or(DebugProbeError::UnsupportedSpeed(speed_khz))?;
+
+        self.adapter
+            .get_mut()
+            .unwrap()
+            .set_clock_divisor(divisor)
+            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
+
+        self.speed_khz = actual_speed_khz;
+        Ok(actual_speed_khz)
     }
 
     fn attach(&mut self) -> Result<(), DebugProbeError> {
@@ -441,71 +713,124 @@ impl DebugProbe for FtdiProbe {
             .attach()
             .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
 
-        let taps = adapter
-            .scan()
-            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
-        if taps.is_empty() {
-            log::warn!("no JTAG taps detected");
-            return Err(DebugProbeError::TargetNotFound);
+        match self.protocol {
+            WireProtocol::Jtag => self.attach_jtag(),
+            WireProtocol::Swd => adapter
+                .swd_attach()
+                .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e))),
         }
-        if taps.len() == 1 {
-            adapter
-                .select_target(taps[0].idcode)
-                .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
-        } else {
-            let known_idcodes = [
-                0x1000563d, // GD32VF103
-            ];
-            let idcode = taps
-                .iter()
-                .map(|tap| tap.idcode)
-                .find(|idcode| known_idcodes.iter().any(|v| v == idcode));
-            if let Some(idcode) = idcode {
-                adapter
-                    .select_target(idcode)
-                    .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
-            } else {
-                return Err(DebugProbeError::TargetNotFound);
-            }
-        }
-        Ok(())
     }
 
     fn detach(&mut self) -> Result<(), DebugProbeError> {
         Ok(())
     }
 
+    fn scan_chain(&mut self) -> Result<Vec<JtagDevice>, DebugProbeError> {
+        let adapter = self.adapter.get_mut().unwrap();
+
+        adapter
+            .attach()
+            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
+
+        let devices = adapter
+            .scan_chain()
+            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))?;
+        log::debug!("JTAG scan chain: {:?}", devices);
+
+        Ok(devices)
+    }
+
     fn target_reset(&mut self) -> Result<(), DebugProbeError> {
-        log::error!("FTDI target_reset");
-        unimplemented!()
+        self.target_reset_assert()?;
+        std::thread::sleep(Duration::from_millis(10));
+        self.target_reset_deassert()
     }
 
     fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
-        log::error!("FTDI target_reset_assert");
-        unimplemented!()
+        // The reset pin of FTDI based adapters is board specific, it has to be set in the pinout.
+        if self.adapter.get_mut().unwrap().pinout.nrst.is_none() {
+            return Err(DebugProbeError::ResetPinNotAvailable);
+        }
+
+        self.adapter
+            .get_mut()
+            .unwrap()
+            .set_reset(true)
+            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))
     }
 
     fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
-        log::error!("FTDI target_reset_deassert");
-        unimplemented!()
+        if self.adapter.get_mut().unwrap().pinout.nrst.is_none() {
+            return Err(DebugProbeError::ResetPinNotAvailable);
+        }
+
+        self.adapter
+            .get_mut()
+            .unwrap()
+            .set_reset(false)
+            .map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))
     }
 
     fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
-        if protocol != WireProtocol::Jtag {
-            Err(DebugProbeError::UnsupportedProtocol(protocol))
+        self.protocol = protocol;
+        Ok(())
+    }
+
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
+        let adapter = self.adapter.get_mut().unwrap();
+        let bytes = bits.to_le_bytes();
+        let result = match self.protocol {
+            WireProtocol::Swd => adapter.swd_sequence(&bytes, bit_len as usize),
+            WireProtocol::Jtag if bit_len > 0 => adapter.shift_tms(&bytes, bit_len as usize),
+            WireProtocol::Jtag => Ok(()),
+        };
+
+        result.map_err(|e| DebugProbeError::ProbeSpecific(Box::new(e)))
+    }
+
+    fn has_arm_interface(&self) -> bool {
+        self.protocol == WireProtocol::Swd
+    }
+
+    fn get_arm_interface<'probe>(
+        self: Box<Self>,
+        config: &AttachConfig,
+    ) -> Result<Option<Box<dyn ArmProbeInterface + 'probe>>, DebugProbeError> {
+        if self.protocol == WireProtocol::Swd {
+            let interface = ArmCommunicationInterface::new(self, config)?;
+
+            Ok(Some(Box::new(interface)))
         } else {
-            Ok(())
+            Ok(None)
         }
     }
 
     fn get_riscv_interface(
         self: Box<Self>,
     ) -> Result<Option<RiscvCommunicationInterface>, DebugProbeError> {
-        Ok(Some(RiscvCommunicationInterface::new(self)?))
+        if self.protocol == WireProtocol::Jtag {
+            Ok(Some(RiscvCommunicationInterface::new(self)?))
+        } else {
+            Ok(None)
+        }
     }
 
     fn has_riscv_interface(&self) -> bool {
-        true
+        self.protocol == WireProtocol::Jtag
+    }
+
+    fn set_ftdi_pinout(&mut self, pinout: FtdiPinout) -> Result<(), DebugProbeError> {
+        self.set_pinout(pinout)
+    }
+
+    fn set_swd_idle_cycles(&mut self, cycles: u8) -> Result<(), DebugProbeError> {
+        self.adapter.get_mut().unwrap().swd_idle_cycles = cycles;
+        Ok(())
+    }
+
+    fn set_swd_turnaround(&mut self, periods: u8) -> Result<(), DebugProbeError> {
+        self.adapter.get_mut().unwrap().swd_turnaround = periods;
+        Ok(())
     }
 }
 
@@ -571,7 +896,7 @@ impl AsMut<dyn DebugProbe> for FtdiProbe {
 
 fn get_device_info(device: &rusb::Device<rusb::Context>) -> Option<DebugProbeInfo> {
     let d_desc = device.device_descriptor().ok()?;
-    if d_desc.vendor_id() != 0x0403 || d_desc.product_id() != 0x6010 {
+    if d_desc.vendor_id() != FTDI_VID || !MPSSE_PIDS.contains(&d_desc.product_id()) {
         return None;
     }
 
@@ -586,6 +911,7 @@ fn get_device_info(device: &rusb::Device<rusb::Context>) -> Option<DebugProbeInf
         product_id: d_desc.product_id(),
         serial_number: sn_str,
         probe_type: DebugProbeType::FTDI,
+        usb_path: usb_path(device),
     })
 }
 
@@ -598,3 +924,18 @@ pub(crate) fn list_ftdi_devices() -> Vec<DebugProbeInfo> {
         Err(_) => vec![],
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clock_divisor_does_not_exceed_speed() {
+        assert_eq!(clock_divisor(30_000), Some((0, 30_000)));
+        assert_eq!(clock_divisor(100_000), Some((0, 30_000)));
+        assert_eq!(clock_divisor(1_000), Some((29, 1_000)));
+        assert_eq!(clock_divisor(4_000), Some((7, 3_750)));
+        assert_eq!(clock_divisor(1), Some((29_999, 1)));
+        assert_eq!(clock_divisor(0), None);
+    }
+}
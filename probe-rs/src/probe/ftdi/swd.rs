@@ -0,0 +1,242 @@
+//! SWD over the MPSSE, with SWDIO driven on TDI and read on TDO, see `FtdiPinout`.
+
+use super::{FtdiPinout, FtdiProbe, JtagAdapter};
+use crate::architecture::arm::{DapError, PortType};
+use crate::probe::{DAPAccess, DebugProbe, DebugProbeError};
+use std::convert::TryInto;
+use std::io::{self, Write};
+
+/// MPSSE commands, which write on the falling and read on the rising edge of SWCLK, LSB first.
+const WRITE_BYTES: u8 = 0x19;
+const WRITE_BITS: u8 = 0x1b;
+const READ_BYTES: u8 = 0x28;
+const READ_BITS: u8 = 0x2a;
+const CLOCK_BITS: u8 = 0x8e;
+const SEND_IMMEDIATE: u8 = 0x87;
+
+/// The acknowledge of a transfer, as received LSB first.
+const ACK_OK: u8 = 0b001;
+const ACK_WAIT: u8 = 0b010;
+const ACK_FAULT: u8 = 0b100;
+
+/// The DP register returning the result of the last AP read.
+const RDBUFF: u16 = 0x0c;
+
+/// Returns the request of a transfer, which starts with the start bit in bit 0.
+fn request(port: PortType, read: bool, address: u16) -> u8 {
+    let ap = matches!(port, PortType::AccessPort(_));
+    let a2 = address & 0x4 != 0;
+    let a3 = address & 0x8 != 0;
+    let parity = ap ^ read ^ a2 ^ a3;
+
+    // The start and park bits are always 1, the stop bit always 0.
+    0x81 | (ap as u8) << 1
+        | (read as u8) << 2
+        | (a2 as u8) << 3
+        | (a3 as u8) << 4
+        | (parity as u8) << 5
+}
+
+/// Returns the acknowledge from the turnaround and acknowledge bits read with `READ_BITS`.
+///
+/// Bits read with `READ_BITS` are shifted in from the top, so the 4 bits are in the high nibble.
+fn ack(response: u8) -> u8 {
+    (response >> 5) & 0b111
+}
+
+/// Returns the parity bit from the parity and turnaround bits read with `READ_BITS`.
+///
+/// The parity bit is read first, so it is followed by the `turnaround` bits in the top of the byte.
+fn parity(response: u8, turnaround: u8) -> bool {
+    (response >> (7 - turnaround)) & 1 == 1
+}
+
+/// Appends the commands to write `bits` bits of `data`.
+fn push_write(command: &mut Vec<u8>, data: &[u8], bits: usize) {
+    let full_bytes = bits / 8;
+    if full_bytes > 0 {
+        let [low, high] = ((full_bytes - 1) as u16).to_le_bytes();
+        command.extend_from_slice(&[WRITE_BYTES, low, high]);
+        command.extend_from_slice(&data[..full_bytes]);
+    }
+
+    let remaining = bits % 8;
+    if remaining > 0 {
+        command.extend_from_slice(&[WRITE_BITS, (remaining - 1) as u8, data[full_bytes]]);
+    }
+}
+
+impl JtagAdapter {
+    /// Returns the command to drive SWDIO, or to release it for the target.
+    fn swdio_command(&self, drive: bool) -> [u8; 3] {
+        let direction = if drive {
+            self.direction | FtdiPinout::SWDIO_OUT
+        } else {
+            self.direction & !FtdiPinout::SWDIO_OUT
+        };
+
+        [0x80, self.output as u8, direction as u8]
+    }
+
+    /// Clocks out the `bits` lowest bits of `data` on SWDIO.
+    pub(super) fn swd_sequence(&mut self, data: &[u8], bits: usize) -> io::Result<()> {
+        let mut command = self.swdio_command(true).to_vec();
+        push_write(&mut command, data, bits);
+        self.device.write_all(&command)
+    }
+
+    /// Switches the target from JTAG to SWD, and resets the SWD line.
+    pub(super) fn swd_attach(&mut self) -> io::Result<()> {
+        self.swd_sequence(&[0xff; 7], 56)?;
+        self.swd_sequence(&[0x9e, 0xe7], 16)?;
+        self.swd_sequence(&[0xff; 7], 56)?;
+        self.swd_sequence(&[0x00], 8)
+    }
+
+    /// Runs a single transfer, and returns its acknowledge together with the value read.
+    ///
+    /// The acknowledge is read back before the data phase, so that nothing is driven
+    /// on SWDIO after a WAIT or FAULT.
+    fn swd_transfer(
+        &mut self,
+        request: u8,
+        write: Option<u32>,
+    ) -> Result<(u8, Option<u32>), DebugProbeError> {
+        let turnaround = self.swd_turnaround;
+
+        let mut command = self.swdio_command(true).to_vec();
+        command.extend_from_slice(&[WRITE_BITS, 7, request]);
+        command.extend_from_slice(&self.swdio_command(false));
+        // The turnaround followed by the 3 acknowledge bits.
+        command.extend_from_slice(&[READ_BITS, turnaround + 2, SEND_IMMEDIATE]);
+        self.device.write_all(&command).map_err(probe_error)?;
+
+        let ack = ack(self.read_response(1).map_err(probe_error)?[0]);
+
+        let mut value = None;
+        let mut command = vec![];
+        if ack == ACK_OK && write.is_none() {
+            // 32 data bits, the parity bit and the turnaround.
+            self.device
+                .write_all(&[READ_BYTES, 3, 0, READ_BITS, turnaround, SEND_IMMEDIATE])
+                .map_err(probe_error)?;
+            let response = self.read_response(5).map_err(probe_error)?;
+
+            let data = u32::from_le_bytes(response[..4].try_into().unwrap());
+            if parity(response[4], turnaround) != (data.count_ones() % 2 == 1) {
+                return Err(DapError::IncorrectParity.into());
+            }
+            value = Some(data);
+        } else {
+            // The turnaround before SWDIO is driven again.
+            command.extend_from_slice(&[CLOCK_BITS, turnaround - 1]);
+        }
+
+        command.extend_from_slice(&self.swdio_command(true));
+        if let (ACK_OK, Some(data)) = (ack, write) {
+            push_write(&mut command, &data.to_le_bytes(), 32);
+            command.extend_from_slice(&[WRITE_BITS, 0, (data.count_ones() % 2) as u8]);
+        }
+        // Idle cycles, so the transfer is clocked through the debug port.
+        let idle_cycles = usize::from(self.swd_idle_cycles);
+        push_write(&mut command, &vec![0; (idle_cycles + 7) / 8], idle_cycles);
+        self.device.write_all(&command).map_err(probe_error)?;
+
+        Ok((ack, value))
+    }
+}
+
+fn probe_error(error: io::Error) -> DebugProbeError {
+    DebugProbeError::ProbeSpecific(Box::new(error))
+}
+
+impl FtdiProbe {
+    fn swd_transfer(
+        &mut self,
+        port: PortType,
+        address: u16,
+        write: Option<u32>,
+    ) -> Result<Option<u32>, DebugProbeError> {
+        let request = request(port, write.is_none(), address);
+        let (ack, value) = self
+            .adapter
+            .get_mut()
+            .unwrap()
+            .swd_transfer(request, write)?;
+
+        match ack {
+            ACK_OK => Ok(value),
+            // WAIT and FAULT are retried by the ARM communication interface.
+            ACK_WAIT => Err(DapError::WaitResponse.into()),
+            ACK_FAULT => Err(DapError::FaultResponse.into()),
+            _ => Err(DapError::NoAcknowledge.into()),
+        }
+    }
+}
+
+impl DAPAccess for FtdiProbe {
+    fn read_register(&mut self, port: PortType, address: u16) -> Result<u32, DebugProbeError> {
+        let value = self.swd_transfer(port, address, None)?;
+
+        // AP reads are posted, their result is returned by the next transfer.
+        match port {
+            PortType::AccessPort(_) => self
+                .swd_transfer(PortType::DebugPort, RDBUFF, None)
+                .map(Option::unwrap_or_default),
+            PortType::DebugPort => Ok(value.unwrap_or_default()),
+        }
+    }
+
+    fn write_register(
+        &mut self,
+        port: PortType,
+        address: u16,
+        value: u32,
+    ) -> Result<(), DebugProbeError> {
+        self.swd_transfer(port, address, Some(value)).map(|_| ())
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn request_bits() {
+        // DPIDR read, ABORT write and RDBUFF read.
+        assert_eq!(request(PortType::DebugPort, true, 0x0), 0xa5);
+        assert_eq!(request(PortType::DebugPort, false, 0x0), 0x81);
+        assert_eq!(request(PortType::DebugPort, true, RDBUFF), 0xbd);
+        // AP read of the DRW register.
+        assert_eq!(request(PortType::AccessPort(0), true, 0xc), 0x9f);
+    }
+
+    #[test]
+    fn ack_from_read_bits() {
+        // The turnaround bit is read first, followed by the acknowledge bits.
+        assert_eq!(ack(0b0011_0000), ACK_OK);
+        assert_eq!(ack(0b0101_1111), ACK_WAIT);
+        assert_eq!(ack(0b1000_0000), ACK_FAULT);
+    }
+
+    #[test]
+    fn parity_from_read_bits() {
+        assert!(parity(0b0100_0000, 1));
+        assert!(!parity(0b1011_1111, 1));
+        assert!(parity(0b0001_0000, 3));
+    }
+
+    #[test]
+    fn writes_are_split_into_bytes_and_bits() {
+        let mut command = vec![];
+        push_write(&mut command, &[0x9e, 0xe7, 0x03], 18);
+        assert_eq!(
+            command,
+            vec![WRITE_BYTES, 1, 0, 0x9e, 0xe7, WRITE_BITS, 1, 0x03]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Stable identifiers for debug probes, which stay the same across runs.
+
+use std::fmt;
+
+/// An identifier of a debug probe, which stays the same across runs, see
+/// `DebugProbeInfo::stable_identifier` and `Probe::open_by_identifier`.
+///
+/// Probes with a serial number are identified by their VID, PID and serial number, in the
+/// form `VID:PID:SERIAL`. This identifier is portable, it stays the same if the probe is
+/// plugged into another port or another computer.
+///
+/// Probes without a serial number are identified by a hash of the USB port they are plugged
+/// into instead, in the form `VID:PID@HASH`. This identifier is not portable, it changes
+/// when the probe is plugged into another port.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StableProbeId {
+    id: String,
+    portable: bool,
+}
+
+impl StableProbeId {
+    pub(crate) fn new(
+        vendor_id: u16,
+        product_id: u16,
+        serial_number: Option<&str>,
+        usb_path: Option<&str>,
+    ) -> Self {
+        match (serial_number, usb_path) {
+            (Some(serial_number), _) if !serial_number.is_empty() => Self {
+                id: format!("{:04x}:{:04x}:{}", vendor_id, product_id, serial_number),
+                portable: true,
+            },
+            (_, Some(usb_path)) => Self {
+                id: format!(
+                    "{:04x}:{:04x}@{:016x}",
+                    vendor_id,
+                    product_id,
+                    fnv1a(usb_path.as_bytes())
+                ),
+                portable: false,
+            },
+            // Without a serial and a path, probes of the same type cannot be told apart.
+            _ => Self {
+                id: format!("{:04x}:{:04x}", vendor_id, product_id),
+                portable: false,
+            },
+        }
+    }
+
+    /// Returns the identifier as a string, as accepted by `Probe::open_by_identifier`.
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns `true` if the identifier stays the same when the probe is plugged into another port.
+    pub fn is_portable(&self) -> bool {
+        self.portable
+    }
+}
+
+impl fmt::Display for StableProbeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.id)
+    }
+}
+
+/// Formats the USB topology path of a device, e.g. `1-3.2` for port 2 of a hub in port 3 of bus 1.
+pub(crate) fn format_usb_path(bus_number: u8, port_numbers: &[u8]) -> String {
+    let ports: Vec<String> = port_numbers.iter().map(|port| port.to_string()).collect();
+    format!("{}-{}", bus_number, ports.join("."))
+}
+
+/// Returns the USB topology path of a device, if it can be determined.
+pub(crate) fn usb_path<T: rusb::UsbContext>(device: &rusb::Device<T>) -> Option<String> {
+    let port_numbers = device.port_numbers().ok()?;
+    Some(format_usb_path(device.bus_number(), &port_numbers))
+}
+
+/// The 64 bit FNV-1a hash, which unlike the std hashers is guaranteed to stay the same across runs.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serial_number_is_portable() {
+        let id = StableProbeId::new(0x0483, 0x374b, Some("066DFF"), Some("1-3.2"));
+
+        assert_eq!(id.as_str(), "0483:374b:066DFF");
+        assert!(id.is_portable());
+    }
+
+    #[test]
+    fn path_is_hashed_without_serial_number() {
+        let id = StableProbeId::new(0x0d28, 0x0204, None, Some("1-3.2"));
+
+        assert_eq!(id.as_str(), "0d28:0204@8932d59ffa72f7d8");
+        assert!(!id.is_portable());
+
+        // An empty serial number does not identify the probe either.
+        assert_eq!(
+            StableProbeId::new(0x0d28, 0x0204, Some(""), Some("1-3.2")),
+            id
+        );
+    }
+
+    #[test]
+    fn probes_on_the_same_hub_are_distinct() {
+        let first = StableProbeId::new(0x0d28, 0x0204, None, Some(&format_usb_path(1, &[3, 1])));
+        let second = StableProbeId::new(0x0d28, 0x0204, None, Some(&format_usb_path(1, &[3, 2])));
+
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn usb_path_format() {
+        assert_eq!(format_usb_path(2, &[1, 4, 3]), "2-1.4.3");
+    }
+}
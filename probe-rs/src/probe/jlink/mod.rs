@@ -17,8 +17,8 @@ use crate::{
         riscv::communication_interface::RiscvCommunicationInterface,
     },
     probe::{
-        DAPAccess, DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeType, JTAGAccess,
-        WireProtocol,
+        format_usb_path, jtag, AttachConfig, DAPAccess, DebugProbe, DebugProbeError,
+        DebugProbeInfo, DebugProbeType, JTAGAccess, JtagDevice, WireProtocol,
     },
     DebugProbeSelector, Error as ProbeRsError,
 };
@@ -34,6 +34,9 @@ pub(crate) struct JLink {
     /// accesses to the DMI register
     jtag_idle_cycles: u8,
 
+    /// Idle cycles before each SWD transfer, which also follow the previous one.
+    swd_idle_cycles: u8,
+
     /// Currently selected protocol
     protocol: Option<WireProtocol>,
 
@@ -313,6 +316,36 @@ impl JLink {
 
         Ok(result)
     }
+
+    /// Resets the TAPs, shifts `tdi` through the instruction or data register path
+    /// and returns the bits shifted out on TDO.
+    fn scan_after_reset(&mut self, ir: bool, tdi: &[bool]) -> Result<Vec<bool>, DebugProbeError> {
+        let tms_reset = [true, true, true, true, true, false];
+        let tms_enter_shift: &[bool] = if ir {
+            &[true, true, false, false]
+        } else {
+            &[true, false, false]
+        };
+        let tms_enter_idle = [true, false];
+
+        let mut tms = Vec::with_capacity(tms_reset.len() + tms_enter_shift.len() + tdi.len() + 2);
+        tms.extend_from_slice(&tms_reset);
+        tms.extend_from_slice(tms_enter_shift);
+        tms.resize(tms.len() + tdi.len() - 1, false);
+        // The last bit is shifted when leaving the shift state.
+        tms.push(true);
+        tms.extend_from_slice(&tms_enter_idle);
+
+        let prefix_len = tms_reset.len() + tms_enter_shift.len();
+        let mut tdi_bits = vec![false; prefix_len];
+        tdi_bits.extend_from_slice(tdi);
+        tdi_bits.resize(tms.len(), false);
+
+        let jlink = self.handle.get_mut().unwrap();
+        let response = jlink.jtag_io(tms, tdi_bits)?;
+
+        Ok(response.skip(prefix_len).take(tdi.len()).collect())
+    }
 }
 
 impl DebugProbe for JLink {
@@ -322,7 +355,10 @@ impl DebugProbe for JLink {
         let selector = selector.into();
         let mut jlinks = jaylink::scan_usb()?
             .filter_map(|usb_info| {
-                if usb_info.vid() == selector.vendor_id && usb_info.pid() == selector.product_id {
+                if usb_info.vid() == selector.vendor_id
+                    && usb_info.pid() == selector.product_id
+                    && selector.matches_usb_path(Some(&jlink_usb_path(&usb_info)))
+                {
                     let device = usb_info.open();
                     if let Some(serial_number) = selector.serial_number.as_deref() {
                         if device
@@ -393,6 +429,7 @@ impl DebugProbe for JLink {
             swo_config: None,
             supported_protocols,
             jtag_idle_cycles: 0,
+            swd_idle_cycles: 2,
             protocol: None,
             current_ir_reg: 1,
             speed_khz: 0,
@@ -567,6 +604,40 @@ impl DebugProbe for JLink {
         Ok(())
     }
 
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
+        let jlink = self.handle.get_mut().unwrap();
+        let sequence = (0..bit_len).map(|bit| bits >> bit & 1 == 1);
+
+        // The sequence is sent on SWDIO in SWD mode, and on TMS in JTAG mode.
+        match self.protocol {
+            Some(WireProtocol::Jtag) => {
+                jlink.jtag_io(sequence, iter::repeat_n(false, bit_len as usize))?;
+            }
+            _ => {
+                jlink.swd_io(iter::repeat_n(true, bit_len as usize), sequence)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn scan_chain(&mut self) -> Result<Vec<JtagDevice>, DebugProbeError> {
+        if self.protocol == Some(WireProtocol::Swd) {
+            return Err(DebugProbeError::NotSupported(
+                "JTAG scan chains in SWD mode",
+            ));
+        }
+        self.select_interface(Some(WireProtocol::Jtag))?;
+
+        let dr_bits = self.scan_after_reset(false, &jtag::dr_scan_input())?;
+        let ir_bits = self.scan_after_reset(true, &jtag::ir_scan_input())?;
+
+        let devices = jtag::decode_chain(&dr_bits, &ir_bits);
+        log::debug!("JTAG scan chain: {:?}", devices);
+
+        Ok(devices)
+    }
+
     fn detach(&mut self) -> Result<(), super::DebugProbeError> {
         unimplemented!()
     }
@@ -587,6 +658,13 @@ impl DebugProbe for JLink {
         Ok(())
     }
 
+    /// The turnaround is handled by the J-Link when switching the direction of SWDIO,
+    /// so only the idle cycles can be configured.
+    fn set_swd_idle_cycles(&mut self, cycles: u8) -> Result<(), DebugProbeError> {
+        self.swd_idle_cycles = cycles;
+        Ok(())
+    }
+
     fn get_riscv_interface(
         self: Box<Self>,
     ) -> Result<Option<RiscvCommunicationInterface>, DebugProbeError> {
@@ -607,9 +685,10 @@ impl DebugProbe for JLink {
 
     fn get_arm_interface<'probe>(
         self: Box<Self>,
+        config: &AttachConfig,
     ) -> Result<Option<Box<dyn ArmProbeInterface + 'probe>>, DebugProbeError> {
         if self.supported_protocols.contains(&WireProtocol::Swd) {
-            let interface = ArmCommunicationInterface::new(self)?;
+            let interface = ArmCommunicationInterface::new(self, config)?;
 
             Ok(Some(Box::new(interface)))
         } else {
@@ -624,6 +703,41 @@ impl DebugProbe for JLink {
     fn has_riscv_interface(&self) -> bool {
         self.supported_protocols.contains(&WireProtocol::Jtag)
     }
+
+    fn target_voltage(&mut self) -> Option<f32> {
+        let jlink = self.handle.get_mut().unwrap();
+        match jlink.read_target_voltage() {
+            Ok(millivolts) => Some(f32::from(millivolts) / 1000.0),
+            Err(e) => {
+                log::warn!("Failed to read the target voltage: {}", e);
+                None
+            }
+        }
+    }
+
+    /// The J-Link executes every register access of a block as a separate SWD transfer.
+    fn max_block_size(&self) -> usize {
+        usize::MAX
+    }
+
+    /// J-Links with the kickstart power capability can supply 5V on pin 19.
+    fn can_power_target(&self) -> bool {
+        let jlink = self.handle.lock().unwrap();
+        jlink
+            .read_capabilities()
+            .map(|capabilities| capabilities.contains(jaylink::Capabilities::SET_KS_POWER))
+            .unwrap_or(false)
+    }
+
+    fn set_target_power(&mut self, on: bool) -> Result<(), DebugProbeError> {
+        if !self.can_power_target() {
+            return Err(DebugProbeError::NotSupported(
+                "supplying power to the target",
+            ));
+        }
+        let jlink = self.handle.get_mut().unwrap();
+        Ok(jlink.set_kickstart_power(on)?)
+    }
 }
 
 impl JTAGAccess for JLink {
@@ -714,10 +828,10 @@ impl DAPAccess for JLink {
         let a3 = (address >> 3) & 0x01 == 1;
 
         // Now we assemble an SWD read request.
-        let mut swd_io_sequence = vec![
-            // First we make sure we have the SDWIO line on idle for at least 2 clock cylces.
-            false, // Line idle.
-            false, // Line idle.
+        // First we make sure we have the SDWIO line on idle for the idle cycles, 2 by default.
+        let idle_cycles = usize::from(self.swd_idle_cycles);
+        let mut swd_io_sequence = vec![false; idle_cycles];
+        swd_io_sequence.extend_from_slice(&[
             // Then we assemble the actual request.
             true,                  // Start bit (always 1).
             port,                  // APnDP (0 for DP, 1 for AP).
@@ -733,7 +847,7 @@ impl DAPAccess for JLink {
             false, // ACK bit.
             false, // ACK bit.
             false, // ACK bit.
-        ];
+        ]);
 
         // Add the data bits to the SWDIO sequence.
         swd_io_sequence.extend_from_slice(&[false; 32]);
@@ -746,7 +860,7 @@ impl DAPAccess for JLink {
 
         // Assemble the direction sequence.
         let direction = iter::repeat(true)
-            .take(2) // Transmit 2 Line idle bits.
+            .take(idle_cycles) // Transmit the line idle bits.
             .chain(iter::repeat(true).take(8)) // Transmit 8 Request bits
             // Here *should* be a Trn bit, but since something with the spec is akward we leave it away.
             // See comments above!
@@ -766,8 +880,8 @@ impl DAPAccess for JLink {
                 .unwrap()
                 .swd_io(direction.clone(), swd_io_sequence.iter().copied())?;
 
-            // Throw away the two idle bits.
-            result_sequence.split_off(2);
+            // Throw away the idle bits.
+            result_sequence.split_off(idle_cycles);
             // Throw away the request bits.
             result_sequence.split_off(8);
 
@@ -856,9 +970,9 @@ impl DAPAccess for JLink {
         let a3 = (address >> 3) & 0x01 == 1;
 
         // Now we assemble an SWD write request.
-        let mut swd_io_sequence = vec![
-            false, // Line idle.
-            false, // Line idle.
+        let idle_cycles = usize::from(self.swd_idle_cycles);
+        let mut swd_io_sequence = vec![false; idle_cycles];
+        swd_io_sequence.extend_from_slice(&[
             // Then we assemble the actual request.
             true,                   // Start bit (always 1).
             port,                   // APnDP (0 for DP, 1 for AP).
@@ -878,7 +992,7 @@ impl DAPAccess for JLink {
             // This seems to not be the case in actual implementations. So we insert two turnaround bits here!
             false, // Turnaround bit.
             false, // Turnaround bit.
-        ];
+        ]);
 
         // Now we add all the data bits to the sequence and in the same loop we also calculate the parity bit.
         let mut parity = false;
@@ -894,7 +1008,7 @@ impl DAPAccess for JLink {
 
         // Assemble the direction sequence.
         let direction = iter::repeat(true)
-            .take(2) // Transmit 2 Line idle bits.
+            .take(idle_cycles) // Transmit the line idle bits.
             .chain(iter::repeat(true).take(8)) // Transmit 8 Request bits
             // Here *should* be a Trn bit, but since something with the spec is akward we leave it away.
             // See comments above!
@@ -914,8 +1028,8 @@ impl DAPAccess for JLink {
                 .unwrap()
                 .swd_io(direction.clone(), swd_io_sequence.iter().copied())?;
 
-            // Throw away the two idle bits.
-            result_sequence.split_off(2);
+            // Throw away the idle bits.
+            result_sequence.split_off(idle_cycles);
             // Throw away the request bits.
             result_sequence.split_off(8);
 
@@ -1026,6 +1140,7 @@ pub(crate) fn list_jlink_devices() -> Result<impl Iterator<Item = DebugProbeInfo
     Ok(jaylink::scan_usb()?.map(|device_info| {
         let vid = device_info.vid();
         let pid = device_info.pid();
+        let usb_path = jlink_usb_path(&device_info);
         let (serial, product) = if let Ok(device) = device_info.open() {
             let serial = device.serial_string();
             let serial = if serial.is_empty() {
@@ -1053,9 +1168,15 @@ pub(crate) fn list_jlink_devices() -> Result<impl Iterator<Item = DebugProbeInfo
             serial,
             DebugProbeType::JLink,
         )
+        .with_usb_path(Some(usb_path))
     }))
 }
 
+/// Returns the USB path of a J-Link, which only contains the last port number of the device.
+fn jlink_usb_path(device_info: &jaylink::UsbDeviceInfo) -> String {
+    format_usb_path(device_info.bus_number(), &[device_info.port_number()])
+}
+
 impl From<jaylink::Error> for DebugProbeError {
     fn from(e: jaylink::Error) -> DebugProbeError {
         DebugProbeError::ProbeSpecific(Box::new(e))
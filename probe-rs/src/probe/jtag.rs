@@ -0,0 +1,202 @@
+//! Helpers for discovering the devices on a JTAG scan chain.
+//!
+//! The probe specific part of a scan only has to shift bits through the DR and IR
+//! paths after a TAP reset, the decoding of the captured bits is done here.
+
+/// The maximum number of TAPs which are detected on a scan chain.
+pub(crate) const MAX_CHAIN_LENGTH: usize = 16;
+
+/// The maximum total length of the instruction registers on a scan chain.
+pub(crate) const MAX_IR_CHAIN_LENGTH: usize = 128;
+
+/// A device (TAP) found on a JTAG scan chain.
+///
+/// The devices are listed starting with the one closest to TDO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JtagDevice {
+    /// The IDCODE of the device, `None` for devices which select BYPASS after a reset.
+    pub idcode: Option<u32>,
+    /// The length of the instruction register, `None` if it could not be determined.
+    pub ir_len: Option<usize>,
+}
+
+/// Bits to shift into the data registers after a TAP reset.
+///
+/// All ones are used, as no valid IDCODE can be all ones.
+pub(crate) fn dr_scan_input() -> Vec<bool> {
+    vec![true; (MAX_CHAIN_LENGTH + 1) * 32]
+}
+
+/// Bits to shift into the instruction registers after a TAP reset.
+///
+/// The chain is first flushed with zeros, the position of the first one
+/// coming out again is the total length of the instruction registers.
+pub(crate) fn ir_scan_input() -> Vec<bool> {
+    let mut bits = vec![false; MAX_IR_CHAIN_LENGTH];
+    bits.resize(2 * MAX_IR_CHAIN_LENGTH, true);
+    bits
+}
+
+/// Decodes the data register values captured after a TAP reset.
+///
+/// A TAP either captures its 32 bit IDCODE, which always has the lowest bit set,
+/// or its single BYPASS bit, which is zero.
+pub(crate) fn parse_idcodes(bits: &[bool]) -> Vec<Option<u32>> {
+    let mut idcodes = Vec::new();
+    let mut position = 0;
+
+    while position < bits.len() && idcodes.len() < MAX_CHAIN_LENGTH {
+        if !bits[position] {
+            idcodes.push(None);
+            position += 1;
+            continue;
+        }
+
+        if position + 32 > bits.len() {
+            break;
+        }
+
+        let idcode = bits_to_u32(&bits[position..position + 32]);
+
+        // Once the ones shifted in come out again, the end of the chain is reached.
+        if idcode == 0xffff_ffff {
+            break;
+        }
+
+        idcodes.push(Some(idcode));
+        position += 32;
+    }
+
+    idcodes
+}
+
+/// Splits the instruction register values captured after a TAP reset across `tap_count` TAPs.
+///
+/// Every TAP captures a value ending in `0b01`. When the remaining captured bits make it
+/// impossible to tell where the instruction registers start, the lengths are reported as `None`.
+pub(crate) fn parse_ir_lengths(bits: &[bool], tap_count: usize) -> Vec<Option<usize>> {
+    let total = match bits.iter().skip(MAX_IR_CHAIN_LENGTH).position(|bit| *bit) {
+        Some(total) => total,
+        None => return vec![None; tap_count],
+    };
+
+    if tap_count == 1 {
+        return vec![Some(total)];
+    }
+
+    let captured = &bits[..total];
+    let starts: Vec<usize> = (0..total)
+        .filter(|&i| captured[i] && !captured.get(i + 1).copied().unwrap_or(true))
+        .collect();
+
+    if starts.len() != tap_count || starts.first() != Some(&0) {
+        log::debug!(
+            "Unable to split {} IR bits across {} TAPs: {:?}",
+            total,
+            tap_count,
+            captured
+        );
+        return vec![None; tap_count];
+    }
+
+    starts
+        .iter()
+        .zip(starts.iter().skip(1).chain(std::iter::once(&total)))
+        .map(|(start, end)| Some(end - start))
+        .collect()
+}
+
+/// Combines the decoded data and instruction register scans into the list of devices.
+pub(crate) fn decode_chain(dr_bits: &[bool], ir_bits: &[bool]) -> Vec<JtagDevice> {
+    let idcodes = parse_idcodes(dr_bits);
+    let ir_lengths = parse_ir_lengths(ir_bits, idcodes.len());
+
+    idcodes
+        .into_iter()
+        .zip(ir_lengths)
+        .map(|(idcode, ir_len)| JtagDevice { idcode, ir_len })
+        .collect()
+}
+
+fn bits_to_u32(bits: &[bool]) -> u32 {
+    bits.iter()
+        .enumerate()
+        .fold(0, |value, (i, bit)| value | ((*bit as u32) << i))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn u32_bits(value: u32) -> Vec<bool> {
+        (0..32).map(|i| value & (1 << i) != 0).collect()
+    }
+
+    /// Simulates shifting `input` through a chain which captured `captured`.
+    fn shift(captured: &[bool], input: &[bool]) -> Vec<bool> {
+        captured
+            .iter()
+            .chain(input.iter())
+            .take(input.len())
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn idcodes_with_bypass_devices() {
+        let mut captured = u32_bits(0x4ba0_0477);
+        captured.push(false);
+        captured.extend(u32_bits(0x0692_5041));
+
+        let idcodes = parse_idcodes(&shift(&captured, &dr_scan_input()));
+
+        assert_eq!(idcodes, vec![Some(0x4ba0_0477), None, Some(0x0692_5041)]);
+    }
+
+    #[test]
+    fn empty_chain() {
+        let input = dr_scan_input();
+
+        assert!(parse_idcodes(&input).is_empty());
+    }
+
+    #[test]
+    fn ir_lengths_are_split() {
+        // IR lengths of 4, 5 and 2, captured values 0b0001, 0b00001 and 0b01.
+        let captured = [
+            true, false, false, false, true, false, false, false, false, true, false,
+        ];
+
+        let lengths = parse_ir_lengths(&shift(&captured, &ir_scan_input()), 3);
+
+        assert_eq!(lengths, vec![Some(4), Some(5), Some(2)]);
+    }
+
+    #[test]
+    fn ambiguous_ir_capture() {
+        // The first TAP captures 0b0101, which looks like two TAPs.
+        let captured = [true, false, true, false, true, false, false];
+
+        let lengths = parse_ir_lengths(&shift(&captured, &ir_scan_input()), 2);
+
+        assert_eq!(lengths, vec![None, None]);
+    }
+
+    #[test]
+    fn single_tap_uses_total_ir_length() {
+        let captured = [true, false, true, true, false];
+
+        let devices = decode_chain(
+            &shift(&u32_bits(0x1000_563d), &dr_scan_input()),
+            &shift(&captured, &ir_scan_input()),
+        );
+
+        assert_eq!(
+            devices,
+            vec![JtagDevice {
+                idcode: Some(0x1000_563d),
+                ir_len: Some(5)
+            }]
+        );
+    }
+}
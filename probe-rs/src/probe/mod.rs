@@ -1,20 +1,33 @@
 pub(crate) mod daplink;
 #[cfg(feature = "ftdi")]
 pub(crate) mod ftdi;
+mod identifier;
 pub(crate) mod jlink;
+mod jtag;
 pub(crate) mod stlink;
 
 use crate::architecture::{
-    arm::{communication_interface::ArmProbeInterface, DAPAccess, PortType, SwoAccess},
+    arm::{
+        authentication::{DebugAuthenticator, NoAuthentication},
+        communication_interface::ArmProbeInterface,
+        dp::{multidrop_selection_sequence, DpAddress, SwjSequence},
+        memory::TAR_AUTO_INCREMENT_BLOCK_SIZE,
+        DAPAccess, PortType, SwoAccess,
+    },
     riscv::communication_interface::RiscvCommunicationInterface,
 };
 use crate::config::{RegistryError, TargetSelector};
 use crate::error::Error;
 use crate::Session;
 use jlink::list_jlink_devices;
-use std::{convert::TryFrom, fmt};
+use std::{convert::TryFrom, fmt, time::Duration};
 use thiserror::Error;
 
+pub use identifier::StableProbeId;
+pub use jtag::JtagDevice;
+
+pub(crate) use identifier::{format_usb_path, usb_path};
+
 #[derive(Copy, Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
 pub enum WireProtocol {
     Swd,
@@ -93,6 +106,8 @@ pub enum DebugProbeError {
     InterfaceInUse,
     #[error("The requested speed setting ({0} kHz) is not supported by the probe")]
     UnsupportedSpeed(u32),
+    #[error("An SWD turnaround period of {0} clock cycles is not supported, it has to be between 1 and 4")]
+    UnsupportedTurnaround(u8),
     #[error("You need to be attached to the target to perform this action")]
     NotAttached,
     #[error("You need to be detached from the target to perform this action")]
@@ -105,8 +120,12 @@ pub enum DebugProbeError {
     BatchError(BatchCommand),
     #[error("Command not supported by probe")]
     CommandNotSupportedByProbe,
+    #[error("The probe does not support {0}")]
+    NotSupported(&'static str),
     #[error("Unable to set hardware breakpoint, all available breakpoint units are in use.")]
     BreakpointUnitsExceeded,
+    #[error("The probe cannot drive the reset pin of the target. Either the probe has no reset pin, or it is not connected to the target.")]
+    ResetPinNotAvailable,
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -147,6 +166,19 @@ pub enum ProbeCreationError {
 pub struct Probe {
     inner: Box<dyn DebugProbe>,
     attached: bool,
+    dp_address: DpAddress,
+    /// The sequence which switches the SWJ-DP to the protocol, chosen by the protocol if `None`.
+    swj_sequence: Option<SwjSequence>,
+    /// The speed in kHz to switch to after the debug port has been powered up.
+    operating_speed_khz: Option<u32>,
+    /// The identifier the probe was opened with, which is used to reconnect to it.
+    stable_id: Option<StableProbeId>,
+    /// The protocol and speed selected before attaching, which are restored when reconnecting.
+    protocol: Option<WireProtocol>,
+    connect_speed_khz: Option<u32>,
+    /// Unlocks the debug access while attaching, if the target requires an authentication.
+    authenticator: Box<dyn DebugAuthenticator>,
+    attach_config: AttachConfig,
 }
 
 impl Probe {
@@ -154,6 +186,14 @@ impl Probe {
         Self {
             inner: Box::new(probe),
             attached: false,
+            dp_address: DpAddress::Default,
+            swj_sequence: None,
+            operating_speed_khz: None,
+            stable_id: None,
+            protocol: None,
+            connect_speed_khz: None,
+            authenticator: Box::new(NoAuthentication),
+            attach_config: AttachConfig::default(),
         }
     }
 
@@ -161,6 +201,14 @@ impl Probe {
         Self {
             inner: probe,
             attached: true,
+            dp_address: DpAddress::Default,
+            swj_sequence: None,
+            operating_speed_khz: None,
+            stable_id: None,
+            protocol: None,
+            connect_speed_khz: None,
+            authenticator: Box::new(NoAuthentication),
+            attach_config: AttachConfig::default(),
         }
     }
 
@@ -168,6 +216,14 @@ impl Probe {
         Probe {
             inner: probe,
             attached: false,
+            dp_address: DpAddress::Default,
+            swj_sequence: None,
+            operating_speed_khz: None,
+            stable_id: None,
+            protocol: None,
+            connect_speed_khz: None,
+            authenticator: Box::new(NoAuthentication),
+            attach_config: AttachConfig::default(),
         }
     }
 
@@ -218,6 +274,27 @@ impl Probe {
         ))
     }
 
+    /// Opens the probe with the given stable identifier, see `DebugProbeInfo::stable_identifier`.
+    ///
+    /// This always opens the same physical probe, regardless of the order the probes are listed in.
+    pub fn open_by_identifier(identifier: &str) -> Result<Self, DebugProbeError> {
+        let mut matching = Self::list_all()
+            .into_iter()
+            .filter(|info| info.stable_identifier().as_str() == identifier);
+
+        match (matching.next(), matching.next()) {
+            (Some(info), None) => info.open(),
+            (Some(_), Some(_)) => Err(DebugProbeError::ProbeCouldNotBeCreated(
+                ProbeCreationError::Other(
+                    "Multiple probes have the same identifier, they cannot be told apart.",
+                ),
+            )),
+            (None, _) => Err(DebugProbeError::ProbeCouldNotBeCreated(
+                ProbeCreationError::NotFound,
+            )),
+        }
+    }
+
     // /// Tries to mass erase a locked nRF52 chip, this process may timeout, if it does, the chip
     // /// might be unlocked or not, it is advised to try again if flashing fails
     // pub fn nrf_recover(&mut self) -> Result<(), DebugProbeError> {
@@ -282,23 +359,28 @@ impl Probe {
     ///
     /// If this doesn't work, you might want to try `attach_under_reset`
     pub fn attach(mut self, target: impl Into<TargetSelector>) -> Result<Session, Error> {
-        self.inner.attach()?;
+        self.attach_with_retries()?;
         self.attached = true;
+        self.select_multidrop_target()?;
 
         Session::new(self, target, AttachMethod::Normal)
     }
 
     pub fn attach_to_unspecified(&mut self) -> Result<(), Error> {
-        self.inner.attach()?;
+        self.attach_with_retries()?;
         self.attached = true;
+        self.select_multidrop_target()?;
         Ok(())
     }
 
     /// Attach to the chip under hard-reset.
     ///
     /// This asserts the reset pin via the probe, plays the protocol init routines and deasserts the pin.
+    /// The core is halted before it executes the first instruction after the reset.
     /// This is necessary if the chip is not responding to the SWD reset sequence.
     /// For example this can happen if the chip has the SWDIO pin remapped.
+    ///
+    /// If the probe cannot drive the reset pin, `DebugProbeError::ResetPinNotAvailable` is returned.
     pub fn attach_under_reset(
         mut self,
         target: impl Into<TargetSelector>,
@@ -306,18 +388,162 @@ impl Probe {
         log::debug!("Asserting reset");
         self.inner.target_reset_assert()?;
 
-        self.inner.attach()?;
+        // The reset stays asserted across the retries.
+        if let Err(e) = self.attach_with_retries() {
+            // Don't keep the target in reset if we can't connect to it anyway.
+            if let Err(e) = self.inner.target_reset_deassert() {
+                log::warn!("Failed to deassert reset: {}", e);
+            }
+            return Err(e.into());
+        }
 
         self.attached = true;
 
+        self.select_multidrop_target()?;
+
         // The session will de-assert reset after connecting to the debug interface.
         Session::new(self, target, AttachMethod::UnderReset)
     }
 
+    /// Sets the timeout and the retries used while attaching, see `AttachConfig`.
+    pub fn set_attach_config(&mut self, config: AttachConfig) {
+        self.attach_config = config;
+    }
+
+    /// Runs the protocol init routines of the probe, retrying them as configured in the `AttachConfig`.
+    ///
+    /// Every retry starts over with the line reset or JTAG-to-SWD sequence, in case the target missed it.
+    fn attach_with_retries(&mut self) -> Result<(), DebugProbeError> {
+        let config = self.attach_config;
+        let mut retry = 0;
+
+        loop {
+            match self.inner.attach() {
+                Err(e) if retry < config.retry_count => {
+                    retry += 1;
+                    log::warn!(
+                        "Attaching failed, retrying ({}/{}): {}",
+                        retry,
+                        config.retry_count,
+                        e
+                    );
+                    std::thread::sleep(config.retry_delay);
+                }
+                result => return result,
+            }
+        }
+    }
+
     /// Selects the transport protocol to be used by the debug probe.
     pub fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
         if !self.attached {
-            self.inner.select_protocol(protocol)
+            self.inner.select_protocol(protocol)?;
+            self.protocol = Some(protocol);
+            Ok(())
+        } else {
+            Err(DebugProbeError::Attached)
+        }
+    }
+
+    /// Selects the debug port to connect to when attaching.
+    ///
+    /// `DpAddress::Multidrop` requires a probe which can send raw SWD sequences,
+    /// otherwise attaching fails with `DebugProbeError::NotSupported`.
+    pub fn select_debug_port(&mut self, dp_address: DpAddress) -> Result<(), DebugProbeError> {
+        if !self.attached {
+            self.dp_address = dp_address;
+            Ok(())
+        } else {
+            Err(DebugProbeError::Attached)
+        }
+    }
+
+    /// Sets the sequence which switches the SWJ-DP of the target to the wire protocol after attaching.
+    ///
+    /// By default, the legacy switching sequence of the selected protocol is sent, see
+    /// `SwjSequence::for_protocol`, if the probe can send raw sequences. Parts whose debug port
+    /// starts in the dormant state need `SwjSequence::DormantToSwd` or `SwjSequence::DormantToJtag`.
+    /// A sequence set explicitly fails to attach with `DebugProbeError::NotSupported` if the probe
+    /// cannot send it.
+    pub fn set_swj_sequence(
+        &mut self,
+        sequence: Option<SwjSequence>,
+    ) -> Result<(), DebugProbeError> {
+        if !self.attached {
+            self.swj_sequence = sequence;
+            Ok(())
+        } else {
+            Err(DebugProbeError::Attached)
+        }
+    }
+
+    /// Sets the authenticator which unlocks the debug access while attaching, and when reconnecting.
+    ///
+    /// By default, `NoAuthentication` is used, which never authenticates.
+    pub fn set_debug_authenticator(&mut self, authenticator: impl DebugAuthenticator + 'static) {
+        self.authenticator = Box::new(authenticator);
+    }
+
+    /// Takes the authenticator set with `set_debug_authenticator`, which is then owned by the session.
+    pub(crate) fn take_debug_authenticator(&mut self) -> Box<dyn DebugAuthenticator> {
+        std::mem::replace(&mut self.authenticator, Box::new(NoAuthentication))
+    }
+
+    /// Sends the SWJ sequence which switches the debug port to the protocol, followed by the SWD
+    /// multi-drop target selection sequence, if a multi-drop debug port is selected.
+    fn select_multidrop_target(&mut self) -> Result<(), DebugProbeError> {
+        self.send_swj_sequence()?;
+
+        if let DpAddress::Multidrop(targetsel) = self.dp_address {
+            log::debug!("Selecting multi-drop target {:#010x}", targetsel);
+            for (bit_len, bits) in multidrop_selection_sequence(targetsel) {
+                self.inner.swj_sequence(bit_len, bits)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the sequence set with `set_swj_sequence`, or the one of the selected protocol.
+    ///
+    /// The sequence chosen by the protocol is skipped if the probe cannot send raw sequences,
+    /// in which case the probe already switched the protocol while attaching. It is skipped for
+    /// multi-drop debug ports as well, whose selection sequence leaves the dormant state on its own.
+    fn send_swj_sequence(&mut self) -> Result<(), DebugProbeError> {
+        let sequence = match (self.swj_sequence, self.protocol, self.dp_address) {
+            (Some(sequence), _, _) => sequence,
+            (None, Some(protocol), DpAddress::Default) => SwjSequence::for_protocol(protocol),
+            _ => return Ok(()),
+        };
+
+        log::debug!("Sending the SWJ sequence {:?}", sequence);
+        for (bit_len, bits) in sequence.bits() {
+            match self.inner.swj_sequence(bit_len, bits) {
+                Err(DebugProbeError::NotSupported(_)) if self.swj_sequence.is_none() => {
+                    log::debug!("The probe cannot send raw SWJ sequences, skipping it");
+                    return Ok(());
+                }
+                result => result?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the devices on the JTAG scan chain.
+    ///
+    /// This can be used on boards with an unknown JTAG topology, before a target is selected.
+    pub fn scan_chain(&mut self) -> Result<Vec<JtagDevice>, DebugProbeError> {
+        self.inner.scan_chain()
+    }
+
+    /// Overrides the pins of an FTDI based probe, which are board specific.
+    ///
+    /// This has to be done before attaching, as the pins are set up when attaching.
+    #[cfg(feature = "ftdi")]
+    pub fn set_ftdi_pinout(&mut self, pinout: ftdi::FtdiPinout) -> Result<(), DebugProbeError> {
+        if !self.attached {
+            self.inner.set_ftdi_pinout(pinout)
         } else {
             Err(DebugProbeError::Attached)
         }
@@ -343,17 +569,103 @@ impl Probe {
     /// Configure protocol speed to use in kHz
     pub fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
         if !self.attached {
-            self.inner.set_speed(speed_khz)
+            let actual_speed_khz = self.inner.set_speed(speed_khz)?;
+            self.connect_speed_khz = Some(speed_khz);
+            Ok(actual_speed_khz)
         } else {
             Err(DebugProbeError::Attached)
         }
     }
 
+    /// Configure the protocol speed in kHz used while connecting to the target.
+    ///
+    /// The probe uses the closest speed it supports, which is returned. Probes which
+    /// only support a single speed ignore the connect speed.
+    pub fn set_connect_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        self.set_speed(speed_khz)
+    }
+
+    /// Configure the protocol speed in kHz used once the connection to the target is established.
+    ///
+    /// For ARM targets, the probe switches to this speed after the debug port has been
+    /// powered up. Until then, the speed set with [`Probe::set_connect_speed`] is used.
+    pub fn set_operating_speed(&mut self, speed_khz: u32) -> Result<(), DebugProbeError> {
+        if !self.attached {
+            self.operating_speed_khz = Some(speed_khz);
+            Ok(())
+        } else {
+            Err(DebugProbeError::Attached)
+        }
+    }
+
+    /// Sets the number of idle cycles clocked after each SWD transfer.
+    ///
+    /// Extra idle cycles can help targets which need more clock cycles to finish a transfer,
+    /// e.g. behind long cables. The default depends on the probe.
+    /// Probes whose firmware does not allow to configure them return `DebugProbeError::NotSupported`.
+    pub fn set_swd_idle_cycles(&mut self, cycles: u8) -> Result<(), DebugProbeError> {
+        self.inner.set_swd_idle_cycles(cycles)
+    }
+
+    /// Sets the length of the SWD turnaround period in clock cycles, from 1, the default, to 4.
+    ///
+    /// Probes whose firmware does not allow to configure it return `DebugProbeError::NotSupported`.
+    pub fn set_swd_turnaround(&mut self, periods: u8) -> Result<(), DebugProbeError> {
+        if !(1..=4).contains(&periods) {
+            return Err(DebugProbeError::UnsupportedTurnaround(periods));
+        }
+        self.inner.set_swd_turnaround(periods)
+    }
+
     /// Configured protocol speed in kHz
     pub fn speed_khz(&self) -> u32 {
         self.inner.speed()
     }
 
+    /// The maximum number of bytes the probe transfers to or from the memory of the target with one command.
+    pub fn max_block_size(&self) -> usize {
+        self.inner.max_block_size()
+    }
+
+    /// Measures the target reference voltage in volts, if the probe supports it.
+    ///
+    /// A voltage close to zero means that the target is not powered.
+    pub fn target_voltage(&mut self) -> Option<f32> {
+        self.inner.target_voltage()
+    }
+
+    /// Checks if the probe can supply power to the target, instead of only measuring its voltage.
+    pub fn can_power_target(&self) -> bool {
+        self.inner.can_power_target()
+    }
+
+    /// Switches the power supplied by the probe to the target on or off.
+    ///
+    /// After switching the power on, this waits for the target to start up, so that it can
+    /// be attached to right away.
+    /// Probes which cannot supply power return `DebugProbeError::NotSupported`.
+    pub fn set_target_power(&mut self, on: bool) -> Result<(), DebugProbeError> {
+        self.inner.set_target_power(on)?;
+        if on {
+            std::thread::sleep(TARGET_POWER_SETTLE_TIME);
+        }
+        Ok(())
+    }
+
+    /// Returns the settings needed to open and attach to this probe again,
+    /// if it was opened from a `DebugProbeInfo`.
+    pub(crate) fn reconnect_settings(&self) -> Option<ReconnectSettings> {
+        Some(ReconnectSettings {
+            stable_id: self.stable_id.clone()?,
+            protocol: self.protocol,
+            connect_speed_khz: self.connect_speed_khz,
+            operating_speed_khz: self.operating_speed_khz,
+            dp_address: self.dp_address,
+            swj_sequence: self.swj_sequence,
+            attach_config: self.attach_config,
+        })
+    }
+
     /// Check if the probe has an interface to
     /// debug ARM chips.
     pub fn has_arm_interface(&self) -> bool {
@@ -367,7 +679,17 @@ impl Probe {
             // TODO: Return self here
             Err(DebugProbeError::NotAttached)
         } else {
-            self.inner.get_arm_interface()
+            let mut interface = self.inner.get_arm_interface(&self.attach_config)?;
+
+            if let (Some(speed_khz), Some(interface)) =
+                (self.operating_speed_khz, interface.as_mut())
+            {
+                let probe: &mut dyn DebugProbe = (**interface).as_mut();
+                let actual_speed_khz = probe.set_speed(speed_khz)?;
+                log::info!("Switched to operating speed of {} kHz", actual_speed_khz);
+            }
+
+            Ok(interface)
         }
     }
 
@@ -396,6 +718,33 @@ impl Probe {
     }
 }
 
+/// The timeout and the retries used while attaching to a target, see `Probe::set_attach_config`.
+///
+/// Attaching is retried if the probe cannot connect to the debug port, and powering up the
+/// debug port is retried if it is not acknowledged within `connect_timeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachConfig {
+    /// How long the debug port may take to acknowledge the power-up request.
+    pub connect_timeout: Duration,
+    /// How often attaching is retried after it failed, never by default.
+    pub retry_count: u32,
+    /// The delay before each retry.
+    pub retry_delay: Duration,
+}
+
+impl Default for AttachConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_millis(100),
+            retry_count: 0,
+            retry_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+/// The time the target is given to start up after the probe switched its power on.
+const TARGET_POWER_SETTLE_TIME: Duration = Duration::from_millis(100);
+
 pub trait DebugProbe: Send + Sync + fmt::Debug {
     fn new_from_selector(
         selector: impl Into<DebugProbeSelector>,
@@ -440,6 +789,8 @@ pub trait DebugProbe: Send + Sync + fmt::Debug {
     fn target_reset(&mut self) -> Result<(), DebugProbeError>;
 
     /// This should assert the reset pin of the target via debug probe.
+    ///
+    /// Probes which cannot drive the reset pin return `DebugProbeError::ResetPinNotAvailable`.
     fn target_reset_assert(&mut self) -> Result<(), DebugProbeError>;
 
     /// This should deassert the reset pin of the target via debug probe.
@@ -448,6 +799,79 @@ pub trait DebugProbe: Send + Sync + fmt::Debug {
     /// Selects the transport protocol to be used by the debug probe.
     fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError>;
 
+    /// Clocks out the `bit_len` lowest bits of `bits` on SWDIO/TMS, starting with the least significant bit.
+    ///
+    /// Probes which cannot send raw sequences return `DebugProbeError::NotSupported`.
+    fn swj_sequence(&mut self, _bit_len: u8, _bits: u64) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotSupported("raw SWJ sequences"))
+    }
+
+    /// Resets the JTAG scan chain and reads the IDCODE and IR length of every TAP on it.
+    ///
+    /// Probes which cannot drive the JTAG signals directly return `DebugProbeError::NotSupported`.
+    fn scan_chain(&mut self) -> Result<Vec<JtagDevice>, DebugProbeError> {
+        Err(DebugProbeError::NotSupported("JTAG scan chains"))
+    }
+
+    /// Overrides the pins used by FTDI based probes, see `FtdiPinout`.
+    ///
+    /// All other probes return `DebugProbeError::NotSupported`.
+    #[cfg(feature = "ftdi")]
+    fn set_ftdi_pinout(&mut self, _pinout: ftdi::FtdiPinout) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotSupported("FTDI pinouts"))
+    }
+
+    /// Sets the number of idle cycles clocked after each SWD transfer.
+    ///
+    /// Probes whose firmware does not allow to configure them return `DebugProbeError::NotSupported`.
+    fn set_swd_idle_cycles(&mut self, _cycles: u8) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotSupported(
+            "configuring the SWD idle cycles",
+        ))
+    }
+
+    /// Sets the length of the SWD turnaround period in clock cycles, which is between 1 and 4.
+    ///
+    /// Probes whose firmware does not allow to configure it return `DebugProbeError::NotSupported`.
+    fn set_swd_turnaround(&mut self, _periods: u8) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotSupported(
+            "configuring the SWD turnaround period",
+        ))
+    }
+
+    /// The maximum number of bytes the probe transfers to or from the memory of the target with one command.
+    ///
+    /// Memory accesses are split into blocks of at most this size, which also never cross the 1 KB
+    /// boundary of the TAR auto-increment. Probes which split transfers into packets themselves
+    /// return `usize::MAX`.
+    ///
+    /// The default of 1 KB is the size of a TAR auto-increment block, so each block requires
+    /// at most a single write of TAR.
+    fn max_block_size(&self) -> usize {
+        TAR_AUTO_INCREMENT_BLOCK_SIZE as usize
+    }
+
+    /// Measures the target reference voltage, in volts.
+    ///
+    /// Returns `None` if the probe cannot measure the voltage, or if the measurement failed.
+    fn target_voltage(&mut self) -> Option<f32> {
+        None
+    }
+
+    /// Checks if the probe can supply power to the target, instead of only measuring its voltage.
+    fn can_power_target(&self) -> bool {
+        false
+    }
+
+    /// Switches the power supplied by the probe to the target on or off.
+    ///
+    /// Probes which cannot supply power return `DebugProbeError::NotSupported`.
+    fn set_target_power(&mut self, _on: bool) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotSupported(
+            "supplying power to the target",
+        ))
+    }
+
     /// Check if the proble offers an interface to debug ARM chips.
     fn has_arm_interface(&self) -> bool {
         false
@@ -455,8 +879,11 @@ pub trait DebugProbe: Send + Sync + fmt::Debug {
 
     /// Get the dedicated interface to debug ARM chips. Ensure that the
     /// probe actually supports this by calling `has_arm_interface` first.
+    ///
+    /// `config` sets how long the debug port may take to power up, and how often powering it up is retried.
     fn get_arm_interface<'probe>(
         self: Box<Self>,
+        _config: &AttachConfig,
     ) -> Result<Option<Box<dyn ArmProbeInterface + 'probe>>, DebugProbeError> {
         Ok(None)
     }
@@ -498,6 +925,8 @@ pub struct DebugProbeInfo {
     pub product_id: u16,
     pub serial_number: Option<String>,
     pub probe_type: DebugProbeType,
+    /// The USB port the probe is plugged into, e.g. `1-3.2`, if it is known.
+    pub usb_path: Option<String>,
 }
 
 impl std::fmt::Debug for DebugProbeInfo {
@@ -531,12 +960,74 @@ impl DebugProbeInfo {
             product_id,
             serial_number,
             probe_type,
+            usb_path: None,
         }
     }
 
+    /// Sets the USB port the probe is plugged into.
+    pub(crate) fn with_usb_path(mut self, usb_path: Option<String>) -> Self {
+        self.usb_path = usb_path;
+        self
+    }
+
+    /// Returns an identifier of the probe which stays the same across runs,
+    /// which can be used to open it again with `Probe::open_by_identifier`.
+    ///
+    /// Probes without a serial number are identified by the USB port they are plugged into,
+    /// see `StableProbeId::is_portable`.
+    pub fn stable_identifier(&self) -> StableProbeId {
+        StableProbeId::new(
+            self.vendor_id,
+            self.product_id,
+            self.serial_number.as_deref(),
+            self.usb_path.as_deref(),
+        )
+    }
+
     /// Open the probe described by this `DebugProbeInfo`.
     pub fn open(&self) -> Result<Probe, DebugProbeError> {
-        Probe::open(self)
+        let mut probe = Probe::open(self)?;
+        probe.stable_id = Some(self.stable_identifier());
+        Ok(probe)
+    }
+}
+
+/// The settings of a `Probe`, which are used to open and attach to the same probe again
+/// after it was disconnected.
+#[derive(Debug, Clone)]
+pub(crate) struct ReconnectSettings {
+    stable_id: StableProbeId,
+    protocol: Option<WireProtocol>,
+    connect_speed_khz: Option<u32>,
+    operating_speed_khz: Option<u32>,
+    dp_address: DpAddress,
+    swj_sequence: Option<SwjSequence>,
+    attach_config: AttachConfig,
+}
+
+impl ReconnectSettings {
+    /// Opens the probe again, and attaches to the target with the same settings as before.
+    pub(crate) fn reopen(&self) -> Result<Probe, DebugProbeError> {
+        let mut probe = Probe::open_by_identifier(self.stable_id.as_str())?;
+
+        if let Some(protocol) = self.protocol {
+            probe.select_protocol(protocol)?;
+        }
+        if let Some(speed_khz) = self.connect_speed_khz {
+            probe.set_speed(speed_khz)?;
+        }
+        if let Some(speed_khz) = self.operating_speed_khz {
+            probe.set_operating_speed(speed_khz)?;
+        }
+        probe.select_debug_port(self.dp_address)?;
+        probe.set_swj_sequence(self.swj_sequence)?;
+        probe.set_attach_config(self.attach_config);
+
+        probe.attach_with_retries()?;
+        probe.attached = true;
+        probe.select_multidrop_target()?;
+
+        Ok(probe)
     }
 }
 
@@ -562,6 +1053,18 @@ pub struct DebugProbeSelector {
     pub vendor_id: u16,
     pub product_id: u16,
     pub serial_number: Option<String>,
+    /// The USB port the probe is plugged into, see `DebugProbeInfo::usb_path`.
+    pub usb_path: Option<String>,
+}
+
+impl DebugProbeSelector {
+    /// Returns `true` if the probe plugged into `usb_path` is selected.
+    pub(crate) fn matches_usb_path(&self, usb_path: Option<&str>) -> bool {
+        match &self.usb_path {
+            Some(selected) => usb_path == Some(selected.as_str()),
+            None => true,
+        }
+    }
 }
 
 impl TryFrom<&str> for DebugProbeSelector {
@@ -573,6 +1076,7 @@ impl TryFrom<&str> for DebugProbeSelector {
                 vendor_id: u16::from_str_radix(split[0], 16)?,
                 product_id: u16::from_str_radix(split[1], 16)?,
                 serial_number: None,
+                usb_path: None,
             }
         } else {
             return Err(DebugProbeSelectorParseError::Format);
@@ -606,6 +1110,7 @@ impl From<DebugProbeInfo> for DebugProbeSelector {
             vendor_id: selector.vendor_id,
             product_id: selector.product_id,
             serial_number: selector.serial_number,
+            usb_path: selector.usb_path,
         }
     }
 }
@@ -616,6 +1121,7 @@ impl From<&DebugProbeInfo> for DebugProbeSelector {
             vendor_id: selector.vendor_id,
             product_id: selector.product_id,
             serial_number: selector.serial_number.clone(),
+            usb_path: selector.usb_path.clone(),
         }
     }
 }
@@ -3,16 +3,24 @@ pub mod tools;
 mod usb_interface;
 
 use self::usb_interface::{STLinkUSBDevice, StLinkUsb};
-use super::{DAPAccess, DebugProbe, DebugProbeError, PortType, ProbeCreationError, WireProtocol};
+use super::{
+    AttachConfig, DAPAccess, DebugProbe, DebugProbeError, PortType, ProbeCreationError,
+    WireProtocol,
+};
 use crate::{
     architecture::arm::{
         ap::{
-            valid_access_ports, APAccess, APClass, APRegister, AccessPort, BaseaddrFormat,
-            GenericAP, MemoryAP, BASE, BASE2, CSW, IDR,
+            valid_access_ports, APAccess, APClass, APRegister, AccessPort, AccessPortError,
+            BaseaddrFormat, GenericAP, MemoryAP, BASE, BASE2, CSW, IDR,
+        },
+        communication_interface::{
+            raw_dp_bank, ArmCommunicationInterfaceState, ArmProbeInterface, Register,
         },
-        communication_interface::{ArmCommunicationInterfaceState, ArmProbeInterface},
         dp::{DPAccess, DPBankSel, DPRegister, DebugPortError, Select},
-        memory::{adi_v5_memory_interface::ArmProbe, Component},
+        memory::{
+            adi_v5_memory_interface::ArmProbe, transfer_blocks, Component, MemoryAccessAttributes,
+            TAR_AUTO_INCREMENT_BLOCK_SIZE,
+        },
         ApInformation, ArmChipInfo, SwoAccess, SwoConfig, SwoMode,
     },
     DebugProbeSelector, Error as ProbeRsError, Memory, Probe,
@@ -241,8 +249,23 @@ impl DebugProbe for STLink<STLinkUSBDevice> {
         Some(self as _)
     }
 
+    fn max_block_size(&self) -> usize {
+        STLINK_MAX_READ_LEN.min(STLINK_MAX_WRITE_LEN)
+    }
+
+    fn target_voltage(&mut self) -> Option<f32> {
+        match self.get_target_voltage() {
+            Ok(voltage) => Some(voltage),
+            Err(e) => {
+                log::warn!("Failed to read the target voltage: {}", e);
+                None
+            }
+        }
+    }
+
     fn get_arm_interface<'probe>(
         self: Box<Self>,
+        _config: &AttachConfig,
     ) -> Result<Option<Box<dyn ArmProbeInterface + 'probe>>, DebugProbeError> {
         let interface = StlinkArmDebug::new(self)?;
 
@@ -858,9 +881,15 @@ impl<D: StLinkUsb> STLink<D> {
 
         log::debug!("Read mem 8 bit, address={:08x}, length={}", address, length);
 
-        // Single-byte reads don't work; read 2 if 1 is requested and then truncate.
-        let read_len = if length == 1 { 2 } else { length as u8 };
-        let mut receive_buffer = vec![0u8; read_len as usize];
+        // The firmware reads two bytes if a single byte is requested, which would access the byte
+        // next to it as well.
+        if length == 1 {
+            return Err(DebugProbeError::NotSupported(
+                "Single byte reads with the ST-Link",
+            ));
+        }
+
+        let mut receive_buffer = vec![0u8; length as usize];
 
         self.device.write(
             &[
@@ -870,7 +899,7 @@ impl<D: StLinkUsb> STLink<D> {
                 (address >> 8) as u8,
                 (address >> 16) as u8,
                 (address >> 24) as u8,
-                read_len,
+                length as u8,
                 (length >> 8) as u8,
                 apsel,
             ],
@@ -879,10 +908,6 @@ impl<D: StLinkUsb> STLink<D> {
             TIMEOUT,
         )?;
 
-        if length == 1 {
-            receive_buffer.resize(length as usize, 0)
-        }
-
         self.get_last_rw_status()?;
 
         Ok(receive_buffer)
@@ -1181,6 +1206,8 @@ impl StlinkArmDebug {
             Ok(ApInformation::MemoryAp {
                 port_number: access_port.port_number(),
                 only_32bit_data_size,
+                // The memory commands of the ST-Link only take 32 bit addresses.
+                supports_large_address: false,
                 debug_base_address: base_address,
             })
         } else {
@@ -1349,6 +1376,101 @@ impl<'probe> ArmProbeInterface for StlinkArmDebug {
         self.state.ap_information.len()
     }
 
+    fn set_memory_access_attributes(
+        &mut self,
+        _access_port: MemoryAP,
+        attributes: MemoryAccessAttributes,
+    ) -> Result<(), ProbeRsError> {
+        // The firmware sets CSW for memory accesses itself.
+        if attributes == MemoryAccessAttributes::default() {
+            Ok(())
+        } else {
+            Err(ProbeRsError::NotSupported(
+                "Memory access attributes are not supported by ST-Link probes",
+            ))
+        }
+    }
+
+    fn read_raw_ap_register(
+        &mut self,
+        access_port: GenericAP,
+        address: u8,
+    ) -> Result<u32, ProbeRsError> {
+        log::debug!("Reading raw AP register {:#04x}", address);
+        self.select_ap_and_ap_bank(access_port.port_number(), address >> 4)?;
+
+        Ok(self.probe.read_register(
+            PortType::AccessPort(u16::from(self.state.current_apsel)),
+            u16::from(address),
+        )?)
+    }
+
+    fn write_raw_ap_register(
+        &mut self,
+        access_port: GenericAP,
+        address: u8,
+        value: u32,
+    ) -> Result<(), ProbeRsError> {
+        log::debug!(
+            "Writing raw AP register {:#04x}, value=0x{:08x}",
+            address,
+            value
+        );
+        self.select_ap_and_ap_bank(access_port.port_number(), address >> 4)?;
+
+        Ok(self.probe.write_register(
+            PortType::AccessPort(u16::from(self.state.current_apsel)),
+            u16::from(address),
+            value,
+        )?)
+    }
+
+    fn read_raw_dp_register(&mut self, bank: u8, address: u8) -> Result<u32, ProbeRsError> {
+        log::debug!("Reading raw DP register {:#04x}", address);
+        self.select_dp_bank(raw_dp_bank(bank, address))
+            .map_err(DebugProbeError::from)?;
+
+        Ok(self
+            .probe
+            .read_register(PortType::DebugPort, u16::from(address))?)
+    }
+
+    fn write_raw_dp_register(
+        &mut self,
+        bank: u8,
+        address: u8,
+        value: u32,
+    ) -> Result<(), ProbeRsError> {
+        log::debug!(
+            "Writing raw DP register {:#04x}, value=0x{:08x}",
+            address,
+            value
+        );
+        self.select_dp_bank(raw_dp_bank(bank, address))
+            .map_err(DebugProbeError::from)?;
+
+        self.probe
+            .write_register(PortType::DebugPort, u16::from(address), value)?;
+
+        if address == Select::ADDRESS {
+            self.state.select_written(Select(value));
+        }
+
+        Ok(())
+    }
+
+    fn reinitialize(&mut self) -> Result<(), ProbeRsError> {
+        // The probe powers up the debug port when it enters the debug mode again.
+        self.probe.attach()?;
+
+        self.state = ArmCommunicationInterfaceState {
+            ap_information: std::mem::take(&mut self.state.ap_information),
+            ..ArmCommunicationInterfaceState::new()
+        };
+
+        Ok(())
+    }
+
     fn close(self: Box<Self>) -> Probe {
         Probe::from_attached_probe(self.probe)
     }
@@ -1484,24 +1606,58 @@ struct StLinkMemoryInterface<'probe> {
     probe: &'probe mut StlinkArmDebug,
 }
 
+/// The memory commands of the ST-Link only take 32 bit addresses.
+fn address_32(address: u64) -> Result<u32, ProbeRsError> {
+    address
+        .try_into()
+        .map_err(|_| ProbeRsError::architecture_specific(AccessPortError::AddressTooLarge(address)))
+}
+
+/// Splits an 8 bit read into blocks of at most `chunk_size` bytes, which do not cross a TAR boundary.
+///
+/// The ST-Link cannot read a single byte, so a single byte block left over at the end of a chunk
+/// takes a byte from the block before it. A byte which is the only one of the read on its side of
+/// a TAR boundary stays a single byte block, as it cannot be combined without crossing the boundary.
+fn read_8_blocks(
+    address: u64,
+    len: usize,
+    chunk_size: usize,
+) -> Vec<(u64, std::ops::Range<usize>)> {
+    let mut blocks = transfer_blocks(address, len, 1, chunk_size);
+
+    for index in 1..blocks.len() {
+        let same_tar_block = blocks[index].0 & (TAR_AUTO_INCREMENT_BLOCK_SIZE - 1) != 0;
+        if blocks[index].1.len() == 1 && same_tar_block && blocks[index - 1].1.len() > 2 {
+            blocks[index - 1].1.end -= 1;
+            blocks[index].1.start -= 1;
+            blocks[index].0 -= 1;
+        }
+    }
+
+    blocks
+}
+
 impl ArmProbe for StLinkMemoryInterface<'_> {
     fn read_32(
         &mut self,
         ap: MemoryAP,
-        address: u32,
+        address: u64,
         data: &mut [u32],
     ) -> Result<(), ProbeRsError> {
+        let address = address_32(address)?;
         self.probe.select_ap(ap)?;
 
-        // Read needs to be chunked into chunks with appropiate max length (see STLINK_MAX_READ_LEN).
-        for (index, chunk) in data.chunks_mut(STLINK_MAX_READ_LEN / 4).enumerate() {
+        // Read needs to be chunked into chunks with appropiate max length (see STLINK_MAX_READ_LEN),
+        // which do not cross the auto-increment boundary of TAR.
+        for (block_address, values) in
+            transfer_blocks(address.into(), data.len(), 4, STLINK_MAX_READ_LEN)
+        {
+            let chunk = &mut data[values];
             let mut buff = vec![0u8; 4 * chunk.len()];
 
-            self.probe.probe.read_mem_32bit(
-                address + (index * STLINK_MAX_READ_LEN) as u32,
-                &mut buff,
-                ap.port_number(),
-            )?;
+            self.probe
+                .probe
+                .read_mem_32bit(block_address as u32, &mut buff, ap.port_number())?;
 
             for (index, word) in buff.chunks_exact(4).enumerate() {
                 chunk[index] = u32::from_le_bytes(word.try_into().unwrap());
@@ -1511,20 +1667,38 @@ impl ArmProbe for StLinkMemoryInterface<'_> {
         Ok(())
     }
 
-    fn read_8(&mut self, ap: MemoryAP, address: u32, data: &mut [u8]) -> Result<(), ProbeRsError> {
+    fn read_8(&mut self, ap: MemoryAP, address: u64, data: &mut [u8]) -> Result<(), ProbeRsError> {
+        let address = address_32(address)?;
         self.probe.select_ap(ap)?;
 
-        let received_data =
-            self.probe
-                .probe
-                .read_mem_8bit(address, data.len() as u16, ap.port_number())?;
+        // The underlying STLink command is limited to a single USB frame at a time.
+        let chunk_size = if self.probe.probe.hw_version < 3 {
+            64
+        } else {
+            512
+        };
 
-        data.copy_from_slice(&received_data);
+        let blocks = read_8_blocks(address.into(), data.len(), chunk_size);
+        // Fail before anything is read, as the bytes read so far might have side effects.
+        if blocks.iter().any(|(_, bytes)| bytes.len() == 1) {
+            return Err(DebugProbeError::NotSupported("Single byte reads with the ST-Link").into());
+        }
+
+        for (block_address, bytes) in blocks {
+            let received_data = self.probe.probe.read_mem_8bit(
+                block_address as u32,
+                bytes.len() as u16,
+                ap.port_number(),
+            )?;
+
+            data[bytes].copy_from_slice(&received_data);
+        }
 
         Ok(())
     }
 
-    fn write_32(&mut self, ap: MemoryAP, address: u32, data: &[u32]) -> Result<(), ProbeRsError> {
+    fn write_32(&mut self, ap: MemoryAP, address: u64, data: &[u32]) -> Result<(), ProbeRsError> {
+        let address = address_32(address)?;
         self.probe.select_ap(ap)?;
 
         let mut tx_buffer = vec![0u8; data.len() * 4];
@@ -1537,10 +1711,12 @@ impl ArmProbe for StLinkMemoryInterface<'_> {
                 .expect("Failed to write into tx_buffer");
         }
 
-        for (index, chunk) in tx_buffer.chunks(STLINK_MAX_WRITE_LEN).enumerate() {
+        for (block_address, values) in
+            transfer_blocks(address.into(), data.len(), 4, STLINK_MAX_WRITE_LEN)
+        {
             self.probe.probe.write_mem_32bit(
-                address + (index * STLINK_MAX_WRITE_LEN) as u32,
-                chunk,
+                block_address as u32,
+                &tx_buffer[values.start * 4..values.end * 4],
                 ap.port_number(),
             )?;
         }
@@ -1548,83 +1724,32 @@ impl ArmProbe for StLinkMemoryInterface<'_> {
         Ok(())
     }
 
-    fn write_8(&mut self, ap: MemoryAP, address: u32, data: &[u8]) -> Result<(), ProbeRsError> {
+    fn write_8(&mut self, ap: MemoryAP, address: u64, data: &[u8]) -> Result<(), ProbeRsError> {
+        let address = address_32(address)?;
         self.probe.select_ap(ap)?;
 
-        // The underlying STLink command is limited to a single USB frame at a time
-        // so we must manually chunk it into multiple command if it exceeds
-        // that size.
+        // The underlying STLink command is limited to a single USB frame at a time.
+        // The aligned part of the data is not written with 32 bit accesses, so the access
+        // size stays the same for registers.
         let chunk_size = if self.probe.probe.hw_version < 3 {
             64
         } else {
             512
         };
 
-        // If we write less than 64 bytes, just write it directly
-        if data.len() < chunk_size {
-            log::trace!("write_8: small - direct 8 bit write to {:08x}", address);
-            self.probe
-                .probe
-                .write_mem_8bit(address, data, ap.port_number())?;
-        } else {
-            // Handle unaligned data in the beginning.
-            let bytes_beginning = if address % 4 == 0 {
-                0
-            } else {
-                (4 - address % 4) as usize
-            };
-
-            let mut current_address = address;
-
-            if bytes_beginning > 0 {
-                log::trace!(
-                    "write_8: at_begin - unaligned write of {} bytes to address {:08x}",
-                    bytes_beginning,
-                    current_address,
-                );
-                self.probe.probe.write_mem_8bit(
-                    current_address,
-                    &data[..bytes_beginning],
-                    ap.port_number(),
-                )?;
-
-                current_address += bytes_beginning as u32;
-            }
-
-            // Address has to be aligned here.
-            assert!(current_address % 4 == 0);
-
-            let aligned_len = ((data.len() - bytes_beginning) / 4) * 4;
-
+        for (block_address, bytes) in transfer_blocks(address.into(), data.len(), 1, chunk_size) {
             log::trace!(
-                "write_8: aligned write of {} bytes to address {:08x}",
-                aligned_len,
-                current_address,
+                "write_8: 8 bit write of {} bytes to address {:08x}",
+                bytes.len(),
+                block_address,
             );
-
-            self.probe.probe.write_mem_32bit(
-                current_address,
-                &data[bytes_beginning..(bytes_beginning + aligned_len)],
+            self.probe.probe.write_mem_8bit(
+                block_address as u32,
+                &data[bytes],
                 ap.port_number(),
             )?;
-
-            current_address += aligned_len as u32;
-
-            let remaining_bytes = &data[bytes_beginning + aligned_len..];
-
-            if !remaining_bytes.is_empty() {
-                log::trace!(
-                    "write_8: at_end -unaligned write of {} bytes to address {:08x}",
-                    bytes_beginning,
-                    current_address,
-                );
-                self.probe.probe.write_mem_8bit(
-                    current_address,
-                    remaining_bytes,
-                    ap.port_number(),
-                )?;
-            }
         }
+
         Ok(())
     }
 
@@ -1634,6 +1759,13 @@ impl ArmProbe for StLinkMemoryInterface<'_> {
         Ok(())
     }
 
+    fn keep_alive(&mut self) -> Result<(), ProbeRsError> {
+        // Reading the target voltage only talks to the ST-Link, not to the target.
+        self.probe.probe.get_target_voltage()?;
+
+        Ok(())
+    }
+
     fn read_core_reg(
         &mut self,
         _ap: MemoryAP,
@@ -1663,7 +1795,7 @@ impl ArmProbe for StLinkMemoryInterface<'_> {
 #[cfg(test)]
 mod test {
 
-    use super::{constants::commands, usb_interface::StLinkUsb, STLink};
+    use super::{constants::commands, read_8_blocks, usb_interface::StLinkUsb, STLink};
     use crate::{DebugProbeError, WireProtocol};
 
     use scroll::Pwrite;
@@ -1819,4 +1951,32 @@ mod test {
             .select_ap(1)
             .expect("Selecting AP other than AP 0 should work");
     }
+
+    #[test]
+    fn byte_reads_are_not_split_into_single_bytes() {
+        // A single byte left over after full blocks.
+        assert_eq!(
+            read_8_blocks(0x0, 65, 64),
+            vec![(0x0, 0..63), (0x3f, 63..65)]
+        );
+        assert_eq!(read_8_blocks(0x10, 1, 64), vec![(0x10, 0..1)]);
+    }
+
+    #[test]
+    fn byte_reads_do_not_cross_the_tar_boundary() {
+        assert_eq!(
+            read_8_blocks(0x3fe, 4, 64),
+            vec![(0x3fe, 0..2), (0x400, 2..4)]
+        );
+        // The byte before the boundary cannot be combined with the ones after it.
+        assert_eq!(
+            read_8_blocks(0x3ff, 4, 64),
+            vec![(0x3ff, 0..1), (0x400, 1..4)]
+        );
+        // Neither can a single byte after the boundary at the end of a chunk.
+        assert_eq!(
+            read_8_blocks(0x3c0, 65, 64),
+            vec![(0x3c0, 0..64), (0x400, 64..65)]
+        );
+    }
 }
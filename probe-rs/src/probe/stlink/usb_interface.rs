@@ -8,7 +8,7 @@ use std::collections::HashMap;
 
 use super::tools::{is_stlink_device, read_serial_number};
 use crate::{
-    probe::{DebugProbeError, ProbeCreationError},
+    probe::{usb_path, DebugProbeError, ProbeCreationError},
     DebugProbeSelector,
 };
 
@@ -118,6 +118,7 @@ impl STLinkUSBDevice {
                 // First match the VID & PID.
                 if selector.vendor_id == descriptor.vendor_id()
                     && selector.product_id == descriptor.product_id()
+                    && selector.matches_usb_path(usb_path(&device).as_deref())
                 {
                     // If the VID & PID match, match the serial if one was given.
                     if let Some(serial) = &selector.serial_number {
@@ -0,0 +1,287 @@
+//! Reading why a target was reset, from the reset status register of its family.
+//!
+//! The reset reason is only supported for some families, use `Session::reset_reason` to read it:
+//!
+//! - STM32F0, STM32F1 and STM32F3
+//! - STM32F2, STM32F4 and STM32F7
+//! - STM32G4 and STM32L4
+//! - nRF52
+//! - Kinetis K and L
+
+use crate::{Core, Error, MemoryInterface};
+
+/// The reason of the last reset of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetReason {
+    /// The target was powered on, or its supply voltage dropped below the brownout threshold.
+    PowerOn,
+    /// The reset pin was asserted.
+    Pin,
+    /// The firmware requested a reset, e.g. with `SYSRESETREQ`.
+    Software,
+    /// A watchdog expired.
+    Watchdog,
+    /// The core entered the lockup state.
+    Lockup,
+    /// The target woke up from a low power mode, or entered one which it can only leave with a reset.
+    LowPower,
+    /// The debugger requested a reset through the debug interface.
+    Debugger,
+    /// None of the known flags are set, the raw value of the reset status register is included.
+    Unknown(u32),
+}
+
+/// How the flags of a reset status register are cleared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Clearing {
+    /// The flags are cleared by setting the given bit of the register.
+    RemoveFlags(u32),
+    /// The flags are cleared by writing ones to them.
+    WriteOne,
+    /// The register only contains the reason of the last reset, and is overwritten by the next one.
+    Never,
+}
+
+/// The register which contains the reset flags of a family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ResetStatusRegister {
+    address: u32,
+    /// Whether the register is made up of two byte registers, which have to be read with byte accesses.
+    byte_registers: bool,
+    /// The flags and the reasons they stand for, in the order they are checked.
+    ///
+    /// Some resets set multiple flags, e.g. a power-on reset also sets the pin reset flag on STM32 parts.
+    flags: &'static [(u32, ResetReason)],
+    /// The reason reported if no flag is set.
+    no_flags: Option<ResetReason>,
+    clearing: Clearing,
+}
+
+impl ResetStatusRegister {
+    /// `RCC_CSR` of STM32F0, STM32F1 and STM32F3.
+    const STM32F0: ResetStatusRegister = ResetStatusRegister {
+        address: 0x4002_1024,
+        byte_registers: false,
+        flags: &[
+            (1 << 27, ResetReason::PowerOn),
+            (1 << 29, ResetReason::Watchdog),
+            (1 << 30, ResetReason::Watchdog),
+            (1 << 28, ResetReason::Software),
+            (1 << 31, ResetReason::LowPower),
+            (1 << 26, ResetReason::Pin),
+        ],
+        no_flags: None,
+        clearing: Clearing::RemoveFlags(1 << 24),
+    };
+
+    /// `RCC_CSR` of STM32F2, STM32F4 and STM32F7.
+    const STM32F4: ResetStatusRegister = ResetStatusRegister {
+        address: 0x4002_3874,
+        byte_registers: false,
+        flags: &[
+            (1 << 27, ResetReason::PowerOn),
+            (1 << 25, ResetReason::PowerOn),
+            (1 << 29, ResetReason::Watchdog),
+            (1 << 30, ResetReason::Watchdog),
+            (1 << 28, ResetReason::Software),
+            (1 << 31, ResetReason::LowPower),
+            (1 << 26, ResetReason::Pin),
+        ],
+        no_flags: None,
+        clearing: Clearing::RemoveFlags(1 << 24),
+    };
+
+    /// `RCC_CSR` of STM32G4 and STM32L4, where the brownout flag is also set on power-on.
+    const STM32L4: ResetStatusRegister = ResetStatusRegister {
+        address: 0x4002_1094,
+        byte_registers: false,
+        flags: &[
+            (1 << 27, ResetReason::PowerOn),
+            (1 << 29, ResetReason::Watchdog),
+            (1 << 30, ResetReason::Watchdog),
+            (1 << 28, ResetReason::Software),
+            (1 << 31, ResetReason::LowPower),
+            (1 << 26, ResetReason::Pin),
+        ],
+        no_flags: None,
+        clearing: Clearing::RemoveFlags(1 << 23),
+    };
+
+    /// `POWER.RESETREAS` of nRF52, where no flag is set after a power-on reset.
+    const NRF52: ResetStatusRegister = ResetStatusRegister {
+        address: 0x4000_0400,
+        byte_registers: false,
+        flags: &[
+            (1 << 1, ResetReason::Watchdog),
+            (1 << 3, ResetReason::Lockup),
+            (1 << 2, ResetReason::Software),
+            (1 << 18, ResetReason::Debugger),
+            (1 << 16, ResetReason::LowPower),
+            (1 << 17, ResetReason::LowPower),
+            (1 << 19, ResetReason::LowPower),
+            (1 << 0, ResetReason::Pin),
+        ],
+        no_flags: Some(ResetReason::PowerOn),
+        clearing: Clearing::WriteOne,
+    };
+
+    /// `RCM_SRS0` and `RCM_SRS1` of Kinetis K and L, with `RCM_SRS1` in the second byte.
+    const KINETIS: ResetStatusRegister = ResetStatusRegister {
+        address: 0x4007_F000,
+        byte_registers: true,
+        flags: &[
+            (1 << 7, ResetReason::PowerOn),
+            (1 << 1, ResetReason::PowerOn),
+            (1 << 5, ResetReason::Watchdog),
+            (1 << 9, ResetReason::Lockup),
+            (1 << 10, ResetReason::Software),
+            (1 << 8, ResetReason::Debugger),
+            (1 << 11, ResetReason::Debugger),
+            (1 << 0, ResetReason::LowPower),
+            (1 << 6, ResetReason::Pin),
+        ],
+        no_flags: None,
+        clearing: Clearing::Never,
+    };
+
+    /// Returns the reset reason for the `raw` value of the register.
+    fn decode(&self, raw: u32) -> ResetReason {
+        match self.flags.iter().find(|(flag, _)| raw & flag != 0) {
+            Some((_, reason)) => *reason,
+            None if raw == 0 => self.no_flags.unwrap_or(ResetReason::Unknown(0)),
+            None => ResetReason::Unknown(raw),
+        }
+    }
+
+    /// Returns all flag bits of the register.
+    fn flag_mask(&self) -> u32 {
+        self.flags.iter().fold(0, |mask, (flag, _)| mask | flag)
+    }
+
+    fn read(&self, core: &mut Core) -> Result<u32, Error> {
+        if self.byte_registers {
+            let mut bytes = [0; 4];
+            core.read_8(self.address.into(), &mut bytes[..2])?;
+            Ok(u32::from_le_bytes(bytes))
+        } else {
+            core.read_word_32(self.address.into())
+        }
+    }
+
+    fn clear(&self, core: &mut Core, raw: u32) -> Result<(), Error> {
+        match self.clearing {
+            Clearing::RemoveFlags(bit) => core.write_word_32(self.address.into(), raw | bit),
+            Clearing::WriteOne => core.write_word_32(self.address.into(), raw & self.flag_mask()),
+            Clearing::Never => Ok(()),
+        }
+    }
+}
+
+/// The families with a supported reset status register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetReasonFamily {
+    /// STM32F0, STM32F1 and STM32F3, where the flags are in `RCC_CSR`.
+    Stm32F0,
+    /// STM32F2, STM32F4 and STM32F7, where the flags are in `RCC_CSR`.
+    Stm32F4,
+    /// STM32G4 and STM32L4, where the flags are in `RCC_CSR`.
+    Stm32L4,
+    /// nRF52, where the flags are in `POWER.RESETREAS`.
+    Nrf52,
+    /// Kinetis K and L, where the flags are in `RCM_SRS0` and `RCM_SRS1`.
+    Kinetis,
+}
+
+impl ResetReasonFamily {
+    /// Determines the family of the chip with the given name, if its reset reason is supported.
+    pub(crate) fn from_chip_name(chip_name: &str) -> Option<Self> {
+        let chip_name = chip_name.to_ascii_uppercase();
+        let prefix = |prefixes: &[&str]| prefixes.iter().any(|p| chip_name.starts_with(p));
+
+        if prefix(&["STM32F0", "STM32F1", "STM32F3"]) {
+            Some(ResetReasonFamily::Stm32F0)
+        } else if prefix(&["STM32F2", "STM32F4", "STM32F7"]) {
+            Some(ResetReasonFamily::Stm32F4)
+        } else if prefix(&["STM32G4", "STM32L4"]) {
+            Some(ResetReasonFamily::Stm32L4)
+        } else if prefix(&["NRF52"]) {
+            Some(ResetReasonFamily::Nrf52)
+        } else if prefix(&["MK"]) && !prefix(&["MKE"]) {
+            Some(ResetReasonFamily::Kinetis)
+        } else {
+            None
+        }
+    }
+
+    fn register(self) -> ResetStatusRegister {
+        match self {
+            ResetReasonFamily::Stm32F0 => ResetStatusRegister::STM32F0,
+            ResetReasonFamily::Stm32F4 => ResetStatusRegister::STM32F4,
+            ResetReasonFamily::Stm32L4 => ResetStatusRegister::STM32L4,
+            ResetReasonFamily::Nrf52 => ResetStatusRegister::NRF52,
+            ResetReasonFamily::Kinetis => ResetStatusRegister::KINETIS,
+        }
+    }
+
+    /// Reads the reason of the last reset through `core`.
+    ///
+    /// If `clear` is set, the sticky reset flags are cleared afterwards, so the next reset is not
+    /// mixed up with the previous ones. On Kinetis parts, the flags are only changed by the next reset.
+    pub fn read(self, core: &mut Core, clear: bool) -> Result<ResetReason, Error> {
+        let register = self.register();
+        let raw = register.read(core)?;
+
+        if clear {
+            register.clear(core, raw)?;
+        }
+
+        Ok(register.decode(raw))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn family_of_chip() {
+        assert_eq!(
+            ResetReasonFamily::from_chip_name("STM32F103C8"),
+            Some(ResetReasonFamily::Stm32F0)
+        );
+        assert_eq!(
+            ResetReasonFamily::from_chip_name("nRF52840_xxAA"),
+            Some(ResetReasonFamily::Nrf52)
+        );
+        assert_eq!(
+            ResetReasonFamily::from_chip_name("MKL25Z128xxx4"),
+            Some(ResetReasonFamily::Kinetis)
+        );
+        assert_eq!(ResetReasonFamily::from_chip_name("MKE02Z64xxx4"), None);
+        assert_eq!(ResetReasonFamily::from_chip_name("STM32H743ZITx"), None);
+    }
+
+    #[test]
+    fn flags_are_decoded() {
+        let stm32 = ResetStatusRegister::STM32F4;
+        // A power-on reset also sets the pin reset flag.
+        assert_eq!(
+            stm32.decode((1 << 27) | (1 << 26) | (1 << 25)),
+            ResetReason::PowerOn
+        );
+        assert_eq!(stm32.decode(1 << 26), ResetReason::Pin);
+        assert_eq!(stm32.decode(1 << 29), ResetReason::Watchdog);
+        // The LSI on bit is not a reset flag.
+        assert_eq!(stm32.decode(1), ResetReason::Unknown(1));
+
+        let nrf52 = ResetStatusRegister::NRF52;
+        assert_eq!(nrf52.decode(0), ResetReason::PowerOn);
+        assert_eq!(nrf52.decode(1 << 2), ResetReason::Software);
+        assert_eq!(nrf52.decode(1 << 4), ResetReason::Unknown(1 << 4));
+
+        assert_eq!(
+            ResetStatusRegister::KINETIS.decode(1 << 9),
+            ResetReason::Lockup
+        );
+    }
+}
@@ -0,0 +1,590 @@
+//! Handling of semihosting calls made by the target.
+//!
+//! Firmware using semihosting halts at a special breakpoint (`BKPT 0xAB` on ARM), with the
+//! number of the operation in the first and a pointer to its parameters in the second argument
+//! register. `SemihostingHandler` services these calls and resumes the core afterwards.
+//!
+//! Only the console operations and `SYS_EXIT` are supported, the target has no access to the
+//! file system of the host.
+
+use crate::{Architecture, Core, CoreStatus, DebugProbeError, Error, HaltReason, MemoryInterface};
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+const SYS_OPEN: u32 = 0x01;
+const SYS_CLOSE: u32 = 0x02;
+const SYS_WRITEC: u32 = 0x03;
+const SYS_WRITE0: u32 = 0x04;
+const SYS_WRITE: u32 = 0x05;
+const SYS_READC: u32 = 0x07;
+const SYS_ISTTY: u32 = 0x09;
+const SYS_ERRNO: u32 = 0x13;
+const SYS_EXIT: u32 = 0x18;
+const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+/// The `SYS_EXIT` reason of a program which exited normally.
+const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x2_0026;
+
+/// The handles returned by `SYS_OPEN` for the console, depending on the open mode.
+const HANDLE_STDIN: u32 = 1;
+const HANDLE_STDOUT: u32 = 2;
+const HANDLE_STDERR: u32 = 3;
+
+/// The value returned by failed operations.
+const FAILURE: u32 = u32::MAX;
+
+/// The longest string accepted by `SYS_WRITE0` and `SYS_OPEN`.
+const MAX_STRING_LENGTH: usize = 4096;
+
+/// The console stream the target writes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// The exit of the program on the target, reported by `SYS_EXIT` or `SYS_EXIT_EXTENDED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemihostingExit {
+    /// The reason for the exit, `0x20026` (`ADP_Stopped_ApplicationExit`) for a normal exit.
+    pub reason: u32,
+    /// The exit code, if the program reported one.
+    pub code: Option<u32>,
+}
+
+impl SemihostingExit {
+    /// Checks if the program exited normally, with exit code 0 if it reported one.
+    pub fn is_success(&self) -> bool {
+        self.reason == ADP_STOPPED_APPLICATION_EXIT && self.code.unwrap_or(0) == 0
+    }
+}
+
+/// The end of `SemihostingHandler::run`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunOutcome {
+    /// The program exited.
+    Exited(SemihostingExit),
+    /// The core halted for another reason than a semihosting call, e.g. a breakpoint.
+    Halted(HaltReason),
+}
+
+/// The result of a single semihosting call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Call {
+    /// The call returns the value to the program.
+    Return(u32),
+    /// The program exited.
+    Exit(SemihostingExit),
+}
+
+type Output = Box<dyn FnMut(Stream, &str) + Send>;
+
+/// Services the semihosting calls of a program.
+///
+/// The console output is collected into lines, which are passed to the log by default,
+/// see `with_output`. Console input is only available if set with `with_input`.
+pub struct SemihostingHandler {
+    input: Option<Box<dyn Read + Send>>,
+    output: Output,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl Default for SemihostingHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SemihostingHandler {
+    /// Creates a handler which passes the console output to the log, and has no console input.
+    pub fn new() -> Self {
+        Self {
+            input: None,
+            output: Box::new(|stream, line| match stream {
+                Stream::Stdout => log::info!(target: "semihosting", "{}", line),
+                Stream::Stderr => log::warn!(target: "semihosting", "{}", line),
+            }),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        }
+    }
+
+    /// Reads the console input of the program from `input`, e.g. `std::io::stdin()`.
+    pub fn with_input(mut self, input: impl Read + Send + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    /// Passes each line of console output to `output`, instead of the log.
+    pub fn with_output(mut self, output: impl FnMut(Stream, &str) + Send + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Resumes the core, and services its semihosting calls until it halts for another reason,
+    /// or the program exits.
+    ///
+    /// If neither happens within `timeout`, a `DebugProbeError::Timeout` error is returned,
+    /// and the core keeps running.
+    pub fn run(&mut self, core: &mut Core<'_>, timeout: Duration) -> Result<RunOutcome, Error> {
+        let start = Instant::now();
+
+        loop {
+            core.run()?;
+
+            let remaining = timeout
+                .checked_sub(start.elapsed())
+                .ok_or(Error::Probe(DebugProbeError::Timeout))?;
+
+            match core.wait_for_halt(remaining)? {
+                HaltReason::Semihosting => {
+                    if let Some(exit) = self.handle(core)? {
+                        return Ok(RunOutcome::Exited(exit));
+                    }
+                }
+                reason => {
+                    self.flush();
+                    return Ok(RunOutcome::Halted(reason));
+                }
+            }
+        }
+    }
+
+    /// Services the semihosting call the core is halted at.
+    ///
+    /// The core stays halted, but its program counter is moved behind the semihosting call,
+    /// so it can be resumed. Returns the exit of the program for `SYS_EXIT`.
+    pub fn handle(&mut self, core: &mut Core<'_>) -> Result<Option<SemihostingExit>, Error> {
+        if core.status()? != CoreStatus::Halted(HaltReason::Semihosting) {
+            return Err(anyhow::anyhow!("The core is not halted at a semihosting call.").into());
+        }
+
+        let registers = core.registers();
+        let (pc, operation, parameter) = (
+            registers.program_counter().address,
+            registers.argument_register(0).address,
+            registers.argument_register(1).address,
+        );
+
+        let operation_number = core.read_core_reg(operation)?;
+        let parameter_value = core.read_core_reg(parameter)?;
+
+        match self.execute(core, operation_number, parameter_value)? {
+            Call::Return(value) => {
+                core.write_core_reg(operation, value)?;
+
+                // Continue after the breakpoint instruction of the call.
+                let breakpoint_length = match core.architecture() {
+                    Architecture::Arm => 2,
+                    Architecture::Riscv => 4,
+                };
+                let address = core.read_core_reg(pc)?;
+                core.write_core_reg(pc, address + breakpoint_length)?;
+
+                Ok(None)
+            }
+            Call::Exit(exit) => {
+                self.flush();
+                Ok(Some(exit))
+            }
+        }
+    }
+
+    /// Executes the semihosting `operation` with the parameter `parameter`.
+    fn execute(
+        &mut self,
+        memory: &mut impl MemoryInterface,
+        operation: u32,
+        parameter: u32,
+    ) -> Result<Call, Error> {
+        let value = match operation {
+            SYS_OPEN => {
+                let mut block = [0; 3];
+                memory.read_32(parameter.into(), &mut block)?;
+                let [name, mode, length] = block;
+                let name = read_bytes(memory, name, length as usize)?;
+
+                // Only the console is available, which is opened as the special file `:tt`.
+                match (name.as_slice(), mode) {
+                    (b":tt", 0..=3) => HANDLE_STDIN,
+                    (b":tt", 4..=7) => HANDLE_STDOUT,
+                    (b":tt", 8..=11) => HANDLE_STDERR,
+                    _ => {
+                        log::warn!(
+                            "Semihosting: opening '{}' is not supported.",
+                            String::from_utf8_lossy(&name)
+                        );
+                        FAILURE
+                    }
+                }
+            }
+            SYS_CLOSE => 0,
+            SYS_WRITEC => {
+                let mut c = [0];
+                memory.read(parameter.into(), &mut c)?;
+                self.write(Stream::Stdout, &c);
+                0
+            }
+            SYS_WRITE0 => {
+                let string = read_c_string(memory, parameter)?;
+                self.write(Stream::Stdout, &string);
+                0
+            }
+            SYS_WRITE => {
+                let mut block = [0; 3];
+                memory.read_32(parameter.into(), &mut block)?;
+                let [handle, data, length] = block;
+                let stream = match handle {
+                    HANDLE_STDOUT => Stream::Stdout,
+                    HANDLE_STDERR => Stream::Stderr,
+                    // Nothing could be written, so all bytes are reported as not written.
+                    _ => return Ok(Call::Return(length)),
+                };
+
+                let data = read_bytes(memory, data, length as usize)?;
+                self.write(stream, &data);
+                0
+            }
+            SYS_READC => {
+                let mut c = [0];
+                let read = match &mut self.input {
+                    Some(input) => input.read(&mut c).unwrap_or(0),
+                    None => 0,
+                };
+
+                if read == 1 {
+                    u32::from(c[0])
+                } else {
+                    FAILURE
+                }
+            }
+            SYS_ISTTY => match memory.read_word_32(parameter.into())? {
+                HANDLE_STDIN | HANDLE_STDOUT | HANDLE_STDERR => 1,
+                _ => 0,
+            },
+            SYS_ERRNO => 0,
+            SYS_EXIT => {
+                return Ok(Call::Exit(SemihostingExit {
+                    reason: parameter,
+                    code: None,
+                }))
+            }
+            SYS_EXIT_EXTENDED => {
+                let mut block = [0; 2];
+                memory.read_32(parameter.into(), &mut block)?;
+                let [reason, code] = block;
+                return Ok(Call::Exit(SemihostingExit {
+                    reason,
+                    code: Some(code),
+                }));
+            }
+            _ => {
+                log::warn!(
+                    "Semihosting: operation {:#04x} is not supported.",
+                    operation
+                );
+                FAILURE
+            }
+        };
+
+        Ok(Call::Return(value))
+    }
+
+    /// Adds `data` to the output of `stream`, and passes all complete lines to the output.
+    fn write(&mut self, stream: Stream, data: &[u8]) {
+        let buffer = match stream {
+            Stream::Stdout => &mut self.stdout,
+            Stream::Stderr => &mut self.stderr,
+        };
+        buffer.extend_from_slice(data);
+
+        while let Some(end) = buffer.iter().position(|&c| c == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..end]);
+            (self.output)(stream, line.trim_end_matches('\r'));
+        }
+    }
+
+    /// Passes incomplete lines to the output.
+    fn flush(&mut self) {
+        for stream in &[Stream::Stdout, Stream::Stderr] {
+            let buffer = match stream {
+                Stream::Stdout => &mut self.stdout,
+                Stream::Stderr => &mut self.stderr,
+            };
+
+            if !buffer.is_empty() {
+                let line = std::mem::take(buffer);
+                (self.output)(*stream, &String::from_utf8_lossy(&line));
+            }
+        }
+    }
+}
+
+fn read_bytes(
+    memory: &mut impl MemoryInterface,
+    address: u32,
+    length: usize,
+) -> Result<Vec<u8>, Error> {
+    let mut data = vec![0; length.min(MAX_STRING_LENGTH)];
+    memory.read(address.into(), &mut data)?;
+    Ok(data)
+}
+
+/// Reads the null terminated string at `address`.
+fn read_c_string(memory: &mut impl MemoryInterface, mut address: u32) -> Result<Vec<u8>, Error> {
+    let mut string = Vec::new();
+    let mut chunk = [0; 32];
+
+    while string.len() < MAX_STRING_LENGTH {
+        memory.read(address.into(), &mut chunk)?;
+
+        match chunk.iter().position(|&c| c == 0) {
+            Some(end) => {
+                string.extend_from_slice(&chunk[..end]);
+                return Ok(string);
+            }
+            None => string.extend_from_slice(&chunk),
+        }
+
+        address += chunk.len() as u32;
+    }
+
+    Ok(string)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// A RAM at `RAM_START`.
+    struct Ram(Vec<u8>);
+
+    const RAM_START: u32 = 0x2000_0000;
+
+    impl Ram {
+        fn new() -> Self {
+            Ram(vec![0; 0x1000])
+        }
+
+        fn offset(&self, address: u64, len: usize) -> Result<usize, Error> {
+            let offset = address.wrapping_sub(RAM_START.into()) as usize;
+            if offset + len <= self.0.len() {
+                Ok(offset)
+            } else {
+                Err(anyhow::anyhow!("Invalid address {:#010x}", address).into())
+            }
+        }
+
+        fn store(&mut self, address: u32, data: &[u8]) {
+            self.write(address.into(), data).unwrap();
+        }
+
+        fn store_words(&mut self, address: u32, words: &[u32]) {
+            self.write_32(address.into(), words).unwrap();
+        }
+    }
+
+    impl MemoryInterface for Ram {
+        fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
+            let mut word = [0];
+            self.read_32(address, &mut word)?;
+            Ok(word[0])
+        }
+
+        fn read_word_8(&mut self, address: u64) -> Result<u8, Error> {
+            let offset = self.offset(address, 1)?;
+            Ok(self.0[offset])
+        }
+
+        fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
+            let mut bytes = vec![0; data.len() * 4];
+            self.read_8(address, &mut bytes)?;
+            for (word, bytes) in data.iter_mut().zip(bytes.chunks_exact(4)) {
+                *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            }
+            Ok(())
+        }
+
+        fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
+            // Reads past the end of the RAM return zeroes, like an unmapped region.
+            let offset = address.wrapping_sub(RAM_START.into()) as usize;
+            for (i, byte) in data.iter_mut().enumerate() {
+                *byte = self.0.get(offset + i).copied().unwrap_or(0);
+            }
+            Ok(())
+        }
+
+        fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), Error> {
+            self.write_32(address, &[data])
+        }
+
+        fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), Error> {
+            let offset = self.offset(address, 1)?;
+            self.0[offset] = data;
+            Ok(())
+        }
+
+        fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), Error> {
+            let offset = self.offset(address, data.len() * 4)?;
+            for (i, word) in data.iter().enumerate() {
+                self.0[offset + i * 4..offset + i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+            }
+            Ok(())
+        }
+
+        fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
+            let offset = self.offset(address, data.len())?;
+            self.0[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    type Lines = Arc<Mutex<Vec<(Stream, String)>>>;
+
+    /// Creates a handler which collects its output lines.
+    fn collecting_handler() -> (SemihostingHandler, Lines) {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let collected = lines.clone();
+
+        let handler = SemihostingHandler::new().with_output(move |stream, line| {
+            collected.lock().unwrap().push((stream, line.to_owned()))
+        });
+
+        (handler, lines)
+    }
+
+    #[test]
+    fn write0_is_line_buffered() {
+        let (mut handler, lines) = collecting_handler();
+        let mut ram = Ram::new();
+
+        ram.store(RAM_START, b"Hello\nWor\0");
+        ram.store(RAM_START + 0x100, b"ld\r\n\0");
+
+        for address in &[RAM_START, RAM_START + 0x100] {
+            assert_eq!(
+                handler.execute(&mut ram, SYS_WRITE0, *address).unwrap(),
+                Call::Return(0)
+            );
+        }
+
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec![
+                (Stream::Stdout, "Hello".to_owned()),
+                (Stream::Stdout, "World".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn long_write0_string() {
+        let (mut handler, lines) = collecting_handler();
+        let mut ram = Ram::new();
+
+        let mut string = vec![b'a'; 100];
+        string.extend_from_slice(b"\n\0");
+        ram.store(RAM_START, &string);
+
+        handler.execute(&mut ram, SYS_WRITE0, RAM_START).unwrap();
+
+        assert_eq!(lines.lock().unwrap()[0].1, "a".repeat(100));
+    }
+
+    #[test]
+    fn write_to_console_handles() {
+        let (mut handler, lines) = collecting_handler();
+        let mut ram = Ram::new();
+
+        ram.store(RAM_START + 0x100, b":tt");
+        ram.store_words(RAM_START, &[RAM_START + 0x100, 8, 3]);
+        let handle = match handler.execute(&mut ram, SYS_OPEN, RAM_START).unwrap() {
+            Call::Return(handle) => handle,
+            call => panic!("Unexpected result {:?}", call),
+        };
+        assert_eq!(handle, HANDLE_STDERR);
+
+        ram.store(RAM_START + 0x200, b"error\n");
+        ram.store_words(RAM_START, &[handle, RAM_START + 0x200, 6]);
+        assert_eq!(
+            handler.execute(&mut ram, SYS_WRITE, RAM_START).unwrap(),
+            Call::Return(0)
+        );
+
+        // Unknown handles write nothing.
+        ram.store_words(RAM_START, &[42, RAM_START + 0x200, 6]);
+        assert_eq!(
+            handler.execute(&mut ram, SYS_WRITE, RAM_START).unwrap(),
+            Call::Return(6)
+        );
+
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec![(Stream::Stderr, "error".to_owned())]
+        );
+    }
+
+    #[test]
+    fn files_cannot_be_opened() {
+        let mut handler = SemihostingHandler::new();
+        let mut ram = Ram::new();
+
+        ram.store(RAM_START + 0x100, b"/etc/passwd");
+        ram.store_words(RAM_START, &[RAM_START + 0x100, 0, 11]);
+
+        assert_eq!(
+            handler.execute(&mut ram, SYS_OPEN, RAM_START).unwrap(),
+            Call::Return(FAILURE)
+        );
+    }
+
+    #[test]
+    fn readc_without_input() {
+        let mut ram = Ram::new();
+
+        let mut handler = SemihostingHandler::new();
+        assert_eq!(
+            handler.execute(&mut ram, SYS_READC, 0).unwrap(),
+            Call::Return(FAILURE)
+        );
+
+        let mut handler = SemihostingHandler::new().with_input(&b"x"[..]);
+        assert_eq!(
+            handler.execute(&mut ram, SYS_READC, 0).unwrap(),
+            Call::Return(u32::from(b'x'))
+        );
+    }
+
+    #[test]
+    fn exit_codes() {
+        let mut handler = SemihostingHandler::new();
+        let mut ram = Ram::new();
+
+        let exit = match handler
+            .execute(&mut ram, SYS_EXIT, ADP_STOPPED_APPLICATION_EXIT)
+            .unwrap()
+        {
+            Call::Exit(exit) => exit,
+            call => panic!("Unexpected result {:?}", call),
+        };
+        assert!(exit.is_success());
+
+        ram.store_words(RAM_START, &[ADP_STOPPED_APPLICATION_EXIT, 3]);
+        assert_eq!(
+            handler
+                .execute(&mut ram, SYS_EXIT_EXTENDED, RAM_START)
+                .unwrap(),
+            Call::Exit(SemihostingExit {
+                reason: ADP_STOPPED_APPLICATION_EXIT,
+                code: Some(3)
+            })
+        );
+    }
+}
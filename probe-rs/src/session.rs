@@ -1,28 +1,142 @@
 use crate::architecture::{
     arm::{
+        ap::{GenericAP, MemoryAP},
+        authentication::{self, DebugAuthenticator},
+        chip_detection::detect_chip,
         communication_interface::{
             ApInformation::{MemoryAp, Other},
             ArmProbeInterface,
         },
-        core::{debug_core_start, reset_catch_clear, reset_catch_set},
-        memory::Component,
-        SwoConfig,
+        component::{Cti, TRIGGER_OUT_DEBUG_REQUEST, TRIGGER_OUT_DEBUG_RESTART},
+        core::{core_was_reset, debug_core_start, reset_catch_finish, reset_catch_set},
+        fast_read,
+        memory::{Component, DebugComponentTree, MemoryAccessAttributes},
+        swo::{Decoder, TracePacket},
+        ChipDetection, SwoConfig,
     },
-    riscv::communication_interface::RiscvCommunicationInterface,
+    riscv::{communication_interface::RiscvCommunicationInterface, set_reset_halt_request},
 };
 use crate::config::{
-    ChipInfo, MemoryRegion, RawFlashAlgorithm, RegistryError, Target, TargetSelector,
+    ChipInfo, MemoryRegion, RamRegion, RawFlashAlgorithm, RegistryError, ResetType, Target,
+    TargetSelector,
 };
-use crate::core::{Architecture, CoreState, SpecificCoreState};
-use crate::{AttachMethod, Core, CoreType, DebugProbe, Error, Probe};
+use crate::core::{Architecture, CoreInformation, CoreState, SpecificCoreState};
+use crate::flashing::{
+    FileDownloadError, FlashAndRunError, FlashAndRunOptions, FlashAndRunSummary, Format,
+    SectorStatus, VerifyMethod, VerifySummary,
+};
+use crate::memory::{
+    self, AccessWidth, DumpError, DumpFormat, DumpOptions, DumpSummary, MemoryCache, ValueWatch,
+};
+use crate::option_bytes::{OptionBytes, OptionBytesFamily};
+use crate::probe::{DebugProbeError, ReconnectSettings};
+use crate::reset_reason::{ResetReason, ResetReasonFamily};
+use crate::{AttachMethod, Core, CoreStatus, CoreType, DebugProbe, Error, MemoryInterface, Probe};
 use anyhow::anyhow;
-use std::time::Duration;
+use std::{
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+/// The CTI channel used to halt all cores at once.
+const CTI_HALT_CHANNEL: u32 = 0;
+/// The CTI channel used to resume all cores at once.
+const CTI_RESTART_CHANNEL: u32 = 1;
+
+/// The time the reset pin is asserted for a hardware reset.
+const HARDWARE_RESET_TIME: Duration = Duration::from_millis(10);
+/// The time a probe has to show up again after it was disconnected.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// The time between attempts to open a disconnected probe again.
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(200);
 
 #[derive(Debug)]
 pub struct Session {
     target: Target,
     interface: ArchitectureInterface,
     cores: Vec<(SpecificCoreState, CoreState)>,
+    debug_components: Option<DebugComponentTree>,
+    swo_decoder: Decoder,
+    memory_cache: MemoryCache,
+    reconnect: AutoReconnect,
+    /// Unlocks the debug access when attaching and reconnecting, see `Probe::set_debug_authenticator`.
+    authenticator: Box<dyn DebugAuthenticator>,
+}
+
+/// A core of the target, as returned by `Session::list_cores`.
+#[derive(Debug, Clone)]
+pub struct CoreInfo {
+    /// The number of the core, which is passed to `Session::core`.
+    pub id: usize,
+    /// The name of the core.
+    pub name: String,
+    pub core_type: CoreType,
+    pub architecture: Architecture,
+    /// Whether the core can currently be accessed, and its state if it can.
+    pub availability: CoreAvailability,
+}
+
+/// The availability of a core, see `CoreInfo`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoreAvailability {
+    /// The core can be accessed and is in the given state.
+    Available(CoreStatus),
+    /// The core cannot be accessed at the moment, e.g. because it is powered down.
+    Unavailable,
+}
+
+/// Reported by `Session::take_reconnect_event` after the session reconnected to the probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectEvent {
+    /// The number of times the session reconnected since the last event was taken.
+    pub reconnects: usize,
+    /// Set if the target was reset or lost power while the probe was disconnected,
+    /// so the state of the firmware was lost. All breakpoints have been cleared in that case,
+    /// and have to be set again.
+    ///
+    /// This is always set for RISC-V targets, where a reset cannot be detected.
+    pub target_reset: bool,
+}
+
+/// The state of the automatic reconnect, see `Session::set_auto_reconnect`.
+#[derive(Debug)]
+struct AutoReconnect {
+    enabled: bool,
+    halt: bool,
+    /// The settings to open the probe again, if it was opened from a `DebugProbeInfo`.
+    settings: Option<ReconnectSettings>,
+    event: Option<ReconnectEvent>,
+}
+
+impl AutoReconnect {
+    fn new(settings: Option<ReconnectSettings>) -> Self {
+        Self {
+            enabled: false,
+            halt: false,
+            settings,
+            event: None,
+        }
+    }
+
+    fn record(&mut self, target_reset: bool) {
+        let previous = self.event.take();
+
+        self.event = Some(ReconnectEvent {
+            reconnects: previous.map_or(0, |event| event.reconnects) + 1,
+            target_reset: target_reset || matches!(previous, Some(event) if event.target_reset),
+        });
+    }
+}
+
+/// Options for `Session::read_memory`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadOptions {
+    /// Read the memory with the help of a small routine loaded into the target RAM,
+    /// which is a lot faster for large reads from slow memories.
+    ///
+    /// This is only possible for halted ARM cores, and if the first RAM region of the target
+    /// is large enough and does not overlap the memory to be read. Otherwise the memory is read directly.
+    pub fast_read: bool,
 }
 
 #[derive(Debug)]
@@ -73,7 +187,10 @@ impl Session {
         target: impl Into<TargetSelector>,
         attach_method: AttachMethod,
     ) -> Result<Self, Error> {
-        let (probe, target) = get_target_from_selector(target, probe)?;
+        let (mut probe, target) = get_target_from_selector(target, probe)?;
+        let mut authenticator = probe.take_debug_authenticator();
+        let memory_cache = MemoryCache::new(cacheable_regions(&target.memory_map));
+        let reconnect = AutoReconnect::new(probe.reconnect_settings());
 
         let mut session = match target.architecture() {
             Architecture::Arm => {
@@ -82,48 +199,83 @@ impl Session {
                     Core::create_state(0),
                 );
 
-                let interface = probe.into_arm_interface()?;
+                let mut interface = probe.into_arm_interface()?.unwrap();
+
+                // Secure targets only allow to access the cores once the debugger is authenticated.
+                let unlocked = authentication::unlock(&mut *authenticator, &mut *interface);
+                if unlocked.is_err() && attach_method == AttachMethod::UnderReset {
+                    let probe: &mut dyn DebugProbe = interface.as_mut().as_mut();
+                    probe.target_reset_deassert()?;
+                }
+                unlocked?;
 
                 let mut session = Session {
                     target,
-                    interface: ArchitectureInterface::Arm(interface.unwrap()),
+                    interface: ArchitectureInterface::Arm(interface),
                     cores: vec![core],
+                    debug_components: None,
+                    swo_decoder: Decoder::new(),
+                    memory_cache,
+                    reconnect,
+                    authenticator,
                 };
 
                 // Enable debug mode
-                debug_core_start(&mut session.core(0)?)?;
+                let started = session
+                    .core(0)
+                    .and_then(|mut core| debug_core_start(&mut core));
 
                 if attach_method == AttachMethod::UnderReset {
                     // we need to halt the chip here
-                    reset_catch_set(&mut session.core(0)?)?;
+                    let caught = started.and_then(|_| reset_catch_set(&mut session.core(0)?));
 
-                    // Deassert the reset pin
+                    // Deassert the reset pin, even if the core could not be set up,
+                    // so the target is not kept in reset.
                     session.interface.as_mut().target_reset_deassert()?;
+                    caught?;
 
                     // Wait for the core to be halted
                     let mut core = session.core(0)?;
 
-                    core.wait_for_core_halted(Duration::from_millis(100))?;
+                    let halted = core.wait_for_core_halted(Duration::from_millis(100));
 
-                    reset_catch_clear(&mut core)?;
+                    reset_catch_finish(&mut core, halted)?;
+                } else {
+                    started?;
                 }
 
                 session
             }
             Architecture::Riscv => {
-                // TODO: Handle attach under reset
-
                 let core = (
                     SpecificCoreState::from_core_type(target.core_type),
                     Core::create_state(0),
                 );
 
-                let interface = probe.into_riscv_interface()?;
+                let mut interface = probe.into_riscv_interface()?.unwrap();
+
+                let reset_halt_requested = if attach_method == AttachMethod::UnderReset {
+                    // Halt the core when it comes out of reset, if the debug module supports it.
+                    // Otherwise it is halted shortly after the reset below.
+                    let requested = set_reset_halt_request(&mut interface, true);
+
+                    // Deassert the reset pin, even if the request failed,
+                    // so the target is not kept in reset.
+                    interface.as_mut().target_reset_deassert()?;
+                    requested?
+                } else {
+                    false
+                };
 
                 let mut session = Session {
                     target,
-                    interface: ArchitectureInterface::Riscv(interface.unwrap()),
+                    interface: ArchitectureInterface::Riscv(interface),
                     cores: vec![core],
+                    debug_components: None,
+                    swo_decoder: Decoder::new(),
+                    memory_cache,
+                    reconnect,
+                    authenticator,
                 };
 
                 {
@@ -132,6 +284,12 @@ impl Session {
                     core.halt(Duration::from_millis(100))?;
                 }
 
+                if reset_halt_requested {
+                    if let ArchitectureInterface::Riscv(interface) = &mut session.interface {
+                        set_reset_halt_request(interface, false)?;
+                    }
+                }
+
                 session
             }
         };
@@ -153,20 +311,327 @@ impl Session {
         probe.attach(target)
     }
 
-    /// Lists the available cores with their number and their type.
-    pub fn list_cores(&self) -> Vec<(usize, CoreType)> {
-        self.cores
-            .iter()
-            .map(|(t, _)| CoreType::from(t))
-            .enumerate()
+    /// Lists the available cores with their number, type and current state.
+    ///
+    /// A core whose state cannot be read, e.g. because it is powered down,
+    /// is reported as `CoreAvailability::Unavailable`.
+    pub fn list_cores(&mut self) -> Vec<CoreInfo> {
+        (0..self.cores.len())
+            .map(|id| {
+                let core_type = CoreType::from(&self.cores[id].0);
+                let availability = match self.core(id).and_then(|mut core| core.status()) {
+                    Ok(status) => CoreAvailability::Available(status),
+                    Err(e) => {
+                        log::debug!("Unable to read the status of core {}: {}", id, e);
+                        CoreAvailability::Unavailable
+                    }
+                };
+
+                CoreInfo {
+                    id,
+                    name: format!("core{}", id),
+                    core_type,
+                    architecture: core_type.architecture(),
+                    availability,
+                }
+            })
             .collect()
     }
 
     /// Attaches to the core with the given number.
+    ///
+    /// Halting, resuming and stepping the returned core does not affect any other core.
+    /// Use `halt_all` and `run_all` to control all cores at once.
     pub fn core(&mut self, n: usize) -> Result<Core<'_>, Error> {
         let (core, core_state) = self.cores.get_mut(n).ok_or(Error::CoreNotFound(n))?;
 
-        self.interface.attach(core, core_state)
+        let core = self.interface.attach(core, core_state)?;
+        Ok(core
+            .with_memory_cache(&mut self.memory_cache)
+            .with_byte_order(self.target.byte_order))
+    }
+
+    /// Enables or disables the read cache for the memory of the target.
+    ///
+    /// While a core is halted, reads of its RAM and flash are served from the cache,
+    /// which speeds up debuggers reading the same memory repeatedly. The cache is cleared whenever
+    /// a core is resumed, stepped or reset, and writes through `MemoryInterface` invalidate
+    /// the written memory.
+    /// Peripherals are never cached.
+    ///
+    /// The cache assumes that the memory does not change while the core is halted. If it is
+    /// modified by another core or a DMA transfer, call `invalidate_cache`.
+    pub fn enable_memory_cache(&mut self, enabled: bool) {
+        self.memory_cache.set_enabled(enabled);
+    }
+
+    /// Drops all memory cached by the read cache, see `enable_memory_cache`.
+    pub fn invalidate_cache(&mut self) {
+        self.memory_cache.invalidate();
+    }
+
+    /// Returns the generation of the memory cache, see `MemoryCache::generation`.
+    pub(crate) fn memory_generation(&self) -> Option<u64> {
+        self.memory_cache.generation()
+    }
+
+    /// Halts all cores of the target.
+    ///
+    /// On ARM targets with cross trigger interfaces, all cores are halted at the same time through the CTIs.
+    /// Otherwise the cores are halted one after another.
+    pub fn halt_all(&mut self, timeout: Duration) -> Result<(), Error> {
+        if !self.synchronize_with_cti(CTI_HALT_CHANNEL, TRIGGER_OUT_DEBUG_REQUEST)? {
+            for n in 0..self.cores.len() {
+                self.core(n)?.halt(timeout)?;
+            }
+            return Ok(());
+        }
+
+        for n in 0..self.cores.len() {
+            self.core(n)?.wait_for_core_halted(timeout)?;
+        }
+
+        Ok(())
+    }
+
+    /// Resumes all cores of the target.
+    ///
+    /// On ARM targets with cross trigger interfaces, all cores are resumed at the same time through the CTIs.
+    /// Otherwise the cores are resumed one after another.
+    pub fn run_all(&mut self) -> Result<(), Error> {
+        if !self.synchronize_with_cti(CTI_RESTART_CHANNEL, TRIGGER_OUT_DEBUG_RESTART)? {
+            for n in 0..self.cores.len() {
+                self.core(n)?.run()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends a single event on `channel` to the `trigger` output of all CTIs.
+    ///
+    /// The trigger outputs are disconnected again afterwards, so single cores can be controlled independently.
+    ///
+    /// Returns `false` if the target has no cross trigger interfaces.
+    fn synchronize_with_cti(&mut self, channel: u32, trigger: u32) -> Result<bool, Error> {
+        if self.architecture() != Architecture::Arm {
+            return Ok(false);
+        }
+
+        let component = self.get_arm_component()?;
+        let ctis = component.ctis();
+        if ctis.is_empty() {
+            log::debug!("No CTI found, cores are controlled one after another.");
+            return Ok(false);
+        }
+
+        let mut core = self.core(0)?;
+        for cti in &ctis {
+            let mut cti = Cti::new(&mut core, cti);
+            cti.unlock()?;
+            cti.enable()?;
+            // A debug request which is still pending from `halt_all` would halt the core again immediately.
+            cti.acknowledge(TRIGGER_OUT_DEBUG_REQUEST)?;
+            cti.set_output_channels(trigger, 1 << channel)?;
+        }
+
+        Cti::new(&mut core, ctis[0]).pulse(channel)?;
+
+        for cti in &ctis {
+            let mut cti = Cti::new(&mut core, cti);
+            cti.acknowledge(trigger)?;
+            cti.set_output_channels(trigger, 0)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the option bytes of the target, or `None` if they are not supported for its family.
+    ///
+    /// See the `option_bytes` module for the supported families.
+    pub fn option_bytes(&mut self) -> Option<OptionBytes<'_>> {
+        let family = OptionBytesFamily::from_chip_name(self.chip_name())?;
+        Some(OptionBytes::new(self, family))
+    }
+
+    /// Returns the sectors of the flash of the target, sorted by address.
+    ///
+    /// The sectors are taken from the flash algorithms of the target, so sectors of different sizes
+    /// are listed individually. Their write protection is read from the option bytes for the
+    /// families which support it, see the `option_bytes` module, and is unknown otherwise.
+    pub fn flash_sectors(&mut self) -> Result<Vec<SectorStatus>, Error> {
+        crate::flashing::flash_sectors(self)
+    }
+
+    /// Flashes the ELF file at `path`, verifies the flash, and resets and starts the target
+    /// as given by `options.after_flashing`.
+    ///
+    /// If flashing or verifying fails, the target is not started. A failed verification
+    /// is reported with the flash region which does not contain the expected data.
+    pub fn flash_and_run(
+        &mut self,
+        path: &std::path::Path,
+        options: FlashAndRunOptions<'_>,
+    ) -> Result<FlashAndRunSummary, FlashAndRunError> {
+        crate::flashing::flash_and_run(self, path, options)
+    }
+
+    /// Checks whether the flash contains the file of given `format` at `path`, without erasing
+    /// or programming anything.
+    ///
+    /// Only the flash regions which the file touches are compared. `VerifyMethod::Crc32` compares
+    /// the checksums of the data first, and only reads back the data whose checksum differs to
+    /// find the first differing address. It fails with `FlashError::RoutineNotSupported` if the
+    /// flash algorithm has no CRC32 routine. If the flash cannot be read, e.g. because it is read
+    /// protected, `FlashError::VerifyReadFailed` is returned.
+    pub fn verify_image(
+        &mut self,
+        path: &std::path::Path,
+        format: Format,
+        method: VerifyMethod,
+    ) -> Result<VerifySummary, FileDownloadError> {
+        crate::flashing::verify_image(self, path, format, method)
+    }
+
+    /// Reads the reason of the last reset of the target through core `core_index`.
+    ///
+    /// If `clear` is set, the sticky reset flags are cleared afterwards.
+    /// See the `reset_reason` module for the supported families.
+    pub fn reset_reason(&mut self, core_index: usize, clear: bool) -> Result<ResetReason, Error> {
+        let family = ResetReasonFamily::from_chip_name(self.chip_name())
+            .ok_or(Error::NotSupported("reading the reset reason"))?;
+        family.read(&mut self.core(core_index)?, clear)
+    }
+
+    /// Re-establishes the connection to the target, after the target and its debug port were reset.
+    pub(crate) fn reconnect(&mut self) -> Result<(), Error> {
+        match &mut self.interface {
+            ArchitectureInterface::Arm(interface) => {
+                interface.reinitialize()?;
+                authentication::unlock(&mut *self.authenticator, &mut **interface)?;
+            }
+            ArchitectureInterface::Riscv(_) => {
+                return Err(Error::NotSupported(
+                    "Reconnecting to RISC-V targets is not supported.",
+                ))
+            }
+        }
+
+        self.debug_components = None;
+
+        debug_core_start(&mut self.core(0)?)
+    }
+
+    /// Enables or disables reconnecting to the probe when an operation run with `with_reconnect`
+    /// fails, because the probe was disconnected from USB.
+    ///
+    /// Reconnecting is only possible if the probe was opened from a `DebugProbeInfo`,
+    /// or with `Probe::open_by_identifier`, so the same probe can be found again.
+    pub fn set_auto_reconnect(&mut self, enabled: bool) {
+        self.reconnect.enabled = enabled;
+    }
+
+    /// Halts all cores after reconnecting to the probe, if `halt` is set.
+    ///
+    /// Otherwise the cores are left in the state they are found in.
+    pub fn set_halt_after_reconnect(&mut self, halt: bool) {
+        self.reconnect.halt = halt;
+    }
+
+    /// Runs `operation`, and runs it again after reconnecting to the probe,
+    /// if it failed because the probe was disconnected.
+    ///
+    /// This only reconnects if it was enabled with `set_auto_reconnect`.
+    /// After a reconnect, `take_reconnect_event` tells whether the state of the target was lost.
+    pub fn with_reconnect<T>(
+        &mut self,
+        mut operation: impl FnMut(&mut Session) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        match operation(self) {
+            Err(e) if self.reconnect.enabled && e.is_probe_disconnected() => {
+                log::warn!("The probe was disconnected, reconnecting: {}", e);
+                self.reopen_probe()?;
+
+                operation(self)
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the reconnects which happened since the last call, if there were any.
+    pub fn take_reconnect_event(&mut self) -> Option<ReconnectEvent> {
+        self.reconnect.event.take()
+    }
+
+    /// Opens the disconnected probe again, once it shows up again, and attaches to the target.
+    fn reopen_probe(&mut self) -> Result<(), Error> {
+        let settings = self.reconnect.settings.clone().ok_or(Error::NotSupported(
+            "reconnecting to a probe which was not opened from a `DebugProbeInfo`",
+        ))?;
+
+        let deadline = Instant::now() + RECONNECT_TIMEOUT;
+        let probe = loop {
+            match settings.reopen() {
+                Ok(probe) => break probe,
+                Err(e) if Instant::now() < deadline => {
+                    log::debug!("The probe could not be opened yet: {}", e);
+                    std::thread::sleep(RECONNECT_INTERVAL);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        // The memory might have changed while the probe was disconnected.
+        self.memory_cache.set_halted(false);
+        self.debug_components = None;
+
+        let target_reset = match self.architecture() {
+            Architecture::Arm => {
+                let mut interface = probe
+                    .into_arm_interface()?
+                    .ok_or(DebugProbeError::InterfaceNotAvailable("ARM"))?;
+                authentication::unlock(&mut *self.authenticator, &mut *interface)?;
+                self.interface = ArchitectureInterface::Arm(interface);
+
+                let mut core = self.core(0)?;
+                let target_reset = core_was_reset(&mut core)?;
+                debug_core_start(&mut core)?;
+
+                target_reset
+            }
+            Architecture::Riscv => {
+                let interface = probe
+                    .into_riscv_interface()?
+                    .ok_or(DebugProbeError::InterfaceNotAvailable("RISC-V"))?;
+                self.interface = ArchitectureInterface::Riscv(interface);
+
+                true
+            }
+        };
+
+        if target_reset {
+            log::warn!("The target was reset while the probe was disconnected.");
+
+            for (id, (core, core_state)) in self.cores.iter_mut().enumerate() {
+                *core = SpecificCoreState::from_core_type(CoreType::from(&*core));
+                *core_state = Core::create_state(id);
+            }
+            self.clear_all_hw_breakpoints()?;
+        }
+
+        if self.reconnect.halt {
+            self.halt_all(Duration::from_millis(100))?;
+        }
+
+        self.reconnect.record(target_reset);
+        log::info!("Reconnected to the probe.");
+
+        Ok(())
+    }
+
+    /// Returns the name of the target chip.
+    pub(crate) fn chip_name(&self) -> &str {
+        &self.target.identifier.chip_name
     }
 
     /// Returns a list of the flash algotithms on the target.
@@ -174,7 +639,21 @@ impl Session {
         &self.target.flash_algorithms
     }
 
-    pub fn read_swo(&mut self) -> Result<Vec<u8>, Error> {
+    /// Reads the SWO data received since the last call and decodes it.
+    ///
+    /// Packets which were not completely received yet are returned by a later call.
+    /// Use [`group_stimulus_data`](crate::architecture::arm::swo::group_stimulus_data)
+    /// to collect the data written to the ITM stimulus ports.
+    pub fn read_swo(&mut self) -> Result<Vec<TracePacket>, Error> {
+        let bytes = self.read_swo_raw()?;
+
+        self.swo_decoder.feed(bytes);
+
+        Ok(std::iter::from_fn(|| self.swo_decoder.pull()).collect())
+    }
+
+    /// Reads the SWO data received since the last call, without decoding it.
+    pub fn read_swo_raw(&mut self) -> Result<Vec<u8>, Error> {
         let interface = self.get_arm_interface()?;
         interface.read_swo()
     }
@@ -188,6 +667,76 @@ impl Session {
         Ok(interface)
     }
 
+    /// Reads the register at `address` of the access port `ap`, where the upper four bits
+    /// of `address` select the bank of the register.
+    ///
+    /// This is meant for registers which probe-rs does not expose otherwise, e.g. of vendor
+    /// specific APs. The value is not interpreted in any way.
+    pub fn read_ap_register(&mut self, ap: u8, address: u8) -> Result<u32, Error> {
+        self.get_arm_interface()?
+            .read_raw_ap_register(GenericAP::new(ap), address)
+    }
+
+    /// Writes `value` to the register at `address` of the access port `ap`, where the upper
+    /// four bits of `address` select the bank of the register.
+    ///
+    /// # Caveats
+    ///
+    /// probe-rs does not know what the write changed. Memory accesses write `CSW` and `TAR`
+    /// again every time, so attributes written to `CSW`, e.g. `HPROT` or `HNONSEC`, only apply
+    /// to `DRW` accesses through these functions until the next memory access. Writes which change the state of the target,
+    /// e.g. to `DRW`, invalidate the memory cache, but not the state of the cores,
+    /// like their halt state or breakpoints.
+    pub fn write_ap_register(&mut self, ap: u8, address: u8, value: u32) -> Result<(), Error> {
+        self.get_arm_interface()?
+            .write_raw_ap_register(GenericAP::new(ap), address, value)?;
+        self.memory_cache.invalidate();
+
+        Ok(())
+    }
+
+    /// Sets the attributes of the memory accesses through the memory AP `ap`, e.g. to read the
+    /// secure view of the memory on a TrustZone target.
+    ///
+    /// Returns an error if the AP or the probe does not support the attributes, or if secure
+    /// accesses are requested while secure debug is disabled. The memory cache is invalidated,
+    /// as the memory may look different with other attributes.
+    pub fn set_memory_access_attributes(
+        &mut self,
+        ap: u8,
+        attributes: MemoryAccessAttributes,
+    ) -> Result<(), Error> {
+        self.get_arm_interface()?
+            .set_memory_access_attributes(MemoryAP::new(ap), attributes)?;
+        self.memory_cache.invalidate();
+
+        Ok(())
+    }
+
+    /// Reads the DP register at `address`.
+    ///
+    /// `bank` is selected in `SELECT.DPBANKSEL` before reading the banked register at 0x4,
+    /// it is ignored for all other registers.
+    pub fn read_dp_register(&mut self, bank: u8, address: u8) -> Result<u32, Error> {
+        self.get_arm_interface()?
+            .read_raw_dp_register(bank, address)
+    }
+
+    /// Writes `value` to the DP register at `address`.
+    ///
+    /// `bank` is selected in `SELECT.DPBANKSEL` before writing the banked register at 0x4,
+    /// it is ignored for all other registers.
+    ///
+    /// # Caveats
+    ///
+    /// A write to `SELECT` is tracked, so later accesses select their AP again. Powering down
+    /// the debug port through `CTRL/STAT`, or aborting transfers, is not, and makes all
+    /// further accesses fail until the session is reconnected.
+    pub fn write_dp_register(&mut self, bank: u8, address: u8, value: u32) -> Result<(), Error> {
+        self.get_arm_interface()?
+            .write_raw_dp_register(bank, address, value)
+    }
+
     pub fn get_arm_component(&mut self) -> Result<Component, Error> {
         let interface = self.get_arm_interface()?;
 
@@ -201,6 +750,7 @@ impl Session {
             MemoryAp {
                 port_number,
                 only_32bit_data_size: _,
+                supports_large_address: _,
                 debug_base_address,
             } => {
                 let access_port_number = *port_number;
@@ -221,14 +771,54 @@ impl Session {
         }
     }
 
+    /// Returns the CoreSight components listed in the ROM tables of the target.
+    ///
+    /// The ROM tables are read on the first call, and the result is cached.
+    /// Components in power domains which were disabled at that time are listed as
+    /// [`DebugComponentKind::Unreadable`](crate::architecture::arm::memory::DebugComponentKind::Unreadable),
+    /// use [`Session::rescan_debug_components`] to read them again after powering them up.
+    pub fn debug_components(&mut self) -> Result<&DebugComponentTree, Error> {
+        if self.debug_components.is_none() {
+            let component = self.get_arm_component()?;
+            self.debug_components = Some(DebugComponentTree::from(&component));
+        }
+
+        Ok(self.debug_components.as_ref().unwrap())
+    }
+
+    /// Reads the ROM tables of the target again, see [`Session::debug_components`].
+    pub fn rescan_debug_components(&mut self) -> Result<&DebugComponentTree, Error> {
+        self.debug_components = None;
+        self.debug_components()
+    }
+
+    /// Identifies the chip by reading its vendor specific device ID registers.
+    ///
+    /// This is meant to be used after attaching with a generic target, e.g. `cortex-m4`,
+    /// to find the exact chip. The debug port is already powered up while attaching.
+    /// If the device ID is not known, no candidates are returned, but the values of
+    /// the device ID registers are.
+    pub fn auto_detect_chip(&mut self) -> Result<ChipDetection, Error> {
+        let manufacturer = self.get_arm_component()?.id().peripheral_id().jep106();
+        let families = crate::config::registry::families()?;
+
+        let mut core = self.core(0)?;
+        Ok(detect_chip(&mut core, manufacturer, &families))
+    }
+
     /// Configure the target and probe for serial wire view (SWV) tracing.
-    pub fn setup_swv(&mut self, config: &SwoConfig) -> Result<(), Error> {
+    ///
+    /// Returns [`Error::NotSupported`] if the probe is unable to receive SWO data.
+    pub fn enable_swo(&mut self, config: &SwoConfig) -> Result<(), Error> {
         // Configure SWO on the probe
         {
             let interface = self.get_arm_interface()?;
             interface.enable_swo(config)?;
         }
 
+        // Drop any partial packet from an earlier trace session.
+        self.swo_decoder = Decoder::new();
+
         // Enable tracing on the target
         {
             let mut core = self.core(0)?;
@@ -246,6 +836,20 @@ impl Session {
         crate::architecture::arm::component::disable_swv(&mut self.core(0)?)
     }
 
+    /// Begin sampling the PC over SWV, every `64 * (postpreset + 1)` core cycles.
+    pub fn enable_swv_pc_sampling(&mut self, postpreset: u8) -> Result<(), Error> {
+        let component = self.get_arm_component()?;
+        let mut core = self.core(0)?;
+        crate::architecture::arm::component::enable_pc_sampling(&mut core, &component, postpreset)
+    }
+
+    /// Stop sampling the PC over SWV.
+    pub fn disable_swv_pc_sampling(&mut self) -> Result<(), Error> {
+        let component = self.get_arm_component()?;
+        let mut core = self.core(0)?;
+        crate::architecture::arm::component::disable_pc_sampling(&mut core, &component)
+    }
+
     /// Begin tracing a memory address over SWV.
     pub fn add_swv_data_trace(&mut self, unit: usize, address: u32) -> Result<(), Error> {
         let component = self.get_arm_component()?;
@@ -263,10 +867,24 @@ impl Session {
     }
 
     /// Returns the memory map of the target.
+    ///
+    /// This contains all the regions known from the target description,
+    /// which might be none at all for a generic target.
     pub fn memory_map(&self) -> &[MemoryRegion] {
         &self.target.memory_map
     }
 
+    /// Returns all the RAM regions of the target.
+    pub fn ram_regions(&self) -> impl Iterator<Item = &RamRegion> {
+        self.target
+            .memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Ram(ram) => Some(ram),
+                _ => None,
+            })
+    }
+
     /// Return the `Architecture` of the currently connected chip.
     pub fn architecture(&self) -> Architecture {
         match self.interface {
@@ -275,6 +893,206 @@ impl Session {
         }
     }
 
+    /// Resets the target with the reset recommended by its description, see `Target::default_reset_type`,
+    /// and lets core `core_index` continue to execute instructions.
+    pub fn reset(&mut self, core_index: usize) -> Result<(), Error> {
+        self.reset_with(core_index, self.target.default_reset_type)
+    }
+
+    /// Resets the target with `reset_type`, and lets core `core_index` continue to execute instructions.
+    ///
+    /// A hardware reset asserts the reset pin of the probe, which fails with
+    /// `DebugProbeError::ResetPinNotAvailable` if the probe cannot drive it.
+    /// The other resets are performed by the core, see `Core::reset_with`.
+    pub fn reset_with(&mut self, core_index: usize, reset_type: ResetType) -> Result<(), Error> {
+        if reset_type != ResetType::Hardware {
+            return self.core(core_index)?.reset_with(reset_type);
+        }
+
+        if core_index >= self.cores.len() {
+            return Err(Error::CoreNotFound(core_index));
+        }
+
+        self.interface.as_mut().target_reset_assert()?;
+        std::thread::sleep(HARDWARE_RESET_TIME);
+        self.interface.as_mut().target_reset_deassert()?;
+        self.memory_cache.set_halted(false);
+
+        Ok(())
+    }
+
+    /// Resets the target and halts core `core_index` at its reset vector,
+    /// before the first instruction is executed.
+    ///
+    /// If `hardware_reset` is set, the reset pin of the probe is used to reset an ARM target.
+    /// Otherwise the core resets itself, see `Core::reset_and_halt_at_reset_vector`.
+    /// If the core does not halt within `timeout`, the debug registers are restored
+    /// before the error is returned.
+    pub fn reset_catch(
+        &mut self,
+        core_index: usize,
+        timeout: Duration,
+        hardware_reset: bool,
+    ) -> Result<CoreInformation, Error> {
+        if !hardware_reset {
+            return self
+                .core(core_index)?
+                .reset_and_halt_at_reset_vector(timeout);
+        }
+
+        if self.architecture() != Architecture::Arm {
+            log::warn!("A reset with the reset pin is only supported for ARM targets, the core is reset by the debug module instead.");
+            return self
+                .core(core_index)?
+                .reset_and_halt_at_reset_vector(timeout);
+        }
+
+        reset_catch_set(&mut self.core(core_index)?)?;
+
+        let reset = self.interface.as_mut().target_reset().map_err(Error::from);
+
+        let mut core = self.core(core_index)?;
+        let halted = reset.and_then(|_| core.wait_for_core_halted(timeout));
+
+        reset_catch_finish(&mut core, halted)?;
+
+        let pc = core.read_core_reg(core.registers().program_counter())?;
+
+        Ok(CoreInformation { pc })
+    }
+
+    /// Reads `data.len()` bytes at `address` through core `core_index`, as configured by `options`.
+    pub fn read_memory(
+        &mut self,
+        core_index: usize,
+        address: u32,
+        data: &mut [u8],
+        options: ReadOptions,
+    ) -> Result<(), Error> {
+        let ram = self.ram_regions().next().map(|ram| ram.range.clone());
+        let architecture = self.architecture();
+        let mut core = self.core(core_index)?;
+
+        match ram {
+            Some(ram) if options.fast_read && architecture == Architecture::Arm => {
+                fast_read::read_8(&mut core, &ram, address, data)
+            }
+            _ => core.read(address.into(), data),
+        }
+    }
+
+    /// Writes the memory in `range` to `output` in `format`, reading it through core `core_index`.
+    ///
+    /// The memory is read as configured by `options.read`. If some of it cannot be read, e.g. holes
+    /// between peripherals, the dump fails with the first inaccessible address, unless `options.fill`
+    /// is set. The inaccessible memory is filled with that byte then, and returned in the summary.
+    pub fn dump_region(
+        &mut self,
+        core_index: usize,
+        range: Range<u32>,
+        format: DumpFormat,
+        options: DumpOptions,
+        output: &mut impl std::io::Write,
+    ) -> Result<DumpSummary, DumpError> {
+        let (data, holes) = memory::read_region(
+            |address, data| self.read_memory(core_index, address, data, options.read),
+            range.clone(),
+            options.fill,
+        )?;
+        memory::write_dump(output, range.start, &data, format)?;
+
+        Ok(DumpSummary {
+            bytes: data.len() as u32,
+            holes,
+        })
+    }
+
+    /// Resolves `location` to an address.
+    ///
+    /// `location` is either a hexadecimal address, with or without a `0x` prefix,
+    /// or the name of a system register defined by the architecture, like `DHCSR` on ARM.
+    pub fn resolve_address(&self, location: &str) -> Result<u32, Error> {
+        memory::resolve_address(self.architecture(), location).ok_or_else(|| {
+            anyhow!(
+                "'{}' is neither an address nor a known register name",
+                location
+            )
+            .into()
+        })
+    }
+
+    /// Reads `count` values of `width` at `address` through core `core_index`.
+    pub fn read_values(
+        &mut self,
+        core_index: usize,
+        address: u32,
+        width: AccessWidth,
+        count: usize,
+    ) -> Result<Vec<u64>, Error> {
+        memory::read_values(&mut self.core(core_index)?, address, width, count)
+    }
+
+    /// Watches the value of `width` at `address`, reading it through core `core_index` every `interval`.
+    ///
+    /// The returned iterator blocks until the value changed, and yields the new one. The core is
+    /// not halted, unless the memory cannot be read while it is running, see [`ValueWatch`].
+    pub fn watch_value(
+        &mut self,
+        core_index: usize,
+        address: u32,
+        width: AccessWidth,
+        interval: Duration,
+    ) -> ValueWatch<'_> {
+        ValueWatch::new(self, core_index, address, width, interval)
+    }
+
+    /// Writes a single `value` of `width` to `address` through core `core_index`.
+    ///
+    /// Writes to memory which is not writable according to the memory map, like flash,
+    /// or which is not part of the memory map at all, are refused unless `force` is set.
+    /// They are attempted with a warning otherwise.
+    pub fn write_value(
+        &mut self,
+        core_index: usize,
+        address: u32,
+        width: AccessWidth,
+        value: u64,
+        force: bool,
+    ) -> Result<(), Error> {
+        if !self.is_writable(address, width.bytes() as u32) {
+            if !force {
+                return Err(anyhow!(
+                    "The memory at {:#010x} is read-only or not part of the memory map of the target",
+                    address
+                )
+                .into());
+            }
+            log::warn!(
+                "Writing to {:#010x}, which is read-only or not part of the memory map of the target",
+                address
+            );
+        }
+
+        memory::write_value(&mut self.core(core_index)?, address, width, value)
+    }
+
+    /// Returns `true` if the `len` bytes at `address` can be written with plain memory writes.
+    ///
+    /// Generic targets have no memory map, so everything is considered writable for them.
+    fn is_writable(&self, address: u32, len: u32) -> bool {
+        if self.target.memory_map.is_empty()
+            || memory::is_architecture_register(self.architecture(), address)
+        {
+            return true;
+        }
+
+        let end = address.saturating_add(len);
+        self.target.memory_map.iter().any(|region| {
+            let range = region.range();
+            region.access().write && range.start <= address && end <= range.end
+        })
+    }
+
     /// Clears all hardware breakpoints on all cores
     pub fn clear_all_hw_breakpoints(&mut self) -> Result<(), Error> {
         { 0..self.cores.len() }
@@ -285,15 +1103,41 @@ impl Session {
             .collect::<Result<Vec<_>, _>>()
             .map(|_| ())
     }
+
+    /// Removes the software breakpoints of all cores, restoring the original instructions.
+    pub fn clear_all_sw_breakpoints(&mut self) -> Result<(), Error> {
+        { 0..self.cores.len() }
+            .map(|n| {
+                self.core(n)
+                    .and_then(|mut core| core.clear_all_sw_breakpoints())
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|_| ())
+    }
 }
 
 impl Drop for Session {
     fn drop(&mut self) {
+        if let Err(err) = self.clear_all_sw_breakpoints() {
+            log::warn!("Could not clear all software breakpoints: {:?}", err);
+        }
         if let Err(err) = self.clear_all_hw_breakpoints() {
             log::warn!("Could not clear all hardware breakpoints: {:?}", err);
         }
     }
 }
+/// Returns the memory regions which can be cached, which are all RAM and flash regions.
+pub(crate) fn cacheable_regions(memory_map: &[MemoryRegion]) -> Vec<Range<u32>> {
+    memory_map
+        .iter()
+        .filter_map(|region| match region {
+            MemoryRegion::Ram(region) => Some(region.range.clone()),
+            MemoryRegion::Flash(region) => Some(region.range.clone()),
+            MemoryRegion::Generic(_) => None,
+        })
+        .collect()
+}
+
 /// Determine the ```Target``` from a ```TargetSelector```.
 ///
 /// If the selector is ```Unspecified```, the target will be looked up in the registry.
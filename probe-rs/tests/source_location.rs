@@ -1,4 +1,4 @@
-use probe_rs::debug::{ColumnType, DebugInfo, SourceLocation};
+use probe_rs::debug::{ColumnType, DebugInfo, InlinedFunction, SourceLocation};
 use std::path::{Path, PathBuf};
 
 const TEST_DATA: [(u64, u64, ColumnType); 8] = [
@@ -99,3 +99,23 @@ fn find_non_existing_unit_by_path() {
         .unwrap()
         .is_none());
 }
+
+#[test]
+fn inlined_functions() {
+    let di = DebugInfo::from_file("tests/gpio_hal_blinky").unwrap();
+
+    assert_eq!(
+        di.get_inlined_functions(0x910),
+        vec![InlinedFunction {
+            function_name: "set<u32>".to_owned(),
+            call_file: Some(
+                "/home/dominik/.cargo/registry/src/github.com-1ecc6299db9ec823/nrf51-0.6.0/src/gpio/pin_cnf.rs"
+                    .to_owned()
+            ),
+            call_line: Some(37),
+        }]
+    );
+
+    // Nothing is inlined at the start of `main`.
+    assert!(di.get_inlined_functions(0x920).is_empty());
+}